@@ -45,13 +45,33 @@ define_conf!(StringConf, SPARK_IO_COMPRESSION_CODEC);
 define_conf!(IntConf, TOKIO_WORKER_THREADS_PER_CPU);
 define_conf!(IntConf, SPARK_TASK_CPUS);
 define_conf!(StringConf, SPILL_COMPRESSION_CODEC);
+define_conf!(BooleanConf, SPILL_CHECKSUM_ENABLE);
+define_conf!(BooleanConf, SERDE_CHECK_DATA_TYPES_ENABLE);
 define_conf!(BooleanConf, SMJ_FALLBACK_ENABLE);
 define_conf!(IntConf, SMJ_FALLBACK_ROWS_THRESHOLD);
 define_conf!(IntConf, SMJ_FALLBACK_MEM_SIZE_THRESHOLD);
+define_conf!(DoubleConf, JOIN_HASH_MAP_LOAD_FACTOR);
+define_conf!(IntConf, JOIN_HASH_SEED);
+define_conf!(BooleanConf, JOIN_HASH_MAP_DICT_COMPRESS_INDICES);
 define_conf!(IntConf, SUGGESTED_BATCH_MEM_SIZE);
 define_conf!(IntConf, SUGGESTED_BATCH_MEM_SIZE_KWAY_MERGE);
 define_conf!(BooleanConf, ORC_FORCE_POSITIONAL_EVOLUTION);
 define_conf!(IntConf, UDAF_FALLBACK_NUM_UDAFS_TRIGGER_SORT_AGG);
+define_conf!(BooleanConf, AGG_DICT_ENCODE_STRING_KEYS);
+define_conf!(BooleanConf, AGG_SPILL_PRE_MERGE_ENABLE);
+define_conf!(BooleanConf, AGG_SPILL_APPEND_RUNS_ENABLE);
+define_conf!(StringConf, COUNT_OVERFLOW_BEHAVIOR);
+define_conf!(IntConf, COUNT_BULK_UPDATE_MIN_ROWS);
+define_conf!(BooleanConf, UDAF_SPILL_CHECKSUM_ENABLE);
+define_conf!(BooleanConf, SHUFFLE_ZSTD_DICT_ENABLE);
+define_conf!(IntConf, SHUFFLE_ZSTD_DICT_SIZE);
+define_conf!(IntConf, IPC_MAX_BATCH_MEM_SIZE);
+define_conf!(LongConf, BROADCAST_MAX_BYTES_PER_EXEC);
+define_conf!(StringConf, VALIDATE_OUTPUT_OPERATORS);
+define_conf!(BooleanConf, VALIDATE_OUTPUT_LOG_HASH);
+define_conf!(BooleanConf, ELIMINATE_REDUNDANT_SORTS_ENABLE);
+define_conf!(BooleanConf, COLUMN_PRUNING_ENABLE);
+define_conf!(IntConf, SHUFFLE_READ_MAX_RETRIES);
 
 pub trait BooleanConf {
     fn key(&self) -> &'static str;
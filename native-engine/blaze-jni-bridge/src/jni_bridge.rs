@@ -454,6 +454,7 @@ pub struct JavaClasses<'a> {
     pub cBlazeArrowFFIExporter: BlazeArrowFFIExporter<'a>,
     pub cBlazeFSDataInputWrapper: BlazeFSDataInputWrapper<'a>,
     pub cBlazeFSDataOutputWrapper: BlazeFSDataOutputWrapper<'a>,
+    pub cBlazeNativeException: BlazeNativeException<'a>,
 }
 
 #[allow(clippy::non_send_fields_in_send_ty)]
@@ -517,6 +518,7 @@ impl JavaClasses<'static> {
                 cBlazeArrowFFIExporter: BlazeArrowFFIExporter::new(env)?,
                 cBlazeFSDataInputWrapper: BlazeFSDataInputWrapper::new(env)?,
                 cBlazeFSDataOutputWrapper: BlazeFSDataOutputWrapper::new(env)?,
+                cBlazeNativeException: BlazeNativeException::new(env)?,
             };
             log::info!("Initializing JavaClasses finished");
             Ok(java_classes)
@@ -573,6 +575,8 @@ pub struct JniBridge<'a> {
     pub method_getTotalMemoryLimited_ret: ReturnType,
     pub method_getDirectWriteSpillToDiskFile: JStaticMethodID,
     pub method_getDirectWriteSpillToDiskFile_ret: ReturnType,
+    pub method_getIoEncryptionKey: JStaticMethodID,
+    pub method_getIoEncryptionKey_ret: ReturnType,
 }
 impl<'a> JniBridge<'a> {
     pub const SIG_TYPE: &'static str = "org/apache/spark/sql/blaze/JniBridge";
@@ -657,6 +661,12 @@ impl<'a> JniBridge<'a> {
                 "()Ljava/lang/String;",
             )?,
             method_getDirectWriteSpillToDiskFile_ret: ReturnType::Object,
+            method_getIoEncryptionKey: env.get_static_method_id(
+                class,
+                "getIoEncryptionKey",
+                "()[B",
+            )?,
+            method_getIoEncryptionKey_ret: ReturnType::Array,
         })
     }
 }
@@ -1219,8 +1229,12 @@ pub struct SparkUDAFWrapperContext<'a> {
     pub ctor: JMethodID,
     pub method_initialize: JMethodID,
     pub method_initialize_ret: ReturnType,
+    pub method_initializeWithCapacity: JMethodID,
+    pub method_initializeWithCapacity_ret: ReturnType,
     pub method_resize: JMethodID,
     pub method_resize_ret: ReturnType,
+    pub method_fillNullRange: JMethodID,
+    pub method_fillNullRange_ret: ReturnType,
     pub method_numRecords: JMethodID,
     pub method_numRecords_ret: ReturnType,
     pub method_update: JMethodID,
@@ -1229,6 +1243,8 @@ pub struct SparkUDAFWrapperContext<'a> {
     pub method_merge_ret: ReturnType,
     pub method_eval: JMethodID,
     pub method_eval_ret: ReturnType,
+    pub method_compact: JMethodID,
+    pub method_compact_ret: ReturnType,
     pub method_serializeRows: JMethodID,
     pub method_serializeRows_ret: ReturnType,
     pub method_deserializeRows: JMethodID,
@@ -1252,12 +1268,24 @@ impl<'a> SparkUDAFWrapperContext<'a> {
                 "(I)Lorg/apache/spark/sql/blaze/BufferRowsColumn;",
             )?,
             method_initialize_ret: ReturnType::Object,
+            method_initializeWithCapacity: env.get_method_id(
+                class,
+                "initializeWithCapacity",
+                "(II)Lorg/apache/spark/sql/blaze/BufferRowsColumn;",
+            )?,
+            method_initializeWithCapacity_ret: ReturnType::Object,
             method_resize: env.get_method_id(
                 class,
                 "resize",
                 "(Lorg/apache/spark/sql/blaze/BufferRowsColumn;I)V",
             )?,
             method_resize_ret: ReturnType::Primitive(Primitive::Void),
+            method_fillNullRange: env.get_method_id(
+                class,
+                "fillNullRange",
+                "(Lorg/apache/spark/sql/blaze/BufferRowsColumn;II)V",
+            )?,
+            method_fillNullRange_ret: ReturnType::Primitive(Primitive::Void),
             method_numRecords: env.get_method_id(
                 class,
                 "numRecords",
@@ -1282,6 +1310,12 @@ impl<'a> SparkUDAFWrapperContext<'a> {
                 "(Lorg/apache/spark/sql/blaze/BufferRowsColumn;[IJ)V",
             )?,
             method_eval_ret: ReturnType::Primitive(Primitive::Void),
+            method_compact: env.get_method_id(
+                class,
+                "compact",
+                "(Lorg/apache/spark/sql/blaze/BufferRowsColumn;[I)V",
+            )?,
+            method_compact_ret: ReturnType::Primitive(Primitive::Void),
             method_serializeRows: env.get_method_id(
                 class,
                 "serializeRows",
@@ -1494,6 +1528,29 @@ impl<'a> BlazeNativeParquetSinkUtils<'a> {
     }
 }
 
+#[allow(non_snake_case)]
+pub struct BlazeNativeException<'a> {
+    pub class: JClass<'a>,
+    pub method_create: JStaticMethodID,
+    pub method_create_ret: ReturnType,
+}
+impl<'a> BlazeNativeException<'a> {
+    pub const SIG_TYPE: &'static str = "org/apache/spark/sql/blaze/BlazeNativeException";
+
+    pub fn new(env: &JNIEnv<'a>) -> JniResult<BlazeNativeException<'a>> {
+        let class = get_global_jclass(env, Self::SIG_TYPE)?;
+        Ok(BlazeNativeException {
+            class,
+            method_create: env.get_static_method_id(
+                class,
+                "create",
+                "(ILjava/lang/String;Ljava/lang/Throwable;)Lorg/apache/spark/sql/blaze/BlazeNativeException;",
+            )?,
+            method_create_ret: ReturnType::Object,
+        })
+    }
+}
+
 #[allow(non_snake_case)]
 pub struct BlazeBlockObject<'a> {
     pub class: JClass<'a>,
@@ -1513,6 +1570,10 @@ pub struct BlazeBlockObject<'a> {
     pub method_getChannel_ret: ReturnType,
     pub method_throwFetchFailed: JMethodID,
     pub method_throwFetchFailed_ret: ReturnType,
+    pub method_isEncrypted: JMethodID,
+    pub method_isEncrypted_ret: ReturnType,
+    pub method_refetch: JMethodID,
+    pub method_refetch_ret: ReturnType,
 }
 
 impl<'a> BlazeBlockObject<'a> {
@@ -1550,6 +1611,14 @@ impl<'a> BlazeBlockObject<'a> {
                 "(Ljava/lang/String;)V",
             )?,
             method_throwFetchFailed_ret: ReturnType::Primitive(Primitive::Void),
+            method_isEncrypted: env.get_method_id(class, "isEncrypted", "()Z")?,
+            method_isEncrypted_ret: ReturnType::Primitive(Primitive::Boolean),
+            method_refetch: env.get_method_id(
+                class,
+                "refetch",
+                "()Lorg/apache/spark/sql/execution/blaze/shuffle/BlockObject;",
+            )?,
+            method_refetch_ret: ReturnType::Object,
         })
     }
 }
@@ -15,6 +15,7 @@
 //! Serde code to convert from protocol buffers to Rust data structures.
 
 use std::{
+    any::Any,
     convert::{TryFrom, TryInto},
     sync::Arc,
 };
@@ -26,6 +27,7 @@ use arrow::{
     row::{RowConverter, SortField},
 };
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use blaze_jni_bridge::is_jni_bridge_inited;
 use datafusion::{
     common::{stats::Precision, Result, ScalarValue},
     datasource::{
@@ -48,23 +50,33 @@ use datafusion::{
     },
     prelude::create_udf,
 };
-use datafusion_ext_commons::downcast_any;
+use datafusion_ext_commons::{batch_size, downcast_any};
 use datafusion_ext_exprs::{
-    bloom_filter_might_contain::BloomFilterMightContainExpr, cast::TryCastExpr,
-    get_indexed_field::GetIndexedFieldExpr, get_map_value::GetMapValueExpr,
-    named_struct::NamedStructExpr, row_num::RowNumExpr,
+    bloom_filter_might_contain::BloomFilterMightContainExpr,
+    cast::TryCastExpr,
+    collated::CollatedExpr,
+    get_indexed_field::GetIndexedFieldExpr,
+    get_map_value::GetMapValueExpr,
+    named_struct::NamedStructExpr,
+    native_udf::{get_native_udf, NativeUDFExpr, NativeUDFSignature},
+    row_num::RowNumExpr,
+    sequence::NativeSequenceExpr,
+    spark_random::{RandDistribution, SparkRandExpr, SparkUuidExpr},
     spark_scalar_subquery_wrapper::SparkScalarSubqueryWrapperExpr,
-    spark_udf_wrapper::SparkUDFWrapperExpr, string_contains::StringContainsExpr,
-    string_ends_with::StringEndsWithExpr, string_starts_with::StringStartsWithExpr,
+    spark_udf_wrapper::SparkUDFWrapperExpr,
+    string_contains::StringContainsExpr,
+    string_ends_with::StringEndsWithExpr,
+    string_starts_with::StringStartsWithExpr,
+    string_substring_index::NativeSubstringIndexExpr,
 };
 use datafusion_ext_plans::{
     agg::{
-        agg::{create_agg, create_udaf_agg},
+        agg::{create_agg_with_fail_on_overflow, create_struct_agg, create_udaf_agg, Agg},
         AggExecMode, AggExpr, AggFunction, AggMode, GroupingExpr,
     },
     agg_exec::AggExec,
     broadcast_join_build_hash_map_exec::BroadcastJoinBuildHashMapExec,
-    broadcast_join_exec::BroadcastJoinExec,
+    broadcast_join_exec::{push_down_limit_to_join, BroadcastJoinExec},
     debug_exec::DebugExec,
     empty_partitions_exec::EmptyPartitionsExec,
     expand_exec::ExpandExec,
@@ -75,14 +87,20 @@ use datafusion_ext_plans::{
     ipc_reader_exec::IpcReaderExec,
     ipc_writer_exec::IpcWriterExec,
     limit_exec::LimitExec,
+    native_distinct_agg_exec::NativeDistinctAggExec,
+    native_in_subquery_exec::NativeInSubqueryExec,
+    native_range_exec::NativeRangeExec,
+    native_scalar_subquery_exec::NativeScalarSubqueryExec,
     orc_exec::OrcExec,
     parquet_exec::ParquetExec,
     parquet_sink_exec::ParquetSinkExec,
     project_exec::ProjectExec,
     rename_columns_exec::RenameColumnsExec,
     rss_shuffle_writer_exec::RssShuffleWriterExec,
+    scan::bucketing::{BucketId, BucketSpec},
     shuffle::Partitioning,
     shuffle_writer_exec::ShuffleWriterExec,
+    skew_aware_hash_join_exec::SkewAwareHashJoinExec,
     sort_exec::SortExec,
     sort_merge_join_exec::SortMergeJoinExec,
     union_exec::{UnionExec, UnionInput},
@@ -149,6 +167,58 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     .collect::<Result<_, Self::Error>>()?;
                 Ok(Arc::new(FilterExec::try_new(predicates, input)?))
             }
+            PhysicalPlanType::InSubquery(in_subquery) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(in_subquery.input)?;
+                let probe_expr =
+                    try_parse_physical_expr_required(&in_subquery.probe_expr, &input.schema())?;
+                let ipc_reader = arrow::ipc::reader::StreamReader::try_new(
+                    &in_subquery.subquery_result_ipc_bytes[..],
+                    None,
+                )
+                .map_err(|e| proto_error(format!("error deserializing subquery result: {e}")))?;
+                let result_schema = ipc_reader.schema();
+                let result_batches = ipc_reader.collect::<Result<Vec<_>, _>>()?;
+                let subquery_result =
+                    arrow::compute::concat_batches(&result_schema, &result_batches)?;
+                Ok(Arc::new(NativeInSubqueryExec::try_new(
+                    input,
+                    probe_expr,
+                    subquery_result,
+                )?))
+            }
+            PhysicalPlanType::DistinctAgg(distinct_agg) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(distinct_agg.input)?;
+                let key_exprs = distinct_agg
+                    .key_expr
+                    .iter()
+                    .map(|expr| try_parse_physical_expr(expr, &input.schema()))
+                    .collect::<Result<_, Self::Error>>()?;
+                let dedup_predicate = try_parse_physical_expr_required(
+                    &distinct_agg.dedup_predicate,
+                    &input.schema(),
+                )?;
+                Ok(Arc::new(NativeDistinctAggExec::try_new(
+                    input,
+                    key_exprs,
+                    dedup_predicate,
+                )?))
+            }
+            PhysicalPlanType::ScalarSubquery(scalar_subquery) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(scalar_subquery.input)?;
+                let subquery: Arc<dyn ExecutionPlan> =
+                    convert_box_required!(scalar_subquery.subquery)?;
+                Ok(Arc::new(NativeScalarSubqueryExec::try_new(
+                    input,
+                    subquery,
+                    scalar_subquery.field_name.clone(),
+                )?))
+            }
+            PhysicalPlanType::Range(range) => Ok(Arc::new(NativeRangeExec::try_new(
+                range.start,
+                range.end,
+                range.step,
+                batch_size(),
+            )?)),
             PhysicalPlanType::ParquetScan(scan) => {
                 let conf: FileScanConfig = scan.base_conf.as_ref().unwrap().try_into()?;
                 let predicate = scan
@@ -160,11 +230,11 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     .fold(phys_expr::lit(true), |a, b| {
                         Arc::new(BinaryExpr::new(a, Operator::And, b))
                     });
-                Ok(Arc::new(ParquetExec::new(
-                    conf,
-                    scan.fs_resource_id.clone(),
-                    Some(predicate),
-                )))
+                let bucket_spec = scan.base_conf.as_ref().unwrap().bucket_spec.as_ref();
+                Ok(Arc::new(
+                    ParquetExec::new(conf, scan.fs_resource_id.clone(), Some(predicate))
+                        .with_bucket_spec(bucket_spec.map(|spec| spec.into())),
+                ))
             }
             PhysicalPlanType::OrcScan(scan) => {
                 let conf: FileScanConfig = scan.base_conf.as_ref().unwrap().try_into()?;
@@ -177,11 +247,11 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     .fold(phys_expr::lit(true), |a, b| {
                         Arc::new(BinaryExpr::new(a, Operator::And, b))
                     });
-                Ok(Arc::new(OrcExec::new(
-                    conf,
-                    scan.fs_resource_id.clone(),
-                    Some(predicate),
-                )))
+                let bucket_spec = scan.base_conf.as_ref().unwrap().bucket_spec.as_ref();
+                Ok(Arc::new(
+                    OrcExec::new(conf, scan.fs_resource_id.clone(), Some(predicate))
+                        .with_bucket_spec(bucket_spec.map(|spec| spec.into())),
+                ))
             }
             PhysicalPlanType::HashJoin(hash_join) => {
                 let schema = Arc::new(convert_required!(hash_join.schema)?);
@@ -204,20 +274,26 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
 
                 let build_side =
                     protobuf::JoinSide::try_from(hash_join.build_side).expect("invalid BuildSide");
-
+                let join_type = join_type
+                    .try_into()
+                    .map_err(|_| proto_error("invalid JoinType"))?;
+                let build_side = build_side
+                    .try_into()
+                    .map_err(|_| proto_error("invalid BuildSide"))?;
+
+                if hash_join.skew_heavy_hitter_fraction > 0.0 {
+                    return Ok(Arc::new(SkewAwareHashJoinExec::try_new(
+                        schema,
+                        left,
+                        right,
+                        on,
+                        join_type,
+                        build_side,
+                        hash_join.skew_heavy_hitter_fraction,
+                    )?));
+                }
                 Ok(Arc::new(BroadcastJoinExec::try_new(
-                    schema,
-                    left,
-                    right,
-                    on,
-                    join_type
-                        .try_into()
-                        .map_err(|_| proto_error("invalid JoinType"))?,
-                    build_side
-                        .try_into()
-                        .map_err(|_| proto_error("invalid BuildSide"))?,
-                    false,
-                    None,
+                    schema, left, right, on, join_type, build_side, false, None,
                 )?))
             }
             PhysicalPlanType::SortMergeJoin(sort_merge_join) => {
@@ -298,11 +374,15 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
             }
             PhysicalPlanType::IpcReader(ipc_reader) => {
                 let schema = Arc::new(convert_required!(ipc_reader.schema)?);
-                Ok(Arc::new(IpcReaderExec::new(
+                let mut ipc_reader_exec = IpcReaderExec::new(
                     ipc_reader.num_partitions as usize,
                     ipc_reader.ipc_provider_resource_id.clone(),
                     schema,
-                )))
+                );
+                if let Some(statistics) = &ipc_reader.statistics {
+                    ipc_reader_exec = ipc_reader_exec.with_statistics(statistics.try_into()?);
+                }
+                Ok(Arc::new(ipc_reader_exec))
             }
             PhysicalPlanType::Debug(debug) => {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(debug.input)?;
@@ -455,29 +535,7 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                                 ));
                             }
                         };
-
-                        let agg_function = protobuf::AggFunction::try_from(agg_node.agg_function)
-                            .expect("invalid AggFunction");
-                        let agg_children_exprs = agg_node
-                            .children
-                            .iter()
-                            .map(|expr| try_parse_physical_expr(expr, &input_schema))
-                            .collect::<Result<Vec<_>, _>>()?;
-                        let return_type = convert_required!(agg_node.return_type)?;
-
-                        let agg = match AggFunction::from(agg_function) {
-                            AggFunction::Udaf => {
-                                let udaf = agg_node.udaf.as_ref().unwrap();
-                                let serialized = udaf.serialized.clone();
-                                create_udaf_agg(serialized, return_type, agg_children_exprs)?
-                            }
-                            _ => create_agg(
-                                AggFunction::from(agg_function),
-                                &agg_children_exprs,
-                                &input_schema,
-                                return_type,
-                            )?,
-                        };
+                        let agg = try_parse_agg(agg_node, &input_schema)?;
 
                         Ok(AggExpr {
                             agg,
@@ -497,6 +555,7 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
             }
             PhysicalPlanType::Limit(limit) => {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(limit.input)?;
+                let input = push_down_limit_to_join(input, limit.limit as usize)?;
                 Ok(Arc::new(LimitExec::new(input, limit.limit)))
             }
             PhysicalPlanType::FfiReader(ffi_reader) => {
@@ -573,6 +632,9 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                                 protobuf::AggFunction::Count => {
                                     WindowFunction::Agg(AggFunction::Count)
                                 }
+                                protobuf::AggFunction::CountPerColumn => {
+                                    WindowFunction::Agg(AggFunction::CountPerColumn)
+                                }
                                 protobuf::AggFunction::CollectList => {
                                     WindowFunction::Agg(AggFunction::CollectList)
                                 }
@@ -585,9 +647,56 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                                 protobuf::AggFunction::FirstIgnoresNull => {
                                     WindowFunction::Agg(AggFunction::FirstIgnoresNull)
                                 }
+                                protobuf::AggFunction::AnyValue => {
+                                    WindowFunction::Agg(AggFunction::AnyValue)
+                                }
                                 protobuf::AggFunction::BloomFilter => {
                                     WindowFunction::Agg(AggFunction::BloomFilter)
                                 }
+                                protobuf::AggFunction::Mode => {
+                                    WindowFunction::Agg(AggFunction::Mode)
+                                }
+                                protobuf::AggFunction::SumList => {
+                                    WindowFunction::Agg(AggFunction::SumList)
+                                }
+                                protobuf::AggFunction::ReservoirSample => {
+                                    WindowFunction::Agg(AggFunction::ReservoirSample)
+                                }
+                                protobuf::AggFunction::Geomean => {
+                                    WindowFunction::Agg(AggFunction::GeoMean)
+                                }
+                                protobuf::AggFunction::Product => {
+                                    WindowFunction::Agg(AggFunction::Product)
+                                }
+                                protobuf::AggFunction::BoolAnd => {
+                                    WindowFunction::Agg(AggFunction::BoolAnd)
+                                }
+                                protobuf::AggFunction::BoolOr => {
+                                    WindowFunction::Agg(AggFunction::BoolOr)
+                                }
+                                protobuf::AggFunction::StructAgg => {
+                                    return Err(proto_error(
+                                        "struct_agg is not supported in window functions",
+                                    ));
+                                }
+                                protobuf::AggFunction::RegrCount => {
+                                    WindowFunction::Agg(AggFunction::RegrCount)
+                                }
+                                protobuf::AggFunction::RegrAvgX => {
+                                    WindowFunction::Agg(AggFunction::RegrAvgX)
+                                }
+                                protobuf::AggFunction::RegrAvgY => {
+                                    WindowFunction::Agg(AggFunction::RegrAvgY)
+                                }
+                                protobuf::AggFunction::RegrSlope => {
+                                    WindowFunction::Agg(AggFunction::RegrSlope)
+                                }
+                                protobuf::AggFunction::RegrIntercept => {
+                                    WindowFunction::Agg(AggFunction::RegrIntercept)
+                                }
+                                protobuf::AggFunction::RegrR2 => {
+                                    WindowFunction::Agg(AggFunction::RegrR2)
+                                }
                                 protobuf::AggFunction::BrickhouseCollect => {
                                     WindowFunction::Agg(AggFunction::BrickhouseCollect)
                                 }
@@ -599,12 +708,10 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                                 }
                             },
                         };
-                        Ok::<_, Self::Error>(WindowExpr::new(
-                            window_func,
-                            children,
-                            field,
-                            return_type,
-                        ))
+                        Ok::<_, Self::Error>(
+                            WindowExpr::new(window_func, children, field, return_type)
+                                .with_fail_on_overflow(w.fail_on_overflow),
+                        )
                     })
                     .collect::<Result<Vec<_>, _>>()?;
 
@@ -692,6 +799,7 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                         children,
                     )?,
                     GenerateFunction::Udtf => {
+                        ensure_jvm_available("SparkUDTFWrapper")?;
                         let udtf = pb_generator.udtf.as_ref().unwrap();
                         let serialized = udtf.serialized.clone();
                         let return_schema = Arc::new(convert_required!(udtf.return_schema)?);
@@ -962,17 +1070,42 @@ fn try_parse_physical_expr(
                     convert_required!(e.return_type)?,
                 ))
             }
-            ExprType::SparkUdfWrapperExpr(e) => Arc::new(SparkUDFWrapperExpr::try_new(
-                e.serialized.clone(),
-                convert_required!(e.return_type)?,
-                e.return_nullable,
-                e.params
+            ExprType::SparkUdfWrapperExpr(e) => {
+                let params = e
+                    .params
                     .iter()
                     .map(|x| try_parse_physical_expr(x, input_schema))
-                    .collect::<Result<Vec<_>, _>>()?,
-                e.expr_string.clone(),
-            )?),
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // udfs registered natively with the same signature are
+                // evaluated without jni
+                let param_types = params
+                    .iter()
+                    .map(|param| param.data_type(input_schema))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let return_type = convert_required!(e.return_type)?;
+                let signature = NativeUDFSignature::new(param_types, return_type);
+                if let Some(func) = get_native_udf(&e.udf_name, &signature) {
+                    Arc::new(NativeUDFExpr::new(
+                        e.udf_name.clone(),
+                        func,
+                        signature.return_type,
+                        e.return_nullable,
+                        params,
+                    ))
+                } else {
+                    ensure_jvm_available("SparkUDFWrapperExpr")?;
+                    Arc::new(SparkUDFWrapperExpr::try_new(
+                        e.serialized.clone(),
+                        convert_required!(e.return_type)?,
+                        e.return_nullable,
+                        params,
+                        e.expr_string.clone(),
+                    )?)
+                }
+            }
             ExprType::SparkScalarSubqueryWrapperExpr(e) => {
+                ensure_jvm_available("SparkScalarSubqueryWrapperExpr")?;
                 Arc::new(SparkScalarSubqueryWrapperExpr::try_new(
                     e.serialized.clone(),
                     convert_required!(e.return_type)?,
@@ -1001,12 +1134,39 @@ fn try_parse_physical_expr(
                 let expr = try_parse_physical_expr_box_required(&e.expr, input_schema)?;
                 Arc::new(StringContainsExpr::new(expr, e.infix.clone()))
             }
+            ExprType::StringSubstringIndexExpr(e) => {
+                let expr = try_parse_physical_expr_box_required(&e.expr, input_schema)?;
+                Arc::new(NativeSubstringIndexExpr::new(expr, e.delim.clone(), e.count))
+            }
             ExprType::RowNumExpr(_) => Arc::new(RowNumExpr::default()),
             ExprType::BloomFilterMightContainExpr(e) => Arc::new(BloomFilterMightContainExpr::new(
                 e.uuid.clone(),
                 try_parse_physical_expr_box_required(&e.bloom_filter_expr, input_schema)?,
                 try_parse_physical_expr_box_required(&e.value_expr, input_schema)?,
             )),
+            ExprType::CollatedExpr(e) => Arc::new(CollatedExpr::new(
+                try_parse_physical_expr_box_required(&e.expr, input_schema)?,
+                protobuf::Collation::try_from(e.collation)
+                    .expect("invalid Collation")
+                    .into(),
+            )),
+            ExprType::SparkRandExpr(e) => {
+                let distribution = if e.gaussian {
+                    RandDistribution::Gaussian
+                } else {
+                    RandDistribution::Uniform
+                };
+                Arc::new(SparkRandExpr::new(e.seed, distribution))
+            }
+            ExprType::SparkUuidExpr(e) => Arc::new(SparkUuidExpr::new(e.seed)),
+            ExprType::SparkSequenceExpr(e) => Arc::new(NativeSequenceExpr::new(
+                try_parse_physical_expr_box_required(&e.start, input_schema)?,
+                try_parse_physical_expr_box_required(&e.stop, input_schema)?,
+                e.step
+                    .as_ref()
+                    .map(|step| try_parse_physical_expr(step, input_schema))
+                    .transpose()?,
+            )),
             ExprType::ScAndExpr(e) => {
                 let l = try_parse_physical_expr_box_required(&e.left, input_schema)?;
                 let r = try_parse_physical_expr_box_required(&e.right, input_schema)?;
@@ -1039,6 +1199,75 @@ fn try_parse_physical_expr(
     Ok(pexpr)
 }
 
+fn try_parse_agg(
+    agg_node: &protobuf::PhysicalAggExprNode,
+    input_schema: &SchemaRef,
+) -> Result<Arc<dyn Agg>, PlanSerDeError> {
+    let agg_function =
+        protobuf::AggFunction::try_from(agg_node.agg_function).expect("invalid AggFunction");
+    let return_type = convert_required!(agg_node.return_type)?;
+
+    Ok(match AggFunction::from(agg_function) {
+        AggFunction::Udaf => {
+            ensure_jvm_available("SparkUDAFWrapper")?;
+            let agg_children_exprs = agg_node
+                .children
+                .iter()
+                .map(|expr| try_parse_physical_expr(expr, input_schema))
+                .collect::<Result<Vec<_>, _>>()?;
+            let udaf = agg_node.udaf.as_ref().unwrap();
+            let serialized = udaf.serialized.clone();
+            let initial_capacity =
+                (udaf.initial_capacity > 0).then_some(udaf.initial_capacity as usize);
+            create_udaf_agg(
+                serialized,
+                return_type,
+                agg_children_exprs,
+                initial_capacity,
+            )?
+        }
+        AggFunction::StructAgg => {
+            // children are inner aggregates instead of input exprs
+            let inner_aggs = agg_node
+                .children
+                .iter()
+                .map(|expr| match &expr.expr_type {
+                    Some(ExprType::AggExpr(inner_agg_node)) => {
+                        try_parse_agg(inner_agg_node, input_schema)
+                    }
+                    _ => Err(proto_error("struct_agg expects aggregate children")),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            create_struct_agg(inner_aggs, return_type)?
+        }
+        agg_function => {
+            let agg_children_exprs = agg_node
+                .children
+                .iter()
+                .map(|expr| try_parse_physical_expr(expr, input_schema))
+                .collect::<Result<Vec<_>, _>>()?;
+            create_agg_with_fail_on_overflow(
+                agg_function,
+                &agg_children_exprs,
+                input_schema,
+                return_type,
+                agg_node.fail_on_overflow,
+            )?
+        }
+    })
+}
+
+// jvm-backed wrappers call back into spark, so plans containing them are
+// rejected when the engine is embedded without a JVM
+fn ensure_jvm_available(name: &str) -> Result<(), PlanSerDeError> {
+    if !is_jni_bridge_inited() {
+        return Err(PlanSerDeError::NotImplemented(format!(
+            "{name} is not supported when running without a JVM"
+        )));
+    }
+    Ok(())
+}
+
 fn try_parse_physical_expr_required(
     proto: &Option<protobuf::PhysicalExprNode>,
     input_schema: &SchemaRef,
@@ -1204,11 +1433,19 @@ impl TryFrom<&protobuf::PartitionedFile> for PartitionedFile {
                 .collect::<Result<Vec<_>, _>>()?,
             range: val.range.as_ref().map(|v| v.try_into()).transpose()?,
             statistics: None,
-            extensions: None,
+            extensions: val.bucket_id.map(|bucket_id| {
+                Arc::new(BucketId(bucket_id as usize)) as Arc<dyn Any + Send + Sync>
+            }),
         })
     }
 }
 
+impl From<&protobuf::BucketSpec> for BucketSpec {
+    fn from(val: &protobuf::BucketSpec) -> Self {
+        BucketSpec::new(val.bucket_columns.clone(), val.num_buckets as usize)
+    }
+}
+
 impl TryFrom<&protobuf::FileRange> for FileRange {
     type Error = PlanSerDeError;
 
@@ -1231,22 +1468,14 @@ impl TryFrom<&protobuf::FileGroup> for Vec<PartitionedFile> {
     }
 }
 
-impl From<&protobuf::ColumnStats> for ColumnStatistics {
-    fn from(cs: &protobuf::ColumnStats) -> ColumnStatistics {
-        ColumnStatistics {
-            null_count: Precision::Exact(cs.null_count as usize),
-            max_value: cs
-                .max_value
-                .as_ref()
-                .map(|m| Precision::Exact(m.try_into().unwrap()))
-                .unwrap_or(Precision::Absent),
-            min_value: cs
-                .min_value
-                .as_ref()
-                .map(|m| Precision::Exact(m.try_into().unwrap()))
-                .unwrap_or(Precision::Absent),
-            distinct_count: Precision::Exact(cs.distinct_count as usize),
-        }
+fn convert_precision<T: std::fmt::Debug + Clone + PartialEq + Eq + PartialOrd>(
+    value: Option<T>,
+    is_exact: bool,
+) -> Precision<T> {
+    match value {
+        Some(value) if is_exact => Precision::Exact(value),
+        Some(value) => Precision::Inexact(value),
+        None => Precision::Absent,
     }
 }
 
@@ -1254,14 +1483,31 @@ impl TryInto<Statistics> for &protobuf::Statistics {
     type Error = PlanSerDeError;
 
     fn try_into(self) -> Result<Statistics, Self::Error> {
+        let is_exact = self.is_exact;
         let column_statistics = self
             .column_stats
             .iter()
-            .map(|s| s.into())
-            .collect::<Vec<_>>();
+            .map(|cs| -> Result<ColumnStatistics, PlanSerDeError> {
+                Ok(ColumnStatistics {
+                    null_count: convert_precision(cs.null_count.map(|n| n as usize), is_exact),
+                    max_value: convert_precision(
+                        cs.max_value.as_ref().map(|v| v.try_into()).transpose()?,
+                        is_exact,
+                    ),
+                    min_value: convert_precision(
+                        cs.min_value.as_ref().map(|v| v.try_into()).transpose()?,
+                        is_exact,
+                    ),
+                    distinct_count: convert_precision(
+                        cs.distinct_count.map(|n| n as usize),
+                        is_exact,
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(Statistics {
-            num_rows: Precision::Exact(self.num_rows as usize),
-            total_byte_size: Precision::Exact(self.total_byte_size as usize),
+            num_rows: convert_precision(self.num_rows.map(|n| n as usize), is_exact),
+            total_byte_size: convert_precision(self.total_byte_size.map(|n| n as usize), is_exact),
             // No column statistic (None) is encoded with empty array
             column_statistics,
         })
@@ -1285,7 +1531,7 @@ impl TryInto<FileScanConfig> for &protobuf::FileScanExecConf {
         };
         let partition_schema: SchemaRef = Arc::new(convert_required!(self.partition_schema)?);
         let mut statistics: Statistics = convert_required!(self.statistics)?;
-        if statistics.column_statistics.is_empty() {
+        if statistics.column_statistics.len() != schema.fields().len() {
             statistics.column_statistics = schema
                 .fields()
                 .iter()
@@ -1323,3 +1569,331 @@ impl TryInto<FileScanConfig> for &protobuf::FileScanExecConf {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Int32Type, Schema},
+    };
+    use datafusion::{
+        common::stats::Precision,
+        datasource::physical_plan::FileScanConfig,
+        physical_expr::expressions::Column,
+        physical_plan::{ExecutionPlan, Partitioning},
+        prelude::SessionContext,
+    };
+    use datafusion_ext_exprs::{
+        native_udf::{register_native_udf, NativeUDFExpr, NativeUDFSignature},
+        sequence::NativeSequenceExpr,
+    };
+    use datafusion_ext_plans::{
+        ipc_reader_exec::IpcReaderExec, native_range_exec::NativeRangeExec,
+    };
+
+    use crate::{error::PlanSerDeError, from_proto::try_parse_physical_expr, protobuf};
+
+    fn int32_schema(names: &[&str]) -> protobuf::Schema {
+        protobuf::Schema {
+            columns: names
+                .iter()
+                .map(|&name| protobuf::Field {
+                    name: name.to_string(),
+                    arrow_type: Some(protobuf::ArrowType {
+                        arrow_type_enum: Some(protobuf::arrow_type::ArrowTypeEnum::Int32(
+                            protobuf::EmptyMessage {},
+                        )),
+                    }),
+                    nullable: true,
+                    children: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn statistics(is_exact: bool) -> protobuf::Statistics {
+        protobuf::Statistics {
+            num_rows: Some(10000),
+            total_byte_size: None,
+            column_stats: vec![
+                protobuf::ColumnStats {
+                    null_count: Some(10),
+                    distinct_count: Some(100),
+                    ..Default::default()
+                },
+                protobuf::ColumnStats::default(),
+            ],
+            is_exact,
+        }
+    }
+
+    #[test]
+    fn test_ipc_reader_with_statistics() -> Result<(), PlanSerDeError> {
+        let plan_node = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(protobuf::physical_plan_node::PhysicalPlanType::IpcReader(
+                protobuf::IpcReaderExecNode {
+                    num_partitions: 1,
+                    schema: Some(int32_schema(&["a", "b"])),
+                    ipc_provider_resource_id: "test".to_string(),
+                    statistics: Some(statistics(false)),
+                },
+            )),
+        };
+        let plan: Arc<dyn ExecutionPlan> = (&plan_node).try_into()?;
+        assert!(plan.as_any().is::<IpcReaderExec>());
+
+        let statistics = plan.statistics()?;
+        assert_eq!(statistics.num_rows, Precision::Inexact(10000));
+        assert_eq!(statistics.total_byte_size, Precision::Absent);
+        assert_eq!(
+            statistics.column_statistics[0].null_count,
+            Precision::Inexact(10)
+        );
+        assert_eq!(
+            statistics.column_statistics[0].distinct_count,
+            Precision::Inexact(100)
+        );
+        assert_eq!(
+            statistics.column_statistics[1].distinct_count,
+            Precision::Absent
+        );
+
+        // missing statistics are treated as unknown
+        let plan_node = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(protobuf::physical_plan_node::PhysicalPlanType::IpcReader(
+                protobuf::IpcReaderExecNode {
+                    num_partitions: 1,
+                    schema: Some(int32_schema(&["a", "b"])),
+                    ipc_provider_resource_id: "test".to_string(),
+                    statistics: None,
+                },
+            )),
+        };
+        let plan: Arc<dyn ExecutionPlan> = (&plan_node).try_into()?;
+        assert_eq!(plan.statistics()?.num_rows, Precision::Absent);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_scan_conf_with_statistics() -> Result<(), PlanSerDeError> {
+        let mut conf = protobuf::FileScanExecConf {
+            num_partitions: 1,
+            partition_index: 0,
+            file_group: Some(protobuf::FileGroup::default()),
+            schema: Some(int32_schema(&["a", "b"])),
+            projection: vec![1, 0],
+            statistics: Some(statistics(true)),
+            partition_schema: Some(int32_schema(&[])),
+            ..Default::default()
+        };
+        let file_scan_config: FileScanConfig = (&conf).try_into()?;
+        let (_, projected_statistics, _) = file_scan_config.project();
+        assert_eq!(projected_statistics.num_rows, Precision::Exact(10000));
+        assert_eq!(
+            projected_statistics.column_statistics[1].distinct_count,
+            Precision::Exact(100),
+        );
+        assert_eq!(
+            projected_statistics.column_statistics[0].distinct_count,
+            Precision::Absent,
+        );
+
+        // default statistics sent by old planners carry no values
+        conf.statistics = Some(protobuf::Statistics::default());
+        let file_scan_config: FileScanConfig = (&conf).try_into()?;
+        assert_eq!(file_scan_config.statistics.num_rows, Precision::Absent);
+        assert_eq!(file_scan_config.statistics.column_statistics.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_scan_with_bucket_spec() -> Result<(), PlanSerDeError> {
+        let parquet_scan = |bucket_ids: &[i32]| -> Result<Arc<dyn ExecutionPlan>, PlanSerDeError> {
+            let files = bucket_ids
+                .iter()
+                .map(|&bucket_id| protobuf::PartitionedFile {
+                    path: format!("/tmp/{bucket_id:05}.parquet"),
+                    bucket_id: Some(bucket_id),
+                    ..Default::default()
+                })
+                .collect();
+            let plan_node = protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(
+                    protobuf::physical_plan_node::PhysicalPlanType::ParquetScan(
+                        protobuf::ParquetScanExecNode {
+                            base_conf: Some(protobuf::FileScanExecConf {
+                                num_partitions: 4,
+                                partition_index: 1,
+                                file_group: Some(protobuf::FileGroup { files }),
+                                schema: Some(int32_schema(&["a", "b"])),
+                                statistics: Some(statistics(false)),
+                                partition_schema: Some(int32_schema(&[])),
+                                bucket_spec: Some(protobuf::BucketSpec {
+                                    bucket_columns: vec!["b".to_string()],
+                                    num_buckets: 8,
+                                }),
+                                ..Default::default()
+                            }),
+                            pruning_predicates: vec![],
+                            fs_resource_id: "test".to_string(),
+                        },
+                    ),
+                ),
+            };
+            (&plan_node).try_into()
+        };
+
+        // 8 buckets are coalesced into 4 partitions
+        let plan = parquet_scan(&[1, 5])?;
+        match plan.output_partitioning() {
+            Partitioning::Hash(exprs, 4) => {
+                let column = exprs[0].as_any().downcast_ref::<Column>();
+                assert_eq!(column.map(|c| c.name()), Some("b"));
+            }
+            partitioning => panic!("unexpected partitioning: {partitioning:?}"),
+        }
+
+        // files of other buckets are rejected
+        let plan = parquet_scan(&[1, 2])?;
+        let err = plan
+            .execute(1, SessionContext::new().task_ctx())
+            .err()
+            .expect("expect bucket id mismatch");
+        assert!(err.to_string().contains("file of bucket 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_udf_matched_by_signature() -> Result<(), PlanSerDeError> {
+        let input_schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let signature = NativeUDFSignature::new(vec![DataType::Int32], DataType::Int32);
+        register_native_udf(
+            "test_serde_identity",
+            signature,
+            Arc::new(|batch: &RecordBatch| Ok(batch.clone())),
+        )?;
+        let udf_expr = |udf_name: &str, return_type: protobuf::ArrowType| {
+            let column = protobuf::PhysicalExprNode {
+                expr_type: Some(protobuf::physical_expr_node::ExprType::Column(
+                    protobuf::PhysicalColumn {
+                        name: "a".to_string(),
+                        index: 0,
+                    },
+                )),
+            };
+            protobuf::PhysicalExprNode {
+                expr_type: Some(protobuf::physical_expr_node::ExprType::SparkUdfWrapperExpr(
+                    protobuf::PhysicalSparkUdfWrapperExprNode {
+                        return_type: Some(return_type),
+                        return_nullable: true,
+                        params: vec![column],
+                        udf_name: udf_name.to_string(),
+                        ..Default::default()
+                    },
+                )),
+            }
+        };
+        let int32_type = int32_schema(&["a"]).columns[0].arrow_type.clone().unwrap();
+        let int64_type = protobuf::ArrowType {
+            arrow_type_enum: Some(protobuf::arrow_type::ArrowTypeEnum::Int64(
+                protobuf::EmptyMessage {},
+            )),
+        };
+
+        let expr = try_parse_physical_expr(
+            &udf_expr("test_serde_identity", int32_type.clone()),
+            &input_schema,
+        )?;
+        assert!(expr.as_any().downcast_ref::<NativeUDFExpr>().is_some());
+
+        // udfs of other names or signatures fall back to jni, which is not
+        // available in tests
+        for (udf_name, return_type) in [
+            ("", int32_type.clone()),
+            ("test_serde_not_registered", int32_type),
+            ("test_serde_identity", int64_type),
+        ] {
+            let expr = try_parse_physical_expr(&udf_expr(udf_name, return_type), &input_schema);
+            assert!(matches!(expr, Err(PlanSerDeError::NotImplemented(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<(), PlanSerDeError> {
+        let plan_node = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(protobuf::physical_plan_node::PhysicalPlanType::Range(
+                protobuf::RangeExecNode {
+                    start: 10,
+                    end: 0,
+                    step: -4,
+                },
+            )),
+        };
+        let plan: Arc<dyn ExecutionPlan> = (&plan_node).try_into()?;
+        let range = plan.as_any().downcast_ref::<NativeRangeExec>().unwrap();
+        assert_eq!(range.num_rows(), 3);
+        assert_eq!(plan.schema().field(0).name(), "id");
+
+        // zero step is rejected
+        let plan_node = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(protobuf::physical_plan_node::PhysicalPlanType::Range(
+                protobuf::RangeExecNode {
+                    start: 0,
+                    end: 10,
+                    step: 0,
+                },
+            )),
+        };
+        let plan: Result<Arc<dyn ExecutionPlan>, _> = (&plan_node).try_into();
+        assert!(plan.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence() -> Result<(), PlanSerDeError> {
+        let input_schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ]));
+        let column = |name: &str, index: u32| {
+            Box::new(protobuf::PhysicalExprNode {
+                expr_type: Some(protobuf::physical_expr_node::ExprType::Column(
+                    protobuf::PhysicalColumn {
+                        name: name.to_string(),
+                        index,
+                    },
+                )),
+            })
+        };
+        let expr_node = protobuf::PhysicalExprNode {
+            expr_type: Some(protobuf::physical_expr_node::ExprType::SparkSequenceExpr(
+                protobuf::SparkSequenceExprNode {
+                    start: Some(column("a", 0)),
+                    stop: Some(column("b", 1)),
+                    step: None,
+                },
+            )),
+        };
+        let expr = try_parse_physical_expr(&expr_node, &input_schema)?;
+        assert!(expr.as_any().downcast_ref::<NativeSequenceExpr>().is_some());
+
+        let batch = RecordBatch::try_new(
+            input_schema,
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), Some(3), None])),
+                Arc::new(Int32Array::from(vec![Some(3), Some(1), Some(1)])),
+            ],
+        )?;
+        let output = expr.evaluate(&batch)?.into_array(batch.num_rows())?;
+        let output = output
+            .as_list::<i32>()
+            .iter()
+            .map(|seq| seq.map(|seq| seq.as_primitive::<Int32Type>().values().to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(output, vec![Some(vec![1, 2, 3]), Some(vec![3, 2, 1]), None]);
+        Ok(())
+    }
+}
@@ -16,6 +16,7 @@ use std::sync::Arc;
 
 use arrow::datatypes::{DataType, Field, Fields, IntervalUnit, Schema, TimeUnit};
 use datafusion::{common::JoinSide, logical_expr::Operator, scalar::ScalarValue};
+use datafusion_ext_commons::arrow::collation::Collation;
 use datafusion_ext_plans::{agg::AggFunction, joins::join_utils::JoinType};
 
 use crate::error::PlanSerDeError;
@@ -123,6 +124,16 @@ impl From<protobuf::JoinSide> for JoinSide {
     }
 }
 
+impl From<protobuf::Collation> for Collation {
+    fn from(c: protobuf::Collation) -> Self {
+        match c {
+            protobuf::Collation::Binary => Collation::Binary,
+            protobuf::Collation::AsciiCaseInsensitive => Collation::AsciiCaseInsensitive,
+            protobuf::Collation::UnicodeCaseInsensitive => Collation::UnicodeCaseInsensitive,
+        }
+    }
+}
+
 impl From<protobuf::AggFunction> for AggFunction {
     fn from(agg_fun: protobuf::AggFunction) -> AggFunction {
         match agg_fun {
@@ -131,11 +142,27 @@ impl From<protobuf::AggFunction> for AggFunction {
             protobuf::AggFunction::Sum => AggFunction::Sum,
             protobuf::AggFunction::Avg => AggFunction::Avg,
             protobuf::AggFunction::Count => AggFunction::Count,
+            protobuf::AggFunction::CountPerColumn => AggFunction::CountPerColumn,
             protobuf::AggFunction::CollectList => AggFunction::CollectList,
             protobuf::AggFunction::CollectSet => AggFunction::CollectSet,
             protobuf::AggFunction::First => AggFunction::First,
             protobuf::AggFunction::FirstIgnoresNull => AggFunction::FirstIgnoresNull,
+            protobuf::AggFunction::AnyValue => AggFunction::AnyValue,
             protobuf::AggFunction::BloomFilter => AggFunction::BloomFilter,
+            protobuf::AggFunction::Mode => AggFunction::Mode,
+            protobuf::AggFunction::SumList => AggFunction::SumList,
+            protobuf::AggFunction::ReservoirSample => AggFunction::ReservoirSample,
+            protobuf::AggFunction::Geomean => AggFunction::GeoMean,
+            protobuf::AggFunction::Product => AggFunction::Product,
+            protobuf::AggFunction::BoolAnd => AggFunction::BoolAnd,
+            protobuf::AggFunction::BoolOr => AggFunction::BoolOr,
+            protobuf::AggFunction::StructAgg => AggFunction::StructAgg,
+            protobuf::AggFunction::RegrCount => AggFunction::RegrCount,
+            protobuf::AggFunction::RegrAvgX => AggFunction::RegrAvgX,
+            protobuf::AggFunction::RegrAvgY => AggFunction::RegrAvgY,
+            protobuf::AggFunction::RegrSlope => AggFunction::RegrSlope,
+            protobuf::AggFunction::RegrIntercept => AggFunction::RegrIntercept,
+            protobuf::AggFunction::RegrR2 => AggFunction::RegrR2,
             protobuf::AggFunction::BrickhouseCollect => AggFunction::BrickhouseCollect,
             protobuf::AggFunction::BrickhouseCombineUnique => AggFunction::BrickhouseCombineUnique,
             protobuf::AggFunction::Udaf => AggFunction::Udaf,
@@ -211,16 +238,12 @@ impl TryInto<arrow::datatypes::DataType> for &protobuf::arrow_type::ArrowTypeEnu
             arrow_type::ArrowTypeEnum::Duration(time_unit) => {
                 DataType::Duration(protobuf::TimeUnit::try_from_to_arrow(*time_unit)?)
             }
+            // spark timestamps are instants, timezone annotations are dropped so that
+            // timestamps from different sources always hash and compare consistently
             arrow_type::ArrowTypeEnum::Timestamp(protobuf::Timestamp {
                 time_unit,
-                timezone,
-            }) => DataType::Timestamp(
-                protobuf::TimeUnit::try_from_to_arrow(*time_unit)?,
-                match timezone.len() {
-                    0 => None,
-                    _ => Some(timezone.to_owned().into()),
-                },
-            ),
+                timezone: _,
+            }) => DataType::Timestamp(protobuf::TimeUnit::try_from_to_arrow(*time_unit)?, None),
             arrow_type::ArrowTypeEnum::Time32(time_unit) => {
                 DataType::Time32(protobuf::TimeUnit::try_from_to_arrow(*time_unit)?)
             }
@@ -450,7 +473,20 @@ impl TryInto<datafusion::scalar::ScalarValue> for &protobuf::ScalarValue {
             arrow::ipc::reader::StreamReader::try_new(&self.ipc_bytes[..], None)
                 .map_err(|e| proto_error(format!("error deserializing arrow stream: {e}")))?;
         let batch = ipc_reader.next().expect("missing record batch")?;
-        let scalar = ScalarValue::try_from_array(batch.column(0), 0)?;
+        let scalar = match ScalarValue::try_from_array(batch.column(0), 0)? {
+            // drop timezone annotations, see conversion of ArrowTypeEnum::Timestamp
+            ScalarValue::TimestampSecond(v, Some(_)) => ScalarValue::TimestampSecond(v, None),
+            ScalarValue::TimestampMillisecond(v, Some(_)) => {
+                ScalarValue::TimestampMillisecond(v, None)
+            }
+            ScalarValue::TimestampMicrosecond(v, Some(_)) => {
+                ScalarValue::TimestampMicrosecond(v, None)
+            }
+            ScalarValue::TimestampNanosecond(v, Some(_)) => {
+                ScalarValue::TimestampNanosecond(v, None)
+            }
+            scalar => scalar,
+        };
         Ok(scalar)
     }
 }
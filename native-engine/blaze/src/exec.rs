@@ -37,6 +37,13 @@ use once_cell::sync::OnceCell;
 
 use crate::{handle_unwinded_scope, logging::init_logging, rt::NativeExecutionRuntime};
 
+pub fn new_session_context(batch_size: usize) -> Result<SessionContext> {
+    let session_config = SessionConfig::new().with_batch_size(batch_size);
+    let runtime_config = RuntimeConfig::new().with_disk_manager(DiskManagerConfig::Disabled);
+    let runtime = Arc::new(RuntimeEnv::new(runtime_config)?);
+    Ok(SessionContext::new_with_config_rt(session_config, runtime))
+}
+
 #[allow(non_snake_case)]
 #[no_mangle]
 pub extern "system" fn Java_org_apache_spark_sql_blaze_JniBridge_callNative(
@@ -74,13 +81,7 @@ pub extern "system" fn Java_org_apache_spark_sql_blaze_JniBridge_callNative(
                 let memory_fraction = conf::MEMORY_FRACTION.value()?;
                 let batch_size = conf::BATCH_SIZE.value()? as usize;
                 MemManager::init((max_memory as f64 * memory_fraction) as usize);
-
-                let session_config = SessionConfig::new().with_batch_size(batch_size);
-                let runtime_config =
-                    RuntimeConfig::new().with_disk_manager(DiskManagerConfig::Disabled);
-                let runtime = Arc::new(RuntimeEnv::new(runtime_config)?);
-                let session = SessionContext::new_with_config_rt(session_config, runtime);
-                Ok::<_, DataFusionError>(session)
+                new_session_context(batch_size)
             })?;
             Ok::<_, DataFusionError>(())
         })?;
@@ -0,0 +1,444 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C ABI for embedding the native engine without a JVM, e.g. reproducing
+//! operator bugs from a python test harness.
+//!
+//! inputs and outputs are exchanged as arrow C streams. inputs are bound to
+//! the FFIReaderExec nodes of the plan by their resource ids. plans containing
+//! jvm-backed operators (UDF/UDAF/UDTF wrappers) are rejected when loading.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::AssertUnwindSafe,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{RecordBatch, RecordBatchReader},
+    datatypes::SchemaRef,
+    error::ArrowError,
+    ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream},
+};
+use blaze_serde::protobuf::TaskDefinition;
+use datafusion::{
+    common::Result,
+    execution::SendableRecordBatchStream,
+    physical_plan::{metrics::ExecutionPlanMetricsSet, ExecutionPlan},
+    prelude::SessionContext,
+};
+use datafusion_ext_commons::{batch_size, df_execution_err};
+use datafusion_ext_plans::{
+    common::execution_context::{cancel_all_tasks, ExecutionContext},
+    ffi_reader_exec::put_native_input,
+    memmgr::MemManager,
+};
+use futures::StreamExt;
+use once_cell::sync::OnceCell;
+use prost::Message;
+use tokio::runtime::Runtime;
+
+use crate::{exec::new_session_context, logging::init_logging, rt::execute_plan};
+
+static SESSION: OnceCell<SessionContext> = OnceCell::new();
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+pub struct BlazeFfiPlan {
+    partition_id: usize,
+    plan: Arc<dyn ExecutionPlan>,
+}
+
+/// initializes the native environment with the given memory limit in bytes,
+/// must be called before any other functions. returns 0 on success.
+#[no_mangle]
+pub extern "C" fn blaze_ffi_init(memory_limit: u64) -> i32 {
+    ffi_scope(-1, || {
+        SESSION.get_or_try_init(|| {
+            init_logging();
+            MemManager::init(memory_limit as usize);
+            new_session_context(batch_size())
+        })?;
+        Ok(0)
+    })
+}
+
+/// returns the error message of the last failed call on the current thread,
+/// or null if there is none. the message is valid until the next failed call.
+#[no_mangle]
+pub extern "C" fn blaze_ffi_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// decodes a protobuf-encoded TaskDefinition into a plan handle, returns null
+/// on failure. the handle must be released with blaze_ffi_free_plan().
+///
+/// # Safety
+/// task_definition must point to task_definition_len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blaze_ffi_load_plan(
+    task_definition: *const u8,
+    task_definition_len: usize,
+) -> *mut BlazeFfiPlan {
+    ffi_scope(std::ptr::null_mut(), || {
+        let raw_task_definition = std::slice::from_raw_parts(task_definition, task_definition_len);
+        let task_definition = TaskDefinition::decode(raw_task_definition)
+            .or_else(|err| df_execution_err!("cannot decode execution plan: {err:?}"))?;
+        let Some(task_id) = &task_definition.task_id else {
+            return df_execution_err!("task_id is empty");
+        };
+        let Some(plan) = &task_definition.plan else {
+            return df_execution_err!("plan is empty");
+        };
+
+        // jvm-backed operators are rejected by the decoder since
+        // the jni bridge is never initialized in this mode
+        let plan: Arc<dyn ExecutionPlan> = plan
+            .try_into()
+            .or_else(|err| df_execution_err!("cannot create execution plan: {err:?}"))?;
+        let ffi_plan = Box::new(BlazeFfiPlan {
+            partition_id: task_id.partition_id as usize,
+            plan,
+        });
+        Ok(Box::into_raw(ffi_plan))
+    })
+}
+
+/// releases a plan handle returned by blaze_ffi_load_plan().
+///
+/// # Safety
+/// plan must be a handle returned by blaze_ffi_load_plan() and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn blaze_ffi_free_plan(plan: *mut BlazeFfiPlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
+
+/// executes the plan, the i-th input stream is consumed by the FFIReaderExec
+/// node whose resource id is the i-th input id. the ownership of the input
+/// streams is taken even if the call fails. the result stream is written to
+/// output. returns 0 on success.
+///
+/// # Safety
+/// input_ids and inputs must point to num_inputs elements, output must point
+/// to writable memory of an FFI_ArrowArrayStream.
+#[no_mangle]
+pub unsafe extern "C" fn blaze_ffi_execute(
+    plan: *const BlazeFfiPlan,
+    input_ids: *const *const c_char,
+    inputs: *mut FFI_ArrowArrayStream,
+    num_inputs: usize,
+    output: *mut FFI_ArrowArrayStream,
+) -> i32 {
+    ffi_scope(-1, || {
+        // take all input streams before anything can fail
+        let input_streams = (0..num_inputs)
+            .map(|i| std::ptr::replace(inputs.add(i), FFI_ArrowArrayStream::empty()))
+            .collect::<Vec<_>>();
+        let Some(session) = SESSION.get() else {
+            return df_execution_err!("blaze_ffi_init() is not called");
+        };
+        let plan = &*plan;
+
+        for (i, input_stream) in input_streams.into_iter().enumerate() {
+            let input_id = CStr::from_ptr(*input_ids.add(i)).to_string_lossy();
+            let input_reader = ArrowArrayStreamReader::try_new(input_stream)?;
+            put_native_input(input_id.into_owned(), Box::new(input_reader));
+        }
+        let output_reader = PlanOutputReader::try_new(session, plan)?;
+        std::ptr::write(output, FFI_ArrowArrayStream::new(Box::new(output_reader)));
+        Ok(0)
+    })
+}
+
+struct PlanOutputReader {
+    exec_ctx: Arc<ExecutionContext>,
+    schema: SchemaRef,
+    stream: Option<SendableRecordBatchStream>,
+    tokio_runtime: Option<Runtime>,
+}
+
+impl PlanOutputReader {
+    fn try_new(session: &SessionContext, ffi_plan: &BlazeFfiPlan) -> Result<Self> {
+        let exec_ctx = ExecutionContext::new(
+            session.task_ctx(),
+            ffi_plan.partition_id,
+            ffi_plan.plan.schema(),
+            &ExecutionPlanMetricsSet::new(),
+        );
+        let tokio_runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name(format!("blaze-ffi-part-{}", ffi_plan.partition_id))
+            .enable_all()
+            .build()?;
+
+        // streams may spawn tasks when executing, so enter the runtime first
+        let stream = {
+            let _guard = tokio_runtime.enter();
+            execute_plan(&exec_ctx, &ffi_plan.plan)?
+        };
+        Ok(Self {
+            exec_ctx,
+            schema: stream.schema(),
+            stream: Some(stream),
+            tokio_runtime: Some(tokio_runtime),
+        })
+    }
+}
+
+impl Iterator for PlanOutputReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stream = self.stream.as_mut()?;
+        let tokio_runtime = self.tokio_runtime.as_ref()?;
+        let next =
+            std::panic::catch_unwind(AssertUnwindSafe(|| tokio_runtime.block_on(stream.next())))
+                .unwrap_or_else(|err| {
+                    let panic_message =
+                        panic_message::get_panic_message(&err).unwrap_or("unknown error");
+                    Some(df_execution_err!("{panic_message}"))
+                });
+        next.map(|batch| batch.map_err(|err| ArrowError::ExternalError(Box::new(err))))
+    }
+}
+
+impl RecordBatchReader for PlanOutputReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl Drop for PlanOutputReader {
+    fn drop(&mut self) {
+        drop(self.stream.take());
+        cancel_all_tasks(&self.exec_ctx.task_ctx()); // cancel all pending streams
+        if let Some(tokio_runtime) = self.tokio_runtime.take() {
+            tokio_runtime.shutdown_background();
+        }
+    }
+}
+
+fn ffi_scope<T>(on_error: T, scope: impl FnOnce() -> Result<T>) -> T {
+    let error_message = match std::panic::catch_unwind(AssertUnwindSafe(scope)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(err)) => err.to_string(),
+        Err(err) => panic_message::panic_message(&err).to_owned(),
+    };
+    log::error!("blaze ffi call failed: {error_message}");
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = CString::new(error_message.replace('\0', " ")).ok();
+    });
+    on_error
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        ffi::{c_char, CStr, CString},
+        sync::Arc,
+    };
+
+    use arrow::{
+        array::{Int64Array, RecordBatch, RecordBatchIterator},
+        datatypes::{DataType, Field, Schema},
+        ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream},
+    };
+    use blaze_serde::protobuf::{
+        self, arrow_type::ArrowTypeEnum, physical_expr_node::ExprType,
+        physical_plan_node::PhysicalPlanType,
+    };
+    use datafusion::{assert_batches_sorted_eq, common::Result};
+    use prost::Message;
+
+    use crate::ffi::{
+        blaze_ffi_execute, blaze_ffi_free_plan, blaze_ffi_init, blaze_ffi_last_error,
+        blaze_ffi_load_plan,
+    };
+
+    fn int64_type() -> protobuf::ArrowType {
+        protobuf::ArrowType {
+            arrow_type_enum: Some(ArrowTypeEnum::Int64(protobuf::EmptyMessage {})),
+        }
+    }
+
+    fn col(name: &str) -> protobuf::PhysicalExprNode {
+        protobuf::PhysicalExprNode {
+            expr_type: Some(ExprType::Column(protobuf::PhysicalColumn {
+                name: name.to_string(),
+                index: 0,
+            })),
+        }
+    }
+
+    fn agg_node(
+        input: protobuf::PhysicalPlanNode,
+        agg_function: protobuf::AggFunction,
+        agg_child: &str,
+        mode: protobuf::AggMode,
+    ) -> protobuf::PhysicalPlanNode {
+        protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(PhysicalPlanType::Agg(Box::new(protobuf::AggExecNode {
+                input: Some(Box::new(input)),
+                exec_mode: protobuf::AggExecMode::HashAgg as i32,
+                grouping_expr: vec![col("k")],
+                agg_expr: vec![protobuf::PhysicalExprNode {
+                    expr_type: Some(ExprType::AggExpr(Box::new(protobuf::PhysicalAggExprNode {
+                        agg_function: agg_function as i32,
+                        udaf: None,
+                        children: vec![col(agg_child)],
+                        return_type: Some(int64_type()),
+                    }))),
+                }],
+                mode: vec![mode as i32],
+                grouping_expr_name: vec!["k".to_string()],
+                agg_expr_name: vec!["s".to_string()],
+                initial_input_buffer_offset: 1,
+                supports_partial_skipping: false,
+            }))),
+        }
+    }
+
+    // select k, sum(v) as s from input where v is not null group by k
+    fn filter_agg_task(agg_function: protobuf::AggFunction) -> Vec<u8> {
+        let input = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(PhysicalPlanType::FfiReader(protobuf::FfiReaderExecNode {
+                num_partitions: 1,
+                schema: Some(protobuf::Schema {
+                    columns: ["k", "v"]
+                        .iter()
+                        .map(|&name| protobuf::Field {
+                            name: name.to_string(),
+                            arrow_type: Some(Box::new(int64_type())),
+                            nullable: true,
+                            children: vec![],
+                        })
+                        .collect(),
+                }),
+                export_iter_provider_resource_id: "input".to_string(),
+            })),
+        };
+        let filter = protobuf::PhysicalPlanNode {
+            physical_plan_type: Some(PhysicalPlanType::Filter(Box::new(
+                protobuf::FilterExecNode {
+                    input: Some(Box::new(input)),
+                    expr: vec![protobuf::PhysicalExprNode {
+                        expr_type: Some(ExprType::IsNotNullExpr(Box::new(
+                            protobuf::PhysicalIsNotNull {
+                                expr: Some(Box::new(col("v"))),
+                            },
+                        ))),
+                    }],
+                },
+            ))),
+        };
+        let partial = agg_node(filter, agg_function, "v", protobuf::AggMode::Partial);
+        let final_ = agg_node(partial, agg_function, "k", protobuf::AggMode::Final);
+
+        protobuf::TaskDefinition {
+            task_id: Some(protobuf::PartitionId {
+                stage_id: 0,
+                partition_id: 0,
+                task_id: 0,
+            }),
+            plan: Some(final_),
+            output_partitioning: None,
+        }
+        .encode_to_vec()
+    }
+
+    fn last_error() -> String {
+        let message = blaze_ffi_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_filter_agg_through_c_abi() -> Result<()> {
+        assert_eq!(blaze_ffi_init(1 << 30), 0);
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int64, true),
+            Field::new("v", DataType::Int64, true),
+        ]));
+        let batches = vec![
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(vec![1, 2, 1, 3])),
+                    Arc::new(Int64Array::from(vec![Some(10), Some(20), None, None])),
+                ],
+            )?,
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(vec![2, 1])),
+                    Arc::new(Int64Array::from(vec![Some(5), Some(7)])),
+                ],
+            )?,
+        ];
+        let input_reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+        let mut inputs = [FFI_ArrowArrayStream::new(Box::new(input_reader))];
+        let input_id = CString::new("input").unwrap();
+        let input_ids = [input_id.as_ptr()];
+
+        let task = filter_agg_task(protobuf::AggFunction::Sum);
+        let mut output = FFI_ArrowArrayStream::empty();
+        unsafe {
+            let plan = blaze_ffi_load_plan(task.as_ptr(), task.len());
+            assert!(!plan.is_null());
+            let ret = blaze_ffi_execute(
+                plan,
+                input_ids.as_ptr() as *const *const c_char,
+                inputs.as_mut_ptr(),
+                inputs.len(),
+                &mut output,
+            );
+            assert_eq!(ret, 0);
+            blaze_ffi_free_plan(plan);
+        }
+
+        // the output stream outlives the plan handle
+        let output_reader = ArrowArrayStreamReader::try_new(output)?;
+        let output_batches = output_reader.collect::<Result<Vec<_>, _>>()?;
+        let expected = vec![
+            "+---+----+",
+            "| k | s  |",
+            "+---+----+",
+            "| 1 | 17 |",
+            "| 2 | 25 |",
+            "+---+----+",
+        ];
+        assert_batches_sorted_eq!(expected, &output_batches);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_jvm_operators() {
+        assert_eq!(blaze_ffi_init(1 << 30), 0);
+
+        let task = filter_agg_task(protobuf::AggFunction::Udaf);
+        let plan = unsafe { blaze_ffi_load_plan(task.as_ptr(), task.len()) };
+        assert!(plan.is_null());
+        assert!(last_error().contains("SparkUDAFWrapper is not supported"));
+    }
+}
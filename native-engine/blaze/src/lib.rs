@@ -15,6 +15,8 @@
 use std::{any::Any, error::Error, fmt::Debug, panic::AssertUnwindSafe};
 
 use blaze_jni_bridge::*;
+use datafusion::error::DataFusionError;
+use datafusion_ext_commons::error::BlazeError;
 use jni::objects::{JObject, JThrowable};
 
 mod alloc;
@@ -26,10 +28,13 @@ mod rt;
 #[cfg(feature = "http-service")]
 mod http;
 
+#[cfg(feature = "blaze-ffi")]
+pub mod ffi;
+
 fn handle_unwinded(err: Box<dyn Any + Send>) {
     // default handling:
     //  * caused by Interrupted/TaskKilled: do nothing but just print a message.
-    //  * other reasons: wrap it into a RuntimeException and throw.
+    //  * other reasons: wrap it into a BlazeNativeException and throw.
     //  * if another error happens during handling, kill the whole JVM instance.
     let recover = || {
         if !is_task_running() {
@@ -64,11 +69,22 @@ fn handle_unwinded_scope<T: Default, E: Debug>(scope: impl FnOnce() -> Result<T,
 }
 
 fn throw_runtime_exception(msg: &str, cause: JObject) -> datafusion::error::Result<()> {
-    let msg = jni_new_string!(msg)?;
-    let e = jni_new_object!(JavaRuntimeException(msg.as_obj(), cause))?;
+    let err = DataFusionError::Execution(msg.to_string());
+    let e = new_native_exception(msg, err, cause)?;
 
     if let Err(err) = jni_throw!(JThrowable::from(e.as_obj())) {
         jni_fatal_error!("Error throwing RuntimeException, cannot result: {err:?}");
     }
     Ok(())
 }
+
+/// creates a BlazeNativeException with the error code of the classified error
+fn new_native_exception(
+    msg: &str,
+    err: DataFusionError,
+    cause: JObject,
+) -> datafusion::error::Result<jni_bridge::LocalRef> {
+    let classified = BlazeError::classify(err);
+    let msg = jni_new_string!(msg)?;
+    jni_call_static!(BlazeNativeException.create(classified.code(), msg.as_obj(), cause) -> JObject)
+}
@@ -16,6 +16,7 @@ use std::sync::Arc;
 
 use blaze_jni_bridge::{jni_call, jni_new_string};
 use datafusion::{common::Result, physical_plan::ExecutionPlan};
+use datafusion_ext_plans::{project_exec::ProjectExec, validate_output_exec::ValidateOutputExec};
 use jni::objects::JObject;
 
 pub fn update_spark_metric_node(
@@ -26,6 +27,18 @@ pub fn update_spark_metric_node(
         return Ok(());
     }
 
+    // validation wrappers have no corresponding spark node
+    if let Some(validate_exec) = execution_plan.as_any().downcast_ref::<ValidateOutputExec>() {
+        return update_spark_metric_node(metric_node, validate_exec.input().clone());
+    }
+
+    // neither do projects inserted by column pruning
+    if let Some(project) = execution_plan.as_any().downcast_ref::<ProjectExec>() {
+        if project.is_column_pruning() {
+            return update_spark_metric_node(metric_node, project.input().clone());
+        }
+    }
+
     // update current node
     update_metrics(
         metric_node,
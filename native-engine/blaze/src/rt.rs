@@ -20,31 +20,41 @@ use std::{
 
 use arrow::{
     array::{Array, StructArray},
+    datatypes::{DataType, Fields, Schema, SchemaRef},
     ffi::{FFI_ArrowArray, FFI_ArrowSchema},
-    record_batch::RecordBatch,
+    record_batch::{RecordBatch, RecordBatchOptions},
 };
 use blaze_jni_bridge::{
-    conf::{IntConf, SPARK_TASK_CPUS, TOKIO_WORKER_THREADS_PER_CPU},
+    conf::{
+        BooleanConf, IntConf, StringConf, COLUMN_PRUNING_ENABLE, ELIMINATE_REDUNDANT_SORTS_ENABLE,
+        SPARK_TASK_CPUS, TOKIO_WORKER_THREADS_PER_CPU, VALIDATE_OUTPUT_LOG_HASH,
+        VALIDATE_OUTPUT_OPERATORS,
+    },
     is_task_running,
     jni_bridge::JavaClasses,
     jni_call, jni_call_static, jni_convert_byte_array, jni_exception_check, jni_exception_occurred,
-    jni_new_global_ref, jni_new_object, jni_new_string,
+    jni_new_global_ref,
 };
 use blaze_serde::protobuf::TaskDefinition;
 use datafusion::{
     common::Result,
     error::DataFusionError,
-    execution::context::TaskContext,
+    execution::{context::TaskContext, SendableRecordBatchStream},
     physical_plan::{
         displayable, empty::EmptyExec, metrics::ExecutionPlanMetricsSet, ExecutionPlan,
     },
 };
 use datafusion_ext_commons::{df_execution_err, downcast_any};
 use datafusion_ext_plans::{
-    common::execution_context::{cancel_all_tasks, ExecutionContext},
+    common::{
+        column_pruning::prune_plan_columns,
+        execution_context::{cancel_all_tasks, ExecutionContext},
+    },
     ipc_writer_exec::IpcWriterExec,
     parquet_sink_exec::ParquetSinkExec,
     shuffle_writer_exec::ShuffleWriterExec,
+    sort_exec::eliminate_redundant_sorts,
+    validate_output_exec::wrap_validate_output,
 };
 use futures::{FutureExt, StreamExt};
 use jni::objects::{GlobalRef, JObject};
@@ -55,6 +65,7 @@ use crate::{
     handle_unwinded_scope,
     logging::{THREAD_PARTITION_ID, THREAD_STAGE_ID, THREAD_TID},
     metrics::update_spark_metric_node,
+    new_native_exception,
 };
 
 pub struct NativeExecutionRuntime {
@@ -89,6 +100,37 @@ impl NativeExecutionRuntime {
             .try_into()
             .or_else(|err| df_execution_err!("cannot create execution plan: {err:?}"))?;
 
+        // prune columns unused by the plan output, so that scans and joins do
+        // not carry them to the jvm side
+        let execution_plan = if COLUMN_PRUNING_ENABLE.value().unwrap_or(true) {
+            prune_plan_columns(execution_plan)?
+        } else {
+            execution_plan
+        };
+
+        // elide sorts whose input is already ordered, like sorts on the keys of
+        // sort merge joins
+        let execution_plan = if ELIMINATE_REDUNDANT_SORTS_ENABLE.value().unwrap_or(true) {
+            eliminate_redundant_sorts(execution_plan)?
+        } else {
+            execution_plan
+        };
+
+        // wrap operators whose output batches are validated for debugging
+        let validate_operators = VALIDATE_OUTPUT_OPERATORS
+            .value()
+            .unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect::<Vec<_>>();
+        let execution_plan = if !validate_operators.is_empty() {
+            let log_hash = VALIDATE_OUTPUT_LOG_HASH.value().unwrap_or(false);
+            wrap_validate_output(execution_plan, &validate_operators, log_hash)?
+        } else {
+            execution_plan
+        };
+
         let exec_ctx = ExecutionContext::new(
             context.clone(),
             partition_id,
@@ -133,25 +175,11 @@ impl NativeExecutionRuntime {
         let exec_ctx_cloned = exec_ctx.clone();
         let native_wrapper_cloned = native_wrapper.clone();
         let consume_stream = async move {
-            // execute plan to output stream
-            let displayable = displayable(execution_plan_cloned.as_ref())
-                .set_show_schema(true)
-                .indent(true)
-                .to_string();
-            log::info!("start executing plan:\n{displayable}");
-            let mut stream = exec_ctx_cloned.execute(&execution_plan_cloned)?;
-
-            // coalesce output stream if necessary
-            if downcast_any!(execution_plan_cloned, EmptyExec).is_err()
-                && downcast_any!(execution_plan_cloned, ParquetSinkExec).is_err()
-                && downcast_any!(execution_plan_cloned, IpcWriterExec).is_err()
-                && downcast_any!(execution_plan_cloned, ShuffleWriterExec).is_err()
-            {
-                stream = exec_ctx_cloned.coalesce_with_default_batch_size(stream);
-            }
+            let mut stream = execute_plan(&exec_ctx_cloned, &execution_plan_cloned)?;
 
-            // init ffi schema
-            let ffi_schema = FFI_ArrowSchema::try_from(stream.schema().as_ref())?;
+            // init ffi schema, dictionary-encoded columns are exported in plain
+            let output_schema = decode_dictionary_schema(&stream.schema());
+            let ffi_schema = FFI_ArrowSchema::try_from(output_schema.as_ref())?;
             jni_call!(BlazeCallNativeWrapper(native_wrapper_cloned.as_obj())
                 .importSchema(&ffi_schema as *const FFI_ArrowSchema as i64) -> ()
             )?;
@@ -168,6 +196,7 @@ impl NativeExecutionRuntime {
                 .transpose()
                 .or_else(|err| df_execution_err!("{err}"))?
             {
+                let batch = decode_dictionary_batch(batch, &output_schema)?;
                 batch_sender
                     .send(Ok(Some(batch)))
                     .or_else(|err| df_execution_err!("send batch error: {err}"))?;
@@ -204,6 +233,7 @@ impl NativeExecutionRuntime {
                     set_error(
                         &native_wrapper_cloned,
                         &format!("task panics: {err}"),
+                        err,
                         cause.map(|e| e.as_obj()),
                     )?;
                     log::info!("task exited abnormally.");
@@ -248,6 +278,7 @@ impl NativeExecutionRuntime {
                 let _ = set_error(
                     &self.native_wrapper,
                     &format!("poll record batch error: {err}"),
+                    err,
                     None,
                 );
                 return false;
@@ -278,12 +309,81 @@ impl NativeExecutionRuntime {
     }
 }
 
-fn set_error(native_wrapper: &GlobalRef, message: &str, cause: Option<JObject>) -> Result<()> {
-    let message = jni_new_string!(message.to_owned())?;
-    let e = jni_new_object!(JavaRuntimeException(
-        message.as_obj(),
-        cause.unwrap_or(JObject::null()),
-    ))?;
+/// executes the plan to output stream, the output is coalesced unless the plan
+/// is a sink or produces no data
+pub fn execute_plan(
+    exec_ctx: &Arc<ExecutionContext>,
+    plan: &Arc<dyn ExecutionPlan>,
+) -> Result<SendableRecordBatchStream> {
+    let displayable = displayable(plan.as_ref())
+        .set_show_schema(true)
+        .indent(true)
+        .to_string();
+    log::info!("start executing plan:\n{displayable}");
+    let mut stream = exec_ctx.execute(plan)?;
+
+    // coalesce output stream if necessary
+    if downcast_any!(plan, EmptyExec).is_err()
+        && downcast_any!(plan, ParquetSinkExec).is_err()
+        && downcast_any!(plan, IpcWriterExec).is_err()
+        && downcast_any!(plan, ShuffleWriterExec).is_err()
+    {
+        stream = exec_ctx.coalesce_with_default_batch_size(stream);
+    }
+    Ok(stream)
+}
+
+/// replaces dictionary types in the output schema with their value types,
+/// the jvm side expects spark types and does not read dictionaries
+fn decode_dictionary_schema(schema: &SchemaRef) -> SchemaRef {
+    if !schema
+        .fields()
+        .iter()
+        .any(|field| matches!(field.data_type(), DataType::Dictionary(..)))
+    {
+        return schema.clone();
+    }
+    Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|field| match field.data_type() {
+                DataType::Dictionary(_, value_type) => Arc::new(
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(value_type.as_ref().clone()),
+                ),
+                _ => field.clone(),
+            })
+            .collect::<Fields>(),
+    ))
+}
+
+fn decode_dictionary_batch(batch: RecordBatch, output_schema: &SchemaRef) -> Result<RecordBatch> {
+    if batch.schema().as_ref() == output_schema.as_ref() {
+        return Ok(batch);
+    }
+    let cols = batch
+        .columns()
+        .iter()
+        .zip(output_schema.fields())
+        .map(|(col, field)| Ok(arrow::compute::cast(col, field.data_type())?))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new_with_options(
+        output_schema.clone(),
+        cols,
+        &RecordBatchOptions::new().with_row_count(Some(batch.num_rows())),
+    )?)
+}
+
+fn set_error(
+    native_wrapper: &GlobalRef,
+    message: &str,
+    err: DataFusionError,
+    cause: Option<JObject>,
+) -> Result<()> {
+    let e = new_native_exception(message, err, cause.unwrap_or(JObject::null()))?;
     jni_call!(BlazeCallNativeWrapper(native_wrapper.as_obj())
         .setError(e.as_obj()) -> ())?;
     Ok(())
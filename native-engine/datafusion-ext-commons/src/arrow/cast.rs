@@ -93,6 +93,21 @@ pub fn cast_impl(
             Arc::new(output)
         }
 
+        // timestamps are instants in spark, changing the timezone annotation
+        // only relabels the values, unlike arrow which adjusts values between
+        // local time and utc
+        (&DataType::Timestamp(from_unit, from_tz), DataType::Timestamp(_, to_tz))
+            if from_tz != to_tz =>
+        {
+            let relabeled = make_array(
+                array
+                    .to_data()
+                    .into_builder()
+                    .data_type(DataType::Timestamp(*from_unit, to_tz.clone()))
+                    .build()?,
+            );
+            cast_impl(&relabeled, cast_type, match_struct_fields)?
+        }
         (&DataType::Timestamp(..), DataType::Float64) => {
             // timestamp to f64 = timestamp to i64 to f64, only used in agg.sum()
             arrow::compute::cast(
@@ -634,4 +649,44 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn test_timestamp_timezones() {
+        // instants around the dst start of America/Los_Angeles (2024-03-10 10:00 UTC)
+        let micros = vec![
+            Some(1710061200_000000),
+            Some(1710064800_000000),
+            None,
+            Some(1710068400_000000),
+        ];
+        let la_array: ArrayRef = Arc::new(
+            TimestampMicrosecondArray::from(micros.clone()).with_timezone("America/Los_Angeles"),
+        );
+
+        // changing timezone annotations never changes the instants
+        let casted = cast(&la_array, &DataType::Timestamp(TimeUnit::Microsecond, None)).unwrap();
+        assert_eq!(
+            casted.as_primitive::<TimestampMicrosecondType>(),
+            &TimestampMicrosecondArray::from(micros.clone()),
+        );
+        let casted = cast(&casted, &la_array.data_type().clone()).unwrap();
+        assert_eq!(&casted, &la_array);
+
+        // unit conversion still works
+        let casted = cast(
+            &la_array,
+            &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        )
+        .unwrap();
+        assert_eq!(
+            casted.as_primitive::<TimestampMillisecondType>(),
+            &TimestampMillisecondArray::from(
+                micros
+                    .iter()
+                    .map(|v| v.map(|v| v / 1000))
+                    .collect::<Vec<_>>()
+            )
+            .with_timezone("UTC"),
+        );
+    }
 }
@@ -0,0 +1,301 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cmp::Ordering, sync::Arc};
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, GenericStringArray, GenericStringBuilder, OffsetSizeTrait,
+};
+use arrow_schema::DataType;
+use datafusion::common::Result;
+
+use crate::arrow::eq_comparator::{make_eq_comparator, DynEqComparator};
+
+/// collation of string values used in key hashing and comparison.
+/// case-insensitive collations behave exactly like comparing values
+/// lowered by `lower()`, without materializing the lowered values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Collation {
+    #[default]
+    Binary,
+    AsciiCaseInsensitive,
+    UnicodeCaseInsensitive,
+}
+
+impl Collation {
+    pub fn is_binary(&self) -> bool {
+        *self == Collation::Binary
+    }
+
+    /// returns false if values of data_type cannot be collated, in which case
+    /// callers should keep the materializing path
+    pub fn supports(&self, data_type: &DataType) -> bool {
+        self.is_binary() || matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+    }
+
+    /// appends bytes of the folded value into buf
+    pub fn fold_into(&self, value: &str, buf: &mut Vec<u8>) {
+        match self {
+            Collation::Binary => buf.extend_from_slice(value.as_bytes()),
+            Collation::AsciiCaseInsensitive => {
+                buf.extend(value.bytes().map(|b| b.to_ascii_lowercase()))
+            }
+            Collation::UnicodeCaseInsensitive => {
+                if value.is_ascii() {
+                    buf.extend(value.bytes().map(|b| b.to_ascii_lowercase()))
+                } else if has_context_sensitive_fold(value) {
+                    buf.extend_from_slice(value.to_lowercase().as_bytes())
+                } else {
+                    let mut char_buf = [0u8; 4];
+                    for c in value.chars().flat_map(char::to_lowercase) {
+                        buf.extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    /// materializes folded values of a string array, this is the fallback for
+    /// operators which require key arrays, like sort and sort-merge join
+    pub fn fold_array(&self, array: &ArrayRef) -> Result<ArrayRef> {
+        if self.is_binary() {
+            return Ok(array.clone());
+        }
+        Ok(match array.data_type() {
+            DataType::Utf8 => Arc::new(self.fold_string_array(array.as_string::<i32>())),
+            DataType::LargeUtf8 => Arc::new(self.fold_string_array(array.as_string::<i64>())),
+            _ => array.clone(),
+        })
+    }
+
+    fn fold_string_array<O: OffsetSizeTrait>(
+        &self,
+        array: &GenericStringArray<O>,
+    ) -> GenericStringArray<O> {
+        let mut builder =
+            GenericStringBuilder::<O>::with_capacity(array.len(), array.value_data().len());
+        let mut buf = vec![];
+        for value in array.iter() {
+            match value {
+                Some(value) => {
+                    buf.clear();
+                    self.fold_into(value, &mut buf);
+                    // safety: folding a valid utf8 string always produces valid
+                    // utf8
+                    builder.append_value(unsafe { std::str::from_utf8_unchecked(&buf) });
+                }
+                None => builder.append_null(),
+            }
+        }
+        builder.finish()
+    }
+}
+
+// final sigma is lowered depending on its context, which cannot be done
+// char by char, so these values are folded with str::to_lowercase
+#[inline]
+fn has_context_sensitive_fold(value: &str) -> bool {
+    value.contains('Σ')
+}
+
+pub fn collated_eq(l: &str, r: &str, collation: Collation) -> bool {
+    match collation {
+        Collation::Binary => l == r,
+        Collation::AsciiCaseInsensitive => l.eq_ignore_ascii_case(r),
+        Collation::UnicodeCaseInsensitive => {
+            if l.is_ascii() && r.is_ascii() {
+                return l.eq_ignore_ascii_case(r);
+            }
+            if has_context_sensitive_fold(l) || has_context_sensitive_fold(r) {
+                return l.to_lowercase() == r.to_lowercase();
+            }
+            let l = l.chars().flat_map(char::to_lowercase);
+            let r = r.chars().flat_map(char::to_lowercase);
+            l.eq(r)
+        }
+    }
+}
+
+/// compares two values in the order of their folded bytes
+pub fn collated_cmp(l: &str, r: &str, collation: Collation) -> Ordering {
+    match collation {
+        Collation::Binary => l.cmp(r),
+        Collation::AsciiCaseInsensitive => {
+            let l = l.bytes().map(|b| b.to_ascii_lowercase());
+            let r = r.bytes().map(|b| b.to_ascii_lowercase());
+            l.cmp(r)
+        }
+        Collation::UnicodeCaseInsensitive => {
+            if has_context_sensitive_fold(l) || has_context_sensitive_fold(r) {
+                return l.to_lowercase().cmp(&r.to_lowercase());
+            }
+            // utf8 bytes are in the same order as chars
+            let l = l.chars().flat_map(char::to_lowercase);
+            let r = r.chars().flat_map(char::to_lowercase);
+            l.cmp(r)
+        }
+    }
+}
+
+/// checks whether the folded value equals to an already folded value
+pub fn folded_eq(value: &str, folded: &str, collation: Collation) -> bool {
+    let ascii_folded_eq = |value: &str| {
+        value.len() == folded.len()
+            && value
+                .bytes()
+                .zip(folded.bytes())
+                .all(|(v, f)| v.to_ascii_lowercase() == f)
+    };
+    match collation {
+        Collation::Binary => value == folded,
+        Collation::AsciiCaseInsensitive => ascii_folded_eq(value),
+        Collation::UnicodeCaseInsensitive => {
+            if value.is_ascii() {
+                return ascii_folded_eq(value);
+            }
+            if has_context_sensitive_fold(value) {
+                return value.to_lowercase() == folded;
+            }
+            value
+                .chars()
+                .flat_map(char::to_lowercase)
+                .eq(folded.chars())
+        }
+    }
+}
+
+/// creates an eq comparator checking whether folded values of left equal to
+/// values of right, which are expected to be folded already (for example,
+/// evaluated by `lower()`). nulls are never equal. unsupported types are
+/// compared in binary.
+pub fn make_folded_eq_comparator(
+    left: &dyn Array,
+    right: &dyn Array,
+    collation: Collation,
+) -> Result<DynEqComparator> {
+    macro_rules! folded_eq_comparator {
+        ($offset:ty) => {{
+            let l = left.as_string::<$offset>().clone();
+            let r = right.as_string::<$offset>().clone();
+            let eq: DynEqComparator = Box::new(move |i, j| {
+                l.is_valid(i) && r.is_valid(j) && folded_eq(l.value(i), r.value(j), collation)
+            });
+            eq
+        }};
+    }
+    Ok(match (left.data_type(), right.data_type()) {
+        _ if collation.is_binary() => make_eq_comparator(left, right, false)?,
+        (DataType::Utf8, DataType::Utf8) => folded_eq_comparator!(i32),
+        (DataType::LargeUtf8, DataType::LargeUtf8) => folded_eq_comparator!(i64),
+        _ => make_eq_comparator(left, right, false)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cmp::Ordering, sync::Arc};
+
+    use arrow::array::{ArrayRef, AsArray, Int32Array, LargeStringArray, StringArray};
+    use datafusion::common::Result;
+
+    use crate::arrow::collation::{
+        collated_cmp, collated_eq, folded_eq, make_folded_eq_comparator, Collation,
+    };
+
+    const VALUES: [&str; 12] = [
+        "",
+        "abc",
+        "ABC",
+        "aBc",
+        "abd",
+        "Straße",
+        "STRASSE",
+        "ÀÉÎ",
+        "àéî",
+        "ΟΔΥΣΣΕΥΣ",
+        "οδυσσευς",
+        "İstanbul",
+    ];
+
+    #[test]
+    fn test_unicode_matches_lower() {
+        let collation = Collation::UnicodeCaseInsensitive;
+        for l in VALUES {
+            let mut buf = vec![];
+            collation.fold_into(l, &mut buf);
+            assert_eq!(buf, l.to_lowercase().as_bytes());
+
+            for r in VALUES {
+                let (l_lower, r_lower) = (l.to_lowercase(), r.to_lowercase());
+                assert_eq!(collated_eq(l, r, collation), l_lower == r_lower, "{l} {r}");
+                assert_eq!(folded_eq(l, &r_lower, collation), l_lower == r_lower);
+                assert_eq!(collated_cmp(l, r, collation), l_lower.cmp(&r_lower));
+            }
+        }
+        assert!(collated_eq("ΟΔΥΣΣΕΥΣ", "οδυσσευς", collation));
+        assert!(!collated_eq("Straße", "STRASSE", collation));
+    }
+
+    #[test]
+    fn test_ascii_case_insensitive() {
+        let collation = Collation::AsciiCaseInsensitive;
+        assert!(collated_eq("aBc", "ABC", collation));
+        assert!(!collated_eq("ÀÉÎ", "àéî", collation));
+        assert_eq!(collated_cmp("ABD", "abc", collation), Ordering::Greater);
+        assert_eq!(collated_cmp("abc", "ABCD", collation), Ordering::Less);
+
+        let mut buf = vec![];
+        collation.fold_into("ÀbC", &mut buf);
+        assert_eq!(buf, "Àbc".as_bytes());
+    }
+
+    #[test]
+    fn test_folded_eq_comparator() -> Result<()> {
+        let l = StringArray::from(vec![Some("Abc"), None, Some("ΣΑΣ"), Some("X"), Some("abc")]);
+        let r = StringArray::from(vec![Some("abc"), None, Some("σας"), Some("x"), Some("aBc")]);
+        let eq = make_folded_eq_comparator(&l, &r, Collation::UnicodeCaseInsensitive)?;
+        assert!(eq(0, 0));
+        assert!(!eq(1, 1));
+        assert!(eq(2, 2));
+        assert!(eq(3, 3));
+        assert!(!eq(4, 4)); // right values are not folded
+
+        let l = LargeStringArray::from(vec!["AbC"]);
+        let r = LargeStringArray::from(vec!["abc"]);
+        let eq = make_folded_eq_comparator(&l, &r, Collation::AsciiCaseInsensitive)?;
+        assert!(eq(0, 0));
+
+        let eq = make_folded_eq_comparator(&l, &r, Collation::Binary)?;
+        assert!(!eq(0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold_array_fallback() -> Result<()> {
+        let collation = Collation::UnicodeCaseInsensitive;
+        let array: ArrayRef =
+            Arc::new(StringArray::from(vec![Some("ÀbC"), None, Some("ΟΔΥΣΣΕΥΣ")]));
+        let folded = collation.fold_array(&array)?;
+        assert_eq!(
+            folded.as_string::<i32>(),
+            &StringArray::from(vec![Some("àbc"), None, Some("οδυσσευς")]),
+        );
+
+        // unsupported types are kept untouched
+        let array: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+        assert!(!collation.supports(array.data_type()));
+        assert_eq!(&collation.fold_array(&array)?, &array);
+        Ok(())
+    }
+}
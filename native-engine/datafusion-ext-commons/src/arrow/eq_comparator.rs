@@ -22,7 +22,10 @@ use arrow::{
 use arrow_schema::DataType;
 use datafusion::common::Result;
 
-use crate::{df_execution_err, downcast_any};
+use crate::{
+    arrow::collation::{make_folded_eq_comparator, Collation},
+    df_execution_err, downcast_any,
+};
 
 // inlines most common cases with single column
 pub enum EqComparator {
@@ -34,6 +37,7 @@ pub enum EqComparator {
     Date64(Date64Array, Date64Array),
     String(StringArray, StringArray),
     Binary(BinaryArray, BinaryArray),
+    FixedSizeBinary(FixedSizeBinaryArray, FixedSizeBinaryArray),
     Other(DynEqComparator),
 }
 
@@ -77,10 +81,48 @@ impl EqComparator {
                 downcast_any!(&cols1[0], BinaryArray)?.clone(),
                 downcast_any!(&cols2[0], BinaryArray)?.clone(),
             ),
+            (Some((DataType::FixedSizeBinary(w1), DataType::FixedSizeBinary(w2))), None)
+                if w1 == w2 =>
+            {
+                EqComparator::FixedSizeBinary(
+                    downcast_any!(&cols1[0], FixedSizeBinaryArray)?.clone(),
+                    downcast_any!(&cols2[0], FixedSizeBinaryArray)?.clone(),
+                )
+            }
             _ => EqComparator::Other(Self::make_eq_comparator_multiple_arrays(cols1, cols2)?),
         })
     }
 
+    /// like try_new, but string values of cols1 are folded by the given
+    /// collations before compared to cols2, which are expected to be folded
+    /// already. all-binary collations keep the inlined comparators.
+    pub fn try_new_with_collations(
+        cols1: &[ArrayRef],
+        cols2: &[ArrayRef],
+        collations: &[Collation],
+    ) -> Result<Self> {
+        if collations.iter().all(|collation| collation.is_binary()) {
+            return Self::try_new(cols1, cols2);
+        }
+        if cols1.len() != cols2.len() || cols1.len() != collations.len() {
+            return df_execution_err!(
+                "try_new_with_collations: cols1.len ({}), cols2.len ({}), collations.len ({})",
+                cols1.len(),
+                cols2.len(),
+                collations.len(),
+            );
+        }
+        let eqs = cols1
+            .iter()
+            .zip(cols2)
+            .zip(collations)
+            .map(|((col1, col2), &collation)| make_folded_eq_comparator(col1, col2, collation))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(EqComparator::Other(Box::new(move |i, j| {
+            eqs.iter().all(|eq| eq(i, j))
+        })))
+    }
+
     #[inline]
     pub fn eq(&self, i: usize, j: usize) -> bool {
         unsafe {
@@ -93,7 +135,10 @@ impl EqComparator {
                 EqComparator::Date32(c1, c2) => c1.value_unchecked(i) == c2.value_unchecked(j),
                 EqComparator::Date64(c1, c2) => c1.value_unchecked(i) == c2.value_unchecked(j),
                 EqComparator::String(c1, c2) => c1.value_unchecked(i) == c2.value_unchecked(j),
-                EqComparator::Binary(c1, c2) => c1.value_unchecked(i) == c2.value_unchecked(j),
+                EqComparator::Binary(c1, c2) => binary_eq_unchecked(c1, i, c2, j),
+                EqComparator::FixedSizeBinary(c1, c2) => {
+                    bytes_eq(c1.value_unchecked(i), c2.value_unchecked(j))
+                }
                 EqComparator::Other(eq) => eq(i, j),
             }
         }
@@ -120,6 +165,51 @@ impl EqComparator {
     }
 }
 
+/// compares byte slices with an early length check. 8/16-byte slices (like
+/// pre-hashed ids) are compared as u64/u128 words, others with memcmp.
+#[inline]
+pub fn bytes_eq(l: &[u8], r: &[u8]) -> bool {
+    if l.len() != r.len() {
+        return false;
+    }
+    unsafe {
+        // safety: lengths are checked
+        match l.len() {
+            8 => {
+                let l = std::ptr::read_unaligned(l.as_ptr() as *const u64);
+                let r = std::ptr::read_unaligned(r.as_ptr() as *const u64);
+                l == r
+            }
+            16 => {
+                let l = std::ptr::read_unaligned(l.as_ptr() as *const u128);
+                let r = std::ptr::read_unaligned(r.as_ptr() as *const u128);
+                l == r
+            }
+            _ => l == r,
+        }
+    }
+}
+
+// checks lengths with offsets before touching the values
+#[inline]
+unsafe fn binary_eq_unchecked<T: ByteArrayType>(
+    l: &GenericByteArray<T>,
+    i: usize,
+    r: &GenericByteArray<T>,
+    j: usize,
+) -> bool {
+    let l_offsets = l.value_offsets();
+    let r_offsets = r.value_offsets();
+    let l_len = *l_offsets.get_unchecked(i + 1) - *l_offsets.get_unchecked(i);
+    let r_len = *r_offsets.get_unchecked(j + 1) - *r_offsets.get_unchecked(j);
+    if l_len != r_len {
+        return false;
+    }
+    let l: &[u8] = l.value_unchecked(i).as_ref();
+    let r: &[u8] = r.value_unchecked(j).as_ref();
+    bytes_eq(l, r)
+}
+
 /// Compare the values at two arbitrary indices in two arrays.
 pub type DynEqComparator = Box<dyn Fn(usize, usize) -> bool + Send + Sync>;
 
@@ -197,9 +287,11 @@ fn eq_bytes<T: ByteArrayType>(
     let l = left.clone();
     let r = right.clone();
     eq_impl(left, right, ignores_null, move |i, j| {
-        let l: &[u8] = l.value(i).as_ref();
-        let r: &[u8] = r.value(j).as_ref();
-        l == r
+        assert!(i < l.len() && j < r.len());
+        unsafe {
+            // safety: bounds checked
+            binary_eq_unchecked(&l, i, &r, j)
+        }
     })
 }
 
@@ -351,7 +443,7 @@ pub fn make_eq_comparator(
             let l = left.clone();
             let r = right.clone();
             Ok(eq_impl(left, right, ignores_null, move |i, j| {
-                l.value(i).eq(r.value(j))
+                bytes_eq(l.value(i), r.value(j))
             }))
         },
         (List(_), List(_)) => eq_list::<i32>(left, right, ignores_null),
@@ -378,7 +470,7 @@ pub fn make_eq_comparator(
 
 #[cfg(test)]
 pub mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Instant};
 
     use arrow::{
         array::builder::{Int32Builder, ListBuilder},
@@ -724,6 +816,93 @@ pub mod tests {
         test_bytes_impl::<LargeBinaryType>();
     }
 
+    #[test]
+    fn test_bytes_eq() {
+        for len in [0, 1, 7, 8, 9, 16, 17, 32] {
+            let a = (0..len as u8).collect::<Vec<_>>();
+            let mut b = a.clone();
+            assert!(bytes_eq(&a, &b));
+            if len > 0 {
+                assert!(!bytes_eq(&a, &b[..len - 1]));
+                b[len - 1] ^= 1;
+                assert!(!bytes_eq(&a, &b));
+                b[len - 1] ^= 1;
+                b[0] ^= 1;
+                assert!(!bytes_eq(&a, &b));
+            }
+        }
+
+        // unaligned slices
+        let data = (0..40u8).collect::<Vec<_>>();
+        assert!(bytes_eq(&data[1..17], &data[1..17]));
+        assert!(!bytes_eq(&data[1..17], &data[2..18]));
+    }
+
+    #[test]
+    fn test_binary_keys_eq_comparator() -> Result<()> {
+        let items = vec![
+            Some(vec![0u8; 16]),
+            None,
+            Some(vec![1u8; 16]),
+            Some(vec![0u8; 16]),
+        ];
+        let fixed: ArrayRef = Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+            items.clone().into_iter(),
+            16,
+        )?);
+        let binary: ArrayRef = Arc::new(BinaryArray::from_iter(items.into_iter()));
+        let short: ArrayRef = Arc::new(BinaryArray::from_iter_values([vec![0u8; 15]]));
+
+        for col in [&fixed, &binary] {
+            let eq = EqComparator::try_new(&[col.clone()], &[col.clone()])?;
+            assert!(eq.eq(0, 0));
+            assert!(eq.eq(0, 3));
+            assert!(!eq.eq(0, 2));
+        }
+        let eq = EqComparator::try_new(&[binary.clone()], &[short.clone()])?;
+        assert!(!eq.eq(0, 0));
+
+        // multiple columns use the dyn comparators
+        let eq = EqComparator::try_new(&[fixed.clone(), binary.clone()], &[fixed, binary])?;
+        assert!(eq.eq(0, 3));
+        assert!(!eq.eq(2, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn bench_16_byte_keys_eq() -> Result<()> {
+        // compares 16-byte pre-hashed ids like join rechecks, with the fast
+        // paths and with plain slice comparison
+        let num_rows = 1000000;
+        let keys = (0..num_rows as u128).map(|i| (i * 0x9e3779b97f4a7c15).to_le_bytes());
+        let fixed: ArrayRef = Arc::new(FixedSizeBinaryArray::try_from_iter(keys.clone())?);
+        let binary: ArrayRef = Arc::new(BinaryArray::from_iter_values(keys));
+
+        let (l, r) = (fixed.as_fixed_size_binary(), fixed.as_fixed_size_binary());
+        let eq = EqComparator::try_new(&[fixed.clone()], &[fixed.clone()])?;
+        let time_start = Instant::now();
+        assert_eq!((0..num_rows).filter(|&i| eq.eq(i, i)).count(), num_rows);
+        eprintln!("fixed_size_binary_fast_eq_time: {:?}", time_start.elapsed());
+        let time_start = Instant::now();
+        let count = (0..num_rows).filter(|&i| l.value(i) == r.value(i)).count();
+        assert_eq!(count, num_rows);
+        eprintln!(
+            "fixed_size_binary_slice_eq_time: {:?}",
+            time_start.elapsed()
+        );
+
+        let (l, r) = (binary.as_binary::<i32>(), binary.as_binary::<i32>());
+        let eq = EqComparator::try_new(&[binary.clone()], &[binary.clone()])?;
+        let time_start = Instant::now();
+        assert_eq!((0..num_rows).filter(|&i| eq.eq(i, i)).count(), num_rows);
+        eprintln!("binary_fast_eq_time: {:?}", time_start.elapsed());
+        let time_start = Instant::now();
+        let count = (0..num_rows).filter(|&i| l.value(i) == r.value(i)).count();
+        assert_eq!(count, num_rows);
+        eprintln!("binary_slice_eq_time: {:?}", time_start.elapsed());
+        Ok(())
+    }
+
     #[test]
     fn test_lists() {
         let mut a = ListBuilder::new(ListBuilder::new(Int32Builder::new()));
@@ -797,4 +976,27 @@ pub mod tests {
         assert_eq!(eq(2, 0), false); // (None, None) eq (None, None)
         assert_eq!(eq(3, 0), false); // None eq (None, None)
     }
+
+    #[test]
+    fn test_eq_comparator_with_collations() {
+        let a: ArrayRef = Arc::new(StringArray::from(vec!["Abc", "ÀÉ", "X"]));
+        let b: ArrayRef = Arc::new(StringArray::from(vec!["abc", "àé", "x"]));
+        let n: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 4]));
+        let m: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+
+        let eq = EqComparator::try_new_with_collations(
+            &[a.clone(), n],
+            &[b.clone(), m],
+            &[Collation::UnicodeCaseInsensitive, Collation::Binary],
+        )
+        .unwrap();
+        assert_eq!(true, eq.eq(0, 0));
+        assert_eq!(true, eq.eq(1, 1));
+        assert_eq!(false, eq.eq(2, 2)); // int keys differ
+        assert_eq!(false, eq.eq(0, 1));
+
+        let eq = EqComparator::try_new_with_collations(&[a], &[b], &[Collation::Binary]).unwrap();
+        assert!(matches!(eq, EqComparator::String(..)));
+        assert_eq!(false, eq.eq(0, 0));
+    }
 }
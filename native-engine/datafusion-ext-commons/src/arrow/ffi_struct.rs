@@ -0,0 +1,212 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! exchanging struct arrays with jvm through the arrow c data interface.
+//!
+//! raw pointers of ffi arrays are only handed to the given closures, so the
+//! ffi arrays always outlive the jni calls using them. jvm is expected to
+//! move the exported array (taking over its release callback) and must not
+//! retain the pointers after the call returns.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, StructArray},
+    datatypes::{DataType, Field, Fields, Schema},
+    ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema},
+};
+use datafusion::common::Result;
+
+use crate::{arrow::cast::cast, df_execution_err};
+
+/// exports the struct array and calls `f` with the pointer of the exported
+/// ffi array. if jvm does not take over the array, it is released after `f`
+/// returns.
+pub fn export_struct_for_jni<T>(
+    struct_array: &StructArray,
+    f: impl FnOnce(i64) -> Result<T>,
+) -> Result<T> {
+    let mut export_ffi_array = FFI_ArrowArray::new(&struct_array.to_data());
+    f(&mut export_ffi_array as *mut FFI_ArrowArray as i64)
+}
+
+/// calls `f` with the pointer of an empty ffi array to be filled by jvm, then
+/// imports the filled array as a struct array of the given schema.
+pub fn import_struct_from_jni(
+    schema: &Schema,
+    f: impl FnOnce(i64) -> Result<()>,
+) -> Result<StructArray> {
+    let mut import_ffi_array = FFI_ArrowArray::empty();
+    f(&mut import_ffi_array as *mut FFI_ArrowArray as i64)?;
+    if import_ffi_array.is_released() {
+        return df_execution_err!("ffi array not filled by jvm");
+    }
+
+    let import_ffi_schema = FFI_ArrowSchema::try_from(schema)?;
+    // safety: the ffi array has been filled with a valid array by jvm
+    let import_data = unsafe { from_ffi(import_ffi_array, &import_ffi_schema)? };
+    Ok(StructArray::from(import_data))
+}
+
+/// schema of the single-field struct array exported by jvm for values of
+/// `data_type`. maps are exported with the jvm layout: a non-null "entries"
+/// struct of a non-null "key" and a "value" field, which may differ from the
+/// declared type in field names and nullability.
+pub fn import_schema(data_type: &DataType) -> Schema {
+    Schema::new(vec![Field::new(
+        "",
+        jvm_exported_data_type(data_type),
+        true,
+    )])
+}
+
+fn jvm_exported_data_type(data_type: &DataType) -> DataType {
+    let exported_field = |field: &Field| {
+        field
+            .clone()
+            .with_data_type(jvm_exported_data_type(field.data_type()))
+    };
+    match data_type {
+        DataType::Map(entries_field, _) => match entries_field.data_type() {
+            DataType::Struct(kv_fields) if kv_fields.len() == 2 => {
+                let key_field = Field::new(
+                    "key",
+                    jvm_exported_data_type(kv_fields[0].data_type()),
+                    false,
+                );
+                let value_field = Field::new(
+                    "value",
+                    jvm_exported_data_type(kv_fields[1].data_type()),
+                    kv_fields[1].is_nullable(),
+                );
+                let entries_type = DataType::Struct(Fields::from(vec![key_field, value_field]));
+                DataType::Map(Arc::new(Field::new("entries", entries_type, false)), false)
+            }
+            _ => data_type.clone(),
+        },
+        DataType::List(field) => DataType::List(Arc::new(exported_field(field))),
+        DataType::Struct(fields) => DataType::Struct(
+            fields
+                .iter()
+                .map(|field| exported_field(field.as_ref()))
+                .collect(),
+        ),
+        _ => data_type.clone(),
+    }
+}
+
+/// validates the struct array imported with `import_schema(data_type)` and
+/// returns its single field as `data_type`, casting if the exported layout
+/// differs from it.
+pub fn imported_as_data_type(imported: &StructArray, data_type: &DataType) -> Result<ArrayRef> {
+    if imported.num_columns() != 1 {
+        return df_execution_err!(
+            "expect 1 field in imported struct array, found {}",
+            imported.num_columns(),
+        );
+    }
+    if let Err(e) = imported.to_data().validate_full() {
+        return df_execution_err!("invalid array imported from jvm: {e}");
+    }
+
+    let output = imported.column(0);
+    if output.data_type() == data_type {
+        return Ok(output.clone());
+    }
+    cast(output, data_type).or_else(|e| {
+        df_execution_err!(
+            "cannot convert imported array of {} to {data_type}: {e}",
+            output.data_type(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, StringArray, StructArray},
+        datatypes::{DataType, Field, Schema},
+        ffi::FFI_ArrowArray,
+    };
+    use datafusion::common::{DataFusionError, Result};
+
+    use crate::arrow::ffi_struct::{export_struct_for_jni, import_struct_from_jni};
+
+    // simulates jvm importing the exported array and exporting it back
+    fn move_ffi_array(export_ptr: i64, import_ptr: i64) {
+        unsafe {
+            let exported =
+                std::ptr::replace(export_ptr as *mut FFI_ArrowArray, FFI_ArrowArray::empty());
+            *(import_ptr as *mut FFI_ArrowArray) = exported;
+        }
+    }
+
+    #[test]
+    fn test_export_import_round_trip() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Utf8, true),
+        ]);
+        let struct_array = StructArray::from(vec![
+            (
+                Arc::new(schema.field(0).clone()),
+                Arc::new(Int32Array::from(vec![Some(1), None, Some(3)])) as ArrayRef,
+            ),
+            (
+                Arc::new(schema.field(1).clone()),
+                Arc::new(StringArray::from(vec![Some("x"), Some("y"), None])) as ArrayRef,
+            ),
+        ]);
+        let sliced = struct_array.slice(1, 2);
+
+        for exported in [struct_array, sliced] {
+            let imported = import_struct_from_jni(&schema, |import_ptr| {
+                export_struct_for_jni(&exported, |export_ptr| {
+                    move_ffi_array(export_ptr, import_ptr);
+                    Ok(())
+                })
+            })?;
+            assert_eq!(imported, exported);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import_errors() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+        let struct_array = StructArray::from(vec![(
+            Arc::new(schema.field(0).clone()),
+            Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef,
+        )]);
+
+        // errors of the jni call are propagated, the exported array not taken
+        // over by jvm is released
+        let err = export_struct_for_jni(&struct_array, |_export_ptr| -> Result<()> {
+            Err(DataFusionError::Execution("jvm failure".to_string()))
+        });
+        assert!(err.is_err());
+
+        let err = import_struct_from_jni(&schema, |_import_ptr| {
+            Err(DataFusionError::Execution("jvm failure".to_string()))
+        });
+        assert!(err.is_err());
+
+        // the ffi array is left empty by jvm
+        let err = import_struct_from_jni(&schema, |_import_ptr| Ok(()));
+        assert!(err.is_err());
+        Ok(())
+    }
+}
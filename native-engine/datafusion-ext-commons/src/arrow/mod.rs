@@ -15,5 +15,7 @@
 pub mod array_size;
 pub mod cast;
 pub mod coalesce;
+pub mod collation;
 pub mod eq_comparator;
+pub mod ffi_struct;
 pub mod selection;
@@ -0,0 +1,189 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use datafusion::common::DataFusionError;
+
+/// classified native errors, surfaced to the jvm as BlazeNativeException
+/// subclasses with the error code. operators wrap their errors with
+/// `BlazeError::xxx(err).into()`, which is carried as an external error in
+/// DataFusionError.
+#[derive(Debug)]
+pub enum BlazeError {
+    OutOfMemory(DataFusionError),
+    SpillIo(DataFusionError),
+    Corruption(DataFusionError),
+    Unsupported(DataFusionError),
+    Cancelled(DataFusionError),
+    Internal(DataFusionError),
+}
+
+impl BlazeError {
+    const KINDS: [(&'static str, i32); 6] = [
+        ("OutOfMemory", 1),
+        ("SpillIo", 2),
+        ("Corruption", 3),
+        ("Unsupported", 4),
+        ("Cancelled", 5),
+        ("Internal", 6),
+    ];
+
+    fn kind_idx(&self) -> usize {
+        match self {
+            BlazeError::OutOfMemory(_) => 0,
+            BlazeError::SpillIo(_) => 1,
+            BlazeError::Corruption(_) => 2,
+            BlazeError::Unsupported(_) => 3,
+            BlazeError::Cancelled(_) => 4,
+            BlazeError::Internal(_) => 5,
+        }
+    }
+
+    fn with_kind_idx(kind_idx: usize, err: DataFusionError) -> Self {
+        match kind_idx {
+            0 => BlazeError::OutOfMemory(err),
+            1 => BlazeError::SpillIo(err),
+            2 => BlazeError::Corruption(err),
+            3 => BlazeError::Unsupported(err),
+            4 => BlazeError::Cancelled(err),
+            _ => BlazeError::Internal(err),
+        }
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        Self::KINDS[self.kind_idx()].0
+    }
+
+    /// error code of BlazeNativeException
+    pub fn code(&self) -> i32 {
+        Self::KINDS[self.kind_idx()].1
+    }
+
+    pub fn inner(&self) -> &DataFusionError {
+        match self {
+            BlazeError::OutOfMemory(err)
+            | BlazeError::SpillIo(err)
+            | BlazeError::Corruption(err)
+            | BlazeError::Unsupported(err)
+            | BlazeError::Cancelled(err)
+            | BlazeError::Internal(err) => err,
+        }
+    }
+
+    /// classifies an error reaching the jni boundary. the innermost wrapped
+    /// BlazeError is used if found. errors are often flattened into strings
+    /// when passing through streams and panics, so the kind is also parsed
+    /// from the message. otherwise the kind is inferred from the variant.
+    pub fn classify(err: DataFusionError) -> Self {
+        let mut kind_idx = None;
+        let mut source: Option<&(dyn Error + 'static)> = Some(&err);
+        while let Some(e) = source {
+            if let Some(blaze_err) = e.downcast_ref::<BlazeError>() {
+                kind_idx = Some(blaze_err.kind_idx());
+            }
+
+            // source() of io errors skips their wrapped error, which is where
+            // spill io errors are kept
+            source = match e.downcast_ref::<std::io::Error>() {
+                Some(io_err) => io_err
+                    .get_ref()
+                    .map(|inner| inner as &(dyn Error + 'static)),
+                None => e.source(),
+            };
+        }
+
+        let kind_idx = kind_idx
+            .or_else(|| Self::parse_kind_idx(&err.to_string()))
+            .unwrap_or_else(|| match &err {
+                DataFusionError::ResourcesExhausted(_) => 0,
+                DataFusionError::NotImplemented(_) => 3,
+                _ => 5,
+            });
+        Self::with_kind_idx(kind_idx, err)
+    }
+
+    fn parse_kind_idx(message: &str) -> Option<usize> {
+        let start = message.rfind("BlazeError[")? + "BlazeError[".len();
+        let len = message[start..].find(']')?;
+        Self::KINDS
+            .iter()
+            .position(|(name, _)| *name == &message[start..][..len])
+    }
+}
+
+impl Display for BlazeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BlazeError[{}]: {}", self.kind_name(), self.inner())
+    }
+}
+
+impl Error for BlazeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.inner())
+    }
+}
+
+impl From<BlazeError> for DataFusionError {
+    fn from(err: BlazeError) -> Self {
+        DataFusionError::External(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use datafusion::common::DataFusionError;
+
+    use crate::error::BlazeError;
+
+    #[test]
+    fn test_classify() {
+        let classify = |err: DataFusionError| {
+            let classified = BlazeError::classify(err);
+            (classified.kind_name(), classified.code())
+        };
+
+        // wrapped errors, including ones wrapped in context
+        let err: DataFusionError =
+            BlazeError::Corruption(DataFusionError::Execution("checksum mismatch".to_string()))
+                .into();
+        assert_eq!(classify(err), ("Corruption", 3));
+        let err = DataFusionError::Context(
+            "reading spill".to_string(),
+            Box::new(BlazeError::SpillIo(DataFusionError::Execution("eof".to_string())).into()),
+        );
+        assert_eq!(classify(err), ("SpillIo", 2));
+        let err = DataFusionError::IoError(std::io::Error::other(BlazeError::SpillIo(
+            DataFusionError::Execution("disk full".to_string()),
+        )));
+        assert_eq!(classify(err), ("SpillIo", 2));
+
+        // errors flattened into strings
+        let err: DataFusionError =
+            BlazeError::Cancelled(DataFusionError::Execution("task killed".to_string())).into();
+        let flattened = DataFusionError::Execution(format!("output_with_sender error: {err}"));
+        assert_eq!(classify(flattened), ("Cancelled", 5));
+
+        // inferred from variants
+        let err = DataFusionError::ResourcesExhausted("memory".to_string());
+        assert_eq!(classify(err), ("OutOfMemory", 1));
+        let err = DataFusionError::NotImplemented("type".to_string());
+        assert_eq!(classify(err), ("Unsupported", 4));
+        let err = DataFusionError::Execution("unknown".to_string());
+        assert_eq!(classify(err), ("Internal", 6));
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub mod mur;
+pub mod xxh3;
 pub mod xxhash;
 
 fn read32(data: &[u8], offset: usize) -> u32 {
@@ -0,0 +1,34 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// xxhash3-64 folded into 32 bits. unlike the spark-compatible hashes, it is
+/// not used by spark, but it is faster and produces the same values on all
+/// platforms regardless of endianness or simd support.
+#[inline]
+pub fn xxh3_hash32<T: AsRef<[u8]>>(data: T, seed: u32) -> u32 {
+    let h = twox_hash::xxh3::hash64_with_seed(data.as_ref(), seed as u64);
+    (h ^ (h >> 32)) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hash::xxh3::xxh3_hash32;
+
+    #[test]
+    fn test_xxh3_hash32() {
+        // XXH3_64bits("", 0) = 0x2d06800538d394c2
+        assert_eq!(xxh3_hash32(b"", 0), 0x2d068005 ^ 0x38d394c2);
+        assert_ne!(xxh3_hash32(b"", 0), xxh3_hash32(b"", 42));
+    }
+}
@@ -58,11 +58,79 @@ impl TransposeOpt {
                 .map(|f| Self::data_type_bytes_width(f.data_type()))
                 .max()
                 .unwrap_or(0),
+            DataType::Dictionary(_, value_type) => Self::data_type_bytes_width(value_type),
             _ => 0,
         }
     }
 }
 
+/// source of serialized arrays, which provides raw array buffers either by
+/// copying or by slicing a shared buffer
+pub trait BufferRead: Read {
+    fn read_buffer(&mut self, len: usize) -> std::io::Result<Buffer>;
+}
+
+struct CopyingRead<R: Read>(R);
+
+impl<R: Read> Read for CopyingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R: Read> BufferRead for CopyingRead<R> {
+    fn read_buffer(&mut self, len: usize) -> std::io::Result<Buffer> {
+        Ok(Buffer::from_vec(Vec::from(read_bytes_slice(
+            &mut self.0,
+            len,
+        )?)))
+    }
+}
+
+/// a reading position over a shared buffer, e.g. a memory-mapped file region
+pub struct BufferCursor {
+    buffer: Buffer,
+    pos: usize,
+}
+
+impl BufferCursor {
+    pub fn new(buffer: Buffer) -> Self {
+        Self { buffer, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.pos
+    }
+}
+
+impl Read for BufferCursor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = &self.buffer.as_slice()[self.pos..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl BufferRead for BufferCursor {
+    fn read_buffer(&mut self, len: usize) -> std::io::Result<Buffer> {
+        if len > self.remaining() {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "buffer exhausted",
+            ));
+        }
+        let buffer = self.buffer.slice_with_length(self.pos, len);
+        self.pos += len;
+        Ok(buffer)
+    }
+}
+
 pub fn write_batch(num_rows: usize, cols: &[ArrayRef], mut output: impl Write) -> Result<()> {
     // write number of columns and rows
     write_len(num_rows, &mut output)?;
@@ -76,12 +144,26 @@ pub fn write_batch(num_rows: usize, cols: &[ArrayRef], mut output: impl Write) -
     Ok(())
 }
 
-pub fn read_batch(
-    mut input: impl Read,
+pub fn read_batch(input: impl Read, schema: &SchemaRef) -> Result<Option<(usize, Vec<ArrayRef>)>> {
+    read_batch_impl(&mut CopyingRead(input), schema)
+}
+
+/// reads a batch from a shared buffer, array buffers which are stored in raw
+/// format (null bitmaps, boolean/byte values and string data) are sliced
+/// from the input buffer without copying.
+pub fn read_batch_from_buffer(
+    input: &mut BufferCursor,
+    schema: &SchemaRef,
+) -> Result<Option<(usize, Vec<ArrayRef>)>> {
+    read_batch_impl(input, schema)
+}
+
+fn read_batch_impl<R: BufferRead>(
+    input: &mut R,
     schema: &SchemaRef,
 ) -> Result<Option<(usize, Vec<ArrayRef>)>> {
     // read number of columns and rows
-    let num_rows = match read_len(&mut input) {
+    let num_rows = match read_len(input) {
         Ok(n) => n,
         Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
         Err(e) => return Err(e.into()),
@@ -93,11 +175,56 @@ pub fn read_batch(
     let cols = schema
         .fields()
         .into_iter()
-        .map(|field| read_array(&mut input, &field.data_type(), num_rows, &mut transpose_opt))
+        .map(|field| read_array_impl(input, &field.data_type(), num_rows, &mut transpose_opt))
         .collect::<Result<_>>()?;
     Ok(Some((num_rows, cols)))
 }
 
+/// finds the first data type which cannot be written by `write_array`,
+/// nested field names are appended to `path`.
+pub fn find_unsupported_data_type<'a>(
+    data_type: &'a DataType,
+    path: &mut String,
+) -> Option<&'a DataType> {
+    match data_type {
+        DataType::Null
+        | DataType::Boolean
+        | DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Decimal128(..)
+        | DataType::Utf8
+        | DataType::Binary
+        | DataType::Date32
+        | DataType::Date64
+        | DataType::Timestamp(..) => None,
+        DataType::List(field) | DataType::Map(field, _) => {
+            path.push_str(&format!(".{}", field.name()));
+            find_unsupported_data_type(field.data_type(), path)
+        }
+        DataType::Struct(fields) => fields.iter().find_map(|field| {
+            let path_len = path.len();
+            path.push_str(&format!(".{}", field.name()));
+            let found = find_unsupported_data_type(field.data_type(), path);
+            if found.is_none() {
+                path.truncate(path_len);
+            }
+            found
+        }),
+        DataType::Dictionary(key_type, value_type) if key_type.is_dictionary_key_type() => {
+            find_unsupported_data_type(value_type, path)
+        }
+        other => Some(other),
+    }
+}
+
 pub fn write_array<W: Write>(
     array: &dyn Array,
     output: &mut W,
@@ -139,6 +266,11 @@ pub fn write_array<W: Write>(
         DataType::List(_field) => write_list_array(as_list_array(array), output, transpose_opt)?,
         DataType::Map(..) => write_map_array(as_map_array(array), output, transpose_opt)?,
         DataType::Struct(_) => write_struct_array(as_struct_array(array), output, transpose_opt)?,
+        DataType::Dictionary(_, value_type) => {
+            // dictionaries are not kept across serde, write plain values
+            let values = arrow::compute::cast(array, value_type)?;
+            write_array(&values, output, transpose_opt)?
+        }
         other => df_unimplemented_err!("unsupported data type: {other}")?,
     }
     Ok(())
@@ -149,6 +281,15 @@ pub fn read_array<R: Read>(
     data_type: &DataType,
     num_rows: usize,
     transpose_opt: &mut TransposeOpt,
+) -> Result<ArrayRef> {
+    read_array_impl(&mut CopyingRead(input), data_type, num_rows, transpose_opt)
+}
+
+fn read_array_impl<R: BufferRead>(
+    input: &mut R,
+    data_type: &DataType,
+    num_rows: usize,
+    transpose_opt: &mut TransposeOpt,
 ) -> Result<ArrayRef> {
     macro_rules! read_primitive {
         ($ty:ident) => {{
@@ -186,6 +327,10 @@ pub fn read_array<R: Read>(
             read_map_array(num_rows, input, map_field, *is_sorted, transpose_opt)?
         }
         DataType::Struct(fields) => read_struct_array(num_rows, input, fields, transpose_opt)?,
+        DataType::Dictionary(_, value_type) => {
+            let values = read_array_impl(input, value_type, num_rows, transpose_opt)?;
+            arrow::compute::cast(&values, data_type)?
+        }
         other => df_unimplemented_err!("unsupported data type: {other}")?,
     })
 }
@@ -211,9 +356,8 @@ fn write_bits_buffer<W: Write>(
     Ok(())
 }
 
-fn read_bits_buffer<R: Read>(input: &mut R, bits_len: usize) -> Result<Buffer> {
-    let buf = read_bytes_slice(input, (bits_len + 7) / 8)?;
-    Ok(Buffer::from_vec(buf.into()))
+fn read_bits_buffer<R: BufferRead>(input: &mut R, bits_len: usize) -> Result<Buffer> {
+    Ok(input.read_buffer((bits_len + 7) / 8)?)
 }
 
 fn write_offsets<W: Write>(
@@ -307,7 +451,7 @@ fn write_primitive_array<W: Write, PT: ArrowPrimitiveType>(
     Ok(())
 }
 
-fn read_primitive_array<R: Read, PT: ArrowPrimitiveType>(
+fn read_primitive_array<R: BufferRead, PT: ArrowPrimitiveType>(
     num_rows: usize,
     input: &mut R,
     transpose_opt: &mut TransposeOpt,
@@ -319,28 +463,32 @@ fn read_primitive_array<R: Read, PT: ArrowPrimitiveType>(
         None
     };
 
-    let mut values: Vec<PT::Native> = Vec::uninitialized_init(num_rows);
     let byte_width = PT::Native::get_byte_width();
-    if let TransposeOpt::Transpose(buffer) = transpose_opt
-        && byte_width > 1
-    {
-        input.read_exact(buffer[..byte_width * num_rows].as_mut())?;
-        transpose::transpose(
-            buffer[..byte_width * num_rows].as_ref(),
-            values.as_raw_bytes_mut(),
-            num_rows,
-            byte_width,
-        );
+    let values_buffer = if byte_width == 1 {
+        // single-byte values are never transposed, read as raw bytes
+        input.read_buffer(num_rows)?
     } else {
-        input.read_exact(values.as_raw_bytes_mut())?;
-    }
+        let mut values: Vec<PT::Native> = Vec::uninitialized_init(num_rows);
+        if let TransposeOpt::Transpose(buffer) = transpose_opt {
+            input.read_exact(buffer[..byte_width * num_rows].as_mut())?;
+            transpose::transpose(
+                buffer[..byte_width * num_rows].as_ref(),
+                values.as_raw_bytes_mut(),
+                num_rows,
+                byte_width,
+            );
+        } else {
+            input.read_exact(values.as_raw_bytes_mut())?;
+        }
+        Buffer::from_vec(values)
+    };
 
     let array_data = ArrayData::try_new(
         PT::DATA_TYPE,
         num_rows,
         null_buffer,
         0,
-        vec![Buffer::from_vec(values)],
+        vec![values_buffer],
         vec![],
     )?;
     Ok(make_array(array_data))
@@ -378,7 +526,7 @@ fn write_list_array<W: Write>(
     Ok(())
 }
 
-fn read_list_array<R: Read>(
+fn read_list_array<R: BufferRead>(
     num_rows: usize,
     input: &mut R,
     list_field: &FieldRef,
@@ -394,7 +542,7 @@ fn read_list_array<R: Read>(
     let offsets = read_offsets(input, num_rows, transpose_opt)?;
     let values_len = offsets.last().cloned().unwrap() as usize;
     let offsets_buffer: Buffer = Buffer::from_vec(offsets);
-    let values = read_array(
+    let values = read_array_impl(
         input,
         list_field.data_type(),
         values_len,
@@ -445,7 +593,7 @@ fn write_map_array<W: Write>(
     Ok(())
 }
 
-fn read_map_array<R: Read>(
+fn read_map_array<R: BufferRead>(
     num_rows: usize,
     input: &mut R,
     map_field: &FieldRef,
@@ -472,7 +620,7 @@ fn read_map_array<R: Read>(
     };
     let key_values: Vec<ArrayRef> = kv_fields
         .iter()
-        .map(|f| read_array(input, f.data_type(), entries_len, &mut child_transpose_opt))
+        .map(|f| read_array_impl(input, f.data_type(), entries_len, &mut child_transpose_opt))
         .collect::<Result<_>>()?;
 
     let struct_array_data = ArrayData::try_new(
@@ -519,7 +667,7 @@ fn write_struct_array<W: Write>(
     Ok(())
 }
 
-fn read_struct_array<R: Read>(
+fn read_struct_array<R: BufferRead>(
     num_rows: usize,
     input: &mut R,
     fields: &Fields,
@@ -534,7 +682,7 @@ fn read_struct_array<R: Read>(
 
     let child_arrays: Vec<ArrayRef> = fields
         .iter()
-        .map(|field| read_array(input, field.data_type(), num_rows, transpose_opt))
+        .map(|field| read_array_impl(input, field.data_type(), num_rows, transpose_opt))
         .collect::<Result<_>>()?;
 
     let array_data = ArrayData::try_new(
@@ -570,7 +718,7 @@ fn write_boolean_array<W: Write>(array: &BooleanArray, output: &mut W) -> Result
     Ok(())
 }
 
-fn read_boolean_array<R: Read>(num_rows: usize, input: &mut R) -> Result<ArrayRef> {
+fn read_boolean_array<R: BufferRead>(num_rows: usize, input: &mut R) -> Result<ArrayRef> {
     let has_null_buffer = read_len(input)? == 1;
     let null_buffer: Option<Buffer> = if has_null_buffer {
         Some(read_bits_buffer(input, num_rows)?)
@@ -620,7 +768,7 @@ fn write_bytes_array<T: ByteArrayType<Offset = i32>, W: Write>(
     Ok(())
 }
 
-fn read_bytes_array<R: Read>(
+fn read_bytes_array<R: BufferRead>(
     num_rows: usize,
     input: &mut R,
     data_type: DataType,
@@ -637,7 +785,7 @@ fn read_bytes_array<R: Read>(
     let values_len = offsets.last().cloned().unwrap() as usize;
     let offsets_buffer = Buffer::from_vec(offsets);
 
-    let data_buffer = Buffer::from_vec(read_bytes_slice(input, values_len)?.into());
+    let data_buffer = input.read_buffer(values_len)?;
     let array_data = ArrayData::try_new(
         data_type,
         num_rows,
@@ -871,4 +1019,38 @@ mod test {
             sliced
         );
     }
+
+    #[test]
+    fn test_write_and_read_batch_for_dictionary() {
+        let values: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("a"),
+            None,
+            Some("b"),
+            Some("a"),
+        ]));
+        let dict = arrow::compute::cast(
+            &values,
+            &DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+        )
+        .unwrap();
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![("dict", dict, true)]).unwrap();
+
+        // dictionaries are written as plain values
+        let mut buf = vec![];
+        write_batch(batch.num_rows(), batch.columns(), &mut buf).unwrap();
+        let mut plain_buf = vec![];
+        write_batch(values.len(), &[values.clone()], &mut plain_buf).unwrap();
+        assert_eq!(buf, plain_buf);
+
+        // and re-encoded on reading
+        let mut cursor = Cursor::new(buf);
+        let (decoded_num_rows, decoded_cols) =
+            read_batch(&mut cursor, &batch.schema()).unwrap().unwrap();
+        assert_eq!(decoded_num_rows, 4);
+        assert_eq!(decoded_cols[0].data_type(), batch.column(0).data_type());
+        assert_eq!(
+            &arrow::compute::cast(&decoded_cols[0], &DataType::Utf8).unwrap(),
+            &values
+        );
+    }
 }
@@ -12,26 +12,73 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Seek, Write};
 
 use arrow::{
     array::{Array, ArrayRef, RecordBatchOptions},
+    buffer::Buffer,
     datatypes::SchemaRef,
+    error::ArrowError,
     record_batch::RecordBatch,
 };
-pub use batch_serde::{read_array, write_array};
-use datafusion::common::Result;
+pub use batch_serde::{read_array, write_array, BufferCursor, BufferRead};
+use blaze_jni_bridge::conf::{BooleanConf, SERDE_CHECK_DATA_TYPES_ENABLE};
+use datafusion::common::{DataFusionError, Result};
+use once_cell::sync::OnceCell;
 pub use scalar_serde::{read_scalar, write_scalar};
 
-use crate::{arrow::cast::cast, UninitializedInit};
+use crate::{arrow::cast::cast, error::BlazeError, UninitializedInit};
 
 mod batch_serde;
 mod scalar_serde;
 
 pub fn write_one_batch(num_rows: usize, cols: &[ArrayRef], mut output: impl Write) -> Result<()> {
+    check_cols_serializable_if_enabled(cols)?;
     batch_serde::write_batch(num_rows, cols, &mut output)
 }
 
+/// checks that all columns can be serialized, returns an error naming the
+/// first unsupported (possibly nested) field and its data type. only data
+/// types are walked, so the check is cheap and fails before anything is
+/// written.
+pub fn check_cols_serializable(cols: &[ArrayRef]) -> Result<()> {
+    for (i, col) in cols.iter().enumerate() {
+        let mut path = format!("#{i}");
+        if let Some(data_type) = batch_serde::find_unsupported_data_type(col.data_type(), &mut path)
+        {
+            let err = ArrowError::SchemaError(format!(
+                "cannot serialize column {path}: unsupported data type {data_type}"
+            ));
+            return Err(BlazeError::Unsupported(err.into()).into());
+        }
+    }
+    Ok(())
+}
+
+fn check_cols_serializable_if_enabled(cols: &[ArrayRef]) -> Result<()> {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    let enabled = *ENABLED.get_or_init(|| SERDE_CHECK_DATA_TYPES_ENABLE.value().unwrap_or(true));
+    if enabled {
+        check_cols_serializable(cols)?;
+    }
+    Ok(())
+}
+
+/// writes one batch like `write_one_batch`, returns the absolute start offset
+/// of the written frame and its length. offsets of all batches can be kept as
+/// an index to seek directly to a specific batch in a multi-batch file.
+pub fn write_one_batch_with_offset(
+    num_rows: usize,
+    cols: &[ArrayRef],
+    mut output: impl Write + Seek,
+) -> Result<(u64, usize)> {
+    check_cols_serializable_if_enabled(cols)?;
+    let start_pos = output.stream_position()?;
+    batch_serde::write_batch(num_rows, cols, &mut output)?;
+    let end_pos = output.stream_position()?;
+    Ok((start_pos, (end_pos - start_pos) as usize))
+}
+
 pub fn read_one_batch(
     mut input: impl Read,
     schema: &SchemaRef,
@@ -39,6 +86,60 @@ pub fn read_one_batch(
     batch_serde::read_batch(&mut input, schema)
 }
 
+/// writes one batch like `write_one_batch`, with a header of the content
+/// length and a 4-byte CRC32C of the content, for detecting corrupted data
+/// in spill files. must be read by `read_one_batch_with_checksum`.
+pub fn write_one_batch_with_checksum(
+    num_rows: usize,
+    cols: &[ArrayRef],
+    mut output: impl Write,
+) -> Result<()> {
+    check_cols_serializable_if_enabled(cols)?;
+    let mut content = vec![];
+    batch_serde::write_batch(num_rows, cols, &mut content)?;
+    write_len(content.len(), &mut output)?;
+    output.write_all(&crc32c::crc32c(&content).to_le_bytes())?;
+    output.write_all(&content)?;
+    Ok(())
+}
+
+/// reads one batch written by `write_one_batch_with_checksum`, the checksum
+/// is verified if `verify_checksum` is true.
+pub fn read_one_batch_with_checksum(
+    mut input: impl Read,
+    schema: &SchemaRef,
+    verify_checksum: bool,
+) -> Result<Option<(usize, Vec<ArrayRef>)>> {
+    let content_len = match read_len(&mut input) {
+        Ok(n) => n,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut checksum_buf = [0u8; 4];
+    input.read_exact(&mut checksum_buf)?;
+    let content = read_bytes_slice(&mut input, content_len)?;
+
+    if verify_checksum {
+        let expected = u32::from_le_bytes(checksum_buf);
+        let actual = crc32c::crc32c(&content);
+        if actual != expected {
+            log::warn!(
+                "batch checksum mismatch: expected={expected:#010x}, actual={actual:#010x}, \
+                 content_len={content_len}"
+            );
+            let err = std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "batch checksum mismatch: expected={expected:#010x}, actual={actual:#010x}"
+                ),
+            );
+            return Err(BlazeError::Corruption(err.into()).into());
+        }
+    }
+    let mut cursor = BufferCursor::new(Buffer::from_vec(content.into_vec()));
+    batch_serde::read_batch_from_buffer(&mut cursor, schema)
+}
+
 pub fn recover_named_batch(
     num_rows: usize,
     cols: &[ArrayRef],
@@ -56,7 +157,28 @@ pub fn recover_named_batch(
     )?)
 }
 
-pub fn write_len<W: Write>(mut len: usize, output: &mut W) -> std::io::Result<()> {
+/// writes a length as a varint of 7-bit groups. lengths like concatenated
+/// row blobs and spill segments may exceed 4GiB, so they must never be
+/// narrowed through i32/u32 before being written.
+pub fn write_len<W: Write>(len: usize, output: &mut W) -> std::io::Result<()> {
+    write_len_u64(len as u64, output)
+}
+
+/// reads a length written by `write_len`, returns an error if the length does
+/// not fit in usize.
+pub fn read_len<R: Read>(input: &mut R) -> std::io::Result<usize> {
+    let len = read_len_u64(input)?;
+    usize::try_from(len).map_err(|_| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("length {len} exceeds the platform usize"),
+        )
+    })
+}
+
+/// writes a 64-bit value in the same varint format as `write_len`, used for
+/// counters which are not bounded by memory size.
+pub fn write_len_u64<W: Write>(mut len: u64, output: &mut W) -> std::io::Result<()> {
     while len >= 128 {
         let v = len % 128;
         len /= 128;
@@ -66,17 +188,25 @@ pub fn write_len<W: Write>(mut len: usize, output: &mut W) -> std::io::Result<()
     Ok(())
 }
 
-pub fn read_len<R: Read>(input: &mut R) -> std::io::Result<usize> {
-    let mut len = 0usize;
-    let mut factor = 1;
+/// reads a value written by `write_len_u64` or `write_len`. corrupted varints
+/// overflowing 64 bits are rejected instead of silently wrapping around.
+pub fn read_len_u64<R: Read>(input: &mut R) -> std::io::Result<u64> {
+    let mut len = 0u64;
+    let mut shift = 0;
     loop {
         let v = read_u8(input)?;
+        let bits = (v % 128) as u64;
+        if shift > 63 || (shift > 57 && bits >> (64 - shift) != 0) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "varint length overflows 64 bits",
+            ));
+        }
+        len |= bits << shift;
         if v < 128 {
-            len += (v as usize) * factor;
             break;
         }
-        len += (v - 128) as usize * factor;
-        factor *= 128;
+        shift += 7;
     }
     Ok(len)
 }
@@ -97,3 +227,173 @@ pub fn read_bytes_slice<R: Read>(input: &mut R, len: usize) -> std::io::Result<B
     input.read_exact(buf.as_mut())?;
     Ok(buf.into())
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Cursor, ErrorKind, Read, Seek, SeekFrom},
+        sync::Arc,
+    };
+
+    use arrow::{
+        array::{new_null_array, ArrayRef, Int32Array, StringArray},
+        datatypes::{DataType, Field, Fields, Schema},
+    };
+    use datafusion::common::Result;
+
+    use crate::{
+        error::BlazeError,
+        io::{
+            check_cols_serializable, read_len, read_len_u64, read_one_batch,
+            read_one_batch_with_checksum, write_len, write_len_u64, write_one_batch,
+            write_one_batch_with_checksum, write_one_batch_with_offset,
+        },
+    };
+
+    #[test]
+    fn test_random_access_with_batch_offsets() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        let batches: Vec<Vec<ArrayRef>> = (0..5)
+            .map(|i| {
+                vec![
+                    Arc::new(Int32Array::from_iter_values(i * 100..i * 100 + 10 + i)) as ArrayRef,
+                    Arc::new(StringArray::from_iter_values(
+                        (0..10 + i).map(|j| format!("{i}-{j}")),
+                    )),
+                ]
+            })
+            .collect();
+
+        let mut file = Cursor::new(vec![]);
+        let mut offsets = vec![];
+        for cols in &batches {
+            let (offset, len) = write_one_batch_with_offset(cols[0].len(), cols, &mut file)?;
+            assert_eq!(offset + len as u64, file.position());
+            offsets.push(offset);
+        }
+        assert_eq!(offsets[0], 0);
+
+        // read batches in reversed order by seeking to their offsets
+        for (i, &offset) in offsets.iter().enumerate().rev() {
+            file.seek(SeekFrom::Start(offset))?;
+            let (num_rows, cols) = read_one_batch(&mut file, &schema)?.expect("batch");
+            assert_eq!(num_rows, batches[i][0].len());
+            assert_eq!(&cols, &batches[i]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_checksum() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let cols: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(vec![Some(1), None, Some(3)])),
+            Arc::new(StringArray::from(vec![Some("x"), Some("yy"), None])),
+        ];
+        let mut spill = vec![];
+        write_one_batch_with_checksum(3, &cols, &mut spill)?;
+        write_one_batch_with_checksum(3, &cols, &mut spill)?;
+
+        let mut input = Cursor::new(&spill);
+        for _ in 0..2 {
+            let (num_rows, read_cols) =
+                read_one_batch_with_checksum(&mut input, &schema, true)?.expect("batch");
+            assert_eq!(num_rows, 3);
+            assert_eq!(&read_cols, &cols);
+        }
+        assert!(read_one_batch_with_checksum(&mut input, &schema, true)?.is_none());
+
+        // corrupt one byte in the content of the second batch
+        let mut corrupted = spill.clone();
+        let last_idx = corrupted.len() - 1;
+        corrupted[last_idx] ^= 0x01;
+        let mut input = Cursor::new(&corrupted);
+        assert!(read_one_batch_with_checksum(&mut input, &schema, true)?.is_some());
+        let err = read_one_batch_with_checksum(&mut input, &schema, true).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+        assert!(matches!(
+            BlazeError::classify(err),
+            BlazeError::Corruption(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_cols_serializable() -> Result<()> {
+        let supported_type = DataType::Map(
+            Arc::new(Field::new(
+                "entries",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("key", DataType::Utf8, false),
+                    Field::new_list("value", Field::new("item", DataType::Int64, true), true),
+                ])),
+                false,
+            )),
+            false,
+        );
+        let cols = vec![
+            new_null_array(&DataType::Int32, 3),
+            new_null_array(&supported_type, 3),
+        ];
+        check_cols_serializable(&cols)?;
+
+        // unsupported type nested in list of struct
+        let unsupported_type = DataType::new_list(
+            DataType::Struct(Fields::from(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", DataType::LargeUtf8, true),
+            ])),
+            true,
+        );
+        let cols = vec![
+            new_null_array(&DataType::Int32, 3),
+            new_null_array(&unsupported_type, 3),
+        ];
+        let mut output = vec![];
+        let err = write_one_batch(3, &cols, &mut output).unwrap_err();
+        assert!(err.to_string().contains("#1.item.b"), "{err}");
+        assert!(err.to_string().contains("LargeUtf8"), "{err}");
+        assert!(matches!(
+            BlazeError::classify(err),
+            BlazeError::Unsupported(_)
+        ));
+        assert!(output.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_len_exceeding_u32() -> Result<()> {
+        for len in [0, 128, u32::MAX as u64 + 1, 5 << 30, u64::MAX] {
+            let mut buf = vec![];
+            write_len_u64(len, &mut buf)?;
+            assert_eq!(read_len_u64(&mut Cursor::new(&buf))?, len);
+        }
+
+        // a >4GiB segment with a length header, simulated with a reader
+        // generating the content instead of storing it in memory
+        let segment_len = u32::MAX as usize + 17;
+        let mut header = vec![];
+        write_len(segment_len, &mut header)?;
+        let mut r = Cursor::new(header).chain(std::io::repeat(0).take(segment_len as u64));
+        let len = read_len(&mut r)?;
+        assert_eq!(len, segment_len);
+        let copied = std::io::copy(&mut (&mut r).take(len as u64), &mut std::io::sink())?;
+        assert_eq!(copied, segment_len as u64);
+        assert_eq!(r.read(&mut [0u8; 1])?, 0);
+
+        // corrupted varints overflowing 64 bits are rejected
+        let overflowed = [[0xff; 9].as_slice(), &[0x02]].concat();
+        let err = read_len_u64(&mut Cursor::new(&overflowed)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let overflowed = [[0xff; 10].as_slice(), &[0x01]].concat();
+        let err = read_len(&mut Cursor::new(&overflowed)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        Ok(())
+    }
+}
@@ -27,6 +27,7 @@ use unchecked_index::UncheckedIndex;
 
 pub mod algorithm;
 pub mod arrow;
+pub mod error;
 pub mod hadoop_fs;
 pub mod hash;
 pub mod io;
@@ -34,6 +35,7 @@ pub mod scalar_value;
 pub mod spark_bit_array;
 pub mod spark_bloom_filter;
 pub mod spark_hash;
+pub mod spark_random;
 pub mod uda;
 
 #[macro_export]
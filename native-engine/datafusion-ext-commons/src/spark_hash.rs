@@ -15,14 +15,20 @@
 //! Functionality used both on logical and physical plans
 
 use arrow::{
-    array::*,
+    array::{cast::AsArray, *},
     datatypes::{
         ArrowDictionaryKeyType, ArrowNativeType, DataType, Int16Type, Int32Type, Int64Type,
         Int8Type, TimeUnit,
     },
 };
 
-use crate::hash::{mur::spark_compatible_murmur3_hash, xxhash::spark_compatible_xxhash64_hash};
+use crate::{
+    arrow::collation::Collation,
+    hash::{
+        mur::spark_compatible_murmur3_hash, xxh3::xxh3_hash32,
+        xxhash::spark_compatible_xxhash64_hash,
+    },
+};
 
 pub fn create_murmur3_hashes(len: usize, arrays: &[ArrayRef], seed: i32) -> Vec<i32> {
     create_hashes(len, arrays, seed, |data: &[u8], seed: i32| {
@@ -36,6 +42,41 @@ pub fn create_xxhash64_hashes(len: usize, arrays: &[ArrayRef], seed: i64) -> Vec
     })
 }
 
+/// creates 32-bit xxhash3 hashes. values are fed with the same bytes as spark's
+/// hash functions, so the hashes are deterministic across platforms, but not
+/// compatible with any hash function in spark.
+pub fn create_xxh3_hashes(len: usize, arrays: &[ArrayRef], seed: u32) -> Vec<u32> {
+    create_hashes(len, arrays, seed, |data: &[u8], seed: u32| {
+        xxh3_hash32(data, seed)
+    })
+}
+
+/// hash algorithms selectable in create_hashes_with_algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// same as spark's murmur3 hash, used when the data must be co-located with
+    /// data partitioned by the jvm
+    SparkMurmur3,
+    /// faster xxhash3, used when hashes are only consumed by native operators
+    Xxh3,
+}
+
+/// creates 32-bit hashes with the specified algorithm
+pub fn create_hashes_with_algorithm(
+    len: usize,
+    arrays: &[ArrayRef],
+    algorithm: HashAlgorithm,
+    seed: u32,
+) -> Vec<u32> {
+    match algorithm {
+        HashAlgorithm::SparkMurmur3 => create_murmur3_hashes(len, arrays, seed as i32)
+            .into_iter()
+            .map(|h| h as u32)
+            .collect(),
+        HashAlgorithm::Xxh3 => create_xxh3_hashes(len, arrays, seed),
+    }
+}
+
 /// Creates hash values for every row, based on the values in the
 /// columns.
 ///
@@ -63,6 +104,47 @@ pub fn create_hashes<T: num::PrimInt>(
     hash_buffer
 }
 
+/// like create_hashes, but string columns are hashed with their values folded
+/// by the given collations. hashes are the same as hashing the materialized
+/// folded columns.
+pub fn create_hashes_with_collations<T: num::PrimInt>(
+    len: usize,
+    arrays: &[ArrayRef],
+    collations: &[Collation],
+    seed: T,
+    h: impl Fn(&[u8], T) -> T + Copy,
+) -> Vec<T> {
+    assert_eq!(arrays.len(), collations.len());
+    if arrays.is_empty() {
+        return vec![seed; len];
+    }
+    let mut hash_buffer = vec![T::zero(); len];
+    let mut fold_buf = vec![];
+
+    for (idx, (col, &collation)) in arrays.iter().zip(collations).enumerate() {
+        let is_initial = idx == 0;
+        macro_rules! hash_collated {
+            ($offset:ty) => {{
+                let array = col.as_string::<$offset>();
+                for (i, hash) in hash_buffer.iter_mut().enumerate() {
+                    if array.is_valid(i) {
+                        fold_buf.clear();
+                        collation.fold_into(array.value(i), &mut fold_buf);
+                        *hash = h(&fold_buf, if is_initial { seed } else { *hash });
+                    }
+                }
+            }};
+        }
+        match col.data_type() {
+            _ if collation.is_binary() => hash_array(col, &mut hash_buffer, seed, is_initial, h),
+            DataType::Utf8 => hash_collated!(i32),
+            DataType::LargeUtf8 => hash_collated!(i64),
+            _ => hash_array(col, &mut hash_buffer, seed, is_initial, h),
+        }
+    }
+    hash_buffer
+}
+
 #[inline]
 fn hash_array<T: num::PrimInt>(
     array: &ArrayRef,
@@ -351,13 +433,13 @@ fn hash_one<T: num::PrimInt>(
             DataType::Float64 => {
                 hash_one_primitive!(Float64Array, col, f64, hash, idx, h);
             }
-            DataType::Timestamp(TimeUnit::Second, None) => {
+            DataType::Timestamp(TimeUnit::Second, _) => {
                 hash_one_primitive!(TimestampSecondArray, col, i64, hash, idx, h);
             }
-            DataType::Timestamp(TimeUnit::Millisecond, None) => {
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
                 hash_one_primitive!(TimestampMillisecondArray, col, i64, hash, idx, h);
             }
-            DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
                 hash_one_primitive!(TimestampMicrosecondArray, col, i64, hash, idx, h);
             }
             DataType::Timestamp(TimeUnit::Nanosecond, _) => {
@@ -656,4 +738,183 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn test_create_hashes_with_collations() {
+        let h = |data: &[u8], seed: i32| spark_compatible_murmur3_hash(data, seed);
+        let mixed = Arc::new(StringArray::from(vec![
+            Some("Hello"),
+            None,
+            Some("ΟΔΥΣΣΕΥΣ"),
+            Some("Àb"),
+        ])) as ArrayRef;
+        let lowered = Arc::new(StringArray::from(vec![
+            Some("hello"),
+            None,
+            Some("οδυσσευς"),
+            Some("àb"),
+        ])) as ArrayRef;
+        let ints = Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef;
+
+        let collated = create_hashes_with_collations(
+            4,
+            &[ints.clone(), mixed.clone()],
+            &[Collation::Binary, Collation::UnicodeCaseInsensitive],
+            42,
+            h,
+        );
+        let expected = create_hashes(4, &[ints.clone(), lowered], 42, h);
+        assert_eq!(collated, expected);
+
+        // binary collations keep the original hashes
+        let binary = create_hashes_with_collations(
+            4,
+            &[ints.clone(), mixed.clone()],
+            &[Collation::Binary, Collation::Binary],
+            42,
+            h,
+        );
+        assert_eq!(binary, create_hashes(4, &[ints, mixed], 42, h));
+    }
+
+    #[test]
+    fn test_timestamps_ignore_timezone() {
+        let micros = vec![Some(1710061200_000000), None, Some(1710068400_000000)];
+        let timestamps = |tz: Option<&str>| -> ArrayRef {
+            let array = TimestampMicrosecondArray::from(micros.clone());
+            match tz {
+                Some(tz) => Arc::new(array.with_timezone(tz)),
+                None => Arc::new(array),
+            }
+        };
+        let timestamp_lists = |tz: Option<&str>| -> ArrayRef {
+            let values = timestamps(tz);
+            let item = Arc::new(Field::new("item", values.data_type().clone(), true));
+            let offsets = arrow::buffer::OffsetBuffer::new(vec![0, 2, 2, 3].into());
+            Arc::new(ListArray::new(item, offsets, values, None))
+        };
+
+        for tz in [Some("UTC"), Some("America/Los_Angeles")] {
+            assert_eq!(
+                create_murmur3_hashes(3, &[timestamps(tz)], 42),
+                create_murmur3_hashes(3, &[timestamps(None)], 42),
+            );
+            assert_eq!(
+                create_xxhash64_hashes(3, &[timestamp_lists(tz)], 42),
+                create_xxhash64_hashes(3, &[timestamp_lists(None)], 42),
+            );
+        }
+    }
+
+    #[test]
+    fn test_xxh3_hashes_deterministic() {
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(BooleanArray::from(vec![
+                Some(true),
+                None,
+                Some(false),
+                Some(true),
+            ])),
+            Arc::new(Int8Array::from(vec![
+                Some(1),
+                Some(-1),
+                None,
+                Some(i8::MAX),
+            ])),
+            Arc::new(Int64Array::from(vec![
+                Some(1),
+                Some(i64::MIN),
+                Some(0),
+                None,
+            ])),
+            Arc::new(Float64Array::from(vec![
+                Some(1.5),
+                None,
+                Some(-0.0),
+                Some(f64::MAX),
+            ])),
+            Arc::new(Date32Array::from(vec![
+                Some(19000),
+                Some(0),
+                None,
+                Some(-1),
+            ])),
+            Arc::new(
+                Decimal128Array::from(vec![Some(12345), None, Some(-1), Some(0)])
+                    .with_precision_and_scale(10, 2)
+                    .unwrap(),
+            ),
+            Arc::new(StringArray::from(vec![
+                Some("a"),
+                Some(""),
+                None,
+                Some("a string longer than sixteen bytes"),
+            ])),
+            Arc::new(BinaryArray::from(vec![
+                Some(b"\x00".as_ref()),
+                None,
+                Some(b""),
+                Some(b"xyz"),
+            ])),
+        ];
+
+        for array in &arrays {
+            let hashes = create_xxh3_hashes(4, &[array.clone()], 42);
+            assert_eq!(hashes, create_xxh3_hashes(4, &[array.clone()], 42));
+
+            // independent of the offsets of underlying buffers
+            let padded =
+                arrow::compute::concat(&[array.slice(1, 3).as_ref(), array.as_ref()]).unwrap();
+            assert_eq!(hashes, create_xxh3_hashes(4, &[padded.slice(3, 4)], 42));
+
+            // null values keep the seed
+            for (i, hash) in hashes.iter().enumerate() {
+                if array.is_null(i) {
+                    assert_eq!(*hash, 42);
+                }
+            }
+        }
+
+        // multiple columns are chained in order
+        let chained = create_xxh3_hashes(4, &arrays, 42);
+        assert_eq!(chained, create_xxh3_hashes(4, &arrays, 42));
+        let mut reversed = arrays.clone();
+        reversed.reverse();
+        assert_ne!(chained, create_xxh3_hashes(4, &reversed, 42));
+
+        // values are fed with the same bytes as spark's hash functions
+        let strings = vec![Some("a"), None, Some("a"), Some("")];
+        let dict = Arc::new(DictionaryArray::<Int32Type>::from_iter(strings.clone())) as ArrayRef;
+        let plain = Arc::new(StringArray::from(strings)) as ArrayRef;
+        assert_eq!(
+            create_xxh3_hashes(4, &[dict], 0),
+            create_xxh3_hashes(4, &[plain.clone()], 0),
+        );
+        assert_eq!(create_xxh3_hashes(4, &[plain], 0)[3], xxh3_hash32(b"", 0));
+        let i8s = Arc::new(Int8Array::from(vec![1, -1, 0, 127])) as ArrayRef;
+        let i32s = Arc::new(Int32Array::from(vec![1, -1, 0, 127])) as ArrayRef;
+        assert_eq!(
+            create_xxh3_hashes(4, &[i8s], 42),
+            create_xxh3_hashes(4, &[i32s], 42),
+        );
+    }
+
+    #[test]
+    fn test_create_hashes_with_algorithm() {
+        let i = Arc::new(Int32Array::from(vec![Some(1), None, Some(3)])) as ArrayRef;
+        let s = Arc::new(StringArray::from(vec![Some("a"), Some("b"), None])) as ArrayRef;
+        let arrays = [i, s];
+
+        // murmur3 hashes are the same as spark's, for co-locating with the jvm
+        let murmur3 = create_hashes_with_algorithm(3, &arrays, HashAlgorithm::SparkMurmur3, 42);
+        let expected = create_murmur3_hashes(3, &arrays, 42);
+        assert_eq!(
+            murmur3,
+            expected.into_iter().map(|h| h as u32).collect::<Vec<_>>()
+        );
+
+        let xxh3 = create_hashes_with_algorithm(3, &arrays, HashAlgorithm::Xxh3, 42);
+        assert_eq!(xxh3, create_xxh3_hashes(3, &arrays, 42));
+        assert_ne!(xxh3, murmur3);
+    }
 }
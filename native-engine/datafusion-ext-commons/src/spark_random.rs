@@ -0,0 +1,202 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! random generators producing exactly the same sequences as spark's.
+
+use crate::hash::mur::spark_compatible_murmur3_hash;
+
+/// same as org.apache.spark.util.random.XORShiftRandom, used by rand() and
+/// randn(), which is seeded with `seed + partition_index`.
+pub struct XorShiftRandom {
+    seed: i64,
+    next_next_gaussian: Option<f64>,
+}
+
+impl XorShiftRandom {
+    pub fn new(init: i64) -> Self {
+        Self {
+            seed: hash_seed(init),
+            next_next_gaussian: None,
+        }
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        let mut next_seed = self.seed ^ (self.seed << 21);
+        next_seed ^= ((next_seed as u64) >> 35) as i64;
+        next_seed ^= next_seed << 4;
+        self.seed = next_seed;
+        (next_seed & ((1i64 << bits) - 1)) as i32
+    }
+
+    /// same as java.util.Random.nextDouble()
+    pub fn next_double(&mut self) -> f64 {
+        const DOUBLE_UNIT: f64 = 1.0 / (1u64 << 53) as f64;
+        (((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 * DOUBLE_UNIT
+    }
+
+    /// same as java.util.Random.nextGaussian(), using the polar method and
+    /// caching the second generated value.
+    /// NOTE: java uses StrictMath.log(), which may differ from ln() in the
+    /// last ulp for rare inputs.
+    pub fn next_gaussian(&mut self) -> f64 {
+        if let Some(next_next_gaussian) = self.next_next_gaussian.take() {
+            return next_next_gaussian;
+        }
+        loop {
+            let v1 = 2.0 * self.next_double() - 1.0;
+            let v2 = 2.0 * self.next_double() - 1.0;
+            let s = v1 * v1 + v2 * v2;
+            if s < 1.0 && s != 0.0 {
+                let multiplier = (-2.0 * s.ln() / s).sqrt();
+                self.next_next_gaussian = Some(v2 * multiplier);
+                return v1 * multiplier;
+            }
+        }
+    }
+}
+
+// same as XORShiftRandom.hashSeed(), which hashes big-endian bytes of the
+// seed with scala's MurmurHash3.bytesHash(). for 8-byte inputs there is no
+// tail, so the result is identical to spark's murmur3 hash.
+fn hash_seed(seed: i64) -> i64 {
+    const ARRAY_SEED: i32 = 0x3c074a61;
+    let bytes = seed.to_be_bytes();
+    let low_bits = spark_compatible_murmur3_hash(bytes, ARRAY_SEED);
+    let high_bits = spark_compatible_murmur3_hash(bytes, low_bits);
+    ((high_bits as i64) << 32) | (low_bits as i64 & 0xffffffff)
+}
+
+const MT_N: usize = 624;
+const MT_M: usize = 397;
+
+/// same as org.apache.commons.math3.random.MersenneTwister (MT19937), used by
+/// uuid(), which is seeded with `seed + partition_index`.
+pub struct MersenneTwister {
+    mt: Box<[u32; MT_N]>,
+    mti: usize,
+}
+
+impl MersenneTwister {
+    pub fn new(seed: i64) -> Self {
+        Self::from_key(&[(seed as u64 >> 32) as u32, seed as u32])
+    }
+
+    // init_by_array() of the reference implementation
+    fn from_key(key: &[u32]) -> Self {
+        let mut mt = Box::new([0u32; MT_N]);
+        mt[0] = 19650218;
+        for i in 1..MT_N {
+            mt[i] = 1812433253u32
+                .wrapping_mul(mt[i - 1] ^ (mt[i - 1] >> 30))
+                .wrapping_add(i as u32);
+        }
+
+        let mut i = 1;
+        let mut j = 0;
+        for _ in 0..MT_N.max(key.len()) {
+            mt[i] = (mt[i] ^ (mt[i - 1] ^ (mt[i - 1] >> 30)).wrapping_mul(1664525))
+                .wrapping_add(key[j])
+                .wrapping_add(j as u32);
+            i += 1;
+            j += 1;
+            if i >= MT_N {
+                mt[0] = mt[MT_N - 1];
+                i = 1;
+            }
+            if j >= key.len() {
+                j = 0;
+            }
+        }
+        for _ in 0..MT_N - 1 {
+            mt[i] = (mt[i] ^ (mt[i - 1] ^ (mt[i - 1] >> 30)).wrapping_mul(1566083941))
+                .wrapping_sub(i as u32);
+            i += 1;
+            if i >= MT_N {
+                mt[0] = mt[MT_N - 1];
+                i = 1;
+            }
+        }
+        mt[0] = 0x80000000;
+        Self { mt, mti: MT_N }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.mti >= MT_N {
+            for k in 0..MT_N {
+                let y = (self.mt[k] & 0x80000000) | (self.mt[(k + 1) % MT_N] & 0x7fffffff);
+                let mag = if y & 1 == 0 { 0 } else { 0x9908b0df };
+                self.mt[k] = self.mt[(k + MT_M) % MT_N] ^ (y >> 1) ^ mag;
+            }
+            self.mti = 0;
+        }
+
+        let mut y = self.mt[self.mti];
+        self.mti += 1;
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9d2c5680;
+        y ^= (y << 15) & 0xefc60000;
+        y ^= y >> 18;
+        y
+    }
+
+    /// same as BitsStreamGenerator.nextLong()
+    pub fn next_i64(&mut self) -> i64 {
+        let high = (self.next_u32() as u64) << 32;
+        let low = self.next_u32() as u64;
+        (high | low) as i64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spark_random::{MersenneTwister, XorShiftRandom};
+
+    #[test]
+    fn test_xor_shift_random() {
+        // expected values are taken from spark's RandomSuite, evaluated in
+        // partition 0
+        assert_eq!(XorShiftRandom::new(30).next_double(), 0.2762195585886885);
+        assert_eq!(XorShiftRandom::new(0).next_double(), 0.7604953758285915);
+        assert_eq!(XorShiftRandom::new(30).next_gaussian(), -1.0451987154313813);
+        assert_eq!(XorShiftRandom::new(0).next_gaussian(), 1.6034991609278433);
+
+        // the second gaussian value is cached without advancing the state
+        let mut rng = XorShiftRandom::new(30);
+        rng.next_gaussian();
+        let seed = rng.seed;
+        assert!(rng.next_next_gaussian.is_some());
+        rng.next_gaussian();
+        assert_eq!(rng.seed, seed);
+        assert!(rng.next_next_gaussian.is_none());
+    }
+
+    #[test]
+    fn test_mersenne_twister() {
+        // reference output of mt19937ar.c with
+        // init_by_array({0x123, 0x234, 0x345, 0x456})
+        let mut rng = MersenneTwister::from_key(&[0x123, 0x234, 0x345, 0x456]);
+        let values = (0..5).map(|_| rng.next_u32()).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![1067595299, 955945823, 477289528, 4107218783, 4228976476]
+        );
+
+        // the long seed is split into high and low words
+        let mut rng1 = MersenneTwister::new(0x0000_0123_0000_0234);
+        let mut rng2 = MersenneTwister::from_key(&[0x123, 0x234]);
+        for _ in 0..1000 {
+            assert_eq!(rng1.next_i64(), rng2.next_i64());
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use arrow::{
+    datatypes::{DataType, Schema},
+    record_batch::RecordBatch,
+};
+use datafusion::{
+    common::{Result, ScalarValue},
+    logical_expr::ColumnarValue,
+    physical_plan::PhysicalExpr,
+};
+use datafusion_ext_commons::arrow::collation::Collation;
+
+use crate::down_cast_any_ref;
+
+/// string key with a collation. operators supporting collations (like
+/// broadcast hash join) unwrap the inner expr and hash/compare its values
+/// with the collation directly, other operators evaluate this expr, which
+/// materializes the folded values.
+#[derive(Debug, Hash)]
+pub struct CollatedExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    collation: Collation,
+}
+
+impl PartialEq<dyn Any> for CollatedExpr {
+    fn eq(&self, other: &dyn Any) -> bool {
+        down_cast_any_ref(other)
+            .downcast_ref::<Self>()
+            .map(|x| self.expr.eq(&x.expr) && self.collation == x.collation)
+            .unwrap_or(false)
+    }
+}
+
+impl CollatedExpr {
+    pub fn new(expr: Arc<dyn PhysicalExpr>, collation: Collation) -> Self {
+        Self { expr, collation }
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn collation(&self) -> Collation {
+        self.collation
+    }
+}
+
+impl Display for CollatedExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Collated({}, {:?})", self.expr, self.collation)
+    }
+}
+
+impl PhysicalExpr for CollatedExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.expr.data_type(input_schema)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        self.expr.nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        match self.expr.evaluate(batch)? {
+            ColumnarValue::Array(array) => {
+                Ok(ColumnarValue::Array(self.collation.fold_array(&array)?))
+            }
+            ColumnarValue::Scalar(scalar) => {
+                let folded = self.collation.fold_array(&scalar.to_array()?)?;
+                Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                    &folded, 0,
+                )?))
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
+        vec![&self.expr]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn PhysicalExpr>> {
+        Ok(Arc::new(Self::new(children[0].clone(), self.collation)))
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        let mut s = state;
+        self.hash(&mut s);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        logical_expr::ColumnarValue,
+        physical_expr::{expressions as phys_expr, PhysicalExpr},
+    };
+    use datafusion_ext_commons::arrow::collation::Collation;
+
+    use crate::collated::CollatedExpr;
+
+    #[test]
+    fn test_evaluate_materializes_folded_values() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("i", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("HeLLo"), None, Some("ÀÉÎ")])),
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+            ],
+        )?;
+
+        let expr = CollatedExpr::new(
+            phys_expr::col("s", &schema)?,
+            Collation::UnicodeCaseInsensitive,
+        );
+        let ret = expr.evaluate(&batch)?.into_array(batch.num_rows())?;
+        let expected: ArrayRef =
+            Arc::new(StringArray::from(vec![Some("hello"), None, Some("àéî")]));
+        assert_eq!(&ret, &expected);
+
+        // unsupported types are evaluated as is
+        let expr = CollatedExpr::new(
+            phys_expr::col("i", &schema)?,
+            Collation::AsciiCaseInsensitive,
+        );
+        let ret = expr.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(&ret, batch.column(1));
+
+        let expr = CollatedExpr::new(phys_expr::lit("ÀbC"), Collation::AsciiCaseInsensitive);
+        match expr.evaluate(&batch)? {
+            ColumnarValue::Scalar(scalar) => {
+                assert_eq!(scalar, ScalarValue::from("Àbc"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        Ok(())
+    }
+}
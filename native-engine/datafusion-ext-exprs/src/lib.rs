@@ -18,15 +18,20 @@ use datafusion::physical_expr::PhysicalExpr;
 
 pub mod bloom_filter_might_contain;
 pub mod cast;
+pub mod collated;
 pub mod get_indexed_field;
 pub mod get_map_value;
 pub mod named_struct;
+pub mod native_udf;
 pub mod row_num;
+pub mod sequence;
+pub mod spark_random;
 pub mod spark_scalar_subquery_wrapper;
 pub mod spark_udf_wrapper;
 pub mod string_contains;
 pub mod string_ends_with;
 pub mod string_starts_with;
+pub mod string_substring_index;
 
 fn down_cast_any_ref(any: &dyn Any) -> &dyn Any {
     if any.is::<Arc<dyn PhysicalExpr>>() {
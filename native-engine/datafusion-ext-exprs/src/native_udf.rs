@@ -0,0 +1,327 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter},
+    hash::Hasher,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{new_empty_array, ArrayRef},
+    datatypes::{DataType, Field, Schema},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use datafusion::{
+    error::Result, logical_expr::ColumnarValue, physical_expr::physical_exprs_bag_equal,
+    physical_plan::PhysicalExpr,
+};
+use datafusion_ext_commons::df_execution_err;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+
+use crate::down_cast_any_ref;
+
+/// function of a native udf. it takes a batch of evaluated params and returns
+/// a batch with the same number of rows, whose first column is the result.
+pub type NativeUDFFunc = Arc<dyn Fn(&RecordBatch) -> Result<RecordBatch> + Send + Sync>;
+
+/// param and return types of a native udf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeUDFSignature {
+    pub param_types: Vec<DataType>,
+    pub return_type: DataType,
+}
+
+impl NativeUDFSignature {
+    pub fn new(param_types: Vec<DataType>, return_type: DataType) -> Self {
+        Self {
+            param_types,
+            return_type,
+        }
+    }
+}
+
+// udfs implemented natively with arrow compute kernels, udfs registered here
+// are evaluated without calling SparkUDFWrapperContext through jni. udfs are
+// keyed by name and param types, so overloads of the same name can coexist.
+type NativeUDFRegistry = HashMap<(String, Vec<DataType>), (DataType, NativeUDFFunc)>;
+
+fn native_udfs() -> &'static Mutex<NativeUDFRegistry> {
+    static NATIVE_UDFS: OnceCell<Mutex<NativeUDFRegistry>> = OnceCell::new();
+    NATIVE_UDFS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// registers a native udf with the given name and signature, replacing the
+/// existing one with the same name and param types
+pub fn register_native_udf(
+    name: &str,
+    signature: NativeUDFSignature,
+    func: NativeUDFFunc,
+) -> Result<()> {
+    if name.is_empty() {
+        return df_execution_err!("cannot register native udf without name");
+    }
+    let key = (name.to_string(), signature.param_types);
+    native_udfs()
+        .lock()
+        .insert(key, (signature.return_type, func));
+    Ok(())
+}
+
+/// returns the native udf matching the name, param types and return type.
+/// anonymous udfs (with empty names) never match.
+pub fn get_native_udf(name: &str, signature: &NativeUDFSignature) -> Option<NativeUDFFunc> {
+    if name.is_empty() {
+        return None;
+    }
+    let key = (name.to_string(), signature.param_types.clone());
+    match native_udfs().lock().get(&key) {
+        Some((return_type, func)) if return_type == &signature.return_type => Some(func.clone()),
+        _ => None,
+    }
+}
+
+/// evaluates a registered native udf, taking the place of SparkUDFWrapperExpr
+pub struct NativeUDFExpr {
+    name: String,
+    func: NativeUDFFunc,
+    return_type: DataType,
+    return_nullable: bool,
+    params: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl NativeUDFExpr {
+    pub fn new(
+        name: String,
+        func: NativeUDFFunc,
+        return_type: DataType,
+        return_nullable: bool,
+        params: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Self {
+        Self {
+            name,
+            func,
+            return_type,
+            return_nullable,
+            params,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq<dyn Any> for NativeUDFExpr {
+    fn eq(&self, other: &dyn Any) -> bool {
+        down_cast_any_ref(other)
+            .downcast_ref::<Self>()
+            .map(|x| {
+                physical_exprs_bag_equal(&self.params, &x.params)
+                    && self.name == x.name
+                    && self.return_type == x.return_type
+                    && self.return_nullable == x.return_nullable
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl Display for NativeUDFExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Debug for NativeUDFExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeUDF({}({:?}))", self.name, self.params)
+    }
+}
+
+impl PhysicalExpr for NativeUDFExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.return_type.clone())
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(self.return_nullable)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let batch_schema = batch.schema();
+        let num_rows = batch.num_rows();
+        if num_rows == 0 {
+            return Ok(ColumnarValue::Array(new_empty_array(&self.return_type)));
+        }
+
+        // evaluate params
+        let mut param_fields = Vec::with_capacity(self.params.len());
+        let mut params: Vec<ArrayRef> = Vec::with_capacity(self.params.len());
+        for param in &self.params {
+            param_fields.push(Field::new(
+                "",
+                param.data_type(&batch_schema)?,
+                param.nullable(&batch_schema)?,
+            ));
+            params.push(param.evaluate(batch)?.into_array(num_rows)?);
+        }
+        let params_batch = RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(param_fields)),
+            params,
+            &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+        )?;
+
+        // invoke udf natively
+        let output_batch = (self.func)(&params_batch)?;
+        if output_batch.num_columns() == 0 || output_batch.num_rows() != num_rows {
+            return df_execution_err!(
+                "NativeUDF {}: expect one output column of {num_rows} rows, found {} columns of \
+                 {} rows",
+                self.name,
+                output_batch.num_columns(),
+                output_batch.num_rows(),
+            );
+        }
+        let output = output_batch.column(0);
+        if output.data_type() != &self.return_type {
+            return df_execution_err!(
+                "NativeUDF {}: expect output of type {}, found {}",
+                self.name,
+                self.return_type,
+                output.data_type(),
+            );
+        }
+        Ok(ColumnarValue::Array(output.clone()))
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
+        self.params.iter().collect()
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn PhysicalExpr>> {
+        Ok(Arc::new(Self::new(
+            self.name.clone(),
+            self.func.clone(),
+            self.return_type.clone(),
+            self.return_nullable,
+            children,
+        )))
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        state.write(self.name.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, AsArray, Int64Array},
+        compute::kernels::numeric::mul,
+        datatypes::{DataType, Int64Type},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalExpr},
+    };
+
+    use crate::native_udf::{
+        get_native_udf, register_native_udf, NativeUDFExpr, NativeUDFSignature,
+    };
+
+    #[test]
+    fn test_native_udf() -> Result<()> {
+        let signature = NativeUDFSignature::new(vec![DataType::Int64], DataType::Int64);
+        register_native_udf(
+            "test_double",
+            signature.clone(),
+            Arc::new(|batch: &RecordBatch| {
+                let doubled = mul(batch.column(0), &Int64Array::new_scalar(2))?;
+                Ok(RecordBatch::try_from_iter(vec![("", doubled)])?)
+            }),
+        )?;
+
+        // mismatched names, arities and types are not matched
+        let lookup = |name: &str, param_types: Vec<DataType>, return_type: DataType| {
+            get_native_udf(name, &NativeUDFSignature::new(param_types, return_type)).is_some()
+        };
+        assert!(lookup(
+            "test_double",
+            vec![DataType::Int64],
+            DataType::Int64
+        ));
+        assert!(!lookup(
+            "test_not_registered",
+            vec![DataType::Int64],
+            DataType::Int64
+        ));
+        assert!(!lookup("", vec![DataType::Int64], DataType::Int64));
+        assert!(!lookup("test_double", vec![], DataType::Int64));
+        assert!(!lookup(
+            "test_double",
+            vec![DataType::Int64, DataType::Int64],
+            DataType::Int64
+        ));
+        assert!(!lookup(
+            "test_double",
+            vec![DataType::Int32],
+            DataType::Int64
+        ));
+        assert!(!lookup(
+            "test_double",
+            vec![DataType::Int64],
+            DataType::Utf8
+        ));
+        assert!(register_native_udf("", signature.clone(), Arc::new(|b| Ok(b.clone()))).is_err());
+
+        let input: ArrayRef = Arc::new(Int64Array::from(vec![Some(1), None, Some(3)]));
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![("a", input, true)])?;
+        let udf = NativeUDFExpr::new(
+            "test_double".to_string(),
+            get_native_udf("test_double", &signature).expect("registered"),
+            DataType::Int64,
+            true,
+            vec![Arc::new(Column::new("a", 0))],
+        );
+        let output = udf.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(
+            output.as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![Some(2), None, Some(6)]),
+        );
+
+        // output of unexpected type is rejected
+        let udf = NativeUDFExpr::new(
+            "test_double".to_string(),
+            get_native_udf("test_double", &signature).expect("registered"),
+            DataType::Int32,
+            true,
+            vec![Arc::new(Column::new("a", 0))],
+        );
+        assert!(udf.evaluate(&batch).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, Int64Builder, ListBuilder},
+    datatypes::{DataType, Field, Int64Type, Schema},
+    record_batch::RecordBatch,
+};
+use datafusion::{common::Result, logical_expr::ColumnarValue, physical_plan::PhysicalExpr};
+use datafusion_ext_commons::{arrow::cast::cast, df_execution_err, df_unimplemented_err};
+
+use crate::down_cast_any_ref;
+
+// same as spark's ByteArrayMethods.MAX_ROUNDED_ARRAY_LENGTH
+const MAX_SEQUENCE_LENGTH: i128 = i32::MAX as i128 - 15;
+
+/// spark's sequence(start, stop[, step]) of integral values, returns the
+/// array of values from start to stop (inclusive) by step. step defaults to 1
+/// if start <= stop, otherwise -1.
+#[derive(Debug, Hash)]
+pub struct NativeSequenceExpr {
+    start: Arc<dyn PhysicalExpr>,
+    stop: Arc<dyn PhysicalExpr>,
+    step: Option<Arc<dyn PhysicalExpr>>,
+}
+
+impl PartialEq<dyn Any> for NativeSequenceExpr {
+    fn eq(&self, other: &dyn Any) -> bool {
+        down_cast_any_ref(other)
+            .downcast_ref::<Self>()
+            .map(|x| {
+                self.start.eq(&x.start)
+                    && self.stop.eq(&x.stop)
+                    && match (&self.step, &x.step) {
+                        (Some(step), Some(other_step)) => step.eq(other_step),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl NativeSequenceExpr {
+    pub fn new(
+        start: Arc<dyn PhysicalExpr>,
+        stop: Arc<dyn PhysicalExpr>,
+        step: Option<Arc<dyn PhysicalExpr>>,
+    ) -> Self {
+        Self { start, stop, step }
+    }
+
+    fn element_type(&self, input_schema: &Schema) -> Result<DataType> {
+        match self.start.data_type(input_schema)? {
+            dt @ (DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64) => Ok(dt),
+            dt => df_unimplemented_err!("sequence: unsupported element type: {dt}"),
+        }
+    }
+}
+
+impl Display for NativeSequenceExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.step {
+            Some(step) => write!(f, "Sequence({}, {}, {step})", self.start, self.stop),
+            None => write!(f, "Sequence({}, {})", self.start, self.stop),
+        }
+    }
+}
+
+impl PhysicalExpr for NativeSequenceExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    // elements are never null, same as spark
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::new_list(self.element_type(input_schema)?, false))
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let element_type = self.element_type(&batch.schema())?;
+        let evaluate_i64 = |expr: &Arc<dyn PhysicalExpr>| -> Result<ArrayRef> {
+            cast(
+                &expr.evaluate(batch)?.into_array(num_rows)?,
+                &DataType::Int64,
+            )
+        };
+        let starts = evaluate_i64(&self.start)?;
+        let stops = evaluate_i64(&self.stop)?;
+        let steps = self.step.as_ref().map(evaluate_i64).transpose()?;
+        let starts = starts.as_primitive::<Int64Type>();
+        let stops = stops.as_primitive::<Int64Type>();
+        let steps = steps
+            .as_ref()
+            .map(|steps| steps.as_primitive::<Int64Type>());
+
+        let mut builder = ListBuilder::new(Int64Builder::new());
+        for i in 0..num_rows {
+            if starts.is_null(i) || stops.is_null(i) || steps.is_some_and(|s| s.is_null(i)) {
+                builder.append_null();
+                continue;
+            }
+            let (start, stop) = (starts.value(i), stops.value(i));
+            let step = match steps {
+                Some(steps) => steps.value(i),
+                None if start <= stop => 1,
+                None => -1,
+            };
+            let len = sequence_len(start, stop, step)?;
+            let values = builder.values();
+            for k in 0..len as i64 {
+                // never overflows since all values are between start and stop
+                values.append_value(start.wrapping_add(k.wrapping_mul(step)));
+            }
+            builder.append(true);
+        }
+        let sequences: ArrayRef = Arc::new(builder.finish());
+        Ok(ColumnarValue::Array(cast(
+            &sequences,
+            &DataType::List(Arc::new(Field::new_list_field(element_type, false))),
+        )?))
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
+        let mut children = vec![&self.start, &self.stop];
+        children.extend(&self.step);
+        children
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn PhysicalExpr>> {
+        Ok(Arc::new(Self::new(
+            children[0].clone(),
+            children[1].clone(),
+            children.get(2).cloned(),
+        )))
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        let mut s = state;
+        self.hash(&mut s);
+    }
+}
+
+/// number of values from start to stop by step, with the same boundary checks
+/// as spark
+fn sequence_len(start: i64, stop: i64, step: i64) -> Result<usize> {
+    let valid = (step > 0 && start <= stop) || (step < 0 && start >= stop) || start == stop;
+    if !valid {
+        return df_execution_err!("sequence: illegal boundaries: {start} to {stop} by {step}");
+    }
+    if start == stop {
+        return Ok(1);
+    }
+    let len = (stop as i128 - start as i128) / step as i128 + 1;
+    if len > MAX_SEQUENCE_LENGTH {
+        return df_execution_err!("sequence: too long sequence: {len}, max {MAX_SEQUENCE_LENGTH}");
+    }
+    Ok(len as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Int32Array, Int64Array},
+        datatypes::{DataType, Int32Type, Int64Type},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        physical_expr::{
+            expressions::{Column, Literal},
+            PhysicalExpr,
+        },
+    };
+
+    use crate::sequence::NativeSequenceExpr;
+
+    fn collect_i64(array: &ArrayRef) -> Vec<Option<Vec<i64>>> {
+        array
+            .as_list::<i32>()
+            .iter()
+            .map(|seq| seq.map(|seq| seq.as_primitive::<Int64Type>().values().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sequence() -> Result<()> {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "start",
+                Arc::new(Int64Array::from(vec![
+                    Some(1),
+                    Some(5),
+                    Some(3),
+                    None,
+                    Some(7),
+                ])) as ArrayRef,
+            ),
+            (
+                "stop",
+                Arc::new(Int64Array::from(vec![
+                    Some(10),
+                    Some(1),
+                    Some(3),
+                    Some(1),
+                    Some(7),
+                ])),
+            ),
+            (
+                "step",
+                Arc::new(Int64Array::from(vec![
+                    Some(3),
+                    Some(-2),
+                    Some(1),
+                    Some(1),
+                    Some(0),
+                ])),
+            ),
+        ])?;
+
+        // forward and backward sequences with steps
+        let seq = NativeSequenceExpr::new(
+            Arc::new(Column::new("start", 0)),
+            Arc::new(Column::new("stop", 1)),
+            Some(Arc::new(Column::new("step", 2))),
+        );
+        let output = seq.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(
+            collect_i64(&output),
+            vec![
+                Some(vec![1, 4, 7, 10]),
+                Some(vec![5, 3, 1]),
+                Some(vec![3]),
+                None,
+                Some(vec![7]),
+            ],
+        );
+
+        // default steps
+        let seq = NativeSequenceExpr::new(
+            Arc::new(Column::new("start", 0)),
+            Arc::new(Column::new("stop", 1)),
+            None,
+        );
+        let output = seq.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(
+            collect_i64(&output),
+            vec![
+                Some((1..=10).collect()),
+                Some(vec![5, 4, 3, 2, 1]),
+                Some(vec![3]),
+                None,
+                Some(vec![7]),
+            ],
+        );
+
+        // steps in the wrong direction
+        let seq = NativeSequenceExpr::new(
+            Arc::new(Column::new("start", 0)),
+            Arc::new(Column::new("stop", 1)),
+            Some(Arc::new(Literal::new(ScalarValue::Int64(Some(1))))),
+        );
+        assert!(seq.evaluate(&batch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_element_type() -> Result<()> {
+        let batch = RecordBatch::try_from_iter(vec![
+            ("start", Arc::new(Int32Array::from(vec![0, 9])) as ArrayRef),
+            ("stop", Arc::new(Int32Array::from(vec![4, 0]))),
+        ])?;
+        let seq = NativeSequenceExpr::new(
+            Arc::new(Column::new("start", 0)),
+            Arc::new(Column::new("stop", 1)),
+            Some(Arc::new(Literal::new(ScalarValue::Int32(Some(-4))))),
+        );
+        assert_eq!(
+            seq.data_type(&batch.schema())?,
+            DataType::new_list(DataType::Int32, false),
+        );
+        assert!(seq.evaluate(&batch).is_err()); // 0 to 4 by -4
+
+        let seq = NativeSequenceExpr::new(
+            Arc::new(Column::new("stop", 1)),
+            Arc::new(Column::new("start", 0)),
+            Some(Arc::new(Literal::new(ScalarValue::Int32(Some(4))))),
+        );
+        let output = seq.evaluate(&batch)?.into_array(batch.num_rows())?;
+        let output = output.as_list::<i32>();
+        assert_eq!(
+            output.value(0).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![4, 8]),
+        );
+        assert_eq!(
+            output.value(1).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![0]),
+        );
+        Ok(())
+    }
+}
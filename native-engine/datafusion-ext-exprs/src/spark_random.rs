@@ -0,0 +1,356 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Display, Formatter},
+    hash::Hasher,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Float64Array, RecordBatch, StringArray},
+    datatypes::{DataType, Schema},
+};
+use datafusion::{
+    common::{
+        tree_node::{Transformed, TreeNode},
+        Result,
+    },
+    logical_expr::ColumnarValue,
+    physical_expr::{PhysicalExpr, PhysicalExprRef},
+};
+use datafusion_ext_commons::{
+    df_execution_err,
+    spark_random::{MersenneTwister, XorShiftRandom},
+};
+use parking_lot::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RandDistribution {
+    Uniform,
+    Gaussian,
+}
+
+/// spark's rand()/randn(). the generator is seeded with `seed + partition`
+/// and must be bound to a partition with [`bind_partition`] before
+/// evaluating.
+pub struct SparkRandExpr {
+    seed: i64,
+    distribution: RandDistribution,
+    rng: Option<Mutex<XorShiftRandom>>,
+}
+
+impl SparkRandExpr {
+    pub fn new(seed: i64, distribution: RandDistribution) -> Self {
+        Self {
+            seed,
+            distribution,
+            rng: None,
+        }
+    }
+
+    fn bind(&self, partition: usize) -> Self {
+        let rng = XorShiftRandom::new(self.seed.wrapping_add(partition as i64));
+        Self {
+            seed: self.seed,
+            distribution: self.distribution,
+            rng: Some(Mutex::new(rng)),
+        }
+    }
+}
+
+impl Display for SparkRandExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.distribution {
+            RandDistribution::Uniform => write!(f, "Rand({})", self.seed),
+            RandDistribution::Gaussian => write!(f, "Randn({})", self.seed),
+        }
+    }
+}
+
+impl Debug for SparkRandExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl PartialEq<dyn Any> for SparkRandExpr {
+    fn eq(&self, _other: &dyn Any) -> bool {
+        // nondeterministic expressions never equal to each other
+        false
+    }
+}
+
+impl PhysicalExpr for SparkRandExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let Some(rng) = &self.rng else {
+            return df_execution_err!("{self} is not bound to a partition");
+        };
+        let mut rng = rng.lock();
+        let array: Float64Array = match self.distribution {
+            RandDistribution::Uniform => (0..batch.num_rows()).map(|_| rng.next_double()).collect(),
+            RandDistribution::Gaussian => {
+                (0..batch.num_rows()).map(|_| rng.next_gaussian()).collect()
+            }
+        };
+        Ok(ColumnarValue::Array(Arc::new(array)))
+    }
+
+    fn children(&self) -> Vec<&PhysicalExprRef> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        Ok(self)
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        state.write(self.to_string().as_bytes())
+    }
+}
+
+/// spark's uuid(). the generator is seeded with `seed + partition` and must
+/// be bound to a partition with [`bind_partition`] before evaluating.
+pub struct SparkUuidExpr {
+    seed: i64,
+    rng: Option<Mutex<MersenneTwister>>,
+}
+
+impl SparkUuidExpr {
+    pub fn new(seed: i64) -> Self {
+        Self { seed, rng: None }
+    }
+
+    fn bind(&self, partition: usize) -> Self {
+        let rng = MersenneTwister::new(self.seed.wrapping_add(partition as i64));
+        Self {
+            seed: self.seed,
+            rng: Some(Mutex::new(rng)),
+        }
+    }
+}
+
+impl Display for SparkUuidExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Uuid({})", self.seed)
+    }
+}
+
+impl Debug for SparkUuidExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl PartialEq<dyn Any> for SparkUuidExpr {
+    fn eq(&self, _other: &dyn Any) -> bool {
+        // nondeterministic expressions never equal to each other
+        false
+    }
+}
+
+impl PhysicalExpr for SparkUuidExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let Some(rng) = &self.rng else {
+            return df_execution_err!("{self} is not bound to a partition");
+        };
+        let mut rng = rng.lock();
+        let array: StringArray = (0..batch.num_rows())
+            .map(|_| Some(next_uuid(&mut rng)))
+            .collect();
+        Ok(ColumnarValue::Array(Arc::new(array)))
+    }
+
+    fn children(&self) -> Vec<&PhysicalExprRef> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<PhysicalExprRef>,
+    ) -> Result<PhysicalExprRef> {
+        Ok(self)
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        state.write(self.to_string().as_bytes())
+    }
+}
+
+// same as spark's RandomUUIDGenerator.getNextUUIDUTF8String()
+fn next_uuid(rng: &mut MersenneTwister) -> String {
+    let msb = (rng.next_i64() as u64 & 0xFFFFFFFFFFFF0FFF) | 0x0000000000004000;
+    let lsb = (rng.next_i64() as u64 | 0x8000000000000000) & 0xBFFFFFFFFFFFFFFF;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        msb >> 32,
+        (msb >> 16) & 0xffff,
+        msb & 0xffff,
+        lsb >> 48,
+        lsb & 0xffffffffffff,
+    )
+}
+
+/// returns true if the expr tree contains random expressions, which must be
+/// bound to a partition with [`bind_partition`] before evaluating.
+pub fn is_partition_dependent(expr: &PhysicalExprRef) -> bool {
+    expr.as_any().is::<SparkRandExpr>()
+        || expr.as_any().is::<SparkUuidExpr>()
+        || expr.children().into_iter().any(is_partition_dependent)
+}
+
+/// binds all random expressions in the expr tree to the given partition,
+/// must be called when opening the stream of each partition.
+pub fn bind_partition(expr: &PhysicalExprRef, partition: usize) -> Result<PhysicalExprRef> {
+    let bound = expr.clone().transform_down(&|node: PhysicalExprRef| {
+        if let Some(rand) = node.as_any().downcast_ref::<SparkRandExpr>() {
+            let bound: PhysicalExprRef = Arc::new(rand.bind(partition));
+            return Ok(Transformed::yes(bound));
+        }
+        if let Some(uuid) = node.as_any().downcast_ref::<SparkUuidExpr>() {
+            let bound: PhysicalExprRef = Arc::new(uuid.bind(partition));
+            return Ok(Transformed::yes(bound));
+        }
+        Ok(Transformed::no(node))
+    })?;
+    Ok(bound.data)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, AsArray, RecordBatch, RecordBatchOptions},
+        datatypes::{Float64Type, Schema},
+    };
+    use datafusion::{
+        common::Result,
+        logical_expr::Operator,
+        physical_expr::{expressions::BinaryExpr, PhysicalExprRef},
+    };
+
+    use crate::spark_random::{bind_partition, RandDistribution, SparkRandExpr, SparkUuidExpr};
+
+    fn empty_batch(num_rows: usize) -> Result<RecordBatch> {
+        Ok(RecordBatch::try_new_with_options(
+            Arc::new(Schema::empty()),
+            vec![],
+            &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+        )?)
+    }
+
+    #[test]
+    fn test_rand() -> Result<()> {
+        let rand: PhysicalExprRef = Arc::new(SparkRandExpr::new(30, RandDistribution::Uniform));
+        let randn: PhysicalExprRef = Arc::new(SparkRandExpr::new(30, RandDistribution::Gaussian));
+        let batch = empty_batch(3)?;
+
+        // unbound exprs cannot be evaluated
+        assert!(rand.evaluate(&batch).is_err());
+
+        // first values of partition 0 and 1 are the same as spark's
+        let bound = bind_partition(&rand, 0)?;
+        let values = bound.evaluate(&batch)?.into_array(3)?;
+        assert_eq!(
+            values.as_primitive::<Float64Type>().value(0),
+            0.2762195585886885
+        );
+        let bound = bind_partition(&randn, 0)?;
+        let values = bound.evaluate(&batch)?.into_array(3)?;
+        assert_eq!(
+            values.as_primitive::<Float64Type>().value(0),
+            -1.0451987154313813
+        );
+
+        // partition 1 with seed 30 shares the sequence of partition 0 with seed
+        // 31
+        let bound1 = bind_partition(&rand, 1)?;
+        let rand31: PhysicalExprRef = Arc::new(SparkRandExpr::new(31, RandDistribution::Uniform));
+        let bound0 = bind_partition(&rand31, 0)?;
+        let values1 = bound1.evaluate(&batch)?.into_array(3)?;
+        let values0 = bound0.evaluate(&batch)?.into_array(3)?;
+        assert_eq!(&values1, &values0);
+
+        // generators continue across batches
+        let next_values1 = bound1.evaluate(&batch)?.into_array(3)?;
+        assert_ne!(&values1, &next_values1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_nested() -> Result<()> {
+        let rand: PhysicalExprRef = Arc::new(SparkRandExpr::new(0, RandDistribution::Uniform));
+        let expr: PhysicalExprRef = Arc::new(BinaryExpr::new(rand.clone(), Operator::Plus, rand));
+        let bound = bind_partition(&expr, 0)?;
+        let values = bound.evaluate(&empty_batch(1)?)?.into_array(1)?;
+        assert_eq!(
+            values.as_primitive::<Float64Type>().value(0),
+            0.7604953758285915 * 2.0
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuid() -> Result<()> {
+        let uuid: PhysicalExprRef = Arc::new(SparkUuidExpr::new(0));
+        let batch = empty_batch(100)?;
+        assert!(uuid.evaluate(&batch).is_err());
+
+        let bound = bind_partition(&uuid, 0)?;
+        let values = bound.evaluate(&batch)?.into_array(100)?;
+        let values = values.as_string::<i32>();
+        assert_eq!(values.null_count(), 0);
+        for value in values.iter().flatten() {
+            let parsed = value.split('-').map(|s| s.len()).collect::<Vec<_>>();
+            assert_eq!(parsed, vec![8, 4, 4, 4, 12]);
+            assert_eq!(&value[14..15], "4"); // version
+            assert!("89ab".contains(&value[19..20])); // variant
+        }
+
+        // same seed and partition produce the same uuids
+        let rebound = bind_partition(&uuid, 0)?;
+        let revalues = rebound.evaluate(&batch)?.into_array(100)?;
+        assert_eq!(values, revalues.as_string::<i32>());
+        Ok(())
+    }
+}
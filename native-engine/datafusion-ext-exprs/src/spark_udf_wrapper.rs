@@ -20,9 +20,8 @@ use std::{
 };
 
 use arrow::{
-    array::{as_struct_array, make_array, new_empty_array, Array, ArrayRef, StructArray},
+    array::{new_empty_array, Array, ArrayRef, StructArray},
     datatypes::{DataType, Field, Schema, SchemaRef},
-    ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema},
     record_batch::{RecordBatch, RecordBatchOptions},
 };
 use blaze_jni_bridge::{
@@ -32,7 +31,15 @@ use datafusion::{
     error::Result, logical_expr::ColumnarValue, physical_expr::physical_exprs_bag_equal,
     physical_plan::PhysicalExpr,
 };
-use datafusion_ext_commons::{arrow::cast::cast, df_execution_err};
+use datafusion_ext_commons::{
+    arrow::{
+        cast::cast,
+        ffi_struct::{
+            export_struct_for_jni, import_schema, import_struct_from_jni, imported_as_data_type,
+        },
+    },
+    df_execution_err,
+};
 use jni::objects::GlobalRef;
 use once_cell::sync::OnceCell;
 
@@ -45,10 +52,40 @@ pub struct SparkUDFWrapperExpr {
     pub params: Vec<Arc<dyn PhysicalExpr>>,
     pub import_schema: SchemaRef,
     pub params_schema: OnceCell<SchemaRef>,
-    jcontext: OnceCell<GlobalRef>,
+    context: OnceCell<Arc<dyn UDFContext>>,
     expr_string: String,
 }
 
+/// context evaluating the udf, which is the SparkUDFWrapperContext in jvm.
+/// params are exported as a struct array and the results are imported as a
+/// single-field struct array, both through pointers of ffi arrays.
+pub trait UDFContext: Send + Sync {
+    fn eval(&self, export_ptr: i64, import_ptr: i64) -> Result<()>;
+}
+
+struct JniUDFContext {
+    jcontext: GlobalRef,
+}
+
+impl JniUDFContext {
+    fn try_new(serialized: &[u8]) -> Result<Self> {
+        let serialized_buf = jni_new_direct_byte_buffer!(serialized)?;
+        let jcontext_local = jni_new_object!(SparkUDFWrapperContext(serialized_buf.as_obj()))?;
+        Ok(Self {
+            jcontext: jni_new_global_ref!(jcontext_local.as_obj())?,
+        })
+    }
+}
+
+impl UDFContext for JniUDFContext {
+    fn eval(&self, export_ptr: i64, import_ptr: i64) -> Result<()> {
+        jni_call!(SparkUDFWrapperContext(self.jcontext.as_obj()).eval(
+            export_ptr,
+            import_ptr,
+        ) -> ())
+    }
+}
+
 impl PartialEq<dyn Any> for SparkUDFWrapperExpr {
     fn eq(&self, other: &dyn Any) -> bool {
         down_cast_any_ref(other)
@@ -76,22 +113,32 @@ impl SparkUDFWrapperExpr {
             return_type: return_type.clone(),
             return_nullable,
             params,
-            import_schema: Arc::new(Schema::new(vec![Field::new("", return_type, true)])),
+            import_schema: Arc::new(import_schema(&return_type)),
             params_schema: OnceCell::new(),
-            jcontext: OnceCell::new(),
+            context: OnceCell::new(),
             expr_string,
         })
     }
 
-    fn jcontext(&self) -> Result<GlobalRef> {
-        self.jcontext
-            .get_or_try_init(|| {
-                let serialized_buf = jni_new_direct_byte_buffer!(&self.serialized)?;
-                let jcontext_local =
-                    jni_new_object!(SparkUDFWrapperContext(serialized_buf.as_obj()))?;
-                jni_new_global_ref!(jcontext_local.as_obj())
-            })
-            .cloned()
+    /// creates a wrapper calling the given context instead of the jvm
+    /// SparkUDFWrapperContext, which is used in native-only tests
+    pub fn try_new_with_context(
+        return_type: DataType,
+        return_nullable: bool,
+        params: Vec<Arc<dyn PhysicalExpr>>,
+        expr_string: String,
+        context: Arc<dyn UDFContext>,
+    ) -> Result<Self> {
+        let wrapper = Self::try_new(vec![], return_type, return_nullable, params, expr_string)?;
+        let _ = wrapper.context.set(context);
+        Ok(wrapper)
+    }
+
+    fn context(&self) -> Result<&Arc<dyn UDFContext>> {
+        self.context.get_or_try_init(|| {
+            let context = JniUDFContext::try_new(&self.serialized)?;
+            Ok(Arc::new(context) as Arc<dyn UDFContext>)
+        })
     }
 }
 
@@ -164,9 +211,10 @@ impl PhysicalExpr for SparkUDFWrapperExpr {
 
         // invoke UDF through JNI
         Ok(ColumnarValue::Array(invoke_udf(
-            self.jcontext()?,
+            self.context()?.as_ref(),
             params_batch,
-            self.import_schema.clone(),
+            &self.import_schema,
+            &self.return_type,
         )?))
     }
 
@@ -193,23 +241,137 @@ impl PhysicalExpr for SparkUDFWrapperExpr {
 }
 
 fn invoke_udf(
-    jcontext: GlobalRef,
+    context: &dyn UDFContext,
     params_batch: RecordBatch,
-    result_schema: SchemaRef,
+    import_schema: &Schema,
+    return_type: &DataType,
 ) -> Result<ArrayRef> {
     // evalute via context
-    let struct_array = StructArray::from(params_batch);
-    let mut export_ffi_array = FFI_ArrowArray::new(&struct_array.to_data());
-    let mut import_ffi_array = FFI_ArrowArray::empty();
-    jni_call!(SparkUDFWrapperContext(jcontext.as_obj()).eval(
-        &mut export_ffi_array as *mut FFI_ArrowArray as i64,
-        &mut import_ffi_array as *mut FFI_ArrowArray as i64,
-    ) -> ())?;
-
-    // import output from context
-    let import_ffi_schema = FFI_ArrowSchema::try_from(result_schema.as_ref())?;
-    let import_struct_array =
-        make_array(unsafe { from_ffi(import_ffi_array, &import_ffi_schema)? });
-    let import_array = as_struct_array(&import_struct_array).column(0).clone();
-    Ok(import_array)
+    let num_rows = params_batch.num_rows();
+    let params = StructArray::from(params_batch);
+    let imported = import_struct_from_jni(import_schema, |import_ptr| {
+        export_struct_for_jni(&params, |export_ptr| context.eval(export_ptr, import_ptr))
+    })?;
+
+    // validate output imported from context
+    let output = imported_as_data_type(&imported, return_type)
+        .or_else(|e| df_execution_err!("SparkUDFWrapper: {e}"))?;
+    if output.len() != num_rows {
+        return df_execution_err!(
+            "SparkUDFWrapper: expect {num_rows} rows imported from context, found {}",
+            output.len(),
+        );
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Int32Array, ListArray, StructArray},
+        datatypes::{DataType, Field, Fields, Int32Type},
+        ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        physical_expr::{expressions::Column, PhysicalExpr},
+    };
+
+    use crate::{
+        get_indexed_field::GetIndexedFieldExpr,
+        spark_udf_wrapper::{SparkUDFWrapperExpr, UDFContext},
+    };
+
+    /// returns struct(array(a, a + 1)) for each int param `a`, truncated to
+    /// `num_output_rows` if specified
+    struct MockUDFContext {
+        num_output_rows: Option<usize>,
+    }
+
+    impl UDFContext for MockUDFContext {
+        fn eval(&self, export_ptr: i64, import_ptr: i64) -> Result<()> {
+            // simulates jvm importing the params and exporting the output
+            let params_type =
+                DataType::Struct(Fields::from(vec![Field::new("", DataType::Int32, true)]));
+            let exported = unsafe {
+                std::ptr::replace(export_ptr as *mut FFI_ArrowArray, FFI_ArrowArray::empty())
+            };
+            let params_data =
+                unsafe { from_ffi(exported, &FFI_ArrowSchema::try_from(&params_type)?)? };
+            let params = StructArray::from(params_data);
+            let values = params.column(0).as_primitive::<Int32Type>();
+
+            let lists: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(
+                values
+                    .iter()
+                    .take(self.num_output_rows.unwrap_or(values.len()))
+                    .map(|v| v.map(|v| vec![Some(v), Some(v + 1)])),
+            ));
+            let output_struct = StructArray::from(vec![(
+                Arc::new(Field::new("element", lists.data_type().clone(), true)),
+                lists,
+            )]);
+            let output = StructArray::from(vec![(
+                Arc::new(Field::new("", output_struct.data_type().clone(), true)),
+                Arc::new(output_struct) as ArrayRef,
+            )]);
+            unsafe {
+                *(import_ptr as *mut FFI_ArrowArray) = FFI_ArrowArray::new(&output.to_data());
+            }
+            Ok(())
+        }
+    }
+
+    fn new_mock_udf(num_output_rows: Option<usize>) -> Result<SparkUDFWrapperExpr> {
+        let return_type = DataType::Struct(Fields::from(vec![Field::new(
+            "arr",
+            DataType::new_list(DataType::Int32, true),
+            true,
+        )]));
+        SparkUDFWrapperExpr::try_new_with_context(
+            return_type,
+            true,
+            vec![Arc::new(Column::new("a", 0))],
+            "mock_udf(a)".to_string(),
+            Arc::new(MockUDFContext { num_output_rows }),
+        )
+    }
+
+    #[test]
+    fn test_nested_return_type() -> Result<()> {
+        let input: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None, Some(3)]));
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![("a", input, true)])?;
+        let udf = Arc::new(new_mock_udf(None)?);
+
+        let output = udf.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(output.data_type(), &udf.return_type);
+
+        // udf(a).arr[2]
+        let get_arr = Arc::new(GetIndexedFieldExpr::new(udf, ScalarValue::from(0_i32)));
+        let get_item = GetIndexedFieldExpr::new(get_arr, ScalarValue::from(2_i64));
+        let items = get_item.evaluate(&batch)?.into_array(batch.num_rows())?;
+        assert_eq!(
+            items.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(2), None, Some(4)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mismatched_output() -> Result<()> {
+        let input: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]));
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![("a", input, true)])?;
+        let udf = new_mock_udf(Some(2))?;
+
+        let err = udf.evaluate(&batch).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("expect 3 rows imported from context, found 2"),
+            "unexpected error: {err}",
+        );
+        Ok(())
+    }
 }
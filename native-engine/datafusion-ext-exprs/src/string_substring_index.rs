@@ -0,0 +1,292 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, StringArray},
+    datatypes::{DataType, Schema},
+    record_batch::RecordBatch,
+};
+use datafusion::{
+    common::{Result, ScalarValue},
+    logical_expr::ColumnarValue,
+    physical_plan::PhysicalExpr,
+};
+use datafusion_ext_commons::df_execution_err;
+
+use crate::down_cast_any_ref;
+
+/// spark's substring_index(str, delim, count), returns the substring before
+/// `count` occurrences of `delim` (counted from the right and returning the
+/// substring after them if `count` is negative).
+#[derive(Debug, Hash)]
+pub struct NativeSubstringIndexExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    delim: String,
+    count: i32,
+}
+
+impl PartialEq<dyn Any> for NativeSubstringIndexExpr {
+    fn eq(&self, other: &dyn Any) -> bool {
+        down_cast_any_ref(other)
+            .downcast_ref::<Self>()
+            .map(|x| self.expr.eq(&x.expr) && self.delim == x.delim && self.count == x.count)
+            .unwrap_or(false)
+    }
+}
+
+impl NativeSubstringIndexExpr {
+    pub fn new(expr: Arc<dyn PhysicalExpr>, delim: String, count: i32) -> Self {
+        Self { expr, delim, count }
+    }
+
+    pub fn delim(&self) -> &str {
+        &self.delim
+    }
+
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+}
+
+impl Display for NativeSubstringIndexExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SubstringIndex({}, {}, {})",
+            self.expr, self.delim, self.count
+        )
+    }
+}
+
+impl PhysicalExpr for NativeSubstringIndexExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let expr = self.expr.evaluate(batch)?;
+
+        match expr {
+            ColumnarValue::Array(array) => {
+                let Some(string_array) = array.as_any().downcast_ref::<StringArray>() else {
+                    return df_execution_err!(
+                        "substring_index: expect string array, got {}",
+                        array.data_type(),
+                    );
+                };
+                let ret_array = Arc::new(StringArray::from_iter(string_array.iter().map(
+                    |maybe_string| {
+                        maybe_string.map(|string| substring_index(string, &self.delim, self.count))
+                    },
+                )));
+                Ok(ColumnarValue::Array(ret_array))
+            }
+            ColumnarValue::Scalar(ScalarValue::Utf8(maybe_string)) => {
+                let ret = maybe_string
+                    .map(|string| substring_index(&string, &self.delim, self.count).to_string());
+                Ok(ColumnarValue::Scalar(ScalarValue::Utf8(ret)))
+            }
+            expr => df_execution_err!("substring_index: invalid expr: {expr:?}"),
+        }
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
+        vec![&self.expr]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn PhysicalExpr>> {
+        Ok(Arc::new(Self::new(
+            children[0].clone(),
+            self.delim.clone(),
+            self.count,
+        )))
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        let mut s = state;
+        self.hash(&mut s);
+    }
+}
+
+/// same as UTF8String.subStringIndex in spark, occurrences of the delimiter
+/// are searched one byte after the previous occurrence, so they may overlap.
+/// the whole string is returned if there are not enough occurrences.
+fn substring_index<'a>(string: &'a str, delim: &str, count: i32) -> &'a str {
+    if delim.is_empty() || count == 0 {
+        return "";
+    }
+
+    if count > 0 {
+        let mut idx = None;
+        for _ in 0..count {
+            let start = idx.map(|idx| idx + 1).unwrap_or(0);
+            match find_from(string, delim, start) {
+                Some(found) => idx = Some(found),
+                None => return string,
+            }
+        }
+        &string[..idx.unwrap_or(0)]
+    } else {
+        let mut idx = string.len() + 1;
+        for _ in 0..count.unsigned_abs() {
+            match idx
+                .checked_sub(1)
+                .and_then(|end| rfind_to(string, delim, end))
+            {
+                Some(found) => idx = found,
+                None => return string,
+            }
+        }
+        &string[idx + delim.len()..]
+    }
+}
+
+/// finds the first occurrence starting at or after `start`
+fn find_from(string: &str, delim: &str, mut start: usize) -> Option<usize> {
+    // occurrences never start inside a multi-byte char
+    while start < string.len() && !string.is_char_boundary(start) {
+        start += 1;
+    }
+    string.get(start..)?.find(delim).map(|idx| idx + start)
+}
+
+/// finds the last occurrence starting at or before `end`
+fn rfind_to(string: &str, delim: &str, end: usize) -> Option<usize> {
+    // occurrences never end inside a multi-byte char
+    let mut search_end = (end + delim.len()).min(string.len());
+    while !string.is_char_boundary(search_end) {
+        search_end -= 1;
+    }
+    string[..search_end].rfind(delim)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        logical_expr::ColumnarValue,
+        physical_expr::{expressions as phys_expr, PhysicalExpr},
+    };
+
+    use crate::string_substring_index::NativeSubstringIndexExpr;
+
+    fn eval(strings: Vec<Option<&str>>, delim: &str, count: i32) -> Result<ArrayRef> {
+        let string_array: ArrayRef = Arc::new(StringArray::from(strings));
+        let schema = Arc::new(Schema::new(vec![Field::new("col", DataType::Utf8, true)]));
+        let batch = RecordBatch::try_new(schema, vec![string_array])?;
+
+        let expr = Arc::new(NativeSubstringIndexExpr::new(
+            phys_expr::col("col", &batch.schema())?,
+            delim.to_string(),
+            count,
+        ));
+        expr.evaluate(&batch)?.into_array(batch.num_rows())
+    }
+
+    #[test]
+    fn test_positive_count() -> Result<()> {
+        let strings = vec![Some("www.apache.org"), Some("a.b"), Some(".x."), Some("")];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["www.apache", "a.b", ".x", ""]));
+        assert_eq!(&eval(strings, ".", 2)?, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_count() -> Result<()> {
+        let strings = vec![Some("www.apache.org"), Some("a.b"), Some(".x."), Some("")];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["apache.org", "a.b", "x.", ""]));
+        assert_eq!(&eval(strings, ".", -2)?, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_exceeding_occurrences() -> Result<()> {
+        let strings = vec![Some("www.apache.org"), Some("abc")];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["www.apache.org", "abc"]));
+        assert_eq!(&eval(strings.clone(), ".", 3)?, &expected);
+        assert_eq!(&eval(strings.clone(), ".", -3)?, &expected);
+        assert_eq!(&eval(strings.clone(), ".", i32::MAX)?, &expected);
+        assert_eq!(&eval(strings.clone(), ".", i32::MIN)?, &expected);
+
+        // zero count and empty delimiter
+        let empty: ArrayRef = Arc::new(StringArray::from(vec!["", ""]));
+        assert_eq!(&eval(strings.clone(), ".", 0)?, &empty);
+        assert_eq!(&eval(strings.clone(), "", 1)?, &empty);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_byte_and_overlapping_delim() -> Result<()> {
+        let strings = vec![Some("张三→李四→王五"), Some("αβ→→γ")];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["张三→李四", "αβ→"]));
+        assert_eq!(&eval(strings.clone(), "→", 2)?, &expected);
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["李四→王五", "→γ"]));
+        assert_eq!(&eval(strings.clone(), "→", -2)?, &expected);
+
+        // occurrences may overlap, like spark
+        let strings = vec![Some("aaaa")];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec!["a"]));
+        assert_eq!(&eval(strings.clone(), "aa", 2)?, &expected);
+        assert_eq!(&eval(strings.clone(), "aa", -2)?, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_inputs() -> Result<()> {
+        let strings = vec![None, Some("a,b,c"), None];
+        let expected: ArrayRef = Arc::new(StringArray::from(vec![None, Some("a,b"), None]));
+        assert_eq!(&eval(strings, ",", 2)?, &expected);
+
+        let expr = NativeSubstringIndexExpr::new(
+            phys_expr::lit(ScalarValue::Utf8(None)),
+            ",".to_string(),
+            1,
+        );
+        let batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+        let ColumnarValue::Scalar(ret) = expr.evaluate(&batch)? else {
+            unreachable!()
+        };
+        assert_eq!(ret, ScalarValue::Utf8(None));
+        Ok(())
+    }
+}
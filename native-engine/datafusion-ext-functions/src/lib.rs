@@ -26,6 +26,7 @@ pub mod spark_get_json_object;
 mod spark_hash;
 mod spark_make_array;
 mod spark_make_decimal;
+mod spark_map;
 mod spark_normalize_nan_and_zero;
 mod spark_null_if;
 mod spark_sha2;
@@ -50,6 +51,10 @@ pub fn create_spark_ext_function(name: &str) -> Result<ScalarFunctionImplementat
         "GetParsedJsonObject" => Arc::new(spark_get_json_object::spark_get_parsed_json_object),
         "ParseJson" => Arc::new(spark_get_json_object::spark_parse_json),
         "MakeArray" => Arc::new(spark_make_array::array),
+        "MapKeys" => Arc::new(spark_map::map_keys),
+        "MapValues" => Arc::new(spark_map::map_values),
+        "MapFromArrays" => Arc::new(spark_map::map_from_arrays),
+        "ElementAt" => Arc::new(spark_map::element_at),
         "StringSpace" => Arc::new(spark_strings::string_space),
         "StringRepeat" => Arc::new(spark_strings::string_repeat),
         "StringSplit" => Arc::new(spark_strings::string_split),
@@ -0,0 +1,410 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Map expressions
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{make_comparator, Array, ArrayRef, ListArray, MapArray, StructArray, UInt32Array},
+    buffer::{NullBuffer, OffsetBuffer},
+    compute::{take, SortOptions},
+    datatypes::{Field, Fields},
+};
+use datafusion::{
+    common::{
+        cast::{as_list_array, as_map_array},
+        Result, ScalarValue,
+    },
+    logical_expr::ColumnarValue,
+};
+use datafusion_ext_commons::df_execution_err;
+
+/// map_keys(map): returns an unordered array of the keys of the map.
+pub fn map_keys(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let map_array = args[0].clone().into_array(1)?;
+    let map_array = as_map_array(&map_array)?;
+    let keys = map_array.keys();
+    Ok(ColumnarValue::Array(Arc::new(ListArray::try_new(
+        Arc::new(Field::new("item", keys.data_type().clone(), false)),
+        map_array.offsets().clone(),
+        keys.clone(),
+        map_array.nulls().cloned(),
+    )?)))
+}
+
+/// map_values(map): returns an unordered array of the values of the map.
+pub fn map_values(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let map_array = args[0].clone().into_array(1)?;
+    let map_array = as_map_array(&map_array)?;
+    let values = map_array.values();
+    let values_nullable = map_array.entries().fields()[1].is_nullable();
+    Ok(ColumnarValue::Array(Arc::new(ListArray::try_new(
+        Arc::new(Field::new(
+            "item",
+            values.data_type().clone(),
+            values_nullable,
+        )),
+        map_array.offsets().clone(),
+        values.clone(),
+        map_array.nulls().cloned(),
+    )?)))
+}
+
+/// element_at(map, key, fail_on_error): returns value of the first entry
+/// matching the key. returns null if the key does not exist, or raises an
+/// error if fail_on_error is set (ansi mode).
+pub fn element_at(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let num_rows = num_rows_of_args(args);
+    let map_array = args[0].clone().into_array(num_rows)?;
+    let map_array = as_map_array(&map_array)?;
+    let key_array = args[1].clone().into_array(num_rows)?;
+    let fail_on_error = match &args[2] {
+        ColumnarValue::Scalar(ScalarValue::Boolean(Some(fail_on_error))) => *fail_on_error,
+        _ => df_execution_err!("element_at fail_on_error only supports literal bool")?,
+    };
+
+    let comparator = make_comparator(map_array.keys(), &key_array, SortOptions::default())?;
+    let mut value_indices: Vec<Option<u32>> = Vec::with_capacity(num_rows);
+    for row_idx in 0..num_rows {
+        if map_array.is_null(row_idx) || key_array.is_null(row_idx) {
+            value_indices.push(None);
+            continue;
+        }
+        let start = map_array.value_offsets()[row_idx] as usize;
+        let end = map_array.value_offsets()[row_idx + 1] as usize;
+        match (start..end).find(|&entry_idx| comparator(entry_idx, row_idx).is_eq()) {
+            Some(entry_idx) => value_indices.push(Some(entry_idx as u32)),
+            None if fail_on_error => {
+                let key = ScalarValue::try_from_array(&key_array, row_idx)?;
+                df_execution_err!(
+                    "element_at: key {key} does not exist in map. use try_element_at() to \
+                     tolerate non-existent key and return null instead, or set \
+                     spark.sql.ansi.enabled to false to bypass this error"
+                )?;
+            }
+            None => value_indices.push(None),
+        }
+    }
+    let values = take(map_array.values(), &UInt32Array::from(value_indices), None)?;
+    Ok(ColumnarValue::Array(values))
+}
+
+/// map_from_arrays(keys, values, last_win): creates a map from the given key
+/// and value arrays. duplicated keys raise an error, unless last_win is set
+/// (spark.sql.mapKeyDedupPolicy=LAST_WIN), in which case the value inserted
+/// at last takes precedence.
+pub fn map_from_arrays(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let num_rows = num_rows_of_args(args);
+    let key_lists = args[0].clone().into_array(num_rows)?;
+    let key_lists = as_list_array(&key_lists)?;
+    let value_lists = args[1].clone().into_array(num_rows)?;
+    let value_lists = as_list_array(&value_lists)?;
+    let last_win = match &args[2] {
+        ColumnarValue::Scalar(ScalarValue::Boolean(Some(last_win))) => *last_win,
+        _ => df_execution_err!("map_from_arrays last_win only supports literal bool")?,
+    };
+
+    let key_values = key_lists.values();
+    let value_values = value_lists.values();
+    let mut key_indices: Vec<u32> = vec![];
+    let mut value_indices: Vec<u32> = vec![];
+    let mut offsets: Vec<i32> = Vec::with_capacity(num_rows + 1);
+    let mut valids: Vec<bool> = Vec::with_capacity(num_rows);
+    let mut entry_indices: HashMap<ScalarValue, usize> = HashMap::new();
+    offsets.push(0);
+
+    for row_idx in 0..num_rows {
+        if key_lists.is_null(row_idx) || value_lists.is_null(row_idx) {
+            offsets.push(key_indices.len() as i32);
+            valids.push(false);
+            continue;
+        }
+        let key_start = key_lists.value_offsets()[row_idx] as usize;
+        let value_start = value_lists.value_offsets()[row_idx] as usize;
+        let len = key_lists.value_length(row_idx) as usize;
+        if len != value_lists.value_length(row_idx) as usize {
+            df_execution_err!(
+                "map_from_arrays: the key array and value array of MapData must have the same \
+                 length"
+            )?;
+        }
+
+        entry_indices.clear();
+        for i in 0..len {
+            if key_values.is_null(key_start + i) {
+                df_execution_err!("map_from_arrays: cannot use null as map key")?;
+            }
+            let key = ScalarValue::try_from_array(key_values, key_start + i)?;
+            match entry_indices.entry(key) {
+                Entry::Occupied(e) if last_win => {
+                    value_indices[*e.get()] = (value_start + i) as u32;
+                }
+                Entry::Occupied(e) => {
+                    df_execution_err!(
+                        "map_from_arrays: duplicate map key {} was found, please check the input \
+                         data. if you want to remove the duplicated keys, you can set \
+                         spark.sql.mapKeyDedupPolicy to LAST_WIN so that the key inserted at \
+                         last takes precedence",
+                        e.key()
+                    )?;
+                }
+                Entry::Vacant(e) => {
+                    e.insert(key_indices.len());
+                    key_indices.push((key_start + i) as u32);
+                    value_indices.push((value_start + i) as u32);
+                }
+            }
+        }
+        offsets.push(key_indices.len() as i32);
+        valids.push(true);
+    }
+
+    let keys = take(key_values, &UInt32Array::from(key_indices), None)?;
+    let values = take(value_values, &UInt32Array::from(value_indices), None)?;
+    let entries = StructArray::try_new(
+        Fields::from(vec![
+            Field::new("key", keys.data_type().clone(), false),
+            Field::new("value", values.data_type().clone(), true),
+        ]),
+        vec![keys, values],
+        None,
+    )?;
+    let map_array: ArrayRef = Arc::new(MapArray::try_new(
+        Arc::new(Field::new("entries", entries.data_type().clone(), false)),
+        OffsetBuffer::new(offsets.into()),
+        entries,
+        Some(NullBuffer::from(valids)),
+        false,
+    )?);
+    Ok(ColumnarValue::Array(map_array))
+}
+
+fn num_rows_of_args(args: &[ColumnarValue]) -> usize {
+    args.iter()
+        .filter_map(|arg| match arg {
+            ColumnarValue::Array(array) => Some(array.len()),
+            ColumnarValue::Scalar(_) => None,
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{
+            Array, ArrayRef, AsArray, Int32Array, Int32Builder, ListArray, MapBuilder, StringArray,
+            StringBuilder,
+        },
+        datatypes::Int32Type,
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        physical_plan::ColumnarValue,
+    };
+
+    use crate::spark_map::{element_at, map_from_arrays, map_keys, map_values};
+
+    // [{a: 1, b: null, a: 3}, null, {}, {c: 4}]
+    fn test_map_array() -> Result<ArrayRef> {
+        let mut builder = MapBuilder::new(None, StringBuilder::new(), Int32Builder::new());
+        builder.keys().append_value("a");
+        builder.values().append_value(1);
+        builder.keys().append_value("b");
+        builder.values().append_null();
+        builder.keys().append_value("a");
+        builder.values().append_value(3);
+        builder.append(true)?;
+        builder.append(false)?;
+        builder.append(true)?;
+        builder.keys().append_value("c");
+        builder.values().append_value(4);
+        builder.append(true)?;
+        Ok(Arc::new(builder.finish()))
+    }
+
+    fn string_lists(array: &ArrayRef) -> Vec<Option<Vec<String>>> {
+        array
+            .as_list::<i32>()
+            .iter()
+            .map(|list| {
+                list.map(|list| {
+                    list.as_string::<i32>()
+                        .iter()
+                        .map(|s| s.unwrap().to_string())
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_map_keys_and_values() -> Result<()> {
+        let map_array = test_map_array()?;
+
+        let keys = map_keys(&[ColumnarValue::Array(map_array.clone())])?.into_array(4)?;
+        assert_eq!(
+            string_lists(&keys),
+            vec![
+                Some(vec!["a".to_string(), "b".to_string(), "a".to_string()]),
+                None,
+                Some(vec![]),
+                Some(vec!["c".to_string()]),
+            ]
+        );
+
+        let values = map_values(&[ColumnarValue::Array(map_array.clone())])?.into_array(4)?;
+        let expected = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), None, Some(3)]),
+            None,
+            Some(vec![]),
+            Some(vec![Some(4)]),
+        ]);
+        assert_eq!(values.as_list::<i32>(), &expected);
+
+        // sliced
+        let keys = map_keys(&[ColumnarValue::Array(map_array.slice(2, 2))])?.into_array(2)?;
+        assert_eq!(
+            string_lists(&keys),
+            vec![Some(vec![]), Some(vec!["c".to_string()])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_element_at() -> Result<()> {
+        let map_array = test_map_array()?;
+
+        // scalar key, the first matching entry is taken for duplicated keys
+        let values = element_at(&[
+            ColumnarValue::Array(map_array.clone()),
+            ColumnarValue::Scalar(ScalarValue::from("a")),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(false))),
+        ])?
+        .into_array(4)?;
+        assert_eq!(
+            values.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(1), None, None, None])
+        );
+
+        // array key
+        let keys: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("b"),
+            Some("a"),
+            Some("a"),
+            Some("c"),
+        ]));
+        let values = element_at(&[
+            ColumnarValue::Array(map_array.clone()),
+            ColumnarValue::Array(keys),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(false))),
+        ])?
+        .into_array(4)?;
+        assert_eq!(
+            values.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![None, None, None, Some(4)])
+        );
+
+        // ansi mode: null maps and existing keys with null values are fine
+        let values = element_at(&[
+            ColumnarValue::Array(map_array.slice(0, 2)),
+            ColumnarValue::Scalar(ScalarValue::from("b")),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))),
+        ])?
+        .into_array(2)?;
+        assert_eq!(values.null_count(), 2);
+
+        // ansi mode: non-existent key
+        let err = element_at(&[
+            ColumnarValue::Array(map_array.clone()),
+            ColumnarValue::Scalar(ScalarValue::from("c")),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("key c does not exist"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_from_arrays() -> Result<()> {
+        let keys: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), Some(2)]),
+            None,
+            Some(vec![Some(3), Some(4), Some(3)]),
+        ]));
+        let values: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(10), None]),
+            Some(vec![Some(0)]),
+            Some(vec![Some(30), Some(40), Some(50)]),
+        ]));
+
+        // duplicated keys
+        let err = map_from_arrays(&[
+            ColumnarValue::Array(keys.clone()),
+            ColumnarValue::Array(values.clone()),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(false))),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("duplicate map key 3"));
+
+        // last win
+        let map_array = map_from_arrays(&[
+            ColumnarValue::Array(keys.clone()),
+            ColumnarValue::Array(values.clone()),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))),
+        ])?
+        .into_array(3)?;
+        let map_array = map_array.as_map();
+        assert!(map_array.is_valid(0));
+        assert!(map_array.is_null(1));
+        assert_eq!(map_array.value_offsets(), &[0, 2, 2, 4]);
+        assert_eq!(
+            map_array.keys().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            map_array.values().as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(10), None, Some(50), Some(40)])
+        );
+
+        // mismatched lengths
+        let err = map_from_arrays(&[
+            ColumnarValue::Array(values.slice(1, 1)),
+            ColumnarValue::Array(keys.slice(0, 1)),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("must have the same length"));
+
+        // null keys
+        let null_keys: ArrayRef =
+            Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                Some(vec![Some(1), None]),
+            ]));
+        let err = map_from_arrays(&[
+            ColumnarValue::Array(null_keys),
+            ColumnarValue::Array(values.slice(0, 1)),
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot use null as map key"));
+        Ok(())
+    }
+}
@@ -22,12 +22,12 @@ use arrow::{
     array::*,
     datatypes::{DataType, *},
 };
-use bitvec::{bitvec, vec::BitVec};
+use bitvec::{bitvec, slice::BitSlice, vec::BitVec};
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use datafusion::common::{utils::proxy::VecAllocExt, Result, ScalarValue};
 use datafusion_ext_commons::{
-    df_execution_err, downcast_any,
-    io::{read_len, read_scalar, write_len, write_scalar},
+    df_execution_err, df_unimplemented_err, downcast_any,
+    io::{read_bytes_slice, read_len, read_scalar, write_len, write_scalar},
     scalar_value::scalar_value_heap_mem_size,
     SliceAsRawBytes, UninitializedInit,
 };
@@ -43,6 +43,21 @@ pub trait AccColumn: Send {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn resize(&mut self, len: usize);
+
+    /// resets records in `start..end` to their initial (null) state in bulk.
+    fn fill_null_range(&mut self, start: usize, end: usize);
+
+    /// marks the accumulator as permanently null, it is not updated by later
+    /// inputs and merges until it is reset by `fill_null_range`.
+    fn set_null(&mut self, _acc_idx: usize) -> Result<()> {
+        df_unimplemented_err!("set_null is not supported by this AccColumn")
+    }
+
+    /// returns true if the accumulator is marked by `set_null`
+    fn is_null(&self, _acc_idx: usize) -> bool {
+        false
+    }
+
     fn shrink_to_fit(&mut self);
     fn num_records(&self) -> usize;
     fn mem_used(&self) -> usize;
@@ -51,6 +66,73 @@ pub trait AccColumn: Send {
     fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()>;
     fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()>;
 
+    /// keeps only the records at `retained` (in increasing order) and shifts
+    /// them down in place, removed records are freed.
+    fn compact(&mut self, retained: &[usize]) -> Result<()>;
+
+    /// freezes rows and writes them to `w` group by group, so that at most
+    /// `FREEZE_ROW_GROUP_SIZE` frozen rows are buffered in memory at a time.
+    fn freeze_to_writer(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // row buffers are cleared but not freed between groups
+        let mut rows: Vec<Vec<u8>> = vec![];
+        for group_idx in idx.chunks(FREEZE_ROW_GROUP_SIZE) {
+            rows.resize_with(group_idx.len(), Vec::new);
+            rows.iter_mut().for_each(|row| row.clear());
+            self.freeze_to_rows(group_idx, &mut rows)?;
+            for row in &rows {
+                write_len(row.len(), w)?;
+                w.write_all(row)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// reads rows written by `freeze_to_writer`
+    fn unfreeze_from_reader(
+        &mut self,
+        num_rows: usize,
+        r: &mut SpillCompressedReader,
+    ) -> Result<()> {
+        let mut rows = Vec::with_capacity(num_rows);
+        for _ in 0..num_rows {
+            let len = read_len(r)?;
+            rows.push(read_bytes_slice(r, len)?);
+        }
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_ref()))
+            .collect::<Vec<_>>();
+        self.unfreeze_from_rows(&mut cursors)
+    }
+
+    /// spills the selected records with `freeze_to_writer` and removes them
+    /// from the column, so that a part of the groups can be evicted to free
+    /// memory. remaining records are shifted down in their original order.
+    /// returns the new index of every record before removal (None for the
+    /// removed ones), for the grouping layer to update its record indices.
+    /// spilled records are read back with `unfreeze_from_reader`.
+    fn spill_and_remove(
+        &mut self,
+        idx: IdxSelection<'_>,
+        w: &mut SpillCompressedWriter,
+    ) -> Result<Vec<Option<usize>>> {
+        self.freeze_to_writer(idx, w)?;
+        let (retained, remap) = retained_records(self.num_records(), idx);
+        self.compact(&retained)?;
+        Ok(remap)
+    }
+
+    /// returns the frozen rows of selected accumulators as a binary array, so
+    /// that prototype aggregates can decode their values in final_merge()
+    /// without downcasting and matching the selection. production aggregates
+    /// should override it, or build the output directly from their columnar
+    /// values, since every value is copied through the row format.
+    fn iter_values_as_array(&self, idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let mut rows = vec![vec![]; idx.len()];
+        self.freeze_to_rows(idx, &mut rows)?;
+        Ok(Arc::new(BinaryArray::from_iter_values(rows)))
+    }
+
     fn ensure_size(&mut self, idx: IdxSelection<'_>) {
         let idx_max_value = match idx {
             IdxSelection::Single(v) => v,
@@ -66,6 +148,46 @@ pub trait AccColumn: Send {
 
 pub type AccColumnRef = Box<dyn AccColumn>;
 
+/// returns indices of records not in `removed` in increasing order, and the
+/// new index of every record after removing (None for the removed ones)
+pub fn retained_records(
+    num_records: usize,
+    removed: IdxSelection<'_>,
+) -> (Vec<usize>, Vec<Option<usize>>) {
+    let mut is_removed = bitvec![0; num_records];
+    idx_for! {
+        (i in removed) => {
+            is_removed.set(i, true);
+        }
+    }
+    let mut retained = Vec::with_capacity(num_records);
+    let mut remap = vec![None; num_records];
+    for i in is_removed.iter_zeros() {
+        remap[i] = Some(retained.len());
+        retained.push(i);
+    }
+    (retained, remap)
+}
+
+/// moves items at `retained` (in increasing order) to the front in place, the
+/// removed items are left after them for the caller to resize away
+pub fn compact_items<T>(items: &mut [T], retained: &[usize]) {
+    for (new_idx, &idx) in retained.iter().enumerate() {
+        items.swap(new_idx, idx);
+    }
+}
+
+/// like `compact_items`, for bit-packed items
+pub fn compact_bits(bits: &mut BitSlice, retained: &[usize]) {
+    for (new_idx, &idx) in retained.iter().enumerate() {
+        bits.swap(new_idx, idx);
+    }
+}
+
+/// max number of rows frozen into memory at a time in
+/// `AccColumn::freeze_to_writer`
+pub const FREEZE_ROW_GROUP_SIZE: usize = 4096;
+
 pub type AccBytes = SmallVec<u8, 24>;
 const _ACC_BYTES_SIZE_CHECKER: [(); 32] = [(); size_of::<AccBytes>()];
 
@@ -98,6 +220,15 @@ impl AccTable {
     pub fn mem_size(&self) -> usize {
         self.cols.iter().map(|c| c.mem_used()).sum()
     }
+
+    /// keeps only the records at `retained` of all columns, see
+    /// `AccColumn::compact`
+    pub fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        for col in &mut self.cols {
+            col.compact(retained)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct AccBooleanColumn {
@@ -164,6 +295,11 @@ impl AccColumn for AccBooleanColumn {
         self.values.resize(len, false);
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.valids[start..end].fill(false);
+        self.values[start..end].fill(false);
+    }
+
     fn shrink_to_fit(&mut self) {
         self.valids.shrink_to_fit();
         self.values.shrink_to_fit();
@@ -209,33 +345,36 @@ impl AccColumn for AccBooleanColumn {
     }
 
     fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
-        let mut buf = vec![];
-
+        // bit-packed valids and values, 2 bits for each record
+        let mut valid_bits: BitVec<u8> = BitVec::with_capacity(idx.len());
+        let mut value_bits: BitVec<u8> = BitVec::with_capacity(idx.len());
         idx_for! {
-             (idx in idx) => {
-                 if self.valids[idx] {
-                    buf.push(1 + self.values[idx] as u8);
-                } else {
-                    buf.push(0);
-                }
-             }
+            (idx in idx) => {
+                valid_bits.push(self.valids[idx]);
+                value_bits.push(self.valids[idx] && self.values[idx]);
+            }
         }
-        w.write_all(&buf)?;
+        w.write_all(valid_bits.as_raw_slice())?;
+        w.write_all(value_bits.as_raw_slice())?;
         Ok(())
     }
 
     fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
-        self.resize(num_rows);
-        let mut buf = Vec::uninitialized_init(num_rows);
-        r.read_exact(&mut buf)?;
-        for (i, v) in buf.into_iter().enumerate() {
-            if v == 0 {
-                self.valids.set(i, false);
-            } else {
-                self.valids.set(i, true);
-                self.values.set(i, v - 1 != 0);
-            }
-        }
+        let mut valid_bits: BitVec<u8> = BitVec::repeat(false, num_rows);
+        let mut value_bits: BitVec<u8> = BitVec::repeat(false, num_rows);
+        r.read_exact(valid_bits.as_raw_mut_slice())?;
+        r.read_exact(value_bits.as_raw_mut_slice())?;
+        self.valids.clear();
+        self.valids.extend_from_bitslice(valid_bits.as_bitslice());
+        self.values.clear();
+        self.values.extend_from_bitslice(value_bits.as_bitslice());
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_bits(&mut self.valids, retained);
+        compact_bits(&mut self.values, retained);
+        self.resize(retained.len());
         Ok(())
     }
 }
@@ -243,6 +382,7 @@ impl AccColumn for AccBooleanColumn {
 pub struct AccPrimColumn<T: ArrowNativeType> {
     values: Vec<T>,
     valids: BitVec,
+    nulls: BitVec,
 }
 
 impl<T: ArrowNativeType> AccPrimColumn<T> {
@@ -250,6 +390,7 @@ impl<T: ArrowNativeType> AccPrimColumn<T> {
         Self {
             values: vec![T::default(); num_records],
             valids: bitvec![0; num_records],
+            nulls: bitvec![0; num_records],
         }
     }
 
@@ -262,6 +403,9 @@ impl<T: ArrowNativeType> AccPrimColumn<T> {
     }
 
     pub fn set_value(&mut self, idx: usize, value: Option<T>) {
+        if self.nulls[idx] {
+            return;
+        }
         if let Some(value) = value {
             self.values[idx] = value;
             self.valids.set(idx, true);
@@ -271,6 +415,9 @@ impl<T: ArrowNativeType> AccPrimColumn<T> {
     }
 
     pub fn update_value(&mut self, idx: usize, default_value: T, update: impl Fn(T) -> T) {
+        if self.nulls[idx] {
+            return;
+        }
         if self.valids[idx] {
             self.values[idx] = update(self.values[idx]);
         } else {
@@ -317,11 +464,29 @@ impl<T: ArrowNativeType> AccColumn for AccPrimColumn<T> {
     fn resize(&mut self, len: usize) {
         self.values.resize(len, T::default());
         self.valids.resize(len, false);
+        self.nulls.resize(len, false);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start..end].fill(T::default());
+        self.valids[start..end].fill(false);
+        self.nulls[start..end].fill(false);
+    }
+
+    fn set_null(&mut self, acc_idx: usize) -> Result<()> {
+        self.valids.set(acc_idx, false);
+        self.nulls.set(acc_idx, true);
+        Ok(())
+    }
+
+    fn is_null(&self, acc_idx: usize) -> bool {
+        self.nulls[acc_idx]
     }
 
     fn shrink_to_fit(&mut self) {
         self.values.shrink_to_fit();
         self.valids.shrink_to_fit();
+        self.nulls.shrink_to_fit();
     }
 
     fn num_records(&self) -> usize {
@@ -329,15 +494,20 @@ impl<T: ArrowNativeType> AccColumn for AccPrimColumn<T> {
     }
 
     fn mem_used(&self) -> usize {
-        self.values.allocated_size() + (self.valids.capacity() + 7) / 8
+        self.values.allocated_size()
+            + (self.valids.capacity() + 7) / 8
+            + (self.nulls.capacity() + 7) / 8
     }
 
     fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        // 0: no value, 1: valid value, 2: marked null by set_null()
         idx_with_iter!((idx @ idx) => {
             for (i, w) in idx.zip(array) {
                 if self.valids[i] {
                     w.write_u8(1)?;
                     w.write_all([self.values[i]].as_raw_bytes())?;
+                } else if self.nulls[i] {
+                    w.write_u8(2)?;
                 } else {
                     w.write_u8(0)?;
                 }
@@ -360,6 +530,7 @@ impl<T: ArrowNativeType> AccColumn for AccPrimColumn<T> {
                 self.values.push(T::default());
                 self.valids.push(false);
             }
+            self.nulls.push(valid == 2);
         }
         Ok(())
     }
@@ -386,6 +557,19 @@ impl<T: ArrowNativeType> AccColumn for AccPrimColumn<T> {
             }
         }
         w.write_all(values.as_raw_bytes())?;
+
+        // write nulls marked by set_null(), omitted if there are none
+        let mut null_bits: BitVec<u8> = BitVec::with_capacity(idx.len());
+        idx_for! {
+            (idx in idx) => {
+                null_bits.push(self.nulls[idx]);
+            }
+        }
+        let has_nulls = null_bits.any();
+        w.write_u8(has_nulls as u8)?;
+        if has_nulls {
+            w.write_all(null_bits.as_raw_slice())?;
+        }
         Ok(())
     }
 
@@ -407,6 +591,24 @@ impl<T: ArrowNativeType> AccColumn for AccPrimColumn<T> {
             self.values[i] = read_values[read_value_pos];
             read_value_pos += 1;
         }
+
+        // read nulls
+        self.nulls.clear();
+        if r.read_u8()? == 1 {
+            let mut null_bits: BitVec<u8> = BitVec::repeat(false, num_rows);
+            r.read_exact(null_bits.as_raw_mut_slice())?;
+            self.nulls.extend_from_bitslice(null_bits.as_bitslice());
+        } else {
+            self.nulls.resize(num_rows, false);
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.values, retained);
+        compact_bits(&mut self.valids, retained);
+        compact_bits(&mut self.nulls, retained);
+        self.resize(retained.len());
         Ok(())
     }
 }
@@ -516,13 +718,18 @@ impl AccColumn for AccBytesColumn {
         if len > self.items.len() {
             self.items.resize(len, Default::default());
         } else {
-            for idx in len..self.items.len() {
-                self.heap_mem_used -= self.item_heap_mem_used(idx);
-            }
+            self.fill_null_range(len, self.items.len());
             self.items.truncate(len);
         }
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.heap_mem_used -= self.item_heap_mem_used(idx);
+            self.items[idx] = None;
+        }
+    }
+
     fn shrink_to_fit(&mut self) {
         self.items.shrink_to_fit();
     }
@@ -569,6 +776,12 @@ impl AccColumn for AccBytesColumn {
         self.refresh_heap_mem_used();
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.items, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
 }
 
 pub struct AccScalarValueColumn {
@@ -626,13 +839,18 @@ impl AccColumn for AccScalarValueColumn {
         if len > self.items.len() {
             self.items.resize_with(len, || self.null_value.clone());
         } else {
-            for idx in len..self.items.len() {
-                self.heap_mem_used -= scalar_value_heap_mem_size(&self.items[idx]);
-            }
+            self.fill_null_range(len, self.items.len());
             self.items.truncate(len);
         }
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.heap_mem_used -= scalar_value_heap_mem_size(&self.items[idx]);
+            self.items[idx] = self.null_value.clone();
+        }
+    }
+
     fn shrink_to_fit(&mut self) {
         self.items.shrink_to_fit();
     }
@@ -686,6 +904,12 @@ impl AccColumn for AccScalarValueColumn {
         }
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.items, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
 }
 
 pub fn create_acc_generic_column(dt: &DataType, num_rows: usize) -> AccColumnRef {
@@ -728,3 +952,71 @@ pub fn acc_generic_column_to_array(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use datafusion::common::Result;
+
+    use crate::{
+        agg::{
+            acc::{AccBytes, AccBytesColumn, AccColumn, AccPrimColumn},
+            agg::IdxSelection,
+        },
+        memmgr::spill::Spill,
+    };
+
+    #[test]
+    fn test_fill_null_range() {
+        let mut prims = AccPrimColumn::<i64>::new(10);
+        for i in 0..10 {
+            prims.set_value(i, Some(i as i64));
+        }
+        prims.fill_null_range(3, 7);
+        for i in 0..10 {
+            let expected = if (3..7).contains(&i) {
+                None
+            } else {
+                Some(i as i64)
+            };
+            assert_eq!(prims.value(i), expected);
+        }
+
+        let mut bytes = AccBytesColumn::new(10);
+        let base_mem_used = bytes.mem_used();
+        for i in 0..10 {
+            bytes.set_value(i, Some(AccBytes::from_vec(vec![i as u8; 1000])));
+        }
+        bytes.fill_null_range(0, 5);
+        assert!((0..5).all(|i| bytes.value(i).is_none()));
+        assert!((5..10).all(|i| bytes.value(i).is_some()));
+        bytes.fill_null_range(5, 10);
+        assert_eq!(bytes.mem_used(), base_mem_used);
+    }
+    #[test]
+    fn test_spill_and_remove() -> Result<()> {
+        let mut prims = AccPrimColumn::<i64>::new(6);
+        for i in 0..6 {
+            prims.set_value(i, Some(i as i64 * 10));
+        }
+        prims.set_value(2, None);
+
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        let remap = prims.spill_and_remove(IdxSelection::Indices(&[1, 4, 5]), &mut spill_writer)?;
+        spill_writer.finish()?;
+        assert_eq!(remap, vec![Some(0), None, Some(1), Some(2), None, None]);
+        assert_eq!(prims.num_records(), 3);
+        assert_eq!(
+            (0..3).map(|i| prims.value(i)).collect::<Vec<_>>(),
+            vec![Some(0), None, Some(30)],
+        );
+
+        let mut unspilled = AccPrimColumn::<i64>::new(0);
+        unspilled.unfreeze_from_reader(3, &mut spill.get_compressed_reader())?;
+        assert_eq!(
+            (0..3).map(|i| unspilled.value(i)).collect::<Vec<_>>(),
+            vec![Some(10), Some(40), Some(50)],
+        );
+        Ok(())
+    }
+}
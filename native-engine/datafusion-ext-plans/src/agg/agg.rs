@@ -16,35 +16,66 @@ use std::{any::Any, fmt::Debug, sync::Arc};
 
 use arrow::{
     array::{ArrayRef, AsArray, RecordBatch},
-    datatypes::{DataType, Int64Type, Schema, SchemaRef},
+    datatypes::{DataType, Float64Type, Int64Type, Schema, SchemaRef},
+};
+use datafusion::{
+    common::{DataFusionError, Result, ScalarValue},
+    physical_expr::PhysicalExpr,
 };
-use datafusion::{common::Result, physical_expr::PhysicalExpr};
 use datafusion_ext_commons::df_execution_err;
 use datafusion_ext_exprs::cast::TryCastExpr;
+use tokio::runtime::{Handle, RuntimeFlavor};
 
 use crate::agg::{
     acc::AccColumnRef,
+    any_value::AggAnyValue,
     avg::AggAvg,
     bloom_filter::AggBloomFilter,
+    bool_and_or::{AggBoolAnd, AggBoolOr},
     brickhouse,
     collect::{AggCollectList, AggCollectSet},
-    count::AggCount,
+    count::{bulk_update_threshold_from_conf, AggCount, CountOverflowBehavior},
     first::AggFirst,
     first_ignores_null::AggFirstIgnoresNull,
-    maxmin::{AggMax, AggMin},
+    geomean::AggGeoMean,
+    maxmin::{AggMax, AggMin, AggTimestampMax, AggTimestampMin},
+    mode::AggModeValue,
+    product::AggProduct,
+    regr::{AggRegrAvgX, AggRegrAvgY, AggRegrCount, AggRegrIntercept, AggRegrR2, AggRegrSlope},
+    reservoir_sample::AggReservoirSample,
     spark_udaf_wrapper::SparkUDAFWrapper,
+    struct_agg::AggStructAgg,
     sum::AggSum,
+    sum_list::AggSumList,
     AggFunction,
 };
 
+/// min number of input rows for parallel_partial_update() to run in parallel
+pub const PARALLEL_PARTIAL_UPDATE_MIN_ROWS: usize = 100_000;
+
 pub trait Agg: Send + Sync + Debug {
     fn as_any(&self) -> &dyn Any;
     fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>>;
     fn data_type(&self) -> &DataType;
     fn nullable(&self) -> bool;
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef;
+
+    /// creates the acc column of the main in-memory hashing table, which may
+    /// be pre-sized for the expected number of groups. merging, spilling and
+    /// per-chunk tables use create_acc_column() instead.
+    fn create_hashing_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        self.create_acc_column(num_rows)
+    }
     fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>>;
 
+    /// hint of frozen accumulator size of each group in bytes, used for
+    /// pre-allocating row buffers in freeze_acc_table(). defaults to a flag
+    /// byte followed by a primitive value, which is the format of generic
+    /// accumulator columns.
+    fn expected_partial_state_size_bytes_per_group(&self) -> usize {
+        1 + self.data_type().primitive_width().unwrap_or(0)
+    }
+
     fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
         // default implementation: directly return the inputs
         Ok(partial_inputs.iter().cloned().collect())
@@ -58,6 +89,12 @@ pub trait Agg: Send + Sync + Debug {
         partial_arg_idx: IdxSelection<'_>,
     ) -> Result<()>;
 
+    /// whether partial_update() can be called concurrently on separated
+    /// accumulators, required by parallel_partial_update()
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        false
+    }
+
     fn partial_merge(
         &self,
         accs: &mut AccColumnRef,
@@ -69,6 +106,144 @@ pub trait Agg: Send + Sync + Debug {
     fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef>;
 }
 
+/// same as agg.partial_update(), but large range inputs are split into chunks
+/// updated in parallel on the tokio blocking pool. each chunk is updated into
+/// its own accumulators covering only the groups it touches, which are then
+/// merged into `accs`. returns the memory size of the per-chunk accumulators.
+pub fn parallel_partial_update(
+    agg: &Arc<dyn Agg>,
+    accs: &mut AccColumnRef,
+    acc_idx: IdxSelection<'_>,
+    partial_args: &[ArrayRef],
+    partial_arg_idx: IdxSelection<'_>,
+) -> Result<usize> {
+    // blocking on the spawned tasks requires a multi-thread runtime
+    let parallel_handle = Handle::try_current().ok().filter(|handle| {
+        handle.runtime_flavor() == RuntimeFlavor::MultiThread
+            && handle.metrics().num_workers() > 1
+            && agg.is_thread_safe_for_parallel()
+    });
+    let (Some(handle), IdxSelection::Range(arg_begin, arg_end)) =
+        (parallel_handle, partial_arg_idx)
+    else {
+        agg.partial_update(accs, acc_idx, partial_args, partial_arg_idx)?;
+        return Ok(0);
+    };
+    if arg_end - arg_begin < PARALLEL_PARTIAL_UPDATE_MIN_ROWS {
+        agg.partial_update(accs, acc_idx, partial_args, partial_arg_idx)?;
+        return Ok(0);
+    }
+    let num_threads = handle.metrics().num_workers();
+
+    // acc_idx and partial_arg_idx are zipped, so they are chunked in the
+    // same way unless all rows go to a single accumulator
+    accs.ensure_size(acc_idx);
+    let chunk_size = (arg_end - arg_begin).div_ceil(num_threads);
+    let partial_arg_idx_chunks = partial_arg_idx.chunks(chunk_size);
+    let acc_idx_chunks = match acc_idx {
+        IdxSelection::Single(_) => vec![acc_idx; partial_arg_idx_chunks.len()],
+        _ => acc_idx.chunks(chunk_size),
+    };
+
+    let tasks = acc_idx_chunks
+        .into_iter()
+        .zip(partial_arg_idx_chunks)
+        .map(|(acc_idx, partial_arg_idx)| {
+            let agg = agg.clone();
+            let chunk_acc_idx = ChunkAccIdx::new(acc_idx);
+            let IdxSelection::Range(chunk_begin, chunk_end) = partial_arg_idx else {
+                unreachable!("chunks of a range selection must be ranges");
+            };
+            let chunk_args = partial_args
+                .iter()
+                .map(|arg| arg.slice(chunk_begin, chunk_end - chunk_begin))
+                .collect::<Vec<_>>();
+            handle.spawn_blocking(move || {
+                let mut chunk_accs = agg.create_acc_column(chunk_acc_idx.num_accs);
+                agg.partial_update(
+                    &mut chunk_accs,
+                    chunk_acc_idx.local_idx(),
+                    &chunk_args,
+                    IdxSelection::Range(0, chunk_end - chunk_begin),
+                )?;
+                Ok::<_, DataFusionError>((chunk_acc_idx.base, chunk_accs))
+            })
+        })
+        .collect::<Vec<_>>();
+    let chunk_results =
+        tokio::task::block_in_place(|| handle.block_on(futures::future::join_all(tasks)));
+
+    let mut chunk_accs_mem_used = 0;
+    for chunk_result in chunk_results {
+        let (base, mut chunk_accs) = match chunk_result {
+            Ok(chunk_result) => chunk_result?,
+            Err(e) => return df_execution_err!("parallel partial_update task failed: {e}"),
+        };
+        let num_accs = chunk_accs.num_records();
+        chunk_accs_mem_used += chunk_accs.mem_used();
+        agg.partial_merge(
+            accs,
+            IdxSelection::Range(base, base + num_accs),
+            &mut chunk_accs,
+            IdxSelection::Range(0, num_accs),
+        )?;
+    }
+    Ok(chunk_accs_mem_used)
+}
+
+/// accumulator indices of a chunk, rebased to the min index it touches
+struct ChunkAccIdx {
+    base: usize,
+    num_accs: usize,
+    local_indices: Option<Vec<u32>>,
+}
+
+impl ChunkAccIdx {
+    fn new(acc_idx: IdxSelection<'_>) -> Self {
+        match acc_idx {
+            IdxSelection::Single(idx) => Self {
+                base: idx,
+                num_accs: 1,
+                local_indices: None,
+            },
+            IdxSelection::Range(begin, end) => Self {
+                base: begin,
+                num_accs: end - begin,
+                local_indices: None,
+            },
+            _ => {
+                let mut min_idx = usize::MAX;
+                let mut max_idx = 0;
+                crate::idx_for! {
+                    (idx in acc_idx) => {
+                        min_idx = min_idx.min(idx);
+                        max_idx = max_idx.max(idx);
+                    }
+                }
+                let mut local_indices = Vec::with_capacity(acc_idx.len());
+                crate::idx_for! {
+                    (idx in acc_idx) => {
+                        local_indices.push((idx - min_idx) as u32);
+                    }
+                }
+                Self {
+                    base: min_idx,
+                    num_accs: max_idx - min_idx + 1,
+                    local_indices: Some(local_indices),
+                }
+            }
+        }
+    }
+
+    fn local_idx(&self) -> IdxSelection<'_> {
+        match &self.local_indices {
+            Some(local_indices) => IdxSelection::IndicesU32(local_indices),
+            None if self.num_accs == 1 => IdxSelection::Single(0),
+            None => IdxSelection::Range(0, self.num_accs),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum IdxSelection<'a> {
     Single(usize),
@@ -77,7 +252,7 @@ pub enum IdxSelection<'a> {
     Range(usize, usize),
 }
 
-impl IdxSelection<'_> {
+impl<'a> IdxSelection<'a> {
     pub fn len(&self) -> usize {
         match *self {
             IdxSelection::Single(_) => 1,
@@ -87,6 +262,28 @@ impl IdxSelection<'_> {
         }
     }
 
+    /// splits the selection into sub-selections of at most `chunk_size` indices
+    pub fn chunks(&self, chunk_size: usize) -> Vec<IdxSelection<'a>> {
+        assert!(chunk_size > 0, "chunk_size must be positive");
+        match *self {
+            IdxSelection::Single(idx) => vec![IdxSelection::Single(idx)],
+            IdxSelection::Indices(indices) => indices
+                .chunks(chunk_size)
+                .map(IdxSelection::Indices)
+                .collect(),
+            IdxSelection::IndicesU32(indices) => indices
+                .chunks(chunk_size)
+                .map(IdxSelection::IndicesU32)
+                .collect(),
+            IdxSelection::Range(begin, end) => (begin..end)
+                .step_by(chunk_size)
+                .map(|chunk_begin| {
+                    IdxSelection::Range(chunk_begin, end.min(chunk_begin + chunk_size))
+                })
+                .collect(),
+        }
+    }
+
     pub fn to_int32_vec(&self) -> Vec<i32> {
         let mut vec = Vec::with_capacity(self.len());
         crate::idx_for! {
@@ -96,6 +293,28 @@ impl IdxSelection<'_> {
         }
         vec
     }
+
+    /// compacts the selection into runs of consecutive indices, encoded as
+    /// (start, len) pairs.
+    pub fn to_run_lengths(&self) -> Vec<(usize, usize)> {
+        if let IdxSelection::Range(begin, end) = *self {
+            return if begin < end {
+                vec![(begin, end - begin)]
+            } else {
+                vec![]
+            };
+        }
+        let mut runs: Vec<(usize, usize)> = vec![];
+        crate::idx_for! {
+            (idx in *self) => {
+                match runs.last_mut() {
+                    Some((start, len)) if *start + *len == idx => *len += 1,
+                    _ => runs.push((idx, 1)),
+                }
+            }
+        }
+        runs
+    }
 }
 
 #[macro_export]
@@ -172,6 +391,18 @@ pub fn create_agg(
     children: &[Arc<dyn PhysicalExpr>],
     input_schema: &SchemaRef,
     return_type: DataType,
+) -> Result<Arc<dyn Agg>> {
+    create_agg_with_fail_on_overflow(agg_function, children, input_schema, return_type, false)
+}
+
+/// creates an aggregate raising errors instead of returning null on overflow if
+/// `fail_on_overflow` is set (ansi mode), currently only respected by sum()
+pub fn create_agg_with_fail_on_overflow(
+    agg_function: AggFunction,
+    children: &[Arc<dyn PhysicalExpr>],
+    input_schema: &SchemaRef,
+    return_type: DataType,
+    fail_on_overflow: bool,
 ) -> Result<Arc<dyn Agg>> {
     Ok(match agg_function {
         AggFunction::Count => {
@@ -184,24 +415,43 @@ pub fn create_agg(
                 })
                 .cloned()
                 .collect::<Vec<_>>();
-            Arc::new(AggCount::try_new(children, return_type)?)
-        }
-        AggFunction::Sum => Arc::new(AggSum::try_new(
-            Arc::new(TryCastExpr::new(children[0].clone(), return_type.clone())),
-            return_type,
-        )?),
-        AggFunction::Avg => Arc::new(AggAvg::try_new(
-            Arc::new(TryCastExpr::new(children[0].clone(), return_type.clone())),
-            return_type,
-        )?),
-        AggFunction::Max => {
-            let dt = children[0].data_type(input_schema)?;
-            Arc::new(AggMax::try_new(children[0].clone(), dt)?)
-        }
-        AggFunction::Min => {
-            let dt = children[0].data_type(input_schema)?;
-            Arc::new(AggMin::try_new(children[0].clone(), dt)?)
+            Arc::new(
+                AggCount::try_new(children, return_type)?
+                    .with_overflow_behavior(CountOverflowBehavior::from_conf()?)
+                    .with_bulk_update_threshold(bulk_update_threshold_from_conf()?),
+            )
         }
+        AggFunction::CountPerColumn => Arc::new(
+            AggCount::try_new_per_column(children.to_vec(), return_type)?
+                .with_overflow_behavior(CountOverflowBehavior::from_conf()?)
+                .with_bulk_update_threshold(bulk_update_threshold_from_conf()?),
+        ),
+        AggFunction::Sum => Arc::new(
+            AggSum::try_new(
+                Arc::new(TryCastExpr::new(children[0].clone(), return_type.clone())),
+                return_type,
+            )?
+            .with_fail_on_overflow(fail_on_overflow),
+        ),
+        AggFunction::Avg => Arc::new(
+            AggAvg::try_new(
+                Arc::new(TryCastExpr::new(children[0].clone(), return_type.clone())),
+                return_type,
+            )?
+            .with_overflow_behavior(CountOverflowBehavior::from_conf()?),
+        ),
+        AggFunction::Max => match children[0].data_type(input_schema)? {
+            dt @ DataType::Timestamp(..) => {
+                Arc::new(AggTimestampMax::try_new(children[0].clone(), dt)?)
+            }
+            dt => Arc::new(AggMax::try_new(children[0].clone(), dt)?),
+        },
+        AggFunction::Min => match children[0].data_type(input_schema)? {
+            dt @ DataType::Timestamp(..) => {
+                Arc::new(AggTimestampMin::try_new(children[0].clone(), dt)?)
+            }
+            dt => Arc::new(AggMin::try_new(children[0].clone(), dt)?),
+        },
         AggFunction::First => {
             let dt = children[0].data_type(input_schema)?;
             Arc::new(AggFirst::try_new(children[0].clone(), dt)?)
@@ -210,6 +460,10 @@ pub fn create_agg(
             let dt = children[0].data_type(input_schema)?;
             Arc::new(AggFirstIgnoresNull::try_new(children[0].clone(), dt)?)
         }
+        AggFunction::AnyValue => {
+            let dt = children[0].data_type(input_schema)?;
+            Arc::new(AggAnyValue::try_new(children[0].clone(), dt)?)
+        }
         AggFunction::BloomFilter => {
             let dt = children[0].data_type(input_schema)?;
             let empty_batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
@@ -268,20 +522,309 @@ pub fn create_agg(
                 arg_list_inner_type,
             )?)
         }
+        AggFunction::Mode => {
+            let dt = children[0].data_type(input_schema)?;
+            Arc::new(AggModeValue::try_new(children[0].clone(), dt)?)
+        }
+        AggFunction::SumList => match children[0].data_type(input_schema)? {
+            DataType::List(field) if field.data_type() == &DataType::Float64 => Arc::new(
+                AggSumList::<Float64Type>::try_new(children[0].clone(), DataType::List(field))?,
+            ),
+            dt => Arc::new(AggSumList::<Int64Type>::try_new(children[0].clone(), dt)?),
+        },
+        AggFunction::ReservoirSample => {
+            let arg_type = children[0].data_type(input_schema)?;
+            let k = eval_int_literal(&children[1], "reservoir_sample k")?;
+            if k < 0 {
+                return df_execution_err!("reservoir_sample expects non-negative k, got {k}");
+            }
+            let seed = match children.get(2) {
+                Some(seed) => eval_int_literal(seed, "reservoir_sample seed")?,
+                None => 0,
+            };
+            Arc::new(AggReservoirSample::try_new(
+                children[0].clone(),
+                return_type,
+                arg_type,
+                k as usize,
+                seed,
+            )?)
+        }
+        AggFunction::GeoMean => Arc::new(AggGeoMean::try_new(children[0].clone())?),
+        AggFunction::Product => Arc::new(AggProduct::try_new(children[0].clone())?),
+        AggFunction::BoolAnd => Arc::new(AggBoolAnd::try_new(children[0].clone())?),
+        AggFunction::BoolOr => Arc::new(AggBoolOr::try_new(children[0].clone())?),
+        AggFunction::RegrCount => Arc::new(AggRegrCount::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::RegrAvgX => Arc::new(AggRegrAvgX::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::RegrAvgY => Arc::new(AggRegrAvgY::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::RegrSlope => Arc::new(AggRegrSlope::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::RegrIntercept => Arc::new(AggRegrIntercept::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::RegrR2 => Arc::new(AggRegrR2::try_new(
+            children[0].clone(),
+            children[1].clone(),
+        )?),
+        AggFunction::StructAgg => {
+            unreachable!("StructAgg should be handled in create_struct_agg")
+        }
         AggFunction::Udaf => {
             unreachable!("UDAF should be handled in create_udaf_agg")
         }
     })
 }
 
+// evaluates a foldable integer argument, like k of reservoir_sample
+fn eval_int_literal(expr: &Arc<dyn PhysicalExpr>, name: &str) -> Result<i64> {
+    let empty_batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+    let value = expr.evaluate(&empty_batch)?.into_array(1)?;
+    match ScalarValue::try_from_array(&value, 0)? {
+        ScalarValue::Int8(Some(v)) => Ok(v as i64),
+        ScalarValue::Int16(Some(v)) => Ok(v as i64),
+        ScalarValue::Int32(Some(v)) => Ok(v as i64),
+        ScalarValue::Int64(Some(v)) => Ok(v),
+        other => df_execution_err!("{name} expects a non-null integer literal, got {other:?}"),
+    }
+}
+
 pub fn create_udaf_agg(
     serialized: Vec<u8>,
     return_type: DataType,
     children: Vec<Arc<dyn PhysicalExpr>>,
+    initial_capacity: Option<usize>,
 ) -> Result<Arc<dyn Agg>> {
-    Ok(Arc::new(SparkUDAFWrapper::try_new(
-        serialized,
-        return_type,
-        children,
-    )?))
+    let mut udaf = SparkUDAFWrapper::try_new(serialized, return_type, children)?;
+    if let Some(capacity) = initial_capacity {
+        udaf = udaf.with_initial_capacity(capacity);
+    }
+    Ok(Arc::new(udaf))
+}
+
+pub fn create_struct_agg(aggs: Vec<Arc<dyn Agg>>, return_type: DataType) -> Result<Arc<dyn Agg>> {
+    let DataType::Struct(fields) = &return_type else {
+        return df_execution_err!("struct_agg expects struct return type, got {return_type}");
+    };
+    let field_names = fields.iter().map(|field| field.name().clone()).collect();
+    let struct_agg = AggStructAgg::try_new(aggs, field_names)?;
+    if !struct_agg.data_type().equals_datatype(&return_type) {
+        return df_execution_err!(
+            "struct_agg return type mismatched: expect {return_type}, got {}",
+            struct_agg.data_type(),
+        );
+    }
+    Ok(Arc::new(struct_agg))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Decimal128Array, Int64Array},
+        datatypes::{DataType, Field, Int64Type, Schema},
+    };
+    use datafusion::{
+        common::{Result, ScalarValue},
+        physical_expr::expressions::{lit, Column},
+    };
+
+    use crate::agg::{
+        agg::{
+            create_agg, create_agg_with_fail_on_overflow, create_struct_agg,
+            parallel_partial_update, Agg, IdxSelection, PARALLEL_PARTIAL_UPDATE_MIN_ROWS,
+        },
+        avg::AggAvg,
+        count::AggCount,
+        sum::AggSum,
+        AggFunction,
+    };
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_partial_update() -> Result<()> {
+        // run on a worker, where agg tasks call parallel_partial_update()
+        tokio::spawn(async { check_parallel_partial_update() })
+            .await
+            .expect("tokio spawn error")
+    }
+
+    fn check_parallel_partial_update() -> Result<()> {
+        let num_rows = PARALLEL_PARTIAL_UPDATE_MIN_ROWS * 3 + 7;
+        let num_groups = 1000;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from_iter(
+            (0..num_rows as i64).map(|i| (i % 7 != 0).then_some(i)),
+        ))];
+        let acc_idx = (0..num_rows)
+            .map(|i| i * 31 % num_groups)
+            .collect::<Vec<_>>();
+
+        let child = Arc::new(Column::new("a", 0));
+        let aggs: Vec<Arc<dyn Agg>> = vec![
+            Arc::new(AggSum::try_new(child.clone(), DataType::Int64)?),
+            Arc::new(AggCount::try_new(vec![child.clone()], DataType::Int64)?),
+            Arc::new(AggAvg::try_new(child.clone(), DataType::Float64)?),
+        ];
+        for agg in aggs {
+            assert!(agg.is_thread_safe_for_parallel());
+            let partial_args = agg.prepare_partial_args(&args)?;
+            let mut results = vec![];
+            for parallel in [false, true] {
+                for acc_idx in [IdxSelection::Indices(&acc_idx), IdxSelection::Single(3)] {
+                    let mut accs = agg.create_acc_column(0);
+                    let partial_arg_idx = IdxSelection::Range(0, num_rows);
+                    if parallel {
+                        let chunk_accs_mem_used = parallel_partial_update(
+                            &agg,
+                            &mut accs,
+                            acc_idx,
+                            &partial_args,
+                            partial_arg_idx,
+                        )?;
+                        assert!(chunk_accs_mem_used > 0);
+                    } else {
+                        agg.partial_update(&mut accs, acc_idx, &partial_args, partial_arg_idx)?;
+                    }
+                    let num_records = accs.num_records();
+                    results.push(agg.final_merge(&mut accs, IdxSelection::Range(0, num_records))?);
+                }
+            }
+            assert_eq!(&results[0], &results[2]);
+            assert_eq!(&results[1], &results[3]);
+        }
+
+        // sanity check: sum of a single group, the other groups are untouched
+        let agg: Arc<dyn Agg> = Arc::new(AggSum::try_new(child, DataType::Int64)?);
+        let mut accs = agg.create_acc_column(3);
+        parallel_partial_update(
+            &agg,
+            &mut accs,
+            IdxSelection::Single(1),
+            &args,
+            IdxSelection::Range(0, num_rows),
+        )?;
+        let expected: i64 = (0..num_rows as i64).filter(|i| i % 7 != 0).sum();
+        let result = agg.final_merge(&mut accs, IdxSelection::Range(0, 3))?;
+        assert_eq!(
+            result.as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![None, Some(expected), None]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_reservoir_sample_args() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::Int64, true)]));
+        let return_type = DataType::new_list(DataType::Int64, true);
+        let create = |k: ScalarValue| {
+            create_agg(
+                AggFunction::ReservoirSample,
+                &[Arc::new(Column::new("v", 0)), lit(k)],
+                &schema,
+                return_type.clone(),
+            )
+        };
+
+        // integer literals of any width are accepted
+        assert!(create(ScalarValue::Int32(Some(5))).is_ok());
+        assert!(create(ScalarValue::Int64(Some(5))).is_ok());
+
+        // invalid arguments are errors instead of panics
+        assert!(create(ScalarValue::Int32(Some(-1))).is_err());
+        assert!(create(ScalarValue::Int32(None)).is_err());
+        assert!(create(ScalarValue::Utf8(Some("5".to_string()))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_sum_fail_on_overflow() -> Result<()> {
+        let dt = DataType::Decimal128(38, 0);
+        let schema = Arc::new(Schema::new(vec![Field::new("v", dt.clone(), true)]));
+        let large = 9 * 10i128.pow(37);
+        let args: Vec<ArrayRef> = vec![Arc::new(
+            Decimal128Array::from(vec![large, large]).with_data_type(dt.clone()),
+        )];
+
+        for fail_on_overflow in [false, true] {
+            let agg = create_agg_with_fail_on_overflow(
+                AggFunction::Sum,
+                &[Arc::new(Column::new("v", 0))],
+                &schema,
+                dt.clone(),
+                fail_on_overflow,
+            )?;
+            let partial_args = agg.prepare_partial_args(&args)?;
+            let mut accs = agg.create_acc_column(1);
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(0),
+                &partial_args,
+                IdxSelection::Range(0, 2),
+            )?;
+            let result = agg.final_merge(&mut accs, IdxSelection::Single(0));
+            if fail_on_overflow {
+                assert!(result.is_err());
+            } else {
+                assert!(result?.is_null(0));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_struct_agg() -> Result<()> {
+        let child = Arc::new(Column::new("a", 0));
+        let create_inner_aggs = || -> Result<Vec<Arc<dyn Agg>>> {
+            Ok(vec![
+                Arc::new(AggSum::try_new(child.clone(), DataType::Int64)?),
+                Arc::new(AggCount::try_new(vec![child.clone()], DataType::Int64)?),
+            ])
+        };
+        let inner_aggs = create_inner_aggs()?;
+        let fields = vec![
+            Field::new("s", DataType::Int64, inner_aggs[0].nullable()),
+            Field::new("c", DataType::Int64, inner_aggs[1].nullable()),
+        ];
+        let return_type = DataType::Struct(fields.clone().into());
+        let struct_agg = create_struct_agg(inner_aggs, return_type.clone())?;
+        assert_eq!(struct_agg.data_type(), &return_type);
+
+        // mismatched or non-struct return types are errors
+        let mismatched_type = DataType::Struct(
+            vec![
+                fields[0].clone().with_data_type(DataType::Float64),
+                fields[1].clone(),
+            ]
+            .into(),
+        );
+        assert!(create_struct_agg(create_inner_aggs()?, mismatched_type).is_err());
+        assert!(create_struct_agg(create_inner_aggs()?, DataType::Int64).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_run_lengths() {
+        assert_eq!(IdxSelection::Single(5).to_run_lengths(), vec![(5, 1)]);
+        assert_eq!(IdxSelection::Range(3, 10).to_run_lengths(), vec![(3, 7)]);
+        assert_eq!(IdxSelection::Range(3, 3).to_run_lengths(), vec![]);
+        assert_eq!(
+            IdxSelection::Indices(&[0, 1, 2, 5, 6, 9, 8]).to_run_lengths(),
+            vec![(0, 3), (5, 2), (9, 1), (8, 1)],
+        );
+        assert_eq!(
+            IdxSelection::IndicesU32(&[7, 8, 9, 10]).to_run_lengths(),
+            vec![(7, 4)],
+        );
+    }
 }
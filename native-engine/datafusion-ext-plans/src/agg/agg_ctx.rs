@@ -19,7 +19,8 @@ use std::{
 };
 
 use arrow::{
-    array::{ArrayRef, BinaryArray, RecordBatchOptions},
+    array::{Array, ArrayRef, BinaryArray, RecordBatchOptions},
+    buffer::BooleanBuffer,
     datatypes::{DataType, Field, Fields, Schema, SchemaRef},
     record_batch::RecordBatch,
     row::{RowConverter, Rows, SortField},
@@ -29,18 +30,20 @@ use blaze_jni_bridge::{
     conf::{BooleanConf, DoubleConf, IntConf},
 };
 use datafusion::{
-    common::{cast::as_binary_array, Result},
-    physical_expr::PhysicalExprRef,
+    common::{cast::as_binary_array, Result, Statistics},
+    physical_expr::{expressions::Column, PhysicalExprRef},
 };
-use datafusion_ext_commons::{downcast_any, suggested_batch_mem_size};
+use datafusion_ext_commons::{df_execution_err, downcast_any, suggested_batch_mem_size};
+use datafusion_ext_exprs::spark_random::is_partition_dependent;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 
 use crate::{
     agg::{
         acc::AccTable,
-        agg::{Agg, IdxSelection},
+        agg::{parallel_partial_update, Agg, IdxSelection},
         agg_hash_map::AggHashMapKey,
+        grouping_dict::{dict_encoded_type, StringDictionaryBuilder},
         spark_udaf_wrapper::{AccUDAFBufferRowsColumn, SparkUDAFMemTracker, SparkUDAFWrapper},
         AggExecMode, AggExpr, AggMode, GroupingExpr, AGG_BUF_COLUMN_NAME,
     },
@@ -60,6 +63,7 @@ pub struct AggContext {
 
     pub output_schema: SchemaRef,
     pub grouping_row_converter: Arc<Mutex<RowConverter>>,
+    pub grouping_dict_builders: Option<Mutex<Vec<Option<StringDictionaryBuilder>>>>,
     pub groupings: Vec<GroupingExpr>,
     pub aggs: Vec<AggExpr>,
     pub supports_partial_skipping: bool,
@@ -67,11 +71,51 @@ pub struct AggContext {
     pub partial_skipping_min_rows: usize,
     pub partial_skipping_skip_spill: bool,
     pub is_expand_agg: bool,
+    pub spill_pre_merge: bool,
+    pub spill_append_runs: bool,
     pub agg_expr_evaluator: CachedExprsEvaluator,
     pub num_spill_buckets: OnceCell<usize>,
     pub udaf_mem_tracker: OnceCell<SparkUDAFMemTracker>,
 }
 
+/// per-operator scratch space for per-batch index vectors. the vectors are
+/// cleared but not freed between batches, so no allocations are needed after
+/// warmup.
+#[derive(Default)]
+pub struct AggScratch {
+    pub record_indices: Vec<u32>,
+    pub non_null_record_indices: Vec<u32>,
+    pub udaf_zipped_indices: Vec<i64>,
+
+    /// peak memory of the per-chunk accumulators of parallel_partial_update()
+    pub parallel_accs_mem_used: usize,
+}
+
+/// returns rows whose grouping keys are all null, these rows belong to the
+/// null group. returns None if there are no such rows.
+pub fn null_group_rows(grouping_arrays: &[ArrayRef]) -> Option<BooleanBuffer> {
+    let mut null_rows: Option<BooleanBuffer> = None;
+    for array in grouping_arrays {
+        let is_null = !array.logical_nulls()?.inner();
+        null_rows = Some(match null_rows {
+            Some(null_rows) => &null_rows & &is_null,
+            None => is_null,
+        });
+    }
+    null_rows.filter(|null_rows| null_rows.count_set_bits() > 0)
+}
+
+fn contains_map_type(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Map(..) => true,
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            contains_map_type(field.data_type())
+        }
+        DataType::Struct(fields) => fields.iter().any(|f| contains_map_type(f.data_type())),
+        _ => false,
+    }
+}
+
 impl Debug for AggContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "[groupings={:?}, aggs={:?}]", self.groupings, self.aggs,)
@@ -87,6 +131,18 @@ impl AggContext {
         supports_partial_skipping: bool,
         is_expand_agg: bool,
     ) -> Result<Self> {
+        // same as spark, map types are not orderable and cannot be grouped
+        for grouping in &groupings {
+            let data_type = grouping.expr.data_type(&input_schema)?;
+            if contains_map_type(&data_type) {
+                df_execution_err!(
+                    "expression {} cannot be used as a grouping expression because its data type \
+                     {data_type} is not an orderable data type",
+                    grouping.expr,
+                )?;
+            }
+        }
+
         let grouping_schema = Arc::new(Schema::new(
             groupings
                 .iter()
@@ -182,6 +238,23 @@ impl AggContext {
             } else {
                 Default::default()
             };
+        let dict_encode_string_keys = conf::AGG_DICT_ENCODE_STRING_KEYS.value().unwrap_or(false);
+
+        // udaf buffers are managed by the udaf mem tracker and are spilled
+        // as they are
+        let udafs = aggs
+            .iter()
+            .filter_map(|agg| downcast_any!(agg.agg, SparkUDAFWrapper).ok())
+            .collect::<Vec<_>>();
+        let spill_pre_merge =
+            conf::AGG_SPILL_PRE_MERGE_ENABLE.value().unwrap_or(true) && udafs.is_empty();
+        if !udafs.is_empty() {
+            let udaf_spill_checksum = conf::UDAF_SPILL_CHECKSUM_ENABLE.value().unwrap_or(false);
+            udafs
+                .iter()
+                .for_each(|udaf| udaf.set_spill_checksum(udaf_spill_checksum));
+        }
+        let spill_append_runs = conf::AGG_SPILL_APPEND_RUNS_ENABLE.value().unwrap_or(false);
 
         Ok(Self {
             exec_mode,
@@ -192,6 +265,7 @@ impl AggContext {
             need_partial_merge_aggs,
             output_schema,
             grouping_row_converter,
+            grouping_dict_builders: None,
             groupings,
             aggs,
             agg_expr_evaluator,
@@ -200,9 +274,117 @@ impl AggContext {
             partial_skipping_min_rows,
             partial_skipping_skip_spill,
             is_expand_agg,
+            spill_pre_merge,
+            spill_append_runs,
             num_spill_buckets: Default::default(),
             udaf_mem_tracker: Default::default(),
-        })
+        }
+        .with_dict_encoded_string_keys(dict_encode_string_keys))
+    }
+
+    /// returns the context with partition-dependent exprs bound to the
+    /// partition of exec_ctx, or itself if there are no such exprs
+    pub fn bind_partition(
+        self: &Arc<Self>,
+        input_schema: SchemaRef,
+        exec_ctx: &ExecutionContext,
+    ) -> Result<Arc<Self>> {
+        let groupings_dependent = self
+            .groupings
+            .iter()
+            .any(|grouping| is_partition_dependent(&grouping.expr));
+        let aggs_dependent = self
+            .aggs
+            .iter()
+            .flat_map(|agg| agg.agg.exprs())
+            .any(|expr| is_partition_dependent(&expr));
+        if !groupings_dependent && !aggs_dependent {
+            return Ok(self.clone());
+        }
+
+        let groupings = self
+            .groupings
+            .iter()
+            .map(|grouping| {
+                Ok(GroupingExpr {
+                    field_name: grouping.field_name.clone(),
+                    expr: exec_ctx.bind_partition(&grouping.expr)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let aggs = self
+            .aggs
+            .iter()
+            .map(|agg| {
+                Ok(AggExpr {
+                    field_name: agg.field_name.clone(),
+                    mode: agg.mode,
+                    agg: agg
+                        .agg
+                        .with_new_exprs(exec_ctx.bind_partition_exprs(&agg.agg.exprs())?)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Arc::new(Self::try_new(
+            self.exec_mode,
+            input_schema,
+            groupings,
+            aggs,
+            self.supports_partial_skipping,
+            self.is_expand_agg,
+        )?))
+    }
+
+    /// emits string grouping columns dictionary-encoded, with one dictionary
+    /// per output batch. dictionaries are decoded to plain strings when
+    /// batches are serialized or exported to the jvm.
+    pub fn with_dict_encoded_string_keys(mut self, enabled: bool) -> Self {
+        let num_groupings = self.groupings.len();
+        let output_schema = self.output_schema.clone();
+        let fields = output_schema.fields();
+        let is_string_key = |i: usize| fields[i].data_type() == &DataType::Utf8;
+        if !enabled || !(0..num_groupings).any(is_string_key) {
+            return self;
+        }
+
+        self.grouping_dict_builders = Some(Mutex::new(
+            (0..num_groupings)
+                .map(|i| is_string_key(i).then(StringDictionaryBuilder::default))
+                .collect(),
+        ));
+        self.output_schema = Arc::new(Schema::new(
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    if i < num_groupings {
+                        let data_type = dict_encoded_type(field.data_type());
+                        Arc::new(field.as_ref().clone().with_data_type(data_type))
+                    } else {
+                        field.clone()
+                    }
+                })
+                .collect::<Fields>(),
+        ));
+        self
+    }
+
+    pub fn encode_grouping_columns(
+        &self,
+        grouping_columns: Vec<ArrayRef>,
+    ) -> Result<Vec<ArrayRef>> {
+        let Some(grouping_dict_builders) = &self.grouping_dict_builders else {
+            return Ok(grouping_columns);
+        };
+        let mut grouping_dict_builders = grouping_dict_builders.lock();
+        grouping_columns
+            .into_iter()
+            .zip(grouping_dict_builders.iter_mut())
+            .map(|(col, builder)| match builder {
+                Some(builder) => builder.encode(&col),
+                None => Ok(col),
+            })
+            .collect()
     }
 
     pub fn create_acc_table(&self, num_rows: usize) -> AccTable {
@@ -215,18 +397,35 @@ impl AggContext {
         )
     }
 
+    pub fn create_hashing_acc_table(&self, num_rows: usize) -> AccTable {
+        AccTable::new(
+            self.aggs
+                .iter()
+                .map(|agg| agg.agg.create_hashing_acc_column(num_rows))
+                .collect(),
+            num_rows,
+        )
+    }
+
     pub fn create_grouping_rows(&self, input_batch: &RecordBatch) -> Result<Rows> {
-        let grouping_arrays: Vec<ArrayRef> = self
-            .groupings
+        let grouping_arrays = self.evaluate_grouping_arrays(input_batch)?;
+        self.convert_grouping_arrays(&grouping_arrays)
+    }
+
+    pub fn evaluate_grouping_arrays(&self, input_batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+        self.groupings
             .iter()
             .map(|grouping| grouping.expr.evaluate(&input_batch))
             .map(|r| r.and_then(|columnar| columnar.into_array(input_batch.num_rows())))
             .collect::<Result<_>>()
-            .map_err(|err| err.context("agg: evaluating grouping arrays error"))?;
+            .map_err(|err| err.context("agg: evaluating grouping arrays error"))
+    }
+
+    pub fn convert_grouping_arrays(&self, grouping_arrays: &[ArrayRef]) -> Result<Rows> {
         Ok(self
             .grouping_row_converter
             .lock()
-            .convert_columns(&grouping_arrays)?)
+            .convert_columns(grouping_arrays)?)
     }
 
     pub fn update_batch_to_acc_table(
@@ -234,8 +433,16 @@ impl AggContext {
         batch: &RecordBatch,
         acc_table: &mut AccTable,
         acc_idx: IdxSelection,
+        scratch: &mut AggScratch,
     ) -> Result<()> {
-        self.update_batch_slice_to_acc_table(batch, 0, batch.num_rows(), acc_table, acc_idx)
+        self.update_batch_slice_to_acc_table(
+            batch,
+            0,
+            batch.num_rows(),
+            acc_table,
+            acc_idx,
+            scratch,
+        )
     }
 
     pub fn update_batch_slice_to_acc_table(
@@ -245,6 +452,7 @@ impl AggContext {
         batch_end_idx: usize,
         acc_table: &mut AccTable,
         acc_idx: IdxSelection,
+        scratch: &mut AggScratch,
     ) -> Result<()> {
         // NOTE:
         // arrow-ffi with sliced batch is buggy in older arrow-java, so we use unsliced
@@ -268,7 +476,7 @@ impl AggContext {
                 }
             }
             let batch_selection = IdxSelection::Range(batch_start_idx, batch_end_idx);
-            self.partial_update(acc_table, acc_idx, &input_arrays, batch_selection)?;
+            self.partial_update(acc_table, acc_idx, &input_arrays, batch_selection, scratch)?;
         }
 
         // partial merge
@@ -294,7 +502,13 @@ impl AggContext {
                 }
             }
             let batch_selection = IdxSelection::Range(0, batch_end_idx - batch_start_idx);
-            self.partial_merge(acc_table, acc_idx, &mut merging_acc_table, batch_selection)?;
+            self.partial_merge(
+                acc_table,
+                acc_idx,
+                &mut merging_acc_table,
+                batch_selection,
+                scratch,
+            )?;
         }
         Ok(())
     }
@@ -336,6 +550,7 @@ impl AggContext {
             keys.iter()
                 .map(|key| grouping_row_parser.parse(key.as_ref())),
         )?;
+        let grouping_columns = self.encode_grouping_columns(grouping_columns)?;
         let agg_columns = self.build_agg_columns(acc_table, acc_idx)?;
 
         // at least one column exists
@@ -351,6 +566,7 @@ impl AggContext {
         acc_idx: IdxSelection,
         input_arrays: &[Vec<ArrayRef>],
         input_idx: IdxSelection,
+        scratch: &mut AggScratch,
     ) -> Result<()> {
         if self.need_partial_update {
             let udaf_indices_cache = OnceCell::new();
@@ -364,9 +580,18 @@ impl AggContext {
                         &input_arrays[*agg_idx],
                         input_idx,
                         &udaf_indices_cache,
+                        &mut scratch.udaf_zipped_indices,
                     )?;
                 } else {
-                    agg.partial_update(acc_col, acc_idx, &input_arrays[*agg_idx], input_idx)?;
+                    let chunk_accs_mem_used = parallel_partial_update(
+                        agg,
+                        acc_col,
+                        acc_idx,
+                        &input_arrays[*agg_idx],
+                        input_idx,
+                    )?;
+                    scratch.parallel_accs_mem_used =
+                        scratch.parallel_accs_mem_used.max(chunk_accs_mem_used);
                 }
             }
         }
@@ -379,6 +604,7 @@ impl AggContext {
         acc_idx: IdxSelection,
         merging_acc_table: &mut AccTable,
         merging_acc_idx: IdxSelection,
+        scratch: &mut AggScratch,
     ) -> Result<()> {
         if self.need_partial_merge {
             let udaf_indices_cache = OnceCell::new();
@@ -394,6 +620,7 @@ impl AggContext {
                         merging_acc_col,
                         merging_acc_idx,
                         &udaf_indices_cache,
+                        &mut scratch.udaf_zipped_indices,
                     )?;
                 } else {
                     agg.partial_merge(acc_col, acc_idx, merging_acc_col, merging_acc_idx)?;
@@ -409,7 +636,14 @@ impl AggContext {
         acc_idx: IdxSelection,
     ) -> Result<Vec<Vec<u8>>> {
         let udaf_indices_cache = OnceCell::new();
-        let mut vec = vec![vec![]; acc_idx.len()];
+        let row_capacity = self
+            .aggs
+            .iter()
+            .map(|agg| agg.agg.expected_partial_state_size_bytes_per_group())
+            .sum::<usize>();
+        let mut vec = (0..acc_idx.len())
+            .map(|_| Vec::with_capacity(row_capacity))
+            .collect::<Vec<_>>();
         for acc_col in acc_table.cols() {
             if let Ok(udaf_acc_col) = downcast_any!(acc_col, AccUDAFBufferRowsColumn) {
                 udaf_acc_col.freeze_to_rows_with_indices_cache(
@@ -436,6 +670,7 @@ impl AggContext {
             &batch,
             &mut acc_table,
             IdxSelection::Range(0, batch_num_rows),
+            &mut AggScratch::default(),
         )?;
 
         // create output batch
@@ -445,6 +680,7 @@ impl AggContext {
             .map(|grouping| grouping.expr.evaluate(&batch))
             .map(|r| r.and_then(|columnar| columnar.into_array(batch_num_rows)))
             .collect::<Result<Vec<ArrayRef>>>()?;
+        let grouping_columns = self.encode_grouping_columns(grouping_columns)?;
         let agg_columns =
             self.build_agg_columns(&mut acc_table, IdxSelection::Range(0, batch_num_rows))?;
         let output_batch = RecordBatch::try_new_with_options(
@@ -460,6 +696,22 @@ impl AggContext {
         return Ok(());
     }
 
+    /// estimates the number of groups from input statistics. grouping by
+    /// columns with known distinct counts produces at most the product of
+    /// their distinct counts (plus one for the null group of each column),
+    /// and never more than the number of input rows.
+    pub fn estimated_num_groups(&self, input_statistics: &Statistics) -> Option<usize> {
+        let num_rows = *input_statistics.num_rows.get_value()?;
+        let mut num_groups = 1usize;
+        for grouping in &self.groupings {
+            let column = grouping.expr.as_any().downcast_ref::<Column>()?;
+            let column_statistics = input_statistics.column_statistics.get(column.index())?;
+            let distinct_count = *column_statistics.distinct_count.get_value()?;
+            num_groups = num_groups.saturating_mul(distinct_count + 1);
+        }
+        Some(num_groups.min(num_rows))
+    }
+
     pub fn num_spill_buckets(&self, mem_size: usize) -> usize {
         *self
             .num_spill_buckets
@@ -475,3 +727,49 @@ impl AggContext {
             .get_or_try_init(|| SparkUDAFMemTracker::try_new())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::datatypes::{DataType, Field, Fields, Schema};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::agg::{agg_ctx::AggContext, AggExecMode, GroupingExpr};
+
+    #[test]
+    fn test_grouping_by_map_type() -> Result<()> {
+        let entries = Field::new(
+            "entries",
+            DataType::Struct(Fields::from(vec![
+                Field::new("key", DataType::Utf8, false),
+                Field::new("value", DataType::Int64, true),
+            ])),
+            false,
+        );
+        let map_type = DataType::Map(Arc::new(entries), false);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("m", map_type.clone(), true),
+            Field::new("l", DataType::new_list(map_type, true), true),
+        ]));
+
+        for (name, idx) in [("m", 0), ("l", 1)] {
+            let err = AggContext::try_new(
+                AggExecMode::HashAgg,
+                schema.clone(),
+                vec![GroupingExpr {
+                    field_name: name.to_string(),
+                    expr: Arc::new(Column::new(name, idx)),
+                }],
+                vec![],
+                false,
+                false,
+            )
+            .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("cannot be used as a grouping expression"));
+        }
+        Ok(())
+    }
+}
@@ -14,13 +14,17 @@
 
 use std::{
     hash::BuildHasher,
+    mem::ManuallyDrop,
     simd::{cmp::SimdPartialEq, Simd},
 };
 
-use datafusion_ext_commons::{likely, prefetch_write_data, unchecked};
+use datafusion::common::Result;
+use datafusion_ext_commons::{
+    arrow::eq_comparator::bytes_eq, likely, prefetch_write_data, unchecked,
+};
 use unchecked_index::UncheckedIndex;
 
-use crate::agg::agg_table::OwnedKey;
+use crate::agg::{acc::compact_items, agg_table::OwnedKey};
 
 const MAP_VALUE_GROUP_SIZE: usize = 8;
 
@@ -35,8 +39,7 @@ const _MAP_VALUE_GROUP_SIZE_CHECKER: [(); 64] = [(); size_of::<MapValueGroup>()]
 struct Table {
     pub map: UncheckedIndex<Vec<MapValueGroup>>,
     pub map_mod_bits: u32,
-    pub key_heap_mem_size: usize,
-    pub keys: UncheckedIndex<Vec<OwnedKey>>,
+    pub len: usize,
 }
 
 impl Default for Table {
@@ -44,26 +47,18 @@ impl Default for Table {
         Self {
             map: unchecked!(vec![]),
             map_mod_bits: 0,
-            key_heap_mem_size: 0,
-            keys: unchecked!(vec![]),
+            len: 0,
         }
     }
 }
 
 impl Table {
-    fn len(&self) -> usize {
-        self.keys.len()
-    }
-
     fn mem_size(&self) -> usize {
-        size_of_val(self)
-            + self.map.capacity() * 2 * size_of::<MapValueGroup>()
-            + self.keys.capacity() * 2 * size_of::<OwnedKey>()
-            + self.key_heap_mem_size
+        self.map.capacity() * 2 * size_of::<MapValueGroup>()
     }
 
     fn reserve(&mut self, num_new_items: usize) {
-        let num_reserved_items = self.len() + num_new_items;
+        let num_reserved_items = self.len + num_new_items;
         let new_map_mod_bits = (num_reserved_items.max(128) * 2 / MAP_VALUE_GROUP_SIZE)
             .next_power_of_two()
             .trailing_zeros();
@@ -73,46 +68,31 @@ impl Table {
         }
     }
 
-    fn upsert_many(&mut self, keys: Vec<impl AggHashMapKey>) -> Vec<u32> {
-        let mut hashes = unchecked!(keys.iter().map(agg_hash).collect::<Vec<_>>());
-        const PREFETCH_AHEAD: usize = 4;
-
-        macro_rules! entries {
-            ($i:expr) => {{
-                (hashes[$i] % (1 << self.map_mod_bits))
-            }};
-        }
-
-        macro_rules! prefetch_at {
-            ($i:expr) => {{
-                if $i < hashes.len() {
-                    prefetch_write_data!(&self.map[entries!($i) as usize]);
-                }
-            }};
-        }
-
-        for i in 0..PREFETCH_AHEAD {
-            prefetch_at!(i);
-        }
+    fn clear(&mut self) {
+        self.map.fill(MapValueGroup::default());
+        self.len = 0;
+    }
 
-        for (i, key) in keys.into_iter().enumerate() {
-            prefetch_at!(i + PREFETCH_AHEAD);
-            hashes[i] = self.upsert_one_impl(key, hashes[i], entries!(i) as usize);
-        }
+    #[inline]
+    fn entries(&self, hash: u32) -> usize {
+        (hash % (1 << self.map_mod_bits)) as usize
+    }
 
-        // safety: transmute to Vec<u32>
-        unsafe { std::mem::transmute(hashes) }
+    #[inline]
+    fn prefetch(&self, hash: u32) {
+        prefetch_write_data!(&self.map[self.entries(hash)]);
     }
 
     #[inline]
-    fn upsert_one_impl(&mut self, key: impl AggHashMapKey, hash: u32, mut entries: usize) -> u32 {
+    fn upsert_one(&mut self, keys: &mut MapKeys, key: impl AggHashMapKey, hash: u32) -> u32 {
         let hashes = Simd::splat(hash);
         let zeros = Simd::splat(0);
+        let mut entries = self.entries(hash);
         loop {
             let mut hash_matched = self.map[entries].hashes.simd_eq(hashes);
             while let Some(i) = hash_matched.first_set() {
                 let record_idx = self.map[entries].values[i] as usize;
-                if likely!(self.keys[record_idx].as_ref() == key.as_bytes()) {
+                if likely!(bytes_eq(keys.keys[record_idx].as_ref(), key.as_bytes())) {
                     return record_idx as u32;
                 }
                 hash_matched.set(i, false);
@@ -120,22 +100,46 @@ impl Table {
 
             let empty = self.map[entries].hashes.simd_eq(zeros);
             if let Some(empty_pos) = empty.first_set() {
-                let record_idx = self.len();
+                let record_idx = keys.push(key.into_owned());
                 self.map[entries].hashes[empty_pos] = hash;
-                self.map[entries].values[empty_pos] = record_idx as u32;
+                self.map[entries].values[empty_pos] = record_idx;
+                self.len += 1;
+                return record_idx;
+            }
+            entries += 1;
+            entries %= 1 << self.map_mod_bits;
+        }
+    }
 
-                let key = key.into_owned();
-                if key.spilled() {
-                    self.key_heap_mem_size += key.len();
-                }
-                self.keys.push(key);
-                return record_idx as u32;
+    /// inserts an entry which is known to be absent from the table
+    #[inline]
+    fn insert_unique(&mut self, hash: u32, value: u32) {
+        let zeros = Simd::splat(0);
+        let mut entries = self.entries(hash);
+        loop {
+            if let Some(empty_pos) = self.map[entries].hashes.simd_eq(zeros).first_set() {
+                self.map[entries].hashes[empty_pos] = hash;
+                self.map[entries].values[empty_pos] = value;
+                self.len += 1;
+                return;
             }
             entries += 1;
             entries %= 1 << self.map_mod_bits;
         }
     }
 
+    fn for_each_entry(&self, mut f: impl FnMut(u32, u32)) {
+        let zeros = Simd::splat(0);
+        for group in self.map.iter() {
+            let non_empty = group.hashes.simd_ne(zeros);
+            for j in 0..MAP_VALUE_GROUP_SIZE {
+                if non_empty.test(j) {
+                    f(group.hashes[j], group.values[j]);
+                }
+            }
+        }
+    }
+
     #[inline]
     fn rehash(&mut self, map_mod_bits: u32) {
         let mut rehashed_map = unchecked!(vec![MapValueGroup::default(); 1 << map_mod_bits]);
@@ -169,6 +173,33 @@ impl Table {
     }
 }
 
+/// keys of all records, indexed by record indices
+struct MapKeys {
+    keys: UncheckedIndex<Vec<OwnedKey>>,
+    heap_mem_size: usize,
+}
+
+impl Default for MapKeys {
+    fn default() -> Self {
+        Self {
+            keys: unchecked!(vec![]),
+            heap_mem_size: 0,
+        }
+    }
+}
+
+impl MapKeys {
+    #[inline]
+    fn push(&mut self, key: OwnedKey) -> u32 {
+        let record_idx = self.keys.len() as u32;
+        if key.spilled() {
+            self.heap_mem_size += key.len();
+        }
+        self.keys.push(key);
+        record_idx
+    }
+}
+
 pub trait AggHashMapKey {
     fn as_bytes(&self) -> &[u8];
     fn into_owned(self) -> OwnedKey;
@@ -193,36 +224,267 @@ impl AggHashMapKey for OwnedKey {
     }
 }
 
-// map<key: bytes, value: u32> where value is the index of accumulators
-#[derive(Default)]
+// map<key: bytes, value: u32> where value is the index of accumulators.
+//
+// huge maps are split into NUM_PARTITIONS sub-tables by the top bits of hashes.
+// rows of each batch are bucketed by sub-tables before upserting, so that only
+// one small sub-table is accessed at a time instead of randomly accessing a
+// table much larger than cpu caches.
 pub struct AggHashMap {
-    map: Table,
+    // one table, or NUM_PARTITIONS sub-tables after partitioned
+    tables: Vec<Table>,
+    keys: MapKeys,
+    partitioning_threshold: usize,
+
+    // rows of the upserting batch bucketed by sub-tables, reused across batches
+    bucketed_rows: Vec<u32>,
+}
+
+const NUM_PARTITIONS: usize = 256;
+const PARTITIONING_NUM_RECORDS_THRESHOLD: usize = 1 << 20;
+
+impl Default for AggHashMap {
+    fn default() -> Self {
+        Self::with_partitioning_threshold(PARTITIONING_NUM_RECORDS_THRESHOLD)
+    }
 }
 
 impl AggHashMap {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut map = Self::default();
+        if capacity > 0 {
+            map.reserve(capacity);
+        }
+        map
+    }
+
+    fn with_partitioning_threshold(partitioning_threshold: usize) -> Self {
+        Self {
+            tables: vec![Table::default()],
+            keys: MapKeys::default(),
+            partitioning_threshold,
+            bucketed_rows: vec![],
+        }
+    }
+
     pub fn len(&self) -> usize {
-        self.map.len()
+        self.keys.keys.len()
     }
 
     pub fn mem_size(&self) -> usize {
-        self.map.mem_size()
+        size_of_val(self)
+            + self
+                .tables
+                .iter()
+                .map(|table| table.mem_size())
+                .sum::<usize>()
+            + self.tables.capacity() * size_of::<Table>()
+            + self.keys.keys.capacity() * 2 * size_of::<OwnedKey>()
+            + self.keys.heap_mem_size
+            + self.bucketed_rows.capacity() * size_of::<u32>()
+    }
+
+    /// keys of all records, indexed by record indices
+    pub fn keys(&self) -> &[OwnedKey] {
+        &self.keys.keys
+    }
+
+    /// calls `f` with (hash, record_idx) entries of each sub-table in order.
+    /// sub-tables are partitioned by the top bits of hashes, so (ignoring the
+    /// always-set highest bit) hashes in a sub-table are all smaller than
+    /// hashes in the following sub-tables.
+    pub fn for_each_sub_table_entries(
+        &self,
+        mut f: impl FnMut(&mut Vec<(u32, u32)>) -> Result<()>,
+    ) -> Result<()> {
+        let mut entries = vec![];
+        for table in &self.tables {
+            entries.clear();
+            table.for_each_entry(|hash, record_idx| entries.push((hash, record_idx)));
+            f(&mut entries)?;
+        }
+        Ok(())
     }
 
     pub fn upsert_records(&mut self, keys: Vec<impl AggHashMapKey>) -> Vec<u32> {
-        self.map.reserve(keys.len());
-        self.map.upsert_many(keys)
+        let mut record_indices = vec![];
+        self.upsert_records_into(keys, &mut record_indices);
+        record_indices
+    }
+
+    /// like `upsert_records`, but writes record indices into a reused vector
+    pub fn upsert_records_into(
+        &mut self,
+        keys: Vec<impl AggHashMapKey>,
+        record_indices: &mut Vec<u32>,
+    ) {
+        self.reserve(keys.len());
+        if self.is_partitioned() {
+            self.upsert_many_partitioned(keys, record_indices);
+        } else {
+            self.upsert_many(keys, record_indices);
+        }
     }
 
     pub fn take_keys(&mut self) -> Vec<OwnedKey> {
-        self.map.map.fill(MapValueGroup::default());
-        self.map.key_heap_mem_size = 0;
-        std::mem::take(&mut *self.map.keys)
+        for table in &mut self.tables {
+            table.clear();
+        }
+        self.keys.heap_mem_size = 0;
+        std::mem::take(&mut *self.keys.keys)
+    }
+
+    /// keeps only the records at `retained` (in increasing order), `remap`
+    /// maps old record indices to new ones (None for removed records)
+    pub fn compact(&mut self, retained: &[usize], remap: &[Option<usize>]) {
+        let mut entries = vec![];
+        for table in &mut self.tables {
+            entries.clear();
+            table.for_each_entry(|hash, record_idx| {
+                if let Some(new_idx) = remap[record_idx as usize] {
+                    entries.push((hash, new_idx as u32));
+                }
+            });
+            table.clear();
+            for &(hash, new_idx) in &entries {
+                table.insert_unique(hash, new_idx);
+            }
+        }
+        compact_items(&mut self.keys.keys, retained);
+        self.keys.keys.truncate(retained.len());
+        self.keys.heap_mem_size = self
+            .keys
+            .keys
+            .iter()
+            .filter(|key| key.spilled())
+            .map(|key| key.len())
+            .sum();
     }
 
     pub fn into_keys(self) -> Vec<OwnedKey> {
-        let mut keys = self.map.keys;
+        let mut keys = self.keys.keys;
         std::mem::take(&mut keys)
     }
+
+    fn is_partitioned(&self) -> bool {
+        self.tables.len() > 1
+    }
+
+    fn reserve(&mut self, num_new_items: usize) {
+        if !self.is_partitioned() {
+            if self.len() + num_new_items <= self.partitioning_threshold {
+                self.tables[0].reserve(num_new_items);
+                return;
+            }
+            self.partition();
+        }
+        // sub-tables are reserved with number of bucketed rows when upserting
+    }
+
+    fn partition(&mut self) {
+        let table = std::mem::take(&mut self.tables[0]);
+        let mut partition_sizes = [0; NUM_PARTITIONS];
+        table.for_each_entry(|hash, _| partition_sizes[partition_of(hash)] += 1);
+
+        self.tables = partition_sizes
+            .iter()
+            .map(|&partition_size| {
+                let mut sub_table = Table::default();
+                sub_table.reserve(partition_size);
+                sub_table
+            })
+            .collect();
+        table.for_each_entry(|hash, value| {
+            self.tables[partition_of(hash)].insert_unique(hash, value);
+        });
+    }
+
+    fn upsert_many(&mut self, keys: Vec<impl AggHashMapKey>, record_indices: &mut Vec<u32>) {
+        // hashes are computed in place and later replaced with record indices
+        record_indices.clear();
+        record_indices.extend(keys.iter().map(agg_hash));
+        let mut hashes = unchecked!(&mut record_indices[..]);
+        let table = &mut self.tables[0];
+        const PREFETCH_AHEAD: usize = 4;
+
+        for i in 0..PREFETCH_AHEAD.min(hashes.len()) {
+            table.prefetch(hashes[i]);
+        }
+
+        for (i, key) in keys.into_iter().enumerate() {
+            if i + PREFETCH_AHEAD < hashes.len() {
+                table.prefetch(hashes[i + PREFETCH_AHEAD]);
+            }
+            hashes[i] = table.upsert_one(&mut self.keys, key, hashes[i]);
+        }
+    }
+
+    fn upsert_many_partitioned(
+        &mut self,
+        keys: Vec<impl AggHashMapKey>,
+        record_indices: &mut Vec<u32>,
+    ) {
+        // hashes are computed in place and later replaced with record indices
+        record_indices.clear();
+        record_indices.extend(keys.iter().map(agg_hash));
+        let mut hashes = unchecked!(&mut record_indices[..]);
+        const PREFETCH_AHEAD: usize = 4;
+
+        // bucket rows by sub-tables
+        let mut offsets = [0; NUM_PARTITIONS + 1];
+        for &hash in hashes.iter() {
+            offsets[partition_of(hash) + 1] += 1;
+        }
+        for p in 0..NUM_PARTITIONS {
+            offsets[p + 1] += offsets[p];
+        }
+        let mut bucketed_rows = std::mem::take(&mut self.bucketed_rows);
+        bucketed_rows.resize(hashes.len(), 0);
+        let mut next_offsets = offsets;
+        for (row, &hash) in hashes.iter().enumerate() {
+            let p = partition_of(hash);
+            bucketed_rows[next_offsets[p]] = row as u32;
+            next_offsets[p] += 1;
+        }
+
+        // keys are moved out in bucketed order, each of them exactly once
+        let mut keys = ManuallyDrop::new(keys);
+        for p in 0..NUM_PARTITIONS {
+            let rows = &bucketed_rows[offsets[p]..offsets[p + 1]];
+            if rows.is_empty() {
+                continue;
+            }
+            let table = &mut self.tables[p];
+            table.reserve(rows.len());
+
+            for &row in rows.iter().take(PREFETCH_AHEAD) {
+                table.prefetch(hashes[row as usize]);
+            }
+            for (i, &row) in rows.iter().enumerate() {
+                if i + PREFETCH_AHEAD < rows.len() {
+                    table.prefetch(hashes[rows[i + PREFETCH_AHEAD] as usize]);
+                }
+                let row = row as usize;
+                // safety: bucketed rows are a permutation of all rows
+                let key = unsafe { std::ptr::read(&keys[row]) };
+                hashes[row] = table.upsert_one(&mut self.keys, key, hashes[row]);
+            }
+        }
+
+        // safety: all keys have been moved out, only free the buffer
+        unsafe {
+            keys.set_len(0);
+            ManuallyDrop::drop(&mut keys);
+        }
+        self.bucketed_rows = bucketed_rows;
+    }
+}
+
+/// sub-table of a hash, taken from the top bits except the highest one,
+/// which is always set. low bits are used for locating entries in the tables.
+#[inline]
+fn partition_of(hash: u32) -> usize {
+    (hash >> 23) as usize % NUM_PARTITIONS
 }
 
 #[inline]
@@ -233,3 +495,166 @@ pub fn agg_hash(value: &impl AggHashMapKey) -> u32 {
         foldhash::fast::FixedState::with_seed(AGG_HASH_SEED_HASHING as u64);
     HASHER.hash_one(value.as_bytes()) as u32 | 0x80000000
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, time::Instant};
+
+    use datafusion::common::Result;
+
+    use crate::agg::agg_hash_map::AggHashMap;
+
+    fn upsert_batches(map: &mut AggHashMap, batches: &[Vec<Vec<u8>>]) -> Vec<Vec<u32>> {
+        batches
+            .iter()
+            .map(|batch| map.upsert_records(batch.iter().map(|key| key.as_slice()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_partitioned_upsert() {
+        // 20000 distinct keys in batches of 3000 rows, with duplicates
+        let batches = (0..10)
+            .map(|b| {
+                (0..3000)
+                    .map(|i| format!("key-{}", (b * 2000 + i) % 20000).into_bytes())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut single = AggHashMap::with_partitioning_threshold(usize::MAX);
+        let mut partitioned = AggHashMap::with_partitioning_threshold(5000);
+        let single_indices = upsert_batches(&mut single, &batches);
+        let partitioned_indices = upsert_batches(&mut partitioned, &batches);
+        assert!(!single.is_partitioned());
+        assert!(partitioned.is_partitioned());
+        assert_eq!(single.len(), 20000);
+        assert_eq!(partitioned.len(), 20000);
+
+        // same keys are mapped to the same records
+        for (map, indices) in [(single, single_indices), (partitioned, partitioned_indices)] {
+            let keys = map.into_keys();
+            let mut key_to_idx = HashMap::new();
+            for (batch, batch_indices) in batches.iter().zip(&indices) {
+                for (key, &idx) in batch.iter().zip(batch_indices) {
+                    assert_eq!(keys[idx as usize].as_ref(), key.as_slice());
+                    assert_eq!(*key_to_idx.entry(key.clone()).or_insert(idx), idx);
+                }
+            }
+            assert_eq!(key_to_idx.len(), 20000);
+        }
+    }
+
+    #[test]
+    fn test_partitioned_take_keys() {
+        let mut map = AggHashMap::with_partitioning_threshold(100);
+        let keys = (0..1000)
+            .map(|i| format!("{i}").into_bytes())
+            .collect::<Vec<_>>();
+        map.upsert_records(keys.iter().map(|key| key.as_slice()).collect());
+        assert!(map.is_partitioned());
+
+        let taken = map.take_keys();
+        assert_eq!(taken.len(), 1000);
+        assert_eq!(map.len(), 0);
+
+        // the map is reusable after keys are taken, new records are numbered
+        // from zero while rows are upserted in order of sub-tables
+        let indices = map.upsert_records(keys[..10].iter().map(|key| key.as_slice()).collect());
+        let mut sorted_indices = indices.clone();
+        sorted_indices.sort();
+        assert_eq!(sorted_indices, (0..10).collect::<Vec<u32>>());
+        let taken = map.take_keys();
+        for (key, &idx) in keys[..10].iter().zip(&indices) {
+            assert_eq!(taken[idx as usize].as_ref(), key.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut map = AggHashMap::with_partitioning_threshold(100);
+        let keys = (0..1000)
+            .map(|i| format!("{i}").into_bytes())
+            .collect::<Vec<_>>();
+        let indices = map.upsert_records(keys.iter().map(|key| key.as_slice()).collect());
+        assert!(map.is_partitioned());
+
+        // remove even records
+        let retained = (0..1000).filter(|i| i % 2 == 1).collect::<Vec<_>>();
+        let remap = (0..1000)
+            .map(|i| (i % 2 == 1).then_some(i / 2))
+            .collect::<Vec<_>>();
+        map.compact(&retained, &remap);
+        assert_eq!(map.len(), 500);
+
+        // retained keys are found at their new indices, removed keys are
+        // inserted as new records
+        let new_indices = map.upsert_records(keys.iter().map(|key| key.as_slice()).collect());
+        for (i, (&idx, &new_idx)) in indices.iter().zip(&new_indices).enumerate() {
+            match remap[idx as usize] {
+                Some(remapped_idx) => assert_eq!(new_idx as usize, remapped_idx),
+                None => assert!(new_idx >= 500),
+            }
+            assert_eq!(map.keys()[new_idx as usize].as_ref(), keys[i].as_slice());
+        }
+        assert_eq!(map.len(), 1000);
+    }
+
+    #[test]
+    fn test_sub_table_entries_ordered_by_hash() -> Result<()> {
+        let mut map = AggHashMap::with_partitioning_threshold(100);
+        let keys = (0..10000)
+            .map(|i| format!("{i}").into_bytes())
+            .collect::<Vec<_>>();
+        map.upsert_records(keys.iter().map(|key| key.as_slice()).collect());
+        assert!(map.is_partitioned());
+
+        // hash ranges of sub-tables never overlap
+        let mut num_entries = 0;
+        let mut max_prev_hash = 0;
+        map.for_each_sub_table_entries(|entries| {
+            num_entries += entries.len();
+            if let Some(min_hash) = entries.iter().map(|&(hash, _)| hash & 0x7fffffff).min() {
+                assert!(min_hash >= max_prev_hash);
+                max_prev_hash = entries
+                    .iter()
+                    .map(|&(hash, _)| hash & 0x7fffffff)
+                    .max()
+                    .unwrap();
+            }
+            Ok(())
+        })?;
+        assert_eq!(num_entries, 10000);
+        Ok(())
+    }
+
+    #[test]
+    fn bench_partitioned_upsert() {
+        // 4M distinct keys in batches of 10000 rows, each key upserted twice
+        let num_keys = 1 << 22;
+        let batch_size = 10000;
+        let keys = (0..num_keys)
+            .map(|i: u64| i.wrapping_mul(0x9E3779B97F4A7C15).to_le_bytes())
+            .collect::<Vec<_>>();
+
+        for partitioning_threshold in [usize::MAX, 1 << 20] {
+            let mut map = AggHashMap::with_partitioning_threshold(partitioning_threshold);
+            let mut record_indices = vec![];
+            let time_start = Instant::now();
+            for _ in 0..2 {
+                for batch in keys.chunks(batch_size) {
+                    map.upsert_records_into(
+                        batch.iter().map(|key| key.as_slice()).collect(),
+                        &mut record_indices,
+                    );
+                }
+            }
+            assert_eq!(map.len(), num_keys as usize);
+            eprintln!(
+                "agg_hash_map_upsert_time(partitioned={}): {:?}",
+                map.is_partitioned(),
+                time_start.elapsed()
+            );
+        }
+    }
+}
@@ -13,12 +13,11 @@
 // limitations under the License.
 
 use std::{
-    hash::BuildHasher,
     io::Write,
     sync::{Arc, Weak},
 };
 
-use arrow::{record_batch::RecordBatch, row::Rows};
+use arrow::{buffer::BooleanBuffer, record_batch::RecordBatch, row::Rows};
 use async_trait::async_trait;
 use bytesize::ByteSize;
 use datafusion::{
@@ -41,10 +40,10 @@ use smallvec::SmallVec;
 
 use crate::{
     agg::{
-        acc::AccTable,
+        acc::{retained_records, AccTable},
         agg::IdxSelection,
-        agg_ctx::AggContext,
-        agg_hash_map::AggHashMap,
+        agg_ctx::{null_group_rows, AggContext, AggScratch},
+        agg_hash_map::{agg_hash, AggHashMap},
         spark_udaf_wrapper::{AccUDAFBufferRowsColumn, SparkUDAFWrapper},
     },
     common::{
@@ -52,7 +51,10 @@ use crate::{
         timer_helper::TimerHelper,
     },
     memmgr::{
-        spill::{try_new_spill, Spill, SpillCompressedReader, SpillCompressedWriter},
+        spill::{
+            try_new_on_heap_spill, try_new_spill, AppendableFileSpill, Spill,
+            SpillCompressedReader, SpillCompressedWriter,
+        },
         MemConsumer, MemConsumerInfo, MemManager,
     },
 };
@@ -64,10 +66,14 @@ const _OWNED_KEY_SIZE_CHECKER: [(); 32] = [(); size_of::<OwnedKey>()];
 // estimated size: bufread=64KB + lz4dec.src=64KB + lz4dec.dest=64KB +
 const SPILL_OFFHEAP_MEM_COST: usize = 200000;
 
+// upper bound of the initial hash map capacity estimated from input statistics
+const MAX_INITIAL_NUM_GROUPS: usize = 65536;
+
 pub struct AggTable {
     mem_consumer_info: Option<Weak<MemConsumerInfo>>,
     in_mem: Mutex<InMemTable>,
     spills: Mutex<Vec<Box<dyn Spill>>>,
+    spill_file: OnceCell<AppendableFileSpill>,
     agg_ctx: Arc<AggContext>,
     exec_ctx: Arc<ExecutionContext>,
     output_time: Time,
@@ -89,6 +95,7 @@ impl AggTable {
                 merging_time.clone(),
             )?),
             spills: Mutex::default(),
+            spill_file: OnceCell::new(),
             agg_ctx,
             exec_ctx,
             output_time,
@@ -99,9 +106,10 @@ impl AggTable {
         let mut in_mem = self.in_mem.lock().await;
 
         // compute input arrays
-        match &mut in_mem.data {
-            InMemData::Hashing(hashing_data) => hashing_data.update_batch(input_batch)?,
-            InMemData::Merging(merging_data) => merging_data.add_batch(input_batch)?,
+        let InMemTable { data, scratch, .. } = &mut *in_mem;
+        match data {
+            InMemData::Hashing(hashing_data) => hashing_data.update_batch(input_batch, scratch)?,
+            InMemData::Merging(merging_data) => merging_data.add_batch(input_batch, scratch)?,
         }
 
         // trigger partial skipping if memory usage is too high
@@ -140,6 +148,23 @@ impl AggTable {
         self.in_mem.lock().await.renew(is_hashing)
     }
 
+    // creates a spill for the next spilling. on-heap spills are still preferred
+    // if available, and if appending runs is enabled, file spills are runs of
+    // one shared spill file
+    fn new_spill(&self) -> Result<Box<dyn Spill>> {
+        let spill_metrics = self.exec_ctx.spill_metrics();
+        if !self.agg_ctx.spill_append_runs {
+            return try_new_spill(spill_metrics);
+        }
+        if let Some(spill) = try_new_on_heap_spill(spill_metrics)? {
+            return Ok(spill);
+        }
+        let spill_file = self
+            .spill_file
+            .get_or_try_init(|| AppendableFileSpill::try_new(spill_metrics))?;
+        Ok(spill_file.new_run())
+    }
+
     pub async fn output(&self, sender: Arc<WrappedRecordBatchSender>) -> Result<()> {
         let _timer = self.output_time.timer();
         self.set_spillable(false);
@@ -210,9 +235,8 @@ impl AggTable {
         let mut spills = spills;
         if in_mem.num_records() > 0 {
             let spill_idx = spills.len();
-            let spill_metrics = self.exec_ctx.spill_metrics().clone();
+            let mut spill = self.new_spill()?;
             let spill = tokio::task::spawn_blocking(move || {
-                let mut spill: Box<dyn Spill> = try_new_spill(&spill_metrics)?;
                 in_mem.try_into_spill(&mut spill, spill_idx)?; // spill staging records
                 Ok::<_, DataFusionError>(spill)
             })
@@ -245,6 +269,7 @@ impl AggTable {
 
         let mut map = AggHashMap::default();
         let mut acc_table = self.agg_ctx.create_acc_table(0);
+        let mut scratch = AggScratch::default();
 
         while let cur_bucket_idx = cursors.peek().cur_bucket_idx
             && cur_bucket_idx < num_spill_buckets
@@ -255,7 +280,8 @@ impl AggTable {
             {
                 // merge records of current bucket
                 let (mut bucket_acc_table, bucket_key_rows) = min_cursor.read_bucket()?;
-                let map_indices = map.upsert_records(bucket_key_rows);
+                map.upsert_records_into(bucket_key_rows, &mut scratch.record_indices);
+                let map_indices = &scratch.record_indices;
                 let udaf_indices_cache = OnceCell::new();
 
                 for (agg_idx, agg) in self.agg_ctx.aggs.iter().enumerate() {
@@ -263,15 +289,16 @@ impl AggTable {
                     if let Ok(udaf_agg) = downcast_any!(agg.agg, SparkUDAFWrapper) {
                         udaf_agg.partial_merge_with_indices_cache(
                             &mut acc_table.cols_mut()[agg_idx],
-                            IdxSelection::IndicesU32(&map_indices),
+                            IdxSelection::IndicesU32(map_indices),
                             &mut bucket_acc_table.cols_mut()[agg_idx],
                             IdxSelection::Range(0, map_indices.len()),
                             &udaf_indices_cache,
+                            &mut scratch.udaf_zipped_indices,
                         )?;
                     } else {
                         agg.agg.partial_merge(
                             &mut acc_table.cols_mut()[agg_idx],
-                            IdxSelection::IndicesU32(&map_indices),
+                            IdxSelection::IndicesU32(map_indices),
                             &mut bucket_acc_table.cols_mut()[agg_idx],
                             IdxSelection::Range(0, map_indices.len()),
                         )?;
@@ -325,6 +352,41 @@ impl MemConsumer for AggTable {
         let mut in_mem = self.in_mem.lock().await;
         let mut spills = self.spills.lock().await;
 
+        // with udaf columns, spill only the cold half of groups and keep hot
+        // groups in memory, so they are not spilled and merged repeatedly
+        if let InMemData::Hashing(hashing_data) = &in_mem.data {
+            let num_evicted = hashing_data.num_records() / 2;
+            if let Some(evicted) = hashing_data.udaf_eviction_candidates(num_evicted)? {
+                let spill_idx = spills.len();
+                let mut spill = self.new_spill()?;
+
+                // move hashing data out (leaving an empty placeholder) so that
+                // spilling runs in a blocking thread
+                let placeholder = InMemData::Merging(MergingData::try_new(
+                    self.agg_ctx.clone(),
+                    in_mem.merging_time.clone(),
+                )?);
+                let InMemData::Hashing(mut hashing_data) =
+                    std::mem::replace(&mut in_mem.data, placeholder)
+                else {
+                    unreachable!()
+                };
+                let (hashing_data, spill) = tokio::task::spawn_blocking(move || {
+                    hashing_data.spill_and_remove(&evicted, &mut spill, spill_idx)?;
+                    Ok::<_, DataFusionError>((hashing_data, spill))
+                })
+                .await
+                .expect("tokio spawn_blocking error")?;
+                in_mem.data = InMemData::Hashing(hashing_data);
+                spills.push(spill);
+                drop(spills);
+                let mem_used = in_mem.mem_used();
+                drop(in_mem);
+                self.update_mem_used(mem_used).await?;
+                return Ok(());
+            }
+        }
+
         // use pre-merging if cardinality is low
         let mut next_is_hashing = false;
         if let InMemData::Hashing(hashing_data) = &in_mem.data {
@@ -334,10 +396,9 @@ impl MemConsumer for AggTable {
         }
         let cur_in_mem = in_mem.renew(next_is_hashing)?;
 
-        let spill_metrics = self.exec_ctx.spill_metrics().clone();
         let spill_idx = spills.len();
+        let mut spill = self.new_spill()?;
         let cur_spill = tokio::task::spawn_blocking(move || {
-            let mut spill = try_new_spill(&spill_metrics)?;
             cur_in_mem.try_into_spill(&mut spill, spill_idx)?;
             Ok::<_, DataFusionError>(spill)
         })
@@ -384,6 +445,7 @@ pub struct InMemTable {
     agg_ctx: Arc<AggContext>,
     exec_ctx: Arc<ExecutionContext>,
     data: InMemData,
+    scratch: AggScratch,
     hashing_time: Time,
     merging_time: Time,
 }
@@ -397,15 +459,32 @@ impl InMemTable {
         hashing_time: Time,
         merging_time: Time,
     ) -> Result<Self> {
+        // only the first table is presized, later tables are created after
+        // spilling and the estimation no longer applies
+        let initial_num_groups = if id == 0 {
+            exec_ctx
+                .input_statistics()
+                .and_then(|statistics| agg_ctx.estimated_num_groups(statistics))
+                .unwrap_or(0)
+                .min(MAX_INITIAL_NUM_GROUPS)
+        } else {
+            0
+        };
         Ok(Self {
             id,
             data: if is_hashing {
-                InMemData::Hashing(HashingData::try_new(agg_ctx.clone(), hashing_time.clone())?)
+                InMemData::Hashing(HashingData::try_new(
+                    agg_ctx.clone(),
+                    initial_num_groups,
+                    id == 0,
+                    hashing_time.clone(),
+                )?)
             } else {
                 InMemData::Merging(MergingData::try_new(agg_ctx.clone(), merging_time.clone())?)
             },
             agg_ctx,
             exec_ctx,
+            scratch: AggScratch::default(),
             hashing_time,
             merging_time,
         })
@@ -421,22 +500,22 @@ impl InMemTable {
         if let Some(udaf_mem_tracker) = agg_ctx.get_udaf_mem_tracker() {
             udaf_mem_tracker.reset()?;
         }
-        Ok(std::mem::replace(
-            self,
-            Self::try_new(
-                id,
-                agg_ctx,
-                task_ctx,
-                is_hashing,
-                hashing_time,
-                merging_time,
-            )?,
-        ))
+        let mut renewed = Self::try_new(
+            id,
+            agg_ctx,
+            task_ctx,
+            is_hashing,
+            hashing_time,
+            merging_time,
+        )?;
+        // reuse scratch space
+        renewed.scratch = std::mem::take(&mut self.scratch);
+        Ok(std::mem::replace(self, renewed))
     }
 
     pub fn mem_used(&self) -> usize {
         let sorting_indices_used = self.num_records() * 16;
-        self.data.mem_used() + sorting_indices_used
+        self.data.mem_used() + sorting_indices_used + self.scratch.parallel_accs_mem_used
     }
 
     pub fn num_records(&self) -> usize {
@@ -473,24 +552,32 @@ pub struct HashingData {
     agg_ctx: Arc<AggContext>,
     acc_table: AccTable,
     map: AggHashMap,
+    null_group_idx: Option<u32>,
     num_input_records: usize,
+    udaf_columns_tracked: bool,
     hashing_time: Time,
 }
 
 impl HashingData {
-    fn try_new(agg_ctx: Arc<AggContext>, hashing_time: Time) -> Result<Self> {
-        let acc_table = agg_ctx.create_acc_table(0);
-        for acc in acc_table.cols() {
-            if let Ok(udaf_column) = downcast_any!(acc, AccUDAFBufferRowsColumn) {
-                let udaf_mem_tracker = agg_ctx.get_or_try_init_udaf_mem_tracker()?;
-                udaf_mem_tracker.add_column(udaf_column)?;
-            }
-        }
+    fn try_new(
+        agg_ctx: Arc<AggContext>,
+        initial_num_groups: usize,
+        is_first_table: bool,
+        hashing_time: Time,
+    ) -> Result<Self> {
+        // like the hash map, only acc columns of the first table are presized
+        let acc_table = if is_first_table {
+            agg_ctx.create_hashing_acc_table(0)
+        } else {
+            agg_ctx.create_acc_table(0)
+        };
 
         Ok(Self {
             acc_table,
-            map: AggHashMap::default(),
+            map: AggHashMap::with_capacity(initial_num_groups),
+            null_group_idx: None,
             num_input_records: 0,
+            udaf_columns_tracked: false,
             agg_ctx,
             hashing_time,
         })
@@ -515,70 +602,177 @@ impl HashingData {
         mem_used
     }
 
-    fn update_batch(&mut self, batch: RecordBatch) -> Result<()> {
+    fn update_batch(&mut self, batch: RecordBatch, scratch: &mut AggScratch) -> Result<()> {
         let _timer = self.hashing_time.timer();
 
         let num_rows = batch.num_rows();
         self.num_input_records += num_rows;
 
-        let grouping_rows = self.agg_ctx.create_grouping_rows(&batch)?;
-        let record_indices = self.map.upsert_records(
-            grouping_rows
-                .iter()
-                .map(|row| row.as_ref().as_raw_bytes())
-                .collect(),
-        );
+        let grouping_arrays = self.agg_ctx.evaluate_grouping_arrays(&batch)?;
+        let grouping_rows = self.agg_ctx.convert_grouping_arrays(&grouping_arrays)?;
+        let mut record_indices = std::mem::take(&mut scratch.record_indices);
+        match null_group_rows(&grouping_arrays) {
+            Some(null_rows) => self.upsert_records_with_null_group(
+                &grouping_rows,
+                &null_rows,
+                &mut record_indices,
+                &mut scratch.non_null_record_indices,
+            ),
+            None => self.map.upsert_records_into(
+                grouping_rows
+                    .iter()
+                    .map(|row| row.as_ref().as_raw_bytes())
+                    .collect(),
+                &mut record_indices,
+            ),
+        }
         self.agg_ctx.update_batch_to_acc_table(
             &batch,
             &mut self.acc_table,
             IdxSelection::IndicesU32(&record_indices),
+            scratch,
         )?;
+        scratch.record_indices = record_indices;
+
+        if num_rows > 0 && !self.udaf_columns_tracked {
+            track_udaf_columns(&self.agg_ctx, &mut self.acc_table)?;
+            self.udaf_columns_tracked = true;
+        }
         Ok(())
     }
 
+    // rows with all-null grouping keys go to a dedicated null group slot
+    // without hashing, other rows are upserted into the hash map. the null
+    // group is still stored in the map with its encoded key, so spilling and
+    // merging treat it like any other group.
+    fn upsert_records_with_null_group(
+        &mut self,
+        grouping_rows: &Rows,
+        null_rows: &BooleanBuffer,
+        record_indices: &mut Vec<u32>,
+        non_null_record_indices: &mut Vec<u32>,
+    ) {
+        let null_group_idx = *self.null_group_idx.get_or_insert_with(|| {
+            let first_null_row = null_rows.set_indices().next().expect("no null rows");
+            let null_key = grouping_rows.row(first_null_row);
+            self.map
+                .upsert_records(vec![null_key.as_ref().as_raw_bytes()])[0]
+        });
+
+        self.map.upsert_records_into(
+            grouping_rows
+                .iter()
+                .zip(null_rows.iter())
+                .filter(|(_, is_null)| !is_null)
+                .map(|(row, _)| row.as_ref().as_raw_bytes())
+                .collect(),
+            non_null_record_indices,
+        );
+        let mut non_null_record_indices = non_null_record_indices.iter();
+        record_indices.clear();
+        record_indices.extend(null_rows.iter().map(|is_null| {
+            if is_null {
+                null_group_idx
+            } else {
+                *non_null_record_indices
+                    .next()
+                    .expect("missing record index")
+            }
+        }));
+    }
+
     fn try_into_spill(self, spill: &mut Box<dyn Spill>, spill_idx: usize) -> Result<()> {
+        self.write_spill(spill, spill_idx, |_| true)
+    }
+
+    /// returns cold groups to evict in a partial spill, picked by the first
+    /// udaf column, whose buffer rows are the most expensive to keep in
+    /// memory. returns None if there is no udaf column or too few groups.
+    fn udaf_eviction_candidates(&self, num_groups: usize) -> Result<Option<Vec<usize>>> {
+        if num_groups == 0 {
+            return Ok(None);
+        }
+        for col in self.acc_table.cols() {
+            if let Ok(udaf_col) = downcast_any!(col, AccUDAFBufferRowsColumn) {
+                let mut evicted = udaf_col.eviction_candidates(num_groups)?;
+                evicted.sort_unstable();
+                return Ok((!evicted.is_empty()).then_some(evicted));
+            }
+        }
+        Ok(None)
+    }
+
+    /// spills the `evicted` records in the same format as `try_into_spill`
+    /// and removes them from the table in place, other records are kept in
+    /// memory and go on being updated
+    fn spill_and_remove(
+        &mut self,
+        evicted: &[usize],
+        spill: &mut Box<dyn Spill>,
+        spill_idx: usize,
+    ) -> Result<()> {
+        let (retained, remap) =
+            retained_records(self.num_records(), IdxSelection::Indices(evicted));
+        self.write_spill(spill, spill_idx, |record_idx| remap[record_idx].is_none())?;
+
+        self.acc_table.compact(&retained)?;
+        self.acc_table.shrink_to_fit();
+        self.map.compact(&retained, &remap);
+        self.null_group_idx = self
+            .null_group_idx
+            .and_then(|idx| remap[idx as usize].map(|new_idx| new_idx as u32));
+
+        // udaf columns are tracked again with the next updated batch
+        if let Some(udaf_mem_tracker) = self.agg_ctx.get_udaf_mem_tracker() {
+            udaf_mem_tracker.reset()?;
+        }
+        self.udaf_columns_tracked = false;
+        Ok(())
+    }
+
+    fn write_spill(
+        &self,
+        spill: &mut Box<dyn Spill>,
+        spill_idx: usize,
+        is_spilled: impl Fn(usize) -> bool,
+    ) -> Result<()> {
         let bucket_batch_size =
             compute_suggested_batch_size_for_kway_merge(self.mem_used(), self.num_records());
 
-        // sort all records using radix sort on hashcodes of keys
+        // spill buckets are derived from the top bits of the map hashes, which
+        // also partition sub-tables. so records are sorted by buckets within
+        // each sub-table, instead of sorting all records at once
         let num_spill_buckets = self.agg_ctx.num_spill_buckets(self.mem_used());
-        let key_rows = self.map.into_keys();
-        let acc_table = self.acc_table;
-        let mut entries = key_rows
-            .iter()
-            .enumerate()
-            .map(|(record_idx, key)| (bucket_id(key, num_spill_buckets) as u32, record_idx as u32))
-            .collect::<Vec<_>>();
-
-        let mut bucket_counts = vec![0; num_spill_buckets];
-        radix_sort_by_key(&mut entries, &mut bucket_counts, |(bucket_id, ..)| {
-            *bucket_id as usize
-        });
+        let map = &self.map;
+        let acc_table = &self.acc_table;
+        let key_rows = map.keys();
 
         let mut writer = spill.get_compressed_writer();
-        let mut offset = 0;
-        for (cur_bucket_id, bucket_count) in bucket_counts.into_iter().enumerate() {
-            let offset_end = offset + bucket_count;
-
-            while offset < offset_end {
-                let cur_bucket_count = bucket_batch_size.min(offset_end - offset);
-                write_len(cur_bucket_id, &mut writer)?;
-                write_len(cur_bucket_count, &mut writer)?;
-                write_spill_bucket(
-                    &mut writer,
-                    &self.agg_ctx,
-                    &acc_table,
-                    entries[offset..][..cur_bucket_count]
-                        .iter()
-                        .map(|&(_, record_idx)| &key_rows[record_idx as usize]),
-                    entries[offset..][..cur_bucket_count]
-                        .iter()
-                        .map(|&(_, record_idx)| record_idx as usize),
-                    spill_idx,
-                )?;
-                offset += cur_bucket_count;
+        map.for_each_sub_table_entries(|entries| {
+            entries.retain(|&(_hash, record_idx)| is_spilled(record_idx as usize));
+            for (hash, _record_idx) in entries.iter_mut() {
+                *hash = bucket_id_of_hash(*hash, num_spill_buckets) as u32;
             }
-        }
+            entries.sort_unstable_by_key(|&(bucket_id, _)| bucket_id);
+
+            for bucket_entries in entries.chunk_by(|(b1, _), (b2, _)| b1 == b2) {
+                for chunk in bucket_entries.chunks(bucket_batch_size) {
+                    write_len(chunk[0].0 as usize, &mut writer)?;
+                    write_len(chunk.len(), &mut writer)?;
+                    write_spill_bucket(
+                        &mut writer,
+                        &self.agg_ctx,
+                        acc_table,
+                        chunk
+                            .iter()
+                            .map(|&(_, record_idx)| &key_rows[record_idx as usize]),
+                        chunk.iter().map(|&(_, record_idx)| record_idx as usize),
+                        spill_idx,
+                    )?;
+                }
+            }
+            Ok(())
+        })?;
         // EOF
         write_len(num_spill_buckets, &mut writer)?;
         write_len(0, &mut writer)?;
@@ -593,24 +787,20 @@ pub struct MergingData {
     key_rows: Vec<Rows>,
     entries: Vec<(u32, u32, u32, u32)>, // (bucket_id, batch_idx, row_idx, acc_idx)
     key_rows_mem_size: usize,
+    udaf_columns_tracked: bool,
     merging_time: Time,
 }
 
 impl MergingData {
     fn try_new(agg_ctx: Arc<AggContext>, merging_time: Time) -> Result<Self> {
         let acc_table = agg_ctx.create_acc_table(0);
-        for acc in acc_table.cols() {
-            if let Ok(udaf_column) = downcast_any!(acc, AccUDAFBufferRowsColumn) {
-                let udaf_mem_tracker = agg_ctx.get_or_try_init_udaf_mem_tracker()?;
-                udaf_mem_tracker.add_column(udaf_column)?;
-            }
-        }
 
         Ok(Self {
             acc_table,
             key_rows: vec![],
             entries: vec![],
             key_rows_mem_size: 0,
+            udaf_columns_tracked: false,
             agg_ctx,
             merging_time,
         })
@@ -630,7 +820,7 @@ impl MergingData {
         mem_used
     }
 
-    fn add_batch(&mut self, batch: RecordBatch) -> Result<()> {
+    fn add_batch(&mut self, batch: RecordBatch, scratch: &mut AggScratch) -> Result<()> {
         let _timer = self.merging_time.timer();
         let num_rows = batch.num_rows();
         let num_entries_old = self.entries.len();
@@ -643,6 +833,7 @@ impl MergingData {
             &batch,
             &mut self.acc_table,
             IdxSelection::Range(num_entries_old, num_entries_old + num_rows),
+            scratch,
         )?;
 
         // add key rows
@@ -662,6 +853,11 @@ impl MergingData {
         }
         self.key_rows_mem_size += grouping_rows.size();
         self.key_rows.push(grouping_rows);
+
+        if num_rows > 0 && !self.udaf_columns_tracked {
+            track_udaf_columns(&self.agg_ctx, &mut self.acc_table)?;
+            self.udaf_columns_tracked = true;
+        }
         Ok(())
     }
 
@@ -673,13 +869,24 @@ impl MergingData {
         entries.shrink_to_fit();
 
         let key_rows = self.key_rows;
-        let acc_table = self.acc_table;
+        let mut acc_table = self.acc_table;
         let mut bucket_counts = vec![0; num_spill_buckets];
 
         radix_sort_by_key(&mut entries, &mut bucket_counts, |(bucket_id, ..)| {
             *bucket_id as usize
         });
 
+        // combine records with identical keys, so that each key is spilled
+        // only once
+        if self.agg_ctx.spill_pre_merge {
+            (entries, acc_table) =
+                pre_merge_entries(&self.agg_ctx, entries, &bucket_counts, &key_rows, acc_table)?;
+            bucket_counts.fill(0);
+            for &(bucket_id, ..) in &entries {
+                bucket_counts[bucket_id as usize] += 1;
+            }
+        }
+
         let mut writer = spill.get_compressed_writer();
         let mut offset = 0;
         for (cur_bucket_id, bucket_count) in bucket_counts.into_iter().enumerate() {
@@ -718,6 +925,56 @@ impl MergingData {
     }
 }
 
+// sorts records of each bucket by keys and combines adjacent records with
+// identical keys with partial_merge. records of the same key are merged in
+// their input order. entries are expected to be sorted by bucket ids.
+fn pre_merge_entries(
+    agg_ctx: &AggContext,
+    mut entries: Vec<(u32, u32, u32, u32)>,
+    bucket_counts: &[usize],
+    key_rows: &[Rows],
+    mut acc_table: AccTable,
+) -> Result<(Vec<(u32, u32, u32, u32)>, AccTable)> {
+    let key = |&(_, batch_idx, row_idx, _): &(u32, u32, u32, u32)| {
+        key_rows[batch_idx as usize].row(row_idx as usize)
+    };
+
+    let mut offset = 0;
+    for &bucket_count in bucket_counts {
+        entries[offset..][..bucket_count]
+            .sort_unstable_by(|a, b| key(a).cmp(&key(b)).then(a.3.cmp(&b.3)));
+        offset += bucket_count;
+    }
+
+    let mut merged_entries: Vec<(u32, u32, u32, u32)> = vec![];
+    let mut merged_acc_indices = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        if merged_entries
+            .last()
+            .is_none_or(|last| key(last) != key(entry))
+        {
+            let merged_acc_idx = merged_entries.len() as u32;
+            merged_entries.push((entry.0, entry.1, entry.2, merged_acc_idx));
+        }
+        merged_acc_indices.push(merged_entries.len() as u32 - 1);
+    }
+    let acc_indices = entries
+        .iter()
+        .map(|&(.., acc_idx)| acc_idx)
+        .collect::<Vec<_>>();
+
+    let mut merged_acc_table = agg_ctx.create_acc_table(0);
+    for (agg_idx, agg) in agg_ctx.aggs.iter().enumerate() {
+        agg.agg.partial_merge(
+            &mut merged_acc_table.cols_mut()[agg_idx],
+            IdxSelection::IndicesU32(&merged_acc_indices),
+            &mut acc_table.cols_mut()[agg_idx],
+            IdxSelection::IndicesU32(&acc_indices),
+        )?;
+    }
+    Ok((merged_entries, merged_acc_table))
+}
+
 fn write_spill_bucket(
     w: &mut SpillCompressedWriter,
     agg_ctx: &AggContext,
@@ -751,6 +1008,18 @@ fn write_spill_bucket(
     Ok(())
 }
 
+/// adds udaf columns to the udaf memory tracker. udaf columns are lazily
+/// initialized, so this is deferred until the first non-empty batch is added.
+fn track_udaf_columns(agg_ctx: &AggContext, acc_table: &mut AccTable) -> Result<()> {
+    for acc in acc_table.cols_mut() {
+        if let Ok(udaf_column) = downcast_any!(acc, mut AccUDAFBufferRowsColumn) {
+            let udaf_mem_tracker = agg_ctx.get_or_try_init_udaf_mem_tracker()?;
+            udaf_mem_tracker.add_column(udaf_column)?;
+        }
+    }
+    Ok(())
+}
+
 fn read_spill_bucket(
     mut r: &mut SpillCompressedReader,
     num_rows: usize,
@@ -834,9 +1103,176 @@ impl<'a> KeyForRadixQueue for RecordsSpillCursor<'a> {
 
 #[inline]
 fn bucket_id(key: impl AsRef<[u8]>, num_spill_buckets: usize) -> u16 {
-    const AGG_HASH_SEED_HASHING: i64 = 0xC732BD66;
-    const HASHER: foldhash::fast::FixedState =
-        foldhash::fast::FixedState::with_seed(AGG_HASH_SEED_HASHING as u64);
-    let hash = HASHER.hash_one(key.as_ref()) as u32;
-    (hash % num_spill_buckets as u32) as u16
+    bucket_id_of_hash(agg_hash(&key.as_ref()), num_spill_buckets)
+}
+
+/// spill bucket of a map hash, taken from its top bits (except the highest
+/// one, which is always set) so that buckets are monotonic in hashes
+#[inline]
+fn bucket_id_of_hash(hash: u32, num_spill_buckets: usize) -> u16 {
+    (((hash & 0x7fffffff) as u64 * num_spill_buckets as u64) >> 31) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{ArrayRef, AsArray, Int64Array, RecordBatch},
+        datatypes::{DataType, Field, Int64Type, Schema},
+    };
+    use datafusion::{
+        common::Result, physical_expr::expressions::Column, physical_plan::metrics::Time,
+    };
+
+    use crate::{
+        agg::{
+            agg::IdxSelection,
+            agg_ctx::{AggContext, AggScratch},
+            agg_table::{HashingData, MergingData, RecordsSpillCursor},
+            sum::AggSum,
+            AggExecMode, AggExpr, AggMode, GroupingExpr,
+        },
+        memmgr::spill::Spill,
+    };
+
+    fn test_schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int64, false),
+            Field::new("v", DataType::Int64, false),
+        ]))
+    }
+
+    fn test_agg_ctx(schema: Arc<Schema>, spill_pre_merge: bool) -> Result<Arc<AggContext>> {
+        let mut agg_ctx = AggContext::try_new(
+            AggExecMode::HashAgg,
+            schema.clone(),
+            vec![GroupingExpr {
+                field_name: "k".to_string(),
+                expr: Arc::new(Column::new("k", 0)),
+            }],
+            vec![AggExpr {
+                field_name: "sum".to_string(),
+                mode: AggMode::Partial,
+                agg: Arc::new(AggSum::try_new(
+                    Arc::new(Column::new("v", 1)),
+                    DataType::Int64,
+                )?),
+            }],
+            false,
+            false,
+        )?;
+        agg_ctx.spill_pre_merge = spill_pre_merge;
+        Ok(Arc::new(agg_ctx))
+    }
+
+    // reads records of a spill, returns number of records and sums of each key
+    fn read_spill(
+        spill: &mut Box<dyn Spill>,
+        agg_ctx: &Arc<AggContext>,
+    ) -> Result<(usize, HashMap<Vec<u8>, i64>)> {
+        let mut num_records = 0;
+        let mut sums = HashMap::new();
+        let mut cursor = RecordsSpillCursor::try_from_spill(spill, 0, agg_ctx)?;
+        while cursor.has_next_bucket() {
+            let (mut acc_table, keys) = cursor.read_bucket()?;
+            let values = agg_ctx.aggs[0].agg.final_merge(
+                &mut acc_table.cols_mut()[0],
+                IdxSelection::Range(0, keys.len()),
+            )?;
+            for (key, value) in keys.iter().zip(values.as_primitive::<Int64Type>()) {
+                *sums.entry(key.to_vec()).or_default() += value.unwrap_or_default();
+            }
+            num_records += keys.len();
+        }
+        Ok((num_records, sums))
+    }
+
+    // spills merging data of a 10:1 duplicated input, returns spilled bytes
+    // and sums of each spilled key
+    fn spill_merging_data(spill_pre_merge: bool) -> Result<(usize, usize, HashMap<Vec<u8>, i64>)> {
+        let schema = test_schema();
+        let agg_ctx = test_agg_ctx(schema.clone(), spill_pre_merge)?;
+
+        let mut merging_data = MergingData::try_new(agg_ctx.clone(), Time::new())?;
+        let mut scratch = AggScratch::default();
+        for batch_idx in 0..10 {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from_iter_values(0..1000)),
+                    Arc::new(Int64Array::from_iter_values(
+                        (0..1000).map(|i| i * batch_idx),
+                    )),
+                ],
+            )?;
+            merging_data.add_batch(batch, &mut scratch)?;
+        }
+
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        merging_data.try_into_spill(&mut spill, 0)?;
+        let spill_size = spill.as_any().downcast_ref::<Vec<u8>>().unwrap().len();
+        let (num_records, sums) = read_spill(&mut spill, &agg_ctx)?;
+        Ok((spill_size, num_records, sums))
+    }
+
+    #[test]
+    fn test_spill_pre_merge() -> Result<()> {
+        let (raw_spill_size, raw_num_records, raw_sums) = spill_merging_data(false)?;
+        let (spill_size, num_records, sums) = spill_merging_data(true)?;
+        assert_eq!(raw_num_records, 10000);
+        assert_eq!(num_records, 1000);
+        assert_eq!(sums, raw_sums);
+        assert!(
+            spill_size * 2 < raw_spill_size,
+            "spill size: {spill_size}, without pre-merging: {raw_spill_size}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hashing_data_spill_and_remove() -> Result<()> {
+        let schema = test_schema();
+        let agg_ctx = test_agg_ctx(schema.clone(), false)?;
+        let mut hashing_data = HashingData::try_new(agg_ctx.clone(), 0, true, Time::new())?;
+        let mut scratch = AggScratch::default();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(0..1000)),
+                Arc::new(Int64Array::from_iter_values(0..1000)),
+            ],
+        )?;
+        hashing_data.update_batch(batch.clone(), &mut scratch)?;
+
+        // spill every other record, the others are kept and updated again
+        let evicted = (0..1000).step_by(2).collect::<Vec<_>>();
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        hashing_data.spill_and_remove(&evicted, &mut spill, 0)?;
+        assert_eq!(hashing_data.num_records(), 500);
+        hashing_data.update_batch(batch, &mut scratch)?;
+        assert_eq!(hashing_data.num_records(), 1000);
+
+        let mut remaining_spill: Box<dyn Spill> = Box::new(vec![]);
+        hashing_data.try_into_spill(&mut remaining_spill, 0)?;
+        let (num_spilled, spilled_sums) = read_spill(&mut spill, &agg_ctx)?;
+        let (num_remaining, remaining_sums) = read_spill(&mut remaining_spill, &agg_ctx)?;
+        assert_eq!(num_spilled, 500);
+        assert_eq!(num_remaining, 1000);
+
+        let mut sums = spilled_sums;
+        for (key, sum) in remaining_sums {
+            *sums.entry(key).or_default() += sum;
+        }
+        let expected_sums = agg_ctx
+            .convert_grouping_arrays(
+                &[Arc::new(Int64Array::from_iter_values(0..1000)) as ArrayRef],
+            )?
+            .iter()
+            .zip(0..1000)
+            .map(|(key, i)| (key.as_ref().to_vec(), i * 2))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(sums, expected_sums);
+        Ok(())
+    }
 }
@@ -0,0 +1,460 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use bitvec::{bitvec, vec::BitVec};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::{
+    downcast_any, scalar_value::compacted_scalar_value_from_array, UninitializedInit,
+};
+
+use crate::{
+    agg::{
+        acc::{
+            acc_generic_column_to_array, compact_bits, create_acc_generic_column, AccBooleanColumn,
+            AccBytes, AccBytesColumn, AccColumn, AccColumnRef, AccPrimColumn, AccScalarValueColumn,
+        },
+        agg::IdxSelection,
+        Agg,
+    },
+    idx_for, idx_for_zipped, idx_with_iter,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// returns an arbitrary non-null value of each group. unlike first(), there
+/// is no ordering requirement, so inputs of a group are skipped as soon as
+/// a non-null value is stored.
+pub struct AggAnyValue {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+}
+
+impl AggAnyValue {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
+        Ok(Self { child, data_type })
+    }
+}
+
+impl Debug for AggAnyValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AnyValue({:?})", self.child)
+    }
+}
+
+impl Agg for AggAnyValue {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(
+            exprs[0].clone(),
+            self.data_type.clone(),
+        )?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        Box::new(AccAnyValueColumn {
+            values: create_acc_generic_column(&self.data_type, num_rows),
+            has_value: bitvec![0; num_rows],
+        })
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let partial_arg = &partial_args[0];
+        let accs = downcast_any!(accs, mut AccAnyValueColumn)?;
+        accs.ensure_size(acc_idx);
+
+        // no values to store
+        if partial_arg.null_count() == partial_arg.len() {
+            return Ok(());
+        }
+        let (value_accs, has_value) = accs.inner_mut();
+
+        macro_rules! handle_bytes {
+            ($array:expr) => {{
+                let value_accs = downcast_any!(value_accs, mut AccBytesColumn)?;
+                let partial_arg = $array;
+                idx_for_zipped! {
+                    ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                        if !has_value[acc_idx] && partial_arg.is_valid(partial_arg_idx) {
+                            value_accs.set_value(acc_idx, Some(AccBytes::from(partial_arg.value(partial_arg_idx).as_ref())));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }}
+        }
+
+        macro_rules! handle_scalar_value {
+            () => {{
+                let value_accs = downcast_any!(value_accs, mut AccScalarValueColumn)?;
+                idx_for_zipped! {
+                    ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                        if !has_value[acc_idx] && partial_arg.is_valid(partial_arg_idx) {
+                            value_accs.set_value(acc_idx, compacted_scalar_value_from_array(partial_arg, partial_arg_idx)?);
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }};
+        }
+
+        downcast_primitive_array! {
+            partial_arg => {
+                if let Ok(value_accs) = downcast_any!(value_accs, mut AccPrimColumn<_>) {
+                    idx_for_zipped! {
+                        ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                            if !has_value[acc_idx] && partial_arg.is_valid(partial_arg_idx) {
+                                value_accs.set_value(acc_idx, Some(partial_arg.value(partial_arg_idx)));
+                                has_value.set(acc_idx, true);
+                            }
+                        }
+                    }
+                } else {
+                    handle_scalar_value!()
+                }
+            }
+            DataType::Boolean => {
+                let value_accs = downcast_any!(value_accs, mut AccBooleanColumn)?;
+                let partial_arg = downcast_any!(partial_arg, BooleanArray)?;
+                idx_for_zipped! {
+                    ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                        if !has_value[acc_idx] && partial_arg.is_valid(partial_arg_idx) {
+                            value_accs.set_value(acc_idx, Some(partial_arg.value(partial_arg_idx)));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }
+            DataType::Utf8 => handle_bytes!(downcast_any!(partial_arg, StringArray)?),
+            DataType::Binary => handle_bytes!(downcast_any!(partial_arg, BinaryArray)?),
+            _other => handle_scalar_value!(),
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccAnyValueColumn)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccAnyValueColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let (value_accs, has_value) = accs.inner_mut();
+        let (merging_value_accs, merging_has_value) = merging_accs.inner_mut();
+
+        // primitive types
+        macro_rules! handle_primitive {
+            ($ty:ty) => {{
+                type TNative = <$ty as ArrowPrimitiveType>::Native;
+                let value_accs = downcast_any!(value_accs, mut AccPrimColumn<TNative>)?;
+                let merging_value_accs = downcast_any!(merging_value_accs, mut AccPrimColumn<_>)?;
+                idx_for_zipped! {
+                    ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                        if !has_value[acc_idx] && merging_has_value[merging_acc_idx] {
+                            value_accs.set_value(acc_idx, merging_value_accs.value(merging_acc_idx));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }}
+        }
+
+        macro_rules! handle_boolean {
+            () => {{
+                let value_accs = downcast_any!(value_accs, mut AccBooleanColumn)?;
+                let merging_value_accs = downcast_any!(merging_value_accs, mut AccBooleanColumn)?;
+                idx_for_zipped! {
+                    ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                        if !has_value[acc_idx] && merging_has_value[merging_acc_idx] {
+                            value_accs.set_value(acc_idx, merging_value_accs.value(merging_acc_idx));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }};
+        }
+
+        macro_rules! handle_bytes {
+            () => {{
+                let value_accs = downcast_any!(value_accs, mut AccBytesColumn)?;
+                let merging_value_accs = downcast_any!(merging_value_accs, mut AccBytesColumn)?;
+                idx_for_zipped! {
+                    ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                        if !has_value[acc_idx] && merging_has_value[merging_acc_idx] {
+                            value_accs.set_value(acc_idx, merging_value_accs.take_value(merging_acc_idx));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }};
+        }
+
+        downcast_primitive! {
+            (&self.data_type) => (handle_primitive),
+            DataType::Boolean => handle_boolean!(),
+            DataType::Utf8 | DataType::Binary => handle_bytes!(),
+            DataType::Null => {}
+            _ => {
+                let value_accs = downcast_any!(value_accs, mut AccScalarValueColumn)?;
+                let merging_value_accs = downcast_any!(merging_value_accs, mut AccScalarValueColumn)?;
+                idx_for_zipped! {
+                    ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                        if !has_value[acc_idx] && merging_has_value[merging_acc_idx] {
+                            value_accs.set_value(acc_idx, merging_value_accs.take_value(merging_acc_idx));
+                            has_value.set(acc_idx, true);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccAnyValueColumn)?;
+        acc_generic_column_to_array(&mut accs.values, &self.data_type, acc_idx)
+    }
+}
+
+struct AccAnyValueColumn {
+    values: AccColumnRef,
+    has_value: BitVec,
+}
+
+impl AccAnyValueColumn {
+    fn inner_mut(&mut self) -> (&mut AccColumnRef, &mut BitVec) {
+        (&mut self.values, &mut self.has_value)
+    }
+}
+
+impl AccColumn for AccAnyValueColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.values.resize(len);
+        self.has_value.resize(len, false);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values.fill_null_range(start, end);
+        self.has_value[start..end].fill(false);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+        self.has_value.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.values.num_records()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.values.mem_used() + self.has_value.capacity() / 8
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        self.values.freeze_to_rows(idx, array)?;
+        idx_with_iter!((idx @ idx) => {
+            for (i, w) in idx.zip(array) {
+                w.write_u8(self.has_value[i] as u8)?;
+            }
+        });
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        self.values.unfreeze_from_rows(cursors)?;
+        self.has_value.clear();
+        for cursor in cursors {
+            self.has_value.push(cursor.read_u8()? != 0);
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        self.values.spill(idx, w)?;
+        let mut buf = vec![];
+        idx_for! {
+            (idx in idx) => {
+                buf.push(self.has_value[idx] as u8);
+            }
+        }
+        w.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        self.values.unspill(num_rows, r)?;
+        let mut buf = Vec::uninitialized_init(num_rows);
+        r.read_exact(&mut buf)?;
+        self.has_value.clear();
+        self.has_value.extend(buf.into_iter().map(|v| v != 0));
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        self.values.compact(retained)?;
+        compact_bits(&mut self.has_value, retained);
+        self.has_value.truncate(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::agg::{
+        agg::{Agg, IdxSelection},
+        any_value::AggAnyValue,
+    };
+
+    // updates the first half and the second half of inputs separately, then
+    // merges them
+    fn eval_any_value(
+        input: ArrayRef,
+        acc_indices: &[usize],
+        num_groups: usize,
+    ) -> Result<ArrayRef> {
+        let agg = AggAnyValue::try_new(Arc::new(Column::new("v", 0)), input.data_type().clone())?;
+        let partial_args = agg.prepare_partial_args(&[input])?;
+        let mid = acc_indices.len() / 2;
+
+        let mut accs1 = agg.create_acc_column(0);
+        let mut accs2 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs1,
+            IdxSelection::Indices(&acc_indices[..mid]),
+            &partial_args,
+            IdxSelection::Range(0, mid),
+        )?;
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Indices(&acc_indices[mid..]),
+            &partial_args,
+            IdxSelection::Range(mid, acc_indices.len()),
+        )?;
+        agg.partial_merge(
+            &mut accs1,
+            IdxSelection::Range(0, num_groups),
+            &mut accs2,
+            IdxSelection::Range(0, num_groups),
+        )?;
+        agg.final_merge(&mut accs1, IdxSelection::Range(0, num_groups))
+    }
+
+    #[test]
+    fn test_any_value() -> Result<()> {
+        // group 0: non-null values in both halves
+        // group 1: non-null value only in the second half
+        // group 2: all nulls
+        let acc_indices = [0, 1, 2, 0, 1, 0, 1, 2];
+        let input: ArrayRef = Arc::new(Int32Array::from(vec![
+            Some(1),
+            None,
+            None,
+            Some(2),
+            None,
+            Some(3),
+            Some(4),
+            None,
+        ]));
+        // group 0 keeps the value of the first half
+        let output = eval_any_value(input, &acc_indices, 3)?;
+        assert_eq!(
+            output.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(1), Some(4), None]),
+        );
+
+        let input: ArrayRef = Arc::new(StringArray::from(vec![
+            None,
+            Some("a"),
+            None,
+            None,
+            None,
+            Some("b"),
+            None,
+            None,
+        ]));
+        let output = eval_any_value(input, &acc_indices, 3)?;
+        assert_eq!(
+            output.as_string::<i32>(),
+            &StringArray::from(vec![Some("b"), Some("a"), None]),
+        );
+
+        // scalar value accumulators
+        let input: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            None,
+            None,
+            None,
+            None,
+            Some(vec![Some(5)]),
+            None,
+            None,
+            None,
+        ]));
+        let output = eval_any_value(input, &acc_indices, 3)?;
+        assert_eq!(
+            output.as_list::<i32>(),
+            &ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+                None,
+                Some(vec![Some(5)]),
+                None
+            ]),
+        );
+        Ok(())
+    }
+}
@@ -15,49 +15,41 @@
 use std::{
     any::Any,
     fmt::{Debug, Formatter},
-    io::Cursor,
     sync::Arc,
 };
 
 use arrow::{array::*, datatypes::*};
 use datafusion::{
-    common::{
-        cast::{as_decimal128_array, as_int64_array},
-        Result,
-    },
+    common::{cast::as_decimal128_array, Result},
     physical_expr::PhysicalExpr,
 };
-use datafusion_ext_commons::downcast_any;
-
-use crate::{
-    agg::{
-        acc::{AccColumn, AccColumnRef},
-        agg::IdxSelection,
-        count::AggCount,
-        sum::AggSum,
-        Agg,
-    },
-    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+
+use crate::agg::{
+    acc::AccColumnRef, agg::IdxSelection, count::CountOverflowBehavior, sum_count::AggSumCount, Agg,
 };
 
+/// avg of non-null values, computed from the fused sum and count of
+/// AggSumCount
 pub struct AggAvg {
     child: Arc<dyn PhysicalExpr>,
     data_type: DataType,
-    agg_sum: AggSum,
-    agg_count: AggCount,
+    agg_sum_count: AggSumCount,
 }
 
 impl AggAvg {
     pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
-        let agg_sum = AggSum::try_new(child.clone(), data_type.clone())?;
-        let agg_count = AggCount::try_new(vec![child.clone()], DataType::Int64)?;
+        let agg_sum_count = AggSumCount::try_new(child.clone(), data_type.clone())?;
         Ok(Self {
             child,
             data_type,
-            agg_sum,
-            agg_count,
+            agg_sum_count,
         })
     }
+
+    pub fn with_overflow_behavior(mut self, overflow_behavior: CountOverflowBehavior) -> Self {
+        self.agg_sum_count = self.agg_sum_count.with_overflow_behavior(overflow_behavior);
+        self
+    }
 }
 
 impl Debug for AggAvg {
@@ -76,10 +68,10 @@ impl Agg for AggAvg {
     }
 
     fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
-        Ok(Arc::new(Self::try_new(
-            exprs[0].clone(),
-            self.data_type.clone(),
-        )?))
+        Ok(Arc::new(
+            Self::try_new(exprs[0].clone(), self.data_type.clone())?
+                .with_overflow_behavior(self.agg_sum_count.overflow_behavior()),
+        ))
     }
 
     fn data_type(&self) -> &DataType {
@@ -90,6 +82,10 @@ impl Agg for AggAvg {
         true
     }
 
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
     fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
         // cast arg1 to target data type
         Ok(vec![datafusion_ext_commons::arrow::cast::cast(
@@ -98,11 +94,13 @@ impl Agg for AggAvg {
         )?])
     }
 
+    fn expected_partial_state_size_bytes_per_group(&self) -> usize {
+        self.agg_sum_count
+            .expected_partial_state_size_bytes_per_group()
+    }
+
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
-        Box::new(AccAvgColumn {
-            sum: self.agg_sum.create_acc_column(num_rows),
-            count: self.agg_count.create_acc_column(num_rows),
-        })
+        self.agg_sum_count.create_acc_column(num_rows)
     }
 
     fn partial_update(
@@ -112,12 +110,8 @@ impl Agg for AggAvg {
         partial_args: &[ArrayRef],
         partial_arg_idx: IdxSelection<'_>,
     ) -> Result<()> {
-        let accs = downcast_any!(accs, mut AccAvgColumn)?;
-        self.agg_sum
-            .partial_update(&mut accs.sum, acc_idx, partial_args, partial_arg_idx)?;
-        self.agg_count
-            .partial_update(&mut accs.count, acc_idx, partial_args, partial_arg_idx)?;
-        Ok(())
+        self.agg_sum_count
+            .partial_update(accs, acc_idx, partial_args, partial_arg_idx)
     }
 
     fn partial_merge(
@@ -127,29 +121,13 @@ impl Agg for AggAvg {
         merging_accs: &mut AccColumnRef,
         merging_acc_idx: IdxSelection<'_>,
     ) -> Result<()> {
-        let accs = downcast_any!(accs, mut AccAvgColumn)?;
-        let merging_accs = downcast_any!(merging_accs, mut AccAvgColumn)?;
-        self.agg_sum.partial_merge(
-            &mut accs.sum,
-            acc_idx,
-            &mut merging_accs.sum,
-            merging_acc_idx,
-        )?;
-        self.agg_count.partial_merge(
-            &mut accs.count,
-            acc_idx,
-            &mut merging_accs.count,
-            merging_acc_idx,
-        )?;
-        Ok(())
+        self.agg_sum_count
+            .partial_merge(accs, acc_idx, merging_accs, merging_acc_idx)
     }
 
     fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
-        let accs = downcast_any!(accs, mut AccAvgColumn)?;
-        let sums = self.agg_sum.final_merge(&mut accs.sum, acc_idx)?;
-        let counts = self.agg_count.final_merge(&mut accs.count, acc_idx)?;
-
-        let counts_zero_free: Int64Array = as_int64_array(&counts)?.unary_opt(|count| {
+        let (sums, counts) = self.agg_sum_count.final_sums_and_counts(accs, acc_idx)?;
+        let counts_zero_free: Int64Array = counts.unary_opt(|count| {
             let not_zero = !count.is_zero();
             not_zero.then_some(count)
         });
@@ -171,60 +149,3 @@ impl Agg for AggAvg {
         }
     }
 }
-
-struct AccAvgColumn {
-    sum: AccColumnRef,
-    count: AccColumnRef,
-}
-
-impl AccColumn for AccAvgColumn {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        self
-    }
-
-    fn resize(&mut self, len: usize) {
-        self.sum.resize(len);
-        self.count.resize(len);
-    }
-
-    fn shrink_to_fit(&mut self) {
-        self.sum.shrink_to_fit();
-        self.count.shrink_to_fit();
-    }
-
-    fn num_records(&self) -> usize {
-        self.sum.num_records()
-    }
-
-    fn mem_used(&self) -> usize {
-        self.sum.mem_used() + self.count.mem_used()
-    }
-
-    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
-        self.sum.freeze_to_rows(idx, array)?;
-        self.count.freeze_to_rows(idx, array)?;
-        Ok(())
-    }
-
-    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
-        self.sum.unfreeze_from_rows(cursors)?;
-        self.count.unfreeze_from_rows(cursors)?;
-        Ok(())
-    }
-
-    fn spill(&self, idx: IdxSelection<'_>, buf: &mut SpillCompressedWriter) -> Result<()> {
-        self.sum.spill(idx, buf)?;
-        self.count.spill(idx, buf)?;
-        Ok(())
-    }
-
-    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
-        self.sum.unspill(num_rows, r)?;
-        self.count.unspill(num_rows, r)?;
-        Ok(())
-    }
-}
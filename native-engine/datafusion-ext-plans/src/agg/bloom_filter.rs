@@ -31,7 +31,7 @@ use datafusion_ext_commons::{
 
 use crate::{
     agg::{
-        acc::{AccColumn, AccColumnRef},
+        acc::{compact_items, AccColumn, AccColumnRef},
         agg::IdxSelection,
         Agg,
     },
@@ -228,6 +228,10 @@ impl AccColumn for AccBloomFilterColumn {
         self.bloom_filters.resize(len, None);
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.bloom_filters[start..end].fill(None);
+    }
+
     fn shrink_to_fit(&mut self) {
         self.bloom_filters.shrink_to_fit();
     }
@@ -300,4 +304,10 @@ impl AccColumn for AccBloomFilterColumn {
         }
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.bloom_filters, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
 }
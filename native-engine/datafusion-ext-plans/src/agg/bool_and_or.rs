@@ -0,0 +1,265 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::downcast_any;
+
+use crate::{
+    agg::{
+        acc::{AccBooleanColumn, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for_zipped,
+};
+
+pub type AggBoolAnd = AggBoolAndOr<AggBoolAndParams>;
+pub type AggBoolOr = AggBoolAndOr<AggBoolOrParams>;
+
+/// spark's bool_and/bool_or (and their aliases every/any/some). null inputs
+/// are ignored, groups without any non-null input produce null.
+pub struct AggBoolAndOr<P: AggBoolAndOrParams> {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+    _phantom: PhantomData<P>,
+}
+
+impl<P: AggBoolAndOrParams> AggBoolAndOr<P> {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>) -> Result<Self> {
+        Ok(Self {
+            child,
+            data_type: DataType::Boolean,
+            _phantom: Default::default(),
+        })
+    }
+}
+
+impl<P: AggBoolAndOrParams> Debug for AggBoolAndOr<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?})", P::NAME, self.child)
+    }
+}
+
+impl<P: AggBoolAndOrParams> Agg for AggBoolAndOr<P> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(exprs[0].clone())?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        // bit-packed, 2 bits (has-value and value) for each group
+        Box::new(AccBooleanColumn::new(num_rows))
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccBooleanColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let values = partial_args[0].as_boolean();
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if values.is_valid(partial_arg_idx) {
+                    let value = values.value(partial_arg_idx);
+                    accs.update_value(acc_idx, value, |acc| P::combine(acc, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccBooleanColumn)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccBooleanColumn)?;
+        accs.ensure_size(acc_idx);
+
+        // merging accumulators without any value are skipped, and accumulators
+        // without any value take the merging value directly
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                if let Some(merging_value) = merging_accs.value(merging_acc_idx) {
+                    accs.update_value(acc_idx, merging_value, |acc| {
+                        P::combine(acc, merging_value)
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccBooleanColumn)?;
+        accs.to_array(&self.data_type, acc_idx)
+    }
+}
+
+pub trait AggBoolAndOrParams: 'static + Send + Sync {
+    const NAME: &'static str;
+    fn combine(acc: bool, value: bool) -> bool;
+}
+
+pub struct AggBoolAndParams;
+pub struct AggBoolOrParams;
+
+impl AggBoolAndOrParams for AggBoolAndParams {
+    const NAME: &'static str = "bool_and";
+
+    fn combine(acc: bool, value: bool) -> bool {
+        acc && value
+    }
+}
+
+impl AggBoolAndOrParams for AggBoolOrParams {
+    const NAME: &'static str = "bool_or";
+
+    fn combine(acc: bool, value: bool) -> bool {
+        acc || value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::array::*;
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::{
+        agg::{
+            agg::{Agg, IdxSelection},
+            bool_and_or::{AggBoolAnd, AggBoolOr},
+        },
+        memmgr::spill::Spill,
+    };
+
+    fn evaluate(agg: &dyn Agg) -> Result<ArrayRef> {
+        // group 0: true, true | false
+        // group 1: true, null | null
+        // group 2: null, null | null
+        // group 3: (no inputs) | false
+        // group 4: null, false | (no inputs)
+        let values: ArrayRef = Arc::new(BooleanArray::from(vec![
+            Some(true),
+            Some(true),
+            None,
+            Some(true),
+            None,
+            None,
+            None,
+            Some(false),
+        ]));
+        let acc_indices = [0, 1, 2, 0, 1, 2, 4, 4];
+        let merging_values: ArrayRef = Arc::new(BooleanArray::from(vec![
+            Some(false),
+            None,
+            None,
+            Some(false),
+        ]));
+
+        let mut accs = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&acc_indices),
+            &[values],
+            IdxSelection::Range(0, 8),
+        )?;
+        let mut merging_accs = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut merging_accs,
+            IdxSelection::Range(0, 4),
+            &[merging_values],
+            IdxSelection::Range(0, 4),
+        )?;
+        merging_accs.resize(5);
+
+        // spill the merging accumulators and merge
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        merging_accs.spill(IdxSelection::Range(0, 5), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut unspilled = agg.create_acc_column(0);
+        unspilled.unspill(5, &mut spill.get_compressed_reader())?;
+
+        agg.partial_merge(
+            &mut accs,
+            IdxSelection::Range(0, 5),
+            &mut unspilled,
+            IdxSelection::Range(0, 5),
+        )?;
+        agg.final_merge(&mut accs, IdxSelection::Range(0, 5))
+    }
+
+    #[test]
+    fn test_bool_and() -> Result<()> {
+        let agg = AggBoolAnd::try_new(Arc::new(Column::new("a", 0)))?;
+        let result = evaluate(&agg)?;
+        let expected: ArrayRef = Arc::new(BooleanArray::from(vec![
+            Some(false),
+            Some(true),
+            None,
+            Some(false),
+            Some(false),
+        ]));
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bool_or() -> Result<()> {
+        let agg = AggBoolOr::try_new(Arc::new(Column::new("a", 0)))?;
+        let result = evaluate(&agg)?;
+        let expected: ArrayRef = Arc::new(BooleanArray::from(vec![
+            Some(true),
+            Some(true),
+            None,
+            Some(false),
+            Some(false),
+        ]));
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+}
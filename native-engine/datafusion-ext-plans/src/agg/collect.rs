@@ -14,6 +14,8 @@
 
 use std::{
     any::Any,
+    cmp::Reverse,
+    collections::BinaryHeap,
     fmt::{Debug, Formatter},
     hash::BuildHasher,
     io::{Cursor, Read, Write},
@@ -36,7 +38,7 @@ use smallvec::SmallVec;
 
 use crate::{
     agg::{
-        acc::{AccColumn, AccColumnRef},
+        acc::{compact_items, AccColumn, AccColumnRef},
         agg::{Agg, IdxSelection},
     },
     idx_for, idx_for_zipped,
@@ -109,6 +111,11 @@ impl<C: AccCollectionColumn> Agg for AggGenericCollect<C> {
         false
     }
 
+    fn expected_partial_state_size_bytes_per_group(&self) -> usize {
+        // collections are variable-sized, buffers grow on demand
+        0
+    }
+
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
         let mut col = Box::new(C::empty(self.arg_type.clone()));
         col.resize(num_rows);
@@ -219,17 +226,17 @@ impl AccCollectionColumn for AccSetColumn {
     }
 
     fn append_item(&mut self, idx: usize, value: &ScalarValue) {
-        let old_mem_size = self.set[idx].mem_size();
+        // mem size may shrink when a sorted set is converted into a hashed set
+        self.mem_used -= self.set[idx].mem_size();
         self.set[idx].append(value, false);
-        self.mem_used += self.set[idx].mem_size() - old_mem_size;
+        self.mem_used += self.set[idx].mem_size();
     }
 
     fn merge_items(&mut self, idx: usize, other: &mut Self, other_idx: usize) {
-        let self_value_mem_size = self.set[idx].mem_size();
-        let other_value_mem_size = other.set[other_idx].mem_size();
+        self.mem_used -= self.set[idx].mem_size();
+        other.mem_used -= other.set[other_idx].mem_size();
         self.set[idx].merge(&mut other.set[other_idx]);
-        self.mem_used += self.set[idx].mem_size() - self_value_mem_size;
-        other.mem_used -= other_value_mem_size;
+        self.mem_used += self.set[idx].mem_size();
     }
 
     fn save_raw(&self, idx: usize, w: &mut impl Write) -> Result<()> {
@@ -242,13 +249,13 @@ impl AccCollectionColumn for AccSetColumn {
         self.mem_used -= self.set[idx].mem_size();
         self.set[idx] = AccSet::default();
 
+        // mem_used is updated when appending items
         let len = read_len(r)?;
         let mut cursor = Cursor::new(read_bytes_slice(r, len)?);
         while cursor.position() < len as u64 {
             let scalar = read_scalar(&mut cursor, &self.dt, false)?;
             self.append_item(idx, &scalar);
         }
-        self.mem_used += self.set[idx].mem_size();
         Ok(())
     }
 
@@ -260,6 +267,41 @@ impl AccCollectionColumn for AccSetColumn {
     }
 }
 
+impl AccSetColumn {
+    // spill format: number of items, followed by length-prefixed raw items
+    // sorted by raw bytes
+    fn save_sorted(&self, idx: usize, w: &mut impl Write) -> Result<()> {
+        let set = &self.set[idx];
+        let sorted_pos_lens = set.sorted_pos_lens();
+        write_len(sorted_pos_lens.len(), w)?;
+        for pos_len in sorted_pos_lens {
+            let raw = set.list.ref_raw(pos_len);
+            write_len(raw.len(), w)?;
+            w.write_all(raw)?;
+        }
+        Ok(())
+    }
+
+    fn load_sorted(&mut self, idx: usize, r: &mut impl Read) -> Result<()> {
+        self.mem_used -= self.set[idx].mem_size();
+
+        let num_items = read_len(r)?;
+        let mut set = AccSet::default();
+        let mut sorted_pos_lens = Vec::with_capacity(num_items);
+        for _ in 0..num_items {
+            let len = read_len(r)?;
+            let pos = set.list.raw.len();
+            set.list.raw.resize(pos + len, 0);
+            r.read_exact(&mut set.list.raw[pos..])?;
+            sorted_pos_lens.push((pos as u32, len as u32));
+        }
+        set.set = InternalSet::Sorted(sorted_pos_lens);
+        self.mem_used += set.mem_size();
+        self.set[idx] = set;
+        Ok(())
+    }
+}
+
 impl AccColumn for AccSetColumn {
     fn as_any(&self) -> &dyn Any {
         self
@@ -271,14 +313,18 @@ impl AccColumn for AccSetColumn {
 
     fn resize(&mut self, len: usize) {
         if len < self.set.len() {
-            for idx in len..self.set.len() {
-                self.mem_used -= self.set[idx].mem_size();
-                self.set[idx] = AccSet::default();
-            }
+            self.fill_null_range(len, self.set.len());
         }
         self.set.resize_with(len, || AccSet::default());
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.mem_used -= self.set[idx].mem_size();
+            self.set[idx] = AccSet::default();
+        }
+    }
+
     fn shrink_to_fit(&mut self) {
         self.set.shrink_to_fit();
     }
@@ -300,9 +346,11 @@ impl AccColumn for AccSetColumn {
     }
 
     fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // items are spilled in sorted order, so that sets from different
+        // spilled runs can be merged with a streaming dedup-merge
         idx_for! {
             (idx in idx) => {
-                self.save_raw(idx, w)?;
+                self.save_sorted(idx, w)?;
             }
         }
         Ok(())
@@ -313,10 +361,16 @@ impl AccColumn for AccSetColumn {
         self.resize(num_rows);
 
         for idx in 0..num_rows {
-            self.load_raw(idx, r)?;
+            self.load_sorted(idx, r)?;
         }
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.set, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
 }
 
 pub struct AccListColumn {
@@ -383,14 +437,18 @@ impl AccColumn for AccListColumn {
 
     fn resize(&mut self, len: usize) {
         if len < self.list.len() {
-            for idx in len..self.list.len() {
-                self.mem_used -= self.list[idx].mem_size();
-                self.list[idx] = AccList::default();
-            }
+            self.fill_null_range(len, self.list.len());
         }
         self.list.resize_with(len, || AccList::default());
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.mem_used -= self.list[idx].mem_size();
+            self.list[idx] = AccList::default();
+        }
+    }
+
     fn shrink_to_fit(&mut self) {
         self.list.shrink_to_fit();
     }
@@ -428,6 +486,12 @@ impl AccColumn for AccListColumn {
         }
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.list, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
 }
 
 #[derive(Clone, Default)]
@@ -466,6 +530,10 @@ impl AccList {
     fn ref_raw(&self, pos_len: (u32, u32)) -> &[u8] {
         &self.raw[pos_len.0 as usize..][..pos_len.1 as usize]
     }
+
+    fn raw_items<'a>(&'a self, pos_lens: &'a [(u32, u32)]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        pos_lens.iter().map(|&pos_len| self.ref_raw(pos_len))
+    }
 }
 
 #[derive(Clone, Default)]
@@ -478,6 +546,9 @@ struct AccSet {
 enum InternalSet {
     Small(SmallVec<(u32, u32), 4>),
     Huge(RawTable<(u32, u32)>),
+    // unique items sorted by raw bytes, created when unspilling. converted
+    // into a hashed set on the first appending
+    Sorted(Vec<(u32, u32)>),
 }
 
 impl Default for InternalSet {
@@ -491,13 +562,18 @@ impl InternalSet {
         match self {
             InternalSet::Small(s) => s.len(),
             InternalSet::Huge(s) => s.len(),
+            InternalSet::Sorted(s) => s.len(),
         }
     }
 
-    fn capacity(&self) -> usize {
+    // inline items of a small set are not counted, so that an empty set
+    // takes no memory, as expected by resize() and fill_null_range()
+    fn heap_capacity(&self) -> usize {
         match self {
+            InternalSet::Small(s) if !s.spilled() => 0,
             InternalSet::Small(s) => s.capacity(),
             InternalSet::Huge(s) => s.capacity(),
+            InternalSet::Sorted(s) => s.capacity(),
         }
     }
 
@@ -505,10 +581,30 @@ impl InternalSet {
         let iter: Box<dyn Iterator<Item = (u32, u32)>> = match self {
             InternalSet::Small(s) => Box::new(s.into_iter()),
             InternalSet::Huge(s) => Box::new(s.into_iter()),
+            InternalSet::Sorted(s) => Box::new(s.into_iter()),
+        };
+        iter
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let iter: Box<dyn Iterator<Item = (u32, u32)> + '_> = match self {
+            InternalSet::Small(s) => Box::new(s.iter().copied()),
+            InternalSet::Huge(s) => Box::new(unsafe {
+                // safety: the table is not modified while iterating
+                s.iter().map(|bucket| *bucket.as_ref())
+            }),
+            InternalSet::Sorted(s) => Box::new(s.iter().copied()),
         };
         iter
     }
 
+    fn convert_to_hashed_if_sorted(&mut self, list: &mut AccList) {
+        if let Self::Sorted(s) = self {
+            *self = Self::Small(SmallVec::from_vec(std::mem::take(s)));
+            self.convert_to_huge_if_needed(list);
+        }
+    }
+
     fn convert_to_huge_if_needed(&mut self, list: &mut AccList) {
         if let Self::Small(s) = self
             && s.len() >= 4
@@ -528,7 +624,7 @@ impl InternalSet {
 impl AccSet {
     pub fn mem_size(&self) -> usize {
         // mem size of internal set is estimated for faster computation
-        self.list.mem_size() + self.set.capacity() * size_of::<u128>()
+        self.list.mem_size() + self.set.heap_capacity() * size_of::<u128>()
     }
 
     pub fn append(&mut self, value: &ScalarValue, nullable: bool) {
@@ -538,6 +634,29 @@ impl AccSet {
     }
 
     pub fn merge(&mut self, other: &mut Self) {
+        if let (InternalSet::Sorted(self_sorted), InternalSet::Sorted(other_sorted)) =
+            (&self.set, &other.set)
+        {
+            // both sets are unspilled from sorted runs, dedup-merge them
+            // without building hash tables
+            let mut merged = AccSet::default();
+            let mut merged_pos_lens = Vec::with_capacity(self_sorted.len().max(other_sorted.len()));
+            merge_sorted_runs(
+                [
+                    self.list.raw_items(self_sorted),
+                    other.list.raw_items(other_sorted),
+                ],
+                |raw| {
+                    merged_pos_lens.push((merged.list.raw.len() as u32, raw.len() as u32));
+                    merged.list.raw.extend_from_slice(raw);
+                },
+            );
+            merged.set = InternalSet::Sorted(merged_pos_lens);
+            *self = merged;
+            *other = AccSet::default();
+            return;
+        }
+
         if self.set.len() < other.set.len() {
             // ensure the probed set is smaller
             std::mem::swap(self, other);
@@ -545,13 +664,26 @@ impl AccSet {
         for pos_len in std::mem::take(&mut other.set).into_iter() {
             self.append_raw(other.list.ref_raw(pos_len));
         }
+        // release buffered items of the merged set, which are no longer
+        // accounted by mem_used
+        *other = AccSet::default();
     }
 
     pub fn into_values(self, dt: DataType, nullable: bool) -> impl Iterator<Item = ScalarValue> {
         self.list.into_values(dt, nullable)
     }
 
+    fn sorted_pos_lens(&self) -> Vec<(u32, u32)> {
+        if let InternalSet::Sorted(s) = &self.set {
+            return s.clone();
+        }
+        let mut pos_lens = self.set.iter().collect::<Vec<_>>();
+        pos_lens.sort_unstable_by(|&a, &b| self.list.ref_raw(a).cmp(self.list.ref_raw(b)));
+        pos_lens
+    }
+
     fn append_raw(&mut self, raw: &[u8]) {
+        self.set.convert_to_hashed_if_sorted(&mut self.list);
         let new_len = raw.len();
         let new_pos_len = (self.list.raw.len() as u32, new_len as u32);
 
@@ -591,6 +723,7 @@ impl AccSet {
     }
 
     fn append_raw_inline(&mut self, raw_start: usize) {
+        self.set.convert_to_hashed_if_sorted(&mut self.list);
         let new_len = self.list.raw.len() - raw_start;
         let new_pos_len = (raw_start as u32, new_len as u32);
         let mut inserted = true;
@@ -638,8 +771,35 @@ impl AccSet {
     }
 }
 
+/// merges runs of unique items sorted by raw bytes into one sorted run of
+/// unique items in a single streaming pass. only the head item of each run is
+/// held at a time.
+pub fn merge_sorted_runs<'a, I: Iterator<Item = &'a [u8]>>(
+    runs: impl IntoIterator<Item = I>,
+    mut output: impl FnMut(&'a [u8]),
+) {
+    let mut runs = runs.into_iter().collect::<Vec<_>>();
+    let mut heads = BinaryHeap::with_capacity(runs.len());
+    for (run_idx, run) in runs.iter_mut().enumerate() {
+        if let Some(item) = run.next() {
+            heads.push(Reverse((item, run_idx)));
+        }
+    }
+
+    let mut last_item = None;
+    while let Some(Reverse((item, run_idx))) = heads.pop() {
+        if last_item != Some(item) {
+            output(item);
+            last_item = Some(item);
+        }
+        if let Some(next_item) = runs[run_idx].next() {
+            heads.push(Reverse((next_item, run_idx)));
+        }
+    }
+}
+
 #[inline]
-fn acc_hash(value: impl AsRef<[u8]>) -> u64 {
+pub(crate) fn acc_hash(value: impl AsRef<[u8]>) -> u64 {
     const ACC_HASH_SEED: u32 = 0x7BCB48DA;
     const HASHER: foldhash::fast::FixedState =
         foldhash::fast::FixedState::with_seed(ACC_HASH_SEED as u64);
@@ -649,7 +809,7 @@ fn acc_hash(value: impl AsRef<[u8]>) -> u64 {
 #[cfg(test)]
 mod tests {
     use arrow::datatypes::DataType;
-    use datafusion::common::ScalarValue;
+    use datafusion::{common::ScalarValue, physical_expr::expressions::Column};
 
     use super::*;
     use crate::memmgr::spill::Spill;
@@ -739,4 +899,168 @@ mod tests {
         assert_eq!(acc_col.take_values(1), acc_col_unspill.take_values(1));
         assert_eq!(acc_col.take_values(2), acc_col_unspill.take_values(2));
     }
+
+    #[test]
+    fn test_acc_set_merge_sorted_spills() {
+        // two spilled runs with overlapping items
+        let mut spilled_cols = vec![];
+        for values in [vec![5, 1, 3, 7, 9], vec![4, 3, 9, 1, 10, 2]] {
+            let mut acc_col = AccSetColumn::empty(DataType::Int32);
+            acc_col.resize(1);
+            for v in values {
+                acc_col.append_item(0, &ScalarValue::Int32(Some(v)));
+            }
+            let mut spill: Box<dyn Spill> = Box::new(vec![]);
+            let mut spill_writer = spill.get_compressed_writer();
+            acc_col
+                .spill(IdxSelection::Single(0), &mut spill_writer)
+                .unwrap();
+            spill_writer.finish().unwrap();
+
+            let mut acc_col_unspill = AccSetColumn::empty(DataType::Int32);
+            acc_col_unspill
+                .unspill(1, &mut spill.get_compressed_reader())
+                .unwrap();
+            assert!(matches!(acc_col_unspill.set[0].set, InternalSet::Sorted(_)));
+            spilled_cols.push(acc_col_unspill);
+        }
+
+        let mut merging_col = spilled_cols.pop().unwrap();
+        let mut acc_col = spilled_cols.pop().unwrap();
+        acc_col.merge_items(0, &mut merging_col, 0);
+        assert!(matches!(acc_col.set[0].set, InternalSet::Sorted(_)));
+        assert_eq!(acc_col.set[0].set.len(), 8);
+
+        // appending after merging converts the set into a hashed set
+        acc_col.append_item(0, &ScalarValue::Int32(Some(10)));
+        acc_col.append_item(0, &ScalarValue::Int32(Some(11)));
+        assert_eq!(acc_col.set[0].set.len(), 9);
+
+        let mut values = acc_col
+            .take_values(0)
+            .into_iter()
+            .map(|v| match v {
+                ScalarValue::Int32(Some(v)) => v,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![1, 2, 3, 4, 5, 7, 9, 10, 11]);
+        assert_eq!(acc_col.mem_used, 0);
+        assert_eq!(merging_col.mem_used, 0);
+    }
+
+    #[test]
+    fn test_acc_set_mem_used() -> Result<()> {
+        fn total_mem_size(acc_col: &AccSetColumn) -> usize {
+            acc_col.set.iter().map(|set| set.mem_size()).sum()
+        }
+
+        let mut acc_col = AccSetColumn::empty(DataType::Int32);
+        acc_col.resize(2);
+        for v in 0..100 {
+            acc_col.append_item(0, &ScalarValue::Int32(Some(v)));
+            acc_col.append_item(1, &ScalarValue::Int32(Some(v % 3)));
+        }
+        assert_eq!(acc_col.mem_used, total_mem_size(&acc_col));
+
+        let mut rows = vec![vec![]; 2];
+        AccColumn::freeze_to_rows(&acc_col, IdxSelection::Range(0, 2), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        let mut acc_col_unfrozen = AccSetColumn::empty(DataType::Int32);
+        AccColumn::unfreeze_from_rows(&mut acc_col_unfrozen, &mut cursors)?;
+        assert_eq!(acc_col_unfrozen.mem_used, total_mem_size(&acc_col_unfrozen));
+
+        acc_col.merge_items(1, &mut acc_col_unfrozen, 0);
+        assert_eq!(acc_col.mem_used, total_mem_size(&acc_col));
+        assert_eq!(acc_col_unfrozen.mem_used, total_mem_size(&acc_col_unfrozen));
+
+        acc_col.resize(0);
+        assert_eq!(acc_col.mem_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_list_spill_mid_collection() -> Result<()> {
+        let agg = AggCollectList::try_new(
+            Arc::new(Column::new("v", 0)),
+            DataType::new_list(DataType::Int32, true),
+            DataType::Int32,
+        )?;
+
+        // group 0 is a hot group collecting most of the values
+        let input: ArrayRef = Arc::new(Int32Array::from_iter_values(0..10000));
+        let acc_indices = (0..10000)
+            .map(|i| if i % 100 == 0 { 1 } else { 0 })
+            .collect::<Vec<usize>>();
+
+        // collect the first half, then spill
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&acc_indices[..5000]),
+            &[input.clone()],
+            IdxSelection::Range(0, 5000),
+        )?;
+        assert!(accs.mem_used() >= 5000 * size_of::<i32>());
+
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 2), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        // collect the second half into a new in-mem accumulator
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&acc_indices[5000..]),
+            &[input.clone()],
+            IdxSelection::Range(5000, 10000),
+        )?;
+
+        // reload the spilled accumulator and merge the in-mem one into it
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(2, &mut spill.get_compressed_reader())?;
+        agg.partial_merge(
+            &mut spilled_accs,
+            IdxSelection::Range(0, 2),
+            &mut accs,
+            IdxSelection::Range(0, 2),
+        )?;
+        assert_eq!(downcast_any!(accs, AccListColumn)?.mem_used, 0);
+
+        let output = agg.final_merge(&mut spilled_accs, IdxSelection::Range(0, 2))?;
+        let output = output.as_list::<i32>();
+        assert_eq!(
+            output.value(0).as_primitive::<Int32Type>(),
+            &Int32Array::from_iter_values((0..10000).filter(|i| i % 100 != 0)),
+        );
+        assert_eq!(
+            output.value(1).as_primitive::<Int32Type>(),
+            &Int32Array::from_iter_values((0..10000).step_by(100)),
+        );
+        assert_eq!(downcast_any!(spilled_accs, AccListColumn)?.mem_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_sorted_runs() {
+        let runs = vec![
+            vec!["a", "c", "e"],
+            vec!["b", "c", "d"],
+            vec![],
+            vec!["a", "f"],
+        ];
+        let mut merged = vec![];
+        merge_sorted_runs(
+            runs.iter()
+                .map(|run| run.iter().map(|item| item.as_bytes())),
+            |item| merged.push(item),
+        );
+        let expected = ["a", "b", "c", "d", "e", "f"].map(str::as_bytes);
+        assert_eq!(merged, expected);
+    }
 }
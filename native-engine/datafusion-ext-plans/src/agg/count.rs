@@ -15,29 +15,132 @@
 use std::{
     any::Any,
     fmt::{Debug, Formatter},
-    io::Cursor,
-    sync::Arc,
+    io::{Cursor, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
 };
 
-use arrow::{array::*, datatypes::*};
+use arrow::{array::*, buffer::NullBuffer, datatypes::*};
+use bitvec::vec::BitVec;
+use blaze_jni_bridge::{
+    conf::{self, IntConf, StringConf},
+    is_jni_bridge_inited,
+};
+use byteorder::{ReadBytesExt, WriteBytesExt};
 use datafusion::{common::Result, physical_expr::PhysicalExpr};
 use datafusion_ext_commons::{
-    downcast_any,
-    io::{read_len, write_len},
+    df_execution_err, downcast_any,
+    io::{read_len_u64, write_len_u64},
+    SliceAsRawBytes,
 };
 
 use crate::{
     agg::{
-        acc::{AccColumn, AccColumnRef},
+        acc::{compact_bits, compact_items, AccColumn, AccColumnRef},
         agg::{Agg, IdxSelection},
     },
     idx_for, idx_for_zipped, idx_with_iter,
     memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountMode {
+    /// counts rows where all children are non-null, like spark's count(a, b)
+    AllNonNull,
+
+    /// counts non-null values of each child independently, the result is a
+    /// struct of int64 counts, one field per child
+    PerColumn,
+}
+
+/// behavior when a counter exceeds i64::MAX
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CountOverflowBehavior {
+    /// fails with an execution error
+    Error,
+
+    /// keeps the counter at i64::MAX and logs a warning, default behavior
+    #[default]
+    Saturate,
+
+    /// wraps around silently
+    Wrap,
+}
+
+impl CountOverflowBehavior {
+    /// reads the behavior from spark.blaze.agg.count.overflowBehavior, falls
+    /// back to the default behavior if the conf is empty or not available
+    pub fn from_conf() -> Result<Self> {
+        if !is_jni_bridge_inited() {
+            return Ok(Self::default());
+        }
+        Self::parse(&conf::COUNT_OVERFLOW_BEHAVIOR.value()?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" => Ok(Self::default()),
+            "error" => Ok(Self::Error),
+            "saturate" => Ok(Self::Saturate),
+            "wrap" => Ok(Self::Wrap),
+            other => df_execution_err!("unsupported count overflow behavior: {other}"),
+        }
+    }
+
+    #[inline]
+    pub fn add(self, count: &mut i64, delta: i64) -> Result<()> {
+        match count.checked_add(delta) {
+            Some(sum) => {
+                *count = sum;
+                Ok(())
+            }
+            None => self.handle_overflow(count, delta),
+        }
+    }
+
+    #[cold]
+    fn handle_overflow(self, count: &mut i64, delta: i64) -> Result<()> {
+        match self {
+            Self::Error => {
+                return df_execution_err!("count overflow: {count} + {delta} exceeds i64::MAX");
+            }
+            Self::Saturate => {
+                // saturated counters overflow again on every update, so warn
+                // only once
+                static WARNED: AtomicBool = AtomicBool::new(false);
+                if !WARNED.swap(true, Relaxed) {
+                    log::warn!("count overflow: {count} + {delta} exceeds i64::MAX, saturated");
+                }
+                *count = i64::MAX;
+            }
+            Self::Wrap => *count = count.wrapping_add(delta),
+        }
+        Ok(())
+    }
+}
+
+/// default min number of rows in a range selection for partial_update() to
+/// take the bulk path. smaller selections are updated row by row, which avoids
+/// the overhead of combining null buffers. see bench_count_bulk_update().
+pub const DEFAULT_BULK_UPDATE_THRESHOLD: usize = 64;
+
+/// reads the threshold from spark.blaze.agg.count.bulkUpdateMinRows, falls
+/// back to the default threshold if the conf is not available
+pub fn bulk_update_threshold_from_conf() -> Result<usize> {
+    if !is_jni_bridge_inited() {
+        return Ok(DEFAULT_BULK_UPDATE_THRESHOLD);
+    }
+    Ok(conf::COUNT_BULK_UPDATE_MIN_ROWS.value()?.max(1) as usize)
+}
+
 pub struct AggCount {
     children: Vec<Arc<dyn PhysicalExpr>>,
     data_type: DataType,
+    mode: CountMode,
+    bulk_update_threshold: usize,
+    overflow_behavior: CountOverflowBehavior,
 }
 
 impl AggCount {
@@ -46,13 +149,124 @@ impl AggCount {
         Ok(Self {
             children,
             data_type,
+            mode: CountMode::AllNonNull,
+            bulk_update_threshold: DEFAULT_BULK_UPDATE_THRESHOLD,
+            overflow_behavior: CountOverflowBehavior::default(),
         })
     }
+
+    pub fn try_new_per_column(
+        children: Vec<Arc<dyn PhysicalExpr>>,
+        data_type: DataType,
+    ) -> Result<Self> {
+        let num_fields = match &data_type {
+            DataType::Struct(fields)
+                if fields.iter().all(|f| f.data_type() == &DataType::Int64) =>
+            {
+                fields.len()
+            }
+            other => return df_execution_err!("expect struct of int64 counts, got {other:?}"),
+        };
+        if children.is_empty() || num_fields != children.len() {
+            return df_execution_err!(
+                "per-column count expects one field per child, got {} fields for {} children",
+                num_fields,
+                children.len(),
+            );
+        }
+        Ok(Self {
+            children,
+            data_type,
+            mode: CountMode::PerColumn,
+            bulk_update_threshold: DEFAULT_BULK_UPDATE_THRESHOLD,
+            overflow_behavior: CountOverflowBehavior::default(),
+        })
+    }
+
+    pub fn with_bulk_update_threshold(mut self, bulk_update_threshold: usize) -> Self {
+        self.bulk_update_threshold = bulk_update_threshold;
+        self
+    }
+
+    pub fn with_overflow_behavior(mut self, overflow_behavior: CountOverflowBehavior) -> Self {
+        self.overflow_behavior = overflow_behavior;
+        self
+    }
+
+    /// updates counts in bulk if both selections are ranges, one of which may
+    /// be a single accumulator. returns false if the selections are not
+    /// eligible, in which case nothing is updated.
+    fn try_bulk_update(
+        &self,
+        accs: &mut AccCountColumn,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<bool> {
+        let IdxSelection::Range(begin, end) = partial_arg_idx else {
+            return Ok(false);
+        };
+        let len = end - begin;
+        if len < self.bulk_update_threshold.max(1) {
+            return Ok(false);
+        }
+
+        // rows counted only if all args are valid
+        let valids = partial_args
+            .iter()
+            .map(|arg| arg.nulls().map(|nulls| nulls.slice(begin, len)))
+            .fold(None, |valids, nulls| {
+                NullBuffer::union(valids.as_ref(), nulls.as_ref())
+            });
+
+        match acc_idx {
+            IdxSelection::Single(idx) => {
+                let num_valids = len - valids.map(|v| v.null_count()).unwrap_or(0);
+                self.overflow_behavior
+                    .add(&mut accs.values[idx], num_valids as i64)?;
+            }
+            IdxSelection::Range(acc_begin, acc_end) if acc_end - acc_begin == len => {
+                let values = &mut accs.values[acc_begin..acc_end];
+
+                // each count grows by at most 1, so only counts at i64::MAX can
+                // overflow. checking them first keeps the common loop free of
+                // overflow branches.
+                if values.iter().any(|&value| value == i64::MAX) {
+                    match valids {
+                        Some(valids) => {
+                            for (value, valid) in values.iter_mut().zip(valids.iter()) {
+                                self.overflow_behavior.add(value, valid as i64)?;
+                            }
+                        }
+                        None => {
+                            for value in values.iter_mut() {
+                                self.overflow_behavior.add(value, 1)?;
+                            }
+                        }
+                    }
+                    return Ok(true);
+                }
+                match valids {
+                    Some(valids) => {
+                        for (value, valid) in values.iter_mut().zip(valids.iter()) {
+                            *value += valid as i64;
+                        }
+                    }
+                    None => values.iter_mut().for_each(|value| *value += 1),
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 }
 
 impl Debug for AggCount {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Count({:?})", self.children)
+        match self.mode {
+            CountMode::AllNonNull => write!(f, "Count({:?})", self.children),
+            CountMode::PerColumn => write!(f, "CountPerColumn({:?})", self.children),
+        }
     }
 }
 
@@ -66,10 +280,16 @@ impl Agg for AggCount {
     }
 
     fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
-        Ok(Arc::new(Self::try_new(
-            exprs.clone(),
-            self.data_type.clone(),
-        )?))
+        Ok(Arc::new(
+            match self.mode {
+                CountMode::AllNonNull => Self::try_new(exprs.clone(), self.data_type.clone())?,
+                CountMode::PerColumn => {
+                    Self::try_new_per_column(exprs.clone(), self.data_type.clone())?
+                }
+            }
+            .with_bulk_update_threshold(self.bulk_update_threshold)
+            .with_overflow_behavior(self.overflow_behavior),
+        ))
     }
 
     fn data_type(&self) -> &DataType {
@@ -80,10 +300,28 @@ impl Agg for AggCount {
         false
     }
 
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
+    fn expected_partial_state_size_bytes_per_group(&self) -> usize {
+        // counts are varint-encoded after a null flag, rarely exceeding 8 bytes
+        match self.mode {
+            CountMode::AllNonNull => 8,
+            CountMode::PerColumn => 8 * self.children.len(),
+        }
+    }
+
     fn create_acc_column(&self, num_rows: usize) -> Box<dyn AccColumn> {
-        Box::new(AccCountColumn {
-            values: vec![0; num_rows],
-        })
+        match self.mode {
+            CountMode::AllNonNull => Box::new(AccCountColumn {
+                values: vec![0; num_rows],
+                nulls: BitVec::new(),
+            }),
+            CountMode::PerColumn => {
+                Box::new(AccCountMatrixColumn::new(self.children.len(), num_rows))
+            }
+        }
     }
 
     fn partial_update(
@@ -93,16 +331,35 @@ impl Agg for AggCount {
         partial_args: &[ArrayRef],
         partial_arg_idx: IdxSelection<'_>,
     ) -> Result<()> {
+        if self.mode == CountMode::PerColumn {
+            let accs = downcast_any!(accs, mut AccCountMatrixColumn)?;
+            accs.ensure_size(acc_idx);
+
+            idx_for_zipped! {
+                ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                    let counts = accs.counts_mut(acc_idx);
+                    for (count, arg) in counts.iter_mut().zip(partial_args) {
+                        self.overflow_behavior
+                            .add(count, arg.is_valid(partial_arg_idx) as i64)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
         let accs = downcast_any!(accs, mut AccCountColumn)?;
         accs.ensure_size(acc_idx);
 
+        if self.try_bulk_update(accs, acc_idx, partial_args, partial_arg_idx)? {
+            return Ok(());
+        }
         if partial_args.is_empty() {
             idx_for_zipped! {
                 ((acc_idx, _partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
                     if acc_idx >= accs.values.len() {
                         accs.values.push(1);
                     } else {
-                        accs.values[acc_idx] += 1;
+                        self.overflow_behavior.add(&mut accs.values[acc_idx], 1)?;
                     }
                 }
             }
@@ -116,7 +373,7 @@ impl Agg for AggCount {
                     if acc_idx >= accs.values.len() {
                         accs.values.push(add);
                     } else {
-                        accs.values[acc_idx] += add;
+                        self.overflow_behavior.add(&mut accs.values[acc_idx], add)?;
                     }
                 }
             }
@@ -131,6 +388,23 @@ impl Agg for AggCount {
         merging_accs: &mut AccColumnRef,
         merging_acc_idx: IdxSelection<'_>,
     ) -> Result<()> {
+        if self.mode == CountMode::PerColumn {
+            let accs = downcast_any!(accs, mut AccCountMatrixColumn)?;
+            let merging_accs = downcast_any!(merging_accs, mut AccCountMatrixColumn)?;
+            accs.ensure_size(acc_idx);
+
+            idx_for_zipped! {
+                ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                    let merging_counts = merging_accs.counts(merging_acc_idx);
+                    let counts = accs.counts_mut(acc_idx);
+                    for (count, merging_count) in counts.iter_mut().zip(merging_counts) {
+                        self.overflow_behavior.add(count, *merging_count)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
         let accs = downcast_any!(accs, mut AccCountColumn)?;
         let merging_accs = downcast_any!(merging_accs, mut AccCountColumn)?;
         accs.ensure_size(acc_idx);
@@ -138,7 +412,8 @@ impl Agg for AggCount {
         idx_for_zipped! {
             ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
                 if acc_idx < accs.values.len() {
-                    accs.values[acc_idx] += merging_accs.values[merging_acc_idx];
+                    self.overflow_behavior
+                        .add(&mut accs.values[acc_idx], merging_accs.values[merging_acc_idx])?;
                 } else {
                     accs.values.push(merging_accs.values[merging_acc_idx]);
                 }
@@ -148,12 +423,35 @@ impl Agg for AggCount {
     }
 
     fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        if self.mode == CountMode::PerColumn {
+            let accs = downcast_any!(accs, mut AccCountMatrixColumn)?;
+            let DataType::Struct(fields) = &self.data_type else {
+                unreachable!("per-column count must return a struct");
+            };
+            let columns = (0..accs.num_columns)
+                .map(|col| {
+                    idx_with_iter! {
+                        (acc_idx_iter @ acc_idx) => {
+                            Arc::new(Int64Array::from_iter_values(
+                                acc_idx_iter.map(|idx| accs.counts(idx)[col])
+                            )) as ArrayRef
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            return Ok(Arc::new(StructArray::try_new(
+                fields.clone(),
+                columns,
+                None,
+            )?));
+        }
+
         let accs = downcast_any!(accs, mut AccCountColumn)?;
 
         idx_with_iter! {
             (acc_idx_iter @ acc_idx) => {
-                Ok(Arc::new(Int64Array::from_iter_values(
-                    acc_idx_iter.map(|idx| accs.values[idx])
+                Ok(Arc::new(Int64Array::from_iter(
+                    acc_idx_iter.map(|idx| (!accs.is_null(idx)).then_some(accs.values[idx]))
                 )))
             }
         }
@@ -162,6 +460,10 @@ impl Agg for AggCount {
 
 pub struct AccCountColumn {
     pub values: Vec<i64>,
+
+    /// accumulators marked by `set_null()`. the mask is lazily grown and may
+    /// be shorter than `values`, missing bits are treated as non-null.
+    nulls: BitVec,
 }
 
 impl AccColumn for AccCountColumn {
@@ -175,10 +477,33 @@ impl AccColumn for AccCountColumn {
 
     fn resize(&mut self, num_accs: usize) {
         self.values.resize(num_accs, 0);
+        self.nulls.truncate(num_accs);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start..end].fill(0);
+        if start < self.nulls.len() {
+            let end = end.min(self.nulls.len());
+            self.nulls[start..end].fill(false);
+        }
+    }
+
+    fn set_null(&mut self, acc_idx: usize) -> Result<()> {
+        if self.nulls.len() <= acc_idx {
+            self.nulls.resize(self.values.len().max(acc_idx + 1), false);
+        }
+        self.values[acc_idx] = 0;
+        self.nulls.set(acc_idx, true);
+        Ok(())
+    }
+
+    fn is_null(&self, acc_idx: usize) -> bool {
+        self.nulls.get(acc_idx).is_some_and(|null| *null)
     }
 
     fn shrink_to_fit(&mut self) {
         self.values.shrink_to_fit();
+        self.nulls.shrink_to_fit();
     }
 
     fn num_records(&self) -> usize {
@@ -186,15 +511,22 @@ impl AccColumn for AccCountColumn {
     }
 
     fn mem_used(&self) -> usize {
-        self.values.capacity() * 2 * size_of::<i64>()
+        self.values.capacity() * 2 * size_of::<i64>() + (self.nulls.capacity() + 7) / 8
     }
 
     fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        // 0: valid count, 1: marked null by set_null()
         let mut array_idx = 0;
 
         idx_for! {
             (idx in idx) => {
-                write_len(self.values[idx] as usize, &mut array[array_idx])?;
+                let w = &mut array[array_idx];
+                if self.is_null(idx) {
+                    w.write_u8(1)?;
+                } else {
+                    w.write_u8(0)?;
+                    write_len_u64(self.values[idx] as u64, w)?;
+                }
                 array_idx += 1;
             }
         }
@@ -203,26 +535,555 @@ impl AccColumn for AccCountColumn {
 
     fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
         assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.nulls.clear();
         for cursor in cursors {
-            self.values.push(read_len(cursor)? as i64);
+            if cursor.read_u8()? == 1 {
+                self.nulls.resize(self.values.len(), false);
+                self.nulls.push(true);
+                self.values.push(0);
+            } else {
+                self.values.push(read_len_u64(cursor)? as i64);
+            }
         }
         Ok(())
     }
 
     fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        let mut null_bits: BitVec<u8> = BitVec::with_capacity(idx.len());
         idx_for! {
             (idx in idx) => {
-                write_len(self.values[idx] as usize, w)?;
+                write_len_u64(self.values[idx] as u64, w)?;
+                null_bits.push(self.is_null(idx));
             }
         }
+
+        // write nulls marked by set_null(), omitted if there are none
+        let has_nulls = null_bits.any();
+        w.write_u8(has_nulls as u8)?;
+        if has_nulls {
+            w.write_all(null_bits.as_raw_slice())?;
+        }
         Ok(())
     }
 
     fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
         assert_eq!(self.num_records(), 0, "expect empty AccColumn");
         for _ in 0..num_rows {
-            self.values.push(read_len(r)? as i64);
+            self.values.push(read_len_u64(r)? as i64);
+        }
+
+        // read nulls
+        self.nulls.clear();
+        if r.read_u8()? == 1 {
+            let mut null_bits: BitVec<u8> = BitVec::repeat(false, num_rows);
+            r.read_exact(null_bits.as_raw_mut_slice())?;
+            self.nulls.extend_from_bitslice(null_bits.as_bitslice());
+        }
+        Ok(())
+    }
+
+    fn freeze_to_writer(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // fixed-width values are directly written without buffering rows
+        self.spill(idx, w)
+    }
+
+    fn unfreeze_from_reader(
+        &mut self,
+        num_rows: usize,
+        r: &mut SpillCompressedReader,
+    ) -> Result<()> {
+        self.unspill(num_rows, r)
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.values, retained);
+        if !self.nulls.is_empty() {
+            self.nulls.resize(self.values.len(), false);
+            compact_bits(&mut self.nulls, retained);
+        }
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+/// counters of per-column count, stored row-major with `num_columns`
+/// counters per record.
+pub struct AccCountMatrixColumn {
+    num_columns: usize,
+    values: Vec<i64>,
+}
+
+impl AccCountMatrixColumn {
+    pub fn new(num_columns: usize, num_records: usize) -> Self {
+        Self {
+            num_columns,
+            values: vec![0; num_columns * num_records],
         }
+    }
+
+    pub fn counts(&self, idx: usize) -> &[i64] {
+        &self.values[idx * self.num_columns..][..self.num_columns]
+    }
+
+    pub fn counts_mut(&mut self, idx: usize) -> &mut [i64] {
+        &mut self.values[idx * self.num_columns..][..self.num_columns]
+    }
+}
+
+impl AccColumn for AccCountMatrixColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, num_accs: usize) {
+        self.values.resize(num_accs * self.num_columns, 0);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start * self.num_columns..end * self.num_columns].fill(0);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.values.len() / self.num_columns
+    }
+
+    fn mem_used(&self) -> usize {
+        self.values.capacity() * 2 * size_of::<i64>()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                for &count in self.counts(idx) {
+                    write_len_u64(count as u64, &mut array[array_idx])?;
+                }
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        for cursor in cursors {
+            for _ in 0..self.num_columns {
+                self.values.push(read_len_u64(cursor)? as i64);
+            }
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // counters of each record are written as fixed-width values
+        idx_for! {
+            (idx in idx) => {
+                w.write_all(self.counts(idx).as_raw_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(num_rows);
+        r.read_exact(self.values.as_raw_bytes_mut())?;
+        Ok(())
+    }
+
+    fn freeze_to_writer(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // fixed-width values are directly written without buffering rows
+        self.spill(idx, w)
+    }
+
+    fn unfreeze_from_reader(
+        &mut self,
+        num_rows: usize,
+        r: &mut SpillCompressedReader,
+    ) -> Result<()> {
+        self.unspill(num_rows, r)
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        let num_columns = self.num_columns;
+        for (new_idx, &idx) in retained.iter().enumerate() {
+            self.values.copy_within(
+                idx * num_columns..(idx + 1) * num_columns,
+                new_idx * num_columns,
+            );
+        }
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Cursor, sync::Arc, time::Instant};
+
+    use arrow::{
+        array::{ArrayRef, AsArray, Int32Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Fields, Int64Type},
+    };
+    use bitvec::vec::BitVec;
+    use datafusion::{
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalExpr},
+    };
+    use datafusion_ext_commons::downcast_any;
+
+    use crate::{
+        agg::{
+            acc::{AccColumn, FREEZE_ROW_GROUP_SIZE},
+            agg::{Agg, IdxSelection},
+            count::{AccCountColumn, AccCountMatrixColumn, AggCount, CountOverflowBehavior},
+        },
+        memmgr::spill::Spill,
+    };
+
+    #[test]
+    fn test_count_freeze_to_writer() -> Result<()> {
+        let num_rows = FREEZE_ROW_GROUP_SIZE * 2 + 17;
+        let acc_col = AccCountColumn {
+            values: (0..num_rows as i64).map(|i| i * 1000).collect(),
+            nulls: BitVec::new(),
+        };
+
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        acc_col.freeze_to_writer(IdxSelection::Range(0, num_rows), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        let mut unfreezed = AccCountColumn {
+            values: vec![],
+            nulls: BitVec::new(),
+        };
+        unfreezed.unfreeze_from_reader(num_rows, &mut spill.get_compressed_reader())?;
+        assert_eq!(unfreezed.values, acc_col.values);
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_nulls_persisted() -> Result<()> {
+        // a wrapped count of -1 is not confused with null accumulators
+        let mut acc_col = AccCountColumn {
+            values: vec![-1, 5, 0, i64::MIN],
+            nulls: BitVec::new(),
+        };
+        acc_col.set_null(2)?;
+
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        acc_col.spill(IdxSelection::Range(0, 4), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut unspilled = AccCountColumn {
+            values: vec![],
+            nulls: BitVec::new(),
+        };
+        unspilled.unspill(4, &mut spill.get_compressed_reader())?;
+
+        let mut rows = vec![vec![]; 4];
+        acc_col.freeze_to_rows(IdxSelection::Range(0, 4), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        let mut unfreezed = AccCountColumn {
+            values: vec![],
+            nulls: BitVec::new(),
+        };
+        unfreezed.unfreeze_from_rows(&mut cursors)?;
+
+        for restored in [unspilled, unfreezed] {
+            assert_eq!(restored.values, vec![-1, 5, 0, i64::MIN]);
+            assert_eq!(
+                (0..4).map(|i| restored.is_null(i)).collect::<Vec<_>>(),
+                vec![false, false, true, false],
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_freeze_to_rows_preallocated() -> Result<()> {
+        let agg = AggCount::try_new(vec![Arc::new(Column::new("a", 0))], DataType::Int64)?;
+        let num_rows = 1000;
+        let acc_col = AccCountColumn {
+            values: (0..num_rows as i64).map(|i| i * 1_000_000_000).collect(),
+            nulls: BitVec::new(),
+        };
+
+        // row buffers allocated with the hint are never reallocated
+        let hint = agg.expected_partial_state_size_bytes_per_group();
+        let mut rows = (0..num_rows)
+            .map(|_| Vec::with_capacity(hint))
+            .collect::<Vec<_>>();
+        let buffers = rows
+            .iter()
+            .map(|row| (row.as_ptr(), row.capacity()))
+            .collect::<Vec<_>>();
+        acc_col.freeze_to_rows(IdxSelection::Range(0, num_rows), &mut rows)?;
+        for (row, buffer) in rows.iter().zip(buffers) {
+            assert!(!row.is_empty());
+            assert_eq!((row.as_ptr(), row.capacity()), buffer);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_bulk_update() -> Result<()> {
+        let num_rows = 1000;
+        let args: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from_iter(
+                (0..num_rows).map(|i| (i % 3 != 0).then_some(i as i32)),
+            )),
+            Arc::new(Int32Array::from_iter(
+                (0..num_rows).map(|i| (i % 5 != 0).then_some(i as i32)),
+            )),
+        ];
+
+        // bulk path (threshold=1) and per-row path must produce the same counts
+        let counts = |threshold: usize, args: &[ArrayRef]| -> Result<Vec<i64>> {
+            let children = (0..args.len())
+                .map(|i| Arc::new(Column::new("a", i)) as Arc<dyn PhysicalExpr>)
+                .collect();
+            let agg =
+                AggCount::try_new(children, DataType::Int64)?.with_bulk_update_threshold(threshold);
+            let mut accs = agg.create_acc_column(0);
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(3),
+                args,
+                IdxSelection::Range(0, num_rows),
+            )?;
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Range(0, 500),
+                args,
+                IdxSelection::Range(100, 600),
+            )?;
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Range(10, 20),
+                args,
+                IdxSelection::Range(990, 1000),
+            )?;
+            Ok(downcast_any!(accs, AccCountColumn)?.values.clone())
+        };
+        for args in [&args[..], &args[..1], &[]] {
+            let bulk_counts = counts(1, args)?;
+            assert_eq!(bulk_counts, counts(usize::MAX, args)?);
+            assert_eq!(bulk_counts.len(), 500);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bench_count_bulk_update() -> Result<()> {
+        let num_rows = 1 << 20;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int32Array::from_iter(
+            (0..num_rows).map(|i| (i % 3 != 0).then_some(i as i32)),
+        ))];
+        let agg = |threshold: usize| -> Result<AggCount> {
+            Ok(
+                AggCount::try_new(vec![Arc::new(Column::new("a", 0))], DataType::Int64)?
+                    .with_bulk_update_threshold(threshold),
+            )
+        };
+
+        // updates ranges of various lengths through the per-row and bulk paths
+        for range_len in [8, 16, 32, 64, 128, 1024] {
+            for (name, threshold) in [("per_row", usize::MAX), ("bulk", 1)] {
+                let agg = agg(threshold)?;
+                let mut accs = agg.create_acc_column(range_len);
+                let time_start = Instant::now();
+                for begin in (0..num_rows).step_by(range_len) {
+                    let end = (begin + range_len).min(num_rows);
+                    agg.partial_update(
+                        &mut accs,
+                        IdxSelection::Range(0, end - begin),
+                        &args,
+                        IdxSelection::Range(begin, end),
+                    )?;
+                }
+                eprintln!(
+                    "count_{name}_update_time(range_len={range_len}): {:?}",
+                    time_start.elapsed()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_final_merge() -> Result<()> {
+        let agg = AggCount::try_new(vec![Arc::new(Column::new("a", 0))], DataType::Int64)?;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int32Array::from(vec![
+            Some(1),
+            None,
+            Some(3),
+            Some(4),
+            Some(5),
+        ]))];
+        let mut accs = agg.create_acc_column(3);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1, 1, 2]),
+            &args,
+            IdxSelection::Range(0, 5),
+        )?;
+
+        let result = agg.final_merge(&mut accs, IdxSelection::Indices(&[2, 0]))?;
+        assert_eq!(
+            result.as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![1, 1]),
+        );
+        let result = agg.final_merge(&mut accs, IdxSelection::Range(0, 3))?;
+        assert_eq!(
+            result.as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![1, 2, 1]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_per_column() -> Result<()> {
+        let fields = Fields::from(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]);
+        let agg = AggCount::try_new_per_column(
+            vec![Arc::new(Column::new("a", 0)), Arc::new(Column::new("b", 1))],
+            DataType::Struct(fields.clone()),
+        )?;
+        let args: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(vec![Some(1), None, Some(3), Some(4)])),
+            Arc::new(StringArray::from(vec![None, Some("x"), Some("y"), None])),
+        ];
+
+        // rows 0,1 go to group 0 and rows 2,3 go to group 1
+        let mut accs = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1, 1]),
+            &args,
+            IdxSelection::Range(0, 4),
+        )?;
+
+        // merge into a new accumulator with groups swapped, spilled in between
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 2), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut unspilled = agg.create_acc_column(0);
+        unspilled.unspill(2, &mut spill.get_compressed_reader())?;
+
+        let mut merged = agg.create_acc_column(2);
+        agg.partial_merge(
+            &mut merged,
+            IdxSelection::Range(0, 2),
+            &mut unspilled,
+            IdxSelection::Indices(&[1, 0]),
+        )?;
+        agg.partial_merge(
+            &mut merged,
+            IdxSelection::Single(0),
+            &mut accs,
+            IdxSelection::Single(1),
+        )?;
+
+        let result = agg.final_merge(&mut merged, IdxSelection::Range(0, 2))?;
+        let result = result.as_struct();
+        assert_eq!(result.fields(), &fields);
+        assert_eq!(
+            result.column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![4, 1]),
+        );
+        assert_eq!(
+            result.column(1).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![2, 1]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_overflow() -> Result<()> {
+        // repeatedly merges a large count into one group until it overflows
+        let merged_count = |overflow_behavior: CountOverflowBehavior| -> Result<i64> {
+            let agg = AggCount::try_new(vec![], DataType::Int64)?
+                .with_overflow_behavior(overflow_behavior);
+            let mut large_accs: Box<dyn AccColumn> = Box::new(AccCountColumn {
+                values: vec![i64::MAX / 4 + 1],
+                nulls: BitVec::new(),
+            });
+            let mut accs = agg.create_acc_column(1);
+            for _ in 0..4 {
+                agg.partial_merge(
+                    &mut accs,
+                    IdxSelection::Single(0),
+                    &mut large_accs,
+                    IdxSelection::Single(0),
+                )?;
+            }
+            // saturated or wrapped counts keep updating without further checks
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(0),
+                &[],
+                IdxSelection::Single(0),
+            )?;
+            Ok(downcast_any!(accs, AccCountColumn)?.values[0])
+        };
+
+        assert_eq!(merged_count(CountOverflowBehavior::Saturate)?, i64::MAX);
+        assert_eq!(
+            merged_count(CountOverflowBehavior::Wrap)?,
+            (i64::MAX / 4 + 1).wrapping_mul(4).wrapping_add(1),
+        );
+        assert!(merged_count(CountOverflowBehavior::Error).is_err());
+
+        assert_eq!(
+            CountOverflowBehavior::parse("Saturate")?,
+            CountOverflowBehavior::Saturate
+        );
+        assert_eq!(
+            CountOverflowBehavior::parse("")?,
+            CountOverflowBehavior::Saturate
+        );
+        assert!(CountOverflowBehavior::parse("clamp").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_matrix_freeze_to_rows() -> Result<()> {
+        let num_rows = 100;
+        let mut acc_col = AccCountMatrixColumn::new(3, num_rows);
+        for i in 0..num_rows {
+            acc_col
+                .counts_mut(i)
+                .copy_from_slice(&[i as i64, 0, 1 << 40]);
+        }
+
+        let mut rows = vec![vec![]; num_rows];
+        acc_col.freeze_to_rows(IdxSelection::Range(0, num_rows), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| std::io::Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+
+        let mut unfreezed = AccCountMatrixColumn::new(3, 0);
+        unfreezed.unfreeze_from_rows(&mut cursors)?;
+        assert_eq!(unfreezed.num_records(), num_rows);
+        assert_eq!(unfreezed.values, acc_col.values);
         Ok(())
     }
 }
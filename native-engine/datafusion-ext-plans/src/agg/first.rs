@@ -298,6 +298,11 @@ impl AccColumn for AccFirstColumn {
         self.flags.resize(len);
     }
 
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values.fill_null_range(start, end);
+        self.flags.fill_null_range(start, end);
+    }
+
     fn shrink_to_fit(&mut self) {
         self.values.shrink_to_fit();
         self.flags.shrink_to_fit();
@@ -334,4 +339,9 @@ impl AccColumn for AccFirstColumn {
         self.flags.unspill(num_rows, r)?;
         Ok(())
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        self.values.compact(retained)?;
+        self.flags.compact(retained)
+    }
 }
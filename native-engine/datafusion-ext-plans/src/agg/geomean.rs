@@ -0,0 +1,450 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{
+    common::{utils::proxy::VecAllocExt, Result},
+    physical_expr::PhysicalExpr,
+};
+use datafusion_ext_commons::{downcast_any, SliceAsRawBytes, UninitializedInit};
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// geometric mean of positive values, accumulated in log space.
+/// like spark's exp(avg(ln(x))), null and non-positive values are ignored
+/// (spark's ln() returns null for them), NaN values make the result NaN and
+/// groups without any positive value produce null.
+pub struct AggGeoMean {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+}
+
+impl AggGeoMean {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>) -> Result<Self> {
+        Ok(Self {
+            child,
+            data_type: DataType::Float64,
+        })
+    }
+}
+
+impl Debug for AggGeoMean {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GeoMean({:?})", self.child)
+    }
+}
+
+impl Agg for AggGeoMean {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(exprs[0].clone())?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        Box::new(AccLogSumColumn {
+            values: vec![LogSum::default(); num_rows],
+        })
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        // logarithms are always computed in double precision
+        partial_inputs
+            .iter()
+            .map(|input| datafusion_ext_commons::arrow::cast::cast(input, &DataType::Float64))
+            .collect()
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccLogSumColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let values = partial_args[0].as_primitive::<Float64Type>();
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if values.is_valid(partial_arg_idx) {
+                    accs.values[acc_idx].update(values.value(partial_arg_idx));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccLogSumColumn)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccLogSumColumn)?;
+        accs.ensure_size(acc_idx);
+
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                let merging_value = merging_accs.values[merging_acc_idx];
+                accs.values[acc_idx].merge(&merging_value);
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccLogSumColumn)?;
+        let mut builder = Float64Builder::with_capacity(acc_idx.len());
+        idx_for! {
+            (acc_idx in acc_idx) => {
+                builder.append_option(accs.values[acc_idx].evaluate());
+            }
+        }
+        Ok(Arc::new(builder.finish()))
+    }
+}
+
+/// sum of ln(x) and number of the accumulated values
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[repr(C)]
+pub struct LogSum {
+    pub sum_ln: f64,
+    pub count: i64,
+}
+
+impl LogSum {
+    pub fn update(&mut self, value: f64) {
+        // NaN is not comparable and falls through, so it propagates to sum_ln
+        if value <= 0.0 {
+            return;
+        }
+        self.sum_ln += value.ln();
+        self.count += 1;
+    }
+
+    pub fn merge(&mut self, other: &LogSum) {
+        self.sum_ln += other.sum_ln;
+        self.count += other.count;
+    }
+
+    pub fn evaluate(&self) -> Option<f64> {
+        (self.count > 0).then(|| (self.sum_ln / self.count as f64).exp())
+    }
+}
+
+pub struct AccLogSumColumn {
+    pub values: Vec<LogSum>,
+}
+
+impl AccColumn for AccLogSumColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.values.resize(len, LogSum::default());
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start..end].fill(LogSum::default());
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.values.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.values.allocated_size()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                array[array_idx].write_all([self.values[idx]].as_raw_bytes())?;
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut value_buf = [LogSum::default()];
+
+        for cursor in cursors {
+            cursor.read_exact(value_buf.as_raw_bytes_mut())?;
+            self.values.push(value_buf[0]);
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        let mut values = Vec::with_capacity(idx.len());
+        idx_for! {
+            (idx in idx) => {
+                values.push(self.values[idx]);
+            }
+        }
+        w.write_all(values.as_raw_bytes())?;
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut values: Vec<LogSum> = Vec::uninitialized_init(num_rows);
+        r.read_exact(values.as_raw_bytes_mut())?;
+        self.values = values;
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.values, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{any::Any, io::Read, sync::Arc};
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalExpr},
+    };
+    use datafusion_ext_commons::SliceAsRawBytes;
+
+    use crate::{
+        agg::{
+            acc::AccColumnRef,
+            agg::{Agg, IdxSelection},
+            geomean::{AggGeoMean, LogSum},
+        },
+        memmgr::spill::Spill,
+    };
+
+    /// prototype of geomean, evaluating final results from the frozen rows
+    #[derive(Debug)]
+    struct AggProtoGeoMean(AggGeoMean);
+
+    impl Agg for AggProtoGeoMean {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+            self.0.exprs()
+        }
+
+        fn data_type(&self) -> &DataType {
+            self.0.data_type()
+        }
+
+        fn nullable(&self) -> bool {
+            self.0.nullable()
+        }
+
+        fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+            self.0.create_acc_column(num_rows)
+        }
+
+        fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+            Ok(Arc::new(Self(AggGeoMean::try_new(exprs[0].clone())?)))
+        }
+
+        fn partial_update(
+            &self,
+            accs: &mut AccColumnRef,
+            acc_idx: IdxSelection<'_>,
+            partial_args: &[ArrayRef],
+            partial_arg_idx: IdxSelection<'_>,
+        ) -> Result<()> {
+            self.0
+                .partial_update(accs, acc_idx, partial_args, partial_arg_idx)
+        }
+
+        fn partial_merge(
+            &self,
+            accs: &mut AccColumnRef,
+            acc_idx: IdxSelection<'_>,
+            merging_accs: &mut AccColumnRef,
+            merging_acc_idx: IdxSelection<'_>,
+        ) -> Result<()> {
+            self.0
+                .partial_merge(accs, acc_idx, merging_accs, merging_acc_idx)
+        }
+
+        fn final_merge(
+            &self,
+            accs: &mut AccColumnRef,
+            acc_idx: IdxSelection<'_>,
+        ) -> Result<ArrayRef> {
+            let rows = accs.iter_values_as_array(acc_idx)?;
+            let mut value_buf = [LogSum::default()];
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            for row in rows.as_binary::<i32>().iter().flatten() {
+                let mut row = row;
+                row.read_exact(value_buf.as_raw_bytes_mut())?;
+                builder.append_option(value_buf[0].evaluate());
+            }
+            Ok(Arc::new(builder.finish()))
+        }
+    }
+
+    #[test]
+    fn test_geomean() -> Result<()> {
+        let agg = AggGeoMean::try_new(Arc::new(Column::new("a", 0)))?;
+
+        // group 0: geomean(1, 2, 4) = 2, null and non-positive values ignored
+        // group 1: large values overflowing a plain product
+        // group 2: NaN value
+        // group 3: no positive values
+        let values: ArrayRef = Arc::new(Int64Array::from(vec![
+            Some(1),
+            None,
+            Some(2),
+            Some(0),
+            Some(-8),
+            Some(4),
+            Some(i64::MAX),
+            Some(i64::MAX),
+            Some(i64::MAX),
+            Some(0),
+            None,
+        ]));
+        let partial_args = agg.prepare_partial_args(&[values])?;
+        let acc_indices = [0, 0, 0, 0, 0, 0, 1, 1, 1, 3, 3];
+
+        // update first half and second half separately, then merge them
+        let mut accs1 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs1,
+            IdxSelection::Indices(&acc_indices[..4]),
+            &partial_args,
+            IdxSelection::Range(0, 4),
+        )?;
+        let mut accs2 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Indices(&acc_indices[4..]),
+            &partial_args,
+            IdxSelection::Range(4, 11),
+        )?;
+        let nan_args: Vec<ArrayRef> = vec![Arc::new(Float64Array::from(vec![1.0, f64::NAN]))];
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Single(2),
+            &nan_args,
+            IdxSelection::Range(0, 2),
+        )?;
+
+        // spill the second half and merge
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs2.spill(IdxSelection::Range(0, 4), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut unspilled = agg.create_acc_column(0);
+        unspilled.unspill(4, &mut spill.get_compressed_reader())?;
+
+        agg.partial_merge(
+            &mut accs1,
+            IdxSelection::Range(0, 4),
+            &mut unspilled,
+            IdxSelection::Range(0, 4),
+        )?;
+        let result = agg.final_merge(&mut accs1, IdxSelection::Range(0, 4))?;
+        let result = result.as_primitive::<Float64Type>();
+
+        assert!((result.value(0) - 2.0).abs() < 1e-12);
+        assert!((result.value(1) / i64::MAX as f64 - 1.0).abs() < 1e-12);
+        assert!(result.value(2).is_nan());
+        assert!(result.is_null(3));
+        Ok(())
+    }
+    #[test]
+    fn test_proto_geomean() -> Result<()> {
+        let agg = AggGeoMean::try_new(Arc::new(Column::new("a", 0)))?;
+        let proto_agg = AggProtoGeoMean(AggGeoMean::try_new(Arc::new(Column::new("a", 0)))?);
+
+        let values: ArrayRef = Arc::new(Int64Array::from(vec![
+            Some(2),
+            Some(8),
+            None,
+            Some(3),
+            Some(-1),
+        ]));
+        let partial_args = agg.prepare_partial_args(&[values])?;
+        let mut accs = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1, 2, 1]),
+            &partial_args,
+            IdxSelection::Range(0, 5),
+        )?;
+
+        // both evaluated from the same accumulators in a shuffled order
+        let acc_idx = IdxSelection::Indices(&[2, 0, 1]);
+        let expected = agg.final_merge(&mut accs, acc_idx)?;
+        let result = proto_agg.final_merge(&mut accs, acc_idx)?;
+        assert_eq!(&result, &expected);
+        let result = result.as_primitive::<Float64Type>();
+        assert!((result.value(0) - 3.0).abs() < 1e-12);
+        assert!((result.value(1) - 4.0).abs() < 1e-12);
+        assert!(result.is_null(2));
+        Ok(())
+    }
+}
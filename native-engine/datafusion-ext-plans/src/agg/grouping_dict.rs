@@ -0,0 +1,138 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, DictionaryArray, Int32Array, StringBuilder},
+    datatypes::{DataType, Int32Type},
+};
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+use hashbrown::HashMap;
+
+pub fn dict_encoded_type(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Utf8 => DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+        other => other.clone(),
+    }
+}
+
+/// dictionary builder for string group keys. each output batch carries its
+/// own dictionary, holding the distinct values of that batch only, so the
+/// dictionary never grows beyond the batch size. the dedup map is reused
+/// across batches to avoid reallocation.
+#[derive(Default)]
+pub struct StringDictionaryBuilder {
+    dedup: HashMap<Box<str>, i32>,
+    values: StringBuilder,
+}
+
+impl StringDictionaryBuilder {
+    pub fn encode(&mut self, array: &ArrayRef) -> Result<ArrayRef> {
+        if array.data_type() != &DataType::Utf8 {
+            return df_execution_err!(
+                "StringDictionaryBuilder expects Utf8 array, got {:?}",
+                array.data_type(),
+            );
+        }
+        self.dedup.clear();
+        let keys = array
+            .as_string::<i32>()
+            .iter()
+            .map(|value| {
+                value.map(|value| match self.dedup.get(value) {
+                    Some(&key) => key,
+                    None => {
+                        let key = self.dedup.len() as i32;
+                        self.dedup.insert(value.into(), key);
+                        self.values.append_value(value);
+                        key
+                    }
+                })
+            })
+            .collect::<Int32Array>();
+        let values: ArrayRef = Arc::new(self.values.finish());
+        let dict = DictionaryArray::<Int32Type>::try_new(keys, values)?;
+        Ok(Arc::new(dict))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Int32Array, StringArray},
+        compute::cast,
+        datatypes::{DataType, Int32Type},
+    };
+    use datafusion::common::Result;
+
+    use crate::agg::grouping_dict::{dict_encoded_type, StringDictionaryBuilder};
+
+    #[test]
+    fn test_dictionary_per_batch() -> Result<()> {
+        let mut builder = StringDictionaryBuilder::default();
+        let batches: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![
+                Some("a"),
+                None,
+                Some("b"),
+                Some("a"),
+            ])),
+            Arc::new(StringArray::from(vec![Some("b"), Some("c"), None])),
+        ];
+
+        for batch in &batches {
+            let encoded = builder.encode(batch)?;
+            assert_eq!(encoded.data_type(), &dict_encoded_type(&DataType::Utf8));
+
+            // values are equivalent after decoding
+            let decoded = cast(&encoded, &DataType::Utf8)?;
+            assert_eq!(&decoded, batch);
+        }
+
+        // dictionaries only hold values of their own batch
+        let encoded = builder.encode(&batches[1])?;
+        let dict = encoded.as_dictionary::<Int32Type>();
+        assert_eq!(dict.values().len(), 2);
+        assert_eq!(dict.keys(), &Int32Array::from(vec![Some(0), Some(1), None]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dictionary_output_size_on_low_cardinality_keys() -> Result<()> {
+        let mut builder = StringDictionaryBuilder::default();
+        let num_rows = 4096;
+        let keys = ["beijing", "shanghai", "guangzhou", "shenzhen"];
+        let mut plain_bytes = 0;
+        let mut dict_bytes = 0;
+
+        for _ in 0..4 {
+            let batch: ArrayRef = Arc::new(StringArray::from_iter_values(
+                (0..num_rows).map(|i| keys[i % keys.len()].repeat(4)),
+            ));
+            let encoded = builder.encode(&batch)?;
+            assert_eq!(&cast(&encoded, &DataType::Utf8)?, &batch);
+            plain_bytes += batch.get_array_memory_size();
+            dict_bytes += encoded.get_array_memory_size();
+        }
+        assert!(
+            dict_bytes * 2 < plain_bytes,
+            "dict_bytes={dict_bytes}, plain_bytes={plain_bytes}"
+        );
+        Ok(())
+    }
+}
@@ -16,13 +16,21 @@ use std::{
     any::Any,
     cmp::Ordering,
     fmt::{Debug, Formatter},
+    io::Cursor,
     marker::PhantomData,
     sync::Arc,
 };
 
-use arrow::{array::*, datatypes::*};
+use arrow::{
+    array::*,
+    compute::cast,
+    datatypes::*,
+    row::{RowConverter, Rows, SortField},
+};
 use datafusion::{common::Result, physical_expr::PhysicalExpr};
-use datafusion_ext_commons::{downcast_any, scalar_value::compacted_scalar_value_from_array};
+use datafusion_ext_commons::{
+    df_execution_err, downcast_any, scalar_value::compacted_scalar_value_from_array,
+};
 
 use crate::{
     agg::{
@@ -34,22 +42,32 @@ use crate::{
         Agg,
     },
     idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
 };
 
 pub type AggMax = AggMaxMin<AggMaxParams>;
 pub type AggMin = AggMaxMin<AggMinParams>;
+pub type AggTimestampMax = AggTimestampMaxMin<AggMaxParams>;
+pub type AggTimestampMin = AggTimestampMaxMin<AggMinParams>;
 
 pub struct AggMaxMin<P: AggMaxMinParams> {
     child: Arc<dyn PhysicalExpr>,
     data_type: DataType,
+    sort_key_converter: Option<RowConverter>,
     _phantom: PhantomData<P>,
 }
 
 impl<P: AggMaxMinParams> AggMaxMin<P> {
     pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
+        let sort_key_converter = if is_nested_type(&data_type) {
+            Some(RowConverter::new(vec![SortField::new(data_type.clone())])?)
+        } else {
+            None
+        };
         Ok(Self {
             child,
             data_type,
+            sort_key_converter,
             _phantom: Default::default(),
         })
     }
@@ -85,7 +103,14 @@ impl<P: AggMaxMinParams> Agg for AggMaxMin<P> {
         true
     }
 
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        if is_nested_type(&self.data_type) {
+            return Box::new(AccNestedMaxMinColumn::new(&self.data_type, num_rows));
+        }
         create_acc_generic_column(&self.data_type, num_rows)
     }
 
@@ -99,6 +124,28 @@ impl<P: AggMaxMinParams> Agg for AggMaxMin<P> {
         let partial_arg = &partial_args[0];
         accs.ensure_size(acc_idx);
 
+        if is_nested_type(&self.data_type) {
+            let accs = downcast_any!(accs, mut AccNestedMaxMinColumn)?;
+            let keys = self.sort_keys(partial_arg)?;
+            idx_for_zipped! {
+                ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                    if !partial_arg.is_valid(partial_arg_idx) {
+                        continue;
+                    }
+                    let key = keys.row(partial_arg_idx);
+                    if let Some(w) = accs.keys.value(acc_idx) && w.as_ref().cmp(key.as_ref()) == P::ORD {
+                        continue;
+                    }
+                    accs.keys.set_value(acc_idx, Some(AccBytes::from(key.as_ref())));
+                    accs.values.set_value(
+                        acc_idx,
+                        compacted_scalar_value_from_array(partial_arg, partial_arg_idx)?,
+                    );
+                }
+            }
+            return Ok(());
+        }
+
         macro_rules! handle_primitive {
             ($array:expr) => {{
                 let partial_arg = $array;
@@ -196,6 +243,25 @@ impl<P: AggMaxMinParams> Agg for AggMaxMin<P> {
     ) -> Result<()> {
         accs.ensure_size(acc_idx);
 
+        if is_nested_type(&self.data_type) {
+            let accs = downcast_any!(accs, mut AccNestedMaxMinColumn)?;
+            let merging_accs = downcast_any!(merging_accs, mut AccNestedMaxMinColumn)?;
+            idx_for_zipped! {
+                ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                    let Some(merging_key) = merging_accs.keys.take_value(merging_acc_idx) else {
+                        continue;
+                    };
+                    let merging_value = merging_accs.values.take_value(merging_acc_idx);
+                    if let Some(w) = accs.keys.value(acc_idx) && w.cmp(&merging_key) == P::ORD {
+                        continue;
+                    }
+                    accs.keys.set_value(acc_idx, Some(merging_key));
+                    accs.values.set_value(acc_idx, merging_value);
+                }
+            }
+            return Ok(());
+        }
+
         macro_rules! handle_primitive {
             ($ty:ty) => {{
                 type TNative = <$ty as ArrowPrimitiveType>::Native;
@@ -285,10 +351,202 @@ impl<P: AggMaxMinParams> Agg for AggMaxMin<P> {
     }
 
     fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        if is_nested_type(&self.data_type) {
+            let accs = downcast_any!(accs, mut AccNestedMaxMinColumn)?;
+            return accs.values.to_array(&self.data_type, acc_idx);
+        }
         acc_generic_column_to_array(accs, &self.data_type, acc_idx)
     }
 }
 
+impl<P: AggMaxMinParams> AggMaxMin<P> {
+    /// encodes values into memcmp-able sort keys, which follow spark's
+    /// ordering of nested values (fields/elements are compared in order,
+    /// nulls first)
+    fn sort_keys(&self, values: &ArrayRef) -> Result<Rows> {
+        let Some(converter) = &self.sort_key_converter else {
+            return df_execution_err!("{}: sort keys of non-nested type", P::NAME);
+        };
+        Ok(converter.convert_columns(&[values.clone()])?)
+    }
+}
+
+/// whether values of the type are compared with row-encoded sort keys
+fn is_nested_type(dt: &DataType) -> bool {
+    matches!(
+        dt,
+        DataType::Struct(_)
+            | DataType::List(_)
+            | DataType::LargeList(_)
+            | DataType::FixedSizeList(..)
+    )
+}
+
+/// acc column of max/min over nested types. the sort key of the current
+/// max/min value is kept for comparison, and the original value for output.
+pub struct AccNestedMaxMinColumn {
+    keys: AccBytesColumn,
+    values: AccScalarValueColumn,
+}
+
+impl AccNestedMaxMinColumn {
+    pub fn new(dt: &DataType, num_rows: usize) -> Self {
+        Self {
+            keys: AccBytesColumn::new(num_rows),
+            values: AccScalarValueColumn::new(dt, num_rows),
+        }
+    }
+}
+
+impl AccColumn for AccNestedMaxMinColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.keys.resize(len);
+        self.values.resize(len);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.keys.fill_null_range(start, end);
+        self.values.fill_null_range(start, end);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.keys.shrink_to_fit();
+        self.values.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.keys.num_records()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.keys.mem_used() + self.values.mem_used()
+    }
+
+    // keys are always written before values and read back in the same order
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        self.keys.freeze_to_rows(idx, array)?;
+        self.values.freeze_to_rows(idx, array)
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        self.keys.unfreeze_from_rows(cursors)?;
+        self.values.unfreeze_from_rows(cursors)
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        self.keys.spill(idx, w)?;
+        self.values.spill(idx, w)
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        self.keys.unspill(num_rows, r)?;
+        self.values.unspill(num_rows, r)
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        self.keys.compact(retained)?;
+        self.values.compact(retained)
+    }
+}
+
+/// max/min for timestamp types. values are aggregated as i64 and casted back to
+/// the original timestamp type (with unit and time zone) in final merge.
+pub struct AggTimestampMaxMin<P: AggMaxMinParams> {
+    inner: AggMaxMin<P>,
+    data_type: DataType,
+}
+
+impl<P: AggMaxMinParams> AggTimestampMaxMin<P> {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
+        if !matches!(data_type, DataType::Timestamp(..)) {
+            return df_execution_err!("{} expect timestamp type, got {data_type:?}", P::NAME);
+        }
+        Ok(Self {
+            inner: AggMaxMin::try_new(child, DataType::Int64)?,
+            data_type,
+        })
+    }
+}
+
+impl<P: AggMaxMinParams> Debug for AggTimestampMaxMin<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?})", P::NAME, self.inner.child)
+    }
+}
+
+impl<P: AggMaxMinParams> Agg for AggTimestampMaxMin<P> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        self.inner.exprs()
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(
+            exprs[0].clone(),
+            self.data_type.clone(),
+        )?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        self.inner.create_acc_column(num_rows)
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        // timestamp values are compared as their underlying i64 values
+        Ok(vec![cast(&partial_inputs[0], &DataType::Int64)?])
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        self.inner
+            .partial_update(accs, acc_idx, partial_args, partial_arg_idx)
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        self.inner
+            .partial_merge(accs, acc_idx, merging_accs, merging_acc_idx)
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let values = self.inner.final_merge(accs, acc_idx)?;
+        Ok(cast(&values, &self.data_type)?)
+    }
+}
+
 pub trait AggMaxMinParams: 'static + Send + Sync {
     const NAME: &'static str;
     const ORD: Ordering;
@@ -306,3 +564,172 @@ impl AggMaxMinParams for AggMinParams {
     const NAME: &'static str = "min";
     const ORD: Ordering = Ordering::Less;
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::*, buffer::NullBuffer, datatypes::*};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::{
+        agg::{
+            agg::{Agg, IdxSelection},
+            maxmin::{AggMax, AggMin, AggTimestampMax, AggTimestampMin},
+        },
+        memmgr::spill::Spill,
+    };
+
+    fn test_timestamp_max_min<T: ArrowTimestampType>() -> Result<()> {
+        let data_type = DataType::Timestamp(T::UNIT, Some("UTC".into()));
+        let input: ArrayRef = Arc::new(
+            PrimitiveArray::<T>::from(vec![Some(3), None, Some(7), Some(-1), Some(5), None])
+                .with_timezone("UTC"),
+        );
+        let acc_indices = [0, 1, 0, 1, 1, 2];
+        let child = Arc::new(Column::new("ts", 0));
+
+        let aggs: [(Arc<dyn Agg>, Vec<Option<i64>>); 2] = [
+            (
+                Arc::new(AggTimestampMax::try_new(child.clone(), data_type.clone())?),
+                vec![Some(7), Some(5), None],
+            ),
+            (
+                Arc::new(AggTimestampMin::try_new(child.clone(), data_type.clone())?),
+                vec![Some(3), Some(-1), None],
+            ),
+        ];
+        for (agg, expected) in aggs {
+            let mut accs = agg.create_acc_column(0);
+            let partial_args = agg.prepare_partial_args(&[input.clone()])?;
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Indices(&acc_indices),
+                &partial_args,
+                IdxSelection::Range(0, input.len()),
+            )?;
+
+            // merge into another acc column to verify merging
+            let mut merged_accs = agg.create_acc_column(0);
+            agg.partial_merge(
+                &mut merged_accs,
+                IdxSelection::Range(0, 3),
+                &mut accs,
+                IdxSelection::Range(0, 3),
+            )?;
+
+            let output = agg.final_merge(&mut merged_accs, IdxSelection::Range(0, 3))?;
+            let expected: ArrayRef =
+                Arc::new(PrimitiveArray::<T>::from(expected).with_timezone("UTC"));
+            assert_eq!(output.data_type(), &data_type);
+            assert_eq!(agg.data_type(), &data_type);
+            assert_eq!(&output, &expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_second_max_min() -> Result<()> {
+        test_timestamp_max_min::<TimestampSecondType>()
+    }
+
+    #[test]
+    fn test_timestamp_millisecond_max_min() -> Result<()> {
+        test_timestamp_max_min::<TimestampMillisecondType>()
+    }
+
+    #[test]
+    fn test_timestamp_microsecond_max_min() -> Result<()> {
+        test_timestamp_max_min::<TimestampMicrosecondType>()
+    }
+
+    #[test]
+    fn test_timestamp_nanosecond_max_min() -> Result<()> {
+        test_timestamp_max_min::<TimestampNanosecondType>()
+    }
+
+    fn struct_array(values: Vec<Option<(Option<i32>, Option<&str>)>>) -> ArrayRef {
+        let fields = Fields::from(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Utf8, true),
+        ]);
+        let a = Int32Array::from_iter(values.iter().map(|v| v.and_then(|v| v.0)));
+        let b = StringArray::from_iter(values.iter().map(|v| v.and_then(|v| v.1)));
+        let nulls = NullBuffer::from_iter(values.iter().map(|v| v.is_some()));
+        Arc::new(StructArray::new(
+            fields,
+            vec![Arc::new(a), Arc::new(b)],
+            Some(nulls),
+        ))
+    }
+
+    #[test]
+    fn test_struct_max_min() -> Result<()> {
+        // spark compares struct fields in order, null fields are the smallest
+        let input = struct_array(vec![
+            Some((Some(1), Some("b"))),
+            Some((Some(1), None)),
+            Some((None, Some("z"))),
+            Some((Some(2), Some("a"))),
+            None,
+            Some((Some(1), Some("b"))),
+            Some((Some(1), Some("a"))),
+            Some((Some(1), None)),
+            None,
+        ]);
+        let acc_indices = [0, 0, 0, 0, 0, 1, 1, 1, 2];
+        let data_type = input.data_type().clone();
+        let child = Arc::new(Column::new("s", 0));
+
+        let aggs: [(Arc<dyn Agg>, ArrayRef); 2] = [
+            (
+                Arc::new(AggMax::try_new(child.clone(), data_type.clone())?),
+                struct_array(vec![
+                    Some((Some(2), Some("a"))),
+                    Some((Some(1), Some("b"))),
+                    None,
+                ]),
+            ),
+            (
+                Arc::new(AggMin::try_new(child.clone(), data_type.clone())?),
+                struct_array(vec![Some((None, Some("z"))), Some((Some(1), None)), None]),
+            ),
+        ];
+        for (agg, expected) in aggs {
+            // update the first and second half separately
+            let mut accs1 = agg.create_acc_column(0);
+            agg.partial_update(
+                &mut accs1,
+                IdxSelection::Indices(&acc_indices[..3]),
+                &[input.clone()],
+                IdxSelection::Range(0, 3),
+            )?;
+            let mut accs2 = agg.create_acc_column(0);
+            agg.partial_update(
+                &mut accs2,
+                IdxSelection::Indices(&acc_indices[3..]),
+                &[input.clone()],
+                IdxSelection::Range(3, input.len()),
+            )?;
+
+            // spill the second half and merge
+            let mut spill: Box<dyn Spill> = Box::new(vec![]);
+            let mut spill_writer = spill.get_compressed_writer();
+            accs2.spill(IdxSelection::Range(0, 3), &mut spill_writer)?;
+            spill_writer.finish()?;
+            let mut unspilled = agg.create_acc_column(0);
+            unspilled.unspill(3, &mut spill.get_compressed_reader())?;
+
+            agg.partial_merge(
+                &mut accs1,
+                IdxSelection::Range(0, 3),
+                &mut unspilled,
+                IdxSelection::Range(0, 3),
+            )?;
+            let output = agg.final_merge(&mut accs1, IdxSelection::Range(0, 3))?;
+            assert_eq!(output.data_type(), &data_type);
+            assert_eq!(&output, &expected);
+        }
+        Ok(())
+    }
+}
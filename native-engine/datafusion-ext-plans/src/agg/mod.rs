@@ -17,16 +17,28 @@ pub mod agg;
 pub mod agg_ctx;
 pub mod agg_hash_map;
 pub mod agg_table;
+pub mod any_value;
 pub mod avg;
 pub mod bloom_filter;
+pub mod bool_and_or;
 pub mod brickhouse;
 pub mod collect;
 pub mod count;
 pub mod first;
 pub mod first_ignores_null;
+pub mod geomean;
+pub mod grouping_dict;
 pub mod maxmin;
+pub mod mode;
+pub mod product;
+pub mod regr;
+pub mod reservoir_sample;
 pub mod spark_udaf_wrapper;
+pub mod struct_agg;
 pub mod sum;
+pub mod sum_count;
+pub mod sum_list;
+pub mod udaf_context;
 
 use std::{fmt::Debug, sync::Arc};
 
@@ -64,17 +76,33 @@ impl AggMode {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AggFunction {
     Count,
+    CountPerColumn,
     Sum,
     Avg,
     Max,
     Min,
     First,
     FirstIgnoresNull,
+    AnyValue,
     CollectList,
     CollectSet,
     BloomFilter,
     BrickhouseCollect,
     BrickhouseCombineUnique,
+    Mode,
+    SumList,
+    ReservoirSample,
+    GeoMean,
+    Product,
+    BoolAnd,
+    BoolOr,
+    StructAgg,
+    RegrCount,
+    RegrAvgX,
+    RegrAvgY,
+    RegrSlope,
+    RegrIntercept,
+    RegrR2,
     Udaf,
 }
 
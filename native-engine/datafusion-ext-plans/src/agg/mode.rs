@@ -0,0 +1,531 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    cmp::Ordering,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{
+    common::{Result, ScalarValue},
+    physical_expr::PhysicalExpr,
+};
+use datafusion_ext_commons::{
+    downcast_any,
+    io::{read_len, read_len_u64, read_scalar, write_len, write_len_u64, write_scalar},
+    scalar_value::compacted_scalar_value_from_array,
+};
+use hashbrown::raw::RawTable;
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+        collect::acc_hash,
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// mode() returning the most frequent non-null value of each group. ties are
+/// broken by choosing the smallest value, like spark's deterministic mode.
+pub struct AggModeValue {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+}
+
+impl AggModeValue {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
+        Ok(Self { child, data_type })
+    }
+}
+
+impl Debug for AggModeValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mode({:?})", self.child)
+    }
+}
+
+impl Agg for AggModeValue {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(
+            exprs[0].clone(),
+            self.data_type.clone(),
+        )?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        let mut col = Box::new(AccFreqMapColumn::empty(self.data_type.clone()));
+        col.resize(num_rows);
+        col
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccFreqMapColumn)?;
+        accs.ensure_size(acc_idx);
+
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                let scalar = compacted_scalar_value_from_array(&partial_args[0], partial_arg_idx)?;
+                if !scalar.is_null() {
+                    accs.add_value(acc_idx, &scalar);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccFreqMapColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let merging_accs = downcast_any!(merging_accs, mut AccFreqMapColumn)?;
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                accs.merge_items(acc_idx, merging_accs, merging_acc_idx);
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccFreqMapColumn)?;
+        let mut modes = Vec::with_capacity(acc_idx.len());
+
+        idx_for! {
+            (acc_idx in acc_idx) => {
+                modes.push(accs.take_mode(acc_idx)?);
+            }
+        }
+        if modes.is_empty() {
+            return Ok(new_empty_array(&self.data_type));
+        }
+        ScalarValue::iter_to_array(modes)
+    }
+}
+
+pub struct AccFreqMapColumn {
+    maps: Vec<AccFreqMap>,
+    dt: DataType,
+    mem_used: usize,
+}
+
+impl AccFreqMapColumn {
+    pub fn empty(dt: DataType) -> Self {
+        Self {
+            maps: vec![],
+            dt,
+            mem_used: 0,
+        }
+    }
+
+    pub fn add_value(&mut self, idx: usize, value: &ScalarValue) {
+        self.mem_used -= self.maps[idx].mem_size();
+        self.maps[idx].add(value, 1);
+        self.mem_used += self.maps[idx].mem_size();
+    }
+
+    pub fn merge_items(&mut self, idx: usize, other: &mut Self, other_idx: usize) {
+        self.mem_used -= self.maps[idx].mem_size();
+        other.mem_used -= other.maps[other_idx].mem_size();
+        self.maps[idx].merge(&mut other.maps[other_idx]);
+        self.mem_used += self.maps[idx].mem_size();
+    }
+
+    pub fn take_mode(&mut self, idx: usize) -> Result<ScalarValue> {
+        self.mem_used -= self.maps[idx].mem_size();
+        std::mem::take(&mut self.maps[idx]).into_mode(&self.dt)
+    }
+
+    fn save_raw(&self, idx: usize, w: &mut impl Write) -> Result<()> {
+        self.maps[idx].write_to(w)
+    }
+
+    fn load_raw(&mut self, idx: usize, r: &mut impl Read) -> Result<()> {
+        self.mem_used -= self.maps[idx].mem_size();
+        self.maps[idx] = AccFreqMap::read_from(r)?;
+        self.mem_used += self.maps[idx].mem_size();
+        Ok(())
+    }
+}
+
+impl AccColumn for AccFreqMapColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        if len < self.maps.len() {
+            self.fill_null_range(len, self.maps.len());
+        }
+        self.maps.resize_with(len, || AccFreqMap::default());
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.mem_used -= self.maps[idx].mem_size();
+            self.maps[idx] = AccFreqMap::default();
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.maps.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.maps.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.mem_used + self.maps.capacity() * size_of::<AccFreqMap>()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, &mut array[array_idx])?;
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(cursors.len());
+
+        for (idx, cursor) in cursors.iter_mut().enumerate() {
+            self.load_raw(idx, cursor)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(num_rows);
+
+        for idx in 0..num_rows {
+            self.load_raw(idx, r)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.maps, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+/// frequencies of distinct values, values are serialized into one buffer and
+/// referenced by (pos, len) from the hash table
+#[derive(Default)]
+struct AccFreqMap {
+    raw: Vec<u8>,
+    freqs: RawTable<((u32, u32), u64)>,
+}
+
+impl AccFreqMap {
+    fn mem_size(&self) -> usize {
+        self.raw.capacity() + self.freqs.capacity() * size_of::<((u32, u32), u64)>()
+    }
+
+    fn add(&mut self, value: &ScalarValue, freq: u64) {
+        let raw_start = self.raw.len();
+        write_scalar(value, false, &mut self.raw).unwrap();
+        self.add_raw_inline(raw_start, freq);
+    }
+
+    fn add_raw(&mut self, raw: &[u8], freq: u64) {
+        let raw_start = self.raw.len();
+        self.raw.extend_from_slice(raw);
+        self.add_raw_inline(raw_start, freq);
+    }
+
+    // adds the value serialized at the end of raw buffer, the value is
+    // removed from the buffer if it already exists
+    fn add_raw_inline(&mut self, raw_start: usize, freq: u64) {
+        let raw = &self.raw;
+        let new_value = &raw[raw_start..];
+        let new_pos_len = (raw_start as u32, new_value.len() as u32);
+        let hash = acc_hash(new_value);
+
+        match self.freqs.find_or_find_insert_slot(
+            hash,
+            |&(pos_len, _)| pos_len.1 == new_pos_len.1 && ref_raw(raw, pos_len) == new_value,
+            |&(pos_len, _)| acc_hash(ref_raw(raw, pos_len)),
+        ) {
+            Ok(found) => {
+                unsafe {
+                    // safety: the bucket is valid until the table is modified
+                    found.as_mut().1 += freq;
+                }
+                self.raw.truncate(raw_start);
+            }
+            Err(slot) => unsafe {
+                // safety: call unsafe `insert_in_slot` method
+                self.freqs.insert_in_slot(hash, slot, (new_pos_len, freq));
+            },
+        }
+    }
+
+    fn merge(&mut self, other: &mut Self) {
+        if self.freqs.len() < other.freqs.len() {
+            // ensure the probed map is smaller
+            std::mem::swap(self, other);
+        }
+        for (pos_len, freq) in std::mem::take(&mut other.freqs).into_iter() {
+            self.add_raw(ref_raw(&other.raw, pos_len), freq);
+        }
+        // release buffered values of the merged map, which are no longer
+        // accounted by mem_used
+        *other = AccFreqMap::default();
+    }
+
+    fn into_mode(self, dt: &DataType) -> Result<ScalarValue> {
+        let mut mode: Option<(u64, ScalarValue)> = None;
+        for (pos_len, freq) in self.freqs.into_iter() {
+            if let Some((mode_freq, _)) = &mode
+                && freq < *mode_freq
+            {
+                continue;
+            }
+            let value = read_scalar(&mut Cursor::new(ref_raw(&self.raw, pos_len)), dt, false)?;
+            let is_new_mode = match &mode {
+                Some((mode_freq, mode_value)) => {
+                    freq > *mode_freq || value.partial_cmp(mode_value) == Some(Ordering::Less)
+                }
+                None => true,
+            };
+            if is_new_mode {
+                mode = Some((freq, value));
+            }
+        }
+        match mode {
+            Some((_, value)) => Ok(value),
+            None => ScalarValue::try_from(dt),
+        }
+    }
+
+    // format: number of values, followed by length-prefixed raw values and
+    // their frequencies
+    fn write_to(&self, w: &mut impl Write) -> Result<()> {
+        write_len(self.freqs.len(), w)?;
+        for (pos_len, freq) in unsafe {
+            // safety: the table is not modified while iterating
+            self.freqs.iter().map(|bucket| *bucket.as_ref())
+        } {
+            let raw = ref_raw(&self.raw, pos_len);
+            write_len(raw.len(), w)?;
+            w.write_all(raw)?;
+            write_len_u64(freq, w)?;
+        }
+        Ok(())
+    }
+
+    fn read_from(r: &mut impl Read) -> Result<Self> {
+        let num_values = read_len(r)?;
+        let mut map = AccFreqMap::default();
+        for _ in 0..num_values {
+            let len = read_len(r)?;
+            let raw_start = map.raw.len();
+            map.raw.resize(raw_start + len, 0);
+            r.read_exact(&mut map.raw[raw_start..])?;
+            let freq = read_len_u64(r)?;
+            map.add_raw_inline(raw_start, freq);
+        }
+        Ok(map)
+    }
+}
+
+fn ref_raw(raw: &[u8], pos_len: (u32, u32)) -> &[u8] {
+    &raw[pos_len.0 as usize..][..pos_len.1 as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+    use crate::memmgr::spill::Spill;
+
+    fn total_mem_size(acc_col: &AccFreqMapColumn) -> usize {
+        acc_col.maps.iter().map(|map| map.mem_size()).sum()
+    }
+
+    #[test]
+    fn test_mode() -> Result<()> {
+        let agg = AggModeValue::try_new(Arc::new(Column::new("v", 0)), DataType::Utf8)?;
+        let input: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("b"),
+            Some("a"),
+            None,
+            Some("b"),
+            Some("c"),
+            None,
+            Some("c"),
+            Some("a"),
+            None,
+            Some("x"),
+        ]));
+
+        // group 0: b=2, a=2, c=2 (tie), group 1: only nulls, group 2: x
+        let acc_indices = vec![0, 0, 1, 0, 0, 1, 0, 0, 1, 2];
+        let mut accs = agg.create_acc_column(3);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&acc_indices),
+            &[input],
+            IdxSelection::Range(0, 10),
+        )?;
+        assert_eq!(
+            downcast_any!(accs, AccFreqMapColumn)?.mem_used,
+            total_mem_size(downcast_any!(accs, AccFreqMapColumn)?),
+        );
+
+        let output = agg.final_merge(&mut accs, IdxSelection::Range(0, 3))?;
+        assert_eq!(
+            output.as_string::<i32>(),
+            &StringArray::from(vec![Some("a"), None, Some("x")]),
+        );
+        assert_eq!(downcast_any!(accs, AccFreqMapColumn)?.mem_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mode_merge_spilled_freqs() -> Result<()> {
+        let agg = AggModeValue::try_new(Arc::new(Column::new("v", 0)), DataType::Int32)?;
+
+        // value 7 is the most frequent in the first half, but value 3 is the
+        // most frequent after merging both halves
+        let first_half: ArrayRef = Arc::new(Int32Array::from(vec![7, 7, 7, 3, 3, 1]));
+        let second_half: ArrayRef = Arc::new(Int32Array::from(vec![3, 3, 1, 1, 1, 1]));
+
+        let mut accs = agg.create_acc_column(1);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0; 6]),
+            &[first_half],
+            IdxSelection::Range(0, 6),
+        )?;
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 1), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        let mut accs = agg.create_acc_column(1);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0; 6]),
+            &[second_half],
+            IdxSelection::Range(0, 6),
+        )?;
+
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(1, &mut spill.get_compressed_reader())?;
+        agg.partial_merge(
+            &mut spilled_accs,
+            IdxSelection::Range(0, 1),
+            &mut accs,
+            IdxSelection::Range(0, 1),
+        )?;
+        assert_eq!(downcast_any!(accs, AccFreqMapColumn)?.mem_used, 0);
+
+        // frequencies: 7=3, 3=4, 1=5
+        let merged = downcast_any!(spilled_accs, AccFreqMapColumn)?;
+        assert_eq!(merged.maps[0].freqs.len(), 3);
+        assert_eq!(merged.mem_used, total_mem_size(merged));
+
+        let output = agg.final_merge(&mut spilled_accs, IdxSelection::Range(0, 1))?;
+        assert_eq!(
+            output.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![1])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mode_freeze_and_unfreeze() -> Result<()> {
+        let mut acc_col = AccFreqMapColumn::empty(DataType::Int64);
+        acc_col.resize(2);
+        for v in 0..100 {
+            acc_col.add_value(0, &ScalarValue::Int64(Some(v % 10)));
+            acc_col.add_value(1, &ScalarValue::Int64(Some(v % 7)));
+        }
+
+        let mut rows = vec![vec![]; 2];
+        AccColumn::freeze_to_rows(&acc_col, IdxSelection::Range(0, 2), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        let mut acc_col_unfrozen = AccFreqMapColumn::empty(DataType::Int64);
+        AccColumn::unfreeze_from_rows(&mut acc_col_unfrozen, &mut cursors)?;
+        assert_eq!(acc_col_unfrozen.mem_used, total_mem_size(&acc_col_unfrozen));
+
+        // 0 wins the tie of 10 values in group 0, and is most frequent in group
+        // 1
+        assert_eq!(acc_col_unfrozen.take_mode(0)?, ScalarValue::Int64(Some(0)));
+        assert_eq!(acc_col_unfrozen.take_mode(1)?, ScalarValue::Int64(Some(0)));
+        assert_eq!(acc_col_unfrozen.mem_used, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::downcast_any;
+
+use crate::{
+    agg::{
+        acc::{AccColumn, AccColumnRef, AccPrimColumn},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for_zipped,
+};
+
+/// product of the input values in double precision. unlike sum, a single null
+/// input makes the result null, the accumulator is then marked by
+/// `AccColumn::set_null` and ignores all later inputs. groups without any
+/// input also produce null.
+pub struct AggProduct {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+}
+
+impl AggProduct {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>) -> Result<Self> {
+        Ok(Self {
+            child,
+            data_type: DataType::Float64,
+        })
+    }
+}
+
+impl Debug for AggProduct {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Product({:?})", self.child)
+    }
+}
+
+impl Agg for AggProduct {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(exprs[0].clone())?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        Box::new(AccPrimColumn::<f64>::new(num_rows))
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        partial_inputs
+            .iter()
+            .map(|input| datafusion_ext_commons::arrow::cast::cast(input, &DataType::Float64))
+            .collect()
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccPrimColumn<f64>)?;
+        accs.ensure_size(acc_idx);
+
+        let values = partial_args[0].as_primitive::<Float64Type>();
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if accs.is_null(acc_idx) {
+                    continue;
+                }
+                if values.is_valid(partial_arg_idx) {
+                    let value = values.value(partial_arg_idx);
+                    accs.update_value(acc_idx, value, |product| product * value);
+                } else {
+                    accs.set_null(acc_idx)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccPrimColumn<f64>)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccPrimColumn<f64>)?;
+        accs.ensure_size(acc_idx);
+
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                if merging_accs.is_null(merging_acc_idx) {
+                    accs.set_null(acc_idx)?;
+                } else if let Some(merging_value) = merging_accs.value(merging_acc_idx) {
+                    accs.update_value(acc_idx, merging_value, |product| product * merging_value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        // accs marked by set_null() have no valid value and output null
+        let accs = downcast_any!(accs, mut AccPrimColumn<f64>)?;
+        accs.to_array(&self.data_type, acc_idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::{
+        agg::{
+            agg::{Agg, IdxSelection},
+            product::AggProduct,
+        },
+        memmgr::spill::Spill,
+    };
+
+    #[test]
+    fn test_product() -> Result<()> {
+        let agg = AggProduct::try_new(Arc::new(Column::new("a", 0)))?;
+
+        // group 0: 2 * 3 * 4 = 24
+        // group 1: contains null in the first half
+        // group 2: contains null in the second half
+        // group 3: no inputs
+        let values: ArrayRef = Arc::new(Int32Array::from(vec![
+            Some(2),
+            None,
+            Some(3),
+            Some(5),
+            Some(4),
+            Some(7),
+            None,
+            Some(5),
+        ]));
+        let partial_args = agg.prepare_partial_args(&[values])?;
+        let acc_indices = [0, 1, 0, 2, 0, 1, 2, 1];
+
+        // update first half and second half separately, then merge them
+        let mut accs1 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs1,
+            IdxSelection::Indices(&acc_indices[..4]),
+            &partial_args,
+            IdxSelection::Range(0, 4),
+        )?;
+        let mut accs2 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Indices(&acc_indices[4..]),
+            &partial_args,
+            IdxSelection::Range(4, 8),
+        )?;
+        accs1.resize(4);
+        accs2.resize(4);
+        assert!(accs1.is_null(1) && !accs1.is_null(2));
+        assert!(!accs2.is_null(1) && accs2.is_null(2));
+
+        // nulls are kept in spilled and frozen accs
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs2.spill(IdxSelection::Range(0, 4), &mut spill_writer)?;
+        accs2.freeze_to_writer(IdxSelection::Range(0, 4), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut spill_reader = spill.get_compressed_reader();
+        let mut unspilled = agg.create_acc_column(0);
+        unspilled.unspill(4, &mut spill_reader)?;
+        let mut unfrozen = agg.create_acc_column(0);
+        unfrozen.unfreeze_from_reader(4, &mut spill_reader)?;
+        assert!(unspilled.is_null(2) && unfrozen.is_null(2));
+
+        agg.partial_merge(
+            &mut accs1,
+            IdxSelection::Range(0, 4),
+            &mut unspilled,
+            IdxSelection::Range(0, 4),
+        )?;
+        let result = agg.final_merge(&mut accs1, IdxSelection::Range(0, 4))?;
+        assert_eq!(
+            result.as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![Some(24.0), None, None, None]),
+        );
+
+        // null accs are reset by fill_null_range
+        accs1.fill_null_range(0, 4);
+        assert!((0..4).all(|i| !accs1.is_null(i)));
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{
+    common::{utils::proxy::VecAllocExt, Result},
+    physical_expr::PhysicalExpr,
+};
+use datafusion_ext_commons::{downcast_any, SliceAsRawBytes, UninitializedInit};
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+pub type AggRegrCount = AggRegr<RegrCountParams>;
+pub type AggRegrAvgX = AggRegr<RegrAvgXParams>;
+pub type AggRegrAvgY = AggRegr<RegrAvgYParams>;
+pub type AggRegrSlope = AggRegr<RegrSlopeParams>;
+pub type AggRegrIntercept = AggRegr<RegrInterceptParams>;
+pub type AggRegrR2 = AggRegr<RegrR2Params>;
+
+/// regr_*(y, x) aggregations, all sharing the co-moments accumulator.
+/// rows with null y or null x are ignored.
+pub struct AggRegr<P: AggRegrParams> {
+    y: Arc<dyn PhysicalExpr>,
+    x: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+    _phantom: PhantomData<P>,
+}
+
+impl<P: AggRegrParams> AggRegr<P> {
+    pub fn try_new(y: Arc<dyn PhysicalExpr>, x: Arc<dyn PhysicalExpr>) -> Result<Self> {
+        Ok(Self {
+            y,
+            x,
+            data_type: P::data_type(),
+            _phantom: Default::default(),
+        })
+    }
+}
+
+impl<P: AggRegrParams> Debug for AggRegr<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?}, {:?})", P::NAME, self.y, self.x)
+    }
+}
+
+impl<P: AggRegrParams> Agg for AggRegr<P> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.y.clone(), self.x.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(exprs[0].clone(), exprs[1].clone())?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        P::nullable()
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        Box::new(AccCoMomentsColumn {
+            values: vec![CoMoments::default(); num_rows],
+        })
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        // moments are always computed in double precision
+        partial_inputs
+            .iter()
+            .map(|input| datafusion_ext_commons::arrow::cast::cast(input, &DataType::Float64))
+            .collect()
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccCoMomentsColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let ys = partial_args[0].as_primitive::<Float64Type>();
+        let xs = partial_args[1].as_primitive::<Float64Type>();
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if ys.is_valid(partial_arg_idx) && xs.is_valid(partial_arg_idx) {
+                    accs.values[acc_idx].update(xs.value(partial_arg_idx), ys.value(partial_arg_idx));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccCoMomentsColumn)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccCoMomentsColumn)?;
+        accs.ensure_size(acc_idx);
+
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                let merging_value = merging_accs.values[merging_acc_idx];
+                accs.values[acc_idx].merge(&merging_value);
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccCoMomentsColumn)?;
+        let mut moments = Vec::with_capacity(acc_idx.len());
+        idx_for! {
+            (acc_idx in acc_idx) => {
+                moments.push(accs.values[acc_idx]);
+            }
+        }
+        Ok(P::build_output(&moments))
+    }
+}
+
+/// co-moments of (x, y) pairs, updated with Welford's online algorithm
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[repr(C)]
+pub struct CoMoments {
+    pub count: f64,
+    pub mean_x: f64,
+    pub mean_y: f64,
+    pub c: f64,
+    pub m2_x: f64,
+    pub m2_y: f64,
+}
+
+impl CoMoments {
+    pub fn update(&mut self, x: f64, y: f64) {
+        self.count += 1.0;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / self.count;
+        self.mean_y += dy / self.count;
+        self.c += dx * (y - self.mean_y);
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+    }
+
+    pub fn merge(&mut self, other: &CoMoments) {
+        if other.count == 0.0 {
+            return;
+        }
+        if self.count == 0.0 {
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let dx = other.mean_x - self.mean_x;
+        let dy = other.mean_y - self.mean_y;
+        let factor = self.count * other.count / count;
+        self.mean_x += dx * other.count / count;
+        self.mean_y += dy * other.count / count;
+        self.c += other.c + dx * dy * factor;
+        self.m2_x += other.m2_x + dx * dx * factor;
+        self.m2_y += other.m2_y + dy * dy * factor;
+        self.count = count;
+    }
+}
+
+pub struct AccCoMomentsColumn {
+    pub values: Vec<CoMoments>,
+}
+
+impl AccColumn for AccCoMomentsColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.values.resize(len, CoMoments::default());
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start..end].fill(CoMoments::default());
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.values.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.values.allocated_size()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                array[array_idx].write_all([self.values[idx]].as_raw_bytes())?;
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut value_buf = [CoMoments::default()];
+
+        for cursor in cursors {
+            cursor.read_exact(value_buf.as_raw_bytes_mut())?;
+            self.values.push(value_buf[0]);
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        let mut values = Vec::with_capacity(idx.len());
+        idx_for! {
+            (idx in idx) => {
+                values.push(self.values[idx]);
+            }
+        }
+        w.write_all(values.as_raw_bytes())?;
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut values: Vec<CoMoments> = Vec::uninitialized_init(num_rows);
+        r.read_exact(values.as_raw_bytes_mut())?;
+        self.values = values;
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.values, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+pub trait AggRegrParams: 'static + Send + Sync {
+    const NAME: &'static str;
+
+    fn evaluate(moments: &CoMoments) -> Option<f64>;
+
+    fn data_type() -> DataType {
+        DataType::Float64
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+
+    fn build_output(moments: &[CoMoments]) -> ArrayRef {
+        Arc::new(Float64Array::from_iter(moments.iter().map(Self::evaluate)))
+    }
+}
+
+pub struct RegrCountParams;
+pub struct RegrAvgXParams;
+pub struct RegrAvgYParams;
+pub struct RegrSlopeParams;
+pub struct RegrInterceptParams;
+pub struct RegrR2Params;
+
+impl AggRegrParams for RegrCountParams {
+    const NAME: &'static str = "regr_count";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        Some(moments.count)
+    }
+
+    fn data_type() -> DataType {
+        DataType::Int64
+    }
+
+    fn nullable() -> bool {
+        false
+    }
+
+    fn build_output(moments: &[CoMoments]) -> ArrayRef {
+        Arc::new(Int64Array::from_iter_values(
+            moments.iter().map(|m| m.count as i64),
+        ))
+    }
+}
+
+impl AggRegrParams for RegrAvgXParams {
+    const NAME: &'static str = "regr_avgx";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        (moments.count > 0.0).then_some(moments.mean_x)
+    }
+}
+
+impl AggRegrParams for RegrAvgYParams {
+    const NAME: &'static str = "regr_avgy";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        (moments.count > 0.0).then_some(moments.mean_y)
+    }
+}
+
+impl AggRegrParams for RegrSlopeParams {
+    const NAME: &'static str = "regr_slope";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        // null if x has zero variance
+        (moments.count > 0.0 && moments.m2_x != 0.0).then(|| moments.c / moments.m2_x)
+    }
+}
+
+impl AggRegrParams for RegrInterceptParams {
+    const NAME: &'static str = "regr_intercept";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        // null if x has zero variance
+        (moments.count > 0.0 && moments.m2_x != 0.0)
+            .then(|| moments.mean_y - moments.c / moments.m2_x * moments.mean_x)
+    }
+}
+
+impl AggRegrParams for RegrR2Params {
+    const NAME: &'static str = "regr_r2";
+
+    fn evaluate(moments: &CoMoments) -> Option<f64> {
+        // null if x has zero variance, 1.0 if y has zero variance
+        if moments.count == 0.0 || moments.m2_x == 0.0 {
+            return None;
+        }
+        if moments.m2_y == 0.0 {
+            return Some(1.0);
+        }
+        Some(moments.c * moments.c / (moments.m2_x * moments.m2_y))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::agg::{
+        agg::{Agg, IdxSelection},
+        regr::{AggRegrAvgX, AggRegrAvgY, AggRegrCount, AggRegrIntercept, AggRegrR2, AggRegrSlope},
+    };
+
+    fn eval_regr(agg: Arc<dyn Agg>) -> Result<ArrayRef> {
+        // group 0: y = 2x + 1 with a null pair ignored
+        // group 1: constant x (zero variance)
+        // group 2: no valid pairs
+        let ys: ArrayRef = Arc::new(Float64Array::from(vec![
+            Some(3.0),
+            Some(5.0),
+            None,
+            Some(7.0),
+            Some(9.0),
+            Some(1.0),
+            Some(2.0),
+            Some(100.0),
+        ]));
+        let xs: ArrayRef = Arc::new(Float64Array::from(vec![
+            Some(1.0),
+            Some(2.0),
+            Some(10.0),
+            Some(3.0),
+            Some(4.0),
+            Some(5.0),
+            Some(5.0),
+            None,
+        ]));
+        let acc_indices = [0, 0, 0, 0, 0, 1, 1, 2];
+        let partial_args = agg.prepare_partial_args(&[ys, xs])?;
+
+        // update first half and second half separately, then merge them
+        let mut accs1 = agg.create_acc_column(0);
+        let mut accs2 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs1,
+            IdxSelection::Indices(&acc_indices[..4]),
+            &partial_args,
+            IdxSelection::Range(0, 4),
+        )?;
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Indices(&acc_indices[4..]),
+            &partial_args,
+            IdxSelection::Range(4, 8),
+        )?;
+        agg.partial_merge(
+            &mut accs1,
+            IdxSelection::Range(0, 3),
+            &mut accs2,
+            IdxSelection::Range(0, 3),
+        )?;
+        agg.final_merge(&mut accs1, IdxSelection::Range(0, 3))
+    }
+
+    fn assert_f64_output(output: ArrayRef, expected: Vec<Option<f64>>) {
+        let output = output.as_primitive::<Float64Type>();
+        assert_eq!(output.len(), expected.len());
+        for (value, expected) in output.iter().zip(expected) {
+            match (value, expected) {
+                (Some(v), Some(e)) => assert!((v - e).abs() < 1e-9, "{v} != {e}"),
+                (v, e) => assert_eq!(v, e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_regr() -> Result<()> {
+        let y = Arc::new(Column::new("y", 0));
+        let x = Arc::new(Column::new("x", 1));
+
+        let count = eval_regr(Arc::new(AggRegrCount::try_new(y.clone(), x.clone())?))?;
+        assert_eq!(
+            count.as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![4, 2, 0]),
+        );
+        assert_f64_output(
+            eval_regr(Arc::new(AggRegrAvgX::try_new(y.clone(), x.clone())?))?,
+            vec![Some(2.5), Some(5.0), None],
+        );
+        assert_f64_output(
+            eval_regr(Arc::new(AggRegrAvgY::try_new(y.clone(), x.clone())?))?,
+            vec![Some(6.0), Some(1.5), None],
+        );
+        assert_f64_output(
+            eval_regr(Arc::new(AggRegrSlope::try_new(y.clone(), x.clone())?))?,
+            vec![Some(2.0), None, None],
+        );
+        assert_f64_output(
+            eval_regr(Arc::new(AggRegrIntercept::try_new(y.clone(), x.clone())?))?,
+            vec![Some(1.0), None, None],
+        );
+        assert_f64_output(
+            eval_regr(Arc::new(AggRegrR2::try_new(y.clone(), x.clone())?))?,
+            vec![Some(1.0), None, None],
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{
+    common::{Result, ScalarValue},
+    physical_expr::PhysicalExpr,
+};
+use datafusion_ext_commons::{
+    downcast_any,
+    io::{read_len, read_len_u64, read_scalar, write_len, write_len_u64, write_scalar},
+    scalar_value::compacted_scalar_value_from_array,
+    spark_random::XorShiftRandom,
+};
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// uniformly samples up to k non-null values of each group into a list.
+/// results are reproducible for the same seed and input order.
+pub struct AggReservoirSample {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+    arg_type: DataType,
+    k: usize,
+    seed: i64,
+}
+
+impl AggReservoirSample {
+    pub fn try_new(
+        child: Arc<dyn PhysicalExpr>,
+        data_type: DataType,
+        arg_type: DataType,
+        k: usize,
+        seed: i64,
+    ) -> Result<Self> {
+        Ok(Self {
+            child,
+            data_type,
+            arg_type,
+            k,
+            seed,
+        })
+    }
+}
+
+impl Debug for AggReservoirSample {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ReservoirSample({:?}, k={})", self.child, self.k)
+    }
+}
+
+impl Agg for AggReservoirSample {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(
+            exprs[0].clone(),
+            self.data_type.clone(),
+            self.arg_type.clone(),
+            self.k,
+            self.seed,
+        )?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        false
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        let mut col = Box::new(AccReservoirColumn::empty(
+            self.arg_type.clone(),
+            self.k,
+            self.seed,
+        ));
+        col.resize(num_rows);
+        col
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccReservoirColumn)?;
+        accs.ensure_size(acc_idx);
+
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if partial_args[0].is_valid(partial_arg_idx) {
+                    accs.add_value(acc_idx, &partial_args[0], partial_arg_idx)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccReservoirColumn)?;
+        accs.ensure_size(acc_idx);
+
+        let merging_accs = downcast_any!(merging_accs, mut AccReservoirColumn)?;
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                let merging = merging_accs.take(merging_acc_idx);
+                accs.merge_reservoir(acc_idx, merging);
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccReservoirColumn)?;
+        let mut list = Vec::with_capacity(acc_idx.len());
+
+        idx_for! {
+            (acc_idx in acc_idx) => {
+                list.push(ScalarValue::List(ScalarValue::new_list(
+                    &accs.take(acc_idx).slots,
+                    &self.arg_type,
+                    true,
+                )));
+            }
+        }
+        if list.is_empty() {
+            return Ok(new_empty_array(&self.data_type));
+        }
+        ScalarValue::iter_to_array(list)
+    }
+}
+
+#[derive(Default)]
+struct Reservoir {
+    seen: u64,
+    slots: Vec<ScalarValue>,
+}
+
+impl Reservoir {
+    fn mem_size(&self) -> usize {
+        self.slots.capacity() * size_of::<ScalarValue>()
+            + self
+                .slots
+                .iter()
+                .map(|slot| slot.size() - size_of::<ScalarValue>())
+                .sum::<usize>()
+    }
+}
+
+pub struct AccReservoirColumn {
+    reservoirs: Vec<Reservoir>,
+    dt: DataType,
+    k: usize,
+    rng: XorShiftRandom,
+    mem_used: usize,
+}
+
+impl AccReservoirColumn {
+    pub fn empty(dt: DataType, k: usize, seed: i64) -> Self {
+        Self {
+            reservoirs: vec![],
+            dt,
+            k,
+            rng: XorShiftRandom::new(seed),
+            mem_used: 0,
+        }
+    }
+
+    // uniformly random integer in [0, n)
+    fn next_index(&mut self, n: u64) -> u64 {
+        ((self.rng.next_double() * n as f64) as u64).min(n - 1)
+    }
+
+    // algorithm R: the i-th value replaces a random slot with probability k/i
+    fn add_value(&mut self, idx: usize, array: &ArrayRef, row_idx: usize) -> Result<()> {
+        let seen = self.reservoirs[idx].seen + 1;
+        self.reservoirs[idx].seen = seen;
+
+        let slot_idx = if self.reservoirs[idx].slots.len() < self.k {
+            None
+        } else {
+            match self.next_index(seen) as usize {
+                slot_idx if slot_idx < self.k => Some(slot_idx),
+                _ => return Ok(()),
+            }
+        };
+        let value = compacted_scalar_value_from_array(array, row_idx)?;
+        let reservoir = &mut self.reservoirs[idx];
+        self.mem_used -= reservoir.mem_size();
+        match slot_idx {
+            Some(slot_idx) => reservoir.slots[slot_idx] = value,
+            None => reservoir.slots.push(value),
+        }
+        self.mem_used += reservoir.mem_size();
+        Ok(())
+    }
+
+    // merges two reservoirs into a sample of the union. the number of values
+    // taken from each side follows the hypergeometric distribution weighted
+    // by their seen counts.
+    fn merge_reservoir(&mut self, idx: usize, mut other: Reservoir) {
+        if other.seen == 0 {
+            return;
+        }
+        let mut this = self.take(idx);
+        if this.seen == 0 {
+            self.put(idx, other);
+            return;
+        }
+
+        let seen = this.seen + other.seen;
+        let (mut this_remaining, mut other_remaining) = (this.seen, other.seen);
+        let mut slots = Vec::with_capacity(self.k.min(this.slots.len() + other.slots.len()));
+        while slots.len() < self.k && this_remaining + other_remaining > 0 {
+            let from = if self.next_index(this_remaining + other_remaining) < this_remaining {
+                this_remaining -= 1;
+                &mut this.slots
+            } else {
+                other_remaining -= 1;
+                &mut other.slots
+            };
+            // a reservoir holds all its seen values when it is not full, so
+            // it never runs out before its seen count
+            let slot_idx = self.next_index(from.len() as u64) as usize;
+            slots.push(from.swap_remove(slot_idx));
+        }
+        self.put(idx, Reservoir { seen, slots });
+    }
+
+    fn take(&mut self, idx: usize) -> Reservoir {
+        self.mem_used -= self.reservoirs[idx].mem_size();
+        std::mem::take(&mut self.reservoirs[idx])
+    }
+
+    fn put(&mut self, idx: usize, reservoir: Reservoir) {
+        self.mem_used -= self.reservoirs[idx].mem_size();
+        self.mem_used += reservoir.mem_size();
+        self.reservoirs[idx] = reservoir;
+    }
+
+    // format: seen count, number of slots, followed by slot values
+    fn save_raw(&self, idx: usize, w: &mut impl Write) -> Result<()> {
+        let reservoir = &self.reservoirs[idx];
+        write_len_u64(reservoir.seen, w)?;
+        write_len(reservoir.slots.len(), w)?;
+        for slot in &reservoir.slots {
+            write_scalar(slot, false, w)?;
+        }
+        Ok(())
+    }
+
+    fn load_raw(&mut self, idx: usize, r: &mut impl Read) -> Result<()> {
+        let seen = read_len_u64(r)?;
+        let num_slots = read_len(r)?;
+        let slots = (0..num_slots)
+            .map(|_| read_scalar(r, &self.dt, false))
+            .collect::<Result<_>>()?;
+        self.put(idx, Reservoir { seen, slots });
+        Ok(())
+    }
+}
+
+impl AccColumn for AccReservoirColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        if len < self.reservoirs.len() {
+            self.fill_null_range(len, self.reservoirs.len());
+        }
+        self.reservoirs.resize_with(len, Reservoir::default);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.take(idx);
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.reservoirs.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.reservoirs.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.mem_used + self.reservoirs.capacity() * size_of::<Reservoir>()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, &mut array[array_idx])?;
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(cursors.len());
+
+        for (idx, cursor) in cursors.iter_mut().enumerate() {
+            self.load_raw(idx, cursor)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(num_rows);
+
+        for idx in 0..num_rows {
+            self.load_raw(idx, r)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.reservoirs, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+    use crate::memmgr::spill::Spill;
+
+    fn create_agg(k: usize, seed: i64) -> Result<AggReservoirSample> {
+        AggReservoirSample::try_new(
+            Arc::new(Column::new("v", 0)),
+            DataType::new_list(DataType::Int64, true),
+            DataType::Int64,
+            k,
+            seed,
+        )
+    }
+
+    fn sampled(output: &ArrayRef, idx: usize) -> Vec<i64> {
+        let list = output.as_list::<i32>().value(idx);
+        list.as_primitive::<Int64Type>().values().to_vec()
+    }
+
+    #[test]
+    fn test_reservoir_sample() -> Result<()> {
+        let agg = create_agg(5, 42)?;
+        let input: ArrayRef = Arc::new(Int64Array::from_iter((0..1000).map(|v| {
+            if v % 10 == 9 {
+                None
+            } else {
+                Some(v)
+            }
+        })));
+
+        // group 0: 896 non-null values, group 1: 4 values, group 2: none
+        let acc_indices = (0..1000)
+            .map(|v| if v % 300 == 0 { 1 } else { 0 })
+            .collect::<Vec<_>>();
+        let run = || -> Result<ArrayRef> {
+            let mut accs = agg.create_acc_column(3);
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Indices(&acc_indices),
+                &[input.clone()],
+                IdxSelection::Range(0, 1000),
+            )?;
+            let accs_col = downcast_any!(accs, AccReservoirColumn)?;
+            assert_eq!(accs_col.reservoirs[0].seen, 896);
+            assert_eq!(
+                accs_col.mem_used,
+                accs_col
+                    .reservoirs
+                    .iter()
+                    .map(|r| r.mem_size())
+                    .sum::<usize>(),
+            );
+            agg.final_merge(&mut accs, IdxSelection::Range(0, 3))
+        };
+
+        let output = run()?;
+        let group0 = sampled(&output, 0);
+        assert_eq!(group0.len(), 5);
+        assert_eq!(group0.iter().collect::<HashSet<_>>().len(), 5);
+        assert!(group0.iter().all(|v| v % 10 != 9 && v % 300 != 0));
+        assert_eq!(sampled(&output, 1), vec![0, 300, 600, 900]);
+        assert!(sampled(&output, 2).is_empty());
+
+        // results are reproducible with the same seed
+        assert_eq!(&output, &run()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reservoir_sample_uniformity() -> Result<()> {
+        // each of 20 values should be sampled with probability 1/4
+        let agg = create_agg(5, 0)?;
+        let input: ArrayRef = Arc::new(Int64Array::from_iter_values(0..20));
+        let mut counts = [0; 20];
+        let num_groups = 2000;
+        let mut accs = agg.create_acc_column(num_groups);
+        for group in 0..num_groups {
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(group),
+                &[input.clone()],
+                IdxSelection::Range(0, 20),
+            )?;
+        }
+        let output = agg.final_merge(&mut accs, IdxSelection::Range(0, num_groups))?;
+        for group in 0..num_groups {
+            for v in sampled(&output, group) {
+                counts[v as usize] += 1;
+            }
+        }
+        assert!(
+            counts.iter().all(|&c| (400..600).contains(&c)),
+            "{counts:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reservoir_sample_merge_spilled() -> Result<()> {
+        // the first side has seen 9000 values and the second side 1000, so
+        // about 90% of the merged samples come from the first side
+        let agg = create_agg(10, 1)?;
+        let mut merged_from_first = 0;
+        let num_groups = 200;
+
+        let first: ArrayRef = Arc::new(Int64Array::from_iter_values(0..9000));
+        let mut accs = agg.create_acc_column(num_groups);
+        for group in 0..num_groups {
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(group),
+                &[first.clone()],
+                IdxSelection::Range(0, 9000),
+            )?;
+        }
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, num_groups), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        let second: ArrayRef = Arc::new(Int64Array::from_iter_values(9000..10000));
+        let mut accs = agg.create_acc_column(num_groups);
+        for group in 0..num_groups {
+            agg.partial_update(
+                &mut accs,
+                IdxSelection::Single(group),
+                &[second.clone()],
+                IdxSelection::Range(0, 1000),
+            )?;
+        }
+
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(num_groups, &mut spill.get_compressed_reader())?;
+        agg.partial_merge(
+            &mut spilled_accs,
+            IdxSelection::Range(0, num_groups),
+            &mut accs,
+            IdxSelection::Range(0, num_groups),
+        )?;
+        assert_eq!(downcast_any!(accs, AccReservoirColumn)?.mem_used, 0);
+        let merged = downcast_any!(spilled_accs, AccReservoirColumn)?;
+        assert!(merged.reservoirs.iter().all(|r| r.seen == 10000));
+
+        let output = agg.final_merge(&mut spilled_accs, IdxSelection::Range(0, num_groups))?;
+        for group in 0..num_groups {
+            let values = sampled(&output, group);
+            assert_eq!(values.len(), 10);
+            assert_eq!(values.iter().collect::<HashSet<_>>().len(), 10);
+            merged_from_first += values.iter().filter(|&&v| v < 9000).count();
+        }
+        assert!(
+            (1700..1900).contains(&merged_from_first),
+            "{merged_from_first}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reservoir_sample_merge_partial_reservoirs() -> Result<()> {
+        // reservoirs that are not full are merged without losing values
+        let agg = create_agg(10, 7)?;
+        let first: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        let second: ArrayRef = Arc::new(Int64Array::from(vec![4, 5]));
+        let mut accs = agg.create_acc_column(1);
+        let mut merging_accs = agg.create_acc_column(1);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Single(0),
+            &[first],
+            IdxSelection::Range(0, 3),
+        )?;
+        agg.partial_update(
+            &mut merging_accs,
+            IdxSelection::Single(0),
+            &[second],
+            IdxSelection::Range(0, 2),
+        )?;
+        agg.partial_merge(
+            &mut accs,
+            IdxSelection::Single(0),
+            &mut merging_accs,
+            IdxSelection::Single(0),
+        )?;
+
+        let mut rows = vec![vec![]];
+        accs.freeze_to_rows(IdxSelection::Single(0), &mut rows)?;
+        let mut unfrozen = agg.create_acc_column(0);
+        unfrozen.unfreeze_from_rows(&mut [Cursor::new(rows[0].as_slice())])?;
+
+        let output = agg.final_merge(&mut unfrozen, IdxSelection::Single(0))?;
+        let mut values = sampled(&output, 0);
+        values.sort();
+        assert_eq!(values, vec![1, 2, 3, 4, 5]);
+        Ok(())
+    }
+}
@@ -16,47 +16,82 @@ use std::{
     any::Any,
     fmt::{Debug, Display, Formatter},
     io::{Cursor, Read, Write},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
 };
 
 use arrow::{
-    array::{as_struct_array, make_array, Array, ArrayRef, StructArray},
+    array::{new_empty_array, Array, ArrayRef, StructArray},
     datatypes::{DataType, Field, Schema, SchemaRef},
-    ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema},
     record_batch::{RecordBatch, RecordBatchOptions},
 };
-use blaze_jni_bridge::{
-    jni_bridge::LocalRef, jni_call, jni_get_byte_array_len, jni_get_byte_array_region,
-    jni_new_direct_byte_buffer, jni_new_global_ref, jni_new_object, jni_new_prim_array,
-};
+use blaze_jni_bridge::{jni_call, jni_new_global_ref, jni_new_object};
 use datafusion::{
     common::{DataFusionError, Result},
     physical_expr::PhysicalExpr,
 };
 use datafusion_ext_commons::{
-    downcast_any,
+    df_execution_err, downcast_any,
+    hash::xxhash::spark_compatible_xxhash64_hash,
     io::{read_len, write_len},
-    UninitializedInit,
 };
 use jni::objects::{GlobalRef, JObject};
 use once_cell::sync::OnceCell;
 
 use crate::{
     agg::{
-        acc::{AccColumn, AccColumnRef},
+        acc::{compact_items, AccColumn, AccColumnRef, FREEZE_ROW_GROUP_SIZE},
         agg::{Agg, IdxSelection},
+        udaf_context::{
+            map_udaf_err, JniUDAFContext, UDAFContext, UDAFIndices, UDAFRows, IDX_FORMAT_PLAIN,
+            IDX_FORMAT_RUNS,
+        },
     },
-    idx_for_zipped,
+    idx_for, idx_for_zipped,
     memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
 };
 
 pub struct SparkUDAFWrapper {
-    serialized: Vec<u8>,
     pub return_type: DataType,
     child: Vec<Arc<dyn PhysicalExpr>>,
-    import_schema: SchemaRef,
     params_schema: OnceCell<SchemaRef>,
-    jcontext: OnceCell<GlobalRef>,
+    context: Arc<LazyUDAFContext>,
+}
+
+/// udaf context shared by the wrapper and its acc columns. the jvm context is
+/// created on the first real update, so plans never producing any rows do not
+/// attach jni threads at all.
+struct LazyUDAFContext {
+    serialized: Vec<u8>,
+    return_type: DataType,
+    context: OnceCell<Arc<dyn UDAFContext>>,
+    is_initialized: AtomicBool,
+    spill_checksum: AtomicBool,
+    initial_capacity: OnceCell<usize>,
+}
+
+impl LazyUDAFContext {
+    fn get(&self) -> Result<&Arc<dyn UDAFContext>> {
+        let context = self.context.get_or_try_init(|| {
+            let context = JniUDAFContext::try_new(&self.serialized, &self.return_type)?;
+            Ok::<_, DataFusionError>(Arc::new(context) as Arc<dyn UDAFContext>)
+        })?;
+        self.is_initialized.store(true, SeqCst);
+        Ok(context)
+    }
+
+    /// creates rows of an acc column, pre-sized with the capacity hint if any
+    fn initialize_rows(&self, num_rows: usize, capacity: Option<usize>) -> Result<UDAFRows> {
+        let context = self.get()?;
+        match capacity {
+            Some(capacity) if capacity > num_rows => {
+                context.initialize_with_capacity(num_rows, capacity)
+            }
+            _ => context.initialize(num_rows),
+        }
+    }
 }
 
 impl SparkUDAFWrapper {
@@ -66,24 +101,76 @@ impl SparkUDAFWrapper {
         child: Vec<Arc<dyn PhysicalExpr>>,
     ) -> Result<Self> {
         Ok(Self {
-            serialized,
             return_type: return_type.clone(),
             child,
-            import_schema: Arc::new(Schema::new(vec![Field::new("", return_type, true)])),
             params_schema: OnceCell::new(),
-            jcontext: OnceCell::new(),
+            context: Arc::new(LazyUDAFContext {
+                serialized,
+                return_type,
+                context: OnceCell::new(),
+                is_initialized: AtomicBool::new(false),
+                spill_checksum: AtomicBool::new(false),
+                initial_capacity: OnceCell::new(),
+            }),
         })
     }
 
-    fn jcontext(&self) -> Result<GlobalRef> {
-        self.jcontext
-            .get_or_try_init(|| {
-                let serialized_buf = jni_new_direct_byte_buffer!(&self.serialized)?;
-                let jcontext_local =
-                    jni_new_object!(SparkUDAFWrapperContext(serialized_buf.as_obj()))?;
-                jni_new_global_ref!(jcontext_local.as_obj())
-            })
-            .cloned()
+    /// sets the expected number of groups (e.g. estimated distinct count),
+    /// rows of the hashing acc column are pre-sized to it instead of growing
+    /// gradually
+    pub fn with_initial_capacity(self, capacity: usize) -> Self {
+        let _ = self.context.initial_capacity.set(capacity);
+        self
+    }
+
+    fn create_acc_column_with_capacity(
+        &self,
+        num_rows: usize,
+        initial_capacity: Option<usize>,
+    ) -> AccColumnRef {
+        // returns a placeholder before the context is initialized, which is
+        // initialized on the first real update
+        if !self.is_initialized() {
+            return Box::new(AccUDAFBufferRowsColumn {
+                rows: LazyUDAFRows::Uninitialized(num_rows),
+                context: self.context.clone(),
+                initial_capacity,
+                group_stats: UDAFGroupStats::new(num_rows),
+            });
+        }
+        let rows = match self.context.initialize_rows(num_rows, initial_capacity) {
+            Ok(rows) => rows,
+            Err(e) => panic!("SparkUDAFWrapper::create_acc_column failed: {e}"),
+        };
+        Box::new(AccUDAFBufferRowsColumn {
+            rows: LazyUDAFRows::Initialized(rows),
+            context: self.context.clone(),
+            initial_capacity,
+            group_stats: UDAFGroupStats::new(num_rows),
+        })
+    }
+
+    /// enables writing and verifying checksums of spilled buffers, which is
+    /// read from conf once for all udafs of an aggregation
+    pub fn set_spill_checksum(&self, spill_checksum: bool) {
+        self.context.spill_checksum.store(spill_checksum, SeqCst);
+    }
+
+    /// creates a wrapper calling the given context instead of the jvm
+    /// SparkUDAFWrapperContext, which is used in native-only tests
+    pub fn try_new_with_context(
+        return_type: DataType,
+        child: Vec<Arc<dyn PhysicalExpr>>,
+        context: Arc<dyn UDAFContext>,
+    ) -> Result<Self> {
+        let wrapper = Self::try_new(vec![], return_type, child)?;
+        let _ = wrapper.context.context.set(context);
+        Ok(wrapper)
+    }
+
+    /// whether the udaf context has been initialized
+    pub fn is_initialized(&self) -> bool {
+        self.context.is_initialized.load(SeqCst)
     }
 
     pub fn partial_update_with_indices_cache(
@@ -92,9 +179,17 @@ impl SparkUDAFWrapper {
         acc_idx: IdxSelection<'_>,
         partial_args: &[ArrayRef],
         partial_arg_idx: IdxSelection<'_>,
-        cache: &OnceCell<LocalRef>,
+        cache: &OnceCell<UDAFIndices>,
+        zipped_indices: &mut Vec<i64>,
     ) -> Result<()> {
+        // empty params batches are rejected by some jvm paths, and updating
+        // nothing is a no-op anyway
+        if acc_idx.len() == 0 || partial_arg_idx.len() == 0 {
+            return Ok(());
+        }
         let accs = downcast_any!(accs, mut AccUDAFBufferRowsColumn)?;
+        let context = self.context.get()?;
+        let rows = accs.rows_mut()?;
 
         let params_schema = self.params_schema.get_or_init(|| {
             Arc::new(Schema::new(
@@ -115,25 +210,20 @@ impl SparkUDAFWrapper {
             &RecordBatchOptions::new().with_row_count(Some(params_batch_num_rows)),
         )?;
         let batch_struct_array = StructArray::from(params_batch);
-        let mut export_ffi_batch_array = FFI_ArrowArray::new(&batch_struct_array.to_data());
 
         // create zipped indices (using cached indices array)
-        let zipped_indices_array = cache.get_or_try_init(move || {
-            let max_len = std::cmp::max(acc_idx.len(), partial_arg_idx.len());
-            let mut zipped_indices = Vec::with_capacity(max_len);
+        let zipped_indices_array = cache.get_or_try_init(|| {
+            zipped_indices.clear();
             idx_for_zipped! {
                 ((acc_idx, updating_acc_idx) in (acc_idx, partial_arg_idx)) => {
                     zipped_indices.push((acc_idx as i64) << 32 | updating_acc_idx as i64);
                 }
             }
-            Ok::<_, DataFusionError>(jni_new_prim_array!(long, &zipped_indices[..])?)
+            context.export_zipped_indices(zipped_indices)
         })?;
-
-        jni_call!(SparkUDAFWrapperContext(self.jcontext()?.as_obj()).update(
-            accs.obj.as_obj(),
-            &mut export_ffi_batch_array as *mut FFI_ArrowArray as i64,
-            zipped_indices_array.as_obj(),
-        )-> ())
+        context.update(rows, &batch_struct_array, zipped_indices_array)?;
+        accs.group_stats.record_update(acc_idx);
+        Ok(())
     }
 
     pub fn partial_merge_with_indices_cache(
@@ -142,55 +232,46 @@ impl SparkUDAFWrapper {
         acc_idx: IdxSelection<'_>,
         merging_accs: &mut AccColumnRef,
         merging_acc_idx: IdxSelection<'_>,
-        cache: &OnceCell<LocalRef>,
+        cache: &OnceCell<UDAFIndices>,
+        zipped_indices: &mut Vec<i64>,
     ) -> Result<()> {
+        if acc_idx.len() == 0 || merging_acc_idx.len() == 0 {
+            return Ok(());
+        }
         let accs = downcast_any!(accs, mut AccUDAFBufferRowsColumn)?;
         let merging_accs = downcast_any!(merging_accs, mut AccUDAFBufferRowsColumn)?;
+        let context = self.context.get()?;
+        let rows = accs.rows_mut()?;
+        let merging_rows = merging_accs.rows_mut()?;
 
         // create zipped indices (using cached indices array)
-        let zipped_indices_array = cache.get_or_try_init(move || {
-            let max_len = std::cmp::max(acc_idx.len(), merging_acc_idx.len());
-            let mut zipped_indices = Vec::with_capacity(max_len);
+        let zipped_indices_array = cache.get_or_try_init(|| {
+            zipped_indices.clear();
             idx_for_zipped! {
                 ((acc_idx, updating_acc_idx) in (acc_idx, merging_acc_idx)) => {
                     zipped_indices.push((acc_idx as i64) << 32 | updating_acc_idx as i64);
                 }
             }
-            Ok::<_, DataFusionError>(jni_new_prim_array!(long, &zipped_indices[..])?)
+            context.export_zipped_indices(zipped_indices)
         })?;
-
-        jni_call!(SparkUDAFWrapperContext(self.jcontext()?.as_obj()).merge(
-            accs.obj.as_obj(),
-            merging_accs.obj.as_obj(),
-            zipped_indices_array.as_obj(),
-        )-> ())
+        context.merge(rows, merging_rows, zipped_indices_array)?;
+        accs.group_stats.record_update(acc_idx);
+        Ok(())
     }
 
     pub fn final_merge_with_indices_cache(
         &self,
         accs: &mut AccColumnRef,
         acc_idx: IdxSelection<'_>,
-        cache: &OnceCell<LocalRef>,
+        cache: &OnceCell<UDAFIndices>,
     ) -> Result<ArrayRef> {
+        if acc_idx.len() == 0 {
+            return Ok(new_empty_array(&self.return_type));
+        }
         let accs = downcast_any!(accs, mut AccUDAFBufferRowsColumn)?;
-        let acc_indices_array = cache.get_or_try_init(move || {
-            let acc_indices = acc_idx.to_int32_vec();
-            Ok::<_, DataFusionError>(jni_new_prim_array!(int, &acc_indices[..])?)
-        })?;
-        let mut import_ffi_array = FFI_ArrowArray::empty();
-
-        jni_call!(SparkUDAFWrapperContext(self.jcontext()?.as_obj()).eval(
-            accs.obj.as_obj(),
-            acc_indices_array.as_obj(),
-            &mut import_ffi_array as *mut FFI_ArrowArray as i64,
-        )-> ())?;
-
-        // import output from context
-        let import_ffi_schema = FFI_ArrowSchema::try_from(self.import_schema.as_ref())?;
-        let import_struct_array =
-            make_array(unsafe { from_ffi(import_ffi_array, &import_ffi_schema)? });
-        let import_array = as_struct_array(&import_struct_array).column(0).clone();
-        Ok(import_array)
+        let context = self.context.get()?;
+        let acc_indices_array = cache.get_or_try_init(|| export_idx_runs(&**context, acc_idx))?;
+        context.eval(accs.rows_mut()?, acc_indices_array)
     }
 }
 
@@ -224,23 +305,24 @@ impl Agg for SparkUDAFWrapper {
     }
 
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
-        let jcontext = self.jcontext().unwrap();
-        let rows = jni_call!(SparkUDAFWrapperContext(jcontext.as_obj()).initialize(
-            num_rows as i32,
-        )-> JObject)
-        .unwrap();
+        self.create_acc_column_with_capacity(num_rows, None)
+    }
 
-        let jcontext = self.jcontext().unwrap();
-        let obj = jni_new_global_ref!(rows.as_obj()).unwrap();
-        Box::new(AccUDAFBufferRowsColumn { obj, jcontext })
+    fn create_hashing_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        let initial_capacity = self.context.initial_capacity.get().copied();
+        self.create_acc_column_with_capacity(num_rows, initial_capacity)
     }
 
     fn with_new_exprs(&self, _exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
-        Ok(Arc::new(Self::try_new(
-            self.serialized.clone(),
-            self.return_type.clone(),
-            self.child.clone(),
-        )?))
+        // clones carry the same serialized udaf, so share the lazy context
+        // instead of constructing another jvm context with duplicated state.
+        // GlobalRef is Send + Sync and every jni call attaches its own thread.
+        Ok(Arc::new(Self {
+            return_type: self.return_type.clone(),
+            child: self.child.clone(),
+            params_schema: OnceCell::new(),
+            context: self.context.clone(),
+        }))
     }
 
     fn partial_update(
@@ -256,6 +338,7 @@ impl Agg for SparkUDAFWrapper {
             partial_args,
             partial_arg_idx,
             &OnceCell::new(), // without cache
+            &mut vec![],
         )
     }
 
@@ -272,6 +355,7 @@ impl Agg for SparkUDAFWrapper {
             merging_accs,
             merging_acc_idx,
             &OnceCell::new(), // without cache
+            &mut vec![],
         )
     }
 
@@ -280,35 +364,210 @@ impl Agg for SparkUDAFWrapper {
     }
 }
 
+/// reads the big-endian i32 length prefix of a serialized UnsafeRow
+fn read_row_len(r: &mut impl Read) -> Result<usize> {
+    let mut bytes_len_buf = [0; 4];
+    r.read_exact(&mut bytes_len_buf)?;
+    let bytes_len = i32::from_be_bytes(bytes_len_buf);
+    match usize::try_from(bytes_len) {
+        Ok(bytes_len) => Ok(bytes_len),
+        Err(_) => df_execution_err!("udaf: invalid serialized row length {bytes_len}"),
+    }
+}
+
+/// writes the big-endian i32 length prefix of a serialized UnsafeRow, rows
+/// larger than i32::MAX bytes cannot be represented by the jvm format
+fn write_row_len(bytes_len: usize, w: &mut impl Write) -> Result<()> {
+    let Ok(bytes_len) = i32::try_from(bytes_len) else {
+        return df_execution_err!(
+            "udaf: serialized row of {bytes_len} bytes exceeds the i32 length limit"
+        );
+    };
+    w.write_all(&bytes_len.to_be_bytes())?;
+    Ok(())
+}
+
+/// exports indices to the context, prefixed by a format tag. selections
+/// compressing well into runs are sent as flattened (start, len) runs,
+/// others as plain indices, so scattered selections never cost more than
+/// one int per row.
+fn export_idx_runs(context: &dyn UDAFContext, idx: IdxSelection<'_>) -> Result<UDAFIndices> {
+    let runs = idx.to_run_lengths();
+    let encoded = if runs.len() * 2 < idx.len() {
+        std::iter::once(IDX_FORMAT_RUNS)
+            .chain(
+                runs.into_iter()
+                    .flat_map(|(start, len)| [start as i32, len as i32]),
+            )
+            .collect::<Vec<_>>()
+    } else {
+        let mut encoded = Vec::with_capacity(idx.len() + 1);
+        encoded.push(IDX_FORMAT_PLAIN);
+        idx_for! {
+            (idx in idx) => {
+                encoded.push(idx as i32);
+            }
+        }
+        encoded
+    };
+    context.export_idx_runs(&encoded)
+}
+
+fn rows_checksum(
+    context: &dyn UDAFContext,
+    rows: &UDAFRows,
+    idx_runs: &UDAFIndices,
+) -> Result<i64> {
+    let mut serialized = vec![];
+    context.serialize_rows(rows, idx_runs, &mut serialized)?;
+    Ok(spark_compatible_xxhash64_hash(&serialized, 0))
+}
+
+/// writes number of rows and checksum of the spilled rows after the spill
+/// block size, only used for debugging the spill format
+fn write_spill_checksum(
+    context: &dyn UDAFContext,
+    rows: &UDAFRows,
+    num_rows: usize,
+    idx_runs: &UDAFIndices,
+    w: &mut impl Write,
+) -> Result<()> {
+    write_len(num_rows, w)?;
+    w.write_all(&rows_checksum(context, rows, idx_runs)?.to_le_bytes())?;
+    Ok(())
+}
+
+/// verifies the unspilled rows against the header written by
+/// `write_spill_checksum`
+fn verify_spill_checksum(
+    context: &dyn UDAFContext,
+    rows: &UDAFRows,
+    r: &mut impl Read,
+) -> Result<()> {
+    let expected_num_rows = read_len(r)?;
+    let mut checksum_buf = [0u8; 8];
+    r.read_exact(&mut checksum_buf)?;
+    let expected_checksum = i64::from_le_bytes(checksum_buf);
+
+    let num_rows = context.num_records(rows)?;
+    if num_rows != expected_num_rows {
+        return df_execution_err!(
+            "udaf spill verification failed: expect {expected_num_rows} rows, unspilled {num_rows}"
+        );
+    }
+    let idx_runs = export_idx_runs(context, IdxSelection::Range(0, num_rows))?;
+    let checksum = rows_checksum(context, rows, &idx_runs)?;
+    if checksum != expected_checksum {
+        return df_execution_err!(
+            "udaf spill verification failed: checksum mismatch, expect {expected_checksum}, \
+             unspilled {checksum}"
+        );
+    }
+    Ok(())
+}
+
 pub struct AccUDAFBufferRowsColumn {
-    obj: GlobalRef,
-    jcontext: GlobalRef,
+    rows: LazyUDAFRows,
+    context: Arc<LazyUDAFContext>,
+    initial_capacity: Option<usize>,
+    group_stats: UDAFGroupStats,
+}
+
+enum LazyUDAFRows {
+    /// number of rows in initial state, created before the context is
+    /// initialized
+    Uninitialized(usize),
+    Initialized(UDAFRows),
 }
 
 impl AccUDAFBufferRowsColumn {
+    pub fn is_initialized(&self) -> bool {
+        matches!(self.rows, LazyUDAFRows::Initialized(_))
+    }
+
+    /// returns the rows, initializing the context if necessary
+    fn rows_mut(&mut self) -> Result<&mut UDAFRows> {
+        if let LazyUDAFRows::Uninitialized(num_rows) = self.rows {
+            let rows = self
+                .context
+                .initialize_rows(num_rows, self.initial_capacity)?;
+            self.rows = LazyUDAFRows::Initialized(rows);
+        }
+        match &mut self.rows {
+            LazyUDAFRows::Initialized(rows) => Ok(rows),
+            LazyUDAFRows::Uninitialized(_) => unreachable!(),
+        }
+    }
+
+    /// calls f with the rows, uninitialized rows are temporarily created
+    fn with_rows<T>(&self, f: impl FnOnce(&dyn UDAFContext, &UDAFRows) -> Result<T>) -> Result<T> {
+        let context = self.context.get()?;
+        match &self.rows {
+            LazyUDAFRows::Initialized(rows) => f(&**context, rows),
+            LazyUDAFRows::Uninitialized(num_rows) => f(&**context, &context.initialize(*num_rows)?),
+        }
+    }
+
+    /// last-updated order of groups, for picking groups to evict
+    pub fn group_stats(&self) -> &UDAFGroupStats {
+        &self.group_stats
+    }
+
+    /// returns at most `num_groups` groups ordered by eviction priority, cold
+    /// and large groups come first. sizes are sampled from the context only
+    /// when called, unsampled groups are assumed to have the average size.
+    pub fn eviction_candidates(&self, num_groups: usize) -> Result<Vec<usize>> {
+        let num_records = self.group_stats.num_groups();
+        let mut sizes = vec![None; num_records];
+        if self.is_initialized() && num_records > 0 {
+            let stride = num_records.div_ceil(GROUP_STATS_SIZE_SAMPLES);
+            let sampled = (0..num_records).step_by(stride).collect::<Vec<_>>();
+            let mut serialized_bytes = vec![];
+            self.with_rows(|context, rows| {
+                let idx_runs = export_idx_runs(context, IdxSelection::Indices(&sampled))?;
+                context.serialize_rows(rows, &idx_runs, &mut serialized_bytes)
+            })?;
+
+            // UnsafeRow is serialized with big-endian i32 length prefix
+            let mut cursor = Cursor::new(&serialized_bytes);
+            for &idx in &sampled {
+                let bytes_len = read_row_len(&mut cursor)?;
+                cursor.set_position(cursor.position() + bytes_len as u64);
+                sizes[idx] = Some(bytes_len);
+            }
+        }
+        let (sum_sizes, num_sampled) = sizes
+            .iter()
+            .flatten()
+            .fold((0, 0), |(sum, n), &size| (sum + size, n + 1));
+        let avg_size = sum_sizes / num_sampled.max(1);
+
+        let score = |idx: usize| {
+            let size = sizes[idx].unwrap_or(avg_size);
+            (self.group_stats.age(idx) as u64 + 1) * (size as u64 + 1)
+        };
+        let mut candidates = (0..num_records).collect::<Vec<_>>();
+        candidates.sort_by_key(|&idx| std::cmp::Reverse(score(idx)));
+        candidates.truncate(num_groups);
+        Ok(candidates)
+    }
+
     pub fn freeze_to_rows_with_indices_cache(
         &self,
         idx: IdxSelection<'_>,
         array: &mut [Vec<u8>],
-        cache: &OnceCell<LocalRef>,
+        cache: &OnceCell<UDAFIndices>,
     ) -> Result<()> {
-        let idx_array =
-            cache.get_or_try_init(move || jni_new_prim_array!(int, &idx.to_int32_vec()[..]))?;
-        let serialized = jni_call!(
-            SparkUDAFWrapperContext(self.jcontext.as_obj()).serializeRows(
-                self.obj.as_obj(),
-                idx_array.as_obj(),
-            ) -> JObject)?;
-        let serialized_len = jni_get_byte_array_len!(serialized.as_obj())?;
-        let mut serialized_bytes = Vec::uninitialized_init(serialized_len);
-        jni_get_byte_array_region!(serialized.as_obj(), 0, &mut serialized_bytes[..])?;
+        let mut serialized_bytes = vec![];
+        self.with_rows(|context, rows| {
+            let idx_array = cache.get_or_try_init(|| export_idx_runs(context, idx))?;
+            context.serialize_rows(rows, idx_array, &mut serialized_bytes)
+        })?;
 
         // UnsafeRow is serialized with big-endian i32 length prefix
         let mut cursor = Cursor::new(&serialized_bytes);
         for i in 0..array.len() {
-            let mut bytes_len_buf = [0; 4];
-            cursor.read_exact(&mut bytes_len_buf)?;
-            let bytes_len = i32::from_be_bytes(bytes_len_buf) as usize;
+            let bytes_len = read_row_len(&mut cursor)?;
             write_len(bytes_len, &mut array[i])?;
             std::io::copy(&mut (&mut cursor).take(bytes_len as u64), &mut array[i])?;
         }
@@ -321,19 +580,18 @@ impl AccUDAFBufferRowsColumn {
         buf: &mut SpillCompressedWriter,
         spill_idx: usize,
         mem_tracker: &SparkUDAFMemTracker,
-        cache: &OnceCell<LocalRef>,
+        cache: &OnceCell<UDAFIndices>,
     ) -> Result<()> {
-        let idx_array =
-            cache.get_or_try_init(move || jni_new_prim_array!(int, &idx.to_int32_vec()[..]))?;
-        let spill_block_size = jni_call!(
-            SparkUDAFWrapperContext(self.jcontext.as_obj()).spill(
-                mem_tracker.as_obj(),
-                self.obj.as_obj(),
-                idx_array.as_obj(),
-                spill_idx as i64,
-            ) -> i32)?;
-        write_len(spill_block_size as usize, buf)?;
-        Ok(())
+        let spill_checksum = self.context.spill_checksum.load(SeqCst);
+        self.with_rows(|context, rows| {
+            let idx_array = cache.get_or_try_init(|| export_idx_runs(context, idx))?;
+            let spill_block_size = context.spill(mem_tracker, rows, idx_array, spill_idx)?;
+            write_len(spill_block_size, buf)?;
+            if spill_checksum {
+                write_spill_checksum(context, rows, idx.len(), idx_array, buf)?;
+            }
+            Ok(())
+        })
     }
 
     pub fn unspill_with_key(
@@ -344,10 +602,14 @@ impl AccUDAFBufferRowsColumn {
         spill_idx: usize,
     ) -> Result<()> {
         assert_eq!(self.num_records(), 0, "expect empty AccColumn");
-        let spill_block_size = read_len(r)? as i32;
-        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
-            .unspill(mem_tracker.as_obj(), spill_block_size, spill_idx as i64) -> JObject)?;
-        self.obj = jni_new_global_ref!(rows.as_obj())?;
+        let spill_block_size = read_len(r)?;
+        let context = self.context.get()?;
+        let rows = context.unspill(mem_tracker, spill_block_size, spill_idx)?;
+        if self.context.spill_checksum.load(SeqCst) {
+            verify_spill_checksum(&**context, &rows, r)?;
+        }
+        self.rows = LazyUDAFRows::Initialized(rows);
+        self.group_stats = UDAFGroupStats::new(num_rows);
         assert_eq!(self.num_records(), num_rows, "unspill rows count mismatch");
         Ok(())
     }
@@ -363,27 +625,55 @@ impl AccColumn for AccUDAFBufferRowsColumn {
     }
 
     fn resize(&mut self, len: usize) {
-        match jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
-            .resize(self.obj.as_obj(), len as i32)-> ())
+        self.group_stats.resize(len);
+        let rows = match &mut self.rows {
+            LazyUDAFRows::Initialized(rows) => rows,
+            LazyUDAFRows::Uninitialized(num_rows) => {
+                *num_rows = len;
+                return;
+            }
+        };
+        match self.context.get().and_then(|c| c.resize(rows, len)) {
+            Ok(_) => {}
+            Err(e) => panic!("SparkUDAFBufferRowsColumn::resize failed: {e}"),
+        }
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.group_stats.reset_range(start, end);
+        let rows = match &mut self.rows {
+            LazyUDAFRows::Initialized(rows) => rows,
+            LazyUDAFRows::Uninitialized(_) => return, // all rows are in initial state
+        };
+        match self
+            .context
+            .get()
+            .and_then(|c| c.fill_null_range(rows, start, end))
         {
             Ok(_) => {}
-            Err(e) => panic!("SparkUDAFBufferRowsColumn::resize failed: {e:?}"),
+            Err(e) => panic!("SparkUDAFBufferRowsColumn::fill_null_range failed: {e}"),
         }
     }
 
     fn shrink_to_fit(&mut self) {}
 
     fn num_records(&self) -> usize {
-        match jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
-            .numRecords(self.obj.as_obj()) -> i32)
-        {
-            Ok(n) => n as usize,
-            Err(e) => panic!("SparkUDAFBufferRowsColumn::num_records failed: {e:?}"),
+        let rows = match &self.rows {
+            LazyUDAFRows::Initialized(rows) => rows,
+            LazyUDAFRows::Uninitialized(num_rows) => return *num_rows,
+        };
+        match self.context.get().and_then(|c| c.num_records(rows)) {
+            Ok(n) => n,
+            Err(e) => panic!("SparkUDAFBufferRowsColumn::num_records failed: {e}"),
         }
     }
 
     fn mem_used(&self) -> usize {
-        0 // memory is managed in jvm side
+        let rows_mem_used = match (&self.rows, self.context.context.get()) {
+            (LazyUDAFRows::Initialized(rows), Some(context)) => context.mem_used(rows),
+            _ => 0,
+        };
+        rows_mem_used + self.group_stats.mem_used()
     }
 
     fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
@@ -395,14 +685,12 @@ impl AccColumn for AccUDAFBufferRowsColumn {
         let mut data = vec![];
         for cursor in cursors.iter_mut() {
             let bytes_len = read_len(cursor)?;
-            data.write_all((bytes_len as i32).to_be_bytes().as_ref())?;
+            write_row_len(bytes_len, &mut data)?;
             std::io::copy(&mut cursor.take(bytes_len as u64), &mut data)?;
         }
 
-        let data_buffer = jni_new_direct_byte_buffer!(data)?;
-        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
-            .deserializeRows(data_buffer.as_obj()) -> JObject)?;
-        self.obj = jni_new_global_ref!(rows.as_obj())?;
+        self.rows = LazyUDAFRows::Initialized(self.context.get()?.deserialize_rows(&data)?);
+        self.group_stats = UDAFGroupStats::new(cursors.len());
         assert_eq!(
             self.num_records(),
             cursors.len(),
@@ -415,9 +703,131 @@ impl AccColumn for AccUDAFBufferRowsColumn {
         unimplemented!("should call spill_with_indices_cache instead")
     }
 
+    fn freeze_to_writer(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        // serialize rows group by group, only one group of serialized bytes
+        // is buffered at a time
+        let mut serialized_bytes = vec![];
+        for group_idx in idx.chunks(FREEZE_ROW_GROUP_SIZE) {
+            self.with_rows(|context, rows| {
+                let idx_array = export_idx_runs(context, group_idx)?;
+                context.serialize_rows(rows, &idx_array, &mut serialized_bytes)
+            })?;
+
+            // UnsafeRow is serialized with big-endian i32 length prefix
+            let mut cursor = Cursor::new(&serialized_bytes);
+            for _ in 0..group_idx.len() {
+                let bytes_len = read_row_len(&mut cursor)?;
+                write_len(bytes_len, w)?;
+                std::io::copy(&mut (&mut cursor).take(bytes_len as u64), w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_reader(
+        &mut self,
+        num_rows: usize,
+        r: &mut SpillCompressedReader,
+    ) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut data = vec![];
+        for _ in 0..num_rows {
+            let bytes_len = read_len(r)?;
+            write_row_len(bytes_len, &mut data)?;
+            std::io::copy(&mut r.take(bytes_len as u64), &mut data)?;
+        }
+
+        self.rows = LazyUDAFRows::Initialized(self.context.get()?.deserialize_rows(&data)?);
+        self.group_stats = UDAFGroupStats::new(num_rows);
+        assert_eq!(self.num_records(), num_rows, "unfreeze rows count mismatch");
+        Ok(())
+    }
+
     fn unspill(&mut self, _num_rows: usize, _r: &mut SpillCompressedReader) -> Result<()> {
         unimplemented!("should call unspill_with_key instead")
     }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        self.group_stats.compact(retained);
+        match &mut self.rows {
+            LazyUDAFRows::Initialized(rows) => {
+                let context = self.context.get()?;
+                let idx_runs = export_idx_runs(&**context, IdxSelection::Indices(retained))?;
+                context.compact(rows, &idx_runs)
+            }
+            LazyUDAFRows::Uninitialized(num_rows) => {
+                *num_rows = retained.len(); // all rows are in initial state
+                Ok(())
+            }
+        }
+    }
+}
+
+/// only every n-th group of an update is marked as touched
+const GROUP_STATS_TOUCH_STRIDE: usize = 16;
+
+/// max number of groups whose sizes are sampled for picking eviction
+/// candidates
+const GROUP_STATS_SIZE_SAMPLES: usize = 64;
+
+/// approximate last-updated order of udaf buffer rows. the spill scheduler
+/// uses it to evict cold groups first. only every n-th group of an update is
+/// touched, so the update path stays cheap and never calls into the JVM.
+pub struct UDAFGroupStats {
+    /// sequence number of the last sampled update, 0 if never updated
+    last_updated: Vec<u32>,
+    update_seq: u32,
+}
+
+impl UDAFGroupStats {
+    pub fn new(num_groups: usize) -> Self {
+        Self {
+            last_updated: vec![0; num_groups],
+            update_seq: 0,
+        }
+    }
+
+    fn num_groups(&self) -> usize {
+        self.last_updated.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.last_updated.capacity() * size_of::<u32>()
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.last_updated.resize(len, 0);
+    }
+
+    fn reset_range(&mut self, start: usize, end: usize) {
+        self.last_updated[start..end].fill(0);
+    }
+
+    fn compact(&mut self, retained: &[usize]) {
+        compact_items(&mut self.last_updated, retained);
+        self.last_updated.truncate(retained.len());
+    }
+
+    /// marks sampled groups of the selection as updated
+    fn record_update(&mut self, idx: IdxSelection<'_>) {
+        self.update_seq = self.update_seq.saturating_add(1);
+        let seq = self.update_seq;
+
+        crate::idx_with_iter! {
+            (iter @ idx) => {
+                for idx in iter.step_by(GROUP_STATS_TOUCH_STRIDE) {
+                    if idx < self.last_updated.len() {
+                        self.last_updated[idx] = seq;
+                    }
+                }
+            }
+        }
+    }
+
+    /// number of updates since the group was last seen updated
+    pub fn age(&self, idx: usize) -> u32 {
+        self.update_seq - self.last_updated[idx]
+    }
 }
 
 pub struct SparkUDAFMemTracker {
@@ -426,20 +836,27 @@ pub struct SparkUDAFMemTracker {
 
 impl SparkUDAFMemTracker {
     pub fn try_new() -> Result<Self> {
-        let obj = jni_new_global_ref!(jni_new_object!(SparkUDAFMemTracker())?.as_obj())?;
+        let obj = jni_new_object!(SparkUDAFMemTracker())
+            .map_err(map_udaf_err("SparkUDAFMemTracker.<init>"))?;
+        let obj = jni_new_global_ref!(obj.as_obj())?;
         Ok(Self { obj })
     }
 
-    pub fn add_column(&self, column: &AccUDAFBufferRowsColumn) -> Result<()> {
-        Ok(jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).addColumn(column.obj.as_obj())-> ())?)
+    /// adds the column to be tracked, the column is initialized if necessary
+    pub fn add_column(&self, column: &mut AccUDAFBufferRowsColumn) -> Result<()> {
+        let column_obj = column.rows_mut()?.downcast_ref::<GlobalRef>()?;
+        jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).addColumn(column_obj.as_obj())-> ())
+            .map_err(map_udaf_err("SparkUDAFMemTracker.addColumn"))
     }
 
     pub fn reset(&self) -> Result<()> {
-        Ok(jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).reset()-> ())?)
+        jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).reset()-> ())
+            .map_err(map_udaf_err("SparkUDAFMemTracker.reset"))
     }
 
     pub fn update_used(&self) -> Result<bool> {
-        Ok(jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).updateUsed()-> bool)?)
+        jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).updateUsed()-> bool)
+            .map_err(map_udaf_err("SparkUDAFMemTracker.updateUsed"))
     }
 
     pub fn as_obj(&self) -> JObject {
@@ -452,3 +869,448 @@ impl Drop for SparkUDAFMemTracker {
         let _ = jni_call!(SparkUDAFMemTracker(self.obj.as_obj()).reset()-> ());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{io::Cursor, sync::Arc, time::Instant};
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Int64Array},
+        datatypes::{DataType, Int64Type},
+    };
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+    use datafusion_ext_commons::downcast_any;
+
+    use crate::{
+        agg::{
+            acc::{AccColumn, AccColumnRef},
+            agg::{Agg, IdxSelection},
+            spark_udaf_wrapper::{
+                export_idx_runs, verify_spill_checksum, write_spill_checksum,
+                AccUDAFBufferRowsColumn, LazyUDAFRows, SparkUDAFWrapper,
+            },
+            udaf_context::{
+                mock::{MockRows, MockSumUDAFContext},
+                UDAFRows, IDX_FORMAT_PLAIN, IDX_FORMAT_RUNS,
+            },
+        },
+        memmgr::spill::Spill,
+    };
+
+    fn new_mock_udaf() -> Result<SparkUDAFWrapper> {
+        SparkUDAFWrapper::try_new_with_context(
+            DataType::Int64,
+            vec![Arc::new(Column::new("a", 0))],
+            Arc::new(MockSumUDAFContext),
+        )
+    }
+
+    fn eval(udaf: &SparkUDAFWrapper, accs: &mut AccColumnRef) -> Result<Int64Array> {
+        let num_records = accs.num_records();
+        let values = udaf.final_merge(accs, IdxSelection::Range(0, num_records))?;
+        Ok(values.as_primitive::<Int64Type>().clone())
+    }
+
+    #[test]
+    fn test_partial_update_and_merge() -> Result<()> {
+        let udaf = new_mock_udaf()?;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![
+            Some(1),
+            None,
+            Some(3),
+            Some(4),
+            Some(5),
+        ]))];
+
+        let mut accs = udaf.create_acc_column(3);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 1, 1, 2, 0]),
+            &args,
+            IdxSelection::Range(0, 5),
+        )?;
+        assert_eq!(
+            eval(&udaf, &mut accs)?,
+            Int64Array::from(vec![Some(6), Some(3), Some(4)]),
+        );
+
+        let mut merging_accs = udaf.create_acc_column(2);
+        udaf.partial_update(
+            &mut merging_accs,
+            IdxSelection::Range(0, 2),
+            &args,
+            IdxSelection::Indices(&[3, 1]),
+        )?;
+        udaf.partial_merge(
+            &mut accs,
+            IdxSelection::Indices(&[2, 1]),
+            &mut merging_accs,
+            IdxSelection::Range(0, 2),
+        )?;
+
+        accs.resize(5);
+        accs.fill_null_range(0, 1);
+        assert_eq!(
+            eval(&udaf, &mut accs)?,
+            Int64Array::from(vec![None, Some(3), Some(8), None, None]),
+        );
+        assert!(accs.mem_used() > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_selections() -> Result<()> {
+        let udaf = new_mock_udaf()?;
+        let empty_args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(Vec::<i64>::new()))];
+
+        let mut accs = udaf.create_acc_column(1);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Single(0),
+            &empty_args,
+            IdxSelection::Range(0, 0),
+        )?;
+        let mut merging_accs = udaf.create_acc_column(0);
+        udaf.partial_merge(
+            &mut accs,
+            IdxSelection::Indices(&[]),
+            &mut merging_accs,
+            IdxSelection::Range(0, 0),
+        )?;
+        assert_eq!(eval(&udaf, &mut accs)?, Int64Array::from(vec![None]));
+
+        let values = udaf.final_merge(&mut accs, IdxSelection::Range(0, 0))?;
+        assert_eq!(values.len(), 0);
+        assert_eq!(values.data_type(), &DataType::Int64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_new_exprs_shares_context() -> Result<()> {
+        let udaf = SparkUDAFWrapper::try_new(
+            vec![1, 2, 3],
+            DataType::Int64,
+            vec![Arc::new(Column::new("a", 0))],
+        )?;
+        let cloned = udaf.with_new_exprs(vec![Arc::new(Column::new("a", 0))])?;
+        let cloned = downcast_any!(cloned, SparkUDAFWrapper)?;
+        assert!(Arc::ptr_eq(&udaf.context, &cloned.context));
+
+        // the mocked context is reused by clones without creating a jvm context
+        let udaf = new_mock_udaf()?;
+        let cloned = udaf.with_new_exprs(vec![Arc::new(Column::new("a", 0))])?;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1, 2, 3]))];
+        let mut accs = cloned.create_acc_column(1);
+        cloned.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 0]),
+            &args,
+            IdxSelection::Range(0, 3),
+        )?;
+        assert_eq!(eval(&udaf, &mut accs)?, Int64Array::from(vec![6]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_context_initialization() -> Result<()> {
+        // no jvm is available in native tests, so any jni call would fail
+        let udaf = SparkUDAFWrapper::try_new(
+            vec![],
+            DataType::Int64,
+            vec![Arc::new(Column::new("a", 0))],
+        )?;
+        let empty_args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(Vec::<i64>::new()))];
+        let mut accs = udaf.create_acc_column(0);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Range(0, 0),
+            &empty_args,
+            IdxSelection::Range(0, 0),
+        )?;
+        let mut merging_accs = udaf.create_acc_column(0);
+        udaf.partial_merge(
+            &mut accs,
+            IdxSelection::Range(0, 0),
+            &mut merging_accs,
+            IdxSelection::Range(0, 0),
+        )?;
+        accs.resize(3);
+        accs.fill_null_range(0, 3);
+        assert_eq!(accs.num_records(), 3);
+        assert_eq!(accs.mem_used(), 0);
+        assert_eq!(
+            udaf.final_merge(&mut accs, IdxSelection::Range(0, 0))?
+                .len(),
+            0
+        );
+        assert!(!udaf.is_initialized());
+
+        // placeholder columns are initialized on the first real update
+        let udaf = new_mock_udaf()?;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1, 2, 3]))];
+        let mut accs = udaf.create_acc_column(2);
+        accs.resize(3);
+        assert!(!downcast_any!(accs, AccUDAFBufferRowsColumn)?.is_initialized());
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[2, 1]),
+            &args,
+            IdxSelection::Range(0, 2),
+        )?;
+        assert!(udaf.is_initialized());
+        assert!(downcast_any!(accs, AccUDAFBufferRowsColumn)?.is_initialized());
+        assert_eq!(
+            eval(&udaf, &mut accs)?,
+            Int64Array::from(vec![None, Some(2), Some(1)]),
+        );
+
+        // columns created afterwards are initialized directly
+        let accs = udaf.create_acc_column(1);
+        assert!(downcast_any!(accs, AccUDAFBufferRowsColumn)?.is_initialized());
+        Ok(())
+    }
+
+    fn rows_capacity(accs: &AccColumnRef) -> Result<usize> {
+        match &downcast_any!(accs, AccUDAFBufferRowsColumn)?.rows {
+            LazyUDAFRows::Initialized(rows) => Ok(rows.downcast_ref::<MockRows>()?.capacity()),
+            LazyUDAFRows::Uninitialized(_) => Ok(0),
+        }
+    }
+
+    #[test]
+    fn test_initial_capacity() -> Result<()> {
+        let row_size = size_of::<Option<i64>>();
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1, 2, 3]))];
+        let udaf = new_mock_udaf()?.with_initial_capacity(1000);
+
+        // hashing rows are pre-sized when lazily initialized
+        let mut accs = udaf.create_hashing_acc_column(2);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 1, 1]),
+            &args,
+            IdxSelection::Range(0, 3),
+        )?;
+        assert_eq!(rows_capacity(&accs)?, 1000);
+        assert_eq!(eval(&udaf, &mut accs)?, Int64Array::from(vec![1, 5]));
+
+        // reserved capacity is not counted, like the jvm side
+        let group_stats_mem_used = downcast_any!(accs, AccUDAFBufferRowsColumn)?
+            .group_stats()
+            .mem_used();
+        assert_eq!(accs.mem_used(), 2 * row_size + group_stats_mem_used);
+
+        // and when created directly, the hint is shared by clones
+        let cloned = udaf.with_new_exprs(vec![Arc::new(Column::new("a", 0))])?;
+        assert!(rows_capacity(&cloned.create_hashing_acc_column(2))? >= 1000);
+
+        // other tables (merging, spilling, per-chunk) are not pre-sized
+        let mut accs = udaf.create_acc_column(2);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 1, 1]),
+            &args,
+            IdxSelection::Range(0, 3),
+        )?;
+        assert!(rows_capacity(&accs)? < 1000);
+
+        // no pre-sizing without the hint
+        let udaf = new_mock_udaf()?;
+        let mut accs = udaf.create_hashing_acc_column(2);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 1, 1]),
+            &args,
+            IdxSelection::Range(0, 3),
+        )?;
+        assert!(rows_capacity(&accs)? < 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze_and_spill_round_trip() -> Result<()> {
+        let udaf = new_mock_udaf()?;
+        let num_rows = 10000;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from_iter(
+            (0..num_rows as i64).map(|i| (i % 7 != 0).then_some(i * 3 - 100)),
+        ))];
+        let mut accs = udaf.create_acc_column(num_rows);
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Range(0, num_rows),
+            &args,
+            IdxSelection::Range(0, num_rows),
+        )?;
+        let expected = eval(&udaf, &mut accs)?;
+        assert_eq!(&expected, args[0].as_primitive::<Int64Type>());
+
+        // freeze to rows and unfreeze
+        let mut rows = vec![vec![]; num_rows];
+        accs.freeze_to_rows(IdxSelection::Range(0, num_rows), &mut rows)?;
+        let mut unfrozen = udaf.create_acc_column(0);
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        unfrozen.unfreeze_from_rows(&mut cursors)?;
+        assert_eq!(eval(&udaf, &mut unfrozen)?, expected);
+
+        // freeze to spill in multiple row groups and unfreeze
+        let indices = (0..num_rows).rev().collect::<Vec<_>>();
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.freeze_to_writer(IdxSelection::Indices(&indices), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        let mut unspilled = udaf.create_acc_column(0);
+        unspilled.unfreeze_from_reader(num_rows, &mut spill.get_compressed_reader())?;
+        let reversed = Int64Array::from_iter(expected.iter().rev());
+        assert_eq!(eval(&udaf, &mut unspilled)?, reversed);
+
+        // unfrozen accs can be merged
+        udaf.partial_merge(
+            &mut unspilled,
+            IdxSelection::Range(0, num_rows),
+            &mut unfrozen,
+            IdxSelection::Indices(&indices),
+        )?;
+        let doubled = Int64Array::from_iter(reversed.iter().map(|v| v.map(|v| v * 2)));
+        assert_eq!(eval(&udaf, &mut unspilled)?, doubled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_stats() -> Result<()> {
+        let udaf = new_mock_udaf()?;
+        let mut accs = udaf.create_acc_column(4);
+        let one: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1]))];
+
+        // group 0 is updated first and never again, group 1-3 are hot
+        udaf.partial_update(
+            &mut accs,
+            IdxSelection::Single(0),
+            &one,
+            IdxSelection::Single(0),
+        )?;
+        for i in 1..128 {
+            udaf.partial_update(
+                &mut accs,
+                IdxSelection::Single(1 + i as usize % 3),
+                &one,
+                IdxSelection::Single(0),
+            )?;
+        }
+        let udaf_accs = downcast_any!(accs, AccUDAFBufferRowsColumn)?;
+        let stats = udaf_accs.group_stats();
+        assert_eq!(stats.age(0), 127);
+        assert!(stats.age(1) < 3 && stats.age(2) < 3 && stats.age(3) < 3);
+        assert_eq!(udaf_accs.eviction_candidates(1)?, vec![0]);
+        assert_eq!(udaf_accs.eviction_candidates(10)?.len(), 4);
+
+        // reset groups are no longer considered updated
+        accs.fill_null_range(0, 4);
+        accs.resize(5);
+        let stats = downcast_any!(accs, AccUDAFBufferRowsColumn)?.group_stats();
+        assert_eq!(stats.age(4), stats.age(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_idx_runs_format() -> Result<()> {
+        let context = MockSumUDAFContext;
+        let exported = |idx| -> Result<Vec<i32>> {
+            let idx_runs = export_idx_runs(&context, idx)?;
+            Ok(idx_runs.downcast_ref::<Vec<i32>>()?.clone())
+        };
+
+        // contiguous selections are sent as runs
+        assert_eq!(
+            exported(IdxSelection::Range(3, 10))?,
+            vec![IDX_FORMAT_RUNS, 3, 7],
+        );
+        assert_eq!(
+            exported(IdxSelection::Indices(&[0, 1, 2, 5, 6, 7]))?,
+            vec![IDX_FORMAT_RUNS, 0, 3, 5, 3],
+        );
+
+        // scattered selections are sent as plain indices
+        assert_eq!(
+            exported(IdxSelection::Indices(&[3, 2, 1, 0]))?,
+            vec![IDX_FORMAT_PLAIN, 3, 2, 1, 0],
+        );
+        assert_eq!(
+            exported(IdxSelection::Single(5))?,
+            vec![IDX_FORMAT_PLAIN, 5]
+        );
+        assert_eq!(exported(IdxSelection::Range(3, 3))?, vec![IDX_FORMAT_PLAIN]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spill_checksum() -> Result<()> {
+        let context = MockSumUDAFContext;
+        let rows = UDAFRows::new::<Vec<Option<i64>>>(vec![Some(1), None, Some(3), Some(4)]);
+
+        // header of rows spilled in reversed order
+        let idx = IdxSelection::Indices(&[3, 2, 1, 0]);
+        let idx_runs = export_idx_runs(&context, idx)?;
+        let mut header = vec![];
+        write_spill_checksum(&context, &rows, idx.len(), &idx_runs, &mut header)?;
+
+        let unspilled = UDAFRows::new::<Vec<Option<i64>>>(vec![Some(4), Some(3), None, Some(1)]);
+        verify_spill_checksum(&context, &unspilled, &mut Cursor::new(&header))?;
+
+        // drifted values
+        let drifted = UDAFRows::new::<Vec<Option<i64>>>(vec![Some(4), Some(3), Some(1), None]);
+        let err = verify_spill_checksum(&context, &drifted, &mut Cursor::new(&header));
+        assert!(err.unwrap_err().to_string().contains("checksum mismatch"));
+
+        // missing rows
+        let truncated = UDAFRows::new::<Vec<Option<i64>>>(vec![Some(4), Some(3), None]);
+        let err = verify_spill_checksum(&context, &truncated, &mut Cursor::new(&header));
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("expect 4 rows, unspilled 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn bench_resize_and_fill_null_range() -> Result<()> {
+        let udaf = new_mock_udaf()?;
+        let args: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![1]))];
+        let new_initialized_accs = || -> Result<AccColumnRef> {
+            let mut accs = udaf.create_acc_column(1);
+            udaf.partial_update(
+                &mut accs,
+                IdxSelection::Single(0),
+                &args,
+                IdxSelection::Single(0),
+            )?;
+            Ok(accs)
+        };
+        let num_rows = 1_000_000;
+
+        // one context call per row, as resizing row by row
+        let mut per_row = new_initialized_accs()?;
+        let time_start = Instant::now();
+        for len in 2..=num_rows {
+            per_row.resize(len);
+        }
+        for i in 0..num_rows {
+            per_row.fill_null_range(i, i + 1);
+        }
+        eprintln!("udaf_resize_per_row_time: {:?}", time_start.elapsed());
+
+        // one context call for all rows
+        let mut bulk = new_initialized_accs()?;
+        let time_start = Instant::now();
+        bulk.resize(num_rows);
+        bulk.fill_null_range(0, num_rows);
+        eprintln!("udaf_resize_bulk_time: {:?}", time_start.elapsed());
+
+        assert_eq!(eval(&udaf, &mut per_row)?, eval(&udaf, &mut bulk)?);
+        assert_eq!(eval(&udaf, &mut bulk)?.null_count(), num_rows);
+        Ok(())
+    }
+}
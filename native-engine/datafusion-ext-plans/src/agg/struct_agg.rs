@@ -0,0 +1,345 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::Cursor,
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::{df_execution_err, downcast_any};
+
+use crate::{
+    agg::{
+        acc::{AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// struct_agg(agg1, agg2, ...): evaluates each inner aggregate independently
+/// and assembles the results into a struct, one field per inner aggregate.
+pub struct AggStructAgg {
+    aggs: Vec<Arc<dyn Agg>>,
+    data_type: DataType,
+}
+
+impl AggStructAgg {
+    pub fn try_new(aggs: Vec<Arc<dyn Agg>>, field_names: Vec<String>) -> Result<Self> {
+        if aggs.len() != field_names.len() {
+            return df_execution_err!(
+                "AggStructAgg: expect {} field names, got {}",
+                aggs.len(),
+                field_names.len(),
+            );
+        }
+        let fields = aggs
+            .iter()
+            .zip(field_names)
+            .map(|(agg, name)| Field::new(name, agg.data_type().clone(), agg.nullable()))
+            .collect::<Fields>();
+        Ok(Self {
+            aggs,
+            data_type: DataType::Struct(fields),
+        })
+    }
+
+    // splits args of all inner aggregates into per-aggregate slices.
+    // prepare_partial_args() keeps the number of args, so this works for both
+    // raw and prepared args.
+    fn split_args<'a>(&self, args: &'a [ArrayRef]) -> Vec<&'a [ArrayRef]> {
+        let mut offset = 0;
+        self.aggs
+            .iter()
+            .map(|agg| {
+                let num_args = agg.exprs().len();
+                let agg_args = &args[offset..][..num_args];
+                offset += num_args;
+                agg_args
+            })
+            .collect()
+    }
+}
+
+impl Debug for AggStructAgg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StructAgg({:?})", self.aggs)
+    }
+}
+
+impl Agg for AggStructAgg {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        self.aggs.iter().flat_map(|agg| agg.exprs()).collect()
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        let mut exprs = exprs.into_iter();
+        let aggs = self
+            .aggs
+            .iter()
+            .map(|agg| {
+                let num_exprs = agg.exprs().len();
+                agg.with_new_exprs(exprs.by_ref().take(num_exprs).collect())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let DataType::Struct(fields) = &self.data_type else {
+            unreachable!()
+        };
+        let field_names = fields.iter().map(|f| f.name().clone()).collect();
+        Ok(Arc::new(Self::try_new(aggs, field_names)?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        false
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        let mut prepared = vec![];
+        for (agg, agg_inputs) in self.aggs.iter().zip(self.split_args(partial_inputs)) {
+            prepared.extend(agg.prepare_partial_args(agg_inputs)?);
+        }
+        Ok(prepared)
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        Box::new(AccStructAggColumn {
+            fields: self
+                .aggs
+                .iter()
+                .map(|agg| agg.create_acc_column(num_rows))
+                .collect(),
+        })
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccStructAggColumn)?;
+        for ((agg, field_accs), agg_args) in self
+            .aggs
+            .iter()
+            .zip(&mut accs.fields)
+            .zip(self.split_args(partial_args))
+        {
+            agg.partial_update(field_accs, acc_idx, agg_args, partial_arg_idx)?;
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccStructAggColumn)?;
+        let merging_accs = downcast_any!(merging_accs, mut AccStructAggColumn)?;
+        for ((agg, field_accs), merging_field_accs) in self
+            .aggs
+            .iter()
+            .zip(&mut accs.fields)
+            .zip(&mut merging_accs.fields)
+        {
+            agg.partial_merge(field_accs, acc_idx, merging_field_accs, merging_acc_idx)?;
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccStructAggColumn)?;
+        let field_arrays = self
+            .aggs
+            .iter()
+            .zip(&mut accs.fields)
+            .map(|(agg, field_accs)| agg.final_merge(field_accs, acc_idx))
+            .collect::<Result<Vec<_>>>()?;
+        let DataType::Struct(fields) = &self.data_type else {
+            unreachable!()
+        };
+        Ok(Arc::new(StructArray::try_new(
+            fields.clone(),
+            field_arrays,
+            None,
+        )?))
+    }
+}
+
+struct AccStructAggColumn {
+    fields: Vec<AccColumnRef>,
+}
+
+impl AccColumn for AccStructAggColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.fields.iter_mut().for_each(|f| f.resize(len));
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.fields
+            .iter_mut()
+            .for_each(|f| f.fill_null_range(start, end));
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.fields.iter_mut().for_each(|f| f.shrink_to_fit());
+    }
+
+    fn num_records(&self) -> usize {
+        self.fields.first().map(|f| f.num_records()).unwrap_or(0)
+    }
+
+    fn mem_used(&self) -> usize {
+        self.fields.iter().map(|f| f.mem_used()).sum()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        for field in &self.fields {
+            field.freeze_to_rows(idx, array)?;
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        for field in &mut self.fields {
+            field.unfreeze_from_rows(cursors)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, buf: &mut SpillCompressedWriter) -> Result<()> {
+        for field in &self.fields {
+            field.spill(idx, buf)?;
+        }
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        for field in &mut self.fields {
+            field.unspill(num_rows, r)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        for field in &mut self.fields {
+            field.compact(retained)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+    use crate::{
+        agg::{count::AggCount, maxmin::AggMax, sum::AggSum},
+        memmgr::spill::Spill,
+    };
+
+    #[test]
+    fn test_struct_agg() -> Result<()> {
+        let a: Arc<dyn PhysicalExpr> = Arc::new(Column::new("a", 0));
+        let b: Arc<dyn PhysicalExpr> = Arc::new(Column::new("b", 1));
+        let agg = AggStructAgg::try_new(
+            vec![
+                Arc::new(AggSum::try_new(a.clone(), DataType::Int64)?),
+                Arc::new(AggMax::try_new(b.clone(), DataType::Utf8)?),
+                Arc::new(AggCount::try_new(vec![a.clone()], DataType::Int64)?),
+            ],
+            vec![
+                "sum_a".to_string(),
+                "max_b".to_string(),
+                "cnt_a".to_string(),
+            ],
+        )?;
+        assert_eq!(agg.exprs().len(), 3);
+
+        let a_values: ArrayRef = Arc::new(Int64Array::from(vec![Some(1), None, Some(3), Some(4)]));
+        let b_values: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("x"),
+            Some("z"),
+            None,
+            Some("y"),
+        ]));
+        let partial_args = agg.prepare_partial_args(&[a_values.clone(), b_values, a_values])?;
+
+        // group 0: rows 0, 1, group 1: rows 2, 3
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1, 1]),
+            &partial_args,
+            IdxSelection::Range(0, 4),
+        )?;
+
+        // spill and merge into a fresh acc column
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 2), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(2, &mut spill.get_compressed_reader())?;
+
+        let mut merged_accs = agg.create_acc_column(2);
+        agg.partial_merge(
+            &mut merged_accs,
+            IdxSelection::Range(0, 2),
+            &mut spilled_accs,
+            IdxSelection::Range(0, 2),
+        )?;
+        let output = agg.final_merge(&mut merged_accs, IdxSelection::Range(0, 2))?;
+        let output = output.as_struct();
+
+        assert_eq!(output.len(), 2);
+        assert_eq!(output.column_names(), vec!["sum_a", "max_b", "cnt_a"]);
+        assert_eq!(
+            output.column(0).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![1, 7]),
+        );
+        assert_eq!(
+            output.column(1).as_string::<i32>(),
+            &StringArray::from(vec!["z", "y"]),
+        );
+        assert_eq!(
+            output.column(2).as_primitive::<Int64Type>(),
+            &Int64Array::from(vec![1, 2]),
+        );
+        Ok(())
+    }
+}
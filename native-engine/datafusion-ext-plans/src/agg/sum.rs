@@ -15,32 +15,56 @@
 use std::{
     any::Any,
     fmt::{Debug, Formatter},
+    io::Cursor,
     sync::Arc,
 };
 
 use arrow::{array::*, datatypes::*};
+use byteorder::{ReadBytesExt, WriteBytesExt};
 use datafusion::{common::Result, physical_expr::PhysicalExpr};
-use datafusion_ext_commons::{df_unimplemented_err, downcast_any};
+use datafusion_ext_commons::{df_execution_err, df_unimplemented_err, downcast_any};
 
 use crate::{
     agg::{
         acc::{
-            acc_generic_column_to_array, create_acc_generic_column, AccColumnRef, AccPrimColumn,
+            acc_generic_column_to_array, create_acc_generic_column, AccColumn, AccColumnRef,
+            AccPrimColumn,
         },
         agg::IdxSelection,
         Agg,
     },
-    idx_for_zipped,
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
 };
 
 pub struct AggSum {
     child: Arc<dyn PhysicalExpr>,
     data_type: DataType,
+    fail_on_overflow: bool,
 }
 
 impl AggSum {
     pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
-        Ok(Self { child, data_type })
+        Ok(Self {
+            child,
+            data_type,
+            fail_on_overflow: false,
+        })
+    }
+
+    /// raises an error instead of returning null when a decimal sum overflows
+    /// the result precision (ansi mode)
+    pub fn with_fail_on_overflow(mut self, fail_on_overflow: bool) -> Self {
+        self.fail_on_overflow = fail_on_overflow;
+        self
+    }
+
+    // spark promotes sum(decimal(p, s)) to decimal(min(38, p + 10), s), so the
+    // result precision is capped at 38 exactly when the input precision exceeds
+    // 28. such sums may overflow a 128-bit accumulator before reaching the
+    // result, so they are accumulated in 256 bits and checked in final_merge.
+    fn use_wide_decimal(&self) -> bool {
+        matches!(self.data_type, DataType::Decimal128(38, _))
     }
 }
 
@@ -60,10 +84,10 @@ impl Agg for AggSum {
     }
 
     fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
-        Ok(Arc::new(Self::try_new(
-            exprs[0].clone(),
-            self.data_type.clone(),
-        )?))
+        Ok(Arc::new(
+            Self::try_new(exprs[0].clone(), self.data_type.clone())?
+                .with_fail_on_overflow(self.fail_on_overflow),
+        ))
     }
 
     fn data_type(&self) -> &DataType {
@@ -74,6 +98,10 @@ impl Agg for AggSum {
         true
     }
 
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
     fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
         // cast arg1 to target data type
         Ok(vec![datafusion_ext_commons::arrow::cast::cast(
@@ -83,6 +111,9 @@ impl Agg for AggSum {
     }
 
     fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        if self.use_wide_decimal() {
+            return Box::new(AccWideDecimalColumn::new(num_rows));
+        }
         create_acc_generic_column(&self.data_type, num_rows)
     }
 
@@ -96,6 +127,22 @@ impl Agg for AggSum {
         let partial_arg = &partial_args[0];
         accs.ensure_size(acc_idx);
 
+        if self.use_wide_decimal() {
+            let partial_arg = partial_arg.as_primitive::<Decimal128Type>();
+            let accs = downcast_any!(accs, mut AccWideDecimalColumn)?;
+            idx_for_zipped! {
+                ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                    if partial_arg.is_valid(partial_arg_idx) {
+                        let partial_value = i256::from_i128(partial_arg.value(partial_arg_idx));
+                        accs.sums.update_value(acc_idx, partial_value, |v| {
+                            v.wrapping_add(partial_value)
+                        });
+                    }
+                }
+            }
+            return Ok(());
+        }
+
         downcast_primitive_array! {
             partial_arg => {
                 let accs = downcast_any!(accs, mut AccPrimColumn<_>)?;
@@ -122,6 +169,21 @@ impl Agg for AggSum {
     ) -> Result<()> {
         accs.ensure_size(acc_idx);
 
+        if self.use_wide_decimal() {
+            let accs = downcast_any!(accs, mut AccWideDecimalColumn)?;
+            let merging_accs = downcast_any!(merging_accs, mut AccWideDecimalColumn)?;
+            idx_for_zipped! {
+                ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                    if let Some(merging_value) = merging_accs.sums.value(merging_acc_idx) {
+                        accs.sums.update_value(acc_idx, merging_value, |v| {
+                            v.wrapping_add(merging_value)
+                        });
+                    }
+                }
+            }
+            return Ok(());
+        }
+
         macro_rules! handle_primitive {
             ($ty:ty) => {{
                 type TNative = <$ty as ArrowPrimitiveType>::Native;
@@ -144,6 +206,192 @@ impl Agg for AggSum {
     }
 
     fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        if self.use_wide_decimal() {
+            let accs = downcast_any!(accs, mut AccWideDecimalColumn)?;
+            return accs.to_decimal128_array(&self.data_type, acc_idx, self.fail_on_overflow);
+        }
         acc_generic_column_to_array(accs, &self.data_type, acc_idx)
     }
 }
+
+/// version of the AccWideDecimalColumn spill format, written ahead of the
+/// spilled sums so that incompatible spills are detected on reading
+const WIDE_DECIMAL_SPILL_VERSION: u8 = 1;
+
+/// 256-bit decimal sums, downcasted to the 128-bit result in final_merge
+struct AccWideDecimalColumn {
+    sums: AccPrimColumn<i256>,
+}
+
+impl AccWideDecimalColumn {
+    fn new(num_records: usize) -> Self {
+        Self {
+            sums: AccPrimColumn::new(num_records),
+        }
+    }
+
+    /// converts sums to the result type, sums exceeding the result precision
+    /// are null, or raise an error if fail_on_overflow is set.
+    fn to_decimal128_array(
+        &self,
+        dt: &DataType,
+        idx: IdxSelection<'_>,
+        fail_on_overflow: bool,
+    ) -> Result<ArrayRef> {
+        let &DataType::Decimal128(precision, _) = dt else {
+            return df_execution_err!("expected decimal128 type, got {dt:?}");
+        };
+        let max = i256::from_i128(10i128.pow(precision as u32));
+        let min = i256::from_i128(-10i128.pow(precision as u32));
+
+        let mut builder = Decimal128Builder::with_capacity(idx.len());
+        idx_for! {
+            (idx in idx) => {
+                match self.sums.value(idx) {
+                    Some(sum) if sum > min && sum < max => builder.append_value(sum.as_i128()),
+                    Some(_) if fail_on_overflow => {
+                        return df_execution_err!(
+                            "arithmetic overflow in sum of decimals, result exceeds {dt:?}"
+                        );
+                    }
+                    _ => builder.append_null(),
+                }
+            }
+        }
+        Ok(Arc::new(builder.finish().with_data_type(dt.clone())))
+    }
+}
+
+impl AccColumn for AccWideDecimalColumn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.sums.resize(len);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.sums.fill_null_range(start, end);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.sums.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.sums.num_records()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.sums.mem_used()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        self.sums.freeze_to_rows(idx, array)
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        self.sums.unfreeze_from_rows(cursors)
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        w.write_u8(WIDE_DECIMAL_SPILL_VERSION)?;
+        self.sums.spill(idx, w)
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        let version = r.read_u8()?;
+        if version != WIDE_DECIMAL_SPILL_VERSION {
+            return df_execution_err!(
+                "unsupported wide decimal sum spill version: {version}, expected \
+                 {WIDE_DECIMAL_SPILL_VERSION}"
+            );
+        }
+        self.sums.unspill(num_rows, r)
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        self.sums.compact(retained)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+    use crate::memmgr::spill::Spill;
+
+    #[test]
+    fn test_sum_decimal_beyond_128_bits() -> Result<()> {
+        let dt = DataType::Decimal128(38, 0);
+        let agg = AggSum::try_new(Arc::new(Column::new("v", 0)), dt.clone())?;
+        let large = 9 * 10i128.pow(37);
+        let input: ArrayRef = Arc::new(
+            Decimal128Array::from(vec![large, large, large, -large, -large, large, large])
+                .with_data_type(dt.clone()),
+        );
+        let partial_args = agg.prepare_partial_args(&[input])?;
+
+        // group 0: 9e37 * 3 - 9e37 * 2 overflows 128 bits in the middle
+        // group 1: 9e37 * 2 overflows the result precision
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 0, 0, 0, 1, 1]),
+            &partial_args,
+            IdxSelection::Range(0, 7),
+        )?;
+
+        // spill and merge into fresh accs
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 2), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(2, &mut spill.get_compressed_reader())?;
+        let mut merged_accs = agg.create_acc_column(2);
+        agg.partial_merge(
+            &mut merged_accs,
+            IdxSelection::Range(0, 2),
+            &mut spilled_accs,
+            IdxSelection::Range(0, 2),
+        )?;
+
+        // same as spark: select sum(v) ... returns 9e37 and null
+        let output = agg.final_merge(&mut merged_accs, IdxSelection::Range(0, 2))?;
+        assert_eq!(output.data_type(), &dt);
+        assert_eq!(
+            output.as_primitive::<Decimal128Type>(),
+            &Decimal128Array::from(vec![Some(large), None]).with_data_type(dt.clone()),
+        );
+
+        // ansi mode raises an error on overflow
+        let agg = agg.with_fail_on_overflow(true);
+        assert!(agg
+            .final_merge(&mut merged_accs, IdxSelection::Single(0))
+            .is_ok());
+        assert!(agg
+            .final_merge(&mut merged_accs, IdxSelection::Single(1))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_decimal_spill_version() -> Result<()> {
+        let agg = AggSum::try_new(Arc::new(Column::new("v", 0)), DataType::Decimal128(38, 2))?;
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        spill_writer.write_u8(WIDE_DECIMAL_SPILL_VERSION + 1)?;
+        spill_writer.finish()?;
+
+        let mut accs = agg.create_acc_column(0);
+        assert!(accs.unspill(0, &mut spill.get_compressed_reader()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,642 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use arrow::{array::*, datatypes::*};
+use datafusion::{
+    common::{utils::proxy::VecAllocExt, Result},
+    physical_expr::PhysicalExpr,
+};
+use datafusion_ext_commons::{
+    df_execution_err, df_unimplemented_err, downcast_any, SliceAsRawBytes,
+};
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::IdxSelection,
+        count::CountOverflowBehavior,
+        Agg,
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// sum and count of non-null values, accumulated together in one acc column
+/// and used by avg(). the result is a struct of {sum, count}. like spark's
+/// sum(), the sum of a group without any non-null value is null, and the count
+/// follows spark.blaze.agg.count.overflowBehavior like count().
+pub struct AggSumCount {
+    child: Arc<dyn PhysicalExpr>,
+    sum_type: DataType,
+    data_type: DataType,
+    overflow_behavior: CountOverflowBehavior,
+}
+
+impl AggSumCount {
+    pub fn try_new(child: Arc<dyn PhysicalExpr>, sum_type: DataType) -> Result<Self> {
+        let data_type = DataType::Struct(Fields::from(vec![
+            Field::new("sum", sum_type.clone(), true),
+            Field::new("count", DataType::Int64, false),
+        ]));
+        Ok(Self {
+            child,
+            sum_type,
+            data_type,
+            overflow_behavior: CountOverflowBehavior::default(),
+        })
+    }
+
+    pub fn with_overflow_behavior(mut self, overflow_behavior: CountOverflowBehavior) -> Self {
+        self.overflow_behavior = overflow_behavior;
+        self
+    }
+
+    pub fn sum_type(&self) -> &DataType {
+        &self.sum_type
+    }
+
+    pub fn overflow_behavior(&self) -> CountOverflowBehavior {
+        self.overflow_behavior
+    }
+
+    // same as sum(), decimal sums with capped result precision are accumulated
+    // in 256 bits and checked when converting to the result
+    fn use_wide_decimal(&self) -> bool {
+        matches!(self.sum_type, DataType::Decimal128(38, _))
+    }
+
+    /// evaluates sums and counts of the groups
+    pub fn final_sums_and_counts(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+    ) -> Result<(ArrayRef, Int64Array)> {
+        if self.use_wide_decimal() {
+            let accs = downcast_any!(accs, mut AccSumCountColumn<i256>)?;
+            let max = i256::from_i128(10i128.pow(38));
+            let min = i256::from_i128(-10i128.pow(38));
+            let mut sums = Decimal128Builder::with_capacity(acc_idx.len());
+            let mut counts = Int64Builder::with_capacity(acc_idx.len());
+            idx_for! {
+                (acc_idx in acc_idx) => {
+                    let value = accs.values[acc_idx];
+                    match value.sum {
+                        sum if value.count > 0 && sum > min && sum < max => {
+                            sums.append_value(sum.as_i128());
+                        }
+                        _ => sums.append_null(),
+                    }
+                    counts.append_value(value.count);
+                }
+            }
+            let sums = sums.finish().with_data_type(self.sum_type.clone());
+            return Ok((Arc::new(sums), counts.finish()));
+        }
+
+        macro_rules! handle_primitive {
+            ($ty:ty) => {{
+                type TNative = <$ty as ArrowPrimitiveType>::Native;
+                let accs = downcast_any!(accs, mut AccSumCountColumn<TNative>)?;
+                let mut sums = PrimitiveBuilder::<$ty>::with_capacity(acc_idx.len());
+                let mut counts = Int64Builder::with_capacity(acc_idx.len());
+                idx_for! {
+                    (acc_idx in acc_idx) => {
+                        let value = accs.values[acc_idx];
+                        sums.append_option((value.count > 0).then_some(value.sum));
+                        counts.append_value(value.count);
+                    }
+                }
+                let sums = sums.finish().with_data_type(self.sum_type.clone());
+                Ok((Arc::new(sums), counts.finish()))
+            }};
+        }
+        downcast_primitive! {
+            (&self.sum_type) => (handle_primitive),
+            other => df_unimplemented_err!("unsupported data type in sum_count(): {other}"),
+        }
+    }
+}
+
+impl Debug for AggSumCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SumCount({:?})", self.child)
+    }
+}
+
+impl Agg for AggSumCount {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(
+            Self::try_new(exprs[0].clone(), self.sum_type.clone())?
+                .with_overflow_behavior(self.overflow_behavior),
+        ))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        false
+    }
+
+    fn is_thread_safe_for_parallel(&self) -> bool {
+        true
+    }
+
+    fn prepare_partial_args(&self, partial_inputs: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        // cast arg1 to sum data type
+        Ok(vec![datafusion_ext_commons::arrow::cast::cast(
+            &partial_inputs[0],
+            &self.sum_type,
+        )?])
+    }
+
+    fn expected_partial_state_size_bytes_per_group(&self) -> usize {
+        // same as AccSumCountColumn::RECORD_SIZE
+        let sum_size = match self.use_wide_decimal() {
+            true => size_of::<i256>(),
+            false => self.sum_type.primitive_width().unwrap_or(0),
+        };
+        sum_size + size_of::<i64>()
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        if self.use_wide_decimal() {
+            return Box::new(AccSumCountColumn::<i256>::new(num_rows));
+        }
+        macro_rules! handle_primitive {
+            ($ty:ty) => {{
+                type TNative = <$ty as ArrowPrimitiveType>::Native;
+                Box::new(AccSumCountColumn::<TNative>::new(num_rows)) as AccColumnRef
+            }};
+        }
+        downcast_primitive! {
+            (&self.sum_type) => (handle_primitive),
+            other => panic!("unsupported data type in sum_count(): {other}"),
+        }
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let partial_arg = &partial_args[0];
+        accs.ensure_size(acc_idx);
+
+        if self.use_wide_decimal() {
+            let partial_arg = partial_arg.as_primitive::<Decimal128Type>();
+            let accs = downcast_any!(accs, mut AccSumCountColumn<i256>)?;
+            idx_for_zipped! {
+                ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                    if partial_arg.is_valid(partial_arg_idx) {
+                        let partial_value = i256::from_i128(partial_arg.value(partial_arg_idx));
+                        accs.update(acc_idx, partial_value, self.overflow_behavior)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        downcast_primitive_array! {
+            partial_arg => {
+                let accs = downcast_any!(accs, mut AccSumCountColumn<_>)?;
+                idx_for_zipped! {
+                    ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                        if partial_arg.is_valid(partial_arg_idx) {
+                            let partial_value = partial_arg.value(partial_arg_idx);
+                            accs.update(acc_idx, partial_value, self.overflow_behavior)?;
+                        }
+                    }
+                }
+            }
+            other => df_unimplemented_err!("unsupported data type in sum_count(): {other}")?,
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        accs.ensure_size(acc_idx);
+
+        fn merge<T: ArrowNativeTypeOp>(
+            accs: &mut AccColumnRef,
+            acc_idx: IdxSelection<'_>,
+            merging_accs: &mut AccColumnRef,
+            merging_acc_idx: IdxSelection<'_>,
+            overflow_behavior: CountOverflowBehavior,
+        ) -> Result<()> {
+            let accs = downcast_any!(accs, mut AccSumCountColumn<T>)?;
+            let merging_accs = downcast_any!(merging_accs, mut AccSumCountColumn<T>)?;
+            idx_for_zipped! {
+                ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                    let merging_value = merging_accs.values[merging_acc_idx];
+                    accs.merge(acc_idx, merging_value, overflow_behavior)?;
+                }
+            }
+            Ok(())
+        }
+
+        let overflow_behavior = self.overflow_behavior;
+        if self.use_wide_decimal() {
+            return merge::<i256>(
+                accs,
+                acc_idx,
+                merging_accs,
+                merging_acc_idx,
+                overflow_behavior,
+            );
+        }
+        macro_rules! handle_primitive {
+            ($ty:ty) => {{
+                type TNative = <$ty as ArrowPrimitiveType>::Native;
+                merge::<TNative>(
+                    accs,
+                    acc_idx,
+                    merging_accs,
+                    merging_acc_idx,
+                    overflow_behavior,
+                )
+            }};
+        }
+        downcast_primitive! {
+            (&self.sum_type) => (handle_primitive),
+            other => df_unimplemented_err!("unsupported data type in sum_count(): {other}"),
+        }
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let (sums, counts) = self.final_sums_and_counts(accs, acc_idx)?;
+        let DataType::Struct(fields) = &self.data_type else {
+            return df_execution_err!("sum_count() expect struct data type");
+        };
+        Ok(Arc::new(StructArray::try_new(
+            fields.clone(),
+            vec![sums, Arc::new(counts)],
+            None,
+        )?))
+    }
+}
+
+/// sum and count of a group
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct SumCount<T> {
+    pub sum: T,
+    pub count: i64,
+}
+
+/// sums and counts of groups. each group is spilled and frozen as a fixed
+/// width record of its sum followed by its count.
+pub struct AccSumCountColumn<T: ArrowNativeTypeOp> {
+    pub values: Vec<SumCount<T>>,
+}
+
+impl<T: ArrowNativeTypeOp> AccSumCountColumn<T> {
+    const RECORD_SIZE: usize = size_of::<T>() + size_of::<i64>();
+
+    pub fn new(num_records: usize) -> Self {
+        Self {
+            values: vec![SumCount::default(); num_records],
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        idx: usize,
+        value: T,
+        overflow_behavior: CountOverflowBehavior,
+    ) -> Result<()> {
+        let acc = &mut self.values[idx];
+        acc.sum = acc.sum.add_wrapping(value);
+        overflow_behavior.add(&mut acc.count, 1)
+    }
+
+    pub fn merge(
+        &mut self,
+        idx: usize,
+        other: SumCount<T>,
+        overflow_behavior: CountOverflowBehavior,
+    ) -> Result<()> {
+        let acc = &mut self.values[idx];
+        acc.sum = acc.sum.add_wrapping(other.sum);
+        overflow_behavior.add(&mut acc.count, other.count)
+    }
+
+    fn write_record(&self, idx: usize, buf: &mut Vec<u8>) {
+        let value = self.values[idx];
+        buf.extend_from_slice([value.sum].as_raw_bytes());
+        buf.extend_from_slice([value.count].as_raw_bytes());
+    }
+
+    fn read_record(record: &[u8]) -> SumCount<T> {
+        let mut sum = [T::default()];
+        let mut count = [0i64];
+        let (sum_bytes, count_bytes) = record.split_at(size_of::<T>());
+        sum.as_raw_bytes_mut().copy_from_slice(sum_bytes);
+        count.as_raw_bytes_mut().copy_from_slice(count_bytes);
+        SumCount {
+            sum: sum[0],
+            count: count[0],
+        }
+    }
+}
+
+impl<T: ArrowNativeTypeOp> AccColumn for AccSumCountColumn<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.values.resize(len, SumCount::default());
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        self.values[start..end].fill(SumCount::default());
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.values.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.values.allocated_size()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                self.write_record(idx, &mut array[array_idx]);
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut record = vec![0u8; Self::RECORD_SIZE];
+
+        for cursor in cursors {
+            cursor.read_exact(&mut record)?;
+            self.values.push(Self::read_record(&record));
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        let mut buf = Vec::with_capacity(idx.len() * Self::RECORD_SIZE);
+        idx_for! {
+            (idx in idx) => {
+                self.write_record(idx, &mut buf);
+            }
+        }
+        w.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        let mut buf = vec![0u8; num_rows * Self::RECORD_SIZE];
+        r.read_exact(&mut buf)?;
+        self.values = buf
+            .chunks_exact(Self::RECORD_SIZE)
+            .map(Self::read_record)
+            .collect();
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.values, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Instant};
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{common::Result, physical_expr::expressions::Column};
+
+    use crate::{
+        agg::{
+            agg::{Agg, IdxSelection},
+            count::{AggCount, CountOverflowBehavior},
+            sum::AggSum,
+            sum_count::{AccSumCountColumn, AggSumCount},
+        },
+        memmgr::spill::Spill,
+    };
+
+    #[test]
+    fn test_sum_count() -> Result<()> {
+        let child = Arc::new(Column::new("a", 0));
+        let agg = AggSumCount::try_new(child.clone(), DataType::Int64)?;
+        let agg_sum = AggSum::try_new(child.clone(), DataType::Int64)?;
+        let agg_count = AggCount::try_new(vec![child.clone()], DataType::Int64)?;
+
+        // group 2 has only null values
+        let values: ArrayRef = Arc::new(Int32Array::from(vec![
+            Some(1),
+            None,
+            Some(2),
+            Some(10),
+            None,
+            Some(-3),
+            Some(20),
+        ]));
+        let acc_indices = [0, 0, 0, 1, 2, 0, 1];
+        let partial_args = agg.prepare_partial_args(&[values])?;
+
+        let mut accs1 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs1,
+            IdxSelection::Indices(&acc_indices[..4]),
+            &partial_args,
+            IdxSelection::Range(0, 4),
+        )?;
+        let mut accs2 = agg.create_acc_column(0);
+        agg.partial_update(
+            &mut accs2,
+            IdxSelection::Indices(&acc_indices[4..]),
+            &partial_args,
+            IdxSelection::Range(4, 7),
+        )?;
+
+        // spill and merge
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs2.spill(IdxSelection::Range(0, 3), &mut spill_writer)?;
+        spill_writer.finish()?;
+        let mut unspilled = agg.create_acc_column(0);
+        unspilled.unspill(3, &mut spill.get_compressed_reader())?;
+        agg.partial_merge(
+            &mut accs1,
+            IdxSelection::Range(0, 3),
+            &mut unspilled,
+            IdxSelection::Range(0, 3),
+        )?;
+
+        let result = agg.final_merge(&mut accs1, IdxSelection::Range(0, 3))?;
+        let result_struct = result.as_struct();
+        let sums = result_struct.column(0).as_primitive::<Int64Type>();
+        let counts = result_struct.column(1).as_primitive::<Int64Type>();
+        assert_eq!(sums, &Int64Array::from(vec![Some(0), Some(30), None]));
+        assert_eq!(counts, &Int64Array::from(vec![3, 2, 0]));
+
+        // same results as separate sum() and count()
+        let mut sum_accs = agg_sum.create_acc_column(0);
+        let mut count_accs = agg_count.create_acc_column(0);
+        agg_sum.partial_update(
+            &mut sum_accs,
+            IdxSelection::Indices(&acc_indices),
+            &partial_args,
+            IdxSelection::Range(0, 7),
+        )?;
+        agg_count.partial_update(
+            &mut count_accs,
+            IdxSelection::Indices(&acc_indices),
+            &partial_args,
+            IdxSelection::Range(0, 7),
+        )?;
+        let expected_sums = agg_sum.final_merge(&mut sum_accs, IdxSelection::Range(0, 3))?;
+        let expected_counts = agg_count.final_merge(&mut count_accs, IdxSelection::Range(0, 3))?;
+        assert_eq!(result_struct.column(0), &expected_sums);
+        assert_eq!(result_struct.column(1), &expected_counts);
+
+        // freeze and unfreeze
+        let mut rows = vec![vec![]; 3];
+        accs1.freeze_to_rows(IdxSelection::Range(0, 3), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| std::io::Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        let mut unfreezed = agg.create_acc_column(0);
+        unfreezed.unfreeze_from_rows(&mut cursors)?;
+        let unfreezed_result = agg.final_merge(&mut unfreezed, IdxSelection::Range(0, 3))?;
+        assert_eq!(&unfreezed_result, &result);
+        Ok(())
+    }
+    #[test]
+    fn test_sum_count_overflow() -> Result<()> {
+        let child = Arc::new(Column::new("a", 0));
+        let merged_count = |overflow_behavior: CountOverflowBehavior| -> Result<i64> {
+            let agg = AggSumCount::try_new(child.clone(), DataType::Int64)?
+                .with_overflow_behavior(overflow_behavior);
+            let mut accs = agg.create_acc_column(1);
+            let mut merging_accs = agg.create_acc_column(1);
+            accs.as_any_mut()
+                .downcast_mut::<AccSumCountColumn<i64>>()
+                .unwrap()
+                .values[0]
+                .count = i64::MAX;
+            merging_accs
+                .as_any_mut()
+                .downcast_mut::<AccSumCountColumn<i64>>()
+                .unwrap()
+                .values[0]
+                .count = 1;
+            agg.partial_merge(
+                &mut accs,
+                IdxSelection::Single(0),
+                &mut merging_accs,
+                IdxSelection::Single(0),
+            )?;
+            let result = agg.final_merge(&mut accs, IdxSelection::Single(0))?;
+            Ok(result
+                .as_struct()
+                .column(1)
+                .as_primitive::<Int64Type>()
+                .value(0))
+        };
+        assert_eq!(merged_count(CountOverflowBehavior::Saturate)?, i64::MAX);
+        assert_eq!(merged_count(CountOverflowBehavior::Wrap)?, i64::MIN);
+        assert!(merged_count(CountOverflowBehavior::Error).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bench_sum_count_update() -> Result<()> {
+        let num_rows = 1 << 20;
+        let num_groups = 1024;
+        let child = Arc::new(Column::new("a", 0));
+        let agg = AggSumCount::try_new(child.clone(), DataType::Int64)?;
+        let agg_sum = AggSum::try_new(child.clone(), DataType::Int64)?;
+        let agg_count = AggCount::try_new(vec![child.clone()], DataType::Int64)?;
+
+        let values: ArrayRef = Arc::new(Int64Array::from_iter(
+            (0..num_rows).map(|i| (i % 5 != 0).then_some(i as i64)),
+        ));
+        let partial_args = agg.prepare_partial_args(&[values])?;
+        let acc_indices = (0..num_rows)
+            .map(|i| (i * 7919) % num_groups)
+            .collect::<Vec<_>>();
+        let acc_idx = IdxSelection::Indices(&acc_indices);
+        let partial_arg_idx = IdxSelection::Range(0, num_rows);
+
+        // fused sum and count
+        let mut accs = agg.create_acc_column(num_groups);
+        let time_start = Instant::now();
+        agg.partial_update(&mut accs, acc_idx, &partial_args, partial_arg_idx)?;
+        let result = agg.final_merge(&mut accs, IdxSelection::Range(0, num_groups))?;
+        eprintln!("sum_count_fused_time: {:?}", time_start.elapsed());
+
+        // separate sum() and count()
+        let mut sum_accs = agg_sum.create_acc_column(num_groups);
+        let mut count_accs = agg_count.create_acc_column(num_groups);
+        let time_start = Instant::now();
+        agg_sum.partial_update(&mut sum_accs, acc_idx, &partial_args, partial_arg_idx)?;
+        agg_count.partial_update(&mut count_accs, acc_idx, &partial_args, partial_arg_idx)?;
+        let sums = agg_sum.final_merge(&mut sum_accs, IdxSelection::Range(0, num_groups))?;
+        let counts = agg_count.final_merge(&mut count_accs, IdxSelection::Range(0, num_groups))?;
+        eprintln!("sum_count_separate_time: {:?}", time_start.elapsed());
+
+        assert_eq!(result.as_struct().column(0), &sums);
+        assert_eq!(result.as_struct().column(1), &counts);
+        Ok(())
+    }
+}
@@ -0,0 +1,507 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    io::{Cursor, Read, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use arrow::{
+    array::*,
+    buffer::{NullBuffer, OffsetBuffer},
+    datatypes::*,
+};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::{
+    df_execution_err, downcast_any,
+    io::{read_len, write_len},
+    SliceAsRawBytes,
+};
+
+use crate::{
+    agg::{
+        acc::{compact_items, AccColumn, AccColumnRef},
+        agg::{Agg, IdxSelection},
+    },
+    idx_for, idx_for_zipped,
+    memmgr::spill::{SpillCompressedReader, SpillCompressedWriter},
+};
+
+/// element-wise sum of list values of each group. shorter lists are padded
+/// with zeros, null lists and null elements are ignored.
+pub struct AggSumList<T: ArrowPrimitiveType> {
+    child: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: ArrowPrimitiveType> AggSumList<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    pub fn try_new(child: Arc<dyn PhysicalExpr>, data_type: DataType) -> Result<Self> {
+        match &data_type {
+            DataType::List(field) if field.data_type() == &T::DATA_TYPE => {}
+            other => return df_execution_err!("sum_list: unsupported data type: {other}"),
+        }
+        Ok(Self {
+            child,
+            data_type,
+            _phantom: Default::default(),
+        })
+    }
+}
+
+impl<T: ArrowPrimitiveType> Debug for AggSumList<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SumList({:?})", self.child)
+    }
+}
+
+impl<T: ArrowPrimitiveType> Agg for AggSumList<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn exprs(&self) -> Vec<Arc<dyn PhysicalExpr>> {
+        vec![self.child.clone()]
+    }
+
+    fn with_new_exprs(&self, exprs: Vec<Arc<dyn PhysicalExpr>>) -> Result<Arc<dyn Agg>> {
+        Ok(Arc::new(Self::try_new(
+            exprs[0].clone(),
+            self.data_type.clone(),
+        )?))
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    fn nullable(&self) -> bool {
+        true
+    }
+
+    fn create_acc_column(&self, num_rows: usize) -> AccColumnRef {
+        let mut col = Box::new(AccSumListColumn::<T::Native>::empty());
+        col.resize(num_rows);
+        col
+    }
+
+    fn partial_update(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        partial_args: &[ArrayRef],
+        partial_arg_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccSumListColumn<T::Native>)?;
+        accs.ensure_size(acc_idx);
+
+        let list = downcast_any!(&partial_args[0], ListArray)?;
+        let values = list.values().as_primitive::<T>();
+        let offsets = list.value_offsets();
+
+        idx_for_zipped! {
+            ((acc_idx, partial_arg_idx) in (acc_idx, partial_arg_idx)) => {
+                if list.is_valid(partial_arg_idx) {
+                    let start = offsets[partial_arg_idx] as usize;
+                    let end = offsets[partial_arg_idx + 1] as usize;
+                    accs.add_values(acc_idx, values, start, end);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_merge(
+        &self,
+        accs: &mut AccColumnRef,
+        acc_idx: IdxSelection<'_>,
+        merging_accs: &mut AccColumnRef,
+        merging_acc_idx: IdxSelection<'_>,
+    ) -> Result<()> {
+        let accs = downcast_any!(accs, mut AccSumListColumn<T::Native>)?;
+        accs.ensure_size(acc_idx);
+
+        let merging_accs = downcast_any!(merging_accs, mut AccSumListColumn<T::Native>)?;
+        idx_for_zipped! {
+            ((acc_idx, merging_acc_idx) in (acc_idx, merging_acc_idx)) => {
+                let merging_sums = merging_accs.take(merging_acc_idx);
+                accs.merge_sums(acc_idx, merging_sums);
+            }
+        }
+        Ok(())
+    }
+
+    fn final_merge(&self, accs: &mut AccColumnRef, acc_idx: IdxSelection<'_>) -> Result<ArrayRef> {
+        let accs = downcast_any!(accs, mut AccSumListColumn<T::Native>)?;
+        let mut values: Vec<T::Native> = vec![];
+        let mut lengths = Vec::with_capacity(acc_idx.len());
+        let mut valids = Vec::with_capacity(acc_idx.len());
+
+        idx_for! {
+            (acc_idx in acc_idx) => {
+                match accs.take(acc_idx) {
+                    Some(sums) => {
+                        lengths.push(sums.len());
+                        valids.push(true);
+                        values.extend(sums);
+                    }
+                    None => {
+                        lengths.push(0);
+                        valids.push(false);
+                    }
+                }
+            }
+        }
+
+        let field = match &self.data_type {
+            DataType::List(field) => field.clone(),
+            _ => unreachable!(),
+        };
+        let values = PrimitiveArray::<T>::new(values.into(), None);
+        Ok(Arc::new(ListArray::try_new(
+            field,
+            OffsetBuffer::from_lengths(lengths),
+            Arc::new(values),
+            Some(NullBuffer::from(valids)),
+        )?))
+    }
+}
+
+pub struct AccSumListColumn<T: ArrowNativeTypeOp> {
+    sums: Vec<Option<Vec<T>>>,
+    heap_mem_used: usize,
+}
+
+impl<T: ArrowNativeTypeOp> AccSumListColumn<T> {
+    pub fn empty() -> Self {
+        Self {
+            sums: vec![],
+            heap_mem_used: 0,
+        }
+    }
+
+    pub fn add_values<P: ArrowPrimitiveType<Native = T>>(
+        &mut self,
+        idx: usize,
+        values: &PrimitiveArray<P>,
+        start: usize,
+        end: usize,
+    ) {
+        let sums = self.sums[idx].get_or_insert_with(Vec::new);
+        self.heap_mem_used -= sums.capacity() * size_of::<T>();
+        if sums.len() < end - start {
+            sums.resize(end - start, T::ZERO);
+        }
+        for (sum, value_idx) in sums.iter_mut().zip(start..end) {
+            if values.is_valid(value_idx) {
+                *sum = sum.add_wrapping(values.value(value_idx));
+            }
+        }
+        self.heap_mem_used += sums.capacity() * size_of::<T>();
+    }
+
+    pub fn merge_sums(&mut self, idx: usize, other: Option<Vec<T>>) {
+        let Some(mut other) = other else {
+            return;
+        };
+        let Some(mut sums) = self.take(idx) else {
+            self.put(idx, Some(other));
+            return;
+        };
+        if sums.len() < other.len() {
+            // ensure the merged list is longer
+            std::mem::swap(&mut sums, &mut other);
+        }
+        for (sum, value) in sums.iter_mut().zip(other) {
+            *sum = sum.add_wrapping(value);
+        }
+        self.put(idx, Some(sums));
+    }
+
+    pub fn take(&mut self, idx: usize) -> Option<Vec<T>> {
+        let sums = std::mem::take(&mut self.sums[idx]);
+        self.heap_mem_used -= sums.as_ref().map(|s| s.capacity()).unwrap_or(0) * size_of::<T>();
+        sums
+    }
+
+    fn put(&mut self, idx: usize, sums: Option<Vec<T>>) {
+        self.heap_mem_used += sums.as_ref().map(|s| s.capacity()).unwrap_or(0) * size_of::<T>();
+        self.sums[idx] = sums;
+    }
+
+    // format: 0 for null, otherwise 1 + number of elements followed by raw
+    // element values
+    fn save_raw(&self, idx: usize, w: &mut impl Write) -> Result<()> {
+        match &self.sums[idx] {
+            Some(sums) => {
+                write_len(sums.len() + 1, w)?;
+                w.write_all(sums.as_raw_bytes())?;
+            }
+            None => write_len(0, w)?,
+        }
+        Ok(())
+    }
+
+    fn load_raw(&mut self, idx: usize, r: &mut impl Read) -> Result<()> {
+        let sums = match read_len(r)? {
+            0 => None,
+            len => {
+                let mut sums = vec![T::ZERO; len - 1];
+                r.read_exact(sums.as_raw_bytes_mut())?;
+                Some(sums)
+            }
+        };
+        self.take(idx);
+        self.put(idx, sums);
+        Ok(())
+    }
+}
+
+impl<T: ArrowNativeTypeOp> AccColumn for AccSumListColumn<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn resize(&mut self, len: usize) {
+        if len < self.sums.len() {
+            self.fill_null_range(len, self.sums.len());
+        }
+        self.sums.resize(len, None);
+    }
+
+    fn fill_null_range(&mut self, start: usize, end: usize) {
+        for idx in start..end {
+            self.take(idx);
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.sums.shrink_to_fit();
+    }
+
+    fn num_records(&self) -> usize {
+        self.sums.len()
+    }
+
+    fn mem_used(&self) -> usize {
+        self.heap_mem_used + self.sums.capacity() * size_of::<Option<Vec<T>>>()
+    }
+
+    fn freeze_to_rows(&self, idx: IdxSelection<'_>, array: &mut [Vec<u8>]) -> Result<()> {
+        let mut array_idx = 0;
+
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, &mut array[array_idx])?;
+                array_idx += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn unfreeze_from_rows(&mut self, cursors: &mut [Cursor<&[u8]>]) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(cursors.len());
+
+        for (idx, cursor) in cursors.iter_mut().enumerate() {
+            self.load_raw(idx, cursor)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&self, idx: IdxSelection<'_>, w: &mut SpillCompressedWriter) -> Result<()> {
+        idx_for! {
+            (idx in idx) => {
+                self.save_raw(idx, w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unspill(&mut self, num_rows: usize, r: &mut SpillCompressedReader) -> Result<()> {
+        assert_eq!(self.num_records(), 0, "expect empty AccColumn");
+        self.resize(num_rows);
+
+        for idx in 0..num_rows {
+            self.load_raw(idx, r)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, retained: &[usize]) -> Result<()> {
+        compact_items(&mut self.sums, retained);
+        self.resize(retained.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+    use crate::memmgr::spill::Spill;
+
+    fn list_type(dt: DataType) -> DataType {
+        DataType::List(Arc::new(Field::new_list_field(dt, true)))
+    }
+
+    #[test]
+    fn test_sum_list_uniform_length() -> Result<()> {
+        let agg = AggSumList::<Int64Type>::try_new(
+            Arc::new(Column::new("v", 0)),
+            list_type(DataType::Int64),
+        )?;
+        let input: ArrayRef = Arc::new(ListArray::from_iter_primitive::<Int64Type, _, _>(vec![
+            Some(vec![Some(1), Some(2), Some(3)]),
+            Some(vec![Some(10), None, Some(30)]),
+            None,
+            Some(vec![Some(100), Some(200), Some(300)]),
+            None,
+        ]));
+
+        // group 0: rows 0, 1, group 1: rows 2, 3, group 2: only nulls
+        let mut accs = agg.create_acc_column(3);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1, 1, 2]),
+            &[input],
+            IdxSelection::Range(0, 5),
+        )?;
+        let output = agg.final_merge(&mut accs, IdxSelection::Range(0, 3))?;
+        assert_eq!(
+            output.as_list::<i32>(),
+            &ListArray::from_iter_primitive::<Int64Type, _, _>(vec![
+                Some(vec![Some(11), Some(2), Some(33)]),
+                Some(vec![Some(100), Some(200), Some(300)]),
+                None,
+            ]),
+        );
+        assert_eq!(downcast_any!(accs, AccSumListColumn<i64>)?.heap_mem_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_list_ragged_length() -> Result<()> {
+        let agg = AggSumList::<Float64Type>::try_new(
+            Arc::new(Column::new("v", 0)),
+            list_type(DataType::Float64),
+        )?;
+        let first_half: ArrayRef =
+            Arc::new(ListArray::from_iter_primitive::<Float64Type, _, _>(vec![
+                Some(vec![Some(1.0)]),
+                Some(vec![Some(1.0), Some(2.0), Some(3.0)]),
+                Some(vec![]),
+            ]));
+        let second_half: ArrayRef =
+            Arc::new(ListArray::from_iter_primitive::<Float64Type, _, _>(vec![
+                Some(vec![Some(0.5), Some(0.5), Some(0.5), Some(0.5)]),
+                Some(vec![Some(2.0)]),
+            ]));
+
+        // spill accs of the first half, then merge them with the second half
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0, 1]),
+            &[first_half],
+            IdxSelection::Range(0, 3),
+        )?;
+        let mut spill: Box<dyn Spill> = Box::new(vec![]);
+        let mut spill_writer = spill.get_compressed_writer();
+        accs.spill(IdxSelection::Range(0, 2), &mut spill_writer)?;
+        spill_writer.finish()?;
+
+        let mut accs = agg.create_acc_column(2);
+        agg.partial_update(
+            &mut accs,
+            IdxSelection::Indices(&[0, 0]),
+            &[second_half],
+            IdxSelection::Range(0, 2),
+        )?;
+        let mut spilled_accs = agg.create_acc_column(0);
+        spilled_accs.unspill(2, &mut spill.get_compressed_reader())?;
+        agg.partial_merge(
+            &mut spilled_accs,
+            IdxSelection::Range(0, 2),
+            &mut accs,
+            IdxSelection::Range(0, 2),
+        )?;
+        let merged = downcast_any!(spilled_accs, AccSumListColumn<f64>)?;
+        assert_eq!(
+            merged.heap_mem_used,
+            merged
+                .sums
+                .iter()
+                .flatten()
+                .map(|sums| sums.capacity() * size_of::<f64>())
+                .sum::<usize>(),
+        );
+
+        // group 0: [1] + [1, 2, 3] + [0.5, 0.5, 0.5, 0.5] + [2], group 1: []
+        let output = agg.final_merge(&mut spilled_accs, IdxSelection::Range(0, 2))?;
+        assert_eq!(
+            output.as_list::<i32>(),
+            &ListArray::from_iter_primitive::<Float64Type, _, _>(vec![
+                Some(vec![Some(4.5), Some(2.5), Some(3.5), Some(0.5)]),
+                Some(vec![]),
+            ]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_list_freeze_and_unfreeze() -> Result<()> {
+        let mut acc_col = AccSumListColumn::<i64>::empty();
+        acc_col.resize(3);
+        let values = Int64Array::from(vec![1, 2, 3, 4]);
+        acc_col.add_values(0, &values, 0, 4);
+        acc_col.add_values(1, &values, 1, 2);
+
+        let mut rows = vec![vec![]; 3];
+        AccColumn::freeze_to_rows(&acc_col, IdxSelection::Range(0, 3), &mut rows)?;
+        let mut cursors = rows
+            .iter()
+            .map(|row| Cursor::new(row.as_slice()))
+            .collect::<Vec<_>>();
+        let mut acc_col_unfrozen = AccSumListColumn::<i64>::empty();
+        AccColumn::unfreeze_from_rows(&mut acc_col_unfrozen, &mut cursors)?;
+
+        assert_eq!(acc_col_unfrozen.take(0), Some(vec![1, 2, 3, 4]));
+        assert_eq!(acc_col_unfrozen.take(1), Some(vec![2]));
+        assert_eq!(acc_col_unfrozen.take(2), None);
+        assert_eq!(acc_col_unfrozen.heap_mem_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_list_unsupported_type() {
+        let child = Arc::new(Column::new("v", 0));
+        assert!(AggSumList::<Int64Type>::try_new(child.clone(), DataType::Int64).is_err());
+        assert!(AggSumList::<Int64Type>::try_new(child, list_type(DataType::Float64)).is_err());
+    }
+}
@@ -0,0 +1,729 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, StructArray},
+    datatypes::{DataType, SchemaRef},
+};
+use blaze_jni_bridge::{
+    jni_bridge::LocalRef, jni_call, jni_get_byte_array_len, jni_get_byte_array_region,
+    jni_new_direct_byte_buffer, jni_new_global_ref, jni_new_object, jni_new_prim_array,
+};
+use datafusion::common::{DataFusionError, Result};
+use datafusion_ext_commons::{
+    arrow::ffi_struct::{
+        export_struct_for_jni, import_schema, import_struct_from_jni, imported_as_data_type,
+    },
+    df_execution_err,
+};
+use jni::objects::{GlobalRef, JObject};
+
+use crate::agg::spark_udaf_wrapper::SparkUDAFMemTracker;
+
+/// buffer rows created and accessed by a udaf context, which is a
+/// BufferRowsColumn object in jvm for the jni context
+pub struct UDAFRows(Box<dyn Any + Send + Sync>);
+
+impl UDAFRows {
+    pub fn new<T: Any + Send + Sync>(rows: T) -> Self {
+        Self(Box::new(rows))
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Result<&T> {
+        match self.0.downcast_ref() {
+            Some(rows) => Ok(rows),
+            None => df_execution_err!("UDAFRows: unexpected rows type"),
+        }
+    }
+
+    pub fn downcast_mut<T: Any>(&mut self) -> Result<&mut T> {
+        match self.0.downcast_mut() {
+            Some(rows) => Ok(rows),
+            None => df_execution_err!("UDAFRows: unexpected rows type"),
+        }
+    }
+}
+
+/// indices exported to a udaf context, which can be cached and shared by all
+/// UDAFs processing the same rows
+pub struct UDAFIndices(Box<dyn Any>);
+
+impl UDAFIndices {
+    pub fn new<T: Any>(indices: T) -> Self {
+        Self(Box::new(indices))
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Result<&T> {
+        match self.0.downcast_ref() {
+            Some(indices) => Ok(indices),
+            None => df_execution_err!("UDAFIndices: unexpected indices type"),
+        }
+    }
+}
+
+/// failure of a jni call into the udaf implementation in jvm. the source error
+/// reported by the bridge contains class and message of the java exception.
+#[derive(Debug)]
+pub struct SparkUDAFError {
+    call: &'static str,
+    source: Box<dyn Error + Send + Sync>,
+}
+
+impl Display for SparkUDAFError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed: {}", self.call, self.source)
+    }
+}
+
+impl Error for SparkUDAFError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// maps an error of the named jni call into a readable SparkUDAFError, more
+/// like the java exception than the generic errors of the bridge
+pub fn map_udaf_err(call: &'static str) -> impl FnOnce(DataFusionError) -> DataFusionError {
+    move |err| {
+        // java exceptions are external errors, unwrapped to keep the message
+        // free of the "External error: " prefix
+        let source = match err {
+            DataFusionError::External(source) => source,
+            err => Box::new(err),
+        };
+        DataFusionError::External(Box::new(SparkUDAFError { call, source }))
+    }
+}
+
+/// format tags of exported idx runs, must match SparkUDAFWrapperContext
+pub const IDX_FORMAT_PLAIN: i32 = 0;
+pub const IDX_FORMAT_RUNS: i32 = 1;
+
+/// all calls of SparkUDAFWrapper and its acc column into the udaf
+/// implementation. the jni context calls SparkUDAFWrapperContext in jvm,
+/// tests may use a native implementation instead.
+///
+/// zipped indices are (acc_idx << 32 | other_idx). idx runs start with a
+/// format tag, followed by flattened (start, len) pairs for IDX_FORMAT_RUNS
+/// or by plain indices for IDX_FORMAT_PLAIN. serialized rows are prefixed with
+/// big-endian i32 lengths, like serialized UnsafeRows.
+pub trait UDAFContext: Send + Sync {
+    fn export_zipped_indices(&self, zipped_indices: &[i64]) -> Result<UDAFIndices>;
+    fn export_idx_runs(&self, idx_runs: &[i32]) -> Result<UDAFIndices>;
+
+    fn initialize(&self, num_rows: usize) -> Result<UDAFRows>;
+
+    /// creates rows like initialize, with storage pre-sized for the expected
+    /// number of rows. contexts not supporting pre-sizing just initialize.
+    fn initialize_with_capacity(&self, num_rows: usize, _capacity: usize) -> Result<UDAFRows> {
+        self.initialize(num_rows)
+    }
+    fn resize(&self, rows: &mut UDAFRows, len: usize) -> Result<()>;
+    fn fill_null_range(&self, rows: &mut UDAFRows, start: usize, end: usize) -> Result<()>;
+    fn num_records(&self, rows: &UDAFRows) -> Result<usize>;
+    fn mem_used(&self, rows: &UDAFRows) -> usize;
+
+    fn update(
+        &self,
+        rows: &mut UDAFRows,
+        params: &StructArray,
+        zipped_indices: &UDAFIndices,
+    ) -> Result<()>;
+    fn merge(
+        &self,
+        rows: &mut UDAFRows,
+        merging_rows: &mut UDAFRows,
+        zipped_indices: &UDAFIndices,
+    ) -> Result<()>;
+    fn eval(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<ArrayRef>;
+
+    /// keeps only the selected rows (in increasing order) and shifts them
+    /// down in place, removed rows are freed
+    fn compact(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<()>;
+
+    /// serializes selected rows into buf, replacing its old content
+    fn serialize_rows(
+        &self,
+        rows: &UDAFRows,
+        idx_runs: &UDAFIndices,
+        buf: &mut Vec<u8>,
+    ) -> Result<()>;
+    fn deserialize_rows(&self, data: &[u8]) -> Result<UDAFRows>;
+
+    /// spills selected rows into the spill of mem tracker and returns the
+    /// size of spilled block
+    fn spill(
+        &self,
+        mem_tracker: &SparkUDAFMemTracker,
+        rows: &UDAFRows,
+        idx_runs: &UDAFIndices,
+        spill_idx: usize,
+    ) -> Result<usize>;
+    fn unspill(
+        &self,
+        mem_tracker: &SparkUDAFMemTracker,
+        spill_block_size: usize,
+        spill_idx: usize,
+    ) -> Result<UDAFRows>;
+}
+
+pub struct JniUDAFContext {
+    jcontext: GlobalRef,
+    import_schema: SchemaRef,
+    return_type: DataType,
+}
+
+impl JniUDAFContext {
+    pub fn try_new(serialized: &[u8], return_type: &DataType) -> Result<Self> {
+        let serialized_buf = jni_new_direct_byte_buffer!(serialized)?;
+        let jcontext_local = jni_new_object!(SparkUDAFWrapperContext(serialized_buf.as_obj()))
+            .map_err(map_udaf_err("SparkUDAFWrapperContext.<init>"))?;
+        Ok(Self {
+            jcontext: jni_new_global_ref!(jcontext_local.as_obj())?,
+            import_schema: Arc::new(import_schema(return_type)),
+            return_type: return_type.clone(),
+        })
+    }
+
+    fn jobj(rows: &UDAFRows) -> Result<JObject> {
+        Ok(rows.downcast_ref::<GlobalRef>()?.as_obj())
+    }
+
+    fn jindices(indices: &UDAFIndices) -> Result<JObject> {
+        Ok(indices.downcast_ref::<LocalRef<'static>>()?.as_obj())
+    }
+
+    fn new_rows(rows: JObject) -> Result<UDAFRows> {
+        Ok(UDAFRows::new(jni_new_global_ref!(rows)?))
+    }
+}
+
+impl UDAFContext for JniUDAFContext {
+    fn export_zipped_indices(&self, zipped_indices: &[i64]) -> Result<UDAFIndices> {
+        Ok(UDAFIndices::new(jni_new_prim_array!(long, zipped_indices)?))
+    }
+
+    fn export_idx_runs(&self, idx_runs: &[i32]) -> Result<UDAFIndices> {
+        Ok(UDAFIndices::new(jni_new_prim_array!(int, idx_runs)?))
+    }
+
+    fn initialize(&self, num_rows: usize) -> Result<UDAFRows> {
+        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).initialize(
+            num_rows as i32,
+        )-> JObject)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.initialize"))?;
+        Self::new_rows(rows.as_obj())
+    }
+
+    fn initialize_with_capacity(&self, num_rows: usize, capacity: usize) -> Result<UDAFRows> {
+        let capacity = capacity.min(i32::MAX as usize);
+        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
+            .initializeWithCapacity(num_rows as i32, capacity as i32)-> JObject)
+        .map_err(map_udaf_err(
+            "SparkUDAFWrapperContext.initializeWithCapacity",
+        ))?;
+        Self::new_rows(rows.as_obj())
+    }
+
+    fn resize(&self, rows: &mut UDAFRows, len: usize) -> Result<()> {
+        jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
+            .resize(Self::jobj(rows)?, len as i32)-> ())
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.resize"))
+    }
+
+    fn fill_null_range(&self, rows: &mut UDAFRows, start: usize, end: usize) -> Result<()> {
+        jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
+            .fillNullRange(Self::jobj(rows)?, start as i32, end as i32)-> ())
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.fillNullRange"))
+    }
+
+    fn num_records(&self, rows: &UDAFRows) -> Result<usize> {
+        let num_records = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
+            .numRecords(Self::jobj(rows)?) -> i32)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.numRecords"))?;
+        Ok(num_records as usize)
+    }
+
+    fn mem_used(&self, _rows: &UDAFRows) -> usize {
+        0 // memory is managed in jvm side
+    }
+
+    fn update(
+        &self,
+        rows: &mut UDAFRows,
+        params: &StructArray,
+        zipped_indices: &UDAFIndices,
+    ) -> Result<()> {
+        export_struct_for_jni(params, |export_ptr| {
+            jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).update(
+                Self::jobj(rows)?,
+                export_ptr,
+                Self::jindices(zipped_indices)?,
+            )-> ())
+            .map_err(map_udaf_err("SparkUDAFWrapperContext.update"))
+        })
+    }
+
+    fn merge(
+        &self,
+        rows: &mut UDAFRows,
+        merging_rows: &mut UDAFRows,
+        zipped_indices: &UDAFIndices,
+    ) -> Result<()> {
+        jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).merge(
+            Self::jobj(rows)?,
+            Self::jobj(merging_rows)?,
+            Self::jindices(zipped_indices)?,
+        )-> ())
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.merge"))
+    }
+
+    fn eval(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<ArrayRef> {
+        let imported = import_struct_from_jni(&self.import_schema, |import_ptr| {
+            jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).eval(
+                Self::jobj(rows)?,
+                Self::jindices(idx_runs)?,
+                import_ptr,
+            )-> ())
+            .map_err(map_udaf_err("SparkUDAFWrapperContext.eval"))
+        })?;
+        imported_as_data_type(&imported, &self.return_type)
+    }
+
+    fn compact(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<()> {
+        jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).compact(
+            Self::jobj(rows)?,
+            Self::jindices(idx_runs)?,
+        )-> ())
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.compact"))
+    }
+
+    fn serialize_rows(
+        &self,
+        rows: &UDAFRows,
+        idx_runs: &UDAFIndices,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        let serialized = jni_call!(
+            SparkUDAFWrapperContext(self.jcontext.as_obj()).serializeRows(
+                Self::jobj(rows)?,
+                Self::jindices(idx_runs)?,
+            ) -> JObject)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.serializeRows"))?;
+        let serialized_len = jni_get_byte_array_len!(serialized.as_obj())?;
+        buf.resize(serialized_len, 0);
+        jni_get_byte_array_region!(serialized.as_obj(), 0, &mut buf[..])?;
+        Ok(())
+    }
+
+    fn deserialize_rows(&self, data: &[u8]) -> Result<UDAFRows> {
+        let data_buffer = jni_new_direct_byte_buffer!(data)?;
+        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj())
+            .deserializeRows(data_buffer.as_obj()) -> JObject)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.deserializeRows"))?;
+        Self::new_rows(rows.as_obj())
+    }
+
+    fn spill(
+        &self,
+        mem_tracker: &SparkUDAFMemTracker,
+        rows: &UDAFRows,
+        idx_runs: &UDAFIndices,
+        spill_idx: usize,
+    ) -> Result<usize> {
+        let spill_block_size = jni_call!(
+            SparkUDAFWrapperContext(self.jcontext.as_obj()).spill(
+                mem_tracker.as_obj(),
+                Self::jobj(rows)?,
+                Self::jindices(idx_runs)?,
+                spill_idx as i64,
+            ) -> i32)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.spill"))?;
+        match usize::try_from(spill_block_size) {
+            Ok(spill_block_size) => Ok(spill_block_size),
+            Err(_) => df_execution_err!("udaf: invalid spill block size {spill_block_size}"),
+        }
+    }
+
+    fn unspill(
+        &self,
+        mem_tracker: &SparkUDAFMemTracker,
+        spill_block_size: usize,
+        spill_idx: usize,
+    ) -> Result<UDAFRows> {
+        // spill blocks are addressed by i32 sizes on the jvm side
+        let Ok(spill_block_size) = i32::try_from(spill_block_size) else {
+            return df_execution_err!(
+                "udaf: spill block of {spill_block_size} bytes exceeds the i32 size limit"
+            );
+        };
+        let rows = jni_call!(SparkUDAFWrapperContext(self.jcontext.as_obj()).unspill(
+            mem_tracker.as_obj(),
+            spill_block_size,
+            spill_idx as i64,
+        ) -> JObject)
+        .map_err(map_udaf_err("SparkUDAFWrapperContext.unspill"))?;
+        Self::new_rows(rows.as_obj())
+    }
+}
+
+/// native udaf context computing sum of longs, for unit testing the UDAF path
+/// without jvm. null buffers are represented as None.
+#[cfg(test)]
+pub mod mock {
+    use std::{
+        io::{Cursor, Read},
+        sync::Arc,
+    };
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, Int64Array, StructArray},
+        datatypes::Int64Type,
+    };
+    use datafusion::common::Result;
+    use datafusion_ext_commons::df_execution_err;
+
+    use crate::agg::{
+        acc::compact_items,
+        spark_udaf_wrapper::SparkUDAFMemTracker,
+        udaf_context::{UDAFContext, UDAFIndices, UDAFRows, IDX_FORMAT_PLAIN, IDX_FORMAT_RUNS},
+    };
+
+    pub type MockRows = Vec<Option<i64>>;
+
+    #[derive(Default)]
+    pub struct MockSumUDAFContext;
+
+    fn unzip(zipped_indices: &UDAFIndices) -> Result<impl Iterator<Item = (usize, usize)> + '_> {
+        Ok(zipped_indices
+            .downcast_ref::<Vec<i64>>()?
+            .iter()
+            .map(|&zipped| ((zipped >> 32) as usize, (zipped & 0xffffffff) as usize)))
+    }
+
+    fn expand_runs(idx_runs: &UDAFIndices) -> Result<Box<dyn Iterator<Item = usize> + '_>> {
+        let idx_runs = idx_runs.downcast_ref::<Vec<i32>>()?;
+        match idx_runs.split_first() {
+            Some((&IDX_FORMAT_PLAIN, indices)) => {
+                Ok(Box::new(indices.iter().map(|&idx| idx as usize)))
+            }
+            Some((&IDX_FORMAT_RUNS, runs)) => {
+                Ok(Box::new(runs.chunks(2).flat_map(|run| {
+                    run[0] as usize..(run[0] + run[1]) as usize
+                })))
+            }
+            _ => df_execution_err!("MockSumUDAFContext: invalid idx runs format"),
+        }
+    }
+
+    fn sum(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.wrapping_add(b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
+    impl UDAFContext for MockSumUDAFContext {
+        fn export_zipped_indices(&self, zipped_indices: &[i64]) -> Result<UDAFIndices> {
+            Ok(UDAFIndices::new(zipped_indices.to_vec()))
+        }
+
+        fn export_idx_runs(&self, idx_runs: &[i32]) -> Result<UDAFIndices> {
+            Ok(UDAFIndices::new(idx_runs.to_vec()))
+        }
+
+        fn initialize(&self, num_rows: usize) -> Result<UDAFRows> {
+            Ok(UDAFRows::new::<MockRows>(vec![None; num_rows]))
+        }
+
+        fn initialize_with_capacity(&self, num_rows: usize, capacity: usize) -> Result<UDAFRows> {
+            let mut rows = MockRows::with_capacity(capacity);
+            rows.resize(num_rows, None);
+            Ok(UDAFRows::new(rows))
+        }
+
+        fn resize(&self, rows: &mut UDAFRows, len: usize) -> Result<()> {
+            rows.downcast_mut::<MockRows>()?.resize(len, None);
+            Ok(())
+        }
+
+        fn fill_null_range(&self, rows: &mut UDAFRows, start: usize, end: usize) -> Result<()> {
+            rows.downcast_mut::<MockRows>()?[start..end].fill(None);
+            Ok(())
+        }
+
+        fn num_records(&self, rows: &UDAFRows) -> Result<usize> {
+            Ok(rows.downcast_ref::<MockRows>()?.len())
+        }
+
+        // like the jvm side, only used rows are counted and reserved
+        // capacity is not
+        fn mem_used(&self, rows: &UDAFRows) -> usize {
+            rows.downcast_ref::<MockRows>()
+                .map(|rows| rows.len() * size_of::<Option<i64>>())
+                .unwrap_or(0)
+        }
+
+        fn update(
+            &self,
+            rows: &mut UDAFRows,
+            params: &StructArray,
+            zipped_indices: &UDAFIndices,
+        ) -> Result<()> {
+            let rows = rows.downcast_mut::<MockRows>()?;
+            let values = params.column(0).as_primitive::<Int64Type>();
+            for (acc_idx, param_idx) in unzip(zipped_indices)? {
+                if values.is_valid(param_idx) {
+                    rows[acc_idx] = sum(rows[acc_idx], Some(values.value(param_idx)));
+                }
+            }
+            Ok(())
+        }
+
+        fn merge(
+            &self,
+            rows: &mut UDAFRows,
+            merging_rows: &mut UDAFRows,
+            zipped_indices: &UDAFIndices,
+        ) -> Result<()> {
+            let rows = rows.downcast_mut::<MockRows>()?;
+            let merging_rows = merging_rows.downcast_ref::<MockRows>()?;
+            for (acc_idx, merging_idx) in unzip(zipped_indices)? {
+                rows[acc_idx] = sum(rows[acc_idx], merging_rows[merging_idx]);
+            }
+            Ok(())
+        }
+
+        fn eval(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<ArrayRef> {
+            let rows = rows.downcast_ref::<MockRows>()?;
+            let values = expand_runs(idx_runs)?.map(|idx| rows[idx]);
+            Ok(Arc::new(Int64Array::from_iter(values)))
+        }
+
+        fn compact(&self, rows: &mut UDAFRows, idx_runs: &UDAFIndices) -> Result<()> {
+            let rows = rows.downcast_mut::<MockRows>()?;
+            let retained = expand_runs(idx_runs)?.collect::<Vec<_>>();
+            compact_items(rows, &retained);
+            rows.truncate(retained.len());
+            Ok(())
+        }
+
+        fn serialize_rows(
+            &self,
+            rows: &UDAFRows,
+            idx_runs: &UDAFIndices,
+            buf: &mut Vec<u8>,
+        ) -> Result<()> {
+            let rows = rows.downcast_ref::<MockRows>()?;
+            buf.clear();
+            for idx in expand_runs(idx_runs)? {
+                match rows[idx] {
+                    Some(value) => {
+                        buf.extend_from_slice(&8i32.to_be_bytes());
+                        buf.extend_from_slice(&value.to_le_bytes());
+                    }
+                    None => buf.extend_from_slice(&0i32.to_be_bytes()),
+                }
+            }
+            Ok(())
+        }
+
+        fn deserialize_rows(&self, data: &[u8]) -> Result<UDAFRows> {
+            let mut cursor = Cursor::new(data);
+            let mut rows = MockRows::new();
+            while (cursor.position() as usize) < data.len() {
+                let mut len_buf = [0; 4];
+                cursor.read_exact(&mut len_buf)?;
+                match i32::from_be_bytes(len_buf) {
+                    0 => rows.push(None),
+                    8 => {
+                        let mut value_buf = [0; 8];
+                        cursor.read_exact(&mut value_buf)?;
+                        rows.push(Some(i64::from_le_bytes(value_buf)));
+                    }
+                    len => return df_execution_err!("mock udaf: unexpected row length: {len}"),
+                }
+            }
+            Ok(UDAFRows::new(rows))
+        }
+
+        fn spill(
+            &self,
+            _mem_tracker: &SparkUDAFMemTracker,
+            _rows: &UDAFRows,
+            _idx_runs: &UDAFIndices,
+            _spill_idx: usize,
+        ) -> Result<usize> {
+            df_execution_err!("mock udaf: spilling to jvm mem tracker is not supported")
+        }
+
+        fn unspill(
+            &self,
+            _mem_tracker: &SparkUDAFMemTracker,
+            _spill_block_size: usize,
+            _spill_idx: usize,
+        ) -> Result<UDAFRows> {
+            df_execution_err!("mock udaf: spilling to jvm mem tracker is not supported")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc};
+
+    use arrow::{
+        array::{
+            as_map_array, Array, ArrayRef, AsArray, Int64Builder, MapBuilder, MapFieldNames,
+            StringBuilder, StructArray,
+        },
+        datatypes::{DataType, Field, Int64Type},
+        ffi::FFI_ArrowArray,
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::{DataFusionError, Result, ScalarValue},
+        physical_expr::{expressions::Column, PhysicalExpr},
+    };
+    use datafusion_ext_commons::arrow::ffi_struct::{
+        import_schema, import_struct_from_jni, imported_as_data_type,
+    };
+    use datafusion_ext_exprs::get_map_value::GetMapValueExpr;
+
+    use crate::agg::udaf_context::{map_udaf_err, SparkUDAFError};
+
+    #[test]
+    fn test_map_udaf_err() {
+        let java_err = DataFusionError::External(
+            "Java exception thrown at udaf_context.rs:1: java.lang.IllegalStateException: bad row"
+                .into(),
+        );
+        let err = map_udaf_err("SparkUDAFWrapperContext.update")(java_err);
+        let DataFusionError::External(ref udaf_err) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(udaf_err.downcast_ref::<SparkUDAFError>().is_some());
+        assert!(udaf_err.source().is_some());
+        assert_eq!(
+            udaf_err.to_string(),
+            "SparkUDAFWrapperContext.update failed: Java exception thrown at \
+             udaf_context.rs:1: java.lang.IllegalStateException: bad row",
+        );
+
+        let err = map_udaf_err("SparkUDAFWrapperContext.eval")(DataFusionError::Execution(
+            "oops".to_string(),
+        ));
+        assert_eq!(
+            err.to_string(),
+            "External error: SparkUDAFWrapperContext.eval failed: Execution error: oops",
+        );
+    }
+
+    fn build_map(field_names: Option<MapFieldNames>) -> ArrayRef {
+        let mut builder = MapBuilder::new(field_names, StringBuilder::new(), Int64Builder::new());
+        builder.keys().append_value("a");
+        builder.values().append_value(1);
+        builder.keys().append_value("b");
+        builder.values().append_value(2);
+        builder.append(true).unwrap();
+        builder.append(false).unwrap();
+        builder.keys().append_value("b");
+        builder.values().append_null();
+        builder.keys().append_value("c");
+        builder.values().append_value(3);
+        builder.append(true).unwrap();
+        Arc::new(builder.finish())
+    }
+
+    #[test]
+    fn test_import_map_output() -> Result<()> {
+        // map<string, bigint> exported by jvm, with field names different from
+        // those in the return type
+        let jvm_map = build_map(Some(MapFieldNames {
+            entry: "entries".to_string(),
+            key: "key".to_string(),
+            value: "value".to_string(),
+        }));
+        let return_type = build_map(None).data_type().clone();
+        assert_ne!(jvm_map.data_type(), &return_type);
+
+        let exported = StructArray::from(vec![(
+            Arc::new(Field::new("", jvm_map.data_type().clone(), true)),
+            jvm_map,
+        )]);
+        let imported = import_struct_from_jni(&import_schema(&return_type), |import_ptr| {
+            // simulates jvm exporting the output
+            unsafe {
+                *(import_ptr as *mut FFI_ArrowArray) = FFI_ArrowArray::new(&exported.to_data())
+            };
+            Ok(())
+        })?;
+        let output = imported_as_data_type(&imported, &return_type)?;
+        assert_eq!(output.data_type(), &return_type);
+        assert_eq!(as_map_array(&output).len(), 3);
+        assert!(output.is_null(1));
+
+        // element_at(m, 'b')
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![("m", output, true)])?;
+        let element_at =
+            GetMapValueExpr::new(Arc::new(Column::new("m", 0)), ScalarValue::from("b"));
+        let values = element_at.evaluate(&batch)?.into_array(batch.num_rows())?;
+        let values = values.as_primitive::<Int64Type>();
+        assert_eq!(values.iter().collect::<Vec<_>>(), vec![Some(2), None, None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_schema_of_nested_maps() {
+        let map_type = |value_type: DataType| {
+            let entries = Field::new_struct(
+                "key_value",
+                vec![
+                    Field::new("k", DataType::Utf8, true),
+                    Field::new("v", value_type, true),
+                ],
+                true,
+            );
+            DataType::Map(Arc::new(entries), false)
+        };
+        let return_type = DataType::new_list(map_type(map_type(DataType::Int64)), true);
+        let DataType::List(item) = import_schema(&return_type).field(0).data_type().clone() else {
+            unreachable!()
+        };
+        let DataType::Map(entries, false) = item.data_type() else {
+            unreachable!()
+        };
+        assert_eq!(entries.name(), "entries");
+        assert!(!entries.is_nullable());
+        let DataType::Struct(kv_fields) = entries.data_type() else {
+            unreachable!()
+        };
+        assert_eq!(kv_fields[0].name(), "key");
+        assert!(!kv_fields[0].is_nullable());
+        assert_eq!(kv_fields[1].name(), "value");
+        assert!(kv_fields[1].is_nullable());
+        let DataType::Map(inner_entries, false) = kv_fields[1].data_type() else {
+            unreachable!()
+        };
+        assert_eq!(inner_entries.name(), "entries");
+    }
+}
@@ -27,7 +27,7 @@ use datafusion::{
     common::{Result, Statistics},
     error::DataFusionError,
     execution::context::TaskContext,
-    physical_expr::EquivalenceProperties,
+    physical_expr::{expressions::Column, EquivalenceProperties, LexOrdering, PhysicalSortExpr},
     physical_plan::{
         metrics::{ExecutionPlanMetricsSet, MetricsSet},
         DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
@@ -41,7 +41,7 @@ use once_cell::sync::OnceCell;
 use crate::{
     agg::{
         agg::IdxSelection,
-        agg_ctx::AggContext,
+        agg_ctx::{AggContext, AggScratch},
         agg_table::{AggTable, OwnedKey},
         spark_udaf_wrapper::SparkUDAFWrapper,
         AggExecMode, AggExpr, GroupingExpr,
@@ -94,6 +94,31 @@ impl AggExec {
             props: OnceCell::new(),
         })
     }
+
+    /// sort agg emits groups in the input order, so the output is ordered by
+    /// the grouping columns the input is ordered by
+    fn output_ordering(&self) -> LexOrdering {
+        if !matches!(self.agg_ctx.exec_mode, AggExecMode::SortAgg) {
+            return vec![];
+        }
+        let output_schema = self.schema();
+        self.input
+            .output_ordering()
+            .unwrap_or_default()
+            .iter()
+            .map_while(|sort_expr| {
+                let idx = self
+                    .agg_ctx
+                    .groupings
+                    .iter()
+                    .position(|grouping| grouping.expr.eq(&sort_expr.expr))?;
+                Some(PhysicalSortExpr {
+                    expr: Arc::new(Column::new(output_schema.field(idx).name(), idx)),
+                    options: sort_expr.options,
+                })
+            })
+            .collect()
+    }
 }
 
 impl ExecutionPlan for AggExec {
@@ -112,7 +137,7 @@ impl ExecutionPlan for AggExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                EquivalenceProperties::new_with_orderings(self.schema(), &[self.output_ordering()]),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -140,8 +165,12 @@ impl ExecutionPlan for AggExec {
         partition: usize,
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
-        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
-        let output = execute_agg(self.input.clone(), exec_ctx.clone(), self.agg_ctx.clone())?;
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics)
+            .with_input_statistics(&self.input);
+        let agg_ctx = self
+            .agg_ctx
+            .bind_partition(self.input.schema(), &exec_ctx)?;
+        let output = execute_agg(self.input.clone(), exec_ctx.clone(), agg_ctx)?;
         Ok(exec_ctx.coalesce_with_default_batch_size(output))
     }
 
@@ -280,6 +309,7 @@ fn execute_agg_no_grouping(
     agg_ctx: Arc<AggContext>,
 ) -> Result<SendableRecordBatchStream> {
     let mut acc_table = agg_ctx.create_acc_table(1);
+    let mut scratch = AggScratch::default();
 
     // start processing input batches
     let mut coalesced = exec_ctx.coalesce_with_default_batch_size(input_stream);
@@ -303,6 +333,7 @@ fn execute_agg_no_grouping(
                     &batch,
                     &mut acc_table,
                     IdxSelection::Single(0),
+                    &mut scratch,
                 )?;
             }
 
@@ -339,6 +370,7 @@ fn execute_agg_sorted(
             let mut staging_keys: Vec<OwnedKey> = vec![];
             let mut staging_acc_table = agg_ctx.create_acc_table(0);
             let mut acc_indices = vec![];
+            let mut scratch = AggScratch::default();
 
             macro_rules! flush_staging {
                 () => {{
@@ -378,6 +410,7 @@ fn execute_agg_sorted(
                                 batch_range_end,
                                 &mut staging_acc_table,
                                 IdxSelection::Indices(&acc_indices),
+                                &mut scratch,
                             )?;
                             acc_indices.clear();
                             batch_range_start = batch_range_end;
@@ -395,6 +428,7 @@ fn execute_agg_sorted(
                     batch_range_end,
                     &mut staging_acc_table,
                     IdxSelection::Indices(&acc_indices),
+                    &mut scratch,
                 )?;
                 acc_indices.clear();
             }
@@ -408,17 +442,21 @@ fn execute_agg_sorted(
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    };
 
     use arrow::{
-        array::Int32Array,
-        datatypes::{DataType, Field, Schema},
+        array::{AsArray, Int32Array},
+        datatypes::{DataType, Field, Int32Type, Int64Type, Schema, SchemaRef},
         record_batch::RecordBatch,
     };
     use datafusion::{
         assert_batches_sorted_eq,
-        common::{Result, ScalarValue},
-        physical_expr::{expressions as phys_expr, expressions::Column},
+        common::{stats::Precision, Result, ScalarValue, Statistics},
+        execution::context::TaskContext,
+        physical_expr::{expressions as phys_expr, expressions::Column, PhysicalExprRef},
         physical_plan::{common, memory::MemoryExec, ExecutionPlan},
         prelude::SessionContext,
     };
@@ -432,6 +470,7 @@ mod test {
             GroupingExpr,
         },
         agg_exec::AggExec,
+        expand_exec::ExpandExec,
         memmgr::MemManager,
     };
 
@@ -676,6 +715,328 @@ mod test {
         assert_batches_sorted_eq!(expected, &batches);
         Ok(())
     }
+
+    fn groupings(names: &[&str], schema: &SchemaRef) -> Result<Vec<GroupingExpr>> {
+        names
+            .iter()
+            .map(|&name| {
+                Ok(GroupingExpr {
+                    field_name: name.to_string(),
+                    expr: phys_expr::col(name, schema)?,
+                })
+            })
+            .collect()
+    }
+
+    fn to_final_aggs(aggs: Vec<AggExpr>) -> Result<Vec<AggExpr>> {
+        aggs.into_iter()
+            .map(|mut agg| {
+                agg.agg = agg
+                    .agg
+                    .with_new_exprs(vec![Arc::new(phys_expr::Literal::new(ScalarValue::Null))])?;
+                agg.mode = Final;
+                Ok(agg)
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_expand_based_distinct_agg() -> Result<()> {
+        MemManager::init(10000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        // select k, count(distinct a), sum(b) from t group by k
+        // key 0 takes 80% of rows
+        let num_rows = 10000;
+        let k: Vec<i32> = (0..num_rows)
+            .map(|i| if i % 10 < 8 { 0 } else { i % 100 })
+            .collect();
+        let a: Vec<i32> = (0..num_rows).map(|i| i % 37).collect();
+        let b: Vec<i32> = (0..num_rows).map(|i| i % 11).collect();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, false),
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(k.clone())),
+                Arc::new(Int32Array::from(a.clone())),
+                Arc::new(Int32Array::from(b.clone())),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema.clone(), None)?);
+
+        // expand each row into a regular row (gid=0) and a distinct row
+        // (gid=1), like spark's RewriteDistinctAggregates
+        let null_i32 =
+            || -> PhysicalExprRef { Arc::new(phys_expr::Literal::new(ScalarValue::Int32(None))) };
+        let gid = |id: i32| -> PhysicalExprRef {
+            Arc::new(phys_expr::Literal::new(ScalarValue::Int32(Some(id))))
+        };
+        let expand_schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, false),
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+            Field::new("gid", DataType::Int32, false),
+        ]));
+        let expand = Arc::new(ExpandExec::try_new(
+            expand_schema.clone(),
+            vec![
+                vec![
+                    phys_expr::col("k", &schema)?,
+                    null_i32(),
+                    phys_expr::col("b", &schema)?,
+                    gid(0),
+                ],
+                vec![
+                    phys_expr::col("k", &schema)?,
+                    phys_expr::col("a", &schema)?,
+                    null_i32(),
+                    gid(1),
+                ],
+            ],
+            input,
+        )?);
+
+        // first aggregation: group by (k, a, gid), evaluates regular aggregates
+        let first_aggs = vec![AggExpr {
+            field_name: "sum_b".to_string(),
+            mode: Partial,
+            agg: create_agg(
+                AggFunction::Sum,
+                &[phys_expr::col("b", &expand_schema)?],
+                &expand_schema,
+                DataType::Int64,
+            )?,
+        }];
+        let first_partial = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(&["k", "a", "gid"], &expand_schema)?,
+            first_aggs.clone(),
+            false,
+            expand,
+        )?);
+        let first_final = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(&["k", "a", "gid"], &first_partial.schema())?,
+            to_final_aggs(first_aggs)?,
+            false,
+            first_partial,
+        )?);
+
+        // second aggregation: group by k, counts distinct rows and picks the
+        // regular aggregate result. a is null in regular rows and sum_b is null
+        // in distinct rows, so no gid filters are needed here
+        let first_schema = first_final.schema();
+        let second_aggs = vec![
+            AggExpr {
+                field_name: "cnt_distinct_a".to_string(),
+                mode: Partial,
+                agg: create_agg(
+                    AggFunction::Count,
+                    &[phys_expr::col("a", &first_schema)?],
+                    &first_schema,
+                    DataType::Int64,
+                )?,
+            },
+            AggExpr {
+                field_name: "sum_b".to_string(),
+                mode: Partial,
+                agg: create_agg(
+                    AggFunction::FirstIgnoresNull,
+                    &[phys_expr::col("sum_b", &first_schema)?],
+                    &first_schema,
+                    DataType::Int64,
+                )?,
+            },
+        ];
+        let second_partial = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(&["k"], &first_schema)?,
+            second_aggs.clone(),
+            false,
+            first_final,
+        )?);
+        let second_final = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(&["k"], &second_partial.schema())?,
+            to_final_aggs(second_aggs)?,
+            false,
+            second_partial,
+        )?);
+        let output = common::collect(second_final.execute(0, task_ctx)?).await?;
+
+        let mut expected: HashMap<i32, (HashSet<i32>, i64)> = HashMap::new();
+        for ((&k, &a), &b) in k.iter().zip(&a).zip(&b) {
+            let entry = expected.entry(k).or_default();
+            entry.0.insert(a);
+            entry.1 += b as i64;
+        }
+        let mut num_output_rows = 0;
+        for batch in &output {
+            let keys = batch.column(0).as_primitive::<Int32Type>();
+            let cnts = batch.column(1).as_primitive::<Int64Type>();
+            let sums = batch.column(2).as_primitive::<Int64Type>();
+            for row in 0..batch.num_rows() {
+                let (distinct_a, sum_b) = &expected[&keys.value(row)];
+                assert_eq!(cnts.value(row), distinct_a.len() as i64);
+                assert_eq!(sums.value(row), *sum_b);
+            }
+            num_output_rows += batch.num_rows();
+        }
+        assert_eq!(num_output_rows, expected.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimated_num_groups_from_statistics() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+            Field::new("c", DataType::Int32, true),
+        ]));
+        let input = Arc::new(MemoryExec::try_new(&[vec![]], schema.clone(), None)?);
+        let mut statistics = Statistics::new_unknown(&schema);
+        statistics.num_rows = Precision::Inexact(10000);
+        statistics.column_statistics[0].distinct_count = Precision::Inexact(9);
+        statistics.column_statistics[1].distinct_count = Precision::Inexact(99);
+        let agg_ctx = |names: &[&str]| -> Result<_> {
+            let agg_exec = AggExec::try_new(
+                HashAgg,
+                groupings(names, &schema)?,
+                vec![],
+                false,
+                input.clone(),
+            )?;
+            Ok(agg_exec.agg_ctx)
+        };
+
+        // bounded by distinct counts, with one more group for nulls
+        assert_eq!(agg_ctx(&["a"])?.estimated_num_groups(&statistics), Some(10));
+        assert_eq!(
+            agg_ctx(&["a", "b"])?.estimated_num_groups(&statistics),
+            Some(1000)
+        );
+
+        // bounded by number of rows
+        statistics.column_statistics[0].distinct_count = Precision::Inexact(999);
+        assert_eq!(
+            agg_ctx(&["a", "b"])?.estimated_num_groups(&statistics),
+            Some(10000)
+        );
+
+        // unknown distinct count
+        assert_eq!(
+            agg_ctx(&["a", "c"])?.estimated_num_groups(&statistics),
+            None
+        );
+        Ok(())
+    }
+
+    // select <keys>, count(j) as cnt from t group by <keys>
+    async fn count_by_keys(
+        names: &[&str],
+        batches: &[RecordBatch],
+        task_ctx: &Arc<TaskContext>,
+    ) -> Result<Vec<RecordBatch>> {
+        let schema = batches[0].schema();
+        let input = Arc::new(MemoryExec::try_new(
+            &[batches.to_vec()],
+            schema.clone(),
+            None,
+        )?);
+        let aggs = vec![AggExpr {
+            field_name: "cnt".to_string(),
+            mode: Partial,
+            agg: create_agg(
+                AggFunction::Count,
+                &[phys_expr::col("j", &schema)?],
+                &schema,
+                DataType::Int64,
+            )?,
+        }];
+        let partial = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(names, &schema)?,
+            aggs.clone(),
+            false,
+            input,
+        )?);
+        let final_agg = Arc::new(AggExec::try_new(
+            HashAgg,
+            groupings(names, &partial.schema())?,
+            to_final_aggs(aggs)?,
+            false,
+            partial,
+        )?);
+        datafusion::physical_plan::collect(final_agg, task_ctx.clone()).await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_agg_with_null_group() -> Result<()> {
+        MemManager::init(10000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, true),
+            Field::new("j", DataType::Int32, false),
+        ]));
+        let batches = vec![
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from(vec![
+                        Some(1),
+                        None,
+                        Some(2),
+                        None,
+                        Some(1),
+                    ])),
+                    Arc::new(Int32Array::from(vec![1, 1, 2, 2, 1])),
+                ],
+            )?,
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from(vec![None, Some(2), None])),
+                    Arc::new(Int32Array::from(vec![1, 2, 1])),
+                ],
+            )?,
+        ];
+
+        // all null keys go to one group
+        let expected = vec![
+            "+---+-----+",
+            "| k | cnt |",
+            "+---+-----+",
+            "|   | 4   |",
+            "| 1 | 2   |",
+            "| 2 | 2   |",
+            "+---+-----+",
+        ];
+        assert_batches_sorted_eq!(expected, &count_by_keys(&["k"], &batches, &task_ctx).await?);
+
+        // null keys are still distinguished by other non-null keys
+        let expected = vec![
+            "+---+---+-----+",
+            "| k | j | cnt |",
+            "+---+---+-----+",
+            "|   | 1 | 3   |",
+            "|   | 2 | 1   |",
+            "| 1 | 1 | 2   |",
+            "| 2 | 2 | 2   |",
+            "+---+---+-----+",
+        ];
+        assert_batches_sorted_eq!(
+            expected,
+            &count_by_keys(&["k", "j"], &batches, &task_ctx).await?
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -15,7 +15,7 @@
 use std::{
     any::Any,
     fmt::{Debug, Formatter},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 
 use arrow::{
@@ -23,6 +23,7 @@ use arrow::{
     datatypes::SchemaRef,
 };
 use arrow_schema::DataType;
+use async_trait::async_trait;
 use blaze_jni_bridge::{
     conf,
     conf::{BooleanConf, IntConf},
@@ -38,7 +39,6 @@ use datafusion::{
         PlanProperties,
     },
 };
-use datafusion_ext_commons::arrow::{array_size::BatchSize, coalesce::coalesce_batches_unchecked};
 use futures::StreamExt;
 use once_cell::sync::OnceCell;
 
@@ -47,7 +47,8 @@ use crate::{
         execution_context::ExecutionContext, stream_exec::create_record_batch_stream_exec,
         timer_helper::TimerHelper,
     },
-    joins::join_hash_map::{join_hash_map_schema, JoinHashMap},
+    joins::join_hash_map::{join_hash_map_schema, JoinHashMapBuilder},
+    memmgr::{MemConsumer, MemConsumerInfo, MemManager},
     sort_exec::create_default_ascending_sort_exec,
 };
 
@@ -125,7 +126,8 @@ impl ExecutionPlan for BroadcastJoinBuildHashMapExec {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
         let input = exec_ctx.execute(&self.input)?;
         let build_time = exec_ctx.register_timer_metric("build_time");
-        execute_build_hash_map(input, self.keys.clone(), exec_ctx, build_time)
+        let keys = exec_ctx.bind_partition_exprs(&self.keys)?;
+        execute_build_hash_map(input, keys, exec_ctx, build_time)
     }
 
     fn metrics(&self) -> Option<MetricsSet> {
@@ -155,37 +157,40 @@ pub fn execute_build_hash_map(
                 .unwrap_or(i32::MAX) as usize;
 
             let data_schema = input.schema();
-            let mut staging_batches: Vec<RecordBatch> = vec![];
-            let mut staging_num_rows = 0;
-            let mut stating_mem_size = 0;
+            let mut builder = JoinHashMapBuilder::new(data_schema.clone(), keys.clone());
             let mut fallback_to_sorted = false;
 
+            // staging batches are not spillable, but tracked so that other
+            // consumers spill earlier
+            let mem_consumer = Arc::new(BuildHashMapMemConsumer::default());
+            MemManager::register_consumer(mem_consumer.clone(), false);
+
             while let Some(batch) = build_time
                 .exclude_timer_async(input.next())
                 .await
                 .transpose()?
             {
-                staging_batches.push(batch.clone());
-                if smj_fallback_enabled {
-                    staging_num_rows += batch.num_rows();
-                    stating_mem_size += batch.get_batch_mem_size();
-
-                    // fallback if staging data is too large
-                    if staging_num_rows > smj_fallback_rows_threshold
-                        || stating_mem_size > smj_fallback_mem_threshold
-                    {
-                        fallback_to_sorted = true;
-                        break;
-                    }
+                // hash build side rows as batches arrive
+                builder.add_batch(batch)?;
+                mem_consumer.update_mem_used(builder.mem_used()).await?;
+
+                // fallback if staging data is too large
+                if smj_fallback_enabled
+                    && (builder.num_rows() > smj_fallback_rows_threshold
+                        || builder.mem_used() > smj_fallback_mem_threshold)
+                {
+                    fallback_to_sorted = true;
+                    break;
                 }
             }
 
-            // no fallbacks - generate one hashmap batch
+            // no fallbacks - generate hashmap batches
             if !fallback_to_sorted {
-                let data_batch =
-                    coalesce_batches_unchecked(data_schema, &std::mem::take(&mut staging_batches));
-                let hash_map = JoinHashMap::create_from_data_batch(data_batch, &keys)?;
-                sender.send(hash_map.into_hash_map_batch()?).await;
+                let hash_map = builder.finish()?;
+                mem_consumer.update_mem_used(0).await?;
+                for hash_map_batch in hash_map.into_hash_map_batches()? {
+                    sender.send(hash_map_batch).await;
+                }
                 exec_ctx
                     .baseline_metrics()
                     .elapsed_compute()
@@ -193,7 +198,10 @@ pub fn execute_build_hash_map(
                 return Ok(());
             }
 
-            // fallback to sort-merge join
+            // fallback to sort-merge join, staging batches are passed to the
+            // sort exec which tracks its own memory
+            let staging_batches = builder.into_batches();
+            mem_consumer.update_mem_used(0).await?;
             // sort all input data
             let input: SendableRecordBatchStream = Box::pin(RecordBatchStreamAdapter::new(
                 data_schema,
@@ -232,3 +240,32 @@ pub fn execute_build_hash_map(
             Ok(())
         }))
 }
+
+/// tracks memory of the staging batches of the build side
+#[derive(Default)]
+struct BuildHashMapMemConsumer {
+    mem_consumer_info: Option<Weak<MemConsumerInfo>>,
+}
+
+#[async_trait]
+impl MemConsumer for BuildHashMapMemConsumer {
+    fn name(&self) -> &str {
+        "BroadcastJoinBuildHashMap"
+    }
+
+    fn set_consumer_info(&mut self, consumer_info: Weak<MemConsumerInfo>) {
+        self.mem_consumer_info = Some(consumer_info);
+    }
+
+    fn get_consumer_info(&self) -> &Weak<MemConsumerInfo> {
+        self.mem_consumer_info
+            .as_ref()
+            .expect("consumer info not set")
+    }
+}
+
+impl Drop for BuildHashMapMemConsumer {
+    fn drop(&mut self) {
+        MemManager::deregister_consumer(self);
+    }
+}
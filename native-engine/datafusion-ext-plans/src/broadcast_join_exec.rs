@@ -28,8 +28,9 @@ use arrow::{
 };
 use arrow_schema::Schema;
 use async_trait::async_trait;
+use blaze_jni_bridge::{conf, conf::LongConf};
 use datafusion::{
-    common::{JoinSide, Result, Statistics},
+    common::{DataFusionError, JoinSide, Result, Statistics},
     execution::context::TaskContext,
     physical_expr::{EquivalenceProperties, PhysicalExprRef},
     physical_plan::{
@@ -86,6 +87,7 @@ pub struct BroadcastJoinExec {
     schema: SchemaRef,
     is_built: bool, // true for BroadcastHashJoin, false for ShuffledHashJoin
     cached_build_hash_map_id: Option<String>,
+    fetch: Option<usize>,
     metrics: ExecutionPlanMetricsSet,
     props: OnceCell<PlanProperties>,
 }
@@ -110,11 +112,23 @@ impl BroadcastJoinExec {
             schema,
             is_built,
             cached_build_hash_map_id,
+            fetch: None,
             metrics: ExecutionPlanMetricsSet::new(),
             props: OnceCell::new(),
         })
     }
 
+    /// sets the limit of a parent LimitExec, semi/anti joins stop probing once
+    /// `fetch` rows are produced. the limit is still applied by the parent.
+    pub fn with_fetch(mut self, fetch: Option<usize>) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    pub fn fetch(&self) -> Option<usize> {
+        self.fetch
+    }
+
     pub fn on(&self) -> &JoinOn {
         &self.on
     }
@@ -127,6 +141,32 @@ impl BroadcastJoinExec {
         self.broadcast_side
     }
 
+    pub fn is_built(&self) -> bool {
+        self.is_built
+    }
+
+    /// creates a join with the same settings on new inputs, the schema and
+    /// join keys must be based on the new inputs
+    pub fn with_new_inputs(
+        &self,
+        schema: SchemaRef,
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: JoinOn,
+    ) -> Result<Self> {
+        Ok(Self::try_new(
+            schema,
+            left,
+            right,
+            on,
+            self.join_type,
+            self.broadcast_side,
+            self.is_built,
+            self.cached_build_hash_map_id.clone(),
+        )?
+        .with_fetch(self.fetch))
+    }
+
     fn create_join_params(&self, projection: &[usize]) -> Result<JoinParams> {
         let left_schema = self.left.schema();
         let right_schema = self.right.schema();
@@ -174,6 +214,7 @@ impl BroadcastJoinExec {
             sort_options: vec![SortOptions::default(); self.on.len()],
             projection,
             key_data_types,
+            fetch: self.fetch,
         })
     }
 
@@ -190,6 +231,7 @@ impl BroadcastJoinExec {
             join_params.projection.schema.clone(),
             &self.metrics,
         );
+        let join_params = join_params.bind_partition(&exec_ctx)?;
         let left = self.left.clone();
         let right = self.right.clone();
         let broadcast_side = self.broadcast_side;
@@ -275,7 +317,8 @@ impl ExecutionPlan for BroadcastJoinExec {
             self.broadcast_side,
             self.is_built,
             None,
-        )?))
+        )?
+        .with_fetch(self.fetch)))
     }
 
     fn execute(
@@ -310,6 +353,31 @@ impl DisplayAs for BroadcastJoinExec {
     }
 }
 
+/// pushes the limit of a parent LimitExec into a semi/anti BroadcastJoinExec,
+/// so that probing stops once enough rows are produced. other plans are
+/// returned unchanged.
+pub fn push_down_limit_to_join(
+    plan: Arc<dyn ExecutionPlan>,
+    limit: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    if let Some(join) = plan.as_any().downcast_ref::<BroadcastJoinExec>()
+        && matches!(join.join_type, LeftSemi | RightSemi | LeftAnti | RightAnti)
+    {
+        let join = BroadcastJoinExec::try_new(
+            join.schema.clone(),
+            join.left.clone(),
+            join.right.clone(),
+            join.on.clone(),
+            join.join_type,
+            join.broadcast_side,
+            join.is_built,
+            join.cached_build_hash_map_id.clone(),
+        )?;
+        return Ok(Arc::new(join.with_fetch(Some(limit))));
+    }
+    Ok(plan)
+}
+
 async fn execute_join_with_map(
     probed_plan: Arc<dyn ExecutionPlan>,
     map: Arc<JoinHashMap>,
@@ -325,30 +393,7 @@ async fn execute_join_with_map(
     let elapsed_compute = exec_ctx.baseline_metrics().elapsed_compute().clone();
     let _timer = elapsed_compute.timer();
 
-    let mut joiner: Pin<Box<dyn Joiner + Send>> = match broadcast_side {
-        JoinSide::Left => match join_params.join_type {
-            Inner => Box::pin(RProbedInnerJoiner::new(join_params, map, sender)),
-            Left => Box::pin(RProbedLeftJoiner::new(join_params, map, sender)),
-            Right => Box::pin(RProbedRightJoiner::new(join_params, map, sender)),
-            Full => Box::pin(RProbedFullOuterJoiner::new(join_params, map, sender)),
-            LeftSemi => Box::pin(RProbedLeftSemiJoiner::new(join_params, map, sender)),
-            LeftAnti => Box::pin(RProbedLeftAntiJoiner::new(join_params, map, sender)),
-            RightSemi => Box::pin(RProbedRightSemiJoiner::new(join_params, map, sender)),
-            RightAnti => Box::pin(RProbedRightAntiJoiner::new(join_params, map, sender)),
-            Existence => Box::pin(RProbedExistenceJoiner::new(join_params, map, sender)),
-        },
-        JoinSide::Right => match join_params.join_type {
-            Inner => Box::pin(LProbedInnerJoiner::new(join_params, map, sender)),
-            Left => Box::pin(LProbedLeftJoiner::new(join_params, map, sender)),
-            Right => Box::pin(LProbedRightJoiner::new(join_params, map, sender)),
-            Full => Box::pin(LProbedFullOuterJoiner::new(join_params, map, sender)),
-            LeftSemi => Box::pin(LProbedLeftSemiJoiner::new(join_params, map, sender)),
-            LeftAnti => Box::pin(LProbedLeftAntiJoiner::new(join_params, map, sender)),
-            RightSemi => Box::pin(LProbedRightSemiJoiner::new(join_params, map, sender)),
-            RightAnti => Box::pin(LProbedRightAntiJoiner::new(join_params, map, sender)),
-            Existence => Box::pin(LProbedExistenceJoiner::new(join_params, map, sender)),
-        },
-    };
+    let mut joiner = create_joiner(join_params, map, broadcast_side, sender);
 
     if !joiner.can_early_stop() {
         let mut probed = exec_ctx.stat_input(exec_ctx.execute(&probed_plan)?);
@@ -360,6 +405,10 @@ async fn execute_join_with_map(
                 .await
                 .transpose()?
         {
+            // empty probed batches never produce output
+            if batch.num_rows() == 0 {
+                continue;
+            }
             joiner
                 .as_mut()
                 .join(
@@ -379,6 +428,38 @@ async fn execute_join_with_map(
     Ok(())
 }
 
+pub fn create_joiner(
+    join_params: JoinParams,
+    map: Arc<JoinHashMap>,
+    broadcast_side: JoinSide,
+    sender: Arc<WrappedRecordBatchSender>,
+) -> Pin<Box<dyn Joiner + Send>> {
+    match broadcast_side {
+        JoinSide::Left => match join_params.join_type {
+            Inner => Box::pin(RProbedInnerJoiner::new(join_params, map, sender)),
+            Left => Box::pin(RProbedLeftJoiner::new(join_params, map, sender)),
+            Right => Box::pin(RProbedRightJoiner::new(join_params, map, sender)),
+            Full => Box::pin(RProbedFullOuterJoiner::new(join_params, map, sender)),
+            LeftSemi => Box::pin(RProbedLeftSemiJoiner::new(join_params, map, sender)),
+            LeftAnti => Box::pin(RProbedLeftAntiJoiner::new(join_params, map, sender)),
+            RightSemi => Box::pin(RProbedRightSemiJoiner::new(join_params, map, sender)),
+            RightAnti => Box::pin(RProbedRightAntiJoiner::new(join_params, map, sender)),
+            Existence => Box::pin(RProbedExistenceJoiner::new(join_params, map, sender)),
+        },
+        JoinSide::Right => match join_params.join_type {
+            Inner => Box::pin(LProbedInnerJoiner::new(join_params, map, sender)),
+            Left => Box::pin(LProbedLeftJoiner::new(join_params, map, sender)),
+            Right => Box::pin(LProbedRightJoiner::new(join_params, map, sender)),
+            Full => Box::pin(LProbedFullOuterJoiner::new(join_params, map, sender)),
+            LeftSemi => Box::pin(LProbedLeftSemiJoiner::new(join_params, map, sender)),
+            LeftAnti => Box::pin(LProbedLeftAntiJoiner::new(join_params, map, sender)),
+            RightSemi => Box::pin(LProbedRightSemiJoiner::new(join_params, map, sender)),
+            RightAnti => Box::pin(LProbedRightAntiJoiner::new(join_params, map, sender)),
+            Existence => Box::pin(LProbedExistenceJoiner::new(join_params, map, sender)),
+        },
+    }
+}
+
 async fn execute_join_with_smj_fallback(
     probed_plan: Arc<dyn ExecutionPlan>,
     built: SendableRecordBatchStream,
@@ -407,7 +488,27 @@ async fn execute_join_with_smj_fallback(
         ));
         create_record_batch_stream_exec(remoted_stream, exec_ctx.partition_id())?
     };
+    execute_sort_merge_join_with_sorted_built(
+        probed_plan,
+        built_sorted,
+        join_params,
+        broadcast_side,
+        exec_ctx,
+        sender,
+    )
+    .await
+}
 
+/// runs a sort merge join with the already sorted build side, probed side is
+/// sorted before joining
+pub async fn execute_sort_merge_join_with_sorted_built(
+    probed_plan: Arc<dyn ExecutionPlan>,
+    built_sorted: Arc<dyn ExecutionPlan>,
+    join_params: JoinParams,
+    broadcast_side: JoinSide,
+    exec_ctx: Arc<ExecutionContext>,
+    sender: Arc<WrappedRecordBatchSender>,
+) -> Result<()> {
     // create sorted streams, build side is already sorted
     let probed = exec_ctx.stat_input(exec_ctx.execute(&probed_plan)?);
     let (left_exec, right_exec) = match broadcast_side {
@@ -521,6 +622,14 @@ async fn execute_join(
 
     match built_collected {
         CollectJoinHashMapResult::Map(map) => {
+            let mem_size = map.total_memory_usage();
+            let max_mem_size = broadcast_max_bytes_per_exec();
+            if mem_size > max_mem_size {
+                return Err(DataFusionError::ResourcesExhausted(format!(
+                    "broadcast join hash map uses {mem_size} bytes, \
+                     exceeded spark.blaze.broadcast.maxBytesPerExec={max_mem_size}"
+                )));
+            }
             let join_with_map = execute_join_with_map(
                 probed_plan,
                 map,
@@ -554,6 +663,18 @@ async fn execute_join(
     Ok(())
 }
 
+const DEFAULT_BROADCAST_MAX_BYTES_PER_EXEC: usize = 8 << 30;
+
+fn broadcast_max_bytes_per_exec() -> usize {
+    static V: OnceCell<usize> = OnceCell::new();
+    *V.get_or_init(|| {
+        conf::BROADCAST_MAX_BYTES_PER_EXEC
+            .value()
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_BROADCAST_MAX_BYTES_PER_EXEC)
+    })
+}
+
 enum CollectJoinHashMapResult {
     Map(Arc<JoinHashMap>),
     SortedStream(Pin<Box<Peekable<SendableRecordBatchStream>>>),
@@ -582,6 +703,14 @@ async fn collect_join_hash_map_without_caching(
     build_time: Time,
 ) -> Result<CollectJoinHashMapResult> {
     let hash_map_schema = input.get_ref().schema();
+
+    // skip leading empty batches, which contain neither a hash map nor sorted
+    // data for detecting smj fallback
+    while let Some(Ok(batch)) = input.as_mut().peek().await
+        && batch.num_rows() == 0
+    {
+        input.next().await;
+    }
     let is_smj_fallback_join = matches!(
         input.as_mut().peek().await,
         Some(Ok(batch)) if !JoinHashMap::record_batch_contains_hash_map(batch),
@@ -590,18 +719,14 @@ async fn collect_join_hash_map_without_caching(
         return Ok(CollectJoinHashMapResult::SortedStream(input));
     }
 
-    let hash_map_batches: Vec<RecordBatch> = input.try_collect().await?;
+    let hash_map_batches: Vec<RecordBatch> = input
+        .try_filter(|batch| futures::future::ready(batch.num_rows() > 0))
+        .try_collect()
+        .await?;
     build_time.with_timer(|| {
         let join_hash_map = match hash_map_batches.len() {
             0 => JoinHashMap::create_empty(hash_map_schema, key_exprs)?,
-            1 => {
-                if hash_map_batches[0].num_rows() == 0 {
-                    JoinHashMap::create_empty(hash_map_schema, key_exprs)?
-                } else {
-                    JoinHashMap::load_from_hash_map_batch(hash_map_batches[0].clone(), key_exprs)?
-                }
-            }
-            n => return df_execution_err!("expect zero or one hash map batch, got {n}"),
+            _ => JoinHashMap::load_from_hash_map_batches(hash_map_batches, key_exprs)?,
         };
         Ok(CollectJoinHashMapResult::Map(Arc::new(join_hash_map)))
     })
@@ -27,15 +27,17 @@ use arrow::{
     compute::{filter, filter_record_batch, prep_null_mask_filter},
     datatypes::{DataType, Schema, SchemaRef},
     record_batch::{RecordBatch, RecordBatchOptions},
+    util::display::array_value_to_string,
 };
 use datafusion::{
     common::{
         cast::as_boolean_array,
         tree_node::{Transformed, TreeNode},
-        Result, ScalarValue,
+        DataFusionError, Result, ScalarValue,
     },
     physical_expr::{
         expressions::{CaseExpr, Column, Literal, NoOp, SCAndExpr, SCOrExpr},
+        utils::collect_columns,
         PhysicalExpr, PhysicalExprRef,
     },
     physical_expr_common::utils::scatter,
@@ -97,7 +99,8 @@ impl CachedExprsEvaluator {
             };
 
             // execute current filtering
-            current_filtered = filter_one_pred(batch, filter_expr, proj, current_filtered)?;
+            current_filtered =
+                filter_one_pred(batch, filter_expr, proj, current_filtered, &self.cache)?;
             if let FilterStat::AllFiltered = &current_filtered {
                 return Ok(RecordBatch::new_empty(batch.schema()));
             }
@@ -147,8 +150,11 @@ impl CachedExprsEvaluator {
             .zip(self.output_schema.fields())
             .map(|(expr, field)| {
                 let col = expr
-                    .evaluate(&filtered_batch)?
-                    .into_array(filtered_batch.num_rows())?;
+                    .evaluate(&filtered_batch)
+                    .and_then(|value| value.into_array(filtered_batch.num_rows()))
+                    .map_err(|err| {
+                        expr_error_with_context(err, expr, &filtered_batch, &self.cache)
+                    })?;
                 if col.data_type() != field.data_type() {
                     return cast(col.as_ref(), field.data_type());
                 }
@@ -479,6 +485,7 @@ fn filter_one_pred(
     pruned_pred_expr: &PhysicalExprRef,
     pruned_projection: &[usize],
     current_filtered: FilterStat,
+    cache: &Cache,
 ) -> Result<FilterStat> {
     let current_selected: Option<BooleanArray> = match &current_filtered {
         FilterStat::AllRetained => None,
@@ -488,9 +495,18 @@ fn filter_one_pred(
 
     let pruned_batch = batch.project(pruned_projection)?;
     let pred_ret = match &current_selected {
-        Some(selected) => pruned_pred_expr.evaluate_selection(&pruned_batch, selected)?,
-        None => pruned_pred_expr.evaluate(&pruned_batch)?,
-    };
+        Some(selected) => pruned_pred_expr.evaluate_selection(&pruned_batch, selected),
+        None => pruned_pred_expr.evaluate(&pruned_batch),
+    }
+    .map_err(|err| match &current_selected {
+        Some(selected) => match filter_record_batch(&pruned_batch, selected) {
+            Ok(selected_batch) => {
+                expr_error_with_context(err, pruned_pred_expr, &selected_batch, cache)
+            }
+            Err(_) => expr_error_with_context(err, pruned_pred_expr, &pruned_batch, cache),
+        },
+        None => expr_error_with_context(err, pruned_pred_expr, &pruned_batch, cache),
+    })?;
 
     match pred_ret {
         ColumnarValue::Scalar(ScalarValue::Boolean(Some(true))) => Ok(current_filtered),
@@ -504,3 +520,132 @@ fn filter_one_pred(
         }
     }
 }
+
+/// Enriches an expression evaluation error with the expression, its source
+/// columns and values of the first failing row.
+fn expr_error_with_context(
+    err: DataFusionError,
+    expr: &PhysicalExprRef,
+    batch: &RecordBatch,
+    cache: &Cache,
+) -> DataFusionError {
+    let source_cols = collect_columns(expr)
+        .into_iter()
+        .sorted_by_key(|col| col.index())
+        .collect::<Vec<_>>();
+    let failing_row_idx = find_first_failing_row(expr, batch, cache);
+
+    let failing_row = match failing_row_idx {
+        Some(row_idx) => source_cols
+            .iter()
+            .filter(|col| col.index() < batch.num_columns())
+            .map(|col| {
+                let value = array_value_to_string(batch.column(col.index()), row_idx)
+                    .unwrap_or_else(|_| "?".to_string());
+                format!("{}={value}", col.name())
+            })
+            .join(", "),
+        None => "unknown".to_string(),
+    };
+    err.context(format!(
+        "error evaluating expr: {expr}, source columns: [{}], first failing row: [{failing_row}]",
+        source_cols.iter().map(|col| col.name()).join(", "),
+    ))
+}
+
+/// locates the first failing row by bisecting the batch, so that only
+/// O(log n) slices are re-evaluated instead of every single row.
+/// cached values must be reset because they are evaluated with the whole
+/// batch.
+fn find_first_failing_row(
+    expr: &PhysicalExprRef,
+    batch: &RecordBatch,
+    cache: &Cache,
+) -> Option<usize> {
+    let fails = |offset: usize, len: usize| {
+        cache.reset();
+        expr.evaluate(&batch.slice(offset, len))
+            .and_then(|value| value.into_array(len))
+            .is_err()
+    };
+
+    let (mut offset, mut len) = (0, batch.num_rows());
+    let failing_row_idx = if len > 0 && fails(offset, len) {
+        while len > 1 {
+            let half = len / 2;
+            if fails(offset, half) {
+                len = half;
+            } else {
+                offset += half;
+                len -= half;
+            }
+        }
+        // the error may depend on other rows in the batch
+        fails(offset, 1).then_some(offset)
+    } else {
+        None
+    };
+    cache.reset();
+    failing_row_idx
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::Result,
+        logical_expr::Operator,
+        physical_expr::{
+            expressions::{binary, col, is_not_null, lit, CaseExpr, CastExpr},
+            PhysicalExprRef,
+        },
+    };
+
+    use crate::common::cached_exprs_evaluator::CachedExprsEvaluator;
+
+    #[test]
+    fn test_cast_error_context_in_nested_case_when() -> Result<()> {
+        let a: ArrayRef = Arc::new(Int32Array::from(vec![1, -1, 2, 3]));
+        let b: ArrayRef = Arc::new(StringArray::from(vec!["1", "x", "2x", "3"]));
+        let batch =
+            RecordBatch::try_from_iter_with_nullable(vec![("a", a, false), ("b", b, true)])?;
+        let schema = batch.schema();
+
+        // case when a > 0 then (case when b is not null then cast(b as int)
+        // else 0) else -1
+        let cast_b: PhysicalExprRef =
+            Arc::new(CastExpr::new(col("b", &schema)?, DataType::Int32, None));
+        let inner: PhysicalExprRef = Arc::new(CaseExpr::try_new(
+            None,
+            vec![(is_not_null(col("b", &schema)?)?, cast_b)],
+            Some(lit(0i32)),
+        )?);
+        let outer: PhysicalExprRef = Arc::new(CaseExpr::try_new(
+            None,
+            vec![(
+                binary(col("a", &schema)?, Operator::Gt, lit(0i32), &schema)?,
+                inner,
+            )],
+            Some(lit(-1i32)),
+        )?);
+
+        let output_schema = Arc::new(Schema::new(vec![Field::new("", DataType::Int32, true)]));
+        let evaluator = CachedExprsEvaluator::try_new(vec![], vec![outer], output_schema)?;
+        let err = evaluator
+            .filter_project(&batch)
+            .expect_err("cast should fail")
+            .to_string();
+
+        assert!(err.contains("error evaluating expr: CASE WHEN"), "{err}");
+        assert!(err.contains("source columns: [a, b]"), "{err}");
+        assert!(err.contains("first failing row: [a=2, b=2x]"), "{err}");
+        assert!(err.contains("Cannot cast string '2x'"), "{err}");
+        Ok(())
+    }
+}
@@ -16,23 +16,37 @@
 // under the License.
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     sync::Arc,
 };
 
-use arrow::record_batch::RecordBatch;
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
 use datafusion::{
     common::{
         tree_node::{Transformed, TreeNode},
-        Result,
+        JoinSide, Result,
     },
     execution::{SendableRecordBatchStream, TaskContext},
-    physical_expr::{expressions::Column, utils::collect_columns, PhysicalExprRef},
-    physical_plan::{stream::RecordBatchStreamAdapter, ExecutionPlan},
+    physical_expr::{
+        expressions::Column, utils::collect_columns, PhysicalExprRef, PhysicalSortExpr,
+    },
+    physical_plan::{joins::utils::JoinOn, stream::RecordBatchStreamAdapter, ExecutionPlan},
 };
+use datafusion_ext_commons::{df_execution_err, downcast_any};
 use futures::StreamExt;
 use itertools::Itertools;
 
+use crate::{
+    broadcast_join_exec::BroadcastJoinExec,
+    filter_exec::FilterExec,
+    joins::join_utils::{JoinType, JoinType::*},
+    orc_exec::OrcExec,
+    parquet_exec::ParquetExec,
+    project_exec::ProjectExec,
+    sort_exec::SortExec,
+    sort_merge_join_exec::SortMergeJoinExec,
+};
+
 pub trait ExecuteWithColumnPruning {
     fn execute_projected(
         &self,
@@ -99,3 +113,610 @@ pub fn prune_columns(exprs: &[PhysicalExprRef]) -> Result<(Vec<PhysicalExprRef>,
         required_columns.into_iter().map(|c| c.index()).collect(),
     ))
 }
+
+/// prunes columns not required by the output of the plan. required columns
+/// are walked from the root, scans and join inputs are tightened to the
+/// columns actually used, so that unused columns are neither read nor carried
+/// through joins to the output boundary. the schema of the root is unchanged.
+pub fn prune_plan_columns(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let required = (0..plan.schema().fields().len()).collect::<Vec<_>>();
+    Ok(prune_output_columns(plan, &required)?.0)
+}
+
+/// returns a plan producing at least the required output columns, with the
+/// sorted original indices of its output columns. the plan is returned as is
+/// if nothing is pruned.
+fn prune_output_columns(
+    plan: Arc<dyn ExecutionPlan>,
+    required: &[usize],
+) -> Result<(Arc<dyn ExecutionPlan>, Vec<usize>)> {
+    let all_columns = (0..plan.schema().fields().len()).collect::<Vec<_>>();
+    let required = non_empty_required(required, all_columns.len());
+    let prunes_output = required.len() < all_columns.len();
+
+    if let Ok(project) = downcast_any!(plan, ProjectExec) {
+        let named_exprs = required
+            .iter()
+            .map(|&i| project.named_exprs()[i].clone())
+            .collect::<Vec<_>>();
+        let input_required = required_columns(named_exprs.iter().map(|(expr, _)| expr));
+        let (input, input_columns) =
+            prune_output_columns(project.input().clone(), &input_required)?;
+        if !prunes_output && Arc::ptr_eq(&input, project.input()) {
+            return Ok((plan, all_columns));
+        }
+        let named_exprs = named_exprs
+            .into_iter()
+            .map(|(expr, name)| Ok((remap_columns(&expr, &input_columns)?, name)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut pruned = ProjectExec::try_new(named_exprs, input)?;
+        if project.is_column_pruning() {
+            pruned = pruned.with_column_pruning();
+        }
+        return Ok((Arc::new(pruned), required));
+    }
+
+    if let Ok(filter) = downcast_any!(plan, FilterExec) {
+        let input_required = merge_required(&required, filter.predicates());
+        let (input, input_columns) = prune_output_columns(filter.input().clone(), &input_required)?;
+        if Arc::ptr_eq(&input, filter.input()) {
+            return Ok((plan, all_columns));
+        }
+        let predicates = filter
+            .predicates()
+            .iter()
+            .map(|pred| remap_columns(pred, &input_columns))
+            .collect::<Result<Vec<_>>>()?;
+        return Ok((
+            Arc::new(FilterExec::try_new(predicates, input)?),
+            input_columns,
+        ));
+    }
+
+    if let Ok(sort) = downcast_any!(plan, SortExec) {
+        let input_required = merge_required(
+            &required,
+            sort.exprs().iter().map(|sort_expr| &sort_expr.expr),
+        );
+        let (input, input_columns) = prune_input_columns(sort.input().clone(), &input_required)?;
+        if Arc::ptr_eq(&input, sort.input()) {
+            return Ok((plan, all_columns));
+        }
+        let exprs = sort
+            .exprs()
+            .iter()
+            .map(|sort_expr| {
+                Ok(PhysicalSortExpr {
+                    expr: remap_columns(&sort_expr.expr, &input_columns)?,
+                    options: sort_expr.options,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let pruned = sort.with_new_input_and_exprs(input, exprs);
+        return Ok((Arc::new(pruned), input_columns));
+    }
+
+    if let Ok(scan) = downcast_any!(plan, ParquetExec) {
+        if prunes_output && scan.bucket_spec().is_none() {
+            return Ok((Arc::new(scan.with_projection(&required)), required));
+        }
+    }
+    if let Ok(scan) = downcast_any!(plan, OrcExec) {
+        if prunes_output && scan.bucket_spec().is_none() {
+            return Ok((Arc::new(scan.with_projection(&required)), required));
+        }
+    }
+
+    if let Ok(join) = downcast_any!(plan, BroadcastJoinExec) {
+        // the built hash map side cannot be pruned
+        let prunable = match join.broadcast_side() {
+            JoinSide::Left => [!join.is_built(), true],
+            JoinSide::Right => [true, !join.is_built()],
+        };
+        let pruned = prune_join_inputs(&plan, join.join_type(), join.on(), &required, prunable)?;
+        if let Some(PrunedJoinInputs {
+            schema,
+            left,
+            right,
+            on,
+            columns,
+        }) = pruned
+        {
+            let pruned = join.with_new_inputs(schema, left, right, on)?;
+            return Ok((Arc::new(pruned), columns));
+        }
+        return Ok((plan, all_columns));
+    }
+
+    if let Ok(join) = downcast_any!(plan, SortMergeJoinExec) {
+        let pruned = prune_join_inputs(&plan, join.join_type(), join.on(), &required, [true; 2])?;
+        if let Some(PrunedJoinInputs {
+            schema,
+            left,
+            right,
+            on,
+            columns,
+        }) = pruned
+        {
+            let pruned = join.with_new_inputs(schema, left, right, on)?;
+            return Ok((Arc::new(pruned), columns));
+        }
+        return Ok((plan, all_columns));
+    }
+
+    // other operators require all columns of their inputs
+    let children = plan.children();
+    let new_children = children
+        .iter()
+        .map(|&child| prune_plan_columns(child.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    if children
+        .iter()
+        .zip(&new_children)
+        .all(|(&child, new_child)| Arc::ptr_eq(child, new_child))
+    {
+        return Ok((plan, all_columns));
+    }
+    Ok((plan.with_new_children(new_children)?, all_columns))
+}
+
+/// prunes an input of an operator buffering its rows, like sorts and joins.
+/// inputs which cannot be pruned themselves are wrapped with a projection, so
+/// the operator never carries unused columns.
+fn prune_input_columns(
+    input: Arc<dyn ExecutionPlan>,
+    required: &[usize],
+) -> Result<(Arc<dyn ExecutionPlan>, Vec<usize>)> {
+    let required = non_empty_required(required, input.schema().fields().len());
+    let (input, columns) = prune_output_columns(input, &required)?;
+    if columns.len() <= required.len() {
+        return Ok((input, columns));
+    }
+
+    let input_schema = input.schema();
+    let named_exprs = required
+        .iter()
+        .map(|&i| {
+            let idx = column_position(&columns, i)?;
+            let name = input_schema.field(idx).name().to_owned();
+            let expr: PhysicalExprRef = Arc::new(Column::new(&name, idx));
+            Ok((expr, name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let project = ProjectExec::try_new(named_exprs, input)?.with_column_pruning();
+    Ok((Arc::new(project), required))
+}
+
+struct PrunedJoinInputs {
+    schema: SchemaRef,
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    on: JoinOn,
+    columns: Vec<usize>,
+}
+
+/// prunes both inputs of a join to the required output columns and the join
+/// keys, returns None if nothing is pruned
+fn prune_join_inputs(
+    join: &Arc<dyn ExecutionPlan>,
+    join_type: JoinType,
+    on: &JoinOn,
+    required: &[usize],
+    prunable: [bool; 2],
+) -> Result<Option<PrunedJoinInputs>> {
+    let children = join.children();
+    let (left, right) = (children[0], children[1]);
+    let num_left_columns = left.schema().fields().len();
+
+    let mut left_required = BTreeSet::new();
+    let mut right_required = BTreeSet::new();
+    for &i in required {
+        match join_type {
+            Inner | Left | Right | Full if i < num_left_columns => left_required.insert(i),
+            Inner | Left | Right | Full => right_required.insert(i - num_left_columns),
+            LeftSemi | LeftAnti => left_required.insert(i),
+            RightSemi | RightAnti => right_required.insert(i),
+            Existence if i < num_left_columns => left_required.insert(i),
+            Existence => false, // the exists column
+        };
+    }
+    left_required.extend(required_columns(on.iter().map(|(left_key, _)| left_key)));
+    right_required.extend(required_columns(on.iter().map(|(_, right_key)| right_key)));
+
+    let prune_input = |input: &Arc<dyn ExecutionPlan>, required: BTreeSet<usize>, prunable| {
+        if prunable {
+            prune_input_columns(input.clone(), &required.into_iter().collect::<Vec<_>>())
+        } else {
+            let num_columns = input.schema().fields().len();
+            Ok((
+                prune_plan_columns(input.clone())?,
+                (0..num_columns).collect(),
+            ))
+        }
+    };
+    let (new_left, left_columns) = prune_input(left, left_required, prunable[0])?;
+    let (new_right, right_columns) = prune_input(right, right_required, prunable[1])?;
+    if Arc::ptr_eq(left, &new_left) && Arc::ptr_eq(right, &new_right) {
+        return Ok(None);
+    }
+
+    let on = on
+        .iter()
+        .map(|(left_key, right_key)| {
+            Ok((
+                remap_columns(left_key, &left_columns)?,
+                remap_columns(right_key, &right_columns)?,
+            ))
+        })
+        .collect::<Result<JoinOn>>()?;
+    let shifted_right_columns = right_columns.iter().map(|&i| i + num_left_columns);
+    let columns: Vec<usize> = match join_type {
+        Inner | Left | Right | Full => left_columns
+            .iter()
+            .copied()
+            .chain(shifted_right_columns)
+            .collect(),
+        LeftSemi | LeftAnti => left_columns,
+        RightSemi | RightAnti => right_columns,
+        Existence => left_columns.into_iter().chain([num_left_columns]).collect(),
+    };
+    Ok(Some(PrunedJoinInputs {
+        schema: Arc::new(join.schema().project(&columns)?),
+        left: new_left,
+        right: new_right,
+        on,
+        columns,
+    }))
+}
+
+// at least one column is kept so that row counts are still carried
+fn non_empty_required(required: &[usize], num_columns: usize) -> Vec<usize> {
+    if required.is_empty() && num_columns > 0 {
+        return vec![0];
+    }
+    required.to_vec()
+}
+
+fn required_columns<'a>(exprs: impl IntoIterator<Item = &'a PhysicalExprRef>) -> Vec<usize> {
+    exprs
+        .into_iter()
+        .flat_map(collect_columns)
+        .map(|column| column.index())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn merge_required<'a>(
+    required: &[usize],
+    exprs: impl IntoIterator<Item = &'a PhysicalExprRef>,
+) -> Vec<usize> {
+    required
+        .iter()
+        .copied()
+        .chain(required_columns(exprs))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn column_position(columns: &[usize], idx: usize) -> Result<usize> {
+    match columns.binary_search(&idx) {
+        Ok(pos) => Ok(pos),
+        Err(_) => df_execution_err!("column pruning: column #{idx} is pruned but still required"),
+    }
+}
+
+/// rewrites column references of the expr to the positions of the original
+/// indices in the pruned columns
+fn remap_columns(expr: &PhysicalExprRef, columns: &[usize]) -> Result<PhysicalExprRef> {
+    Ok(expr
+        .clone()
+        .transform_down(|node: PhysicalExprRef| {
+            if let Some(column) = node.as_any().downcast_ref::<Column>() {
+                let idx = column_position(columns, column.index())?;
+                let column: PhysicalExprRef = Arc::new(Column::new(column.name(), idx));
+                return Ok(Transformed::yes(column));
+            }
+            Ok(Transformed::no(node))
+        })?
+        .data)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, RecordBatch},
+        compute::SortOptions,
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        assert_batches_eq, assert_batches_sorted_eq,
+        common::{JoinSide, Result, ScalarValue},
+        datasource::{
+            listing::PartitionedFile, object_store::ObjectStoreUrl, physical_plan::FileScanConfig,
+        },
+        logical_expr::Operator,
+        physical_expr::{
+            expressions::{BinaryExpr, Column, Literal},
+            PhysicalExprRef, PhysicalSortExpr,
+        },
+        physical_plan::{
+            common, joins::utils::build_join_schema, memory::MemoryExec, ExecutionPlan,
+        },
+        prelude::SessionContext,
+    };
+    use datafusion_ext_commons::downcast_any;
+
+    use crate::{
+        broadcast_join_exec::BroadcastJoinExec, common::column_pruning::prune_plan_columns,
+        filter_exec::FilterExec, joins::join_utils::JoinType, memmgr::MemManager,
+        parquet_exec::ParquetExec, project_exec::ProjectExec, sort_exec::SortExec,
+        sort_merge_join_exec::SortMergeJoinExec,
+    };
+
+    // column i of the table is [1, 2, 3] + i * 10
+    fn build_table(prefix: &str, num_columns: usize) -> Result<Arc<dyn ExecutionPlan>> {
+        let fields = (0..num_columns)
+            .map(|i| Field::new(format!("{prefix}{i}"), DataType::Int32, false))
+            .collect::<Vec<_>>();
+        let columns = (0..num_columns as i32)
+            .map(|i| {
+                Arc::new(Int32Array::from_iter_values((1..=3).map(|v| v + i * 10))) as ArrayRef
+            })
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        Ok(Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?))
+    }
+
+    #[tokio::test]
+    async fn test_prune_join_columns() -> Result<()> {
+        MemManager::init(1000000);
+
+        // jvm side only needs 2 of the 30 joined columns
+        let left = build_table("l", 15)?;
+        let right = build_table("r", 15)?;
+        let schema = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &JoinType::Inner.try_into()?,
+        )
+        .0;
+        let on: Vec<(PhysicalExprRef, PhysicalExprRef)> = vec![(
+            Arc::new(Column::new("l0", 0)),
+            Arc::new(Column::new("r0", 0)),
+        )];
+        let join = Arc::new(BroadcastJoinExec::try_new(
+            Arc::new(schema),
+            left,
+            right,
+            on,
+            JoinType::Inner,
+            JoinSide::Right,
+            false,
+            None,
+        )?);
+        let project: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            vec![
+                (Arc::new(Column::new("l3", 3)), "a".to_string()),
+                (Arc::new(Column::new("r7", 22)), "b".to_string()),
+            ],
+            join,
+        )?);
+        assert_eq!(project.children()[0].schema().fields().len(), 30);
+
+        // the boundary batches only carry the output and join key columns
+        let pruned = prune_plan_columns(project.clone())?;
+        assert_eq!(pruned.schema(), project.schema());
+        let pruned_join = pruned.children()[0].clone();
+        assert_eq!(pruned_join.schema().fields().len(), 4);
+        for input in pruned_join.children() {
+            assert!(downcast_any!(input, ProjectExec)?.is_column_pruning());
+            assert_eq!(input.schema().fields().len(), 2);
+        }
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(pruned.execute(0, task_ctx)?).await?;
+        assert!(output.iter().all(|batch| batch.num_columns() == 2));
+        assert_batches_sorted_eq!(
+            vec![
+                "+----+----+",
+                "| a  | b  |",
+                "+----+----+",
+                "| 31 | 71 |",
+                "| 32 | 72 |",
+                "| 33 | 73 |",
+                "+----+----+",
+            ],
+            &output
+        );
+
+        // nothing is pruned if all columns are required
+        let unpruned = prune_plan_columns(pruned_join.clone())?;
+        assert!(Arc::ptr_eq(&unpruned, &pruned_join));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_sort_merge_join_columns() -> Result<()> {
+        MemManager::init(1000000);
+
+        let left = build_table("l", 15)?;
+        let right = build_table("r", 15)?;
+        let schema = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &JoinType::Inner.try_into()?,
+        )
+        .0;
+        let on: Vec<(PhysicalExprRef, PhysicalExprRef)> = vec![(
+            Arc::new(Column::new("l0", 0)),
+            Arc::new(Column::new("r0", 0)),
+        )];
+        let join = Arc::new(SortMergeJoinExec::try_new(
+            Arc::new(schema),
+            left,
+            right,
+            on,
+            JoinType::Inner,
+            vec![SortOptions::default()],
+        )?);
+        let project: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            vec![
+                (Arc::new(Column::new("r7", 22)), "a".to_string()),
+                (Arc::new(Column::new("l3", 3)), "b".to_string()),
+            ],
+            join,
+        )?);
+
+        // both inputs are pruned to the output and join key columns
+        let pruned = prune_plan_columns(project.clone())?;
+        assert_eq!(pruned.schema(), project.schema());
+        let pruned_join = pruned.children()[0].clone();
+        assert!(downcast_any!(pruned_join, SortMergeJoinExec).is_ok());
+        assert_eq!(pruned_join.schema().fields().len(), 4);
+        for input in pruned_join.children() {
+            assert!(downcast_any!(input, ProjectExec)?.is_column_pruning());
+            assert_eq!(input.schema().fields().len(), 2);
+        }
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(pruned.execute(0, task_ctx)?).await?;
+        assert!(output.iter().all(|batch| batch.num_columns() == 2));
+        assert_batches_eq!(
+            vec![
+                "+----+----+",
+                "| a  | b  |",
+                "+----+----+",
+                "| 71 | 31 |",
+                "| 72 | 32 |",
+                "| 73 | 33 |",
+                "+----+----+",
+            ],
+            &output
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_filter_and_sort_columns() -> Result<()> {
+        MemManager::init(1000000);
+
+        // project(l3) <- sort(l5 desc) <- filter(l2 > 21) <- project(all)
+        let table = build_table("l", 15)?;
+        let table_schema = table.schema();
+        let input: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            table_schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let expr: PhysicalExprRef = Arc::new(Column::new(field.name(), i));
+                    (expr, field.name().to_owned())
+                })
+                .collect(),
+            table,
+        )?);
+        let filter = Arc::new(FilterExec::try_new(
+            vec![Arc::new(BinaryExpr::new(
+                Arc::new(Column::new("l2", 2)),
+                Operator::Gt,
+                Arc::new(Literal::new(ScalarValue::Int32(Some(21)))),
+            ))],
+            input,
+        )?);
+        let sort = Arc::new(SortExec::new(
+            filter,
+            vec![PhysicalSortExpr {
+                expr: Arc::new(Column::new("l5", 5)),
+                options: SortOptions {
+                    descending: true,
+                    nulls_first: false,
+                },
+            }],
+            None,
+        ));
+        let project: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            vec![(Arc::new(Column::new("l3", 3)), "a".to_string())],
+            sort,
+        )?);
+
+        let pruned = prune_plan_columns(project.clone())?;
+        assert_eq!(pruned.schema(), project.schema());
+
+        // the sort only carries the output and sort key columns
+        let pruned_sort = pruned.children()[0].clone();
+        assert!(downcast_any!(pruned_sort, SortExec).is_ok());
+        assert_eq!(pruned_sort.schema().fields().len(), 2);
+
+        // the filter input is pruned to the columns used by its ancestors
+        let pruned_filter = pruned_sort.children()[0].children()[0].clone();
+        assert!(downcast_any!(pruned_filter, FilterExec).is_ok());
+        let filter_fields = pruned_filter
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(filter_fields, vec!["l2", "l3", "l5"]);
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(pruned.execute(0, task_ctx)?).await?;
+        assert_batches_eq!(
+            vec!["+----+", "| a  |", "+----+", "| 33 |", "| 32 |", "+----+"],
+            &output
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_scan_columns() -> Result<()> {
+        let file_schema = Arc::new(Schema::new(
+            (0..5)
+                .map(|i| Field::new(format!("c{i}"), DataType::Int32, true))
+                .collect::<Vec<_>>(),
+        ));
+        let scan = |projection: Option<Vec<usize>>| -> Arc<dyn ExecutionPlan> {
+            let conf = FileScanConfig::new(ObjectStoreUrl::local_filesystem(), file_schema.clone())
+                .with_file(PartitionedFile::new("test.parquet", 100))
+                .with_projection(projection);
+            Arc::new(ParquetExec::new(conf, "test".to_string(), None))
+        };
+        let project_c3_c1 = |input: Arc<dyn ExecutionPlan>| -> Result<Arc<dyn ExecutionPlan>> {
+            let schema = input.schema();
+            let column = |name: &str| -> Result<(PhysicalExprRef, String)> {
+                let idx = schema.index_of(name)?;
+                Ok((Arc::new(Column::new(name, idx)), name.to_owned()))
+            };
+            Ok(Arc::new(ProjectExec::try_new(
+                vec![column("c3")?, column("c1")?],
+                input,
+            )?))
+        };
+        let field_names = |plan: &Arc<dyn ExecutionPlan>| {
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // the scan only reads the used columns
+        let project = project_c3_c1(scan(None))?;
+        let pruned = prune_plan_columns(project.clone())?;
+        assert_eq!(pruned.schema(), project.schema());
+        let pruned_scan = pruned.children()[0].clone();
+        assert!(downcast_any!(pruned_scan, ParquetExec).is_ok());
+        assert_eq!(field_names(&pruned_scan), vec!["c1", "c3"]);
+
+        // pruning is applied on top of an existing projection
+        let project = project_c3_c1(scan(Some(vec![4, 3, 2, 1, 0])))?;
+        let pruned = prune_plan_columns(project.clone())?;
+        assert_eq!(pruned.schema(), project.schema());
+        assert_eq!(field_names(&pruned.children()[0]), vec!["c3", "c1"]);
+        Ok(())
+    }
+}
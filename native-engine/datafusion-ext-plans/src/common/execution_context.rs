@@ -24,8 +24,9 @@ use std::{
 use arrow::{array::RecordBatch, datatypes::SchemaRef};
 use blaze_jni_bridge::{conf, conf::BooleanConf, is_task_running};
 use datafusion::{
-    common::Result,
+    common::{stats::Precision, DataFusionError, Result, Statistics},
     execution::{RecordBatchStream, SendableRecordBatchStream, TaskContext},
+    physical_expr::{PhysicalExprRef, PhysicalSortExpr},
     physical_plan::{
         metrics::{BaselineMetrics, Count, ExecutionPlanMetricsSet, MetricBuilder, Time},
         stream::{RecordBatchReceiverStream, RecordBatchStreamAdapter},
@@ -34,8 +35,11 @@ use datafusion::{
 };
 use datafusion_ext_commons::{
     arrow::{array_size::BatchSize, coalesce::coalesce_batches_unchecked},
-    batch_size, df_execution_err, suggested_batch_mem_size,
+    batch_size, df_execution_err,
+    error::BlazeError,
+    suggested_batch_mem_size,
 };
+use datafusion_ext_exprs::spark_random::bind_partition;
 use futures::{Stream, StreamExt};
 use futures_util::FutureExt;
 use once_cell::sync::OnceCell;
@@ -44,7 +48,10 @@ use tokio::sync::mpsc::Sender;
 
 use crate::{
     common::{column_pruning::ExecuteWithColumnPruning, timer_helper::TimerHelper},
+    ipc_reader_exec::IpcReaderExec,
     memmgr::metrics::SpillMetrics,
+    orc_exec::OrcExec,
+    parquet_exec::ParquetExec,
 };
 
 pub struct ExecutionContext {
@@ -55,6 +62,7 @@ pub struct ExecutionContext {
     baseline_metrics: BaselineMetrics,
     spill_metrics: Arc<OnceCell<SpillMetrics>>,
     input_stat_metrics: Arc<OnceCell<Option<InputBatchStatistics>>>,
+    input_statistics: Option<Arc<Statistics>>,
 }
 
 impl ExecutionContext {
@@ -72,6 +80,7 @@ impl ExecutionContext {
             metrics: metrics.clone(),
             spill_metrics: Arc::default(),
             input_stat_metrics: Arc::default(),
+            input_statistics: None,
         })
     }
 
@@ -84,9 +93,29 @@ impl ExecutionContext {
             baseline_metrics: self.baseline_metrics.clone(),
             spill_metrics: self.spill_metrics.clone(),
             input_stat_metrics: self.input_stat_metrics.clone(),
+            input_statistics: self.input_statistics.clone(),
         })
     }
 
+    /// attaches planner-provided statistics of the input, which are only
+    /// available when the input is a scan or exchange node
+    pub fn with_input_statistics(&self, input: &Arc<dyn ExecutionPlan>) -> Arc<Self> {
+        Arc::new(Self {
+            task_ctx: self.task_ctx.clone(),
+            partition_id: self.partition_id,
+            output_schema: self.output_schema.clone(),
+            metrics: self.metrics.clone(),
+            baseline_metrics: self.baseline_metrics.clone(),
+            spill_metrics: self.spill_metrics.clone(),
+            input_stat_metrics: self.input_stat_metrics.clone(),
+            input_statistics: planner_statistics(input).map(Arc::new),
+        })
+    }
+
+    pub fn input_statistics(&self) -> Option<&Statistics> {
+        self.input_statistics.as_deref()
+    }
+
     pub fn task_ctx(&self) -> Arc<TaskContext> {
         self.task_ctx.clone()
     }
@@ -99,6 +128,31 @@ impl ExecutionContext {
         self.output_schema.clone()
     }
 
+    /// binds partition-dependent exprs (like rand()) to the partition of this
+    /// context. exprs evaluated by execs must be bound before evaluating.
+    pub fn bind_partition(&self, expr: &PhysicalExprRef) -> Result<PhysicalExprRef> {
+        bind_partition(expr, self.partition_id)
+    }
+
+    pub fn bind_partition_exprs(&self, exprs: &[PhysicalExprRef]) -> Result<Vec<PhysicalExprRef>> {
+        exprs.iter().map(|expr| self.bind_partition(expr)).collect()
+    }
+
+    pub fn bind_partition_sort_exprs(
+        &self,
+        exprs: &[PhysicalSortExpr],
+    ) -> Result<Vec<PhysicalSortExpr>> {
+        exprs
+            .iter()
+            .map(|expr| {
+                Ok(PhysicalSortExpr {
+                    expr: self.bind_partition(&expr.expr)?,
+                    options: expr.options,
+                })
+            })
+            .collect()
+    }
+
     pub fn execution_plan_metrics(&self) -> &ExecutionPlanMetricsSet {
         &self.metrics
     }
@@ -322,6 +376,9 @@ impl ExecutionContext {
         stream_builder.spawn(async move {
             let result = AssertUnwindSafe(async move {
                 if let Err(err) = output(wrapped_sender).await {
+                    // keep the error kind in the message, as the error is
+                    // flattened into a string by the panic
+                    let err = BlazeError::classify(err);
                     panic!("output_with_sender[{desc}]: output() returns error: {err}");
                 }
             })
@@ -343,7 +400,10 @@ impl ExecutionContext {
                 // panic current spawn
                 let task_running = is_task_running();
                 if !task_running {
-                    panic!("output_with_sender[{desc}] canceled due to task finished/killed");
+                    let cancelled = BlazeError::Cancelled(DataFusionError::Execution(format!(
+                        "output_with_sender[{desc}] canceled due to task finished/killed"
+                    )));
+                    panic!("{cancelled}");
                 } else {
                     panic!("output_with_sender[{desc}] error: {}", err.to_string());
                 }
@@ -440,6 +500,20 @@ impl WrappedRecordBatchSender {
     }
 }
 
+/// returns statistics provided by the planner. other operators do not
+/// implement `ExecutionPlan::statistics()`, so only known source nodes are
+/// asked for them.
+pub fn planner_statistics(plan: &Arc<dyn ExecutionPlan>) -> Option<Statistics> {
+    let plan_any = plan.as_any();
+    if !(plan_any.is::<ParquetExec>() || plan_any.is::<OrcExec>() || plan_any.is::<IpcReaderExec>())
+    {
+        return None;
+    }
+    plan.statistics()
+        .ok()
+        .filter(|statistics| statistics.num_rows != Precision::Absent)
+}
+
 pub fn cancel_all_tasks(task_ctx: &Arc<TaskContext>) {
     let mut working_senders = working_senders().lock();
     *working_senders = std::mem::take(&mut *working_senders)
@@ -448,7 +522,10 @@ pub fn cancel_all_tasks(task_ctx: &Arc<TaskContext>) {
             Some(wrapped) if Arc::ptr_eq(&wrapped.exec_ctx.task_ctx, task_ctx) => {
                 wrapped
                     .sender
-                    .try_send(df_execution_err!("task completed/cancelled"))
+                    .try_send(Err(BlazeError::Cancelled(DataFusionError::Execution(
+                        "task completed/cancelled".to_string(),
+                    ))
+                    .into()))
                     .unwrap_or_default();
                 false
             }
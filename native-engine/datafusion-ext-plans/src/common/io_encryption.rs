@@ -0,0 +1,285 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use aes::{Aes128, Aes192, Aes256};
+use blaze_jni_bridge::{
+    is_jni_bridge_inited, jni_call_static, jni_get_byte_array_len, jni_get_byte_array_region,
+};
+use ctr::{
+    cipher::{KeyIvInit, StreamCipher},
+    Ctr128BE,
+};
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+use once_cell::sync::OnceCell;
+
+/// length of the initialization vector written in front of each encrypted
+/// stream, same as spark's CryptoStreamUtils.IV_LENGTH_IN_BYTES
+pub const IO_ENCRYPTION_IV_LEN: usize = 16;
+
+/// returns the io encryption key of current application, or None if
+/// spark.io.encryption.enabled is off. the key is fetched from the JVM once.
+pub fn io_encryption_key() -> Result<Option<Arc<Vec<u8>>>> {
+    static KEY: OnceCell<Option<Arc<Vec<u8>>>> = OnceCell::new();
+    KEY.get_or_try_init(|| {
+        if !is_jni_bridge_inited() {
+            return Ok(None); // for testing
+        }
+        let key = jni_call_static!(JniBridge.getIoEncryptionKey() -> JObject)?;
+        if key.as_obj().is_null() {
+            return Ok(None);
+        }
+        let key_len = jni_get_byte_array_len!(key.as_obj())?;
+        let mut key_bytes = vec![0u8; key_len];
+        jni_get_byte_array_region!(key.as_obj(), 0, &mut key_bytes[..])?;
+        Ok(Some(Arc::new(key_bytes)))
+    })
+    .cloned()
+}
+
+/// writes a stream encrypted with AES/CTR/NoPadding in the format of spark's
+/// CryptoStreamUtils.createCryptoOutputStream(): a random initialization
+/// vector followed by the cipher text. without a key, data is written as is.
+pub struct IoEncryptionWriter<W: Write> {
+    inner: W,
+    key: Option<Vec<u8>>,
+    cipher: Option<AesCtr>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> IoEncryptionWriter<W> {
+    pub fn try_new(inner: W, key: Option<&[u8]>) -> Result<Self> {
+        let mut writer = Self::new_unstarted(inner, key);
+        writer.start_new_stream()?;
+        Ok(writer)
+    }
+
+    /// creates a writer without starting a stream, `start_new_stream()` must
+    /// be called before writing any data if a key is given
+    pub fn new_unstarted(inner: W, key: Option<&[u8]>) -> Self {
+        Self {
+            inner,
+            key: key.map(|key| key.to_vec()),
+            cipher: None,
+            buf: vec![],
+        }
+    }
+
+    /// starts a new encrypted stream with a new initialization vector, so the
+    /// data written after it can be decrypted independently
+    pub fn start_new_stream(&mut self) -> Result<()> {
+        if let Some(key) = &self.key {
+            let iv: [u8; IO_ENCRYPTION_IV_LEN] = rand::random();
+            self.inner.write_all(&iv)?;
+            self.cipher = Some(AesCtr::try_new(key, iv)?);
+        }
+        Ok(())
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for IoEncryptionWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.key.is_some() && self.cipher.is_none() {
+            return Err(std::io::Error::other("io encryption stream not started"));
+        }
+        match &mut self.cipher {
+            Some(cipher) => {
+                // the key stream has been consumed, so the whole buffer must
+                // be written
+                self.buf.clear();
+                self.buf.extend_from_slice(buf);
+                cipher.apply(&mut self.buf);
+                self.inner.write_all(&self.buf)?;
+                Ok(buf.len())
+            }
+            None => self.inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// reads a stream written by `IoEncryptionWriter` or spark's
+/// CryptoStreamUtils.createCryptoOutputStream(). without a key, data is read
+/// as is.
+pub struct IoEncryptionReader<R: Read> {
+    inner: R,
+    cipher: Option<AesCtr>,
+}
+
+impl<R: Read> IoEncryptionReader<R> {
+    pub fn try_new(mut inner: R, key: Option<&[u8]>) -> Result<Self> {
+        let cipher = match key {
+            Some(key) => {
+                let mut iv = [0u8; IO_ENCRYPTION_IV_LEN];
+                inner.read_exact(&mut iv)?;
+                Some(AesCtr::try_new(key, iv)?)
+            }
+            None => None,
+        };
+        Ok(Self { inner, cipher })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for IoEncryptionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if let Some(cipher) = &mut self.cipher {
+            cipher.apply(&mut buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+// AES in counter mode, the counter is the 128-bit big-endian initialization
+// vector incremented by one for each block, as in javax.crypto and
+// commons-crypto
+enum AesCtr {
+    Aes128(Ctr128BE<Aes128>),
+    Aes192(Ctr128BE<Aes192>),
+    Aes256(Ctr128BE<Aes256>),
+}
+
+impl AesCtr {
+    fn try_new(key: &[u8], iv: [u8; IO_ENCRYPTION_IV_LEN]) -> Result<Self> {
+        Ok(match key.len() {
+            16 => Self::Aes128(Ctr128BE::new(key.into(), &iv.into())),
+            24 => Self::Aes192(Ctr128BE::new(key.into(), &iv.into())),
+            32 => Self::Aes256(Ctr128BE::new(key.into(), &iv.into())),
+            len => return df_execution_err!("invalid AES key length: {len}"),
+        })
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            Self::Aes128(cipher) => cipher.apply_keystream(data),
+            Self::Aes192(cipher) => cipher.apply_keystream(data),
+            Self::Aes256(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_aes_ctr_vectors() -> Result<()> {
+        // NIST SP 800-38A F.5.1 and F.5.5
+        let iv: [u8; 16] = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").try_into().unwrap();
+        let plain = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172a",
+            "ae2d8a571e03ac9c9eb76fac45af8e51",
+        ));
+        for (key, expected) in [
+            (
+                "2b7e151628aed2a6abf7158809cf4f3c",
+                "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff",
+            ),
+            (
+                "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+                "601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5",
+            ),
+        ] {
+            let mut data = plain.clone();
+            let mut cipher = AesCtr::try_new(&hex(key), iv)?;
+            cipher.apply(&mut data[..5]);
+            cipher.apply(&mut data[5..]);
+            assert_eq!(data, hex(expected));
+        }
+        assert!(AesCtr::try_new(&[0u8; 15], iv).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_encryption_round_trip() -> Result<()> {
+        let key = hex("000102030405060708090a0b0c0d0e0f");
+        let data = (0..10000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+
+        let mut writer = IoEncryptionWriter::try_new(vec![], Some(&key))?;
+        for chunk in data.chunks(333) {
+            writer.write_all(chunk)?;
+        }
+        let encrypted = writer.into_inner();
+        assert_eq!(encrypted.len(), IO_ENCRYPTION_IV_LEN + data.len());
+        assert_ne!(&encrypted[IO_ENCRYPTION_IV_LEN..], &data[..]);
+
+        let mut decrypted = vec![];
+        IoEncryptionReader::try_new(Cursor::new(&encrypted), Some(&key))?
+            .read_to_end(&mut decrypted)?;
+        assert_eq!(decrypted, data);
+
+        // random iv for each stream
+        let mut writer = IoEncryptionWriter::try_new(vec![], Some(&key))?;
+        writer.write_all(&data)?;
+        assert_ne!(writer.into_inner(), encrypted);
+
+        // multiple streams written into the same output
+        let mut writer = IoEncryptionWriter::new_unstarted(vec![], Some(&key));
+        assert!(writer.write_all(&data).is_err());
+        let mut stream_offsets = vec![];
+        for chunk in data.chunks(4000) {
+            stream_offsets.push(writer.inner().len());
+            writer.start_new_stream()?;
+            writer.write_all(chunk)?;
+        }
+        stream_offsets.push(writer.inner().len());
+        let encrypted = writer.into_inner();
+        for (i, chunk) in data.chunks(4000).enumerate() {
+            let stream = &encrypted[stream_offsets[i]..stream_offsets[i + 1]];
+            let mut decrypted = vec![];
+            IoEncryptionReader::try_new(Cursor::new(stream), Some(&key))?
+                .read_to_end(&mut decrypted)?;
+            assert_eq!(decrypted, chunk);
+        }
+
+        // no key
+        let mut writer = IoEncryptionWriter::try_new(vec![], None)?;
+        writer.write_all(&data)?;
+        assert_eq!(writer.into_inner(), data);
+        Ok(())
+    }
+}
@@ -15,26 +15,61 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::io::{BufReader, Read, Take, Write};
+use std::{
+    io::{BufReader, Read, Take, Write},
+    sync::Arc,
+};
 
-use arrow::{array::ArrayRef, datatypes::SchemaRef};
-use blaze_jni_bridge::{conf, conf::StringConf, is_jni_bridge_inited};
+use arrow::{
+    array::{new_null_array, ArrayRef},
+    datatypes::SchemaRef,
+    record_batch::RecordBatch,
+};
+use blaze_jni_bridge::{
+    conf,
+    conf::{IntConf, StringConf},
+    is_jni_bridge_inited,
+};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use datafusion::common::Result;
 use datafusion_ext_commons::{
+    arrow::array_size::{ArraySize, BatchSize},
     df_execution_err,
     io::{read_one_batch, write_one_batch},
 };
 use once_cell::sync::OnceCell;
 
 pub const DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE: usize = 4194304;
+pub const DEFAULT_IPC_MAX_BATCH_MEM_SIZE: usize = 268435456;
 const ZSTD_LEVEL: i32 = 1;
 
+// the high bit of a frame length header marks a dictionary frame, which
+// contains a raw zstd dictionary used to decompress the following blocks of
+// the stream. it is written once in front of the first block of each stream.
+const ZSTD_DICT_FRAME_FLAG: u32 = 1 << 31;
+const FRAME_LEN_MASK: u32 = !ZSTD_DICT_FRAME_FLAG;
+const ZSTD_DICT_SAMPLE_NUM_ROWS: usize = 256;
+
+/// frame lengths share the 32-bit header with flags, larger frames cannot be
+/// represented and must not be silently truncated
+fn checked_frame_len(len: usize) -> Result<u32> {
+    match u32::try_from(len) {
+        Ok(len) if len & !FRAME_LEN_MASK == 0 => Ok(len),
+        _ => df_execution_err!(
+            "ipc frame of {len} bytes exceeds the frame length limit of {FRAME_LEN_MASK} bytes"
+        ),
+    }
+}
+const ZSTD_DICT_SAMPLES_SIZE_RATIO: usize = 100;
+
 pub struct IpcCompressionWriter<W: Write> {
     output: W,
     shared_buf: VecBuffer,
     block_writer: IoCompressionWriter<VecBufferWrite>,
     block_empty: bool,
+    zstd_dict: Option<Arc<Vec<u8>>>,
+    zstd_dict_written: bool,
+    max_batch_mem_size: usize,
 }
 unsafe impl<W: Write> Send for IpcCompressionWriter<W> {}
 
@@ -49,21 +84,80 @@ impl<W: Write> IpcCompressionWriter<W> {
             shared_buf,
             block_writer,
             block_empty: true,
+            zstd_dict: None,
+            zstd_dict_written: false,
+            max_batch_mem_size: ipc_max_batch_mem_size(),
         }
     }
 
+    /// batches larger than this size are split into consecutive sub-batches
+    /// by `write_batch`
+    pub fn with_max_batch_mem_size(mut self, max_batch_mem_size: usize) -> Self {
+        self.max_batch_mem_size = max_batch_mem_size.max(1);
+        self
+    }
+
+    /// compresses blocks with zstd and the given dictionary, regardless of the
+    /// configured codec. the dictionary is written as a dictionary frame in
+    /// front of the first block of each stream (see `start_new_stream`), so
+    /// streams can still be read independently.
+    pub fn with_zstd_dict(mut self, zstd_dict: Arc<Vec<u8>>) -> Result<Self> {
+        assert!(
+            self.block_empty,
+            "IpcCompressionWriter must be empty while setting zstd dict"
+        );
+        self.zstd_dict = Some(zstd_dict);
+        self.zstd_dict_written = false;
+        self.block_writer = self.new_block_writer()?;
+        Ok(self)
+    }
+
     pub fn set_output(&mut self, output: W) {
         assert!(
             self.block_empty,
             "IpcCompressionWriter must be empty while changing output"
         );
         self.output = output;
+        self.zstd_dict_written = false;
+    }
+
+    /// finishes the current block and starts a new stream, which can be read
+    /// independently of the previous ones, like a partition segment of a
+    /// shuffle file.
+    pub fn start_new_stream(&mut self) -> Result<()> {
+        self.finish_current_buf()?;
+        self.zstd_dict_written = false;
+        Ok(())
     }
 
     pub fn write_batch(&mut self, num_rows: usize, cols: &[ArrayRef]) -> Result<()> {
         if num_rows == 0 {
             return Ok(());
         }
+
+        // split oversized batches into sub-batches of roughly equal rows, so
+        // that a single batch never grows the compressed buffer beyond the
+        // budget or overflows the i32 offsets of one message
+        let mem_size = sliced_mem_size(cols);
+        let num_sub_batches = mem_size
+            .div_ceil(self.max_batch_mem_size)
+            .clamp(1, num_rows);
+        if num_sub_batches == 1 {
+            return self.write_one_sub_batch(num_rows, cols);
+        }
+        let sub_batch_num_rows = num_rows.div_ceil(num_sub_batches);
+        for offset in (0..num_rows).step_by(sub_batch_num_rows) {
+            let len = sub_batch_num_rows.min(num_rows - offset);
+            let sub_cols = cols
+                .iter()
+                .map(|col| col.slice(offset, len))
+                .collect::<Vec<_>>();
+            self.write_one_sub_batch(len, &sub_cols)?;
+        }
+        Ok(())
+    }
+
+    fn write_one_sub_batch(&mut self, num_rows: usize, cols: &[ArrayRef]) -> Result<()> {
         write_one_batch(num_rows, cols, &mut self.block_writer)?;
         self.block_empty = false;
 
@@ -79,23 +173,56 @@ impl<W: Write> IpcCompressionWriter<W> {
             // finish current buf
             self.block_writer.finish_internal()?;
 
+            // write dictionary frame in front of the first block of stream
+            if let Some(zstd_dict) = &self.zstd_dict {
+                if !self.zstd_dict_written {
+                    let dict_len = checked_frame_len(zstd_dict.len())?;
+                    self.output
+                        .write_u32::<LittleEndian>(dict_len | ZSTD_DICT_FRAME_FLAG)?;
+                    self.output.write_all(zstd_dict)?;
+                    self.zstd_dict_written = true;
+                }
+            }
+
             // write
-            let block_len = self.shared_buf.inner().len() - 4;
+            let block_len = checked_frame_len(self.shared_buf.inner().len() - 4)?;
             self.shared_buf.inner_mut()[0..4]
                 .as_mut()
-                .write_u32::<LittleEndian>(block_len as u32)?;
+                .write_u32::<LittleEndian>(block_len)?;
             self.output.write_all(self.shared_buf.inner())?;
 
             // open next buf
             self.shared_buf.inner_mut().clear();
             self.shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
-            self.block_writer =
-                IoCompressionWriter::new_with_configured_codec(self.shared_buf.writer());
+            self.block_writer = self.new_block_writer()?;
             self.block_empty = true;
         }
         Ok(())
     }
 
+    /// discards the unfinished block, so the writer can be reused for a new
+    /// independent stream with the same schema. finished frames already
+    /// written to the output are not affected.
+    pub fn reset(&mut self) -> Result<()> {
+        self.shared_buf.inner_mut().clear();
+        self.shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
+        self.block_writer = self.new_block_writer()?;
+        self.block_empty = true;
+        self.zstd_dict_written = false;
+        Ok(())
+    }
+
+    fn new_block_writer(&mut self) -> Result<IoCompressionWriter<VecBufferWrite>> {
+        match &self.zstd_dict {
+            Some(zstd_dict) => {
+                IoCompressionWriter::try_new_zstd_with_dict(self.shared_buf.writer(), zstd_dict)
+            }
+            None => Ok(IoCompressionWriter::new_with_configured_codec(
+                self.shared_buf.writer(),
+            )),
+        }
+    }
+
     pub fn inner(&self) -> &W {
         &self.output
     }
@@ -105,11 +232,90 @@ impl<W: Write> IpcCompressionWriter<W> {
     }
 }
 
+/// logical bytes of sliced columns, which are the bytes actually serialized.
+/// buffer capacities and parts out of the slices are not counted.
+fn sliced_mem_size(cols: &[ArrayRef]) -> usize {
+    cols.iter()
+        .map(|col| {
+            col.to_data()
+                .get_slice_memory_size()
+                .unwrap_or_else(|_| col.get_array_mem_size())
+        })
+        .sum()
+}
+
+/// resets the writer, writes one batch and finishes it as a self-contained
+/// stream. data left by a previously failed write is discarded.
+pub fn write_one_batch_reset<W: Write>(
+    num_rows: usize,
+    cols: &[ArrayRef],
+    writer: &mut IpcCompressionWriter<W>,
+) -> Result<()> {
+    writer.reset()?;
+    writer.write_batch(num_rows, cols)?;
+    writer.finish_current_buf()
+}
+
 pub struct IpcCompressionReader<R: Read + 'static> {
     input: InputState<R>,
+    schema_evolution: Option<SchemaEvolution>,
+    zstd_dict: Option<Vec<u8>>,
 }
 unsafe impl<R: Read> Send for IpcCompressionReader<R> {}
 
+// maps columns written with writer schema to reader schema by field names
+struct SchemaEvolution {
+    reader_schema: SchemaRef,
+    writer_schema: SchemaRef,
+    column_mapping: Vec<Option<usize>>,
+}
+
+impl SchemaEvolution {
+    fn new(reader_schema: SchemaRef, writer_schema: SchemaRef) -> Self {
+        let column_mapping = reader_schema
+            .fields()
+            .iter()
+            .map(|field| writer_schema.index_of(field.name()).ok())
+            .collect();
+        Self {
+            reader_schema,
+            writer_schema,
+            column_mapping,
+        }
+    }
+
+    fn evolve(&self, num_rows: usize, writer_cols: Vec<ArrayRef>) -> Result<Vec<ArrayRef>> {
+        self.reader_schema
+            .fields()
+            .iter()
+            .zip(&self.column_mapping)
+            .map(|(field, &mapped)| match mapped {
+                Some(writer_idx) => {
+                    let col = &writer_cols[writer_idx];
+                    if col.data_type() != field.data_type() {
+                        return df_execution_err!(
+                            "schema evolution: column {} has type {:?} in writer schema, expected {:?}",
+                            field.name(),
+                            col.data_type(),
+                            field.data_type(),
+                        );
+                    }
+                    Ok(col.clone())
+                }
+                None => {
+                    if !field.is_nullable() {
+                        return df_execution_err!(
+                            "schema evolution: missing non-nullable column {}",
+                            field.name(),
+                        );
+                    }
+                    Ok(new_null_array(field.data_type(), num_rows))
+                }
+            })
+            .collect()
+    }
+}
+
 #[derive(Default)]
 enum InputState<R: Read + 'static> {
     #[default]
@@ -122,10 +328,37 @@ impl<R: Read> IpcCompressionReader<R> {
     pub fn new(input: R) -> Self {
         Self {
             input: InputState::BlockStart(input),
+            schema_evolution: None,
+            zstd_dict: None,
         }
     }
 
+    /// reads batches written with `writer_schema` as `reader_schema`. columns
+    /// are mapped by field names, missing columns are filled with nulls and
+    /// extra columns are dropped. the schema passed to `read_batch` is
+    /// ignored.
+    pub fn with_schema_evolution(
+        mut self,
+        reader_schema: SchemaRef,
+        writer_schema: SchemaRef,
+    ) -> Self {
+        self.schema_evolution = Some(SchemaEvolution::new(reader_schema, writer_schema));
+        self
+    }
+
     pub fn read_batch(&mut self, schema: &SchemaRef) -> Result<Option<(usize, Vec<ArrayRef>)>> {
+        if let Some(schema_evolution) = self.schema_evolution.take() {
+            let batch = self.read_batch(&schema_evolution.writer_schema);
+            let evolved = match batch {
+                Ok(Some((num_rows, cols))) => schema_evolution
+                    .evolve(num_rows, cols)
+                    .map(|cols| Some((num_rows, cols))),
+                other => other,
+            };
+            self.schema_evolution = Some(schema_evolution);
+            return evolved;
+        }
+
         struct Reader<'a, R: Read + 'static>(&'a mut IpcCompressionReader<R>);
         impl<'a, R: Read> Read for Reader<'a, R> {
             fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
@@ -140,12 +373,24 @@ impl<R: Read> IpcCompressionReader<R> {
                                 return Err(err);
                             }
                         };
+
+                        // dictionary frame, used by the following blocks
+                        if block_len & ZSTD_DICT_FRAME_FLAG != 0 {
+                            let dict_len = (block_len & !ZSTD_DICT_FRAME_FLAG) as usize;
+                            let mut zstd_dict = vec![0; dict_len];
+                            input.read_exact(&mut zstd_dict)?;
+                            self.0.zstd_dict = Some(zstd_dict);
+                            self.0.input = InputState::BlockStart(input);
+                            return self.read(buf);
+                        }
                         let taken = input.take(block_len as u64);
 
-                        self.0.input = InputState::BlockContent(IoCompressionReader::try_new(
-                            io_compression_codec(),
-                            taken,
-                        )?);
+                        self.0.input = InputState::BlockContent(match &self.0.zstd_dict {
+                            Some(zstd_dict) => {
+                                IoCompressionReader::try_new_zstd_with_dict(taken, zstd_dict)?
+                            }
+                            None => IoCompressionReader::try_new(io_compression_codec(), taken)?,
+                        });
                         self.read(buf)
                     }
                     InputState::BlockContent(mut block_reader) => match block_reader.read(buf) {
@@ -186,6 +431,12 @@ impl<W: Write> IoCompressionWriter<W> {
         }
     }
 
+    pub fn try_new_zstd_with_dict(inner: W, zstd_dict: &[u8]) -> Result<Self> {
+        Ok(Self::ZSTD(zstd::Encoder::with_dictionary(
+            inner, ZSTD_LEVEL, zstd_dict,
+        )?))
+    }
+
     pub fn finish(mut self) -> Result<()> {
         self.finish_internal()
     }
@@ -238,6 +489,13 @@ impl<R: Read> IoCompressionReader<R> {
         }
     }
 
+    pub fn try_new_zstd_with_dict(inner: R, zstd_dict: &[u8]) -> Result<Self> {
+        Ok(Self::ZSTD(zstd::Decoder::with_dictionary(
+            BufReader::new(inner),
+            zstd_dict,
+        )?))
+    }
+
     pub fn finish_into_inner(self) -> Result<R> {
         match self {
             Self::LZ4(r) => Ok(r.into_inner()),
@@ -255,6 +513,53 @@ impl<R: Read> Read for IoCompressionReader<R> {
     }
 }
 
+/// collects sample batches of a shuffle map task and trains a zstd dictionary
+/// from them, which improves compression ratio of small blocks.
+pub struct ZstdDictTrainer {
+    dict_size: usize,
+    samples: Vec<RecordBatch>,
+    samples_mem_size: usize,
+}
+
+impl ZstdDictTrainer {
+    pub fn new(dict_size: usize) -> Self {
+        Self {
+            dict_size,
+            samples: vec![],
+            samples_mem_size: 0,
+        }
+    }
+
+    /// adds a sample batch, returns true if enough samples are collected
+    pub fn add_sample(&mut self, batch: &RecordBatch) -> bool {
+        self.samples_mem_size += batch.get_batch_mem_size();
+        self.samples.push(batch.clone());
+        self.samples_mem_size >= self.dict_size * ZSTD_DICT_SAMPLES_SIZE_RATIO
+    }
+
+    pub fn train(self) -> Result<Vec<u8>> {
+        Self::train_from_batches(&self.samples, self.dict_size)
+    }
+
+    /// serializes sample batches in the same format of shuffle blocks and
+    /// trains a dictionary from them. batches are split into small samples
+    /// because the trainer requires a number of samples.
+    pub fn train_from_batches(sample_batches: &[RecordBatch], dict_size: usize) -> Result<Vec<u8>> {
+        let mut samples = vec![];
+        for batch in sample_batches {
+            for offset in (0..batch.num_rows()).step_by(ZSTD_DICT_SAMPLE_NUM_ROWS) {
+                let len = ZSTD_DICT_SAMPLE_NUM_ROWS.min(batch.num_rows() - offset);
+                let sample = batch.slice(offset, len);
+                let mut sample_buf = vec![];
+                write_one_batch(sample.num_rows(), sample.columns(), &mut sample_buf)?;
+                samples.push(sample_buf);
+            }
+        }
+        zstd::dict::from_samples(&samples, dict_size)
+            .or_else(|err| df_execution_err!("error training zstd dictionary: {err}"))
+    }
+}
+
 fn io_compression_codec() -> &'static str {
     static CODEC: OnceCell<String> = OnceCell::new();
     CODEC
@@ -269,6 +574,16 @@ fn io_compression_codec() -> &'static str {
         .as_str()
 }
 
+fn ipc_max_batch_mem_size() -> usize {
+    static V: OnceCell<usize> = OnceCell::new();
+    *V.get_or_init(|| {
+        conf::IPC_MAX_BATCH_MEM_SIZE
+            .value()
+            .map(|size| size as usize)
+            .unwrap_or(DEFAULT_IPC_MAX_BATCH_MEM_SIZE)
+    })
+}
+
 #[derive(Default)]
 struct VecBuffer {
     vec: Box<Vec<u8>>,
@@ -308,10 +623,10 @@ impl VecBuffer {
 
 #[cfg(test)]
 mod tests {
-    use std::{error::Error, io::Cursor, sync::Arc};
+    use std::{error::Error, io::Cursor, sync::Arc, time::Instant};
 
     use arrow::{
-        array::StringArray,
+        array::{Array, Int32Array, Int64Array, StringArray},
         datatypes::{DataType, Field, Schema},
     };
 
@@ -340,4 +655,241 @@ mod tests {
         assert!(reader.read_batch(&schema)?.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_ipc_compression_reset() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("", DataType::Int32, false)]));
+        let batches: Vec<ArrayRef> = (0..10)
+            .map(|i| Arc::new(Int32Array::from_iter_values(i * 100..i * 100 + i + 1)) as ArrayRef)
+            .collect();
+
+        // unfinished data is discarded by reset
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf);
+        writer.write_batch(batches[0].len(), &[batches[0].clone()])?;
+        writer.reset()?;
+
+        // each batch is written as an independent stream
+        let mut stream_lens = vec![];
+        for batch in &batches {
+            let start = writer.inner().len();
+            write_one_batch_reset(batch.len(), &[batch.clone()], &mut writer)?;
+            stream_lens.push(writer.inner().len() - start);
+        }
+
+        let mut offset = 0;
+        for (batch, stream_len) in batches.iter().zip(stream_lens) {
+            let stream = buf[offset..][..stream_len].to_vec();
+            let mut reader = IpcCompressionReader::new(Cursor::new(stream));
+            let (num_rows, cols) = reader.read_batch(&schema)?.unwrap();
+            assert_eq!(num_rows, batch.len());
+            assert_eq!(cols, &[batch.clone()]);
+            assert!(reader.read_batch(&schema)?.is_none());
+            offset += stream_len;
+        }
+        assert_eq!(offset, buf.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_split_oversized_batch() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let num_rows = 10000;
+        let cols: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from_iter_values(0..num_rows as i32)),
+            Arc::new(StringArray::from_iter((0..num_rows).map(|i| {
+                (i % 7 != 0).then(|| format!("wide-string-value-{i:0>64}"))
+            }))),
+        ];
+        let max_batch_mem_size = sliced_mem_size(&cols) / 10;
+
+        let mut buf = vec![];
+        let mut writer =
+            IpcCompressionWriter::new(&mut buf).with_max_batch_mem_size(max_batch_mem_size);
+        writer.write_batch(num_rows, &cols)?;
+        writer.write_batch(3, &[cols[0].slice(0, 3), cols[1].slice(0, 3)])?;
+        let half_cols = [
+            cols[0].slice(0, num_rows / 2),
+            cols[1].slice(0, num_rows / 2),
+        ];
+        writer.write_batch(num_rows / 2, &half_cols)?;
+        writer.finish_current_buf()?;
+
+        // the oversized batch is read back as consecutive sub-batches under
+        // the budget, followed by the small batch unchanged
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        let mut sub_batches = vec![];
+        let mut num_read_rows = 0;
+        while num_read_rows < num_rows {
+            let (sub_num_rows, sub_cols) = reader.read_batch(&schema)?.unwrap();
+            assert!(sub_num_rows < num_rows);
+            num_read_rows += sub_num_rows;
+            sub_batches.push(RecordBatch::try_new(schema.clone(), sub_cols)?);
+        }
+        assert_eq!(num_read_rows, num_rows);
+        assert!(sub_batches.len() >= 10);
+        let expected = RecordBatch::try_new(schema.clone(), cols.clone())?;
+        assert_eq!(
+            arrow::compute::concat_batches(&schema, &sub_batches)?,
+            expected
+        );
+
+        // slices are sized by their own bytes, not by the shared buffers
+        let (num_rows2, cols2) = reader.read_batch(&schema)?.unwrap();
+        assert_eq!(num_rows2, 3);
+        assert_eq!(cols2, &[cols[0].slice(0, 3), cols[1].slice(0, 3)]);
+
+        let mut num_half_sub_batches = 0;
+        while let Some((sub_num_rows, _)) = reader.read_batch(&schema)? {
+            assert!(sub_num_rows < num_rows / 2);
+            num_half_sub_batches += 1;
+        }
+        assert!(
+            (5..=6).contains(&num_half_sub_batches),
+            "{num_half_sub_batches}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_with_schema_evolution() -> Result<(), Box<dyn Error>> {
+        let writer_schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, true),
+            Field::new("c", DataType::Int64, true),
+            Field::new("d", DataType::Utf8, true),
+            Field::new("e", DataType::Int32, true),
+        ]));
+        let writer_cols: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])),
+            Arc::new(StringArray::from(vec![Some("x"), None, Some("z")])),
+            Arc::new(Int64Array::from(vec![Some(10), Some(20), None])),
+            Arc::new(StringArray::from(vec!["p", "q", "r"])),
+            Arc::new(Int32Array::from(vec![7, 8, 9])),
+        ];
+
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf);
+        writer.write_batch(3, &writer_cols)?;
+        writer.write_batch(3, &writer_cols)?;
+        writer.finish_current_buf()?;
+
+        // reorder columns, drop b/d/e and add a new nullable column
+        let reader_schema = Arc::new(Schema::new(vec![
+            Field::new("c", DataType::Int64, true),
+            Field::new("a", DataType::Int32, false),
+            Field::new("new_col", DataType::Utf8, true),
+        ]));
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf))
+            .with_schema_evolution(reader_schema.clone(), writer_schema);
+        for _ in 0..2 {
+            let (num_rows, arrays) = reader.read_batch(&reader_schema)?.unwrap();
+            assert_eq!(num_rows, 3);
+            assert_eq!(arrays.len(), 3);
+            assert_eq!(&arrays[0], &writer_cols[2]);
+            assert_eq!(&arrays[1], &writer_cols[0]);
+            assert_eq!(arrays[2].data_type(), &DataType::Utf8);
+            assert_eq!(arrays[2].null_count(), 3);
+        }
+        assert!(reader.read_batch(&reader_schema)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_with_zstd_dict() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let new_batch = |i: i64, num_rows: i64| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from_iter_values(i..i + num_rows)),
+                    Arc::new(StringArray::from_iter_values(
+                        (i..i + num_rows).map(|j| format!("customer#{:09}-nation{}", j, j % 25)),
+                    )),
+                ],
+            )
+        };
+        let sample_batches = (0..10)
+            .map(|i| new_batch(i * 10000, 10000))
+            .collect::<Result<Vec<_>, _>>()?;
+        let zstd_dict = Arc::new(ZstdDictTrainer::train_from_batches(&sample_batches, 4096)?);
+        assert!(!zstd_dict.is_empty() && zstd_dict.len() <= 4096);
+
+        // small blocks are compressed better with dict, even counting the
+        // dictionary frame written once in front of the stream
+        let small_batches = (0..100)
+            .map(|i| new_batch(i * 37 + 555555, 20))
+            .collect::<Result<Vec<_>, _>>()?;
+        let start_time = Instant::now();
+        let mut plain_size = 0;
+        for batch in &small_batches {
+            let mut plain_buf = vec![];
+            let mut plain_writer = IoCompressionWriter::try_new("zstd", &mut plain_buf)?;
+            write_one_batch(batch.num_rows(), batch.columns(), &mut plain_writer)?;
+            plain_writer.finish()?;
+            plain_size += 4 + plain_buf.len(); // with frame header
+        }
+        let plain_time = start_time.elapsed();
+
+        let start_time = Instant::now();
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf).with_zstd_dict(zstd_dict.clone())?;
+        for batch in &small_batches {
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+            writer.finish_current_buf()?;
+        }
+        drop(writer);
+        let dict_time = start_time.elapsed();
+        let dict_size = buf.len();
+        eprintln!("zstd_plain_size: {plain_size}, zstd_plain_time: {plain_time:?}");
+        eprintln!("zstd_dict_size: {dict_size}, zstd_dict_time: {dict_time:?}");
+        assert!(dict_size < plain_size, "{dict_size} >= {plain_size}");
+
+        // the dictionary is written only once
+        let dict_frame_header = (zstd_dict.len() as u32 | ZSTD_DICT_FRAME_FLAG).to_le_bytes();
+        assert_eq!(buf[0..4], dict_frame_header);
+        let num_dict_frames = buf
+            .windows(4 + zstd_dict.len())
+            .filter(|w| w[0..4] == dict_frame_header && w[4..] == zstd_dict[..])
+            .count();
+        assert_eq!(num_dict_frames, 1);
+
+        // blocks are readable without knowing the dict
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        for batch in &small_batches {
+            let (num_rows, cols) = reader.read_batch(&schema)?.unwrap();
+            assert_eq!(num_rows, batch.num_rows());
+            assert_eq!(cols, batch.columns());
+        }
+        assert!(reader.read_batch(&schema)?.is_none());
+
+        // every stream starts with the dictionary and is readable alone
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf).with_zstd_dict(zstd_dict.clone())?;
+        writer.start_new_stream()?;
+        writer.write_batch(small_batches[0].num_rows(), small_batches[0].columns())?;
+        writer.start_new_stream()?;
+        let second_stream_offset = writer.inner().len();
+        writer.write_batch(small_batches[1].num_rows(), small_batches[1].columns())?;
+        writer.write_batch(small_batches[2].num_rows(), small_batches[2].columns())?;
+        writer.finish_current_buf()?;
+        drop(writer);
+
+        let second_stream = buf[second_stream_offset..].to_vec();
+        assert_eq!(second_stream[0..4], dict_frame_header);
+        let mut reader = IpcCompressionReader::new(Cursor::new(second_stream));
+        for batch in &small_batches[1..3] {
+            let (num_rows, cols) = reader.read_batch(&schema)?.unwrap();
+            assert_eq!(num_rows, batch.num_rows());
+            assert_eq!(cols, batch.columns());
+        }
+        assert!(reader.read_batch(&schema)?.is_none());
+        Ok(())
+    }
 }
@@ -15,6 +15,7 @@
 pub mod cached_exprs_evaluator;
 pub mod column_pruning;
 pub mod execution_context;
+pub mod io_encryption;
 pub mod ipc_compression;
 pub mod offsetted;
 pub mod stream_exec;
@@ -14,16 +14,19 @@
 
 use std::{
     any::Any,
+    collections::HashMap,
     fmt::{Debug, Formatter},
     sync::Arc,
 };
 
 use arrow::{
-    array::{Array, RecordBatch, RecordBatchOptions, StructArray},
+    array::{Array, RecordBatch, RecordBatchOptions, RecordBatchReader, StructArray},
     datatypes::{DataType, SchemaRef},
     ffi::{from_ffi_and_data_type, FFI_ArrowArray},
 };
-use blaze_jni_bridge::{jni_call, jni_call_static, jni_new_global_ref, jni_new_string};
+use blaze_jni_bridge::{
+    is_jni_bridge_inited, jni_call, jni_call_static, jni_new_global_ref, jni_new_string,
+};
 use datafusion::{
     error::Result,
     execution::context::TaskContext,
@@ -35,12 +38,29 @@ use datafusion::{
         PlanProperties, SendableRecordBatchStream, Statistics,
     },
 };
-use datafusion_ext_commons::arrow::array_size::BatchSize;
+use datafusion_ext_commons::{arrow::array_size::BatchSize, df_execution_err};
 use jni::objects::GlobalRef;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 
 use crate::common::execution_context::ExecutionContext;
 
+pub type NativeInputReader = Box<dyn RecordBatchReader + Send>;
+
+// readers registered by embedders running without a JVM, they take the place
+// of jvm-side exporters. like JniBridge.getResource(), a reader is removed once
+// it is taken by an executing FFIReaderExec.
+fn native_inputs() -> &'static Mutex<HashMap<String, NativeInputReader>> {
+    static NATIVE_INPUTS: OnceCell<Mutex<HashMap<String, NativeInputReader>>> = OnceCell::new();
+    NATIVE_INPUTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// registers an input reader for the FFIReaderExec with the given resource id,
+/// only used when the jni bridge is not initialized
+pub fn put_native_input(resource_id: impl Into<String>, reader: NativeInputReader) {
+    native_inputs().lock().insert(resource_id.into(), reader);
+}
+
 pub struct FFIReaderExec {
     num_partitions: usize,
     schema: SchemaRef,
@@ -121,6 +141,14 @@ impl ExecutionPlan for FFIReaderExec {
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        if !is_jni_bridge_inited() {
+            let resource_id = &self.export_iter_provider_resource_id;
+            let Some(reader) = native_inputs().lock().remove(resource_id) else {
+                return df_execution_err!("FFIReaderExec: no native input for {resource_id}");
+            };
+            return read_native(self.schema(), reader, exec_ctx);
+        }
+
         let resource_id = jni_new_string!(&self.export_iter_provider_resource_id)?;
         let exporter = jni_new_global_ref!(
             jni_call_static!(JniBridge.getResource(resource_id.as_obj()) -> JObject)?.as_obj()
@@ -194,3 +222,41 @@ fn read_ffi(
             Ok(())
         }))
 }
+
+fn read_native(
+    schema: SchemaRef,
+    reader: NativeInputReader,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    let size_counter = exec_ctx.register_counter_metric("size");
+    let exec_ctx_cloned = exec_ctx.clone();
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("FFIReader", move |sender| async move {
+            let mut reader = reader;
+            loop {
+                let (returned_reader, next) = tokio::task::spawn_blocking(move || {
+                    let next = reader.next();
+                    (reader, next)
+                })
+                .await
+                .expect("tokio spawn_blocking error");
+                reader = returned_reader;
+
+                let Some(imported) = next.transpose()? else {
+                    break;
+                };
+                let batch = RecordBatch::try_new_with_options(
+                    schema.clone(),
+                    imported.columns().to_vec(),
+                    &RecordBatchOptions::new().with_row_count(Some(imported.num_rows())),
+                )?;
+                size_counter.add(batch.get_batch_mem_size());
+                exec_ctx_cloned
+                    .baseline_metrics()
+                    .record_output(batch.num_rows());
+                sender.send(batch).await;
+            }
+            Ok(())
+        }))
+}
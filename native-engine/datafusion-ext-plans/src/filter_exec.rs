@@ -18,7 +18,7 @@ use arrow::datatypes::{DataType, SchemaRef};
 use datafusion::{
     common::{Result, Statistics},
     execution::context::TaskContext,
-    physical_expr::{expressions::Column, EquivalenceProperties, PhysicalExprRef},
+    physical_expr::{expressions::Column, PhysicalExprRef},
     physical_plan::{
         metrics::{ExecutionPlanMetricsSet, MetricsSet},
         DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
@@ -73,6 +73,10 @@ impl FilterExec {
     pub fn predicates(&self) -> &[PhysicalExprRef] {
         &self.predicates
     }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
 }
 
 impl DisplayAs for FilterExec {
@@ -101,7 +105,7 @@ impl ExecutionPlan for FilterExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                self.input.equivalence_properties().clone(),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -174,6 +178,9 @@ fn execute_filter(
     exec_ctx: Arc<ExecutionContext>,
 ) -> Result<SendableRecordBatchStream> {
     let input_schema = input.schema();
+
+    // bind random exprs to the current partition
+    let predicates = exec_ctx.bind_partition_exprs(&predicates)?;
     let cached_exprs_evaluator =
         CachedExprsEvaluator::try_new(predicates, vec![], input_schema.clone())?;
 
@@ -160,7 +160,9 @@ impl ExecutionPlan for GenerateExec {
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
-        let generator = self.generator.clone();
+        let generator = self
+            .generator
+            .with_new_exprs(exec_ctx.bind_partition_exprs(&self.generator.exprs())?)?;
         let generator_output_schema = self.generator_output_schema.clone();
         let outer = self.outer;
         let child_output_cols = self.required_child_output_cols.clone();
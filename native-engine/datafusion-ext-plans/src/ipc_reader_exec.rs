@@ -29,8 +29,9 @@ use arrow::{
 };
 use async_trait::async_trait;
 use blaze_jni_bridge::{
-    jni_call, jni_call_static, jni_get_byte_array_region, jni_get_direct_buffer, jni_get_string,
-    jni_new_direct_byte_buffer, jni_new_global_ref, jni_new_string,
+    conf, conf::IntConf, jni_call, jni_call_static, jni_get_byte_array_region,
+    jni_get_direct_buffer, jni_get_string, jni_new_direct_byte_buffer, jni_new_global_ref,
+    jni_new_string,
 };
 use datafusion::{
     common::DataFusionError,
@@ -55,13 +56,18 @@ use jni::objects::{GlobalRef, JObject};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 
-use crate::common::{execution_context::ExecutionContext, ipc_compression::IpcCompressionReader};
+use crate::common::{
+    execution_context::ExecutionContext,
+    io_encryption::{io_encryption_key, IoEncryptionReader},
+    ipc_compression::IpcCompressionReader,
+};
 
 #[derive(Debug, Clone)]
 pub struct IpcReaderExec {
     pub num_partitions: usize,
     pub ipc_provider_resource_id: String,
     pub schema: SchemaRef,
+    pub statistics: Statistics,
     pub metrics: ExecutionPlanMetricsSet,
     props: OnceCell<PlanProperties>,
 }
@@ -74,11 +80,19 @@ impl IpcReaderExec {
         IpcReaderExec {
             num_partitions,
             ipc_provider_resource_id,
+            statistics: Statistics::new_unknown(&schema),
             schema,
             metrics: ExecutionPlanMetricsSet::new(),
             props: OnceCell::new(),
         }
     }
+
+    /// attaches statistics of the exchanged relation, which are provided by
+    /// the planner and used by downstream operators as estimations
+    pub fn with_statistics(mut self, statistics: Statistics) -> Self {
+        self.statistics = statistics;
+        self
+    }
 }
 
 impl DisplayAs for IpcReaderExec {
@@ -119,11 +133,14 @@ impl ExecutionPlan for IpcReaderExec {
         self: Arc<Self>,
         _children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(Self::new(
-            self.num_partitions,
-            self.ipc_provider_resource_id.clone(),
-            self.schema.clone(),
-        )))
+        Ok(Arc::new(
+            Self::new(
+                self.num_partitions,
+                self.ipc_provider_resource_id.clone(),
+                self.schema.clone(),
+            )
+            .with_statistics(self.statistics.clone()),
+        ))
     }
 
     fn execute(
@@ -154,7 +171,7 @@ impl ExecutionPlan for IpcReaderExec {
     }
 
     fn statistics(&self) -> Result<Statistics> {
-        todo!()
+        Ok(self.statistics.clone())
     }
 }
 
@@ -163,6 +180,10 @@ fn read_ipc(
     exec_ctx: Arc<ExecutionContext>,
 ) -> Result<SendableRecordBatchStream> {
     let size_counter = exec_ctx.register_counter_metric("size");
+    let retried_blocks_counter = exec_ctx.register_counter_metric("shuffle_read_retried_blocks");
+    let quarantined_blocks_counter =
+        exec_ctx.register_counter_metric("shuffle_read_quarantined_blocks");
+    let max_retries = conf::SHUFFLE_READ_MAX_RETRIES.value().unwrap_or(0).max(0) as usize;
 
     Ok(exec_ctx
         .clone()
@@ -189,25 +210,24 @@ fn read_ipc(
                 .expect("tokio spawn_blocking error")?
             } {
                 // get ipc reader
-                let block_cloned = block.clone();
-                let mut reader = tokio::task::spawn_blocking(|| {
-                    let block = block_cloned;
-                    if jni_call!(BlazeBlockObject(block.as_obj()).hasFileSegment() -> bool)? {
-                        return get_file_reader(block.as_obj());
-                    }
-                    if jni_call!(BlazeBlockObject(block.as_obj()).hasByteBuffer() -> bool)? {
-                        return get_byte_buffer_reader(block.as_obj());
-                    }
-                    get_channel_reader(block.as_obj())
+                let mut reader = tokio::task::spawn_blocking(move || {
+                    RetryableBlockReader::try_new(JniShuffleBlock(block), max_retries)
                 })
                 .await
                 .expect("tokio spawn_blocking error")?;
 
                 while let Some((num_rows, cols)) =
                     reader.read_batch(&exec_ctx.output_schema()).or_else(|e| {
-                        // throw FetchFailureException
-                        let block = block.clone();
-                        let errmsg = jni_new_string!(e.message().as_ref())?;
+                        // the block is still corrupted after retries, throw
+                        // FetchFailedException on it so that spark regenerates
+                        // the map output
+                        quarantined_blocks_counter.add(1);
+                        let block = reader.block().0.clone();
+                        let errmsg = jni_new_string!(format!(
+                            "{} (after {} retries)",
+                            e.message(),
+                            reader.num_retries(),
+                        ))?;
                         jni_call!(BlazeBlockObject(block.as_obj())
                             .throwFetchFailed(errmsg.as_obj()) -> ())?; // always return error
                         Ok::<_, DataFusionError>(None)
@@ -247,6 +267,9 @@ fn read_ipc(
                         sender.send(batch).await;
                     }
                 }
+                if reader.num_retries() > 0 {
+                    retried_blocks_counter.add(1);
+                }
             }
 
             let cur_staging_num_rows = staging_num_rows.load(SeqCst);
@@ -268,14 +291,129 @@ fn read_ipc(
         }))
 }
 
-fn get_channel_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
+/// a shuffle block which can be requested again when its data is found
+/// corrupted
+trait ShuffleBlock: Send + 'static {
+    fn open(&self) -> Result<Box<dyn Read + Send>>;
+
+    /// requests the block again, returns false if refetching is not supported
+    fn refetch(&mut self) -> Result<bool>;
+}
+
+struct JniShuffleBlock(GlobalRef);
+
+impl ShuffleBlock for JniShuffleBlock {
+    fn open(&self) -> Result<Box<dyn Read + Send>> {
+        get_block_reader(self.0.as_obj())
+    }
+
+    fn refetch(&mut self) -> Result<bool> {
+        let refetched = jni_call!(BlazeBlockObject(self.0.as_obj()).refetch() -> JObject)?;
+        if refetched.as_obj().is_null() {
+            return Ok(false);
+        }
+        self.0 = jni_new_global_ref!(refetched.as_obj())?;
+        Ok(true)
+    }
+}
+
+/// reads batches of a shuffle block. on checksum or decode failures, the block
+/// is refetched up to `max_retries` times and the batches already read are
+/// skipped in the refetched data.
+struct RetryableBlockReader<B: ShuffleBlock> {
+    block: B,
+    reader: IpcCompressionReader<Box<dyn Read + Send>>,
+    max_retries: usize,
+    num_retries: usize,
+    num_read_batches: usize,
+    num_skipped_batches: usize,
+}
+
+impl<B: ShuffleBlock> RetryableBlockReader<B> {
+    fn try_new(block: B, max_retries: usize) -> Result<Self> {
+        let reader = IpcCompressionReader::new(block.open()?);
+        Ok(Self {
+            block,
+            reader,
+            max_retries,
+            num_retries: 0,
+            num_read_batches: 0,
+            num_skipped_batches: 0,
+        })
+    }
+
+    fn block(&self) -> &B {
+        &self.block
+    }
+
+    fn num_retries(&self) -> usize {
+        self.num_retries
+    }
+
+    fn read_batch(&mut self, schema: &SchemaRef) -> Result<Option<(usize, Vec<ArrayRef>)>> {
+        loop {
+            let err = match self.reader.read_batch(schema) {
+                // skip batches already read before refetching
+                Ok(Some(_)) if self.num_skipped_batches < self.num_read_batches => {
+                    self.num_skipped_batches += 1;
+                    continue;
+                }
+                Ok(None) if self.num_skipped_batches < self.num_read_batches => {
+                    DataFusionError::Execution(format!(
+                        "refetched block has {} batches, expect at least {}",
+                        self.num_skipped_batches, self.num_read_batches,
+                    ))
+                }
+                Ok(batch) => {
+                    if batch.is_some() {
+                        self.num_read_batches += 1;
+                        self.num_skipped_batches += 1;
+                    }
+                    return Ok(batch);
+                }
+                Err(err) => err,
+            };
+
+            if self.num_retries >= self.max_retries || !self.block.refetch()? {
+                return Err(err);
+            }
+            self.num_retries += 1;
+            log::warn!(
+                "reading shuffle block failed, refetching ({}/{}): {err}",
+                self.num_retries,
+                self.max_retries,
+            );
+            self.reader = IpcCompressionReader::new(self.block.open()?);
+            self.num_skipped_batches = 0;
+        }
+    }
+}
+
+fn get_block_reader(block: JObject) -> Result<Box<dyn Read + Send>> {
+    let input = if jni_call!(BlazeBlockObject(block).hasFileSegment() -> bool)? {
+        get_file_reader(block)?
+    } else if jni_call!(BlazeBlockObject(block).hasByteBuffer() -> bool)? {
+        get_byte_buffer_reader(block)?
+    } else {
+        get_channel_reader(block)?
+    };
+
+    // shuffle blocks written with io encryption enabled
+    if jni_call!(BlazeBlockObject(block).isEncrypted() -> bool)? {
+        let Some(key) = io_encryption_key()? else {
+            return df_execution_err!("reading encrypted block without io encryption key");
+        };
+        return Ok(Box::new(IoEncryptionReader::try_new(input, Some(&key))?));
+    }
+    Ok(input)
+}
+
+fn get_channel_reader(block: JObject) -> Result<Box<dyn Read + Send>> {
     let channel_reader = ReadableByteChannelReader::try_new(block)?;
-    Ok(IpcCompressionReader::new(Box::new(
-        BufReader::with_capacity(65536, channel_reader),
-    )))
+    Ok(Box::new(BufReader::with_capacity(65536, channel_reader)))
 }
 
-fn get_file_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
+fn get_file_reader(block: JObject) -> Result<Box<dyn Read + Send>> {
     let path = jni_call!(BlazeBlockObject(block).getFilePath() -> JObject)?;
     let path = jni_get_string!(path.as_obj().into())?;
     let offset = jni_call!(BlazeBlockObject(block).getFileOffset() -> i64)?;
@@ -283,20 +421,21 @@ fn get_file_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read +
     let mut file = File::open(&path)?;
     file.seek(SeekFrom::Start(offset as u64))?;
 
-    Ok(IpcCompressionReader::new(Box::new(
-        BufReader::with_capacity(65536, file.take(length as u64)),
+    Ok(Box::new(BufReader::with_capacity(
+        65536,
+        file.take(length as u64),
     )))
 }
 
-fn get_byte_buffer_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
+fn get_byte_buffer_reader(block: JObject) -> Result<Box<dyn Read + Send>> {
     let byte_buffer = jni_call!(BlazeBlockObject(block).getByteBuffer() -> JObject)?;
     if jni_call!(JavaBuffer(byte_buffer.as_obj()).isDirect() -> bool)? {
         let reader = DirectByteBufferReader::try_new(block, byte_buffer.as_obj())?;
-        return Ok(IpcCompressionReader::new(Box::new(reader)));
+        return Ok(Box::new(reader));
     }
     if jni_call!(JavaBuffer(byte_buffer.as_obj()).hasArray() -> bool)? {
         let reader = HeapByteBufferReader::try_new(block, byte_buffer.as_obj())?;
-        return Ok(IpcCompressionReader::new(Box::new(reader)));
+        return Ok(Box::new(reader));
     }
     df_execution_err!("ByteBuffer is not direct and do not have array")
 }
@@ -452,3 +591,105 @@ impl Drop for HeapByteBufferReader {
         let _ = self.block;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Cursor, Read},
+        sync::Arc,
+    };
+
+    use arrow::{
+        array::{ArrayRef, Int32Array},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::common::Result;
+
+    use crate::{
+        common::ipc_compression::IpcCompressionWriter,
+        ipc_reader_exec::{RetryableBlockReader, ShuffleBlock},
+    };
+
+    /// returns the data of each fetch in order
+    struct MockBlock {
+        fetches: Vec<Vec<u8>>,
+        num_fetches: usize,
+    }
+
+    impl ShuffleBlock for MockBlock {
+        fn open(&self) -> Result<Box<dyn Read + Send>> {
+            Ok(Box::new(Cursor::new(
+                self.fetches[self.num_fetches].clone(),
+            )))
+        }
+
+        fn refetch(&mut self) -> Result<bool> {
+            if self.num_fetches + 1 < self.fetches.len() {
+                self.num_fetches += 1;
+                return Ok(true);
+            }
+            Ok(false)
+        }
+    }
+
+    /// returns the block data with two batches, and a corrupted copy whose
+    /// second batch is truncated
+    fn block_data(batches: &[ArrayRef]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut writer = IpcCompressionWriter::new(vec![]);
+        writer.write_batch(batches[0].len(), &[batches[0].clone()])?;
+        writer.finish_current_buf()?;
+        let first_batch_len = writer.inner().len();
+        writer.write_batch(batches[1].len(), &[batches[1].clone()])?;
+        writer.finish_current_buf()?;
+        let data = writer.inner().clone();
+
+        let mut corrupted = data.clone();
+        corrupted.truncate(first_batch_len + (data.len() - first_batch_len) / 2);
+        Ok((data, corrupted))
+    }
+
+    #[test]
+    fn test_retry_corrupted_block() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batches: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from_iter_values(0..100)),
+            Arc::new(Int32Array::from_iter_values(100..300)),
+        ];
+        let (data, corrupted) = block_data(&batches)?;
+
+        // corrupted on first read, succeeded on retry
+        let block = MockBlock {
+            fetches: vec![corrupted.clone(), data.clone()],
+            num_fetches: 0,
+        };
+        let mut reader = RetryableBlockReader::try_new(block, 3)?;
+        let mut read_cols = vec![];
+        while let Some((num_rows, cols)) = reader.read_batch(&schema)? {
+            assert_eq!(num_rows, cols[0].len());
+            read_cols.push(cols[0].clone());
+        }
+        assert_eq!(read_cols, batches);
+        assert_eq!(reader.num_retries(), 1);
+
+        // still corrupted after all retries
+        let block = MockBlock {
+            fetches: vec![corrupted.clone(); 3],
+            num_fetches: 0,
+        };
+        let mut reader = RetryableBlockReader::try_new(block, 2)?;
+        assert!(reader.read_batch(&schema)?.is_some());
+        assert!(reader.read_batch(&schema).is_err());
+        assert_eq!(reader.num_retries(), 2);
+
+        // retrying disabled
+        let block = MockBlock {
+            fetches: vec![corrupted, data],
+            num_fetches: 0,
+        };
+        let mut reader = RetryableBlockReader::try_new(block, 0)?;
+        assert!(reader.read_batch(&schema)?.is_some());
+        assert!(reader.read_batch(&schema).is_err());
+        assert_eq!(reader.num_retries(), 0);
+        Ok(())
+    }
+}
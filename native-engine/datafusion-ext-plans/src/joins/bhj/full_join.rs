@@ -27,10 +27,7 @@ use arrow::{
 use async_trait::async_trait;
 use bitvec::{bitvec, prelude::BitVec};
 use datafusion::{common::Result, physical_plan::metrics::Time};
-use datafusion_ext_commons::{
-    arrow::{eq_comparator::EqComparator, selection::take_cols},
-    likely,
-};
+use datafusion_ext_commons::arrow::{collation::Collation, selection::take_cols};
 
 use crate::{
     broadcast_join_exec::Joiner,
@@ -40,7 +37,7 @@ use crate::{
             full_join::ProbeSide::{L, R},
             ProbeSide,
         },
-        join_hash_map::{join_create_hashes, JoinHashMap},
+        join_hash_map::{evaluate_probed_keys, join_create_hashes_with_collations, JoinHashMap},
         JoinParams,
     },
 };
@@ -109,20 +106,15 @@ impl<const P: JoinerParams> FullJoiner<P> {
         }
     }
 
-    fn create_probed_key_columns(&self, probed_batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+    fn create_probed_key_columns(
+        &self,
+        probed_batch: &RecordBatch,
+    ) -> Result<(Vec<ArrayRef>, Vec<Collation>)> {
         let probed_key_exprs = match P.probe_side {
             L => &self.join_params.left_keys,
             R => &self.join_params.right_keys,
         };
-        let probed_key_columns: Vec<ArrayRef> = probed_key_exprs
-            .iter()
-            .map(|expr| {
-                Ok(expr
-                    .evaluate(probed_batch)?
-                    .into_array(probed_batch.num_rows())?)
-            })
-            .collect::<Result<_>>()?;
-        Ok(probed_key_columns)
+        evaluate_probed_keys(probed_batch, probed_key_exprs)
     }
 
     async fn flush(
@@ -225,12 +217,18 @@ impl<const P: JoinerParams> Joiner for FullJoiner<P> {
         let mut hash_joined_build_outer_indices = vec![];
 
         let batch_size = self.join_params.batch_size.max(probed_batch.num_rows());
-        let probed_key_columns = self.create_probed_key_columns(&probed_batch)?;
-        let probed_hashes = probed_side_hash_time
-            .with_timer(|| join_create_hashes(probed_batch.num_rows(), &probed_key_columns));
+        let (probed_key_columns, probed_key_collations) =
+            self.create_probed_key_columns(&probed_batch)?;
+        let probed_hashes = probed_side_hash_time.with_timer(|| {
+            join_create_hashes_with_collations(
+                probed_batch.num_rows(),
+                &probed_key_columns,
+                &probed_key_collations,
+            )
+        });
 
         let map = self.map.clone();
-        let eq = EqComparator::try_new(&probed_key_columns, map.key_columns())?;
+        let eq = map.create_key_eq_comparator(&probed_key_columns, &probed_key_collations)?;
 
         let probed_valids = probed_key_columns
             .iter()
@@ -265,29 +263,15 @@ impl<const P: JoinerParams> Joiner for FullJoiner<P> {
                 let map_value = map_values[hashes_idx];
                 hashes_idx += 1;
 
-                let mut join = |map_idx| {
-                    if likely!(eq.eq(row_idx, map_idx as usize)) {
-                        if P.probe_side_outer {
-                            hash_joined_probe_indices.push(row_idx as u32);
-                            hash_joined_build_outer_indices.push(Some(map_idx));
-                        } else {
-                            hash_joined_probe_indices.push(row_idx as u32);
-                            hash_joined_build_inner_indices.push(map_idx);
-                        }
-                        joined = true;
-                    }
-                };
-
-                match map_value {
-                    map_value if map_value.is_single() => {
-                        join(map_value.get_single());
-                    }
-                    map_value if map_value.is_range() => {
-                        for &map_idx in map.get_range(map_value) {
-                            join(map_idx);
-                        }
+                for map_idx in map.matched_indices(&eq, row_idx, map_value) {
+                    if P.probe_side_outer {
+                        hash_joined_probe_indices.push(row_idx as u32);
+                        hash_joined_build_outer_indices.push(Some(map_idx));
+                    } else {
+                        hash_joined_probe_indices.push(row_idx as u32);
+                        hash_joined_build_inner_indices.push(map_idx);
                     }
-                    _ => {} // map_value.is_empty
+                    joined = true;
                 }
             }
 
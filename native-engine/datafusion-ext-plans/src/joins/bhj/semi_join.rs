@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::{
+    ops::ControlFlow,
     pin::Pin,
     sync::{
         atomic::{AtomicUsize, Ordering::Relaxed},
@@ -27,10 +28,7 @@ use arrow::{
 use async_trait::async_trait;
 use bitvec::{bitvec, prelude::BitVec};
 use datafusion::{common::Result, physical_plan::metrics::Time};
-use datafusion_ext_commons::{
-    arrow::{eq_comparator::EqComparator, selection::take_cols},
-    likely,
-};
+use datafusion_ext_commons::arrow::{collation::Collation, selection::take_cols};
 
 use crate::{
     broadcast_join_exec::Joiner,
@@ -43,7 +41,7 @@ use crate::{
             },
             ProbeSide,
         },
-        join_hash_map::{join_create_hashes, JoinHashMap},
+        join_hash_map::{evaluate_probed_keys, join_create_hashes_with_collations, JoinHashMap},
         JoinParams,
     },
 };
@@ -100,6 +98,11 @@ pub struct SemiJoiner<const P: JoinerParams> {
     map_joined: BitVec,
     map: Arc<JoinHashMap>,
     output_rows: AtomicUsize,
+
+    /// number of rows still to be produced under the pushed down limit. it
+    /// starts from usize::MAX and is never exhausted if there is no limit or
+    /// the limit is not applicable.
+    remaining: usize,
 }
 
 impl<const P: JoinerParams> SemiJoiner<P> {
@@ -109,29 +112,33 @@ impl<const P: JoinerParams> SemiJoiner<P> {
         output_sender: Arc<WrappedRecordBatchSender>,
     ) -> Self {
         let map_joined = bitvec![0; map.data_batch().num_rows()];
+
+        // anti joins on the map side can only decide unjoined rows after all
+        // probed rows are consumed, existence joins always output all rows
+        let remaining = match join_params.fetch {
+            Some(fetch) if P.mode == Semi => fetch,
+            Some(fetch) if P.mode == Anti && P.probe_is_join_side => fetch,
+            _ => usize::MAX,
+        };
         Self {
             join_params,
             output_sender,
             map,
             map_joined,
             output_rows: AtomicUsize::new(0),
+            remaining,
         }
     }
 
-    fn create_probed_key_columns(&self, probed_batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+    fn create_probed_key_columns(
+        &self,
+        probed_batch: &RecordBatch,
+    ) -> Result<(Vec<ArrayRef>, Vec<Collation>)> {
         let probed_key_exprs = match P.probe_side {
             L => &self.join_params.left_keys,
             R => &self.join_params.right_keys,
         };
-        let probed_key_columns: Vec<ArrayRef> = probed_key_exprs
-            .iter()
-            .map(|expr| {
-                Ok(expr
-                    .evaluate(probed_batch)?
-                    .into_array(probed_batch.num_rows())?)
-            })
-            .collect::<Result<_>>()?;
-        Ok(probed_key_columns)
+        evaluate_probed_keys(probed_batch, probed_key_exprs)
     }
 
     async fn flush(&self, cols: Vec<ArrayRef>) -> Result<()> {
@@ -158,12 +165,18 @@ impl<const P: JoinerParams> Joiner for SemiJoiner<P> {
             std::mem::transmute::<_, &mut BitVec>(&mut self.map_joined)
         };
 
-        let probed_key_columns = self.create_probed_key_columns(&probed_batch)?;
-        let probed_hashes = probed_side_hash_time
-            .with_timer(|| join_create_hashes(probed_batch.num_rows(), &probed_key_columns));
+        let (probed_key_columns, probed_key_collations) =
+            self.create_probed_key_columns(&probed_batch)?;
+        let probed_hashes = probed_side_hash_time.with_timer(|| {
+            join_create_hashes_with_collations(
+                probed_batch.num_rows(),
+                &probed_key_columns,
+                &probed_key_collations,
+            )
+        });
 
         let map = self.map.clone();
-        let eq = EqComparator::try_new(&probed_key_columns, map.key_columns())?;
+        let eq = map.create_key_eq_comparator(&probed_key_columns, &probed_key_collations)?;
 
         let probed_valids = probed_key_columns
             .iter()
@@ -186,8 +199,17 @@ impl<const P: JoinerParams> Joiner for SemiJoiner<P> {
 
         let _probed_side_compare_timer = probed_side_compare_time.timer();
         let mut hashes_idx = 0;
+        let mut remaining = self.remaining;
+        let mut num_probed_rows = probed_batch.num_rows();
 
         for row_idx in 0..probed_batch.num_rows() {
+            if P.probe_is_join_side && remaining == 0 {
+                // enough rows are produced, remaining rows are not output
+                num_probed_rows = row_idx;
+                break;
+            }
+            let mut joined = false;
+
             if probed_valids
                 .as_ref()
                 .map(|nb| nb.is_valid(row_idx))
@@ -196,43 +218,34 @@ impl<const P: JoinerParams> Joiner for SemiJoiner<P> {
                 let map_value = map_values[hashes_idx];
                 hashes_idx += 1;
 
-                match map_value {
-                    map_value if map_value.is_single() => {
-                        let map_idx = map_value.get_single();
-                        if likely!(eq.eq(row_idx, map_idx as usize)) {
-                            if P.probe_is_join_side {
-                                probed_joined.set(row_idx, true);
-                            } else {
-                                map_joined.set(map_idx as usize, true);
+                if P.probe_is_join_side {
+                    joined = map
+                        .for_each_match(&eq, row_idx, map_value, |_| ControlFlow::Break(()))
+                        .is_break();
+                    probed_joined.set(row_idx, joined);
+                } else {
+                    map.for_each_match_limited(
+                        &eq,
+                        row_idx,
+                        map_value,
+                        &mut remaining,
+                        |map_idx| {
+                            if map_joined[map_idx as usize] {
+                                // all map records with this key should have
+                                // already been joined
+                                return ControlFlow::Break(());
                             }
-                        }
-                    }
-                    map_value if map_value.is_range() => {
-                        let range = map.get_range(map_value);
-                        let mut eqs = range
-                            .iter()
-                            .filter(|&map_idx| likely!(eq.eq(row_idx, *map_idx as usize)));
-
-                        if let Some(&map_idx) = eqs.next() {
-                            if P.probe_is_join_side {
-                                probed_joined.set(row_idx, true);
-                            } else {
-                                if !map_joined[map_idx as usize] {
-                                    map_joined.set(map_idx as usize, true);
-                                    for &map_idx in eqs {
-                                        map_joined.set(map_idx as usize, true);
-                                    }
-                                }
-                                // otherwise all map records with this key
-                                // should
-                                // have already been joined
-                            }
-                        }
-                    }
-                    _ => {} // map_value.is_empty()
+                            map_joined.set(map_idx as usize, true);
+                            ControlFlow::Continue(())
+                        },
+                    );
                 }
             }
+            if P.probe_is_join_side && (P.mode == Semi) == joined {
+                remaining -= 1;
+            }
         }
+        self.remaining = remaining;
 
         if P.probe_is_join_side {
             probed_side_compare_time
@@ -253,6 +266,7 @@ impl<const P: JoinerParams> Joiner for SemiJoiner<P> {
                             let probed_indices = probed_joined
                                 .into_iter()
                                 .enumerate()
+                                .take(num_probed_rows)
                                 .filter(|(_, joined)| (P.mode == Semi) ^ !joined)
                                 .map(|(idx, _)| idx as u32)
                                 .collect::<Vec<_>>();
@@ -317,6 +331,10 @@ impl<const P: JoinerParams> Joiner for SemiJoiner<P> {
             // semi join: map is join side and all items are joined
             return true;
         }
+        if self.remaining == 0 {
+            // limited semi/anti join: enough rows are produced
+            return true;
+        }
         false
     }
 
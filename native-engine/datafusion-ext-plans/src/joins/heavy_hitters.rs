@@ -0,0 +1,109 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hashbrown::HashSet;
+
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH_BITS: u32 = 11;
+const SKETCH_WIDTH: usize = 1 << SKETCH_WIDTH_BITS;
+const SKETCH_SEEDS: [u32; SKETCH_DEPTH] = [0x9E3779B1, 0x85EBCA77, 0xC2B2AE3D, 0x27D4EB2F];
+
+/// count-min sketch over join key hashes. estimated counts are never less
+/// than the real counts, so keys are never missed when detecting heavy
+/// hitters, while a few light keys may be falsely reported.
+pub struct CountMinSketch {
+    counters: Vec<u32>,
+}
+
+impl Default for CountMinSketch {
+    fn default() -> Self {
+        Self {
+            counters: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+}
+
+impl CountMinSketch {
+    #[inline]
+    fn slot(depth: usize, hash: u32) -> usize {
+        let h = (hash ^ SKETCH_SEEDS[depth]).wrapping_mul(SKETCH_SEEDS[depth] | 1);
+        depth * SKETCH_WIDTH + (h >> (32 - SKETCH_WIDTH_BITS)) as usize
+    }
+
+    /// adds one occurrence of hash and returns its estimated count
+    #[inline]
+    pub fn insert(&mut self, hash: u32) -> u32 {
+        let mut estimated = u32::MAX;
+        for depth in 0..SKETCH_DEPTH {
+            let counter = &mut self.counters[Self::slot(depth, hash)];
+            *counter = counter.saturating_add(1);
+            estimated = estimated.min(*counter);
+        }
+        estimated
+    }
+
+    pub fn estimate(&self, hash: u32) -> u32 {
+        (0..SKETCH_DEPTH)
+            .map(|depth| self.counters[Self::slot(depth, hash)])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// returns hashes whose estimated count exceeds `fraction` of all hashes.
+/// the threshold is known before scanning, so heavy hitters are collected in
+/// a single pass with the sketch and no exact per-key counting is needed.
+pub fn detect_heavy_hitters(hashes: &[u32], fraction: f64) -> HashSet<u32> {
+    let threshold = (hashes.len() as f64 * fraction).max(1.0);
+    let mut sketch = CountMinSketch::default();
+    let mut heavy_hitters = HashSet::new();
+    for &hash in hashes {
+        if sketch.insert(hash) as f64 > threshold {
+            heavy_hitters.insert(hash);
+        }
+    }
+    heavy_hitters
+}
+
+#[cfg(test)]
+mod test {
+    use crate::joins::heavy_hitters::{detect_heavy_hitters, CountMinSketch};
+
+    #[test]
+    fn test_count_min_sketch() {
+        let mut sketch = CountMinSketch::default();
+        for i in 0..10000u32 {
+            sketch.insert(i % 1000);
+        }
+        for i in 0..1000u32 {
+            assert!(sketch.estimate(i) >= 10);
+        }
+        assert_eq!(sketch.estimate(123456), 0);
+    }
+
+    #[test]
+    fn test_detect_heavy_hitters() {
+        // hash 42 takes 80% of all hashes
+        let hashes = (0..10000u32)
+            .map(|i| if i % 5 == 0 { i } else { 42 })
+            .collect::<Vec<_>>();
+        let heavy_hitters = detect_heavy_hitters(&hashes, 0.1);
+        assert!(heavy_hitters.contains(&42));
+        assert!(heavy_hitters.len() < 10);
+
+        // uniformly distributed hashes have no heavy hitters
+        let hashes = (0..10000u32).collect::<Vec<_>>();
+        assert!(detect_heavy_hitters(&hashes, 0.1).is_empty());
+    }
+}
@@ -13,25 +13,41 @@
 // limitations under the License.
 
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     fmt::{Debug, Formatter},
     hash::{BuildHasher, Hasher},
     io::{Cursor, Read, Write},
+    ops::ControlFlow,
     simd::{cmp::SimdPartialEq, Simd},
     sync::Arc,
 };
 
 use arrow::{
-    array::{Array, ArrayRef, AsArray, BinaryBuilder, RecordBatch},
+    array::{new_null_array, Array, ArrayRef, AsArray, BinaryBuilder, RecordBatch},
     datatypes::{DataType, Field, FieldRef, Schema, SchemaRef},
 };
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, DoubleConf, IntConf},
+};
 use datafusion::{common::Result, physical_expr::PhysicalExprRef};
 use datafusion_ext_commons::{
-    io::{read_len, write_len},
-    prefetch_read_data,
-    spark_hash::create_hashes,
+    arrow::{
+        array_size::{ArraySize, BatchSize},
+        coalesce::coalesce_batches_unchecked,
+        collation::Collation,
+        eq_comparator::EqComparator,
+    },
+    df_execution_err,
+    io::{read_len, read_len_u64, write_len, write_len_u64},
+    likely, prefetch_read_data,
+    spark_hash::{create_hashes, create_hashes_with_collations},
     unchecked, SliceAsRawBytes, UninitializedInit,
 };
-use itertools::Itertools;
+use datafusion_ext_exprs::collated::CollatedExpr;
+use foldhash::fast::FixedState;
+use itertools::Either;
 use once_cell::sync::OnceCell;
 use unchecked_index::UncheckedIndex;
 
@@ -78,6 +94,44 @@ impl MapValue {
 
 const MAP_VALUE_GROUP_SIZE: usize = 8;
 
+// max size of each serialized table chunk in the ~TABLE column
+const TABLE_CHUNK_SIZE: usize = 256 << 20;
+
+// max total size of table chunks in one hash map batch, limited by the i32
+// offsets of the ~TABLE binary column
+const MAX_TABLE_DATA_SIZE_PER_BATCH: usize = i32::MAX as usize;
+
+const DEFAULT_LOAD_FACTOR: f64 = 0.5;
+
+/// collision strategy of the open-addressing table, the tag is serialized
+/// together with the table so that a loaded table is probed the same way as
+/// it was built. tables serialized without a tag are linear probed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum ProbingStrategy {
+    Linear = 0,
+    // probes groups at triangular offsets (1, 3, 6, 10, ...), which visits all
+    // groups of a power-of-two sized table and avoids primary clustering
+    Quadratic = 1,
+}
+
+impl ProbingStrategy {
+    fn from_tag(tag: usize) -> Result<Self> {
+        match tag {
+            0 => Ok(ProbingStrategy::Linear),
+            1 => Ok(ProbingStrategy::Quadratic),
+            _ => df_execution_err!("join hash table: unknown probing strategy tag: {tag}"),
+        }
+    }
+
+    // increment of probing step after visiting each group, linear probing
+    // always steps by 1. used to keep probing loops branch-free
+    #[inline]
+    fn step_inc(self) -> usize {
+        self as usize
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 #[repr(align(64))] // ensure one group can be cached into a cache line
 struct MapValueGroup {
@@ -89,6 +143,8 @@ const _MAP_VALUE_GROUP_SIZE_CHECKER: [(); 64] = [(); size_of::<MapValueGroup>()]
 struct Table {
     num_valid_items: usize,
     map_mod_bits: u32,
+    probing: ProbingStrategy,
+    hash_seed: u32,
     map: UncheckedIndex<Vec<MapValueGroup>>,
     mapped_indices: UncheckedIndex<Vec<u32>>,
 }
@@ -107,69 +163,86 @@ impl Table {
         num_rows: usize,
         key_columns: &[ArrayRef],
         hashes: Vec<u32>,
+    ) -> Result<Self> {
+        Self::create_with_options(
+            num_rows,
+            key_columns,
+            hashes,
+            join_hash_map_load_factor(),
+            ProbingStrategy::Quadratic,
+        )
+    }
+
+    fn create_with_options(
+        num_rows: usize,
+        key_columns: &[ArrayRef],
+        hashes: Vec<u32>,
+        load_factor: f64,
+        probing: ProbingStrategy,
     ) -> Result<Self> {
         assert!(
             num_rows < 1073741824,
             "join hash table: number of rows exceeded 2^30: {num_rows}"
         );
+        let items = valid_items(0, key_columns, hashes);
+        Self::create_from_items(items, load_factor, probing)
+    }
 
-        let key_is_valid = |row_idx| key_columns.iter().all(|col| col.is_valid(row_idx));
-        let mut mapped_indices = unchecked!(vec![]);
-        let mut num_valid_items = 0;
+    /// creates the table from (row_idx, hash) items of rows with valid keys
+    fn create_from_items(
+        mut items: Vec<(u32, u32)>,
+        load_factor: f64,
+        probing: ProbingStrategy,
+    ) -> Result<Self> {
+        let num_valid_items = items.len();
+        items.sort_unstable_by_key(|&(idx, hash)| (hash, idx));
+
+        // count distinct hashes and ranges ahead, so that map items and mapped
+        // indices are allocated exactly
+        let mut num_map_items = 0;
+        let mut num_mapped_indices = 0;
+        for chunk in items.chunk_by(|(_, hash1), (_, hash2)| hash1 == hash2) {
+            num_map_items += 1;
+            if chunk.len() > 1 {
+                num_mapped_indices += chunk.len() + 1; // len header + items
+            }
+        }
+        let mut mapped_indices = unchecked!(Vec::with_capacity(num_mapped_indices));
 
         // collect map items
-        let mut map_items = unchecked!(vec![]);
-        for (hash, chunk) in hashes
-            .into_iter()
-            .enumerate()
-            .filter(|(idx, _)| key_is_valid(*idx))
-            .map(|(idx, hash)| {
-                num_valid_items += 1;
-                (idx as u32, hash)
-            })
-            .sorted_unstable_by_key(|&(idx, hash)| (hash, idx))
-            .chunk_by(|(_, hash)| *hash)
-            .into_iter()
-        {
-            let pos = mapped_indices.len() as u32;
-            mapped_indices.push(0);
-            mapped_indices.extend(chunk.map(|(idx, _hash)| idx));
-
-            let start = pos + 1;
-            let len = mapped_indices.len() as u32 - start;
-            mapped_indices[pos as usize] = len;
-
-            map_items.push((
-                hash,
-                match len {
-                    0 => unreachable!(),
-                    1 => {
-                        let single = mapped_indices.pop().unwrap();
-                        let _len = mapped_indices.pop().unwrap();
-                        MapValue::new_single(single)
-                    }
-                    _ => MapValue::new_range(start),
-                },
-            ));
+        let mut map_items = unchecked!(Vec::with_capacity(num_map_items));
+        for chunk in items.chunk_by(|(_, hash1), (_, hash2)| hash1 == hash2) {
+            let hash = chunk[0].1;
+            if chunk.len() == 1 {
+                map_items.push((hash, MapValue::new_single(chunk[0].0)));
+                continue;
+            }
+            mapped_indices.push(chunk.len() as u32);
+            let start = mapped_indices.len() as u32;
+            mapped_indices.extend(chunk.iter().map(|&(idx, _hash)| idx));
+            map_items.push((hash, MapValue::new_range(start)));
         }
 
         // build map
-        let map_mod_bits = (map_items.len().max(128) * 2 / MAP_VALUE_GROUP_SIZE)
+        let num_slots = map_items.len().max(128) as f64 / load_factor;
+        let map_mod_bits = ((num_slots / MAP_VALUE_GROUP_SIZE as f64).ceil() as usize)
             .next_power_of_two()
             .trailing_zeros();
         let mut map = unchecked!(vec![MapValueGroup::default(); 1usize << map_mod_bits]);
+        let map_mask = (1usize << map_mod_bits) - 1;
 
         macro_rules! entries {
-            [$i:expr] => (map_items[$i].0 % (1 << map_mod_bits))
+            [$i:expr] => (map_items[$i].0 as usize & map_mask)
         }
 
         const PREFETCH_AHEAD: usize = 4;
         for i in 0..map_items.len() {
             if i + PREFETCH_AHEAD < map_items.len() {
-                prefetch_read_data!(&map[entries![i + PREFETCH_AHEAD] as usize]);
+                prefetch_read_data!(&map[entries![i + PREFETCH_AHEAD]]);
             }
 
-            let mut e = entries![i] as usize;
+            let mut e = entries![i];
+            let mut step = 1;
             loop {
                 let empty = map[e].hashes.simd_eq(Simd::splat(0));
                 if let Some(empty_pos) = empty.first_set() {
@@ -177,48 +250,161 @@ impl Table {
                     map[e].values[empty_pos] = map_items[i].1;
                     break;
                 }
-                e += 1;
-                e %= 1 << map_mod_bits;
+                e = (e + step) & map_mask;
+                step += probing.step_inc();
             }
         }
 
         Ok(Table {
             num_valid_items,
             map_mod_bits,
+            probing,
+            hash_seed: join_hash_seed(),
             map,
             mapped_indices,
         })
     }
 
+    fn stats(&self) -> TableStats {
+        let num_groups = 1usize << self.map_mod_bits;
+        let map_mask = num_groups - 1;
+        let mut num_map_items = 0;
+        let mut max_probe_length = 0;
+        for (e, group) in self.map.iter().enumerate() {
+            for &hash in group.hashes.as_array().iter().filter(|&&hash| hash != 0) {
+                // number of groups visited from the initial group of this hash
+                let mut pos = hash as usize & map_mask;
+                let mut step = 1;
+                let mut probe_length = 1;
+                while pos != e {
+                    pos = (pos + step) & map_mask;
+                    step += self.probing.step_inc();
+                    probe_length += 1;
+                }
+                max_probe_length = max_probe_length.max(probe_length);
+                num_map_items += 1;
+            }
+        }
+        TableStats {
+            num_map_items,
+            load_factor: num_map_items as f64 / (num_groups * MAP_VALUE_GROUP_SIZE) as f64,
+            max_probe_length,
+            mem_size: self.mem_size(),
+        }
+    }
+
+    fn mem_size(&self) -> usize {
+        self.map.len() * size_of::<MapValueGroup>() + self.mapped_indices.len() * size_of::<u32>()
+    }
+
     pub fn read_from(mut r: impl Read) -> Result<Self> {
         // read map
         let num_valid_items = read_len(&mut r)?;
-        let map_mod_bits = read_len(&mut r)? as u32;
+        let map_mod_bits_and_tag = read_len(&mut r)?;
+        let map_mod_bits = (map_mod_bits_and_tag & 0xff) as u32;
+        let probing = ProbingStrategy::from_tag((map_mod_bits_and_tag >> 8) & 0xff)?;
+        let hash_seed = match (map_mod_bits_and_tag >> 16) as u32 {
+            0 => DEFAULT_JOIN_HASH_SEED, // tables serialized without a seed
+            hash_seed => hash_seed,
+        };
+        let dict_compressed = (map_mod_bits_and_tag >> 48) & 1 == 1;
+        if map_mod_bits >= u32::BITS {
+            return df_execution_err!("join hash table: invalid map mod bits {map_mod_bits}");
+        }
         let mut map = Vec::uninitialized_init(1usize << map_mod_bits);
         r.read_exact(map.as_raw_bytes_mut())?;
 
         // read mapped indices
-        let mapped_indices_len = read_len(&mut r)?;
-        let mut mapped_indices = Vec::with_capacity(mapped_indices_len);
-        for _ in 0..mapped_indices_len {
-            mapped_indices.push(read_len(&mut r)? as u32);
-        }
+        let mapped_indices = if dict_compressed {
+            read_mapped_indices_dict_compressed(&mut r)?
+        } else {
+            let mapped_indices_len = read_len(&mut r)?;
+            let mut mapped_indices = Vec::with_capacity(prealloc_len(mapped_indices_len));
+            for _ in 0..mapped_indices_len {
+                mapped_indices.push(read_mapped_index(read_len_u64(&mut r)? as i64)?);
+            }
+            mapped_indices
+        };
 
-        Ok(Self {
+        let mut table = Self {
             num_valid_items,
             map_mod_bits,
+            probing,
+            hash_seed,
             map: unchecked!(map),
             mapped_indices: unchecked!(mapped_indices),
-        })
+        };
+        table.compact_mapped_indices()?;
+        Ok(table)
+    }
+
+    /// validates that all ranges referenced by the map are covered by
+    /// mapped_indices, which is accessed without bound checks in
+    /// MapValue::get_range(). trailing indices not referenced by any range
+    /// are truncated.
+    fn compact_mapped_indices(&mut self) -> Result<()> {
+        let mapped_indices_len = self.mapped_indices.len();
+        let mut max_end = 0;
+
+        for group in self.map.iter() {
+            for (&hash, value) in group.hashes.as_array().iter().zip(&group.values) {
+                if hash == 0 || !value.is_range() {
+                    continue;
+                }
+                let start = value.0 as usize;
+                if start > mapped_indices_len {
+                    return df_execution_err!(
+                        "join hash table: range start {start} out of mapped indices \
+                         (len={mapped_indices_len})"
+                    );
+                }
+                let end = start + self.mapped_indices[start - 1] as usize;
+                if end > mapped_indices_len {
+                    return df_execution_err!(
+                        "join hash table: range {start}..{end} out of mapped indices \
+                         (len={mapped_indices_len})"
+                    );
+                }
+                max_end = max_end.max(end);
+            }
+        }
+
+        if max_end < mapped_indices_len {
+            self.mapped_indices.truncate(max_end);
+            self.mapped_indices.shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    pub fn write_to(self, w: impl Write) -> Result<()> {
+        self.write_to_impl(w, false)
+    }
+
+    /// writes the table with mapped indices in delta + dictionary + RLE
+    /// encoding, which is much smaller when build rows of the same key are
+    /// stored contiguously (their indices are then consecutive)
+    pub fn write_to_dict_compressed(self, w: impl Write) -> Result<()> {
+        self.write_to_impl(w, true)
     }
 
-    pub fn write_to(self, mut w: impl Write) -> Result<()> {
+    fn write_to_impl(self, mut w: impl Write, dict_compressed: bool) -> Result<()> {
         // write map
         write_len(self.num_valid_items, &mut w)?;
-        write_len(self.map_mod_bits as usize, &mut w)?;
+        // probing strategy tag, hash seed and encoding of mapped indices are
+        // stored in the high bits of map_mod_bits
+        write_len(
+            self.map_mod_bits as usize
+                | ((self.probing as usize) << 8)
+                | ((self.hash_seed as usize) << 16)
+                | ((dict_compressed as usize) << 48),
+            &mut w,
+        )?;
         w.write_all(self.map.as_raw_bytes())?;
 
         // write mapped indices
+        if dict_compressed {
+            return write_mapped_indices_dict_compressed(&self.mapped_indices, w);
+        }
         write_len(self.mapped_indices.len(), &mut w)?;
         for &v in self.mapped_indices.as_slice() {
             write_len(v as usize, &mut w)?;
@@ -230,14 +416,17 @@ impl Table {
         let mut hashes = unchecked!(hashes);
         const PREFETCH_AHEAD: usize = 4;
 
+        let map_mask = (1usize << self.map_mod_bits) - 1;
+        let step_inc = self.probing.step_inc();
+
         macro_rules! entries {
-            [$i:expr] => (hashes[$i] % (1 << self.map_mod_bits))
+            [$i:expr] => (hashes[$i] as usize & map_mask)
         }
 
         macro_rules! prefetch_at {
             ($i:expr) => {{
                 if $i < hashes.len() {
-                    prefetch_read_data!(&self.map[entries!($i) as usize]);
+                    prefetch_read_data!(&self.map[entries!($i)]);
                 }
             }};
         }
@@ -248,7 +437,8 @@ impl Table {
 
         for i in 0..hashes.len() {
             prefetch_at!(i + PREFETCH_AHEAD);
-            let mut e = entries![i] as usize;
+            let mut e = entries![i];
+            let mut step = 1;
             loop {
                 let hash_matched = self.map[e].hashes.simd_eq(Simd::splat(hashes[i]));
                 let empty = self.map[e].hashes.simd_eq(Simd::splat(0));
@@ -260,8 +450,8 @@ impl Table {
                     };
                     break;
                 }
-                e += 1;
-                e %= 1 << self.map_mod_bits;
+                e = (e + step) & map_mask;
+                step += step_inc;
             }
         }
 
@@ -272,6 +462,153 @@ impl Table {
     }
 }
 
+/// (row_idx, hash) items of rows whose keys are all valid, row indices are
+/// offset by base_idx
+fn valid_items(base_idx: u32, key_columns: &[ArrayRef], hashes: Vec<u32>) -> Vec<(u32, u32)> {
+    let key_is_valid = |row_idx| key_columns.iter().all(|col| col.is_valid(row_idx));
+    hashes
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| key_is_valid(*idx))
+        .map(|(idx, hash)| (base_idx + idx as u32, hash))
+        .collect()
+}
+
+struct TableStats {
+    num_map_items: usize,
+    load_factor: f64,
+    max_probe_length: usize,
+    mem_size: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JoinHashMapStats {
+    pub num_rows: usize,
+    pub num_valid_items: usize,
+    pub num_distinct_hashes: usize,
+    pub load_factor: f64,
+    pub max_probe_length: usize,
+    pub mem_size: usize,
+}
+
+// encodes mapped indices as zigzag deltas to the previous value, the deltas
+// are dictionary encoded and runs of identical dictionary indices are written
+// as (dict_idx, run_len) pairs
+fn write_mapped_indices_dict_compressed(mapped_indices: &[u32], mut w: impl Write) -> Result<()> {
+    let mut dict = HashMap::new();
+    let mut dict_values = vec![];
+    let mut runs: Vec<(usize, usize)> = vec![];
+    let mut prev = 0i64;
+    for &v in mapped_indices {
+        let delta = v as i64 - prev;
+        let zigzag = ((delta << 1) ^ (delta >> 63)) as u64;
+        prev = v as i64;
+
+        let dict_idx = *dict.entry(zigzag).or_insert_with(|| {
+            dict_values.push(zigzag);
+            dict_values.len() - 1
+        });
+        match runs.last_mut() {
+            Some((last_dict_idx, run_len)) if *last_dict_idx == dict_idx => *run_len += 1,
+            _ => runs.push((dict_idx, 1)),
+        }
+    }
+
+    write_len(mapped_indices.len(), &mut w)?;
+    write_len(dict_values.len(), &mut w)?;
+    for &value in &dict_values {
+        write_len_u64(value, &mut w)?;
+    }
+    write_len(runs.len(), &mut w)?;
+    for &(dict_idx, run_len) in &runs {
+        write_len(dict_idx, &mut w)?;
+        write_len(run_len, &mut w)?;
+    }
+    Ok(())
+}
+
+/// lengths read from serialized tables are not trusted, so vectors are
+/// preallocated up to a bound and grow as items are actually read
+fn prealloc_len(len: usize) -> usize {
+    const MAX_PREALLOC_LEN: usize = 1 << 20;
+    len.min(MAX_PREALLOC_LEN)
+}
+
+/// mapped indices are u32 row indices of the data batch, values out of range
+/// are rejected instead of being truncated
+fn read_mapped_index(value: i64) -> Result<u32> {
+    match u32::try_from(value) {
+        Ok(idx) => Ok(idx),
+        Err(_) => df_execution_err!("join hash table: mapped index {value} out of u32 range"),
+    }
+}
+
+fn read_mapped_indices_dict_compressed(mut r: impl Read) -> Result<Vec<u32>> {
+    let mapped_indices_len = read_len(&mut r)?;
+    let dict_len = read_len(&mut r)?;
+    let mut dict_values = Vec::with_capacity(prealloc_len(dict_len));
+    for _ in 0..dict_len {
+        let zigzag = read_len_u64(&mut r)?;
+        dict_values.push((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+    }
+
+    let mut mapped_indices = Vec::with_capacity(prealloc_len(mapped_indices_len));
+    let mut prev = 0i64;
+    let num_runs = read_len(&mut r)?;
+    for _ in 0..num_runs {
+        let dict_idx = read_len(&mut r)?;
+        let run_len = read_len(&mut r)?;
+        if dict_idx >= dict_values.len() || mapped_indices.len() + run_len > mapped_indices_len {
+            return df_execution_err!("join hash table: corrupted dict compressed mapped indices");
+        }
+        for _ in 0..run_len {
+            prev = prev.wrapping_add(dict_values[dict_idx]);
+            mapped_indices.push(read_mapped_index(prev)?);
+        }
+    }
+    if mapped_indices.len() != mapped_indices_len {
+        return df_execution_err!(
+            "join hash table: expect {mapped_indices_len} mapped indices, got {}",
+            mapped_indices.len(),
+        );
+    }
+    Ok(mapped_indices)
+}
+
+fn join_hash_map_dict_compress_indices() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED.get_or_init(|| {
+        conf::JOIN_HASH_MAP_DICT_COMPRESS_INDICES
+            .value()
+            .unwrap_or(false)
+    })
+}
+
+fn join_hash_map_load_factor() -> f64 {
+    static LOAD_FACTOR: OnceCell<f64> = OnceCell::new();
+    *LOAD_FACTOR.get_or_init(|| {
+        let load_factor = conf::JOIN_HASH_MAP_LOAD_FACTOR
+            .value()
+            .unwrap_or(DEFAULT_LOAD_FACTOR);
+        if load_factor > 0.0 && load_factor <= 0.9 {
+            load_factor
+        } else {
+            DEFAULT_LOAD_FACTOR
+        }
+    })
+}
+
+// set env BLAZE_LOG_JOIN_HASH_MAP_STATS=true to log stats of every built hash
+// map
+fn log_join_hash_map_stats_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("BLAZE_LOG_JOIN_HASH_MAP_STATS")
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or(false)
+    })
+}
+
 pub struct JoinHashMap {
     data_batch: RecordBatch,
     key_columns: Vec<ArrayRef>,
@@ -304,11 +641,13 @@ impl JoinHashMap {
 
         let table = Table::create_from_key_columns(data_batch.num_rows(), &key_columns)?;
 
-        Ok(Self {
+        let map = Self {
             data_batch,
             key_columns,
             table,
-        })
+        };
+        map.log_stats_if_enabled();
+        Ok(map)
     }
 
     pub fn create_from_data_batch_and_hashes(
@@ -319,12 +658,41 @@ impl JoinHashMap {
         let table =
             Table::craete_from_key_columns_and_hashes(data_batch.num_rows(), &key_columns, hashes)?;
 
-        Ok(Self {
+        let map = Self {
             data_batch,
             key_columns,
             table,
-        })
+        };
+        map.log_stats_if_enabled();
+        Ok(map)
     }
+
+    /// creates the map from hashes precomputed outside (for example, the
+    /// partition hashes of shuffle), skipping the internal hashing. probed
+    /// hashes must be converted by `join_hashes_from_precomputed` with the
+    /// same precomputed hashes of probed rows.
+    pub fn create_from_data_batch_and_precomputed_hashes(
+        data_batch: RecordBatch,
+        key_columns: Vec<ArrayRef>,
+        precomputed_hashes: &[u32],
+    ) -> Result<Self> {
+        let num_rows = data_batch.num_rows();
+        if precomputed_hashes.len() != num_rows {
+            return df_execution_err!(
+                "join hash map: number of precomputed hashes mismatched: {} != {num_rows}",
+                precomputed_hashes.len(),
+            );
+        }
+        if let Some(col) = key_columns.iter().find(|col| col.len() != num_rows) {
+            return df_execution_err!(
+                "join hash map: number of key rows mismatched: {} != {num_rows}",
+                col.len(),
+            );
+        }
+        let hashes = join_hashes_from_precomputed(precomputed_hashes);
+        Self::create_from_data_batch_and_hashes(data_batch, key_columns, hashes)
+    }
+
     pub fn create_empty(hash_map_schema: SchemaRef, key_exprs: &[PhysicalExprRef]) -> Result<Self> {
         let data_batch = RecordBatch::new_empty(hash_map_schema);
         Self::create_from_data_batch(data_batch, key_exprs)
@@ -335,15 +703,59 @@ impl JoinHashMap {
         table_data_column.is_valid(0)
     }
 
-    pub fn load_from_hash_map_batch(
-        hash_map_batch: RecordBatch,
+    /// loads a hash map from batches created by `into_hash_map_batches`. the
+    /// first batch contains the data rows, table data may continue in the
+    /// following batches.
+    pub fn load_from_hash_map_batches(
+        mut hash_map_batches: Vec<RecordBatch>,
         key_exprs: &[PhysicalExprRef],
     ) -> Result<Self> {
-        let mut data_batch = hash_map_batch.clone();
+        if hash_map_batches.is_empty() {
+            return df_execution_err!("join hash table: missing hash map batch");
+        }
+        let mut data_batch = hash_map_batches.remove(0);
+        if hash_map_batches.iter().any(|batch| {
+            let table_data_column = batch.column(batch.num_columns() - 1);
+            table_data_column.null_count() > 0
+        }) {
+            return df_execution_err!("join hash table: expect table data in extra batches");
+        }
 
+        // table data is split into multiple chunks in leading rows
         let table_data_column = data_batch.remove_column(data_batch.num_columns() - 1);
-        let mut table_data = Cursor::new(table_data_column.as_binary::<i32>().value(0));
-        let table = Table::read_from(&mut table_data)?;
+        let table_data_columns = std::iter::once(table_data_column)
+            .chain(
+                hash_map_batches
+                    .iter()
+                    .map(|batch| batch.column(batch.num_columns() - 1).clone()),
+            )
+            .collect::<Vec<_>>();
+        let table_data_chunks = table_data_columns
+            .iter()
+            .flat_map(|col| col.as_binary::<i32>().iter().flatten())
+            .collect::<Vec<_>>();
+        let table_data = match table_data_chunks.len() {
+            1 => Cow::Borrowed(table_data_chunks[0]),
+            _ => Cow::Owned(table_data_chunks.concat()),
+        };
+        let mut table_data_cursor = Cursor::new(table_data.as_ref());
+        let table = Table::read_from(&mut table_data_cursor)?;
+        if table_data_cursor.position() as usize != table_data.len() {
+            return df_execution_err!(
+                "join hash table: table data has {} trailing bytes",
+                table_data.len() - table_data_cursor.position() as usize,
+            );
+        }
+        if table.hash_seed != join_hash_seed() {
+            // probing with hashes of another seed silently finds nothing
+            return df_execution_err!(
+                "join hash table: table was built with hash seed {:#x}, but the current hash \
+                 seed is {:#x}, spark.blaze.joinHashMap.hashSeed must be consistent in all \
+                 executors",
+                table.hash_seed,
+                join_hash_seed(),
+            );
+        }
 
         let key_columns: Vec<ArrayRef> = key_exprs
             .iter()
@@ -360,26 +772,79 @@ impl JoinHashMap {
         })
     }
 
-    pub fn into_hash_map_batch(self) -> Result<RecordBatch> {
+    /// converts the hash map into batches with a ~TABLE column holding the
+    /// serialized table. the first batch contains all data rows. tables
+    /// exceeding the capacity of one binary column are continued in extra
+    /// batches with null data columns.
+    pub fn into_hash_map_batches(self) -> Result<Vec<RecordBatch>> {
+        self.into_hash_map_batches_with_chunk_size(TABLE_CHUNK_SIZE, MAX_TABLE_DATA_SIZE_PER_BATCH)
+    }
+
+    fn into_hash_map_batches_with_chunk_size(
+        self,
+        chunk_size: usize,
+        max_table_data_size_per_batch: usize,
+    ) -> Result<Vec<RecordBatch>> {
         let schema = join_hash_map_schema(&self.data_batch.schema());
-        if self.data_batch.num_rows() == 0 {
-            return Ok(RecordBatch::new_empty(schema));
+        let num_rows = self.data_batch.num_rows();
+        if num_rows == 0 {
+            return Ok(vec![RecordBatch::new_empty(schema)]);
         }
 
-        let mut table_col_builder = BinaryBuilder::new();
         let mut table_data = vec![];
-        self.table.write_to(&mut table_data)?;
-        table_col_builder.append_value(&table_data);
-
-        for _ in 1..self.data_batch.num_rows() {
-            table_col_builder.append_null();
+        if join_hash_map_dict_compress_indices() {
+            self.table.write_to_dict_compressed(&mut table_data)?;
+        } else {
+            self.table.write_to(&mut table_data)?;
         }
-        let table_col: ArrayRef = Arc::new(table_col_builder.finish());
 
-        Ok(RecordBatch::try_new(
-            schema,
-            vec![self.data_batch.columns().to_vec(), vec![table_col]].concat(),
-        )?)
+        // split table data into chunks, small tables are always kept in one
+        // chunk. chunks are put in leading rows of the data batch, and then
+        // in extra batches if the data batch is not large enough
+        let chunk_size = chunk_size
+            .max(table_data.len().div_ceil(num_rows))
+            .min(max_table_data_size_per_batch);
+        let max_chunks_per_batch = max_table_data_size_per_batch / chunk_size;
+        let mut chunks = table_data.chunks(chunk_size).peekable();
+        let mut hash_map_batches = vec![];
+
+        while chunks.peek().is_some() || hash_map_batches.is_empty() {
+            let batch_num_rows = match hash_map_batches.len() {
+                0 => num_rows,
+                _ => chunks.len().min(max_chunks_per_batch),
+            };
+            let batch_chunks = chunks
+                .by_ref()
+                .take(batch_num_rows.min(max_chunks_per_batch))
+                .collect::<Vec<_>>();
+            let mut table_col_builder = BinaryBuilder::with_capacity(
+                batch_num_rows,
+                batch_chunks.iter().map(|chunk| chunk.len()).sum(),
+            );
+            for chunk in &batch_chunks {
+                table_col_builder.append_value(chunk);
+            }
+            for _ in table_col_builder.len()..batch_num_rows {
+                table_col_builder.append_null();
+            }
+            let table_col: ArrayRef = Arc::new(table_col_builder.finish());
+
+            let data_cols = match hash_map_batches.len() {
+                0 => self.data_batch.columns().to_vec(),
+                _ => self
+                    .data_batch
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|field| new_null_array(field.data_type(), batch_num_rows))
+                    .collect(),
+            };
+            hash_map_batches.push(RecordBatch::try_new(
+                schema.clone(),
+                vec![data_cols, vec![table_col]].concat(),
+            )?);
+        }
+        Ok(hash_map_batches)
     }
 
     pub fn data_schema(&self) -> SchemaRef {
@@ -409,6 +874,224 @@ impl JoinHashMap {
     pub fn get_range(&self, map_value: MapValue) -> &[u32] {
         map_value.get_range(self)
     }
+
+    /// creates the comparator used by `matched_indices`, comparing probed keys
+    /// (folded by collations) to key columns of this map. build side keys must
+    /// be the same CollatedExprs, whose evaluated key columns are folded.
+    pub fn create_key_eq_comparator(
+        &self,
+        probed_key_columns: &[ArrayRef],
+        probed_key_collations: &[Collation],
+    ) -> Result<EqComparator> {
+        EqComparator::try_new_with_collations(
+            probed_key_columns,
+            &self.key_columns,
+            probed_key_collations,
+        )
+    }
+
+    /// returns candidate map rows of a looked up map value whose keys truly
+    /// equal to keys of the probed row. rows are grouped by 31-bit hashes, so a
+    /// candidate may be a hash collision of a different key. all join
+    /// operators should use this instead of iterating candidates directly.
+    pub fn matched_indices<'a>(
+        &'a self,
+        eq: &'a EqComparator,
+        probed_row: usize,
+        map_value: MapValue,
+    ) -> impl Iterator<Item = u32> + 'a {
+        let range: &[u32] = if map_value.is_range() {
+            self.get_range(map_value)
+        } else {
+            &[]
+        };
+        let candidates = if map_value.is_single() {
+            Either::Left(std::iter::once(map_value.get_single()))
+        } else {
+            Either::Right(range.iter().copied())
+        };
+        candidates.filter(move |&map_idx| likely!(eq.eq(probed_row, map_idx as usize)))
+    }
+
+    /// calls `f` on each matched map row of the probed row, stops enumerating
+    /// once `f` breaks.
+    pub fn for_each_match(
+        &self,
+        eq: &EqComparator,
+        probed_row: usize,
+        map_value: MapValue,
+        f: impl FnMut(u32) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.matched_indices(eq, probed_row, map_value)
+            .try_for_each(f)
+    }
+
+    /// like `for_each_match`, but also stops once `remaining` matches are
+    /// produced. a match is produced if `f` does not break on it, `remaining`
+    /// is decreased by the number of produced matches, which is returned.
+    pub fn for_each_match_limited(
+        &self,
+        eq: &EqComparator,
+        probed_row: usize,
+        map_value: MapValue,
+        remaining: &mut usize,
+        mut f: impl FnMut(u32) -> ControlFlow<()>,
+    ) -> usize {
+        let mut num_produced = 0;
+        if *remaining == 0 {
+            return num_produced;
+        }
+        let _ = self.for_each_match(eq, probed_row, map_value, |map_idx| {
+            f(map_idx)?;
+            num_produced += 1;
+            *remaining -= 1;
+            if *remaining == 0 {
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+        num_produced
+    }
+
+    /// memory used by buffers of the data batch and the table, cheap enough to
+    /// be checked after every build
+    pub fn total_memory_usage(&self) -> usize {
+        let data_mem_size: usize = self
+            .data_batch
+            .columns()
+            .iter()
+            .map(|col| col.get_array_mem_size())
+            .sum();
+        data_mem_size + self.table.mem_size()
+    }
+
+    /// walks the whole table, do not call in performance critical path
+    pub fn stats(&self) -> JoinHashMapStats {
+        let table_stats = self.table.stats();
+        JoinHashMapStats {
+            num_rows: self.data_batch.num_rows(),
+            num_valid_items: self.table.num_valid_items,
+            num_distinct_hashes: table_stats.num_map_items,
+            load_factor: table_stats.load_factor,
+            max_probe_length: table_stats.max_probe_length,
+            mem_size: table_stats.mem_size + self.data_batch.get_array_memory_size(),
+        }
+    }
+
+    fn log_stats_if_enabled(&self) {
+        if log_join_hash_map_stats_enabled() {
+            let stats = self.stats();
+            log::info!(
+                "built join hash map: num_rows={}, num_valid_items={}, num_distinct_hashes={}, load_factor={:.3}, max_probe_length={}, mem_size={}",
+                stats.num_rows,
+                stats.num_valid_items,
+                stats.num_distinct_hashes,
+                stats.load_factor,
+                stats.max_probe_length,
+                stats.mem_size,
+            );
+        }
+    }
+}
+
+/// builds a join hash map incrementally from batches. keys are hashed as
+/// batches arrive and the table is assembled in finish(). the built map is
+/// identical to the one created from the concatenated batch.
+pub struct JoinHashMapBuilder {
+    data_schema: SchemaRef,
+    key_exprs: Vec<PhysicalExprRef>,
+    batches: Vec<RecordBatch>,
+    batches_mem_size: usize,
+    items: Vec<(u32, u32)>,
+    num_rows: usize,
+}
+
+impl JoinHashMapBuilder {
+    pub fn new(data_schema: SchemaRef, key_exprs: Vec<PhysicalExprRef>) -> Self {
+        Self {
+            data_schema,
+            key_exprs,
+            batches: vec![],
+            batches_mem_size: 0,
+            items: vec![],
+            num_rows: 0,
+        }
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// memory used by the added batches and hashed items
+    pub fn mem_used(&self) -> usize {
+        self.batches_mem_size + self.items.capacity() * size_of::<(u32, u32)>()
+    }
+
+    /// returns the added batches, dropping the hashed items
+    pub fn into_batches(self) -> Vec<RecordBatch> {
+        self.batches
+    }
+
+    pub fn add_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let num_rows = batch.num_rows();
+        if num_rows == 0 {
+            return Ok(());
+        }
+        assert!(
+            self.num_rows + num_rows < 1073741824,
+            "join hash table: number of rows exceeded 2^30: {}",
+            self.num_rows + num_rows,
+        );
+
+        let key_columns: Vec<ArrayRef> = self
+            .key_exprs
+            .iter()
+            .map(|expr| Ok(expr.evaluate(&batch)?.into_array(num_rows)?))
+            .collect::<Result<_>>()?;
+        let hashes = join_create_hashes(num_rows, &key_columns);
+        self.items
+            .extend(valid_items(self.num_rows as u32, &key_columns, hashes));
+
+        // key columns are not kept, they are evaluated again on the coalesced
+        // data batch in finish(), where key columns of plain column exprs share
+        // buffers with the data batch
+        self.batches_mem_size += batch.get_batch_mem_size();
+        self.batches.push(batch);
+        self.num_rows += num_rows;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<JoinHashMap> {
+        if self.batches.is_empty() {
+            let data_batch = RecordBatch::new_empty(self.data_schema);
+            return JoinHashMap::create_from_data_batch(data_batch, &self.key_exprs);
+        }
+        let data_batch = coalesce_batches_unchecked(self.data_schema, &self.batches);
+        drop(self.batches);
+
+        let key_columns: Vec<ArrayRef> = self
+            .key_exprs
+            .iter()
+            .map(|expr| {
+                Ok(expr
+                    .evaluate(&data_batch)?
+                    .into_array(data_batch.num_rows())?)
+            })
+            .collect::<Result<_>>()?;
+        let table = Table::create_from_items(
+            self.items,
+            join_hash_map_load_factor(),
+            ProbingStrategy::Quadratic,
+        )?;
+
+        let map = JoinHashMap {
+            data_batch,
+            key_columns,
+            table,
+        };
+        map.log_stats_if_enabled();
+        Ok(map)
+    }
 }
 
 #[inline]
@@ -437,16 +1120,60 @@ pub fn join_hash_map_schema(data_schema: &SchemaRef) -> SchemaRef {
 
 #[inline]
 pub fn join_create_hashes(num_rows: usize, key_columns: &[ArrayRef]) -> Vec<u32> {
-    const JOIN_HASH_RANDOM_SEED: u32 = 0x1E39FA04;
-    const HASHER: foldhash::fast::FixedState =
-        foldhash::fast::FixedState::with_seed(JOIN_HASH_RANDOM_SEED as u64);
-    let mut hashes = create_hashes(num_rows, key_columns, JOIN_HASH_RANDOM_SEED, |v, h| {
-        let mut hasher = HASHER.build_hasher();
-        hasher.write_u32(h);
-        hasher.write(v);
-        hasher.finish() as u32
+    let seed = join_hash_seed();
+    let hash_state = FixedState::with_seed(seed as u64);
+    let hashes = create_hashes(num_rows, key_columns, seed, |v, h| {
+        join_hash(v, h, &hash_state)
     });
+    into_join_hashes(hashes)
+}
+
+/// creates join hashes of key columns folded by collations, which are the
+/// same as hashes of the materialized folded key columns
+#[inline]
+pub fn join_create_hashes_with_collations(
+    num_rows: usize,
+    key_columns: &[ArrayRef],
+    key_collations: &[Collation],
+) -> Vec<u32> {
+    let seed = join_hash_seed();
+    let hash_state = FixedState::with_seed(seed as u64);
+    let hashes =
+        create_hashes_with_collations(num_rows, key_columns, key_collations, seed, |v, h| {
+            join_hash(v, h, &hash_state)
+        });
+    into_join_hashes(hashes)
+}
+
+const DEFAULT_JOIN_HASH_SEED: u32 = 0x1E39FA04;
+
+/// seed of join hashes, configurable for reproducing issues with keys badly
+/// clustered under the default seed. 0 selects the default seed.
+pub fn join_hash_seed() -> u32 {
+    static SEED: OnceCell<u32> = OnceCell::new();
+    *SEED.get_or_init(|| match conf::JOIN_HASH_SEED.value().unwrap_or(0) {
+        0 => DEFAULT_JOIN_HASH_SEED,
+        seed => seed as u32,
+    })
+}
 
+#[inline]
+fn join_hash(v: &[u8], h: u32, hash_state: &FixedState) -> u32 {
+    let mut hasher = hash_state.build_hasher();
+    hasher.write_u32(h);
+    hasher.write(v);
+    hasher.finish() as u32
+}
+
+/// converts hashes precomputed outside into join hashes, used on both build
+/// and probe sides so that they always use identical hash values
+#[inline]
+pub fn join_hashes_from_precomputed(precomputed_hashes: &[u32]) -> Vec<u32> {
+    into_join_hashes(precomputed_hashes.to_vec())
+}
+
+#[inline]
+fn into_join_hashes(mut hashes: Vec<u32>) -> Vec<u32> {
     // use 31-bit non-zero hash
     for h in &mut hashes {
         *h |= 0x80000000;
@@ -454,6 +1181,30 @@ pub fn join_create_hashes(num_rows: usize, key_columns: &[ArrayRef]) -> Vec<u32>
     hashes
 }
 
+/// evaluates probed key columns. string keys wrapped in CollatedExpr are
+/// returned unfolded together with their collations, so that they can be
+/// hashed and compared against the materialized build side keys without
+/// folding. other keys are evaluated as is with binary collation.
+pub fn evaluate_probed_keys(
+    batch: &RecordBatch,
+    key_exprs: &[PhysicalExprRef],
+) -> Result<(Vec<ArrayRef>, Vec<Collation>)> {
+    let mut key_columns = Vec::with_capacity(key_exprs.len());
+    let mut key_collations = Vec::with_capacity(key_exprs.len());
+    for expr in key_exprs {
+        let data_type = expr.data_type(&batch.schema())?;
+        let (expr, collation) = match expr.as_any().downcast_ref::<CollatedExpr>() {
+            Some(collated) if collated.collation().supports(&data_type) => {
+                (collated.expr(), collated.collation())
+            }
+            _ => (expr, Collation::Binary),
+        };
+        key_columns.push(expr.evaluate(batch)?.into_array(batch.num_rows())?);
+        key_collations.push(collation);
+    }
+    Ok((key_columns, key_collations))
+}
+
 #[inline]
 pub fn join_table_field() -> FieldRef {
     static BHJ_KEY_FIELD: OnceCell<FieldRef> = OnceCell::new();
@@ -461,3 +1212,621 @@ pub fn join_table_field() -> FieldRef {
         .get_or_init(|| Arc::new(Field::new("~TABLE", DataType::Binary, true)))
         .clone()
 }
+
+#[cfg(test)]
+mod test {
+    use std::{io::Cursor, ops::ControlFlow, sync::Arc};
+
+    use arrow::{
+        array::{Array, ArrayRef, AsArray, BinaryArray, Int64Array, RecordBatch, StringArray},
+        buffer::{Buffer, OffsetBuffer},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalExprRef},
+    };
+    use datafusion_ext_commons::{
+        arrow::{array_size::BatchSize, collation::Collation, eq_comparator::EqComparator},
+        io::write_len,
+    };
+    use datafusion_ext_exprs::collated::CollatedExpr;
+
+    use crate::joins::join_hash_map::{
+        evaluate_probed_keys, join_create_hashes, join_create_hashes_with_collations,
+        join_hash_seed, join_hashes_from_precomputed, JoinHashMap, JoinHashMapBuilder,
+        ProbingStrategy, Table,
+    };
+
+    #[test]
+    fn test_hash_map_batch_with_multiple_table_chunks() -> Result<()> {
+        let num_rows = 1000;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from_iter_values(
+                (0..num_rows).map(|i| i % 300),
+            ))],
+        )?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("a", 0))];
+        let hashes = join_create_hashes(num_rows as usize, batch.columns());
+
+        for chunk_size in [usize::MAX, 4096, 1] {
+            let map = JoinHashMap::create_from_data_batch(batch.clone(), &key_exprs)?;
+            let expected_stats = map.stats();
+            let expected_map_values = map.lookup_many(hashes.clone());
+
+            let hash_map_batches =
+                map.into_hash_map_batches_with_chunk_size(chunk_size, usize::MAX)?;
+            assert_eq!(hash_map_batches.len(), 1);
+            let table_col = hash_map_batches[0].column(hash_map_batches[0].num_columns() - 1);
+            let num_chunks = table_col.len() - table_col.null_count();
+            match chunk_size {
+                usize::MAX => assert_eq!(num_chunks, 1),
+                _ => assert!(num_chunks > 1),
+            }
+            assert!(JoinHashMap::record_batch_contains_hash_map(
+                &hash_map_batches[0]
+            ));
+
+            let loaded = JoinHashMap::load_from_hash_map_batches(hash_map_batches, &key_exprs)?;
+            assert_eq!(loaded.stats(), expected_stats);
+            assert_eq!(loaded.lookup_many(hashes.clone()), expected_map_values);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_map_batches_exceeding_table_data_size_per_batch() -> Result<()> {
+        // only 3 data rows, but the table data takes more than 3 chunks and
+        // more than one batch
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec!["x", "y", "z"])),
+            ],
+        )?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("a", 0))];
+        let hashes = join_create_hashes(batch.num_rows(), &batch.columns()[..1]);
+        let map = JoinHashMap::create_from_data_batch(batch.clone(), &key_exprs)?;
+        let mut table_data = vec![];
+        map.table.write_to(&mut table_data)?;
+        let expected_stats = map.stats();
+        let expected_map_values = map.lookup_many(hashes.clone());
+
+        let chunk_size = table_data.len().div_ceil(3).div_ceil(4);
+        let hash_map_batches =
+            map.into_hash_map_batches_with_chunk_size(chunk_size, chunk_size * 2)?;
+        assert!(hash_map_batches.len() > 1);
+        for hash_map_batch in &hash_map_batches {
+            let table_col = hash_map_batch.column(hash_map_batch.num_columns() - 1);
+            let table_data_size = table_col.as_binary::<i32>().value_data().len();
+            assert!(table_data_size <= chunk_size * 2);
+        }
+        assert_eq!(hash_map_batches[0].num_rows(), 3);
+        assert_eq!(
+            hash_map_batches[0].column(1).as_ref(),
+            batch.column(1).as_ref()
+        );
+
+        let loaded = JoinHashMap::load_from_hash_map_batches(hash_map_batches.clone(), &key_exprs)?;
+        assert_eq!(loaded.data_batch(), &batch);
+        assert_eq!(loaded.stats(), expected_stats);
+        assert_eq!(loaded.lookup_many(hashes), expected_map_values);
+
+        // missing trailing batches are detected
+        let truncated = hash_map_batches[..hash_map_batches.len() - 1].to_vec();
+        assert!(JoinHashMap::load_from_hash_map_batches(truncated, &key_exprs).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_hash_map_with_mismatched_seed() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from_iter_values(0..100))])?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("a", 0))];
+
+        // the seed is kept through serialization
+        let map = JoinHashMap::create_from_data_batch(batch.clone(), &key_exprs)?;
+        let hash_map_batches = map.into_hash_map_batches()?;
+        let loaded = JoinHashMap::load_from_hash_map_batches(hash_map_batches, &key_exprs)?;
+        assert_eq!(loaded.table.hash_seed, join_hash_seed());
+
+        // a table built with another seed is refused
+        let mut map = JoinHashMap::create_from_data_batch(batch, &key_exprs)?;
+        map.table.hash_seed = join_hash_seed() ^ 0x5a5a5a5a;
+        let hash_map_batches = map.into_hash_map_batches()?;
+        let err = JoinHashMap::load_from_hash_map_batches(hash_map_batches, &key_exprs).err();
+        assert!(err.is_some_and(|err| err.to_string().contains("hash seed")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dict_compressed_mapped_indices() -> Result<()> {
+        // 1:1000 join, build rows of each key are stored contiguously
+        let num_rows = 100000;
+        let key_columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from_iter_values(
+            (0..num_rows as i64).map(|i| i / 1000),
+        ))];
+        let hashes = join_create_hashes(num_rows, &key_columns);
+        let create_table = || Table::create_from_key_columns(num_rows, &key_columns);
+
+        let mut plain_data = vec![];
+        create_table()?.write_to(&mut plain_data)?;
+        let mut dict_data = vec![];
+        create_table()?.write_to_dict_compressed(&mut dict_data)?;
+
+        let map_size = create_table()?.map.as_raw_bytes().len();
+        let plain_indices_size = plain_data.len() - map_size;
+        let dict_indices_size = dict_data.len() - map_size;
+        assert!(
+            dict_indices_size * 20 < plain_indices_size,
+            "dict compressed size {dict_indices_size} vs plain size {plain_indices_size}"
+        );
+
+        let plain = Table::read_from(Cursor::new(&plain_data))?;
+        let dict = Table::read_from(Cursor::new(&dict_data))?;
+        assert_eq!(
+            dict.mapped_indices.as_slice(),
+            plain.mapped_indices.as_slice()
+        );
+        assert_eq!(
+            dict.lookup_many(hashes.clone()),
+            plain.lookup_many(hashes.clone())
+        );
+
+        // truncated data is rejected
+        assert!(Table::read_from(Cursor::new(&dict_data[..dict_data.len() - 1])).is_err());
+
+        // corrupted lengths are rejected without preallocating them
+        let table = create_table()?;
+        for dict_compressed in [false, true] {
+            let mut corrupted = vec![];
+            write_len(table.num_valid_items, &mut corrupted)?;
+            write_len(
+                table.map_mod_bits as usize
+                    | ((table.probing as usize) << 8)
+                    | ((table.hash_seed as usize) << 16)
+                    | ((dict_compressed as usize) << 48),
+                &mut corrupted,
+            )?;
+            corrupted.extend_from_slice(table.map.as_raw_bytes());
+            write_len(1 << 60, &mut corrupted)?;
+            write_len(1 << 60, &mut corrupted)?;
+            assert!(Table::read_from(Cursor::new(&corrupted)).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_total_memory_usage() -> Result<()> {
+        // one row with a 1GB binary value, the zeroed value buffer is allocated
+        // lazily and never touched
+        let value_size = 1usize << 30;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int64, false),
+            Field::new("v", DataType::Binary, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1])),
+                Arc::new(BinaryArray::new(
+                    OffsetBuffer::new(vec![0, value_size as i32].into()),
+                    Buffer::from_vec(vec![0u8; value_size]),
+                    None,
+                )),
+            ],
+        )?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("k", 0))];
+        let map = JoinHashMap::create_from_data_batch(batch, &key_exprs)?;
+
+        // the table has the minimal 32 groups and no mapped indices
+        let table_size = 32 * 64;
+        let usage = map.total_memory_usage();
+        assert!(usage >= value_size + table_size, "usage={usage}");
+        assert!(usage < value_size + table_size + 1024, "usage={usage}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let num_rows = 1000;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from_iter(
+                (0..num_rows).map(|i| (i % 10 != 0).then_some(i as i64 % 300)),
+            ))],
+        )?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("a", 0))];
+        let map = JoinHashMap::create_from_data_batch(batch, &key_exprs)?;
+        let stats = map.stats();
+        assert_eq!(stats.num_rows, 1000);
+        assert_eq!(stats.num_valid_items, 900);
+        assert_eq!(stats.num_distinct_hashes, 270);
+        assert!(stats.load_factor > 0.0 && stats.load_factor <= 0.5);
+        assert!(stats.max_probe_length >= 1);
+        assert!(stats.mem_size > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_probing_with_colliding_hashes() -> Result<()> {
+        // distinct hashes sharing low bits collide into a narrow window of
+        // 64 groups under masking
+        let num_rows = 4096;
+        let key_columns: Vec<ArrayRef> =
+            vec![Arc::new(Int64Array::from_iter_values(0..num_rows as i64))];
+        let hashes = (0..num_rows as u32)
+            .map(|i| 0x80000000 | (i << 16) | (i % 64))
+            .collect::<Vec<_>>();
+
+        let linear = Table::create_with_options(
+            num_rows,
+            &key_columns,
+            hashes.clone(),
+            0.5,
+            ProbingStrategy::Linear,
+        )?;
+        let quadratic = Table::create_with_options(
+            num_rows,
+            &key_columns,
+            hashes.clone(),
+            0.5,
+            ProbingStrategy::Quadratic,
+        )?;
+        assert_eq!(linear.map_mod_bits, quadratic.map_mod_bits);
+        assert!(linear.stats().max_probe_length > 256);
+        assert!(quadratic.stats().max_probe_length <= 64);
+
+        // probing strategy is preserved after serialization
+        let mut table_data = vec![];
+        quadratic.write_to(&mut table_data)?;
+        let loaded = Table::read_from(Cursor::new(&table_data))?;
+        assert_eq!(loaded.probing, ProbingStrategy::Quadratic);
+
+        for table in [linear, loaded] {
+            let map_values = table.lookup_many(hashes.clone());
+            for (i, map_value) in map_values.into_iter().enumerate() {
+                assert!(map_value.is_single());
+                assert_eq!(map_value.get_single(), i as u32);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mapped_indices_capacity() -> Result<()> {
+        let num_rows = 10000;
+        let key_columns: Vec<ArrayRef> =
+            vec![Arc::new(Int64Array::from_iter_values(0..num_rows as i64))];
+
+        // (hashes, expected length of mapped indices)
+        let cases = [
+            ((0..num_rows as u32).map(|i| i + 1).collect::<Vec<_>>(), 0),
+            (
+                (0..num_rows as u32).map(|i| i / 2 + 1).collect(),
+                num_rows * 3 / 2,
+            ),
+            ((0..num_rows as u32).map(|i| i / 3 + 1).collect(), 13332),
+            (vec![1; num_rows], num_rows + 1),
+        ];
+        for (hashes, expected_len) in cases {
+            let table =
+                Table::craete_from_key_columns_and_hashes(num_rows, &key_columns, hashes.clone())?;
+            assert_eq!(table.mapped_indices.len(), expected_len);
+            assert_eq!(table.mapped_indices.capacity(), expected_len);
+
+            let mut found = vec![false; num_rows];
+            for map_value in table.lookup_many(hashes) {
+                if map_value.is_single() {
+                    found[map_value.get_single() as usize] = true;
+                } else {
+                    let start = map_value.0 as usize;
+                    let len = table.mapped_indices[start - 1] as usize;
+                    for &idx in &table.mapped_indices[start..][..len] {
+                        found[idx as usize] = true;
+                    }
+                }
+            }
+            assert!(found.into_iter().all(|found| found));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_hash_map_builder() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let batches = (0..5)
+            .map(|i| {
+                let num_rows = 100 + i * 37;
+                let a = Int64Array::from_iter((0..num_rows as i64).map(|j| {
+                    let v = (i as i64 * 31 + j * 7) % 150;
+                    (v % 13 != 0).then_some(v)
+                }));
+                let b = StringArray::from_iter_values((0..num_rows).map(|j| format!("s{}", j % 4)));
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(a), Arc::new(b)])
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let key_exprs: Vec<PhysicalExprRef> =
+            vec![Arc::new(Column::new("a", 0)), Arc::new(Column::new("b", 1))];
+
+        let mut builder = JoinHashMapBuilder::new(schema.clone(), key_exprs.clone());
+        for batch in &batches {
+            builder.add_batch(batch.clone())?;
+        }
+        builder.add_batch(RecordBatch::new_empty(schema.clone()))?;
+        assert_eq!(
+            builder.num_rows(),
+            batches.iter().map(|b| b.num_rows()).sum()
+        );
+        assert!(
+            builder.mem_used()
+                >= batches
+                    .iter()
+                    .map(|b| b.get_batch_mem_size())
+                    .sum::<usize>()
+        );
+        let built = builder.finish()?;
+
+        let data_batch = arrow::compute::concat_batches(&schema, &batches)?;
+        let expected = JoinHashMap::create_from_data_batch(data_batch, &key_exprs)?;
+        assert_eq!(built.stats(), expected.stats());
+        let hashes = join_create_hashes(built.data_batch().num_rows(), built.key_columns());
+        assert_eq!(
+            built.lookup_many(hashes.clone()),
+            expected.lookup_many(hashes)
+        );
+
+        // serialized hash map batches are identical, including table data
+        assert_eq!(
+            built.into_hash_map_batches()?,
+            expected.into_hash_map_batches()?
+        );
+
+        // empty input
+        let built = JoinHashMapBuilder::new(schema.clone(), key_exprs.clone()).finish()?;
+        assert!(built.is_empty());
+        assert_eq!(built.into_hash_map_batches()?[0].num_rows(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_mapped_indices() -> Result<()> {
+        let num_rows = 1000;
+        let key_columns: Vec<ArrayRef> =
+            vec![Arc::new(Int64Array::from_iter_values(0..num_rows as i64))];
+        let hashes = (0..num_rows as u32).map(|i| i / 3 + 1).collect::<Vec<_>>();
+        let serialize = |table: Table| -> Result<Vec<u8>> {
+            let mut data = vec![];
+            table.write_to(&mut data)?;
+            Ok(data)
+        };
+
+        // unreferenced trailing indices are truncated after loading
+        let mut table =
+            Table::craete_from_key_columns_and_hashes(num_rows, &key_columns, hashes.clone())?;
+        let expected_len = table.mapped_indices.len();
+        table.mapped_indices.extend([7, 8, 9]);
+        let loaded = Table::read_from(Cursor::new(&serialize(table)?))?;
+        assert_eq!(loaded.mapped_indices.len(), expected_len);
+        assert_eq!(
+            loaded.lookup_many(hashes.clone()),
+            Table::craete_from_key_columns_and_hashes(num_rows, &key_columns, hashes.clone())?
+                .lookup_many(hashes.clone()),
+        );
+
+        // ranges out of mapped indices are rejected
+        let mut table =
+            Table::craete_from_key_columns_and_hashes(num_rows, &key_columns, hashes.clone())?;
+        table.mapped_indices.truncate(expected_len - 1);
+        let err = Table::read_from(Cursor::new(&serialize(table)?)).err();
+        assert!(err.is_some_and(|err| err.to_string().contains("out of mapped indices")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matched_indices_with_colliding_hashes() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(vec![
+                Some(1),
+                Some(2),
+                None,
+                Some(1),
+                Some(3),
+            ]))],
+        )?;
+        let key_columns = batch.columns().to_vec();
+
+        // all keys share the same hash
+        let map = JoinHashMap::create_from_data_batch_and_hashes(
+            batch,
+            key_columns,
+            vec![0x80000001; 5],
+        )?;
+
+        let probed_keys: Vec<ArrayRef> =
+            vec![Arc::new(Int64Array::from(vec![Some(1), Some(3), Some(4)]))];
+        let eq = map.create_key_eq_comparator(&probed_keys, &[Collation::Binary])?;
+        let map_values = map.lookup_many(vec![0x80000001; 3]);
+        assert!(map_values.iter().all(|map_value| map_value.is_range()));
+
+        let matched = |probed_row: usize| {
+            map.matched_indices(&eq, probed_row, map_values[probed_row])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matched(0), vec![0, 3]);
+        assert_eq!(matched(1), vec![4]);
+        assert!(matched(2).is_empty());
+
+        // empty map value
+        let map_value = map.lookup_many(vec![0x80000002])[0];
+        assert!(map_value.is_empty());
+        assert_eq!(map.matched_indices(&eq, 0, map_value).count(), 0);
+
+        // limited enumeration stops once the remaining limit is reached
+        let mut produced = vec![];
+        let mut remaining = 2;
+        for (probed_row, &map_value) in map_values.iter().enumerate() {
+            map.for_each_match_limited(&eq, probed_row, map_value, &mut remaining, |map_idx| {
+                produced.push(map_idx);
+                ControlFlow::Continue(())
+            });
+        }
+        assert_eq!(produced, vec![0, 3]);
+        assert_eq!(remaining, 0);
+
+        // matches breaking the enumeration are not counted
+        let mut remaining = 2;
+        let num_produced =
+            map.for_each_match_limited(&eq, 0, map_values[0], &mut remaining, |map_idx| {
+                if map_idx == 0 {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            });
+        assert_eq!((num_produced, remaining), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_from_precomputed_hashes() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(vec![
+                Some(10),
+                Some(20),
+                None,
+                Some(10),
+            ]))],
+        )?;
+        let key_columns = batch.columns().to_vec();
+
+        // precomputed hashes may be zero or have the highest bit set
+        let precomputed_hashes = [0, 0x80000000, 7, 0];
+        let map = JoinHashMap::create_from_data_batch_and_precomputed_hashes(
+            batch.clone(),
+            key_columns.clone(),
+            &precomputed_hashes,
+        )?;
+
+        let probed_keys: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![
+            Some(10),
+            Some(20),
+            Some(30),
+        ]))];
+        let probed_hashes = join_hashes_from_precomputed(&[0, 0x80000000, 0]);
+        let eq = map.create_key_eq_comparator(&probed_keys, &[Collation::Binary])?;
+        let map_values = map.lookup_many(probed_hashes);
+        let matched = |probed_row: usize| {
+            map.matched_indices(&eq, probed_row, map_values[probed_row])
+                .collect::<Vec<_>>()
+        };
+        // hashes 0 and 0x80000000 are the same join hash, recheck by keys
+        assert_eq!(matched(0), vec![0, 3]);
+        assert_eq!(matched(1), vec![1]);
+        assert!(matched(2).is_empty());
+
+        // number of hashes must match number of rows
+        assert!(JoinHashMap::create_from_data_batch_and_precomputed_hashes(
+            batch.clone(),
+            key_columns.clone(),
+            &precomputed_hashes[..3],
+        )
+        .is_err());
+        assert!(JoinHashMap::create_from_data_batch_and_precomputed_hashes(
+            batch,
+            vec![key_columns[0].slice(0, 3)],
+            &precomputed_hashes,
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_with_collated_keys() -> Result<()> {
+        // build side keys are collated too, which materializes folded values
+        let build_schema = Arc::new(Schema::new(vec![Field::new("k", DataType::Utf8, true)]));
+        let build_batch = RecordBatch::try_new(
+            build_schema,
+            vec![Arc::new(StringArray::from(vec![
+                Some("aBc"),
+                Some("àÉî"),
+                Some("Οδυσσευς"),
+                None,
+            ]))],
+        )?;
+        let build_key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(CollatedExpr::new(
+            Arc::new(Column::new("k", 0)),
+            Collation::UnicodeCaseInsensitive,
+        ))];
+        let map = JoinHashMap::create_from_data_batch(build_batch, &build_key_exprs)?;
+
+        let probe_schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Utf8, true),
+            Field::new("i", DataType::Int64, false),
+        ]));
+        let probe_batch = RecordBatch::try_new(
+            probe_schema,
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("ABC"),
+                    Some("ÀÉÎ"),
+                    Some("ΟΔΥΣΣΕΥΣ"),
+                    None,
+                    Some("abd"),
+                ])),
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])),
+            ],
+        )?;
+        let probe_key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(CollatedExpr::new(
+            Arc::new(Column::new("k", 0)),
+            Collation::UnicodeCaseInsensitive,
+        ))];
+        let (probe_keys, probe_collations) = evaluate_probed_keys(&probe_batch, &probe_key_exprs)?;
+        assert_eq!(&probe_keys[0], probe_batch.column(0)); // not materialized
+        assert_eq!(probe_collations, vec![Collation::UnicodeCaseInsensitive]);
+
+        // hashes are the same as hashes of materialized lowered keys
+        let probe_hashes = join_create_hashes_with_collations(5, &probe_keys, &probe_collations);
+        let lowered_keys = vec![probe_key_exprs[0].evaluate(&probe_batch)?.into_array(5)?];
+        assert_eq!(probe_hashes, join_create_hashes(5, &lowered_keys));
+
+        let eq = EqComparator::try_new_with_collations(
+            &probe_keys,
+            map.key_columns(),
+            &probe_collations,
+        )?;
+        let map_values = map.lookup_many(probe_hashes);
+        for probe_row in 0..3 {
+            assert!(map_values[probe_row].is_single());
+            let build_row = map_values[probe_row].get_single() as usize;
+            assert!(eq.eq(probe_row, build_row));
+        }
+        assert!(map_values[4].is_empty());
+
+        // unsupported key types keep the materializing path
+        let probe_key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(CollatedExpr::new(
+            Arc::new(Column::new("i", 1)),
+            Collation::UnicodeCaseInsensitive,
+        ))];
+        let (probe_keys, probe_collations) = evaluate_probed_keys(&probe_batch, &probe_key_exprs)?;
+        assert_eq!(&probe_keys[0], probe_batch.column(1));
+        assert_eq!(probe_collations, vec![Collation::Binary]);
+        Ok(())
+    }
+}
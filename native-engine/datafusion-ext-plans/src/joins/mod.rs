@@ -22,12 +22,13 @@ use arrow::{
 use datafusion::{common::Result, physical_expr::PhysicalExprRef};
 use stream_cursor::StreamCursor;
 
-use crate::joins::join_utils::JoinType;
+use crate::{common::execution_context::ExecutionContext, joins::join_utils::JoinType};
 
 pub mod join_utils;
 
 // join implementations
 pub mod bhj;
+pub mod heavy_hitters;
 pub mod join_hash_map;
 pub mod smj;
 pub mod stream_cursor;
@@ -45,6 +46,19 @@ pub struct JoinParams {
     pub sort_options: Vec<SortOptions>,
     pub projection: JoinProjection,
     pub batch_size: usize,
+
+    /// limit pushed down from a parent LimitExec, only used by semi/anti
+    /// joins to stop probing once enough rows are produced
+    pub fetch: Option<usize>,
+}
+
+impl JoinParams {
+    /// binds partition-dependent key exprs to the partition of exec_ctx
+    pub fn bind_partition(mut self, exec_ctx: &ExecutionContext) -> Result<Self> {
+        self.left_keys = exec_ctx.bind_partition_exprs(&self.left_keys)?;
+        self.right_keys = exec_ctx.bind_partition_exprs(&self.right_keys)?;
+        Ok(self)
+    }
 }
 
 #[derive(Debug, Clone)]
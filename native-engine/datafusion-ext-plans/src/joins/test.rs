@@ -14,13 +14,13 @@
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Instant};
 
     use arrow::{
         self,
         array::*,
         compute::SortOptions,
-        datatypes::{DataType, Field, Schema, SchemaRef},
+        datatypes::{DataType, Field, Int32Type, Schema, SchemaRef},
         record_batch::RecordBatch,
     };
     use datafusion::{
@@ -31,23 +31,29 @@ mod tests {
         physical_plan::{common, joins::utils::*, memory::MemoryExec, ExecutionPlan},
         prelude::SessionContext,
     };
+    use datafusion_ext_commons::arrow::collation::Collation;
+    use datafusion_ext_exprs::collated::CollatedExpr;
     use TestType::*;
 
     use crate::{
         broadcast_join_build_hash_map_exec::BroadcastJoinBuildHashMapExec,
-        broadcast_join_exec::BroadcastJoinExec,
+        broadcast_join_exec::{push_down_limit_to_join, BroadcastJoinExec},
         joins::join_utils::{JoinType, JoinType::*},
+        limit_exec::LimitExec,
         memmgr::MemManager,
+        skew_aware_hash_join_exec::SkewAwareHashJoinExec,
         sort_merge_join_exec::SortMergeJoinExec,
     };
 
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Debug)]
     enum TestType {
         SMJ,
         BHJLeftProbed,
         BHJRightProbed,
         SHJLeftProbed,
         SHJRightProbed,
+        SkewHJLeftProbed,
+        SkewHJRightProbed,
     }
 
     fn columns(schema: &Schema) -> Vec<String> {
@@ -190,9 +196,23 @@ mod tests {
         MemManager::init(1000000);
         let session_ctx = SessionContext::new();
         let task_ctx = session_ctx.task_ctx();
+        let join = create_join(test_type, left, right, on, join_type)?;
+        let columns = columns(&join.schema());
+        let stream = join.execute(0, task_ctx)?;
+        let batches = common::collect(stream).await?;
+        Ok((columns, batches))
+    }
+
+    fn create_join(
+        test_type: TestType,
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: JoinOn,
+        join_type: JoinType,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
         let schema = build_join_schema_for_test(&left.schema(), &right.schema(), join_type)?;
 
-        let join: Arc<dyn ExecutionPlan> = match test_type {
+        Ok(match test_type {
             SMJ => {
                 let sort_options = vec![SortOptions::default(); on.len()];
                 Arc::new(SortMergeJoinExec::try_new(
@@ -256,19 +276,36 @@ mod tests {
                 false,
                 None,
             )?),
-        };
-        let columns = columns(&join.schema());
-        let stream = join.execute(0, task_ctx)?;
-        let batches = common::collect(stream).await?;
-        Ok((columns, batches))
+            // keys appearing in more than half of build rows are heavy hitters
+            SkewHJLeftProbed => Arc::new(SkewAwareHashJoinExec::try_new(
+                schema,
+                left,
+                right,
+                on,
+                join_type,
+                JoinSide::Right,
+                0.5,
+            )?),
+            SkewHJRightProbed => Arc::new(SkewAwareHashJoinExec::try_new(
+                schema,
+                left,
+                right,
+                on,
+                join_type,
+                JoinSide::Left,
+                0.5,
+            )?),
+        })
     }
 
-    const ALL_TEST_TYPE: [TestType; 5] = [
+    const ALL_TEST_TYPE: [TestType; 7] = [
         SMJ,
         BHJLeftProbed,
         BHJRightProbed,
         SHJLeftProbed,
         SHJRightProbed,
+        SkewHJLeftProbed,
+        SkewHJRightProbed,
     ];
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -981,4 +1018,154 @@ mod tests {
         }
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn join_collated_string_keys() -> Result<()> {
+        let build_string_table = |a: (&str, Vec<i32>), b: (&str, Vec<&str>)| {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new(a.0, DataType::Int32, false),
+                Field::new(b.0, DataType::Utf8, false),
+            ]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from(a.1)),
+                    Arc::new(StringArray::from(b.1)),
+                ],
+            )
+            .unwrap();
+            Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+        };
+        for test_type in ALL_TEST_TYPE {
+            // mixed cases on both sides, sorted by folded values for SMJ
+            let left = build_string_table(("a1", vec![1, 2, 3]), ("b1", vec!["abc", "xyz", "ÀBD"]));
+            let right =
+                build_string_table(("a2", vec![10, 20, 30]), ("b2", vec!["ABC", "xz", "àbd"]));
+            let on: JoinOn = vec![(
+                Arc::new(CollatedExpr::new(
+                    Arc::new(Column::new_with_schema("b1", &left.schema())?),
+                    Collation::UnicodeCaseInsensitive,
+                )),
+                Arc::new(CollatedExpr::new(
+                    Arc::new(Column::new_with_schema("b2", &right.schema())?),
+                    Collation::UnicodeCaseInsensitive,
+                )),
+            )];
+
+            let (_, batches) = join_collect(test_type, left, right, on, Inner).await?;
+            let mut joined = batches
+                .iter()
+                .flat_map(|batch| {
+                    let a1 = batch.column(0).as_primitive::<Int32Type>();
+                    let a2 = batch.column(2).as_primitive::<Int32Type>();
+                    (0..batch.num_rows())
+                        .map(|i| (a1.value(i), a2.value(i)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            joined.sort();
+            assert_eq!(joined, vec![(1, 10), (3, 30)]);
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn join_16_byte_hash_keys_bench() -> Result<()> {
+        // 16-byte pre-hashed ids, big-endian encoded so that they are also
+        // sorted for SMJ
+        let num_rows = 100000;
+        let build_key_table = |name: &str| {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                name,
+                DataType::FixedSizeBinary(16),
+                false,
+            )]));
+            let keys = FixedSizeBinaryArray::try_from_iter(
+                (0..num_rows as u128).map(|i| (i * 0x9e3779b97f4a7c15).to_be_bytes()),
+            )
+            .unwrap();
+            let batches = (0..num_rows)
+                .step_by(10000)
+                .map(|offset| {
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(keys.slice(offset, 10000))])
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            Arc::new(MemoryExec::try_new(&[batches], schema, None).unwrap())
+        };
+        for test_type in ALL_TEST_TYPE {
+            let left = build_key_table("k1");
+            let right = build_key_table("k2");
+            let on: JoinOn = vec![(
+                Arc::new(Column::new_with_schema("k1", &left.schema())?),
+                Arc::new(Column::new_with_schema("k2", &right.schema())?),
+            )];
+
+            let time_start = Instant::now();
+            let (_, batches) = join_collect(test_type, left, right, on, Inner).await?;
+            eprintln!(
+                "join_16_byte_hash_keys_time({test_type:?}): {:?}",
+                time_start.elapsed()
+            );
+            let num_joined_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+            assert_eq!(num_joined_rows, num_rows);
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn join_semi_anti_with_pushed_down_limit() -> Result<()> {
+        MemManager::init(1000000);
+        let session_ctx = SessionContext::new();
+        for test_type in ALL_TEST_TYPE {
+            for (join_type, expected_a1) in [
+                (LeftSemi, vec![1, 2, 3, 4, 5]),
+                (LeftAnti, vec![6, 7, 8, 9]),
+            ] {
+                let left = build_table(
+                    ("a1", &vec![1, 2, 3, 4, 5, 6, 7, 8, 9]),
+                    ("b1", &vec![4, 5, 5, 4, 5, 7, 7, 8, 9]),
+                    ("c1", &vec![0; 9]),
+                );
+                let right = build_table(
+                    ("a2", &vec![10, 20, 30, 40]),
+                    ("b1", &vec![4, 5, 5, 6]),
+                    ("c2", &vec![0; 4]),
+                );
+                let on: JoinOn = vec![(
+                    Arc::new(Column::new_with_schema("b1", &left.schema())?),
+                    Arc::new(Column::new_with_schema("b1", &right.schema())?),
+                )];
+                let join = create_join(test_type, left, right, on, join_type)?;
+                let join = push_down_limit_to_join(join, 2)?;
+                let limit = Arc::new(LimitExec::new(join.clone(), 2));
+
+                let stream = limit.execute(0, session_ctx.task_ctx())?;
+                let batches = common::collect(stream).await?;
+                let a1 = batches
+                    .iter()
+                    .map(|batch| batch.column(0).as_primitive::<Int32Type>())
+                    .flat_map(|a1| a1.values().to_vec())
+                    .collect::<Vec<_>>();
+                assert_eq!(a1.len(), 2);
+                assert!(a1.iter().all(|v| expected_a1.contains(v)));
+
+                // limited semi joins stop once enough rows are produced
+                if join_type == LeftSemi {
+                    let stream = join.execute(0, session_ctx.task_ctx())?;
+                    let num_rows: usize = common::collect(stream)
+                        .await?
+                        .iter()
+                        .map(|batch| batch.num_rows())
+                        .sum();
+                    let expected_num_rows = match test_type {
+                        SMJ | SkewHJLeftProbed | SkewHJRightProbed => expected_a1.len(),
+                        _ => 2,
+                    };
+                    assert_eq!(num_rows, expected_num_rows);
+                }
+            }
+        }
+        Ok(())
+    }
 }
@@ -34,6 +34,10 @@ pub mod generate_exec;
 pub mod ipc_reader_exec;
 pub mod ipc_writer_exec;
 pub mod limit_exec;
+pub mod native_distinct_agg_exec;
+pub mod native_in_subquery_exec;
+pub mod native_range_exec;
+pub mod native_scalar_subquery_exec;
 pub mod orc_exec;
 pub mod parquet_exec;
 pub mod parquet_sink_exec;
@@ -41,9 +45,11 @@ pub mod project_exec;
 pub mod rename_columns_exec;
 pub mod rss_shuffle_writer_exec;
 pub mod shuffle_writer_exec;
+pub mod skew_aware_hash_join_exec;
 pub mod sort_exec;
 pub mod sort_merge_join_exec;
 pub mod union_exec;
+pub mod validate_output_exec;
 pub mod window_exec;
 
 // memory management
@@ -57,3 +63,6 @@ pub mod joins;
 mod scan;
 pub mod shuffle;
 pub mod window;
+
+#[cfg(test)]
+mod pipeline_test;
@@ -8,7 +8,6 @@ use arrow::datatypes::SchemaRef;
 use datafusion::{
     common::Result,
     execution::context::TaskContext,
-    physical_expr::EquivalenceProperties,
     physical_plan::{
         metrics::ExecutionPlanMetricsSet, DisplayAs, DisplayFormatType, ExecutionMode,
         ExecutionPlan, ExecutionPlanProperties, PlanProperties, SendableRecordBatchStream,
@@ -61,7 +60,7 @@ impl ExecutionPlan for LimitExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                self.input.equivalence_properties().clone(),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -109,15 +108,23 @@ fn execute_limit(
         .clone()
         .output_with_sender("Limit", move |sender| async move {
             let mut remaining = limit;
+            let mut last_batch = None;
             while remaining > 0
-                && let Some(mut batch) = input.next().await.transpose()?
+                && let Some(batch) = input.next().await.transpose()?
             {
-                if remaining < batch.num_rows() as u64 {
-                    batch = batch.slice(0, remaining as usize);
-                    remaining = 0;
-                } else {
-                    remaining -= batch.num_rows() as u64;
+                if remaining <= batch.num_rows() as u64 {
+                    last_batch = Some(batch.slice(0, remaining as usize));
+                    break;
                 }
+                remaining -= batch.num_rows() as u64;
+                exec_ctx.baseline_metrics().record_output(batch.num_rows());
+                sender.send(batch).await;
+            }
+
+            // limit reached, drop the child stream without polling it again,
+            // before waiting for the last batch to be consumed
+            drop(input);
+            if let Some(batch) = last_batch {
                 exec_ctx.baseline_metrics().record_output(batch.num_rows());
                 sender.send(batch).await;
             }
@@ -127,7 +134,10 @@ fn execute_limit(
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+        Arc,
+    };
 
     use arrow::{
         array::Int32Array,
@@ -137,11 +147,18 @@ mod test {
     use datafusion::{
         assert_batches_eq,
         common::{stats::Precision, Result},
-        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        physical_plan::{
+            common, memory::MemoryExec, stream::RecordBatchStreamAdapter, ExecutionPlan,
+            SendableRecordBatchStream,
+        },
         prelude::SessionContext,
     };
+    use futures::StreamExt;
 
-    use crate::{limit_exec::LimitExec, memmgr::MemManager};
+    use crate::{
+        common::stream_exec::create_record_batch_stream_exec, limit_exec::LimitExec,
+        memmgr::MemManager,
+    };
 
     fn build_table_i32(
         a: (&str, &Vec<i32>),
@@ -202,4 +219,66 @@ mod test {
         assert_eq!(row_count, Precision::Exact(2));
         Ok(())
     }
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, SeqCst);
+        }
+    }
+
+    // endless stream of 10-row batches, counting polled batches and flagging
+    // when it is dropped
+    fn endless_input(
+        num_polled: Arc<AtomicUsize>,
+        dropped: Arc<AtomicBool>,
+    ) -> SendableRecordBatchStream {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let drop_flag = DropFlag(dropped);
+        Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            futures::stream::repeat(batch).map(move |batch| {
+                let _ = &drop_flag;
+                num_polled.fetch_add(1, SeqCst);
+                Ok(batch)
+            }),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_limit_exec_drops_child_once_limit_reached() -> Result<()> {
+        MemManager::init(10000);
+        let session_ctx = SessionContext::new();
+
+        // (limit, expected output batch sizes)
+        for (limit, expected) in [
+            (25, vec![10, 10, 5]),
+            (20, vec![10, 10]),
+            (3, vec![3]),
+            (0, vec![]),
+        ] {
+            let num_polled = Arc::new(AtomicUsize::new(0));
+            let dropped = Arc::new(AtomicBool::new(false));
+            let input = endless_input(num_polled.clone(), dropped.clone());
+            let limit_exec = LimitExec::new(create_record_batch_stream_exec(input, 0)?, limit);
+            let mut output = limit_exec.execute(0, session_ctx.task_ctx())?;
+
+            for &expected_num_rows in &expected {
+                let batch = output.next().await.unwrap()?;
+                assert_eq!(batch.num_rows(), expected_num_rows);
+            }
+
+            // the child is dropped once the limit is reached, even when the
+            // limit is exactly at a batch boundary
+            assert!(output.next().await.is_none());
+            assert!(dropped.load(SeqCst));
+            assert_eq!(num_polled.load(SeqCst), expected.len());
+        }
+        Ok(())
+    }
 }
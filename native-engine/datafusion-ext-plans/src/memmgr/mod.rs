@@ -413,7 +413,10 @@ async fn update_consumer_mem_used_with_custom_updater(
             ByteSize(mem_jvm_direct_used as u64),
             ByteSize(mem_proc_total_used as u64),
         );
-        consumer.spill().await?;
+        consumer
+            .spill()
+            .await
+            .map_err(|err| err.context(format!("mem manager spilling {consumer_name}")))?;
         return Ok(());
     }
     Ok(())
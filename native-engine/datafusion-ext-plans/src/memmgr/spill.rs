@@ -17,18 +17,28 @@ use std::{
     fs,
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Cursor, Read, Seek, Write},
+    os::unix::fs::FileExt,
     sync::Arc,
     time::Duration,
 };
 
 use blaze_jni_bridge::{
-    conf, conf::StringConf, is_jni_bridge_inited, jni_bridge::LocalRef, jni_call, jni_call_static,
-    jni_get_string, jni_new_direct_byte_buffer, jni_new_global_ref,
+    conf,
+    conf::{BooleanConf, StringConf},
+    is_jni_bridge_inited,
+    jni_bridge::LocalRef,
+    jni_call, jni_call_static, jni_get_string, jni_new_direct_byte_buffer, jni_new_global_ref,
 };
-use datafusion::{common::Result, parquet::file::reader::Length, physical_plan::metrics::Time};
+use datafusion::{
+    common::{DataFusionError, Result},
+    parquet::file::reader::Length,
+    physical_plan::metrics::Time,
+};
+use datafusion_ext_commons::error::BlazeError;
 use jni::{objects::GlobalRef, sys::jlong};
 use log::warn;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 
 use crate::{
     common::ipc_compression::{IoCompressionReader, IoCompressionWriter},
@@ -87,18 +97,32 @@ fn spill_compression_codec() -> &'static str {
         .as_str()
 }
 
+/// whether batches written to spills are checksummed and verified
+pub fn spill_checksum_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED.get_or_init(|| {
+        is_jni_bridge_inited() && conf::SPILL_CHECKSUM_ENABLE.value().unwrap_or(false)
+    })
+}
+
 pub fn try_new_spill(spill_metrics: &SpillMetrics) -> Result<Box<dyn Spill>> {
+    match try_new_on_heap_spill(spill_metrics)? {
+        Some(spill) => Ok(spill),
+        None => Ok(Box::new(FileSpill::try_new(spill_metrics)?)),
+    }
+}
+
+/// creates an on-heap spill if on-heap memory is available in executor side,
+/// otherwise returns None and file spills should be used
+pub fn try_new_on_heap_spill(spill_metrics: &SpillMetrics) -> Result<Option<Box<dyn Spill>>> {
     if !is_jni_bridge_inited() || jni_call_static!(JniBridge.isDriverSide() -> bool)? {
-        Ok(Box::new(FileSpill::try_new(spill_metrics)?))
-    } else {
-        // use on heap spill if on-heap memory is available, otherwise use file spill
-        let hsm = jni_call_static!(JniBridge.getTaskOnHeapSpillManager() -> JObject)?;
-        if jni_call!(BlazeOnHeapSpillManager(hsm.as_obj()).isOnHeapAvailable() -> bool)? {
-            Ok(Box::new(OnHeapSpill::try_new(hsm, spill_metrics)?))
-        } else {
-            Ok(Box::new(FileSpill::try_new(spill_metrics)?))
-        }
+        return Ok(None);
     }
+    let hsm = jni_call_static!(JniBridge.getTaskOnHeapSpillManager() -> JObject)?;
+    if jni_call!(BlazeOnHeapSpillManager(hsm.as_obj()).isOnHeapAvailable() -> bool)? {
+        return Ok(Some(Box::new(OnHeapSpill::try_new(hsm, spill_metrics)?)));
+    }
+    Ok(None)
 }
 
 /// A spill structure which write data to temporary files
@@ -106,23 +130,27 @@ pub fn try_new_spill(spill_metrics: &SpillMetrics) -> Result<Box<dyn Spill>> {
 struct FileSpill(File, SpillMetrics, Option<String>);
 impl FileSpill {
     fn try_new(spill_metrics: &SpillMetrics) -> Result<Self> {
-        if is_jni_bridge_inited() {
-            let file_name = jni_get_string!(
-                jni_call_static!(JniBridge.getDirectWriteSpillToDiskFile() -> JObject)?
-                    .as_obj()
-                    .into()
-            )?;
-            let file = OpenOptions::new() // create file and open under rw mode
-                .create(true)
-                .truncate(true)
-                .write(true)
-                .read(true)
-                .open(&file_name)?;
-            Ok(Self(file, spill_metrics.clone(), Some(file_name)))
-        } else {
-            let file = tempfile::tempfile()?;
-            Ok(Self(file, spill_metrics.clone(), None))
-        }
+        let open_file = || -> Result<(File, Option<String>)> {
+            if is_jni_bridge_inited() {
+                let file_name = jni_get_string!(
+                    jni_call_static!(JniBridge.getDirectWriteSpillToDiskFile() -> JObject)?
+                        .as_obj()
+                        .into()
+                )?;
+                let file = OpenOptions::new() // create file and open under rw mode
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .read(true)
+                    .open(&file_name)?;
+                Ok((file, Some(file_name)))
+            } else {
+                Ok((tempfile::tempfile()?, None))
+            }
+        };
+        let (file, file_name) =
+            open_file().map_err(|err| DataFusionError::from(BlazeError::SpillIo(err)))?;
+        Ok(Self(file, spill_metrics.clone(), file_name))
     }
 }
 
@@ -177,6 +205,165 @@ impl Drop for FileSpill {
     }
 }
 
+/// A spill file shared by successive spills of an operator. each spill is
+/// appended to the end of the file as a run and indexed by its run index,
+/// so that heavy-spill operators do not create a new file for every spill.
+#[derive(Clone)]
+pub struct AppendableFileSpill(Arc<Mutex<AppendableFileSpillState>>);
+
+struct AppendableFileSpillState {
+    file_spill: FileSpill,
+    len: u64,
+    runs: Vec<(u64, u64)>, // (offset, len) of each run
+}
+
+impl AppendableFileSpill {
+    pub fn try_new(spill_metrics: &SpillMetrics) -> Result<Self> {
+        Ok(Self(Arc::new(Mutex::new(AppendableFileSpillState {
+            file_spill: FileSpill::try_new(spill_metrics)?,
+            len: 0,
+            runs: vec![],
+        }))))
+    }
+
+    /// starts a new run at the end of the file. runs are written one at a
+    /// time, writing to a run after a later run is started is an error
+    pub fn new_run(&self) -> Box<dyn Spill> {
+        let mut state = self.0.lock();
+        let offset = state.len;
+        state.runs.push((offset, 0));
+        Box::new(SpillRun {
+            spill_file: self.clone(),
+            run_idx: state.runs.len() - 1,
+        })
+    }
+
+    pub fn num_runs(&self) -> usize {
+        self.0.lock().runs.len()
+    }
+
+    /// returns the run with the specified run index
+    pub fn run(&self, run_idx: usize) -> Box<dyn Spill> {
+        assert!(run_idx < self.num_runs(), "run index out of bounds");
+        Box::new(SpillRun {
+            spill_file: self.clone(),
+            run_idx,
+        })
+    }
+
+    pub fn runs(&self) -> Vec<Box<dyn Spill>> {
+        (0..self.num_runs())
+            .map(|run_idx| self.run(run_idx))
+            .collect()
+    }
+}
+
+/// A run of AppendableFileSpill. readers of different runs read the shared
+/// file at their own positions, so they can be used concurrently
+struct SpillRun {
+    spill_file: AppendableFileSpill,
+    run_idx: usize,
+}
+
+impl Spill for SpillRun {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_buf_reader<'a>(&'a self) -> BufReader<Box<dyn Read + Send + 'a>> {
+        let state = self.spill_file.0.lock();
+        let (offset, len) = state.runs[self.run_idx];
+        let file_spill = &state.file_spill;
+        let file = file_spill
+            .0
+            .try_clone()
+            .expect("File.try_clone() returns error");
+        BufReader::with_capacity(
+            65536,
+            Box::new(SpillRunReader {
+                file,
+                offset,
+                len,
+                pos: 0,
+                io_time: file_spill.1.mem_spill_iotime.clone(),
+            }),
+        )
+    }
+
+    fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>> {
+        BufWriter::with_capacity(
+            65536,
+            Box::new(SpillRunWriter {
+                spill_file: self.spill_file.clone(),
+                run_idx: self.run_idx,
+            }),
+        )
+    }
+}
+
+/// reads a run with positional reads on its own file handle, so readers do not
+/// hold the lock of the spill file or move the shared file position
+struct SpillRunReader {
+    file: File,
+    offset: u64,
+    len: u64,
+    pos: u64,
+    io_time: Time,
+}
+
+impl Read for SpillRunReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read_len = buf.len().min((self.len - self.pos) as usize);
+        if read_len == 0 {
+            return Ok(0);
+        }
+        let _timer = self.io_time.timer();
+        let read_len = self
+            .file
+            .read_at(&mut buf[..read_len], self.offset + self.pos)
+            .map_err(spill_io_error)?;
+        self.pos += read_len as u64;
+        Ok(read_len)
+    }
+}
+
+struct SpillRunWriter {
+    spill_file: AppendableFileSpill,
+    run_idx: usize,
+}
+
+impl Write for SpillRunWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.spill_file.0.lock();
+        let (offset, len) = state.runs[self.run_idx];
+        if offset + len != state.len {
+            return Err(spill_io_error(std::io::Error::other(format!(
+                "cannot append to run {} of spill file, a later run is started",
+                self.run_idx,
+            ))));
+        }
+        let file_spill = &state.file_spill;
+        let timer = file_spill.1.mem_spill_iotime.timer();
+        file_spill
+            .0
+            .write_all_at(buf, offset + len)
+            .map_err(spill_io_error)?;
+        drop(timer);
+
+        state.len += buf.len() as u64;
+        state.runs[self.run_idx].1 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 /// A spill structure which cooperates with BlazeOnHeapSpillManager
 /// used in executor side
 struct OnHeapSpill(Arc<RawOnHeapSpill>, SpillMetrics);
@@ -284,22 +471,29 @@ struct IoTimeWriteWrapper<W: Write>(W, Time);
 impl<R: Read> Read for IoTimeReadWrapper<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let _timer = self.1.timer();
-        self.0.read(buf)
+        self.0.read(buf).map_err(spill_io_error)
     }
 }
 
 impl<W: Write> Write for IoTimeWriteWrapper<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let _timer = self.1.timer();
-        self.0.write(buf)
+        self.0.write(buf).map_err(spill_io_error)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         let _timer = self.1.timer();
-        self.0.flush()
+        self.0.flush().map_err(spill_io_error)
     }
 }
 
+// classifies io errors of spill files, the kind is kept for callers
+// retrying on interrupted errors
+fn spill_io_error(err: std::io::Error) -> std::io::Error {
+    let kind = err.kind();
+    std::io::Error::new(kind, BlazeError::SpillIo(DataFusionError::IoError(err)))
+}
+
 pub struct OwnedSpillBufReader<'a> {
     spill: Box<dyn Spill>,
     buf_reader: BufReader<Box<dyn Read + Send + 'a>>,
@@ -326,3 +520,138 @@ impl<'a> OwnedSpillBufReader<'a> {
         &mut self.buf_reader
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        sync::{Arc, Weak},
+    };
+
+    use async_trait::async_trait;
+    use datafusion::{common::Result, physical_plan::metrics::ExecutionPlanMetricsSet};
+    use datafusion_ext_commons::error::BlazeError;
+
+    use crate::memmgr::{
+        metrics::SpillMetrics, spill::AppendableFileSpill, MemConsumer, MemConsumerInfo, MemManager,
+    };
+
+    #[test]
+    fn test_appendable_file_spill() -> Result<()> {
+        let spill_metrics = SpillMetrics::new(&ExecutionPlanMetricsSet::new(), 0);
+        let spill_file = AppendableFileSpill::try_new(&spill_metrics)?;
+
+        // append runs of different sizes
+        let run_data = (0..3)
+            .map(|run_idx| {
+                (0..100000 * (run_idx + 1))
+                    .map(|i| (i * 7 + run_idx) as u8)
+                    .collect::<Vec<u8>>()
+            })
+            .collect::<Vec<_>>();
+        let mut runs = vec![];
+        for data in &run_data {
+            let mut run = spill_file.new_run();
+            let mut writer = run.get_compressed_writer();
+            writer.write_all(data)?;
+            writer.finish()?;
+            runs.push(run);
+        }
+        assert_eq!(spill_file.num_runs(), 3);
+
+        // read all runs concurrently
+        let mut readers = runs
+            .iter()
+            .map(|run| run.get_compressed_reader())
+            .collect::<Vec<_>>();
+        let mut read_data = vec![vec![]; 3];
+        let mut buf = [0u8; 1000];
+        let mut num_finished = 0;
+        while num_finished < 3 {
+            num_finished = 0;
+            for (reader, data) in readers.iter_mut().zip(&mut read_data) {
+                let n = reader.read(&mut buf)?;
+                data.extend_from_slice(&buf[..n]);
+                num_finished += (n == 0) as usize;
+            }
+        }
+        assert_eq!(read_data, run_data);
+
+        // runs enumerated from the run index
+        for (run, data) in spill_file.runs().iter().zip(&run_data) {
+            let mut read = vec![];
+            run.get_compressed_reader().read_to_end(&mut read)?;
+            assert_eq!(&read, data);
+        }
+
+        // writing to an earlier run is not allowed
+        let mut writer = runs[0].get_buf_writer();
+        writer.write_all(&[1, 2, 3])?;
+        assert!(writer.flush().is_err());
+        Ok(())
+    }
+
+    /// spills by writing to an earlier run of a spill file, which fails
+    #[derive(Default)]
+    struct FailingSpillConsumer {
+        mem_consumer_info: Option<Weak<MemConsumerInfo>>,
+    }
+
+    #[async_trait]
+    impl MemConsumer for FailingSpillConsumer {
+        fn name(&self) -> &str {
+            "FailingSpillConsumer"
+        }
+
+        fn set_consumer_info(&mut self, consumer_info: Weak<MemConsumerInfo>) {
+            self.mem_consumer_info = Some(consumer_info);
+        }
+
+        fn get_consumer_info(&self) -> &Weak<MemConsumerInfo> {
+            self.mem_consumer_info
+                .as_ref()
+                .expect("consumer info not set")
+        }
+
+        async fn spill(&self) -> Result<()> {
+            let spill_metrics = SpillMetrics::new(&ExecutionPlanMetricsSet::new(), 0);
+            let spill_file = AppendableFileSpill::try_new(&spill_metrics)?;
+            let mut earlier_run = spill_file.new_run();
+            let mut later_run = spill_file.new_run();
+
+            let mut writer = later_run.get_buf_writer();
+            writer.write_all(&[1, 2, 3])?;
+            writer.flush()?;
+            drop(writer);
+
+            let mut writer = earlier_run.get_buf_writer();
+            writer.write_all(&[4, 5, 6])?;
+            writer.flush()?;
+            Ok(())
+        }
+    }
+
+    impl Drop for FailingSpillConsumer {
+        fn drop(&mut self) {
+            MemManager::deregister_consumer(self);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spill_io_error_through_mem_manager() -> Result<()> {
+        MemManager::init(10000);
+        let consumer = Arc::new(FailingSpillConsumer::default());
+        MemManager::register_consumer(consumer.clone(), true);
+
+        // io errors of spill files are not reported as out of memory by the
+        // mem manager
+        let err = consumer.force_spill().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("mem manager spilling FailingSpillConsumer"),
+            "{err}"
+        );
+        assert!(matches!(BlazeError::classify(err), BlazeError::SpillIo(_)));
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::HashSet, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, BooleanArray},
+    compute::filter_record_batch,
+    datatypes::{DataType, SchemaRef},
+    row::{RowConverter, SortField},
+};
+use datafusion::{
+    common::{cast::as_boolean_array, Result, Statistics},
+    execution::context::TaskContext,
+    physical_expr::PhysicalExprRef,
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::{df_execution_err, suggested_batch_mem_size};
+use futures::StreamExt;
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+
+use crate::common::execution_context::ExecutionContext;
+
+/// pre-deduplicates the input of the first aggregation of distinct aggregates
+/// (like the expanded rows of spark's RewriteDistinctAggregates). rows matching
+/// `dedup_predicate` are dropped if a row with the same keys has been seen in
+/// this partition, other rows (inputs of non-distinct aggregates) pass through.
+///
+/// seen keys are forgotten once they exceed a memory budget, so duplicates are
+/// only dropped on a best-effort basis and the following aggregation must still
+/// group by the keys.
+#[derive(Debug)]
+pub struct NativeDistinctAggExec {
+    input: Arc<dyn ExecutionPlan>,
+    key_exprs: Vec<PhysicalExprRef>,
+    dedup_predicate: PhysicalExprRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl NativeDistinctAggExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        key_exprs: Vec<PhysicalExprRef>,
+        dedup_predicate: PhysicalExprRef,
+    ) -> Result<Self> {
+        if key_exprs.is_empty() {
+            return df_execution_err!("NativeDistinctAggExec requires at least one key");
+        }
+        if dedup_predicate.data_type(&input.schema())? != DataType::Boolean {
+            return df_execution_err!("NativeDistinctAggExec predicate must return boolean values");
+        }
+        Ok(Self {
+            input,
+            key_exprs,
+            dedup_predicate,
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+}
+
+impl DisplayAs for NativeDistinctAggExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "NativeDistinctAggExec [keys=({}), predicate={}]",
+            self.key_exprs.iter().map(|e| format!("{e}")).join(", "),
+            self.dedup_predicate,
+        )
+    }
+}
+
+impl ExecutionPlan for NativeDistinctAggExec {
+    fn name(&self) -> &str {
+        "NativeDistinctAggExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                self.input.equivalence_properties().clone(),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            self.key_exprs.clone(),
+            self.dedup_predicate.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let key_exprs = exec_ctx.bind_partition_exprs(&self.key_exprs)?;
+        let dedup_predicate = exec_ctx.bind_partition(&self.dedup_predicate)?;
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        let output = execute_distinct_agg(input, key_exprs, dedup_predicate, exec_ctx.clone())?;
+        Ok(exec_ctx.coalesce_with_default_batch_size(output))
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+fn execute_distinct_agg(
+    mut input: SendableRecordBatchStream,
+    key_exprs: Vec<PhysicalExprRef>,
+    dedup_predicate: PhysicalExprRef,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    let input_schema = input.schema();
+    let row_converter = RowConverter::new(
+        key_exprs
+            .iter()
+            .map(|expr| Ok(SortField::new(expr.data_type(&input_schema)?)))
+            .collect::<Result<_>>()?,
+    )?;
+    let num_dropped_rows = exec_ctx.register_counter_metric("num_dropped_rows");
+
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("DistinctAgg", move |sender| async move {
+            sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+            let mut seen_keys = SeenKeys::new(suggested_batch_mem_size());
+
+            while let Some(batch) = input.next().await.transpose()? {
+                let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                let num_rows = batch.num_rows();
+                let dedup = dedup_predicate.evaluate(&batch)?.into_array(num_rows)?;
+                let dedup = as_boolean_array(&dedup)?;
+                let keys: Vec<ArrayRef> = key_exprs
+                    .iter()
+                    .map(|expr| expr.evaluate(&batch)?.into_array(num_rows))
+                    .collect::<Result<_>>()?;
+                let key_rows = row_converter.convert_columns(&keys)?;
+
+                // rows with null predicates are passed through
+                let retained: BooleanArray = (0..num_rows)
+                    .map(|i| {
+                        let is_dedup = dedup.is_valid(i) && dedup.value(i);
+                        Some(!is_dedup || seen_keys.insert(key_rows.row(i).as_ref()))
+                    })
+                    .collect();
+                let retained_batch = filter_record_batch(&batch, &retained)?;
+                num_dropped_rows.add(num_rows - retained_batch.num_rows());
+
+                exec_ctx
+                    .baseline_metrics()
+                    .record_output(retained_batch.num_rows());
+                sender.send(retained_batch).await;
+            }
+            Ok(())
+        }))
+}
+
+/// set of seen keys encoded in the row format, cleared when its memory usage
+/// exceeds the budget
+struct SeenKeys {
+    keys: HashSet<Box<[u8]>>,
+    mem_used: usize,
+    mem_budget: usize,
+}
+
+impl SeenKeys {
+    fn new(mem_budget: usize) -> Self {
+        Self {
+            keys: HashSet::new(),
+            mem_used: 0,
+            mem_budget,
+        }
+    }
+
+    /// returns true if the key has not been seen
+    fn insert(&mut self, key: &[u8]) -> bool {
+        if self.keys.contains(key) {
+            return false;
+        }
+        if self.mem_used >= self.mem_budget {
+            self.keys.clear();
+            self.mem_used = 0;
+        }
+        self.mem_used += key.len() + size_of::<Box<[u8]>>() + size_of::<u64>();
+        self.keys.insert(Box::from(key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, StringArray},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        assert_batches_sorted_eq,
+        common::{Result, ScalarValue},
+        logical_expr::Operator,
+        physical_expr::expressions::{lit, BinaryExpr, Column},
+        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        memmgr::MemManager,
+        native_distinct_agg_exec::{NativeDistinctAggExec, SeenKeys},
+    };
+
+    #[test]
+    fn test_seen_keys_forgotten_over_budget() {
+        let mut seen_keys = SeenKeys::new(100);
+        assert!(seen_keys.insert(b"a"));
+        assert!(!seen_keys.insert(b"a"));
+        for i in 0..100 {
+            seen_keys.insert(format!("key-{i}").as_bytes());
+        }
+        assert!(seen_keys.mem_used <= 100 + 64);
+        assert!(seen_keys.insert(b"a"));
+    }
+
+    #[tokio::test]
+    async fn test_native_distinct_agg_exec() -> Result<()> {
+        MemManager::init(10000);
+
+        // expanded rows of count(distinct a), count(distinct b), sum(c), where
+        // gid=0 rows are the inputs of sum(c)
+        let batch = RecordBatch::try_from_iter_with_nullable(vec![
+            (
+                "a",
+                Arc::new(Int32Array::from(vec![
+                    Some(1),
+                    Some(1),
+                    None,
+                    None,
+                    Some(1),
+                    None,
+                    None,
+                ])) as ArrayRef,
+                true,
+            ),
+            (
+                "b",
+                Arc::new(StringArray::from(vec![
+                    None,
+                    None,
+                    Some("x"),
+                    Some("x"),
+                    None,
+                    None,
+                    None,
+                ])) as ArrayRef,
+                true,
+            ),
+            (
+                "c",
+                Arc::new(Int32Array::from(vec![
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(5),
+                    Some(5),
+                ])) as ArrayRef,
+                true,
+            ),
+            (
+                "gid",
+                Arc::new(Int32Array::from(vec![1, 1, 2, 2, 1, 0, 0])) as ArrayRef,
+                false,
+            ),
+        ])?;
+        let schema = batch.schema();
+        let input = Arc::new(MemoryExec::try_new(
+            &[vec![batch.slice(0, 3), batch.slice(3, 4)]],
+            schema.clone(),
+            None,
+        )?);
+        let distinct_agg = NativeDistinctAggExec::try_new(
+            input,
+            vec![
+                Arc::new(Column::new("a", 0)),
+                Arc::new(Column::new("b", 1)),
+                Arc::new(Column::new("gid", 3)),
+            ],
+            Arc::new(BinaryExpr::new(
+                Arc::new(Column::new("gid", 3)),
+                Operator::NotEq,
+                lit(ScalarValue::Int32(Some(0))),
+            )),
+        )?;
+        let session_ctx = SessionContext::new();
+        let output = distinct_agg.execute(0, session_ctx.task_ctx())?;
+        let batches = common::collect(output).await?;
+        let expected = vec![
+            "+---+---+---+-----+",
+            "| a | b | c | gid |",
+            "+---+---+---+-----+",
+            "|   |   | 5 | 0   |",
+            "|   |   | 5 | 0   |",
+            "|   | x |   | 2   |",
+            "| 1 |   |   | 1   |",
+            "+---+---+---+-----+",
+        ];
+        assert_batches_sorted_eq!(expected, &batches);
+        Ok(())
+    }
+}
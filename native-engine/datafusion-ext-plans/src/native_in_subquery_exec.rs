@@ -0,0 +1,346 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::HashSet, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    array::{Array, ArrayRef, BooleanArray},
+    compute::filter_record_batch,
+    datatypes::SchemaRef,
+    record_batch::RecordBatch,
+    row::{RowConverter, SortField},
+};
+use datafusion::{
+    common::{DataFusionError, Result, Statistics},
+    execution::context::TaskContext,
+    physical_expr::{EquivalenceProperties, PhysicalExprRef},
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::{arrow::cast::cast, df_execution_err};
+use futures::StreamExt;
+use once_cell::sync::OnceCell;
+
+use crate::common::execution_context::ExecutionContext;
+
+/// filters `probe_expr IN (subquery_result)` by probing a hash set of the
+/// (broadcast) subquery result, keyed by values encoded in the row format.
+/// rows with null predicates are filtered out, as in FilterExec.
+#[derive(Debug)]
+pub struct NativeInSubqueryExec {
+    input: Arc<dyn ExecutionPlan>,
+    probe_expr: PhysicalExprRef,
+    subquery_result: RecordBatch,
+    in_set: OnceCell<Arc<InSubquerySet>>,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl NativeInSubqueryExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        probe_expr: PhysicalExprRef,
+        subquery_result: RecordBatch,
+    ) -> Result<Self> {
+        if subquery_result.num_columns() != 1 {
+            return df_execution_err!(
+                "NativeInSubqueryExec expects subquery result with exactly one column, got {}",
+                subquery_result.num_columns(),
+            );
+        }
+        probe_expr.data_type(&input.schema())?;
+        Ok(Self {
+            input,
+            probe_expr,
+            subquery_result,
+            in_set: OnceCell::new(),
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+
+    fn in_set(&self) -> Result<Arc<InSubquerySet>> {
+        self.in_set
+            .get_or_try_init(|| {
+                let probe_type = self.probe_expr.data_type(&self.input.schema())?;
+                let values = cast(self.subquery_result.column(0), &probe_type)?;
+                Ok::<_, DataFusionError>(Arc::new(InSubquerySet::try_new(values)?))
+            })
+            .cloned()
+    }
+}
+
+impl DisplayAs for NativeInSubqueryExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "NativeInSubqueryExec [{} IN ({} values)]",
+            self.probe_expr,
+            self.subquery_result.num_rows()
+        )
+    }
+}
+
+impl ExecutionPlan for NativeInSubqueryExec {
+    fn name(&self) -> &str {
+        "NativeInSubqueryExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            self.probe_expr.clone(),
+            self.subquery_result.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        let probe_expr = self.probe_expr.clone();
+        let in_set = self.in_set()?;
+        let filtered = execute_in_subquery(input, probe_expr, in_set, exec_ctx.clone())?;
+        Ok(exec_ctx.coalesce_with_default_batch_size(filtered))
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+fn execute_in_subquery(
+    mut input: SendableRecordBatchStream,
+    probe_expr: PhysicalExprRef,
+    in_set: Arc<InSubquerySet>,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("InSubquery", move |sender| async move {
+            sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+
+            while let Some(batch) = input.next().await.transpose()? {
+                let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                let probed = probe_expr
+                    .evaluate(&batch)
+                    .and_then(|v| v.into_array(batch.num_rows()))?;
+                let predicate = in_set.probe(&probed)?;
+                let filtered_batch = filter_record_batch(&batch, &predicate)?;
+                exec_ctx
+                    .baseline_metrics()
+                    .record_output(filtered_batch.num_rows());
+                sender.send(filtered_batch).await;
+            }
+            Ok(())
+        }))
+}
+
+/// hash set of the non-null subquery values encoded in the row format,
+/// where equal values always have equal encodings
+#[derive(Debug)]
+struct InSubquerySet {
+    row_converter: RowConverter,
+    value_set: HashSet<Box<[u8]>>,
+    has_null: bool,
+}
+
+impl InSubquerySet {
+    fn try_new(values: ArrayRef) -> Result<Self> {
+        let row_converter = RowConverter::new(vec![SortField::new(values.data_type().clone())])?;
+        let rows = row_converter.convert_columns(&[values.clone()])?;
+        let value_set = (0..values.len())
+            .filter(|&i| values.is_valid(i))
+            .map(|i| Box::from(rows.row(i).as_ref()))
+            .collect();
+        Ok(Self {
+            row_converter,
+            value_set,
+            has_null: values.null_count() > 0,
+        })
+    }
+
+    /// evaluates IN of each probed value with sql semantics: null if the
+    /// probed value is null, or if it is not found and the set contains null.
+    fn probe(&self, probed: &ArrayRef) -> Result<BooleanArray> {
+        let probed_rows = self.row_converter.convert_columns(&[probed.clone()])?;
+        let not_found = if self.has_null { None } else { Some(false) };
+
+        Ok((0..probed.len())
+            .map(|i| {
+                if probed.is_null(i) {
+                    return None;
+                }
+                if self.value_set.contains(probed_rows.row(i).as_ref()) {
+                    Some(true)
+                } else {
+                    not_found
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Decimal128Array, Int32Array, Int64Array, StringArray},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        assert_batches_sorted_eq,
+        common::Result,
+        physical_expr::expressions::Column,
+        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        memmgr::MemManager,
+        native_in_subquery_exec::{InSubquerySet, NativeInSubqueryExec},
+    };
+
+    fn probe(values: ArrayRef, probed: ArrayRef) -> Result<Vec<Option<bool>>> {
+        let in_set = InSubquerySet::try_new(values)?;
+        Ok(in_set.probe(&probed)?.iter().collect())
+    }
+
+    #[test]
+    fn test_in_subquery_int() -> Result<()> {
+        let values: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3, 2]));
+        let probed: ArrayRef = Arc::new(Int32Array::from(vec![Some(2), Some(4), None]));
+        assert_eq!(
+            probe(values, probed.clone())?,
+            vec![Some(true), Some(false), None],
+        );
+
+        // unmatched values are null if the set contains null
+        let values: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None, Some(3)]));
+        assert_eq!(probe(values, probed)?, vec![None, None, None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_subquery_string() -> Result<()> {
+        let values: ArrayRef = Arc::new(StringArray::from(vec!["apple", "banana", ""]));
+        let probed: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("banana"),
+            Some("cherry"),
+            Some(""),
+            None,
+        ]));
+        assert_eq!(
+            probe(values, probed)?,
+            vec![Some(true), Some(false), Some(true), None],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_subquery_decimal() -> Result<()> {
+        let values: ArrayRef = Arc::new(
+            Decimal128Array::from(vec![Some(12345), Some(-100), None])
+                .with_precision_and_scale(10, 2)?,
+        );
+        let probed: ArrayRef = Arc::new(
+            Decimal128Array::from(vec![Some(-100), Some(12346), None])
+                .with_precision_and_scale(10, 2)?,
+        );
+        assert_eq!(probe(values, probed)?, vec![Some(true), None, None]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_native_in_subquery_exec() -> Result<()> {
+        MemManager::init(10000);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![
+                    Some(1),
+                    Some(2),
+                    None,
+                    Some(4),
+                    Some(5),
+                ])),
+                Arc::new(StringArray::from(vec!["x", "y", "z", "u", "v"])),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+
+        // subquery result of a different integer type is casted to the probed
+        // type
+        let subquery_result = RecordBatch::try_from_iter(vec![(
+            "s",
+            Arc::new(Int64Array::from(vec![5, 1, 3])) as ArrayRef,
+        )])?;
+        let in_subquery =
+            NativeInSubqueryExec::try_new(input, Arc::new(Column::new("a", 0)), subquery_result)?;
+        let session_ctx = SessionContext::new();
+        let output = in_subquery.execute(0, session_ctx.task_ctx())?;
+        let batches = common::collect(output).await?;
+        let expected = vec![
+            "+---+---+",
+            "| a | b |",
+            "+---+---+",
+            "| 1 | x |",
+            "| 5 | v |",
+            "+---+---+",
+        ];
+        assert_batches_sorted_eq!(expected, &batches);
+        Ok(())
+    }
+}
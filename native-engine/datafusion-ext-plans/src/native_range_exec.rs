@@ -0,0 +1,257 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Int64Array, RecordBatch},
+    compute::SortOptions,
+    datatypes::{DataType, Field, Schema, SchemaRef},
+};
+use datafusion::{
+    common::Result,
+    execution::context::TaskContext,
+    physical_expr::{expressions::Column, EquivalenceProperties, PhysicalSortExpr},
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, Partitioning, PlanProperties,
+        SendableRecordBatchStream, Statistics,
+    },
+};
+use datafusion_ext_commons::df_execution_err;
+use once_cell::sync::OnceCell;
+
+use crate::common::execution_context::ExecutionContext;
+
+/// generates a single `id` column of values from start (inclusive) to end
+/// (exclusive) by step, like spark's range(). output batches have at most
+/// batch_size rows.
+pub struct NativeRangeExec {
+    start: i64,
+    end: i64,
+    step: i64,
+    batch_size: usize,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl NativeRangeExec {
+    pub fn try_new(start: i64, end: i64, step: i64, batch_size: usize) -> Result<Self> {
+        if step == 0 {
+            return df_execution_err!("NativeRangeExec: step must not be zero");
+        }
+        if batch_size == 0 {
+            return df_execution_err!("NativeRangeExec: batch_size must not be zero");
+        }
+        Ok(Self {
+            start,
+            end,
+            step,
+            batch_size,
+            schema: Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)])),
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+
+    /// number of generated rows, computed in i128 to avoid overflowing
+    pub fn num_rows(&self) -> u64 {
+        let distance = self.end as i128 - self.start as i128;
+        let step = self.step as i128;
+        if distance == 0 || (distance > 0) != (step > 0) {
+            return 0;
+        }
+        let (distance, step) = (distance.abs(), step.abs());
+        ((distance + step - 1) / step) as u64
+    }
+}
+
+impl Debug for NativeRangeExec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "NativeRangeExec [{}, {}, {}]",
+            self.start, self.end, self.step
+        )
+    }
+}
+
+impl DisplayAs for NativeRangeExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "NativeRangeExec [{}, {}, {}]",
+            self.start, self.end, self.step
+        )
+    }
+}
+
+impl ExecutionPlan for NativeRangeExec {
+    fn name(&self) -> &str {
+        "NativeRangeExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            let ordering = vec![PhysicalSortExpr {
+                expr: Arc::new(Column::new("id", 0)),
+                options: SortOptions {
+                    descending: self.step < 0,
+                    nulls_first: false,
+                },
+            }];
+            PlanProperties::new(
+                EquivalenceProperties::new_with_orderings(self.schema(), &[ordering]),
+                Partitioning::UnknownPartitioning(1),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let start = self.start;
+        let step = self.step;
+        let batch_size = self.batch_size as u64;
+        let num_rows = self.num_rows();
+
+        let output = exec_ctx
+            .clone()
+            .output_with_sender("Range", move |sender| async move {
+                let elapsed_compute = exec_ctx.baseline_metrics().elapsed_compute().clone();
+                sender.exclude_time(&elapsed_compute);
+
+                let mut offset = 0u64;
+                while offset < num_rows {
+                    let _timer = elapsed_compute.timer();
+                    let len = batch_size.min(num_rows - offset);
+
+                    // values never overflow since they are all between start
+                    // and end
+                    let first = start.wrapping_add((offset as i64).wrapping_mul(step));
+                    let ids = Int64Array::from_iter_values(
+                        (0..len as i64).map(|i| first.wrapping_add(i.wrapping_mul(step))),
+                    );
+                    let batch =
+                        RecordBatch::try_new(exec_ctx.output_schema(), vec![Arc::new(ids)])?;
+                    offset += len;
+
+                    exec_ctx.baseline_metrics().record_output(batch.num_rows());
+                    sender.send(batch).await;
+                }
+                Ok(())
+            });
+        Ok(output)
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{array::AsArray, datatypes::Int64Type};
+    use datafusion::{
+        assert_batches_eq,
+        common::Result,
+        physical_plan::{common, ExecutionPlan},
+        prelude::SessionContext,
+    };
+
+    use crate::{memmgr::MemManager, native_range_exec::NativeRangeExec};
+
+    #[tokio::test]
+    async fn test_forward_range() -> Result<()> {
+        MemManager::init(10000);
+        let range = Arc::new(NativeRangeExec::try_new(0, 10, 3, 2)?);
+        assert_eq!(range.num_rows(), 4);
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(range.execute(0, task_ctx)?).await?;
+        assert_eq!(output.len(), 2);
+        assert_batches_eq!(
+            vec!["+----+", "| id |", "+----+", "| 0  |", "| 3  |", "| 6  |", "| 9  |", "+----+",],
+            &output
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backward_range() -> Result<()> {
+        MemManager::init(10000);
+        let range = Arc::new(NativeRangeExec::try_new(10, 0, -4, 2)?);
+        assert_eq!(range.num_rows(), 3);
+
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(range.execute(0, task_ctx)?).await?;
+        assert_eq!(output.len(), 2);
+        assert_batches_eq!(
+            vec!["+----+", "| id |", "+----+", "| 10 |", "| 6  |", "| 2  |", "+----+",],
+            &output
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_boundaries() -> Result<()> {
+        MemManager::init(10000);
+        assert!(NativeRangeExec::try_new(0, 10, 0, 2).is_err());
+        assert_eq!(NativeRangeExec::try_new(0, 10, -1, 2)?.num_rows(), 0);
+        assert_eq!(NativeRangeExec::try_new(5, 5, 1, 2)?.num_rows(), 0);
+
+        // extreme boundaries without overflowing
+        let range = Arc::new(NativeRangeExec::try_new(i64::MIN, i64::MAX, i64::MAX, 8)?);
+        assert_eq!(range.num_rows(), 3);
+        let task_ctx = SessionContext::new().task_ctx();
+        let output = common::collect(range.execute(0, task_ctx)?).await?;
+        let ids = output[0].column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values(), &[i64::MIN, -1, i64::MAX - 1]);
+        Ok(())
+    }
+}
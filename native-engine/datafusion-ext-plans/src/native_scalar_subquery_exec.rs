@@ -0,0 +1,411 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    datatypes::{Field, Schema, SchemaRef},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use datafusion::{
+    common::{Result, ScalarValue, Statistics},
+    execution::context::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        coalesce_partitions::CoalescePartitionsExec,
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::df_execution_err;
+use futures::StreamExt;
+use once_cell::sync::OnceCell;
+
+use crate::common::execution_context::ExecutionContext;
+
+/// appends the result of an uncorrelated scalar subquery to each input row as
+/// a literal column. the subquery is executed only once and its value is
+/// shared by all partitions, subqueries with multiple partitions are coalesced.
+#[derive(Debug)]
+pub struct NativeScalarSubqueryExec {
+    input: Arc<dyn ExecutionPlan>,
+    subquery: Arc<dyn ExecutionPlan>,
+    field_name: String,
+    value: Arc<tokio::sync::OnceCell<ScalarValue>>,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl NativeScalarSubqueryExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        subquery: Arc<dyn ExecutionPlan>,
+        field_name: String,
+    ) -> Result<Self> {
+        let subquery_schema = subquery.schema();
+        if subquery_schema.fields().len() != 1 {
+            return df_execution_err!(
+                "NativeScalarSubqueryExec expects subquery with exactly one column, got {}",
+                subquery_schema.fields().len(),
+            );
+        }
+        let value_field = Field::new(
+            &field_name,
+            subquery_schema.field(0).data_type().clone(),
+            true,
+        );
+        let schema = Arc::new(Schema::new(
+            [
+                input.schema().fields().to_vec(),
+                vec![Arc::new(value_field)],
+            ]
+            .concat(),
+        ));
+        Ok(Self {
+            input,
+            subquery,
+            field_name,
+            value: Arc::new(tokio::sync::OnceCell::new()),
+            schema,
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+
+    /// returns the subquery result, executing the subquery if no partition
+    /// has materialized it yet
+    pub async fn value(&self, task_ctx: Arc<TaskContext>) -> Result<&ScalarValue> {
+        self.value
+            .get_or_try_init(|| materialize_scalar_subquery(self.subquery.clone(), task_ctx))
+            .await
+    }
+}
+
+impl DisplayAs for NativeScalarSubqueryExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "NativeScalarSubqueryExec [{}]", self.field_name)
+    }
+}
+
+impl ExecutionPlan for NativeScalarSubqueryExec {
+    fn name(&self) -> &str {
+        "NativeScalarSubqueryExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input, &self.subquery]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            children[1].clone(),
+            self.field_name.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        let subquery = self.subquery.clone();
+        let value = self.value.clone();
+        execute_scalar_subquery(input, subquery, value, exec_ctx)
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+fn execute_scalar_subquery(
+    mut input: SendableRecordBatchStream,
+    subquery: Arc<dyn ExecutionPlan>,
+    value: Arc<tokio::sync::OnceCell<ScalarValue>>,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("ScalarSubquery", move |sender| async move {
+            sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+
+            // the first partition reaching here executes the subquery, others
+            // wait for its result
+            let value = value
+                .get_or_try_init(|| materialize_scalar_subquery(subquery, exec_ctx.task_ctx()))
+                .await?;
+
+            while let Some(batch) = input.next().await.transpose()? {
+                let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                let num_rows = batch.num_rows();
+                let mut columns = batch.columns().to_vec();
+                columns.push(value.to_array_of_size(num_rows)?);
+                let output_batch = RecordBatch::try_new_with_options(
+                    exec_ctx.output_schema(),
+                    columns,
+                    &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+                )?;
+                exec_ctx
+                    .baseline_metrics()
+                    .record_output(output_batch.num_rows());
+                sender.send(output_batch).await;
+            }
+            Ok(())
+        }))
+}
+
+/// executes the subquery to completion, the result must contain at most one
+/// row. an empty result is taken as null, as in spark.
+async fn materialize_scalar_subquery(
+    subquery: Arc<dyn ExecutionPlan>,
+    task_ctx: Arc<TaskContext>,
+) -> Result<ScalarValue> {
+    let data_type = subquery.schema().field(0).data_type().clone();
+    let subquery: Arc<dyn ExecutionPlan> = match subquery.output_partitioning().partition_count() {
+        1 => subquery,
+        _ => Arc::new(CoalescePartitionsExec::new(subquery)),
+    };
+    let mut value = None;
+    let mut stream = subquery.execute(0, task_ctx)?;
+    while let Some(batch) = stream.next().await.transpose()? {
+        if batch.num_rows() == 0 {
+            continue;
+        }
+        if value.is_some() || batch.num_rows() > 1 {
+            return df_execution_err!(
+                "more than one row returned by a subquery used as an expression"
+            );
+        }
+        value = Some(ScalarValue::try_from_array(batch.column(0), 0)?);
+    }
+    match value {
+        Some(value) => Ok(value),
+        None => Ok(ScalarValue::try_from(&data_type)?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        any::Any,
+        fmt::Formatter,
+        sync::{
+            atomic::{AtomicUsize, Ordering::SeqCst},
+            Arc,
+        },
+    };
+
+    use arrow::{
+        array::{ArrayRef, Int32Array, Int64Array},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        assert_batches_sorted_eq,
+        common::{Result, ScalarValue},
+        execution::context::TaskContext,
+        physical_plan::{
+            common, memory::MemoryExec, DisplayAs, DisplayFormatType, ExecutionPlan,
+            PlanProperties, SendableRecordBatchStream,
+        },
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        memmgr::MemManager,
+        native_scalar_subquery_exec::{materialize_scalar_subquery, NativeScalarSubqueryExec},
+    };
+
+    /// counts how many times the inner plan is executed
+    #[derive(Debug)]
+    struct CountingExec {
+        inner: Arc<dyn ExecutionPlan>,
+        num_executions: Arc<AtomicUsize>,
+    }
+
+    impl DisplayAs for CountingExec {
+        fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+            write!(f, "CountingExec")
+        }
+    }
+
+    impl ExecutionPlan for CountingExec {
+        fn name(&self) -> &str {
+            "CountingExec"
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn schema(&self) -> SchemaRef {
+            self.inner.schema()
+        }
+
+        fn properties(&self) -> &PlanProperties {
+            self.inner.properties()
+        }
+
+        fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+            vec![]
+        }
+
+        fn with_new_children(
+            self: Arc<Self>,
+            _children: Vec<Arc<dyn ExecutionPlan>>,
+        ) -> Result<Arc<dyn ExecutionPlan>> {
+            Ok(self)
+        }
+
+        fn execute(
+            &self,
+            partition: usize,
+            context: Arc<TaskContext>,
+        ) -> Result<SendableRecordBatchStream> {
+            self.num_executions.fetch_add(1, SeqCst);
+            self.inner.execute(partition, context)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_native_scalar_subquery_exec() -> Result<()> {
+        MemManager::init(10000);
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+        };
+        let input = Arc::new(MemoryExec::try_new(
+            &[vec![batch(vec![1, 2])?], vec![batch(vec![3])?]],
+            schema.clone(),
+            None,
+        )?);
+
+        // subquery returns a single row
+        let subquery_result = RecordBatch::try_from_iter(vec![(
+            "m",
+            Arc::new(Int64Array::from(vec![100])) as ArrayRef,
+        )])?;
+        let num_executions = Arc::new(AtomicUsize::new(0));
+        let subquery = Arc::new(CountingExec {
+            inner: Arc::new(MemoryExec::try_new(
+                &[vec![subquery_result.clone()]],
+                subquery_result.schema(),
+                None,
+            )?),
+            num_executions: num_executions.clone(),
+        });
+        let scalar_subquery = NativeScalarSubqueryExec::try_new(input, subquery, "m".to_string())?;
+
+        let session_ctx = SessionContext::new();
+        let mut batches = vec![];
+        for partition in 0..2 {
+            let output = scalar_subquery.execute(partition, session_ctx.task_ctx())?;
+            batches.extend(common::collect(output).await?);
+        }
+        let expected = vec![
+            "+---+-----+",
+            "| a | m   |",
+            "+---+-----+",
+            "| 1 | 100 |",
+            "| 2 | 100 |",
+            "| 3 | 100 |",
+            "+---+-----+",
+        ];
+        assert_batches_sorted_eq!(expected, &batches);
+        assert_eq!(
+            scalar_subquery.value(session_ctx.task_ctx()).await?,
+            &ScalarValue::Int64(Some(100)),
+        );
+        assert_eq!(num_executions.load(SeqCst), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_materialize_scalar_subquery() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let subquery = |values: Vec<i64>| -> Result<Arc<dyn ExecutionPlan>> {
+            let result = RecordBatch::try_from_iter(vec![(
+                "m",
+                Arc::new(Int64Array::from(values)) as ArrayRef,
+            )])?;
+            Ok(Arc::new(MemoryExec::try_new(
+                &[vec![result.clone()]],
+                result.schema(),
+                None,
+            )?))
+        };
+
+        // empty result is taken as null
+        let value = materialize_scalar_subquery(subquery(vec![])?, session_ctx.task_ctx()).await?;
+        assert_eq!(value, ScalarValue::Int64(None));
+
+        let err = materialize_scalar_subquery(subquery(vec![100, 200])?, session_ctx.task_ctx())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("more than one row"));
+
+        // rows from all partitions are checked, not only the first one
+        let batch = |values: Vec<i64>| {
+            RecordBatch::try_from_iter(vec![("m", Arc::new(Int64Array::from(values)) as ArrayRef)])
+        };
+        let multi_partitions = Arc::new(MemoryExec::try_new(
+            &[vec![], vec![batch(vec![100])?]],
+            batch(vec![])?.schema(),
+            None,
+        )?);
+        let value = materialize_scalar_subquery(multi_partitions, session_ctx.task_ctx()).await?;
+        assert_eq!(value, ScalarValue::Int64(Some(100)));
+
+        let multi_partitions = Arc::new(MemoryExec::try_new(
+            &[vec![batch(vec![100])?], vec![batch(vec![200])?]],
+            batch(vec![])?.schema(),
+            None,
+        )?);
+        let err = materialize_scalar_subquery(multi_partitions, session_ctx.task_ctx())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("more than one row"));
+        Ok(())
+    }
+}
@@ -48,7 +48,7 @@ use orc_rust::{
 
 use crate::{
     common::execution_context::ExecutionContext,
-    scan::{internal_file_reader::InternalFileReader, BlazeSchemaMapping},
+    scan::{bucketing::BucketSpec, internal_file_reader::InternalFileReader, BlazeSchemaMapping},
 };
 
 /// Execution plan for scanning one or more Orc partitions
@@ -60,6 +60,7 @@ pub struct OrcExec {
     projected_schema: SchemaRef,
     metrics: ExecutionPlanMetricsSet,
     _predicate: Option<Arc<dyn PhysicalExpr>>,
+    bucket_spec: Option<BucketSpec>,
     props: OnceCell<PlanProperties>,
 }
 
@@ -83,9 +84,39 @@ impl OrcExec {
             projected_schema,
             metrics,
             _predicate,
+            bucket_spec: None,
             props: OnceCell::new(),
         }
     }
+
+    /// scans a bucketed table, partition i reads files of bucket i and the
+    /// output is hash partitioned on bucket columns
+    pub fn with_bucket_spec(mut self, bucket_spec: Option<BucketSpec>) -> Self {
+        self.bucket_spec = bucket_spec;
+        self
+    }
+
+    pub fn bucket_spec(&self) -> Option<&BucketSpec> {
+        self.bucket_spec.as_ref()
+    }
+
+    /// creates a scan producing only the given columns of the current output
+    pub fn with_projection(&self, projection: &[usize]) -> Self {
+        let mut base_config = self.base_config.clone();
+        let num_columns =
+            base_config.file_schema.fields().len() + base_config.table_partition_cols.len();
+        let current = base_config
+            .projection
+            .take()
+            .unwrap_or_else(|| (0..num_columns).collect());
+        base_config.projection = Some(projection.iter().map(|&i| current[i]).collect());
+        Self::new(
+            base_config,
+            self.fs_resource_id.clone(),
+            self._predicate.clone(),
+        )
+        .with_bucket_spec(self.bucket_spec.clone())
+    }
 }
 
 impl DisplayAs for OrcExec {
@@ -125,7 +156,11 @@ impl ExecutionPlan for OrcExec {
         self.props.get_or_init(|| {
             PlanProperties::new(
                 EquivalenceProperties::new(self.schema()),
-                Partitioning::UnknownPartitioning(self.base_config.file_groups.len()),
+                match &self.bucket_spec {
+                    Some(bucket_spec) => bucket_spec
+                        .output_partitioning(&self.schema(), self.base_config.file_groups.len()),
+                    None => Partitioning::UnknownPartitioning(self.base_config.file_groups.len()),
+                },
                 ExecutionMode::Bounded,
             )
         })
@@ -148,6 +183,10 @@ impl ExecutionPlan for OrcExec {
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        if let Some(bucket_spec) = &self.bucket_spec {
+            let file_groups = &self.base_config.file_groups;
+            bucket_spec.verify_file_group(partition, file_groups.len(), &file_groups[partition])?;
+        }
         let io_time = exec_ctx.register_timer_metric("io_time");
 
         // get fs object from jni bridge resource
@@ -19,24 +19,30 @@
 
 use std::{any::Any, fmt, fmt::Formatter, ops::Range, pin::Pin, sync::Arc};
 
-use arrow::datatypes::SchemaRef;
+use arrow::{
+    datatypes::{Schema, SchemaRef},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
 use arrow_schema::DataType;
 use blaze_jni_bridge::{
     conf, conf::BooleanConf, jni_call_static, jni_new_global_ref, jni_new_string,
 };
 use bytes::Bytes;
 use datafusion::{
-    datasource::physical_plan::{
-        parquet::{page_filter::PagePruningAccessPlanFilter, ParquetOpener},
-        FileMeta, FileScanConfig, FileStream, OnError, ParquetFileMetrics,
-        ParquetFileReaderFactory,
+    datasource::{
+        listing::FileRange,
+        physical_plan::{
+            parquet::{page_filter::PagePruningAccessPlanFilter, ParquetOpener},
+            FileMeta, FileOpenFuture, FileOpener, FileScanConfig, FileStream, OnError,
+            ParquetFileMetrics, ParquetFileReaderFactory,
+        },
     },
     error::{DataFusionError, Result},
     execution::context::TaskContext,
     parquet::{
         arrow::async_reader::{fetch_parquet_metadata, AsyncFileReader},
         errors::ParquetError,
-        file::metadata::ParquetMetaData,
+        file::metadata::{ParquetMetaData, RowGroupMetaData},
     },
     physical_expr::{EquivalenceProperties, PhysicalExprRef},
     physical_optimizer::pruning::PruningPredicate,
@@ -56,7 +62,9 @@ use parking_lot::Mutex;
 
 use crate::{
     common::execution_context::ExecutionContext,
-    scan::{internal_file_reader::InternalFileReader, BlazeSchemaAdapterFactory},
+    scan::{
+        bucketing::BucketSpec, internal_file_reader::InternalFileReader, BlazeSchemaAdapterFactory,
+    },
 };
 
 /// Execution plan for scanning one or more Parquet partitions
@@ -70,6 +78,7 @@ pub struct ParquetExec {
     predicate: Option<Arc<dyn PhysicalExpr>>,
     pruning_predicate: Option<Arc<PruningPredicate>>,
     page_pruning_predicate: Option<Arc<PagePruningAccessPlanFilter>>,
+    bucket_spec: Option<BucketSpec>,
     props: OnceCell<PlanProperties>,
 }
 
@@ -125,9 +134,39 @@ impl ParquetExec {
             predicate,
             pruning_predicate,
             page_pruning_predicate,
+            bucket_spec: None,
             props: OnceCell::new(),
         }
     }
+
+    /// scans a bucketed table, partition i reads files of bucket i and the
+    /// output is hash partitioned on bucket columns
+    pub fn with_bucket_spec(mut self, bucket_spec: Option<BucketSpec>) -> Self {
+        self.bucket_spec = bucket_spec;
+        self
+    }
+
+    pub fn bucket_spec(&self) -> Option<&BucketSpec> {
+        self.bucket_spec.as_ref()
+    }
+
+    /// creates a scan producing only the given columns of the current output
+    pub fn with_projection(&self, projection: &[usize]) -> Self {
+        let mut base_config = self.base_config.clone();
+        let num_columns =
+            base_config.file_schema.fields().len() + base_config.table_partition_cols.len();
+        let current = base_config
+            .projection
+            .take()
+            .unwrap_or_else(|| (0..num_columns).collect());
+        base_config.projection = Some(projection.iter().map(|&i| current[i]).collect());
+        Self::new(
+            base_config,
+            self.fs_resource_id.clone(),
+            self.predicate.clone(),
+        )
+        .with_bucket_spec(self.bucket_spec.clone())
+    }
 }
 
 impl DisplayAs for ParquetExec {
@@ -175,7 +214,11 @@ impl ExecutionPlan for ParquetExec {
         self.props.get_or_init(|| {
             PlanProperties::new(
                 EquivalenceProperties::new(self.schema()),
-                Partitioning::UnknownPartitioning(self.base_config.file_groups.len()),
+                match &self.bucket_spec {
+                    Some(bucket_spec) => bucket_spec
+                        .output_partitioning(&self.schema(), self.base_config.file_groups.len()),
+                    None => Partitioning::UnknownPartitioning(self.base_config.file_groups.len()),
+                },
                 ExecutionMode::Bounded,
             )
         })
@@ -194,6 +237,10 @@ impl ExecutionPlan for ParquetExec {
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        if let Some(bucket_spec) = &self.bucket_spec {
+            let file_groups = &self.base_config.file_groups;
+            bucket_spec.verify_file_group(partition, file_groups.len(), &file_groups[partition])?;
+        }
         let elapsed_compute = exec_ctx.baseline_metrics().elapsed_compute().clone();
         let _timer = elapsed_compute.timer();
         let io_time = exec_ctx.register_timer_metric("io_time");
@@ -209,6 +256,20 @@ impl ExecutionPlan for ParquetExec {
             None => (0..self.base_config.file_schema.fields().len()).collect(),
         };
 
+        let parquet_file_reader_factory = Arc::new(FsReaderFactory::new(fs_provider));
+
+        // no file columns and no pushed filter (like count(*)), produce row
+        // counts from row group metadata without decoding any column
+        if projection.is_empty() && self.predicate.is_none() {
+            let opener = ParquetRowCountOpener {
+                partition_index: partition,
+                batch_size: batch_size(),
+                metrics: self.metrics.clone(),
+                parquet_file_reader_factory,
+            };
+            return self.execute_with_opener(partition, opener, exec_ctx);
+        }
+
         let page_filtering_enabled = conf::PARQUET_ENABLE_PAGE_FILTERING.value()?;
         let bloom_filter_enabled = conf::PARQUET_ENABLE_BLOOM_FILTER.value()?;
 
@@ -223,21 +284,14 @@ impl ExecutionPlan for ParquetExec {
             table_schema: self.base_config.file_schema.clone(),
             metadata_size_hint: None,
             metrics: self.metrics.clone(),
-            parquet_file_reader_factory: Arc::new(FsReaderFactory::new(fs_provider)),
+            parquet_file_reader_factory,
             pushdown_filters: page_filtering_enabled,
             reorder_filters: page_filtering_enabled,
             enable_page_index: page_filtering_enabled,
             enable_bloom_filter: bloom_filter_enabled,
             schema_adapter_factory,
         };
-
-        let mut file_stream = FileStream::new(&self.base_config, partition, opener, &self.metrics)?;
-        if conf::IGNORE_CORRUPTED_FILES.value()? {
-            file_stream = file_stream.with_on_error(OnError::Skip);
-        }
-
-        let timed_stream = execute_parquet_scan(Box::pin(file_stream), exec_ctx.clone())?;
-        Ok(exec_ctx.coalesce_with_default_batch_size(timed_stream))
+        self.execute_with_opener(partition, opener, exec_ctx)
     }
 
     fn metrics(&self) -> Option<MetricsSet> {
@@ -249,8 +303,25 @@ impl ExecutionPlan for ParquetExec {
     }
 }
 
-fn execute_parquet_scan(
-    mut stream: Pin<Box<FileStream<ParquetOpener>>>,
+impl ParquetExec {
+    fn execute_with_opener<F: FileOpener + Send + 'static>(
+        &self,
+        partition: usize,
+        opener: F,
+        exec_ctx: Arc<ExecutionContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let mut file_stream = FileStream::new(&self.base_config, partition, opener, &self.metrics)?;
+        if conf::IGNORE_CORRUPTED_FILES.value()? {
+            file_stream = file_stream.with_on_error(OnError::Skip);
+        }
+
+        let timed_stream = execute_parquet_scan(Box::pin(file_stream), exec_ctx.clone())?;
+        Ok(exec_ctx.coalesce_with_default_batch_size(timed_stream))
+    }
+}
+
+fn execute_parquet_scan<F: FileOpener + Send + 'static>(
+    mut stream: Pin<Box<FileStream<F>>>,
     exec_ctx: Arc<ExecutionContext>,
 ) -> Result<SendableRecordBatchStream> {
     Ok(exec_ctx
@@ -265,6 +336,62 @@ fn execute_parquet_scan(
         }))
 }
 
+/// opens parquet files for scans without any file column, only the footer is
+/// read and batches with row counts but zero columns are produced
+struct ParquetRowCountOpener {
+    partition_index: usize,
+    batch_size: usize,
+    metrics: ExecutionPlanMetricsSet,
+    parquet_file_reader_factory: Arc<dyn ParquetFileReaderFactory>,
+}
+
+impl FileOpener for ParquetRowCountOpener {
+    fn open(&self, file_meta: FileMeta) -> Result<FileOpenFuture> {
+        let range = file_meta.range.clone();
+        let mut reader = self.parquet_file_reader_factory.create_reader(
+            self.partition_index,
+            file_meta,
+            None,
+            &self.metrics,
+        )?;
+        let batch_size = self.batch_size.max(1);
+
+        Ok(Box::pin(async move {
+            let metadata = reader.get_metadata().await?;
+            let num_rows: usize = metadata
+                .row_groups()
+                .iter()
+                .filter(|rg| range.as_ref().map_or(true, |r| row_group_in_range(rg, r)))
+                .map(|rg| rg.num_rows() as usize)
+                .sum();
+
+            let schema = Arc::new(Schema::empty());
+            let batches = (0..num_rows).step_by(batch_size).map(move |start| {
+                RecordBatch::try_new_with_options(
+                    schema.clone(),
+                    vec![],
+                    &RecordBatchOptions::new()
+                        .with_row_count(Some(batch_size.min(num_rows - start))),
+                )
+            });
+            Ok(futures::stream::iter(batches).boxed())
+        }))
+    }
+}
+
+/// a row group belongs to the file range containing its starting offset, same
+/// as the range pruning in ParquetOpener
+fn row_group_in_range(rg: &RowGroupMetaData, range: &FileRange) -> bool {
+    if rg.num_columns() == 0 {
+        return range.start == 0;
+    }
+    let col = rg.column(0);
+    let offset = col
+        .dictionary_page_offset()
+        .unwrap_or_else(|| col.data_page_offset());
+    offset >= range.start && offset < range.end
+}
+
 #[derive(Clone)]
 pub struct FsReaderFactory {
     fs_provider: Arc<FsProvider>,
@@ -510,3 +637,78 @@ fn expr_contains_decimal_type(expr: &PhysicalExprRef, schema: &SchemaRef) -> Res
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, Int32Array},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::Result,
+        datasource::physical_plan::{
+            parquet::DefaultParquetFileReaderFactory, FileMeta, FileOpener,
+        },
+        parquet::{arrow::ArrowWriter, file::properties::WriterProperties},
+        physical_plan::metrics::ExecutionPlanMetricsSet,
+    };
+    use futures::TryStreamExt;
+    use object_store::{local::LocalFileSystem, path::Path, ObjectStore};
+
+    use crate::parquet_exec::ParquetRowCountOpener;
+
+    #[tokio::test]
+    async fn test_row_count_only_scan() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let file_path = tmp_dir.path().join("test.parquet");
+
+        // write 10000 rows in 4 row groups
+        let array: ArrayRef = Arc::new(Int32Array::from_iter_values(0..10000));
+        let batch = RecordBatch::try_from_iter(vec![("a", array)])?;
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(3000)
+            .build();
+        let mut writer = ArrowWriter::try_new(
+            std::fs::File::create(&file_path)?,
+            batch.schema(),
+            Some(props),
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        let store = Arc::new(LocalFileSystem::new());
+        let object_meta = store.head(&Path::from_filesystem_path(&file_path)?).await?;
+        let metrics = ExecutionPlanMetricsSet::new();
+        let opener = ParquetRowCountOpener {
+            partition_index: 0,
+            batch_size: 4096,
+            metrics: metrics.clone(),
+            parquet_file_reader_factory: Arc::new(DefaultParquetFileReaderFactory::new(store)),
+        };
+
+        let batches: Vec<RecordBatch> = opener
+            .open(FileMeta::from(object_meta))?
+            .await?
+            .try_collect()
+            .await?;
+        assert!(batches.iter().all(|batch| batch.num_columns() == 0));
+        assert_eq!(
+            batches
+                .iter()
+                .map(|batch| batch.num_rows())
+                .collect::<Vec<_>>(),
+            vec![4096, 4096, 1808],
+        );
+
+        // no column data is decoded
+        let bytes_scanned = metrics
+            .clone_inner()
+            .sum_by_name("bytes_scanned")
+            .map(|v| v.as_usize())
+            .unwrap_or(0);
+        assert_eq!(bytes_scanned, 0);
+        Ok(())
+    }
+}
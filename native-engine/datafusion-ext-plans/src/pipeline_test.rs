@@ -0,0 +1,870 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! pipeline-level tests running plans composed of multiple operators.
+
+use std::{
+    any::Any,
+    fmt::Formatter,
+    io::{Cursor, Write},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        ArrayRef, AsArray, Int32Array, LargeStringArray, RecordBatchOptions,
+        TimestampMicrosecondArray,
+    },
+    datatypes::{DataType, Field, Float64Type, Int32Type, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use datafusion::{
+    assert_batches_sorted_eq,
+    common::{DataFusionError, JoinSide, Result, ScalarValue},
+    execution::context::TaskContext,
+    logical_expr::Operator,
+    physical_expr::{
+        expressions::{self as phys_expr, BinaryExpr},
+        EquivalenceProperties, PhysicalExprRef, PhysicalSortExpr,
+    },
+    physical_plan::{
+        common, memory::MemoryExec, metrics::ExecutionPlanMetricsSet,
+        stream::RecordBatchStreamAdapter, DisplayAs, DisplayFormatType, ExecutionMode,
+        ExecutionPlan, ExecutionPlanProperties, PlanProperties, SendableRecordBatchStream,
+        Statistics,
+    },
+    prelude::SessionContext,
+};
+use datafusion_ext_commons::{
+    arrow::cast::cast_scan_input_array,
+    error::BlazeError,
+    io::{read_one_batch_with_checksum, recover_named_batch, write_one_batch_with_checksum},
+};
+use datafusion_ext_exprs::spark_random::{
+    bind_partition, RandDistribution, SparkRandExpr, SparkUuidExpr,
+};
+use futures::StreamExt;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    agg::{
+        agg::create_agg,
+        AggExecMode::HashAgg,
+        AggExpr, AggFunction,
+        AggMode::{Final, Partial},
+        GroupingExpr,
+    },
+    agg_exec::AggExec,
+    broadcast_join_build_hash_map_exec::BroadcastJoinBuildHashMapExec,
+    broadcast_join_exec::BroadcastJoinExec,
+    common::{execution_context::cancel_all_tasks, ipc_compression::IpcCompressionReader},
+    filter_exec::FilterExec,
+    generate::{create_generator, GenerateFunc},
+    generate_exec::GenerateExec,
+    joins::join_utils::JoinType,
+    memmgr::{metrics::SpillMetrics, spill::AppendableFileSpill, MemManager},
+    shuffle::Partitioning,
+    shuffle_writer_exec::ShuffleWriterExec,
+    sort_exec::SortExec,
+    window::{WindowExpr, WindowFunction},
+    window_exec::WindowExec,
+};
+
+/// passes through batches of its input, with an empty batch injected before
+/// and after every batch.
+#[derive(Debug)]
+struct EmptyBatchInjectorExec {
+    input: Arc<dyn ExecutionPlan>,
+    props: OnceCell<PlanProperties>,
+}
+
+impl EmptyBatchInjectorExec {
+    fn new(input: Arc<dyn ExecutionPlan>) -> Self {
+        Self {
+            input,
+            props: OnceCell::new(),
+        }
+    }
+}
+
+impl DisplayAs for EmptyBatchInjectorExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "EmptyBatchInjectorExec")
+    }
+}
+
+impl ExecutionPlan for EmptyBatchInjectorExec {
+    fn name(&self) -> &str {
+        "EmptyBatchInjectorExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::new(children[0].clone())))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let input = self.input.execute(partition, context)?;
+        let empty_batch = RecordBatch::new_empty(self.schema());
+        let leading_empty_batch = futures::stream::iter([Ok(empty_batch.clone())]);
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            leading_empty_batch
+                .chain(input.flat_map(move |batch| {
+                    futures::stream::iter([batch, Ok(empty_batch.clone())])
+                })),
+        )))
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+fn build_table(
+    a: (&str, &Vec<i32>),
+    b: (&str, &Vec<i32>),
+    num_rows_per_batch: usize,
+) -> Arc<dyn ExecutionPlan> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(a.0, DataType::Int32, false),
+        Field::new(b.0, DataType::Int32, false),
+    ]));
+    let batches =
+        a.1.chunks(num_rows_per_batch)
+            .zip(b.1.chunks(num_rows_per_batch))
+            .map(|(a, b)| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int32Array::from(a.to_vec())),
+                        Arc::new(Int32Array::from(b.to_vec())),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+    Arc::new(MemoryExec::try_new(&[batches], schema, None).unwrap())
+}
+
+/// runs scan -> filter -> join -> agg -> shuffle and reads back all shuffled
+/// partitions. if `inject_empty_batches` is set, empty batches are injected
+/// between every two operators.
+async fn run_pipeline(inject_empty_batches: bool) -> Result<Vec<RecordBatch>> {
+    MemManager::init(1000000);
+    let inject = |plan: Arc<dyn ExecutionPlan>| -> Arc<dyn ExecutionPlan> {
+        if inject_empty_batches {
+            Arc::new(EmptyBatchInjectorExec::new(plan))
+        } else {
+            plan
+        }
+    };
+
+    // scan
+    let probed = inject(build_table(
+        ("k", &vec![1, 2, 3, 4, 5, 1, 2, 3]),
+        ("v", &vec![10, 20, 30, 40, 50, 60, 70, 80]),
+        3,
+    ));
+    let built = inject(build_table(
+        ("k2", &vec![1, 2, 3, 6]),
+        ("w", &vec![100, 200, 300, 600]),
+        2,
+    ));
+
+    // filter
+    let predicate: PhysicalExprRef = Arc::new(BinaryExpr::new(
+        phys_expr::col("v", &probed.schema())?,
+        Operator::Gt,
+        phys_expr::lit(15),
+    ));
+    let filtered = inject(Arc::new(FilterExec::try_new(vec![predicate], probed)?));
+
+    // join
+    let on = vec![(
+        phys_expr::col("k2", &built.schema())?,
+        phys_expr::col("k", &filtered.schema())?,
+    )];
+    let built = inject(Arc::new(BroadcastJoinBuildHashMapExec::new(
+        built,
+        vec![on[0].0.clone()],
+    )));
+    let join_schema = Arc::new(Schema::new(
+        [
+            built.schema().fields()[..2].to_vec(),
+            filtered.schema().fields().to_vec(),
+        ]
+        .concat(),
+    ));
+    let joined = inject(Arc::new(BroadcastJoinExec::try_new(
+        join_schema,
+        built,
+        filtered,
+        on,
+        JoinType::Inner,
+        JoinSide::Left,
+        true,
+        None,
+    )?));
+
+    // agg
+    let joined_schema = joined.schema();
+    let aggs = vec![
+        AggExpr {
+            field_name: "sum_v".to_string(),
+            mode: Partial,
+            agg: create_agg(
+                AggFunction::Sum,
+                &[phys_expr::col("v", &joined_schema)?],
+                &joined_schema,
+                DataType::Int64,
+            )?,
+        },
+        AggExpr {
+            field_name: "cnt".to_string(),
+            mode: Partial,
+            agg: create_agg(
+                AggFunction::Count,
+                &[phys_expr::col("w", &joined_schema)?],
+                &joined_schema,
+                DataType::Int64,
+            )?,
+        },
+    ];
+    let partial_agg = inject(Arc::new(AggExec::try_new(
+        HashAgg,
+        vec![GroupingExpr {
+            field_name: "k".to_string(),
+            expr: phys_expr::col("k", &joined_schema)?,
+        }],
+        aggs.clone(),
+        false,
+        joined,
+    )?));
+    let final_aggs = aggs
+        .into_iter()
+        .map(|mut agg| {
+            agg.agg = agg
+                .agg
+                .with_new_exprs(vec![Arc::new(phys_expr::Literal::new(ScalarValue::Null))])?;
+            agg.mode = Final;
+            Ok(agg)
+        })
+        .collect::<Result<_>>()?;
+    let final_agg = inject(Arc::new(AggExec::try_new(
+        HashAgg,
+        vec![GroupingExpr {
+            field_name: "k".to_string(),
+            expr: Arc::new(phys_expr::Column::new("k", 0)),
+        }],
+        final_aggs,
+        false,
+        partial_agg,
+    )?));
+
+    // shuffle
+    let output_schema = final_agg.schema();
+    let num_partitions = 3;
+    let tmp_dir = tempfile::tempdir()?;
+    let data_file = tmp_dir.path().join("data").to_string_lossy().to_string();
+    let index_file = tmp_dir.path().join("index").to_string_lossy().to_string();
+    let shuffle = ShuffleWriterExec::try_new(
+        final_agg,
+        Partitioning::HashPartitioning(vec![phys_expr::col("k", &output_schema)?], num_partitions),
+        data_file.clone(),
+        index_file.clone(),
+    )?;
+    let session_ctx = SessionContext::new();
+    common::collect(shuffle.execute(0, session_ctx.task_ctx())?).await?;
+
+    // read back all partitions
+    let data = std::fs::read(&data_file)?;
+    let offsets = std::fs::read(&index_file)?
+        .chunks(8)
+        .map(|offset| i64::from_le_bytes(offset.try_into().unwrap()) as usize)
+        .collect::<Vec<_>>();
+    assert_eq!(offsets.len(), num_partitions + 1);
+
+    let mut batches = vec![];
+    for partition_id in 0..num_partitions {
+        let segment = &data[offsets[partition_id]..offsets[partition_id + 1]];
+        let mut reader = IpcCompressionReader::new(Cursor::new(segment.to_vec()));
+        while let Some((num_rows, cols)) = reader.read_batch(&output_schema)? {
+            batches.push(recover_named_batch(num_rows, &cols, output_schema.clone())?);
+        }
+    }
+    Ok(batches)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_pipeline_with_empty_batches() -> Result<()> {
+    let expected = vec![
+        "+---+-------+-----+",
+        "| k | sum_v | cnt |",
+        "+---+-------+-----+",
+        "| 1 | 60    | 1   |",
+        "| 2 | 90    | 2   |",
+        "| 3 | 110   | 2   |",
+        "+---+-------+-----+",
+    ];
+    let batches = run_pipeline(false).await?;
+    assert_batches_sorted_eq!(expected, &batches);
+
+    let batches = run_pipeline(true).await?;
+    assert_batches_sorted_eq!(expected, &batches);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_join_timestamps_across_dst_boundary() -> Result<()> {
+    MemManager::init(1000000);
+    let ts_type = DataType::Timestamp(arrow::datatypes::TimeUnit::Microsecond, None);
+
+    // instants around the dst start of America/Los_Angeles (2024-03-10 10:00
+    // UTC)
+    let micros = vec![1710061200_000000, 1710064800_000000, 1710068400_000000];
+
+    // scanned timestamps are annotated with the session timezone, and
+    // normalized when casted to the plan schema
+    let scanned: ArrayRef = Arc::new(
+        TimestampMicrosecondArray::from(micros.clone()).with_timezone("America/Los_Angeles"),
+    );
+    let scanned_schema = Arc::new(Schema::new(vec![
+        Field::new("ts", ts_type.clone(), false),
+        Field::new("v", DataType::Int32, false),
+    ]));
+    let scanned_batch = RecordBatch::try_new(
+        scanned_schema.clone(),
+        vec![
+            cast_scan_input_array(&scanned, &ts_type)?,
+            Arc::new(Int32Array::from(vec![1, 2, 3])),
+        ],
+    )?;
+    let scanned: Arc<dyn ExecutionPlan> = Arc::new(MemoryExec::try_new(
+        &[vec![scanned_batch]],
+        scanned_schema,
+        None,
+    )?);
+
+    // literal timestamps provided by jvm are timezone-less
+    let literal_schema = Arc::new(Schema::new(vec![
+        Field::new("ts2", ts_type.clone(), false),
+        Field::new("w", DataType::Int32, false),
+    ]));
+    let literal_batch = RecordBatch::try_new(
+        literal_schema.clone(),
+        vec![
+            Arc::new(TimestampMicrosecondArray::from(micros.clone())),
+            Arc::new(Int32Array::from(vec![10, 20, 30])),
+        ],
+    )?;
+    let literals: Arc<dyn ExecutionPlan> = Arc::new(MemoryExec::try_new(
+        &[vec![literal_batch]],
+        literal_schema,
+        None,
+    )?);
+
+    let on = vec![(
+        phys_expr::col("ts2", &literals.schema())?,
+        phys_expr::col("ts", &scanned.schema())?,
+    )];
+    let built = Arc::new(BroadcastJoinBuildHashMapExec::new(
+        literals,
+        vec![on[0].0.clone()],
+    ));
+    let join_schema = Arc::new(Schema::new(
+        [
+            built.schema().fields()[..2].to_vec(),
+            scanned.schema().fields().to_vec(),
+        ]
+        .concat(),
+    ));
+    let joined = BroadcastJoinExec::try_new(
+        join_schema,
+        built,
+        scanned,
+        on,
+        JoinType::Inner,
+        JoinSide::Left,
+        true,
+        None,
+    )?;
+    let session_ctx = SessionContext::new();
+    let batches = common::collect(joined.execute(0, session_ctx.task_ctx())?).await?;
+    let expected = vec![
+        "+---------------------+----+---------------------+---+",
+        "| ts2                 | w  | ts                  | v |",
+        "+---------------------+----+---------------------+---+",
+        "| 2024-03-10T09:00:00 | 10 | 2024-03-10T09:00:00 | 1 |",
+        "| 2024-03-10T10:00:00 | 20 | 2024-03-10T10:00:00 | 2 |",
+        "| 2024-03-10T11:00:00 | 30 | 2024-03-10T11:00:00 | 3 |",
+        "+---------------------+----+---------------------+---+",
+    ];
+    assert_batches_sorted_eq!(expected, &batches);
+    Ok(())
+}
+
+const RAND_TEST_NUM_ROWS: usize = 8;
+
+/// two partitions of column `a` = 0..8, random exprs evaluated on partition 1
+/// must be seeded with `seed + 1`
+fn build_rand_test_input() -> Result<Arc<dyn ExecutionPlan>> {
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(Int32Array::from_iter_values(
+            0..RAND_TEST_NUM_ROWS as i32,
+        ))],
+    )?;
+    Ok(Arc::new(MemoryExec::try_new(
+        &[vec![batch.clone()], vec![batch]],
+        schema,
+        None,
+    )?))
+}
+
+fn rand_expr() -> PhysicalExprRef {
+    Arc::new(SparkRandExpr::new(30, RandDistribution::Uniform))
+}
+
+fn expected_rand_values(partition: usize) -> Result<Vec<f64>> {
+    let batch = RecordBatch::try_new_with_options(
+        Arc::new(Schema::empty()),
+        vec![],
+        &RecordBatchOptions::new().with_row_count(Some(RAND_TEST_NUM_ROWS)),
+    )?;
+    let values = bind_partition(&rand_expr(), partition)?
+        .evaluate(&batch)?
+        .into_array(RAND_TEST_NUM_ROWS)?;
+    Ok(values.as_primitive::<Float64Type>().values().to_vec())
+}
+
+async fn execute_partition(
+    plan: Arc<dyn ExecutionPlan>,
+    partition: usize,
+) -> Result<Vec<RecordBatch>> {
+    MemManager::init(1000000);
+    let session_ctx = SessionContext::new();
+    common::collect(plan.execute(partition, session_ctx.task_ctx())?).await
+}
+
+#[tokio::test]
+async fn test_rand_bound_in_sort() -> Result<()> {
+    let input = build_rand_test_input()?;
+    let sort = Arc::new(SortExec::new(
+        input,
+        vec![PhysicalSortExpr {
+            expr: rand_expr(),
+            options: Default::default(),
+        }],
+        None,
+    ));
+    let batches = execute_partition(sort, 1).await?;
+
+    let rand_values = expected_rand_values(1)?;
+    let mut expected = (0..RAND_TEST_NUM_ROWS as i32).collect::<Vec<_>>();
+    expected.sort_by(|&i, &j| rand_values[i as usize].total_cmp(&rand_values[j as usize]));
+    let output = batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(0)
+                .as_primitive::<Int32Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(output, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rand_bound_in_agg() -> Result<()> {
+    let input = build_rand_test_input()?;
+    let input_schema = input.schema();
+    let agg = Arc::new(AggExec::try_new(
+        HashAgg,
+        vec![GroupingExpr {
+            field_name: "r".to_string(),
+            expr: rand_expr(),
+        }],
+        vec![AggExpr {
+            field_name: "cnt".to_string(),
+            mode: Partial,
+            agg: create_agg(
+                AggFunction::Count,
+                &[phys_expr::col("a", &input_schema)?],
+                &input_schema,
+                DataType::Int64,
+            )?,
+        }],
+        false,
+        input,
+    )?);
+    let batches = execute_partition(agg, 1).await?;
+
+    let mut expected = expected_rand_values(1)?;
+    expected.sort_by(f64::total_cmp);
+    let mut output = batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(0)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    output.sort_by(f64::total_cmp);
+    assert_eq!(output, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rand_bound_in_window() -> Result<()> {
+    let input = build_rand_test_input()?;
+    let input_schema = input.schema();
+    let window = Arc::new(WindowExec::try_new(
+        input,
+        vec![WindowExpr::new(
+            WindowFunction::Agg(AggFunction::Max),
+            vec![rand_expr()],
+            Arc::new(Field::new("running_max", DataType::Float64, true)),
+            DataType::Float64,
+        )],
+        vec![],
+        vec![PhysicalSortExpr {
+            expr: phys_expr::col("a", &input_schema)?,
+            options: Default::default(),
+        }],
+        None,
+        true,
+    )?);
+    let batches = execute_partition(window, 1).await?;
+
+    let expected = expected_rand_values(1)?
+        .into_iter()
+        .scan(f64::MIN, |running_max, v| {
+            *running_max = running_max.max(v);
+            Some(*running_max)
+        })
+        .collect::<Vec<_>>();
+    let output = batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(1)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(output, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rand_bound_in_generate() -> Result<()> {
+    let input = build_rand_test_input()?;
+    let input_schema = input.schema();
+
+    // uuid() is not a json object, all generated fields are null
+    let uuid: PhysicalExprRef = Arc::new(SparkUuidExpr::new(30));
+    let generator = create_generator(
+        &input_schema,
+        GenerateFunc::JsonTuple,
+        vec![uuid, phys_expr::lit("k")],
+    )?;
+    let generate = Arc::new(GenerateExec::try_new(
+        input,
+        generator,
+        vec![phys_expr::Column::new_with_schema("a", &input_schema)?],
+        Arc::new(Schema::new(vec![Field::new("k", DataType::Utf8, true)])),
+        false,
+    )?);
+    let batches = execute_partition(generate, 1).await?;
+    let num_output_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(num_output_rows, RAND_TEST_NUM_ROWS);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rand_bound_in_join() -> Result<()> {
+    // both sides are keyed by rand() of the same partition, so the keys of
+    // the i-th rows are equal
+    let left = build_rand_test_input()?;
+    let right = build_rand_test_input()?;
+    let join_schema = Arc::new(Schema::new(vec![
+        Field::new("a", DataType::Int32, false),
+        Field::new("b", DataType::Int32, false),
+    ]));
+    let join = Arc::new(BroadcastJoinExec::try_new(
+        join_schema,
+        left,
+        right,
+        vec![(rand_expr(), rand_expr())],
+        JoinType::Inner,
+        JoinSide::Right,
+        false,
+        None,
+    )?);
+    let batches = execute_partition(join, 1).await?;
+
+    let mut output = batches
+        .iter()
+        .flat_map(|batch| {
+            let a = batch.column(0).as_primitive::<Int32Type>();
+            let b = batch.column(1).as_primitive::<Int32Type>();
+            (0..batch.num_rows())
+                .map(|i| (a.value(i), b.value(i)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    output.sort();
+    let expected = (0..RAND_TEST_NUM_ROWS as i32)
+        .map(|i| (i, i))
+        .collect::<Vec<_>>();
+    assert_eq!(output, expected);
+    Ok(())
+}
+
+/// yields the batches of its input, then fails with the error made by
+/// `make_err`, or never finishes if `make_err` is not given.
+#[derive(Debug)]
+struct FailingExec {
+    input: Arc<dyn ExecutionPlan>,
+    make_err: Option<fn() -> DataFusionError>,
+    props: OnceCell<PlanProperties>,
+}
+
+impl FailingExec {
+    fn new(input: Arc<dyn ExecutionPlan>, make_err: Option<fn() -> DataFusionError>) -> Self {
+        Self {
+            input,
+            make_err,
+            props: OnceCell::new(),
+        }
+    }
+}
+
+impl DisplayAs for FailingExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "FailingExec")
+    }
+}
+
+impl ExecutionPlan for FailingExec {
+    fn name(&self) -> &str {
+        "FailingExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::new(children[0].clone(), self.make_err)))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let input = self.input.execute(partition, context)?;
+        let make_err = self.make_err;
+        let failed = futures::stream::once(async move {
+            match make_err {
+                Some(make_err) => Err(make_err()),
+                None => futures::future::pending().await,
+            }
+        });
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            input.chain(failed),
+        )))
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+/// runs scan -> failing -> filter -> sort, errors pass through the streams
+/// of two operators before reaching the caller
+fn build_failing_pipeline(
+    make_err: Option<fn() -> DataFusionError>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let input = build_table(("a", &vec![1, 2, 3]), ("b", &vec![4, 5, 6]), 2);
+    let failing: Arc<dyn ExecutionPlan> = Arc::new(FailingExec::new(input, make_err));
+    let predicate: PhysicalExprRef = Arc::new(BinaryExpr::new(
+        phys_expr::col("a", &failing.schema())?,
+        Operator::Gt,
+        phys_expr::lit(1),
+    ));
+    let filtered: Arc<dyn ExecutionPlan> = Arc::new(FilterExec::try_new(vec![predicate], failing)?);
+    Ok(Arc::new(SortExec::new(
+        filtered.clone(),
+        vec![PhysicalSortExpr {
+            expr: phys_expr::col("b", &filtered.schema())?,
+            options: Default::default(),
+        }],
+        None,
+    )))
+}
+
+fn corrupted_batch_error() -> DataFusionError {
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+    let cols: Vec<ArrayRef> = vec![Arc::new(Int32Array::from(vec![1, 2, 3]))];
+    let mut data = vec![];
+    write_one_batch_with_checksum(3, &cols, &mut data).expect("write error");
+    let last_idx = data.len() - 1;
+    data[last_idx] ^= 0x01;
+    read_one_batch_with_checksum(Cursor::new(data), &schema, true).expect_err("corrupted")
+}
+
+fn spill_write_error() -> DataFusionError {
+    let spill_metrics = SpillMetrics::new(&ExecutionPlanMetricsSet::new(), 0);
+    let spill_file = AppendableFileSpill::try_new(&spill_metrics).expect("spill error");
+    let mut earlier_run = spill_file.new_run();
+    let mut later_run = spill_file.new_run();
+    let mut writer = later_run.get_buf_writer();
+    writer.write_all(&[1, 2, 3]).expect("write error");
+    writer.flush().expect("flush error");
+    drop(writer);
+
+    // writing to an earlier run fails
+    let mut writer = earlier_run.get_buf_writer();
+    writer.write_all(&[4, 5, 6]).expect("write error");
+    writer.flush().expect_err("earlier run").into()
+}
+
+#[tokio::test]
+async fn test_errors_classified_through_operators() -> Result<()> {
+    let cases: Vec<(fn() -> DataFusionError, &str)> = vec![
+        (
+            || {
+                DataFusionError::ResourcesExhausted(
+                    "broadcast join hash map exceeded maxBytesPerExec".to_string(),
+                )
+            },
+            "OutOfMemory",
+        ),
+        (spill_write_error, "SpillIo"),
+        (corrupted_batch_error, "Corruption"),
+        (
+            || DataFusionError::Execution("unexpected error".to_string()),
+            "Internal",
+        ),
+    ];
+    for (make_err, expected_kind) in cases {
+        let plan = build_failing_pipeline(Some(make_err))?;
+        let err = execute_partition(plan, 0).await.unwrap_err();
+        let classified = BlazeError::classify(err);
+        assert_eq!(classified.kind_name(), expected_kind, "{classified}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unsupported_error_classified_through_shuffle_writer() -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("k", DataType::Int32, false),
+        Field::new("s", DataType::LargeUtf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])),
+            Arc::new(LargeStringArray::from(vec!["x", "y", "z"])),
+        ],
+    )?;
+    let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema.clone(), None)?);
+    let tmp_dir = tempfile::tempdir()?;
+    let shuffle = Arc::new(ShuffleWriterExec::try_new(
+        input,
+        Partitioning::HashPartitioning(vec![phys_expr::col("k", &schema)?], 2),
+        tmp_dir.path().join("data").to_string_lossy().to_string(),
+        tmp_dir.path().join("index").to_string_lossy().to_string(),
+    )?);
+    let err = execute_partition(shuffle, 0).await.unwrap_err();
+    let classified = BlazeError::classify(err);
+    assert_eq!(classified.kind_name(), "Unsupported", "{classified}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_error_classified_through_operators() -> Result<()> {
+    MemManager::init(1000000);
+    let plan = build_failing_pipeline(None)?;
+    let session_ctx = SessionContext::new();
+    let task_ctx = session_ctx.task_ctx();
+    let output = plan.execute(0, task_ctx.clone())?;
+
+    // the input never finishes, until the task is cancelled
+    cancel_all_tasks(&task_ctx);
+    let err = common::collect(output).await.unwrap_err();
+    let classified = BlazeError::classify(err);
+    assert_eq!(classified.kind_name(), "Cancelled", "{classified}");
+    Ok(())
+}
@@ -21,7 +21,10 @@ use arrow::datatypes::{Field, Fields, Schema, SchemaRef};
 use datafusion::{
     common::{Result, Statistics},
     execution::TaskContext,
-    physical_expr::{EquivalenceProperties, PhysicalExprRef},
+    physical_expr::{
+        equivalence::ProjectionMapping, expressions::Column, EquivalenceProperties, LexOrdering,
+        PhysicalExprRef, PhysicalSortExpr,
+    },
     physical_plan::{
         metrics::{ExecutionPlanMetricsSet, MetricsSet},
         DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
@@ -49,6 +52,7 @@ pub struct ProjectExec {
     input: Arc<dyn ExecutionPlan>,
     schema: SchemaRef,
     metrics: ExecutionPlanMetricsSet,
+    column_pruning: bool,
     props: OnceCell<PlanProperties>,
 }
 
@@ -75,9 +79,62 @@ impl ProjectExec {
             input,
             schema,
             metrics: ExecutionPlanMetricsSet::new(),
+            column_pruning: false,
             props: OnceCell::new(),
         })
     }
+
+    /// marks the project as inserted by column pruning, which has no
+    /// corresponding spark node and is skipped in the metric tree
+    pub fn with_column_pruning(mut self) -> Self {
+        self.column_pruning = true;
+        self
+    }
+
+    pub fn is_column_pruning(&self) -> bool {
+        self.column_pruning
+    }
+
+    pub fn named_exprs(&self) -> &[(PhysicalExprRef, String)] {
+        &self.expr
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    /// orderings of the input are kept on the projected columns, like
+    /// datafusion's ProjectionExec. orderings derived from other exprs are
+    /// dropped, since spark exprs may overflow and do not preserve orders.
+    fn output_equivalence_properties(&self) -> EquivalenceProperties {
+        let projected = match ProjectionMapping::try_new(&self.expr, &self.input.schema()) {
+            Ok(mapping) => self
+                .input
+                .equivalence_properties()
+                .project(&mapping, self.schema()),
+            Err(_) => return EquivalenceProperties::new(self.schema()),
+        };
+        let is_projected_column = |sort_expr: &PhysicalSortExpr| {
+            sort_expr
+                .expr
+                .as_any()
+                .downcast_ref::<Column>()
+                .is_some_and(|column| self.expr[column.index()].0.as_any().is::<Column>())
+        };
+        let orderings = projected
+            .oeq_class()
+            .iter()
+            .map(|ordering| {
+                ordering
+                    .iter()
+                    .take_while(|sort_expr| is_projected_column(sort_expr))
+                    .cloned()
+                    .collect::<LexOrdering>()
+            })
+            .filter(|ordering| !ordering.is_empty())
+            .collect::<Vec<_>>();
+        EquivalenceProperties::new_with_orderings(self.schema(), &orderings)
+    }
 }
 
 impl DisplayAs for ProjectExec {
@@ -109,7 +166,7 @@ impl ExecutionPlan for ProjectExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                self.output_equivalence_properties(),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -124,10 +181,9 @@ impl ExecutionPlan for ProjectExec {
         self: Arc<Self>,
         children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(Self::try_new(
-            self.expr.clone(),
-            children[0].clone(),
-        )?))
+        let mut project = Self::try_new(self.expr.clone(), children[0].clone())?;
+        project.column_pruning = self.column_pruning;
+        Ok(Arc::new(project))
     }
 
     fn execute(
@@ -172,6 +228,7 @@ impl ExecuteWithColumnPruning for ProjectExec {
             expr: projection.iter().map(|&i| self.expr[i].clone()).collect(),
             schema: Arc::new(self.schema.project(projection)?),
             metrics: self.metrics.clone(),
+            column_pruning: self.column_pruning,
             props: OnceCell::new(),
         });
         projected_project.execute(partition, context)
@@ -198,6 +255,10 @@ fn execute_project_with_filtering(
         .cloned()
         .collect::<Vec<PhysicalExprRef>>();
 
+    // bind random exprs to the current partition
+    let filters = exec_ctx.bind_partition_exprs(&filters)?;
+    let exprs = exec_ctx.bind_partition_exprs(&exprs)?;
+
     let cached_expr_evaluator = Arc::new(CachedExprsEvaluator::try_new(
         filters,
         exprs,
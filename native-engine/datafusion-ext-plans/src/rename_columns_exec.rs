@@ -22,7 +22,9 @@ use async_trait::async_trait;
 use datafusion::{
     error::Result,
     execution::context::TaskContext,
-    physical_expr::EquivalenceProperties,
+    physical_expr::{
+        equivalence::ProjectionMapping, expressions::Column, EquivalenceProperties, PhysicalExprRef,
+    },
     physical_plan::{
         metrics::{ExecutionPlanMetricsSet, MetricsSet},
         stream::RecordBatchStreamAdapter,
@@ -88,6 +90,28 @@ impl RenameColumnsExec {
             props: OnceCell::new(),
         })
     }
+
+    /// orderings of the input are kept on the renamed columns
+    fn output_equivalence_properties(&self) -> EquivalenceProperties {
+        let input_schema = self.input.schema();
+        let renaming = input_schema
+            .fields()
+            .iter()
+            .zip(&self.renamed_column_names)
+            .enumerate()
+            .map(|(i, (field, new_name))| {
+                let col: PhysicalExprRef = Arc::new(Column::new(field.name(), i));
+                (col, new_name.clone())
+            })
+            .collect::<Vec<_>>();
+        match ProjectionMapping::try_new(&renaming, &input_schema) {
+            Ok(mapping) => self
+                .input
+                .equivalence_properties()
+                .project(&mapping, self.schema()),
+            Err(_) => EquivalenceProperties::new(self.schema()),
+        }
+    }
 }
 
 impl DisplayAs for RenameColumnsExec {
@@ -113,7 +137,7 @@ impl ExecutionPlan for RenameColumnsExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                self.output_equivalence_properties(),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -0,0 +1,178 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use datafusion::{
+    common::Result,
+    datasource::listing::PartitionedFile,
+    physical_expr::{expressions::Column, PhysicalExprRef},
+    physical_plan::Partitioning,
+};
+use datafusion_ext_commons::df_execution_err;
+
+/// bucketing of a spark bucketed table. a row belongs to bucket
+/// pmod(murmur3_hash(bucket_columns, 42), num_buckets), which is the hash of
+/// spark's bucketing and HashPartitioning. partition i of the scan reads files
+/// of bucket i, or of buckets i, i+n, i+2n... if buckets are coalesced into n
+/// partitions.
+#[derive(Debug, Clone)]
+pub struct BucketSpec {
+    pub bucket_columns: Vec<String>,
+    pub num_buckets: usize,
+}
+
+/// bucket id of a scanned file, stored in extensions of the PartitionedFile
+#[derive(Debug, Clone, Copy)]
+pub struct BucketId(pub usize);
+
+impl BucketSpec {
+    pub fn new(bucket_columns: Vec<String>, num_buckets: usize) -> Self {
+        Self {
+            bucket_columns,
+            num_buckets,
+        }
+    }
+
+    /// hash partitioning on bucket columns if all of them are in the output
+    /// schema and buckets are evenly coalesced into partitions
+    pub fn output_partitioning(&self, schema: &SchemaRef, num_partitions: usize) -> Partitioning {
+        if num_partitions == 0 || self.num_buckets % num_partitions != 0 {
+            return Partitioning::UnknownPartitioning(num_partitions);
+        }
+        let exprs = self
+            .bucket_columns
+            .iter()
+            .map(|name| Ok(Arc::new(Column::new_with_schema(name, schema)?) as PhysicalExprRef))
+            .collect::<Result<Vec<_>>>();
+        match exprs {
+            Ok(exprs) => Partitioning::Hash(exprs, num_partitions),
+            Err(_) => Partitioning::UnknownPartitioning(num_partitions),
+        }
+    }
+
+    /// checks that all files of the partition belong to its buckets
+    pub fn verify_file_group(
+        &self,
+        partition: usize,
+        num_partitions: usize,
+        files: &[PartitionedFile],
+    ) -> Result<()> {
+        for file in files {
+            let bucket_id = file
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.downcast_ref::<BucketId>());
+            match bucket_id {
+                Some(&BucketId(bucket_id)) if bucket_id % num_partitions == partition => {}
+                Some(&BucketId(bucket_id)) => {
+                    return df_execution_err!(
+                        "bucketed scan: file of bucket {bucket_id} is assigned to partition \
+                         {partition}"
+                    );
+                }
+                None => {
+                    return df_execution_err!(
+                        "bucketed scan: missing bucket id of file in partition {partition}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        common::Result, datasource::listing::PartitionedFile, physical_plan::Partitioning,
+    };
+    use datafusion_ext_commons::spark_hash::create_murmur3_hashes;
+
+    use crate::scan::bucketing::{BucketId, BucketSpec};
+
+    // bucket ids of rows, same as spark writing bucketed tables
+    fn bucket_ids_of_batch(spec: &BucketSpec, batch: &RecordBatch) -> Result<Vec<usize>> {
+        let bucket_columns = spec
+            .bucket_columns
+            .iter()
+            .map(|name| Ok(batch.column(batch.schema().index_of(name)?).clone()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(create_murmur3_hashes(batch.num_rows(), &bucket_columns, 42)
+            .into_iter()
+            .map(|hash| hash.rem_euclid(spec.num_buckets as i32) as usize)
+            .collect())
+    }
+
+    #[test]
+    fn test_bucketing() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ]));
+        let spec = BucketSpec::new(vec!["b".to_string()], 8);
+
+        // bucket ids of spark: pmod(murmur3_hash(b, 42), 8)
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![0, 0, 0, 0])),
+                Arc::new(Int32Array::from(vec![1, 2, 3, 4])),
+            ],
+        )?;
+        assert_eq!(bucket_ids_of_batch(&spec, &batch)?, vec![3, 6, 3, 6]);
+
+        // partitioning
+        assert!(matches!(
+            spec.output_partitioning(&schema, 8),
+            Partitioning::Hash(exprs, 8) if exprs.len() == 1
+        ));
+        assert!(matches!(
+            spec.output_partitioning(&schema, 4),
+            Partitioning::Hash(_, 4)
+        ));
+        assert!(matches!(
+            spec.output_partitioning(&schema, 3),
+            Partitioning::UnknownPartitioning(3)
+        ));
+        let projected_schema = Arc::new(schema.project(&[0])?);
+        assert!(matches!(
+            spec.output_partitioning(&projected_schema, 8),
+            Partitioning::UnknownPartitioning(8)
+        ));
+
+        // file to partition assignment
+        let file_of_bucket = |bucket_id: usize| {
+            let mut file = PartitionedFile::new("f", 0);
+            file.extensions = Some(Arc::new(BucketId(bucket_id)));
+            file
+        };
+        spec.verify_file_group(3, 8, &[file_of_bucket(3), file_of_bucket(3)])?;
+        spec.verify_file_group(3, 4, &[file_of_bucket(3), file_of_bucket(7)])?;
+        assert!(spec
+            .verify_file_group(3, 8, &[file_of_bucket(3), file_of_bucket(4)])
+            .is_err());
+        assert!(spec
+            .verify_file_group(3, 8, &[PartitionedFile::new("f", 0)])
+            .is_err());
+        Ok(())
+    }
+}
@@ -24,6 +24,7 @@ use datafusion::{
 };
 use datafusion_ext_commons::df_execution_err;
 
+pub mod bucketing;
 pub mod internal_file_reader;
 
 #[derive(Debug)]
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 use arrow::record_batch::RecordBatch;
 use blaze_jni_bridge::{is_task_running, jni_call};
@@ -34,6 +34,7 @@ use parking_lot::Mutex;
 
 use crate::{
     common::{
+        io_encryption::IoEncryptionWriter,
         ipc_compression::IpcCompressionWriter,
         offsetted::{Offsetted, OffsettedMergeIterator},
         timer_helper::TimerHelper,
@@ -55,6 +56,7 @@ pub struct BufferedData {
     num_rows: usize,
     sorted_mem_used: usize,
     output_io_time: Time,
+    zstd_dict: Option<Arc<Vec<u8>>>,
 }
 
 impl BufferedData {
@@ -70,18 +72,23 @@ impl BufferedData {
             num_rows: 0,
             sorted_mem_used: 0,
             output_io_time,
+            zstd_dict: None,
         }
     }
 
     pub fn drain(&mut self) -> Self {
-        std::mem::replace(
-            self,
-            Self::new(
-                self.partitioning.clone(),
-                self.partition_id,
-                self.output_io_time.clone(),
-            ),
-        )
+        let mut drained = Self::new(
+            self.partitioning.clone(),
+            self.partition_id,
+            self.output_io_time.clone(),
+        );
+        drained.zstd_dict = self.zstd_dict.clone();
+        std::mem::replace(self, drained)
+    }
+
+    /// compresses written data with the zstd dictionary, kept after draining
+    pub fn set_zstd_dict(&mut self, zstd_dict: Arc<Vec<u8>>) {
+        self.zstd_dict = Some(zstd_dict);
     }
 
     pub fn add_batch(&mut self, batch: RecordBatch) -> Result<()> {
@@ -119,7 +126,17 @@ impl BufferedData {
 
     // write buffered data to spill/target file, returns uncompressed size and
     // offsets to each partition
-    pub fn write<W: Write>(mut self, mut w: W) -> Result<Vec<u64>> {
+    pub fn write<W: Write>(self, w: W) -> Result<Vec<u64>> {
+        self.write_with_io_encryption(w, None)
+    }
+
+    // same as write(), and each partition segment is encrypted as a separated
+    // stream if io encryption key is given
+    pub fn write_with_io_encryption<W: Write>(
+        mut self,
+        mut w: W,
+        io_encryption_key: Option<&[u8]>,
+    ) -> Result<Vec<u64>> {
         if self.num_rows == 0 {
             return Ok(vec![0; self.partitioning.partition_count() + 1]);
         }
@@ -133,7 +150,13 @@ impl BufferedData {
 
         let output_io_time = self.output_io_time.clone();
         let num_partitions = self.partitioning.partition_count();
-        let mut writer = IpcCompressionWriter::new(CountWrite::from(&mut w));
+        let mut writer = IpcCompressionWriter::new(IoEncryptionWriter::new_unstarted(
+            CountWrite::from(&mut w),
+            io_encryption_key,
+        ));
+        if let Some(zstd_dict) = self.zstd_dict.clone() {
+            writer = writer.with_zstd_dict(zstd_dict)?;
+        }
         let mut offsets = vec![];
         let mut iter = self.into_sorted_batches()?;
 
@@ -142,14 +165,18 @@ impl BufferedData {
                 df_execution_err!("task completed/killed")?;
             }
 
-            offsets.resize(partition_id + 1, writer.inner().count());
+            // each partition segment is a separate stream, which may be
+            // concatenated with segments of other spills
+            output_io_time.with_timer(|| writer.start_new_stream())?;
+            offsets.resize(partition_id + 1, writer.inner().inner().count());
+            output_io_time.with_timer(|| writer.inner_mut().start_new_stream())?;
             for batch in batch_iter {
                 output_io_time
                     .with_timer(|| writer.write_batch(batch.num_rows(), batch.columns()))?;
             }
             output_io_time.with_timer(|| writer.finish_current_buf())?;
         }
-        offsets.resize(num_partitions + 1, writer.inner().count());
+        offsets.resize(num_partitions + 1, writer.inner().inner().count());
 
         let compressed_size = ByteSize(offsets.last().cloned().unwrap_or_default());
         log::info!("all buffered data drained, compressed_size={compressed_size}");
@@ -351,7 +378,10 @@ fn sort_batches_by_partition_id(
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::{
+        io::{Cursor, Read},
+        sync::Arc,
+    };
 
     use arrow::{
         array::{ArrayRef, Int32Array},
@@ -367,6 +397,7 @@ mod test {
     };
 
     use super::*;
+    use crate::common::{io_encryption::IoEncryptionReader, ipc_compression::IpcCompressionReader};
 
     fn build_table_i32(
         a: (&str, &Vec<i32>),
@@ -422,6 +453,42 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_write_with_io_encryption() -> Result<()> {
+        let record_batch = build_table_i32(
+            ("a", &vec![19, 18, 17, 16, 15, 14, 13, 12, 11, 10]),
+            ("b", &vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            ("c", &vec![5, 6, 7, 8, 9, 0, 1, 2, 3, 4]),
+        );
+        let schema = record_batch.schema();
+        let key = [7u8; 16];
+        let mut data = BufferedData::new(Partitioning::RoundRobinPartitioning(4), 0, Time::new());
+        data.add_batch(record_batch)?;
+
+        let mut output = vec![];
+        let offsets = data.write_with_io_encryption(&mut output, Some(&key))?;
+        assert_eq!(offsets.len(), 5);
+        assert_eq!(offsets[4] as usize, output.len());
+
+        // each partition segment is decrypted independently
+        let mut num_rows = vec![];
+        for i in 0..4 {
+            let segment = &output[offsets[i] as usize..offsets[i + 1] as usize];
+            let mut decrypted = vec![];
+            IoEncryptionReader::try_new(Cursor::new(segment), Some(&key))?
+                .read_to_end(&mut decrypted)?;
+            let mut reader = IpcCompressionReader::new(Cursor::new(decrypted));
+            let mut partition_num_rows = 0;
+            while let Some((batch_num_rows, _)) = reader.read_batch(&schema)? {
+                partition_num_rows += batch_num_rows;
+            }
+            num_rows.push(partition_num_rows);
+        }
+        assert_eq!(num_rows.iter().sum::<usize>(), 10);
+        assert!(num_rows.iter().all(|&n| n == 2 || n == 3));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_range_partition() -> Result<()> {
         let record_batch = build_table_i32(
@@ -25,17 +25,21 @@ use tokio::sync::Mutex;
 
 use crate::{
     common::{
+        io_encryption::IoEncryptionWriter,
         ipc_compression::IpcCompressionWriter,
         timer_helper::{TimedWriter, TimerHelper},
     },
     shuffle::ShuffleRepartitioner,
 };
 
+type OutputWriter = IpcCompressionWriter<IoEncryptionWriter<TimedWriter<File>>>;
+
 pub struct SingleShuffleRepartitioner {
     output_data_file: String,
     output_index_file: String,
-    output_data: Arc<Mutex<Option<IpcCompressionWriter<TimedWriter<File>>>>>,
+    output_data: Arc<Mutex<Option<OutputWriter>>>,
     output_io_time: Time,
+    io_encryption_key: Option<Arc<Vec<u8>>>,
 }
 
 impl SingleShuffleRepartitioner {
@@ -45,23 +49,32 @@ impl SingleShuffleRepartitioner {
             output_index_file,
             output_data: Arc::new(Mutex::default()),
             output_io_time,
+            io_encryption_key: None,
         }
     }
 
+    /// encrypts the output data file in the format of spark's io encryption
+    pub fn with_io_encryption_key(mut self, io_encryption_key: Option<Arc<Vec<u8>>>) -> Self {
+        self.io_encryption_key = io_encryption_key;
+        self
+    }
+
     fn get_output_writer<'a>(
         &self,
-        output_data: &'a mut Option<IpcCompressionWriter<TimedWriter<File>>>,
-    ) -> Result<&'a mut IpcCompressionWriter<TimedWriter<File>>> {
+        output_data: &'a mut Option<OutputWriter>,
+    ) -> Result<&'a mut OutputWriter> {
         if output_data.is_none() {
-            *output_data = Some(IpcCompressionWriter::new(
-                self.output_io_time.wrap_writer(
-                    OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .truncate(true)
-                        .open(&self.output_data_file)?,
-                ),
-            ));
+            let output_file = self.output_io_time.wrap_writer(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&self.output_data_file)?,
+            );
+            *output_data = Some(IpcCompressionWriter::new(IoEncryptionWriter::try_new(
+                output_file,
+                self.io_encryption_key.as_deref().map(|key| key.as_slice()),
+            )?));
         }
         Ok(output_data.as_mut().unwrap())
     }
@@ -89,7 +102,7 @@ impl ShuffleRepartitioner for SingleShuffleRepartitioner {
                     .open(&self.output_index_file)?,
             );
             output_writer.finish_current_buf()?;
-            let offset = output_writer.inner_mut().0.stream_position()?;
+            let offset = output_writer.inner_mut().inner_mut().0.stream_position()?;
             output_index.write_all(&[0u8; 8])?;
             output_index.write_all(&(offset as i64).to_le_bytes()[..])?;
         } else {
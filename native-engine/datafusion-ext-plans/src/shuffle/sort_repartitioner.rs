@@ -20,7 +20,12 @@ use std::{
 
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, IntConf},
+};
 use bytesize::ByteSize;
+use count_write::CountWrite;
 use datafusion::{
     common::{DataFusionError, Result},
     physical_plan::metrics::Time,
@@ -31,6 +36,8 @@ use futures::lock::Mutex;
 use crate::{
     common::{
         execution_context::ExecutionContext,
+        io_encryption::IoEncryptionWriter,
+        ipc_compression::ZstdDictTrainer,
         offsetted::{Offsetted, OffsettedMergeIterator},
         timer_helper::TimerHelper,
     },
@@ -50,6 +57,8 @@ pub struct SortShuffleRepartitioner {
     spills: Mutex<Vec<Offsetted<u64, Box<dyn Spill>>>>,
     num_output_partitions: usize,
     output_io_time: Time,
+    zstd_dict_trainer: Mutex<Option<ZstdDictTrainer>>,
+    io_encryption_key: Option<Arc<Vec<u8>>>,
 }
 
 impl SortShuffleRepartitioner {
@@ -62,6 +71,13 @@ impl SortShuffleRepartitioner {
     ) -> Self {
         let partition_id = exec_ctx.partition_id();
         let num_output_partitions = partitioning.partition_count();
+        let zstd_dict_trainer = conf::SHUFFLE_ZSTD_DICT_ENABLE
+            .value()
+            .unwrap_or(false)
+            .then(|| {
+                let dict_size = conf::SHUFFLE_ZSTD_DICT_SIZE.value().unwrap_or(16384);
+                ZstdDictTrainer::new(dict_size as usize)
+            });
         Self {
             exec_ctx,
             mem_consumer_info: None,
@@ -75,6 +91,39 @@ impl SortShuffleRepartitioner {
             spills: Mutex::default(),
             num_output_partitions,
             output_io_time,
+            zstd_dict_trainer: Mutex::new(zstd_dict_trainer),
+            io_encryption_key: None,
+        }
+    }
+
+    /// encrypts each partition of the output data file as a separated stream,
+    /// in the format of spark's io encryption
+    pub fn with_io_encryption_key(mut self, io_encryption_key: Option<Arc<Vec<u8>>>) -> Self {
+        self.io_encryption_key = io_encryption_key;
+        self
+    }
+
+    // trains zstd dict with collected samples, which is used by all data
+    // written after training
+    async fn train_zstd_dict(&self) {
+        let Some(trainer) = self.zstd_dict_trainer.lock().await.take() else {
+            return;
+        };
+        match trainer.train() {
+            Ok(zstd_dict) => {
+                log::info!(
+                    "{} trained zstd dict, size={}",
+                    self.name(),
+                    ByteSize(zstd_dict.len() as u64),
+                );
+                self.data.lock().await.set_zstd_dict(Arc::new(zstd_dict));
+            }
+            Err(err) => {
+                log::warn!(
+                    "{} failed training zstd dict, compressing without dict: {err}",
+                    self.name(),
+                );
+            }
         }
     }
 }
@@ -96,6 +145,7 @@ impl MemConsumer for SortShuffleRepartitioner {
     }
 
     async fn spill(&self) -> Result<()> {
+        self.train_zstd_dict().await;
         let data = self.data.lock().await.drain();
         let spill_metrics = self.exec_ctx.spill_metrics().clone();
         let spill = tokio::task::spawn_blocking(move || {
@@ -121,6 +171,15 @@ impl Drop for SortShuffleRepartitioner {
 #[async_trait]
 impl ShuffleRepartitioner for SortShuffleRepartitioner {
     async fn insert_batch(&self, input: RecordBatch) -> Result<()> {
+        // collect samples for training zstd dict
+        let zstd_dict_samples_collected = match &mut *self.zstd_dict_trainer.lock().await {
+            Some(trainer) => trainer.add_sample(&input),
+            None => false,
+        };
+        if zstd_dict_samples_collected {
+            self.train_zstd_dict().await;
+        }
+
         // update memory usage before adding to buffered data
         let mem_used = self.data.lock().await.mem_used() + input.get_batch_mem_size() * 2;
         self.update_mem_used(mem_used).await?;
@@ -150,6 +209,7 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
 
     async fn shuffle_write(&self) -> Result<()> {
         self.set_spillable(false);
+        self.train_zstd_dict().await;
         let mut spills = std::mem::take(&mut *self.spills.lock().await);
         let data = self.data.lock().await.drain();
 
@@ -166,6 +226,7 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
         // no spills - directly write current batches into final file
         if spills.is_empty() {
             let output_io_time = self.output_io_time.clone();
+            let io_encryption_key = self.io_encryption_key.clone();
             tokio::task::spawn_blocking(move || {
                 let output_io_time_cloned = output_io_time.clone();
                 let _output_io_timer = output_io_time_cloned.timer();
@@ -183,7 +244,12 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
 
                 // write data file
                 // exclude io timer because it is already included buffered_data.write()
-                let offsets = output_io_time.exclude_timer(|| data.write(&mut output_data))?;
+                let offsets = output_io_time.exclude_timer(|| {
+                    data.write_with_io_encryption(
+                        &mut output_data,
+                        io_encryption_key.as_deref().map(|key| key.as_slice()),
+                    )
+                })?;
 
                 // write index file
                 let mut offsets_data = vec![];
@@ -225,6 +291,7 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
         // append partition in each spills
         let num_output_partitions = self.num_output_partitions;
         let output_io_time = self.output_io_time.clone();
+        let io_encryption_key = self.io_encryption_key.clone();
         tokio::task::spawn_blocking(move || {
             let _output_io_timer = output_io_time.timer();
             let mut output_data = OpenOptions::new()
@@ -246,15 +313,38 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
                     .collect(),
             );
 
-            while let Some((_partition_id, reader, range)) = merge_iter.next() {
-                let mut reader = reader.buf_reader().take(range.end - range.start);
-                std::io::copy(&mut reader, &mut output_data)?;
-            }
-            let offsets = merge_iter.merged_offsets();
+            let offsets = match &io_encryption_key {
+                None => {
+                    while let Some((_partition_id, reader, range)) = merge_iter.next() {
+                        let mut reader = reader.buf_reader().take(range.end - range.start);
+                        std::io::copy(&mut reader, &mut output_data)?;
+                    }
+                    merge_iter.merged_offsets().to_vec()
+                }
+                Some(key) => {
+                    // chunks of the same partition are encrypted as one stream
+                    let mut offsets = vec![];
+                    let mut offset = 0;
+                    while let Some((partition_id, chunks)) = merge_iter.next_partition_chunk() {
+                        offsets.resize(partition_id + 1, offset);
+                        let mut encrypted = IoEncryptionWriter::try_new(
+                            CountWrite::from(&mut output_data),
+                            Some(key),
+                        )?;
+                        for (reader, range) in chunks {
+                            let mut reader = reader.buf_reader().take(range.end - range.start);
+                            std::io::copy(&mut reader, &mut encrypted)?;
+                        }
+                        offset += encrypted.into_inner().count();
+                    }
+                    offsets.resize(num_output_partitions + 1, offset);
+                    offsets
+                }
+            };
 
             // write index file
             let mut offsets_data = vec![];
-            for &offset in offsets {
+            for offset in offsets {
                 offsets_data.extend_from_slice(&(offset as i64).to_le_bytes()[..]);
             }
             output_index.write_all(&offsets_data)?;
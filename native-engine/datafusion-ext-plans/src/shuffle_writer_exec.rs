@@ -33,7 +33,7 @@ use datafusion_ext_commons::df_execution_err;
 use once_cell::sync::OnceCell;
 
 use crate::{
-    common::execution_context::ExecutionContext,
+    common::{execution_context::ExecutionContext, io_encryption::io_encryption_key},
     memmgr::MemManager,
     shuffle::{
         single_repartitioner::SingleShuffleRepartitioner,
@@ -113,23 +113,30 @@ impl ExecutionPlan for ShuffleWriterExec {
         let exec_ctx =
             ExecutionContext::new(context.clone(), partition, self.schema(), &self.metrics);
         let output_time = exec_ctx.register_timer_metric("output_io_time");
+        let io_encryption_key = io_encryption_key()?;
 
         let mut input = self.input.clone();
 
         let repartitioner: Arc<dyn ShuffleRepartitioner> = match &self.partitioning {
-            p if p.partition_count() == 1 => Arc::new(SingleShuffleRepartitioner::new(
-                self.output_data_file.clone(),
-                self.output_index_file.clone(),
-                output_time,
-            )),
-            Partitioning::HashPartitioning(..) | Partitioning::RangePartitioning(..) => {
-                let partitioner = Arc::new(SortShuffleRepartitioner::new(
-                    exec_ctx.clone(),
+            p if p.partition_count() == 1 => Arc::new(
+                SingleShuffleRepartitioner::new(
                     self.output_data_file.clone(),
                     self.output_index_file.clone(),
-                    self.partitioning.clone(),
                     output_time,
-                ));
+                )
+                .with_io_encryption_key(io_encryption_key),
+            ),
+            Partitioning::HashPartitioning(..) | Partitioning::RangePartitioning(..) => {
+                let partitioner = Arc::new(
+                    SortShuffleRepartitioner::new(
+                        exec_ctx.clone(),
+                        self.output_data_file.clone(),
+                        self.output_index_file.clone(),
+                        self.partitioning.clone(),
+                        output_time,
+                    )
+                    .with_io_encryption_key(io_encryption_key),
+                );
                 MemManager::register_consumer(partitioner.clone(), true);
                 partitioner
             }
@@ -149,13 +156,16 @@ impl ExecutionPlan for ShuffleWriterExec {
                     None,
                     false, // do not record output metric
                 );
-                let partitioner = Arc::new(SortShuffleRepartitioner::new(
-                    exec_ctx.clone(),
-                    self.output_data_file.clone(),
-                    self.output_index_file.clone(),
-                    self.partitioning.clone(),
-                    output_time,
-                ));
+                let partitioner = Arc::new(
+                    SortShuffleRepartitioner::new(
+                        exec_ctx.clone(),
+                        self.output_data_file.clone(),
+                        self.output_index_file.clone(),
+                        self.partitioning.clone(),
+                        output_time,
+                    )
+                    .with_io_encryption_key(io_encryption_key),
+                );
                 MemManager::register_consumer(partitioner.clone(), true);
                 partitioner
             }
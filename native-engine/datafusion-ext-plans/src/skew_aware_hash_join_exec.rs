@@ -0,0 +1,654 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, RecordBatch},
+    compute::{filter, filter_record_batch, not, SortOptions},
+    datatypes::{DataType, SchemaRef},
+};
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, IntConf},
+};
+use datafusion::{
+    common::{JoinSide, Result, Statistics},
+    execution::context::TaskContext,
+    physical_expr::{EquivalenceProperties, PhysicalExprRef},
+    physical_plan::{
+        joins::utils::JoinOn,
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        stream::RecordBatchStreamAdapter,
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::{
+    arrow::{array_size::BatchSize, coalesce::coalesce_batches_unchecked},
+    batch_size, df_execution_err,
+};
+use futures::StreamExt;
+use hashbrown::HashSet;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    broadcast_join_exec::{create_joiner, execute_sort_merge_join_with_sorted_built, Joiner},
+    common::{
+        column_pruning::ExecuteWithColumnPruning,
+        execution_context::{ExecutionContext, WrappedRecordBatchSender},
+        stream_exec::create_record_batch_stream_exec,
+        timer_helper::TimerHelper,
+    },
+    joins::{
+        heavy_hitters::detect_heavy_hitters,
+        join_hash_map::{join_create_hashes, JoinHashMap},
+        join_utils::JoinType,
+        JoinParams, JoinProjection,
+    },
+    sort_exec::create_default_ascending_sort_exec,
+};
+
+/// shuffled hash join for skewed build side.
+///
+/// keys taking more than `heavy_hitter_fraction` of build rows are detected
+/// with a frequency sketch while building, rows of these keys are moved into
+/// a dedicated heavy hitter map so that the regular map is sized for the
+/// remaining keys only. probe rows are routed to one of the two maps by key
+/// hash, so every probe row meets all of its matching build rows in exactly
+/// one map and all join types are supported.
+#[derive(Debug)]
+pub struct SkewAwareHashJoinExec {
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    on: JoinOn,
+    join_type: JoinType,
+    broadcast_side: JoinSide,
+    schema: SchemaRef,
+    heavy_hitter_fraction: f64,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl SkewAwareHashJoinExec {
+    pub fn try_new(
+        schema: SchemaRef,
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: JoinOn,
+        join_type: JoinType,
+        broadcast_side: JoinSide,
+        heavy_hitter_fraction: f64,
+    ) -> Result<Self> {
+        if !(heavy_hitter_fraction > 0.0 && heavy_hitter_fraction < 1.0) {
+            return df_execution_err!(
+                "heavy hitter fraction must be in (0, 1), got {heavy_hitter_fraction}"
+            );
+        }
+        Ok(Self {
+            left,
+            right,
+            on,
+            join_type,
+            broadcast_side,
+            schema,
+            heavy_hitter_fraction,
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+
+    fn create_join_params(&self, projection: &[usize]) -> Result<JoinParams> {
+        let left_schema = self.left.schema();
+        let right_schema = self.right.schema();
+        let (left_keys, right_keys): (Vec<PhysicalExprRef>, Vec<PhysicalExprRef>) =
+            self.on.iter().cloned().unzip();
+        let key_data_types: Vec<DataType> = self
+            .on
+            .iter()
+            .map(|(left_key, right_key)| {
+                Ok({
+                    let left_dt = left_key.data_type(&left_schema)?;
+                    let right_dt = right_key.data_type(&right_schema)?;
+                    if left_dt != right_dt {
+                        df_execution_err!(
+                            "join key data type differs {left_dt:?} <-> {right_dt:?}"
+                        )?;
+                    }
+                    left_dt
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let projection = JoinProjection::try_new(
+            self.join_type,
+            &self.schema,
+            &left_schema,
+            &right_schema,
+            projection,
+        )?;
+        Ok(JoinParams {
+            join_type: self.join_type,
+            left_schema,
+            right_schema,
+            output_schema: self.schema(),
+            left_keys,
+            right_keys,
+            batch_size: batch_size(),
+            sort_options: vec![SortOptions::default(); self.on.len()],
+            projection,
+            key_data_types,
+            fetch: None,
+        })
+    }
+
+    fn execute_with_projection(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+        projection: Vec<usize>,
+    ) -> Result<SendableRecordBatchStream> {
+        let join_params = self.create_join_params(&projection)?;
+        let exec_ctx = ExecutionContext::new(
+            context,
+            partition,
+            join_params.projection.schema.clone(),
+            &self.metrics,
+        );
+        let join_params = join_params.bind_partition(&exec_ctx)?;
+        let left = self.left.clone();
+        let right = self.right.clone();
+        let broadcast_side = self.broadcast_side;
+        let heavy_hitter_fraction = self.heavy_hitter_fraction;
+
+        let exec_ctx_cloned = exec_ctx.clone();
+        let output_stream =
+            exec_ctx_cloned
+                .clone()
+                .output_with_sender("SkewAwareHashJoin", move |sender| {
+                    sender.exclude_time(exec_ctx_cloned.baseline_metrics().elapsed_compute());
+                    execute_skew_aware_join(
+                        left,
+                        right,
+                        join_params,
+                        broadcast_side,
+                        heavy_hitter_fraction,
+                        exec_ctx_cloned,
+                        sender,
+                    )
+                });
+        Ok(exec_ctx.coalesce_with_default_batch_size(output_stream))
+    }
+}
+
+impl ExecuteWithColumnPruning for SkewAwareHashJoinExec {
+    fn execute_projected(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+        projection: &[usize],
+    ) -> Result<SendableRecordBatchStream> {
+        self.execute_with_projection(partition, context, projection.to_vec())
+    }
+}
+
+impl ExecutionPlan for SkewAwareHashJoinExec {
+    fn name(&self) -> &str {
+        "SkewAwareHashJoin"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                match self.broadcast_side {
+                    JoinSide::Left => self.right.output_partitioning().clone(),
+                    JoinSide::Right => self.left.output_partitioning().clone(),
+                },
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.left, &self.right]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            self.schema.clone(),
+            children[0].clone(),
+            children[1].clone(),
+            self.on.iter().cloned().collect(),
+            self.join_type,
+            self.broadcast_side,
+            self.heavy_hitter_fraction,
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let projection = (0..self.schema.fields().len()).collect();
+        self.execute_with_projection(partition, context, projection)
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        unimplemented!()
+    }
+}
+
+impl DisplayAs for SkewAwareHashJoinExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "SkewAwareHashJoin")
+    }
+}
+
+/// build side of skew-aware hash join, rows are split into the heavy hitter
+/// map and the regular map by key hash
+pub struct SkewAwareJoinHashMaps {
+    heavy_hitters: HashSet<u32>,
+    heavy_map: Arc<JoinHashMap>,
+    regular_map: Arc<JoinHashMap>,
+}
+
+impl SkewAwareJoinHashMaps {
+    pub fn try_new(
+        data_batch: RecordBatch,
+        key_exprs: &[PhysicalExprRef],
+        heavy_hitter_fraction: f64,
+    ) -> Result<Self> {
+        let num_rows = data_batch.num_rows();
+        let key_columns: Vec<ArrayRef> = key_exprs
+            .iter()
+            .map(|expr| Ok(expr.evaluate(&data_batch)?.into_array(num_rows)?))
+            .collect::<Result<_>>()?;
+        let hashes = join_create_hashes(num_rows, &key_columns);
+        let heavy_hitters = detect_heavy_hitters(&hashes, heavy_hitter_fraction);
+
+        // no skewed keys, all rows go to the regular map
+        if heavy_hitters.is_empty() {
+            let empty_batch = RecordBatch::new_empty(data_batch.schema());
+            let heavy_map = JoinHashMap::create_from_data_batch(empty_batch, key_exprs)?;
+            let regular_map =
+                JoinHashMap::create_from_data_batch_and_hashes(data_batch, key_columns, hashes)?;
+            return Ok(Self {
+                heavy_hitters,
+                heavy_map: Arc::new(heavy_map),
+                regular_map: Arc::new(regular_map),
+            });
+        }
+
+        let is_heavy = heavy_hitters_mask(&heavy_hitters, &hashes);
+        let create_map = |selection: &BooleanArray| -> Result<Arc<JoinHashMap>> {
+            let selected_batch = filter_record_batch(&data_batch, selection)?;
+            let selected_key_columns = key_columns
+                .iter()
+                .map(|key_column| filter(key_column, selection))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let selected_hashes = hashes
+                .iter()
+                .zip(selection.values())
+                .filter(|(_, selected)| *selected)
+                .map(|(&hash, _)| hash)
+                .collect();
+            Ok(Arc::new(JoinHashMap::create_from_data_batch_and_hashes(
+                selected_batch,
+                selected_key_columns,
+                selected_hashes,
+            )?))
+        };
+        let heavy_map = create_map(&is_heavy)?;
+        let regular_map = create_map(&not(&is_heavy)?)?;
+        Ok(Self {
+            heavy_hitters,
+            heavy_map,
+            regular_map,
+        })
+    }
+
+    pub fn num_heavy_hitters(&self) -> usize {
+        self.heavy_hitters.len()
+    }
+
+    pub fn heavy_map(&self) -> &Arc<JoinHashMap> {
+        &self.heavy_map
+    }
+
+    pub fn regular_map(&self) -> &Arc<JoinHashMap> {
+        &self.regular_map
+    }
+}
+
+fn heavy_hitters_mask(heavy_hitters: &HashSet<u32>, hashes: &[u32]) -> BooleanArray {
+    hashes
+        .iter()
+        .map(|hash| Some(heavy_hitters.contains(hash)))
+        .collect()
+}
+
+async fn execute_skew_aware_join(
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    join_params: JoinParams,
+    broadcast_side: JoinSide,
+    heavy_hitter_fraction: f64,
+    exec_ctx: Arc<ExecutionContext>,
+    sender: Arc<WrappedRecordBatchSender>,
+) -> Result<()> {
+    let elapsed_compute = exec_ctx.baseline_metrics().elapsed_compute().clone();
+    let _timer = elapsed_compute.timer();
+
+    let build_time = exec_ctx.register_timer_metric("build_hash_map_time");
+    let probed_side_hash_time = exec_ctx.register_timer_metric("probed_side_hash_time");
+    let probed_side_search_time = exec_ctx.register_timer_metric("probed_side_search_time");
+    let probed_side_compare_time = exec_ctx.register_timer_metric("probed_side_compare_time");
+    let build_output_time = exec_ctx.register_timer_metric("build_output_time");
+    let num_heavy_hitters = exec_ctx.register_counter_metric("num_heavy_hitters");
+    let num_heavy_hitter_rows = exec_ctx.register_counter_metric("num_heavy_hitter_rows");
+
+    let (probed_plan, built_plan) = match broadcast_side {
+        JoinSide::Left => (right, left),
+        JoinSide::Right => (left, right),
+    };
+    let (map_keys, probed_keys) = match broadcast_side {
+        JoinSide::Left => (
+            join_params.left_keys.clone(),
+            join_params.right_keys.clone(),
+        ),
+        JoinSide::Right => (
+            join_params.right_keys.clone(),
+            join_params.left_keys.clone(),
+        ),
+    };
+
+    // collect build side and split it into heavy hitter/regular maps, falls
+    // back to sort merge join if the build side is too large, like shuffled
+    // hash joins do
+    let smj_fallback_enabled = conf::SMJ_FALLBACK_ENABLE.value().unwrap_or(false);
+    let smj_fallback_rows_threshold = conf::SMJ_FALLBACK_ROWS_THRESHOLD
+        .value()
+        .unwrap_or(i32::MAX) as usize;
+    let smj_fallback_mem_threshold = conf::SMJ_FALLBACK_MEM_SIZE_THRESHOLD
+        .value()
+        .unwrap_or(i32::MAX) as usize;
+
+    let mut built_input = exec_ctx.stat_input(exec_ctx.execute(&built_plan)?);
+    let built_schema = built_input.schema();
+    let mut built_batches = vec![];
+    let mut built_num_rows = 0;
+    let mut built_mem_size = 0;
+    while let Some(batch) = elapsed_compute
+        .exclude_timer_async(built_input.next())
+        .await
+        .transpose()?
+    {
+        built_num_rows += batch.num_rows();
+        built_mem_size += batch.get_batch_mem_size();
+        built_batches.push(batch);
+
+        if smj_fallback_enabled
+            && (built_num_rows > smj_fallback_rows_threshold
+                || built_mem_size > smj_fallback_mem_threshold)
+        {
+            drop(_timer);
+            let built_input: SendableRecordBatchStream = Box::pin(RecordBatchStreamAdapter::new(
+                built_schema,
+                futures::stream::iter(built_batches.into_iter().map(Ok)).chain(built_input),
+            ));
+            let built_sorted = create_default_ascending_sort_exec(
+                create_record_batch_stream_exec(built_input, exec_ctx.partition_id())?,
+                &map_keys,
+                Some(exec_ctx.execution_plan_metrics().clone()),
+                false, // do not record output metric
+            );
+            return execute_sort_merge_join_with_sorted_built(
+                probed_plan,
+                built_sorted,
+                join_params,
+                broadcast_side,
+                exec_ctx,
+                sender,
+            )
+            .await;
+        }
+    }
+    let maps = build_time.with_timer(|| {
+        let data_batch =
+            coalesce_batches_unchecked(built_schema, &std::mem::take(&mut built_batches));
+        SkewAwareJoinHashMaps::try_new(data_batch, &map_keys, heavy_hitter_fraction)
+    })?;
+    num_heavy_hitters.add(maps.num_heavy_hitters());
+    num_heavy_hitter_rows.add(maps.heavy_map.data_batch().num_rows());
+
+    let mut heavy_joiner = create_joiner(
+        join_params.clone(),
+        maps.heavy_map.clone(),
+        broadcast_side,
+        sender.clone(),
+    );
+    let mut regular_joiner = create_joiner(
+        join_params,
+        maps.regular_map.clone(),
+        broadcast_side,
+        sender,
+    );
+
+    let mut probed = exec_ctx.stat_input(exec_ctx.execute(&probed_plan)?);
+    while !(heavy_joiner.can_early_stop() && regular_joiner.can_early_stop())
+        && let Some(batch) = elapsed_compute
+            .exclude_timer_async(probed.next())
+            .await
+            .transpose()?
+    {
+        // empty probed batches never produce output
+        if batch.num_rows() == 0 {
+            continue;
+        }
+
+        // route probed rows by key hash, so that they are probed against the
+        // map containing all build rows of the same key
+        let (heavy_batch, regular_batch) = if maps.heavy_hitters.is_empty() {
+            (None, batch)
+        } else {
+            let is_heavy = probed_side_hash_time.with_timer(|| -> Result<_> {
+                let probed_key_columns = probed_keys
+                    .iter()
+                    .map(|expr| Ok(expr.evaluate(&batch)?.into_array(batch.num_rows())?))
+                    .collect::<Result<Vec<_>>>()?;
+                let probed_hashes = join_create_hashes(batch.num_rows(), &probed_key_columns);
+                Ok(heavy_hitters_mask(&maps.heavy_hitters, &probed_hashes))
+            })?;
+            let heavy_batch = filter_record_batch(&batch, &is_heavy)?;
+            let regular_batch = filter_record_batch(&batch, &not(&is_heavy)?)?;
+            (Some(heavy_batch), regular_batch)
+        };
+
+        for (joiner, batch) in [
+            (&mut heavy_joiner, heavy_batch),
+            (&mut regular_joiner, Some(regular_batch)),
+        ] {
+            if let Some(batch) = batch.filter(|batch| batch.num_rows() > 0)
+                && !joiner.can_early_stop()
+            {
+                joiner
+                    .as_mut()
+                    .join(
+                        batch,
+                        &probed_side_hash_time,
+                        &probed_side_search_time,
+                        &probed_side_compare_time,
+                        &build_output_time,
+                    )
+                    .await?;
+            }
+        }
+    }
+    heavy_joiner.as_mut().finish(&build_output_time).await?;
+    regular_joiner.as_mut().finish(&build_output_time).await?;
+    exec_ctx
+        .baseline_metrics()
+        .record_output(heavy_joiner.num_output_rows() + regular_joiner.num_output_rows());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        common::{JoinSide, Result},
+        physical_expr::{expressions::Column, PhysicalExprRef},
+        physical_plan::{
+            common, joins::utils::build_join_schema, memory::MemoryExec, ExecutionPlan,
+        },
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        joins::{join_hash_map::JoinHashMap, join_utils::JoinType},
+        memmgr::MemManager,
+        skew_aware_hash_join_exec::{SkewAwareHashJoinExec, SkewAwareJoinHashMaps},
+    };
+
+    // key 0 appears in 80% of rows, other keys are distinct
+    fn build_skewed_batch(num_rows: i32, key_name: &str, value_name: &str) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(key_name, DataType::Int32, false),
+            Field::new(value_name, DataType::Int32, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from_iter_values((0..num_rows).map(|i| {
+                    if i % 5 == 0 {
+                        i
+                    } else {
+                        0
+                    }
+                }))),
+                Arc::new(Int32Array::from_iter_values(0..num_rows)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_skewed_build_side_memory() -> Result<()> {
+        let num_rows = 100000;
+        let batch = build_skewed_batch(num_rows, "k", "v");
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("k", 0))];
+
+        let maps = SkewAwareJoinHashMaps::try_new(batch.clone(), &key_exprs, 0.1)?;
+        assert!(maps.num_heavy_hitters() >= 1 && maps.num_heavy_hitters() < 10);
+        assert_eq!(maps.heavy_map().data_batch().num_rows(), 80001);
+        assert_eq!(maps.regular_map().data_batch().num_rows(), 19999);
+
+        // splitting does not inflate total memory, and the regular map is only
+        // sized for the non-skewed keys
+        let single_map = JoinHashMap::create_from_data_batch(batch, &key_exprs)?;
+        let single_mem_size = single_map.stats().mem_size;
+        let heavy_mem_size = maps.heavy_map().stats().mem_size;
+        let regular_mem_size = maps.regular_map().stats().mem_size;
+        assert!(
+            heavy_mem_size + regular_mem_size <= single_mem_size * 11 / 10,
+            "heavy={heavy_mem_size}, regular={regular_mem_size}, single={single_mem_size}"
+        );
+        assert!(
+            regular_mem_size <= single_mem_size / 2,
+            "regular={regular_mem_size}, single={single_mem_size}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_skewed_join() -> Result<()> {
+        MemManager::init(1000000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let num_rows = 10000;
+        let left_batch = build_skewed_batch(num_rows, "k1", "v1");
+        let right_batch = build_skewed_batch(num_rows / 100, "k2", "v2");
+        let left_schema = left_batch.schema();
+        let right_schema = right_batch.schema();
+        let left = Arc::new(MemoryExec::try_new(
+            &[vec![left_batch]],
+            left_schema.clone(),
+            None,
+        )?);
+        let right = Arc::new(MemoryExec::try_new(
+            &[vec![right_batch]],
+            right_schema.clone(),
+            None,
+        )?);
+        let on = vec![(
+            Arc::new(Column::new("k1", 0)) as PhysicalExprRef,
+            Arc::new(Column::new("k2", 0)) as PhysicalExprRef,
+        )];
+
+        // left keys: 8001 rows of 0 and 1999 distinct keys in [5, 10000)
+        // right keys: 81 rows of 0 and 19 distinct keys in [5, 100)
+        for (join_type, expected_num_rows) in [
+            (JoinType::Inner, 8001 * 81 + 19),
+            (JoinType::Left, 8001 * 81 + 1999),
+            (JoinType::LeftSemi, 8001 + 19),
+            (JoinType::LeftAnti, 1999 - 19),
+        ] {
+            let schema =
+                Arc::new(build_join_schema(&left_schema, &right_schema, &join_type.try_into()?).0);
+            let join = Arc::new(SkewAwareHashJoinExec::try_new(
+                schema,
+                left.clone(),
+                right.clone(),
+                on.clone(),
+                join_type,
+                JoinSide::Right,
+                0.1,
+            )?);
+            let output = common::collect(join.execute(0, task_ctx.clone())?).await?;
+            let num_output_rows: usize = output.iter().map(|batch| batch.num_rows()).sum();
+            assert_eq!(
+                num_output_rows, expected_num_rows,
+                "join_type={join_type:?}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -52,7 +52,10 @@ use datafusion_ext_commons::{
         selection::{create_batch_interleaver, take_batch, BatchInterleaver},
     },
     compute_suggested_batch_size_for_kway_merge, compute_suggested_batch_size_for_output,
-    io::{read_len, read_one_batch, write_len, write_one_batch},
+    io::{
+        read_len, read_one_batch, read_one_batch_with_checksum, write_len, write_one_batch,
+        write_one_batch_with_checksum,
+    },
 };
 use futures::StreamExt;
 use itertools::Itertools;
@@ -66,7 +69,10 @@ use crate::{
         timer_helper::TimerHelper,
     },
     memmgr::{
-        spill::{try_new_spill, Spill, SpillCompressedReader, SpillCompressedWriter},
+        spill::{
+            spill_checksum_enabled, try_new_spill, Spill, SpillCompressedReader,
+            SpillCompressedWriter,
+        },
         MemConsumer, MemConsumerInfo, MemManager,
     },
 };
@@ -85,6 +91,7 @@ pub struct SortExec {
     fetch: Option<usize>,
     metrics: ExecutionPlanMetricsSet,
     record_output: bool,
+    input_ordered: bool,
     props: OnceCell<PlanProperties>,
 }
 
@@ -101,11 +108,92 @@ impl SortExec {
             fetch,
             metrics,
             record_output: true,
+            input_ordered: false,
+            props: OnceCell::new(),
+        }
+    }
+
+    /// marks the input as already ordered by the sort exprs, the input is
+    /// forwarded without sorting. the node is kept in the plan so that it
+    /// still matches the spark metric tree.
+    pub fn with_input_ordered(mut self) -> Self {
+        self.input_ordered = true;
+        self
+    }
+
+    pub fn is_input_ordered(&self) -> bool {
+        self.input_ordered
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn exprs(&self) -> &[PhysicalSortExpr] {
+        &self.exprs
+    }
+
+    /// creates a sort with the same settings on a new input, whose columns
+    /// are referenced by the given sort exprs
+    pub fn with_new_input_and_exprs(
+        &self,
+        input: Arc<dyn ExecutionPlan>,
+        exprs: Vec<PhysicalSortExpr>,
+    ) -> Self {
+        Self {
+            input,
+            exprs,
+            fetch: self.fetch,
+            metrics: ExecutionPlanMetricsSet::new(),
+            record_output: self.record_output,
+            input_ordered: self.input_ordered,
             props: OnceCell::new(),
         }
     }
 }
 
+/// elides sort execs whose input ordering already satisfies the sort exprs,
+/// including inputs ordered by a longer ordering with the sort exprs as its
+/// prefix. sorts with fetch are kept since they also limit the output. only
+/// sorts on plain columns are elided, datafusion treats exprs like additions
+/// as order preserving, which does not hold for spark exprs overflowing.
+pub fn eliminate_redundant_sorts(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let children = plan.children();
+    let new_children = children
+        .iter()
+        .map(|&child| eliminate_redundant_sorts(child.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    let children_changed = children
+        .iter()
+        .zip(&new_children)
+        .any(|(&child, new_child)| !Arc::ptr_eq(child, new_child));
+    let plan = if children_changed {
+        plan.with_new_children(new_children)?
+    } else {
+        plan
+    };
+
+    if let Some(sort) = plan.as_any().downcast_ref::<SortExec>() {
+        if !sort.input_ordered
+            && sort.fetch.is_none()
+            && !sort.exprs.is_empty()
+            && sort.exprs.iter().all(|e| e.expr.as_any().is::<Column>())
+            && sort
+                .input
+                .equivalence_properties()
+                .ordering_satisfy(&sort.exprs)
+        {
+            log::info!(
+                "eliminating redundant sort, input is already ordered: {}",
+                sort.exprs.iter().map(|e| e.to_string()).join(", ")
+            );
+            let sort = sort.with_new_input_and_exprs(sort.input.clone(), sort.exprs.clone());
+            return Ok(Arc::new(sort.with_input_ordered()));
+        }
+    }
+    Ok(plan)
+}
+
 pub fn create_default_ascending_sort_exec(
     input: Arc<dyn ExecutionPlan>,
     key_exprs: &[PhysicalExprRef],
@@ -138,6 +226,9 @@ impl DisplayAs for SortExec {
             .map(|e| e.to_string())
             .collect::<Vec<_>>()
             .join(", ");
+        if self.input_ordered {
+            return write!(f, "SortExec (input ordered): {}", exprs);
+        }
         write!(f, "SortExec: {}", exprs)
     }
 }
@@ -158,7 +249,7 @@ impl ExecutionPlan for SortExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                EquivalenceProperties::new_with_orderings(self.schema(), &[self.exprs.clone()]),
                 self.input.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
@@ -173,11 +264,9 @@ impl ExecutionPlan for SortExec {
         self: Arc<Self>,
         children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(Self::new(
-            children[0].clone(),
-            self.exprs.clone(),
-            self.fetch,
-        )))
+        let mut sort = Self::new(children[0].clone(), self.exprs.clone(), self.fetch);
+        sort.input_ordered = self.input_ordered;
+        Ok(Arc::new(sort))
     }
 
     fn execute(
@@ -366,6 +455,7 @@ struct SpillSortedBlock {
     pruned_schema: SchemaRef,
     spill: Box<dyn Spill>,
     spill_reader: SpillCompressedReader<'static>,
+    spill_checksum: bool,
     cur_key_reader: SortedKeysReader,
 }
 
@@ -375,8 +465,12 @@ impl SortedBlock for SpillSortedBlock {
     }
 
     fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
-        if let Some((num_rows, cols)) = read_one_batch(&mut self.spill_reader, &self.pruned_schema)?
-        {
+        let batch = if self.spill_checksum {
+            read_one_batch_with_checksum(&mut self.spill_reader, &self.pruned_schema, true)?
+        } else {
+            read_one_batch(&mut self.spill_reader, &self.pruned_schema)?
+        };
+        if let Some((num_rows, cols)) = batch {
             let batch = RecordBatch::try_new_with_options(
                 self.pruned_schema.clone(),
                 cols,
@@ -446,6 +540,7 @@ struct SpillSortedBlockBuilder {
     pruned_schema: SchemaRef,
     spill: Box<dyn Spill>,
     spill_writer: SpillCompressedWriter<'static>,
+    spill_checksum: bool,
 }
 
 impl SpillSortedBlockBuilder {
@@ -458,13 +553,18 @@ impl SpillSortedBlockBuilder {
             pruned_schema,
             spill,
             spill_writer,
+            spill_checksum: spill_checksum_enabled(),
         }
     }
 }
 
 impl SortedBlockBuilder<SpillSortedBlock, SqueezeKeyCollector> for SpillSortedBlockBuilder {
     fn add_batch_and_keys(&mut self, batch: RecordBatch, keys: SqueezeKeyCollector) -> Result<()> {
-        write_one_batch(batch.num_rows(), batch.columns(), &mut self.spill_writer)?;
+        if self.spill_checksum {
+            write_one_batch_with_checksum(batch.num_rows(), batch.columns(), &mut self.spill_writer)?;
+        } else {
+            write_one_batch(batch.num_rows(), batch.columns(), &mut self.spill_writer)?;
+        }
         self.spill_writer.write_all(&keys.store)?;
         Ok(())
     }
@@ -481,6 +581,7 @@ impl SortedBlockBuilder<SpillSortedBlock, SqueezeKeyCollector> for SpillSortedBl
             pruned_schema: self.pruned_schema,
             spill,
             spill_reader,
+            spill_checksum: self.spill_checksum,
             cur_key_reader: SortedKeysReader::default(),
         })
     }
@@ -495,15 +596,16 @@ impl ExecuteWithColumnPruning for SortExec {
     ) -> Result<SendableRecordBatchStream> {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
 
-        // if no sort key expr is specified, just forward the input
-        if self.exprs.is_empty() {
+        // if no sort key expr is specified or the input is already ordered,
+        // just forward the input
+        if self.exprs.is_empty() || self.input_ordered {
             return exec_ctx.execute_projected(&self.input, projection);
         }
 
         let prune_sort_keys_from_batch = Arc::new(PruneSortKeysFromBatch::try_new(
             self.input.schema(),
             projection,
-            &self.exprs,
+            &exec_ctx.bind_partition_sort_exprs(&self.exprs)?,
         )?);
         let mut sorter = Arc::new(ExternalSorter {
             exec_ctx: exec_ctx.clone(),
@@ -1294,20 +1396,37 @@ mod test {
     use std::sync::Arc;
 
     use arrow::{
-        array::Int32Array,
+        array::{AsArray, Int32Array},
         compute::SortOptions,
-        datatypes::{DataType, Field, Schema},
+        datatypes::{DataType, Field, Int32Type, Schema},
         record_batch::RecordBatch,
     };
     use datafusion::{
         assert_batches_eq,
-        common::Result,
-        physical_expr::{expressions::Column, PhysicalSortExpr},
-        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        common::{Result, ScalarValue},
+        logical_expr::Operator,
+        physical_expr::{
+            expressions::{BinaryExpr, Column, Literal},
+            PhysicalExprRef, PhysicalSortExpr,
+        },
+        physical_plan::{
+            common,
+            joins::utils::{build_join_schema, JoinOn},
+            memory::MemoryExec,
+            ExecutionPlan,
+        },
         prelude::SessionContext,
     };
 
-    use crate::{memmgr::MemManager, sort_exec::SortExec};
+    use crate::{
+        joins::join_utils::JoinType,
+        limit_exec::LimitExec,
+        memmgr::MemManager,
+        project_exec::ProjectExec,
+        rename_columns_exec::RenameColumnsExec,
+        sort_exec::{eliminate_redundant_sorts, SortExec},
+        sort_merge_join_exec::SortMergeJoinExec,
+    };
 
     fn build_table_i32(
         a: (&str, &Vec<i32>),
@@ -1375,6 +1494,288 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_eliminate_redundant_sorts() -> Result<()> {
+        MemManager::init(100000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let left = build_table(
+            ("a1", &vec![1, 2, 2, 3]),
+            ("b1", &vec![1, 1, 2, 1]),
+            ("c1", &vec![7, 8, 9, 10]),
+        );
+        let right = build_table(
+            ("a2", &vec![1, 2, 2, 4]),
+            ("b2", &vec![1, 1, 2, 2]),
+            ("c2", &vec![70, 80, 90, 100]),
+        );
+        let on: JoinOn = vec![
+            (Arc::new(Column::new("a1", 0)), Arc::new(Column::new("a2", 0))),
+            (Arc::new(Column::new("b1", 1)), Arc::new(Column::new("b2", 1))),
+        ];
+        let schema = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &JoinType::Inner.try_into()?,
+        )
+        .0;
+        let smj: Arc<dyn ExecutionPlan> = Arc::new(SortMergeJoinExec::try_new(
+            Arc::new(schema),
+            left,
+            right,
+            on,
+            JoinType::Inner,
+            vec![SortOptions::default(); 2],
+        )?);
+
+        let asc = |name: &str, idx: usize| PhysicalSortExpr {
+            expr: Arc::new(Column::new(name, idx)),
+            options: SortOptions::default(),
+        };
+        let is_input_ordered = |plan: &Arc<dyn ExecutionPlan>| {
+            plan.as_any()
+                .downcast_ref::<SortExec>()
+                .map(|sort| sort.is_input_ordered())
+        };
+
+        // sorts on a prefix of left keys or on right keys are elided
+        let sort: Arc<dyn ExecutionPlan> =
+            Arc::new(SortExec::new(smj.clone(), vec![asc("a1", 0)], None));
+        let elided = eliminate_redundant_sorts(sort)?;
+        assert_eq!(is_input_ordered(&elided), Some(true));
+        let sort: Arc<dyn ExecutionPlan> = Arc::new(SortExec::new(
+            smj.clone(),
+            vec![asc("a2", 3), asc("b2", 4)],
+            None,
+        ));
+        assert_eq!(
+            is_input_ordered(&eliminate_redundant_sorts(sort)?),
+            Some(true)
+        );
+
+        // sorts on other columns are kept, and a second sort on them is elided
+        let inner_sort: Arc<dyn ExecutionPlan> =
+            Arc::new(SortExec::new(smj.clone(), vec![asc("c1", 2)], None));
+        let sort: Arc<dyn ExecutionPlan> =
+            Arc::new(SortExec::new(inner_sort, vec![asc("c1", 2)], None));
+        let sort = eliminate_redundant_sorts(sort)?;
+        assert_eq!(is_input_ordered(&sort), Some(true));
+        assert_eq!(is_input_ordered(sort.children()[0]), Some(false));
+
+        // results of the elided sort are still ordered
+        let batches = common::collect(elided.execute(0, task_ctx)?).await?;
+        let expected = vec![
+            "+----+----+----+----+----+----+",
+            "| a1 | b1 | c1 | a2 | b2 | c2 |",
+            "+----+----+----+----+----+----+",
+            "| 1  | 1  | 7  | 1  | 1  | 70 |",
+            "| 2  | 1  | 8  | 2  | 1  | 80 |",
+            "| 2  | 2  | 9  | 2  | 2  | 90 |",
+            "+----+----+----+----+----+----+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_eliminate_redundant_sorts_on_converted_plan() -> Result<()> {
+        MemManager::init(100000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let left = build_table(
+            ("a1", &vec![1, 2, 2, 3]),
+            ("b1", &vec![1, 1, 2, 1]),
+            ("c1", &vec![7, 8, 9, 10]),
+        );
+        let right = build_table(
+            ("a2", &vec![1, 2, 2, 4]),
+            ("b2", &vec![1, 1, 2, 2]),
+            ("c2", &vec![70, 80, 90, 100]),
+        );
+        let on: JoinOn = vec![
+            (
+                Arc::new(Column::new("a1", 0)),
+                Arc::new(Column::new("a2", 0)),
+            ),
+            (
+                Arc::new(Column::new("b1", 1)),
+                Arc::new(Column::new("b2", 1)),
+            ),
+        ];
+        let schema = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &JoinType::Inner.try_into()?,
+        )
+        .0;
+        let smj: Arc<dyn ExecutionPlan> = Arc::new(SortMergeJoinExec::try_new(
+            Arc::new(schema),
+            left,
+            right,
+            on,
+            JoinType::Inner,
+            vec![SortOptions::default(); 2],
+        )?);
+
+        // like plans converted from spark: coalesce batches (converted to
+        // LimitExec), project with exprs ids as names, then renamed to the
+        // output names
+        let coalesced: Arc<dyn ExecutionPlan> = Arc::new(LimitExec::new(smj, 8192));
+        let project: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            vec![
+                (Arc::new(Column::new("a2", 3)), "#10".to_string()),
+                (Arc::new(Column::new("b2", 4)), "#11".to_string()),
+                (Arc::new(Column::new("c1", 2)), "#12".to_string()),
+            ],
+            coalesced,
+        )?);
+        let renamed: Arc<dyn ExecutionPlan> = Arc::new(RenameColumnsExec::try_new(
+            project,
+            vec!["k1".to_string(), "k2".to_string(), "v".to_string()],
+        )?);
+
+        let asc = |name: &str, idx: usize| PhysicalSortExpr {
+            expr: Arc::new(Column::new(name, idx)),
+            options: SortOptions::default(),
+        };
+        let is_input_ordered = |plan: &Arc<dyn ExecutionPlan>| {
+            plan.as_any()
+                .downcast_ref::<SortExec>()
+                .map(|sort| sort.is_input_ordered())
+        };
+
+        // sorts on the projected and renamed join keys are elided
+        let sort: Arc<dyn ExecutionPlan> = Arc::new(SortExec::new(
+            renamed.clone(),
+            vec![asc("k1", 0), asc("k2", 1)],
+            None,
+        ));
+        let elided = eliminate_redundant_sorts(sort)?;
+        assert_eq!(is_input_ordered(&elided), Some(true));
+
+        // sorts on other columns are kept
+        let sort: Arc<dyn ExecutionPlan> =
+            Arc::new(SortExec::new(renamed, vec![asc("v", 2)], None));
+        assert_eq!(
+            is_input_ordered(&eliminate_redundant_sorts(sort)?),
+            Some(false)
+        );
+
+        // results of the elided sort are still ordered
+        let batches = common::collect(elided.execute(0, task_ctx)?).await?;
+        let expected = vec![
+            "+----+----+---+",
+            "| k1 | k2 | v |",
+            "+----+----+---+",
+            "| 1  | 1  | 7 |",
+            "| 2  | 1  | 8 |",
+            "| 2  | 2  | 9 |",
+            "+----+----+---+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keep_sorts_not_satisfied_by_input() -> Result<()> {
+        MemManager::init(100000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+        let left = build_table(
+            ("a1", &vec![1, 2, 2, i32::MAX]),
+            ("b1", &vec![1, 1, 2, 1]),
+            ("c1", &vec![7, 8, 9, 10]),
+        );
+        let right = build_table(
+            ("a2", &vec![1, 2, 2, i32::MAX]),
+            ("b2", &vec![1, 1, 2, 2]),
+            ("c2", &vec![70, 80, 90, 100]),
+        );
+        let on: JoinOn = vec![(
+            Arc::new(Column::new("a1", 0)),
+            Arc::new(Column::new("a2", 0)),
+        )];
+        let schema = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &JoinType::Inner.try_into()?,
+        )
+        .0;
+        let smj: Arc<dyn ExecutionPlan> = Arc::new(SortMergeJoinExec::try_new(
+            Arc::new(schema),
+            left,
+            right,
+            on,
+            JoinType::Inner,
+            vec![SortOptions::default()],
+        )?);
+
+        let sort_by = |input: &Arc<dyn ExecutionPlan>, expr: PhysicalExprRef, descending| {
+            let options = SortOptions {
+                descending,
+                nulls_first: false,
+            };
+            let sort: Arc<dyn ExecutionPlan> = Arc::new(SortExec::new(
+                input.clone(),
+                vec![PhysicalSortExpr { expr, options }],
+                None,
+            ));
+            eliminate_redundant_sorts(sort)
+        };
+        let is_input_ordered = |plan: &Arc<dyn ExecutionPlan>| {
+            plan.as_any()
+                .downcast_ref::<SortExec>()
+                .map(|sort| sort.is_input_ordered())
+        };
+        let a2: PhysicalExprRef = Arc::new(Column::new("a2", 3));
+        let b2: PhysicalExprRef = Arc::new(Column::new("b2", 4));
+        let a2_plus_one: PhysicalExprRef = Arc::new(BinaryExpr::new(
+            a2.clone(),
+            Operator::Plus,
+            Arc::new(Literal::new(ScalarValue::Int32(Some(1)))),
+        ));
+
+        // sorts in other directions or on non-key columns are kept
+        let sort = sort_by(&smj, a2.clone(), true)?;
+        assert_eq!(is_input_ordered(&sort), Some(false));
+        let sort = sort_by(&smj, b2.clone(), false)?;
+        assert_eq!(is_input_ordered(&sort), Some(false));
+
+        // sorts on exprs of ordered columns are kept, since they may overflow
+        let sort = sort_by(&smj, a2_plus_one.clone(), false)?;
+        assert_eq!(is_input_ordered(&sort), Some(false));
+
+        // orderings are kept through projected columns, but not through other
+        // projected exprs
+        let project: Arc<dyn ExecutionPlan> = Arc::new(ProjectExec::try_new(
+            vec![
+                (a2_plus_one, "x".to_string()),
+                (b2, "y".to_string()),
+                (a2, "k".to_string()),
+            ],
+            smj,
+        )?);
+        let sort = sort_by(&project, Arc::new(Column::new("k", 2)), false)?;
+        assert_eq!(is_input_ordered(&sort), Some(true));
+        let sort = sort_by(&project, Arc::new(Column::new("x", 0)), false)?;
+        assert_eq!(is_input_ordered(&sort), Some(false));
+
+        // the kept sort orders the overflowed values
+        let batches = common::collect(sort.execute(0, task_ctx)?).await?;
+        let sorted_x = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_x, vec![i32::MIN, 2, 3, 3, 3, 3]);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -20,7 +20,9 @@ use datafusion::{
     common::{DataFusionError, JoinSide},
     error::Result,
     execution::context::TaskContext,
-    physical_expr::{EquivalenceProperties, PhysicalExprRef},
+    physical_expr::{
+        expressions::Column, EquivalenceProperties, LexOrdering, PhysicalExprRef, PhysicalSortExpr,
+    },
     physical_plan::{
         joins::utils::JoinOn,
         metrics::{ExecutionPlanMetricsSet, MetricsSet, Time},
@@ -110,6 +112,73 @@ impl SortMergeJoinExec {
         })
     }
 
+    pub fn on(&self) -> &JoinOn {
+        &self.on
+    }
+
+    pub fn join_type(&self) -> JoinType {
+        self.join_type
+    }
+
+    /// creates a join with the same settings on new inputs, the schema and
+    /// join keys must be based on the new inputs
+    pub fn with_new_inputs(
+        &self,
+        schema: SchemaRef,
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: JoinOn,
+    ) -> Result<Self> {
+        Self::try_new(
+            schema,
+            left,
+            right,
+            on,
+            self.join_type,
+            self.sort_options.clone(),
+        )
+    }
+
+    /// output is ordered by join keys of the sides whose rows are output in
+    /// the merging order and never null-filled. only keys which are columns
+    /// are propagated, an ordering stops at the first non-column key.
+    fn output_orderings(&self) -> Vec<LexOrdering> {
+        let num_left_columns = self.left.schema().fields().len();
+        let (left_ordered, right_ordered, right_offset) = match self.join_type {
+            Inner => (true, true, num_left_columns),
+            Left | LeftSemi | LeftAnti | Existence => (true, false, 0),
+            Right => (false, true, num_left_columns),
+            RightSemi | RightAnti => (false, true, 0),
+            Full => (false, false, 0),
+        };
+        let key_ordering = |keys: Vec<&PhysicalExprRef>, offset: usize| -> LexOrdering {
+            keys.into_iter()
+                .zip(&self.sort_options)
+                .map_while(|(key, &options)| {
+                    let idx = key.as_any().downcast_ref::<Column>()?.index() + offset;
+                    let name = self.schema.fields().get(idx)?.name();
+                    Some(PhysicalSortExpr {
+                        expr: Arc::new(Column::new(name, idx)),
+                        options,
+                    })
+                })
+                .collect()
+        };
+
+        let mut orderings = vec![];
+        if left_ordered {
+            orderings.push(key_ordering(self.on.iter().map(|(l, _)| l).collect(), 0));
+        }
+        if right_ordered {
+            orderings.push(key_ordering(
+                self.on.iter().map(|(_, r)| r).collect(),
+                right_offset,
+            ));
+        }
+        orderings.retain(|ordering| !ordering.is_empty());
+        orderings
+    }
+
     fn create_join_params(&self, projection: &[usize]) -> Result<JoinParams> {
         let left_schema = self.left.schema();
         let right_schema = self.right.schema();
@@ -150,6 +219,7 @@ impl SortMergeJoinExec {
             sort_options: self.sort_options.clone(),
             projection,
             batch_size: batch_size(),
+            fetch: None,
         })
     }
 
@@ -169,6 +239,7 @@ impl SortMergeJoinExec {
             join_params.projection.schema.clone(),
             &self.metrics,
         );
+        let join_params = join_params.bind_partition(&exec_ctx)?;
         let exec_ctx_cloned = exec_ctx.clone();
         let left = exec_ctx.execute(&self.left)?;
         let right = exec_ctx.execute(&self.right)?;
@@ -218,7 +289,7 @@ impl ExecutionPlan for SortMergeJoinExec {
     fn properties(&self) -> &PlanProperties {
         self.props.get_or_init(|| {
             PlanProperties::new(
-                EquivalenceProperties::new(self.schema()),
+                EquivalenceProperties::new_with_orderings(self.schema(), &self.output_orderings()),
                 self.right.output_partitioning().clone(),
                 ExecutionMode::Bounded,
             )
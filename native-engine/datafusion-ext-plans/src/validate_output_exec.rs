@@ -0,0 +1,364 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    array::{Array, RecordBatch},
+    datatypes::SchemaRef,
+};
+use async_trait::async_trait;
+use datafusion::{
+    error::Result,
+    execution::context::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream, Statistics,
+    },
+};
+use datafusion_ext_commons::{df_execution_err, spark_hash::create_xxhash64_hashes};
+use futures::StreamExt;
+use once_cell::sync::OnceCell;
+
+use crate::common::execution_context::ExecutionContext;
+
+/// validates every output batch of the input operator against its declared
+/// schema and the invariants of arrow arrays (utf-8 strings, monotonic
+/// offsets, dictionary keys in range, etc.). used for debugging wrong results.
+#[derive(Debug)]
+pub struct ValidateOutputExec {
+    input: Arc<dyn ExecutionPlan>,
+    operator_id: String,
+    log_hash: bool,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl ValidateOutputExec {
+    pub fn new(input: Arc<dyn ExecutionPlan>, operator_id: String) -> Self {
+        Self {
+            input,
+            operator_id,
+            log_hash: false,
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        }
+    }
+
+    /// also logs row count and column hashes of each batch, for comparing
+    /// outputs of different runs
+    pub fn with_log_hash(mut self, log_hash: bool) -> Self {
+        self.log_hash = log_hash;
+        self
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+}
+
+impl DisplayAs for ValidateOutputExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "ValidateOutputExec: {}", self.operator_id)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for ValidateOutputExec {
+    fn name(&self) -> &str {
+        "ValidateOutputExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(
+            Self::new(children[0].clone(), self.operator_id.clone()).with_log_hash(self.log_hash),
+        ))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let operator_id = self.operator_id.clone();
+        let log_hash = self.log_hash;
+        let schema = self.schema();
+
+        let mut input = exec_ctx.execute(&self.input)?;
+        Ok(
+            exec_ctx.output_with_sender("ValidateOutput", move |sender| async move {
+                let mut batch_idx = 0;
+                while let Some(batch) = input.next().await.transpose()? {
+                    if let Err(err) = validate_batch(&schema, &batch) {
+                        return df_execution_err!(
+                            "output validation failed: operator={operator_id}, \
+                             partition={partition}, batch={batch_idx}: {err}"
+                        );
+                    }
+                    if log_hash {
+                        log::info!(
+                            "ValidateOutputExec(operator={operator_id}, partition={partition}, \
+                             batch={batch_idx}): num_rows={}, column_hashes={:?}",
+                            batch.num_rows(),
+                            column_hashes(&batch),
+                        );
+                    }
+                    batch_idx += 1;
+                    sender.send(batch).await;
+                }
+                Ok(())
+            }),
+        )
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+/// wraps operators whose id is listed in `operator_ids` with
+/// ValidateOutputExec. an operator id is `{name}#{idx}` where idx is the
+/// pre-order index of the operator in the plan, a bare name matches all
+/// operators with that name and `*` matches all operators.
+pub fn wrap_validate_output(
+    plan: Arc<dyn ExecutionPlan>,
+    operator_ids: &[String],
+    log_hash: bool,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    fn wrap(
+        plan: Arc<dyn ExecutionPlan>,
+        next_idx: &mut usize,
+        operator_ids: &[String],
+        log_hash: bool,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let name = plan.name().to_string();
+        let operator_id = format!("{name}#{next_idx}");
+        *next_idx += 1;
+
+        let children = plan.children();
+        let new_children = children
+            .iter()
+            .map(|&child| wrap(child.clone(), next_idx, operator_ids, log_hash))
+            .collect::<Result<Vec<_>>>()?;
+        let children_changed = children
+            .iter()
+            .zip(&new_children)
+            .any(|(&child, new_child)| !Arc::ptr_eq(child, new_child));
+        let plan = if children_changed {
+            plan.with_new_children(new_children)?
+        } else {
+            plan
+        };
+
+        if operator_ids
+            .iter()
+            .any(|id| id == "*" || id == &name || id == &operator_id)
+        {
+            return Ok(Arc::new(
+                ValidateOutputExec::new(plan, operator_id).with_log_hash(log_hash),
+            ));
+        }
+        Ok(plan)
+    }
+    wrap(plan, &mut 0, operator_ids, log_hash)
+}
+
+/// checks the batch against the declared schema and validates the data of
+/// all columns, including nested children
+pub fn validate_batch(schema: &SchemaRef, batch: &RecordBatch) -> Result<()> {
+    let batch_schema = batch.schema();
+    if batch_schema.fields().len() != schema.fields().len() {
+        return df_execution_err!(
+            "expect {} columns, got {}",
+            schema.fields().len(),
+            batch_schema.fields().len(),
+        );
+    }
+    for (i, field) in schema.fields().iter().enumerate() {
+        let batch_field = batch_schema.field(i);
+        let column = batch.column(i);
+        if batch_field.name() != field.name()
+            || batch_field.data_type() != field.data_type()
+            || batch_field.is_nullable() != field.is_nullable()
+        {
+            return df_execution_err!(
+                "column {i} mismatches declared schema, expect {field:?}, got {batch_field:?}"
+            );
+        }
+        if column.data_type() != field.data_type() {
+            return df_execution_err!(
+                "column {i} ({}) expect type {}, got {}",
+                field.name(),
+                field.data_type(),
+                column.data_type(),
+            );
+        }
+        if !field.is_nullable() && column.null_count() > 0 {
+            return df_execution_err!(
+                "column {i} ({}) is declared non-nullable but contains {} nulls",
+                field.name(),
+                column.null_count(),
+            );
+        }
+        if let Err(err) = column.to_data().validate_full() {
+            return df_execution_err!("column {i} ({}) is invalid: {err}", field.name());
+        }
+    }
+    Ok(())
+}
+
+fn column_hashes(batch: &RecordBatch) -> Vec<u64> {
+    // sum of row hashes, so the result does not depend on row order
+    batch
+        .columns()
+        .iter()
+        .map(|column| {
+            create_xxhash64_hashes(batch.num_rows(), &[column.clone()], 42)
+                .into_iter()
+                .fold(0u64, |sum, hash| sum.wrapping_add(hash as u64))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, DictionaryArray, Int32Array, RecordBatch, StringArray},
+        buffer::{OffsetBuffer, ScalarBuffer},
+        datatypes::{DataType, Field, Int32Type, Schema},
+    };
+    use datafusion::{
+        common::Result,
+        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        prelude::SessionContext,
+    };
+
+    use crate::validate_output_exec::{wrap_validate_output, ValidateOutputExec};
+
+    async fn validate(column: ArrayRef, nullable: bool) -> Result<()> {
+        let field = Field::new("c", column.data_type().clone(), nullable);
+        let batch_field = Field::new("c", column.data_type().clone(), true);
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(vec![batch_field])), vec![column])?;
+
+        // the mock child declares a schema which may mismatch its batches
+        let input = Arc::new(MemoryExec::try_new(
+            &[vec![batch.clone(), batch]],
+            Arc::new(Schema::new(vec![field])),
+            None,
+        )?);
+        let validate_exec = ValidateOutputExec::new(input, "MockExec#0".to_string());
+        let task_ctx = SessionContext::new().task_ctx();
+        common::collect(validate_exec.execute(0, task_ctx)?).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_output() -> Result<()> {
+        // valid batches
+        validate(Arc::new(StringArray::from(vec!["a", "b"])), true).await?;
+
+        // nullability mismatch
+        let err = validate(Arc::new(Int32Array::from(vec![Some(1), None])), false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("operator=MockExec#0"), "{err}");
+        assert!(err.contains("batch=0"), "{err}");
+        assert!(err.contains("mismatches declared schema"), "{err}");
+
+        // invalid utf-8
+        let invalid_utf8 = unsafe {
+            StringArray::new_unchecked(
+                OffsetBuffer::new(ScalarBuffer::from(vec![0, 1, 3])),
+                vec![b'a', 0xff, 0xfe].into(),
+                None,
+            )
+        };
+        let err = validate(Arc::new(invalid_utf8), true)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("column 0 (c) is invalid"), "{err}");
+        assert!(err.to_lowercase().contains("utf8"), "{err}");
+
+        // out-of-range dictionary key
+        let dict = unsafe {
+            DictionaryArray::<Int32Type>::new_unchecked(
+                Int32Array::from(vec![0, 5]),
+                Arc::new(StringArray::from(vec!["x", "y"])),
+            )
+        };
+        let err = validate(Arc::new(dict), true)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("column 0 (c) is invalid"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrap_validate_output() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("c", DataType::Int32, true)]));
+        let memory_exec: Arc<dyn ExecutionPlan> =
+            Arc::new(MemoryExec::try_new(&[vec![]], schema, None)?);
+        let plan: Arc<dyn ExecutionPlan> =
+            Arc::new(ValidateOutputExec::new(memory_exec, "inner".to_string()));
+
+        let wrapped = wrap_validate_output(plan.clone(), &["MemoryExec#1".to_string()], false)?;
+        let inner = wrapped.children()[0]
+            .as_any()
+            .downcast_ref::<ValidateOutputExec>()
+            .expect("MemoryExec#1 should be wrapped");
+        assert_eq!(inner.operator_id, "MemoryExec#1");
+        assert_eq!(inner.input().name(), "MemoryExec");
+
+        let unchanged = wrap_validate_output(plan.clone(), &["MemoryExec#0".to_string()], false)?;
+        assert!(Arc::ptr_eq(&unchanged, &plan));
+        Ok(())
+    }
+}
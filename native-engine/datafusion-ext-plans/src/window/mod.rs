@@ -19,11 +19,12 @@ use arrow_schema::DataType;
 use datafusion::{common::Result, physical_expr::PhysicalExpr};
 
 use crate::{
-    agg::{agg::create_agg, AggFunction},
+    agg::{agg::create_agg_with_fail_on_overflow, AggFunction},
     window::{
         processors::{
             agg_processor::AggProcessor, rank_processor::RankProcessor,
             row_number_processor::RowNumberProcessor,
+            running_agg_processor::try_new_running_agg_processor,
         },
         window_context::WindowContext,
     },
@@ -55,6 +56,7 @@ pub struct WindowExpr {
     func: WindowFunction,
     children: Vec<Arc<dyn PhysicalExpr>>,
     return_type: DataType,
+    fail_on_overflow: bool,
 }
 
 impl WindowExpr {
@@ -69,6 +71,25 @@ impl WindowExpr {
             func,
             children,
             return_type,
+            fail_on_overflow: false,
+        }
+    }
+
+    /// raises an error instead of returning null when a running sum overflows
+    /// (ansi mode)
+    pub fn with_fail_on_overflow(mut self, fail_on_overflow: bool) -> Self {
+        self.fail_on_overflow = fail_on_overflow;
+        self
+    }
+
+    pub fn children(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.children
+    }
+
+    pub fn with_new_children(&self, children: Vec<Arc<dyn PhysicalExpr>>) -> Self {
+        Self {
+            children,
+            ..self.clone()
         }
     }
 
@@ -87,11 +108,21 @@ impl WindowExpr {
                 Ok(Box::new(RankProcessor::new(true)))
             }
             WindowFunction::Agg(agg_func) => {
-                let agg = create_agg(
+                if let Some(processor) = try_new_running_agg_processor(
+                    agg_func,
+                    &self.children,
+                    &context.input_schema,
+                    &self.return_type,
+                    self.fail_on_overflow,
+                )? {
+                    return Ok(processor);
+                }
+                let agg = create_agg_with_fail_on_overflow(
                     agg_func.clone(),
                     &self.children,
                     &context.input_schema,
                     self.return_type.clone(),
+                    self.fail_on_overflow,
                 )?;
                 Ok(Box::new(AggProcessor::try_new(agg)?))
             }
@@ -15,3 +15,4 @@
 pub mod agg_processor;
 pub mod rank_processor;
 pub mod row_number_processor;
+pub mod running_agg_processor;
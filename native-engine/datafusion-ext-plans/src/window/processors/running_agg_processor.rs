@@ -0,0 +1,237 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{downcast_primitive, Array, ArrayRef, AsArray, PrimitiveArray},
+    datatypes::{
+        ArrowNativeTypeOp, ArrowPrimitiveType, DataType, Decimal128Type, DecimalType, Int64Type,
+        SchemaRef,
+    },
+    record_batch::RecordBatch,
+};
+use datafusion::{common::Result, physical_expr::PhysicalExpr};
+use datafusion_ext_commons::df_execution_err;
+use datafusion_ext_exprs::cast::TryCastExpr;
+
+use crate::{
+    agg::AggFunction,
+    window::{window_context::WindowContext, WindowFunctionProcessor},
+};
+
+/// running aggregate over ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW,
+/// keeping a single native accumulator of the current partition instead of
+/// going through the generic agg accumulators row by row.
+pub struct RunningAggProcessor<T: ArrowPrimitiveType> {
+    cur_partition: Box<[u8]>,
+    agg_func: AggFunction,
+    children: Vec<Arc<dyn PhysicalExpr>>,
+    data_type: DataType,
+    acc: Option<T::Native>,
+    overflowed: bool,
+    fail_on_overflow: bool,
+    decimal_precision: Option<(u8, fn(T::Native, u8) -> bool)>,
+}
+
+/// creates a running processor for sum/count/min/max of primitive types,
+/// returns None for other functions and types, which should fall back to
+/// AggProcessor. sums overflowing the result type are null, or raise an error
+/// if fail_on_overflow is set (ansi mode).
+pub fn try_new_running_agg_processor(
+    agg_func: AggFunction,
+    children: &[Arc<dyn PhysicalExpr>],
+    input_schema: &SchemaRef,
+    return_type: &DataType,
+    fail_on_overflow: bool,
+) -> Result<Option<Box<dyn WindowFunctionProcessor>>> {
+    let (children, data_type) = match agg_func {
+        AggFunction::Count => {
+            // non-nullable children never affect the count, same as AggCount
+            let children = children
+                .iter()
+                .filter(|expr| {
+                    expr.nullable(input_schema)
+                        .expect("error evaluating child.nullable()")
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            return Ok(Some(Box::new(RunningAggProcessor::<Int64Type>::new(
+                agg_func,
+                children,
+                DataType::Int64,
+            ))));
+        }
+        AggFunction::Sum => {
+            let child: Arc<dyn PhysicalExpr> =
+                Arc::new(TryCastExpr::new(children[0].clone(), return_type.clone()));
+            if let &DataType::Decimal128(precision, _) = return_type {
+                return Ok(Some(Box::new(
+                    RunningAggProcessor::<Decimal128Type>::new(
+                        agg_func,
+                        vec![child],
+                        return_type.clone(),
+                    )
+                    .with_fail_on_overflow(fail_on_overflow)
+                    .with_decimal_precision(precision),
+                )));
+            }
+            (vec![child], return_type.clone())
+        }
+        AggFunction::Min | AggFunction::Max => (
+            vec![children[0].clone()],
+            children[0].data_type(input_schema)?,
+        ),
+        _ => return Ok(None),
+    };
+
+    macro_rules! handle_primitive {
+        ($ty:ty) => {{
+            Some(Box::new(
+                RunningAggProcessor::<$ty>::new(agg_func, children, data_type.clone())
+                    .with_fail_on_overflow(fail_on_overflow),
+            ) as Box<dyn WindowFunctionProcessor>)
+        }};
+    }
+    Ok(downcast_primitive! {
+        &data_type => (handle_primitive),
+        _ => None,
+    })
+}
+
+impl<T: ArrowPrimitiveType> RunningAggProcessor<T> {
+    fn new(
+        agg_func: AggFunction,
+        children: Vec<Arc<dyn PhysicalExpr>>,
+        data_type: DataType,
+    ) -> Self {
+        Self {
+            cur_partition: Box::default(),
+            agg_func,
+            children,
+            data_type,
+            acc: None,
+            overflowed: false,
+            fail_on_overflow: false,
+            decimal_precision: None,
+        }
+    }
+
+    fn with_fail_on_overflow(mut self, fail_on_overflow: bool) -> Self {
+        self.fail_on_overflow = fail_on_overflow;
+        self
+    }
+
+    fn reset(&mut self) {
+        self.acc = None;
+        self.overflowed = false;
+    }
+
+    fn update(&mut self, args: &[ArrayRef], row_idx: usize) -> Result<()> {
+        if self.agg_func == AggFunction::Count {
+            let add = args.iter().all(|arg| arg.is_valid(row_idx));
+            let count = self.acc.unwrap_or(T::Native::ZERO);
+            self.acc = Some(if add {
+                count.add_wrapping(T::Native::ONE)
+            } else {
+                count
+            });
+            return Ok(());
+        }
+
+        // an overflowed sum stays null until the next partition
+        let arg = args[0].as_primitive::<T>();
+        if arg.is_null(row_idx) || self.overflowed {
+            return Ok(());
+        }
+        let value = arg.value(row_idx);
+        self.acc = match (self.agg_func, self.acc) {
+            (AggFunction::Sum, acc) => {
+                let sum = match acc {
+                    Some(acc) if self.fail_on_overflow || self.decimal_precision.is_some() => {
+                        acc.add_checked(value).ok()
+                    }
+                    Some(acc) => Some(acc.add_wrapping(value)),
+                    None => Some(value),
+                };
+                let sum = sum.filter(|&sum| match self.decimal_precision {
+                    Some((precision, is_valid)) => is_valid(sum, precision),
+                    None => true,
+                });
+                if sum.is_none() {
+                    if self.fail_on_overflow {
+                        return df_execution_err!(
+                            "arithmetic overflow in running sum, result exceeds {:?}",
+                            self.data_type,
+                        );
+                    }
+                    self.overflowed = true;
+                }
+                sum
+            }
+            (_, None) => Some(value),
+            (AggFunction::Min, Some(acc)) if !value.is_lt(acc) => Some(acc),
+            (AggFunction::Max, Some(acc)) if !value.is_gt(acc) => Some(acc),
+            _ => Some(value),
+        };
+        Ok(())
+    }
+}
+
+impl RunningAggProcessor<Decimal128Type> {
+    /// sums exceeding the decimal precision are treated as overflowed, like
+    /// integer sums exceeding their native types
+    fn with_decimal_precision(mut self, precision: u8) -> Self {
+        self.decimal_precision = Some((precision, |value, precision| {
+            Decimal128Type::validate_decimal_precision(value, precision).is_ok()
+        }));
+        self
+    }
+}
+
+impl<T: ArrowPrimitiveType> WindowFunctionProcessor for RunningAggProcessor<T> {
+    fn process_batch(&mut self, context: &WindowContext, batch: &RecordBatch) -> Result<ArrayRef> {
+        let partition_rows = context.get_partition_rows(batch)?;
+        let args: Vec<ArrayRef> = self
+            .children
+            .iter()
+            .map(|expr| {
+                expr.evaluate(batch)
+                    .and_then(|v| v.into_array(batch.num_rows()))
+            })
+            .collect::<Result<_>>()?;
+        let mut output = Vec::with_capacity(batch.num_rows());
+
+        for row_idx in 0..batch.num_rows() {
+            let same_partition = !context.has_partition() || {
+                let partition_row = partition_rows.row(row_idx);
+                if partition_row.as_ref() != self.cur_partition.as_ref() {
+                    self.cur_partition = partition_row.as_ref().into();
+                    false
+                } else {
+                    true
+                }
+            };
+
+            if !same_partition {
+                self.reset();
+            }
+            self.update(&args, row_idx)?;
+            output.push(self.acc);
+        }
+        Ok(Arc::new(
+            PrimitiveArray::<T>::from_iter(output).with_data_type(self.data_type.clone()),
+        ))
+    }
+}
@@ -23,8 +23,9 @@ use datafusion::{
     common::Result,
     physical_expr::{PhysicalExpr, PhysicalSortExpr},
 };
+use datafusion_ext_exprs::spark_random::is_partition_dependent;
 
-use crate::window::WindowExpr;
+use crate::{common::execution_context::ExecutionContext, window::WindowExpr};
 
 #[derive(Debug)]
 pub struct WindowContext {
@@ -128,6 +129,38 @@ impl WindowContext {
         })
     }
 
+    /// returns the context with partition-dependent exprs bound to the
+    /// partition of exec_ctx, or itself if there are no such exprs
+    pub fn bind_partition(self: &Arc<Self>, exec_ctx: &ExecutionContext) -> Result<Arc<Self>> {
+        let window_exprs_dependent = self
+            .window_exprs
+            .iter()
+            .flat_map(|expr| expr.children())
+            .any(is_partition_dependent);
+        let specs_dependent = self.partition_spec.iter().any(is_partition_dependent)
+            || self
+                .order_spec
+                .iter()
+                .any(|expr| is_partition_dependent(&expr.expr));
+        if !window_exprs_dependent && !specs_dependent {
+            return Ok(self.clone());
+        }
+
+        let window_exprs = self
+            .window_exprs
+            .iter()
+            .map(|expr| Ok(expr.with_new_children(exec_ctx.bind_partition_exprs(expr.children())?)))
+            .collect::<Result<_>>()?;
+        Ok(Arc::new(Self::try_new(
+            self.input_schema.clone(),
+            window_exprs,
+            exec_ctx.bind_partition_exprs(&self.partition_spec)?,
+            exec_ctx.bind_partition_sort_exprs(&self.order_spec)?,
+            self.group_limit,
+            self.output_window_cols,
+        )?))
+    }
+
     pub fn has_partition(&self) -> bool {
         !self.partition_schema.fields().is_empty()
     }
@@ -184,7 +184,7 @@ impl ExecutionPlan for WindowExec {
         let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
         let input = exec_ctx.execute_with_input_stats(&self.input)?;
         let coalesced = exec_ctx.coalesce_with_default_batch_size(input);
-        let window_ctx = self.context.clone();
+        let window_ctx = self.context.bind_partition(&exec_ctx)?;
         execute_window(coalesced, exec_ctx, window_ctx)
     }
 
@@ -269,7 +269,7 @@ mod test {
     use arrow::{array::*, datatypes::*, record_batch::RecordBatch};
     use datafusion::{
         assert_batches_eq,
-        physical_expr::{expressions::Column, PhysicalSortExpr},
+        physical_expr::{expressions::Column, PhysicalExpr, PhysicalSortExpr},
         physical_plan::{memory::MemoryExec, ExecutionPlan},
         prelude::SessionContext,
     };
@@ -439,6 +439,168 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_running_window_aggs() -> Result<(), Box<dyn std::error::Error>> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("p", DataType::Int32, false),
+            Field::new("o", DataType::Int32, false),
+            Field::new("v", DataType::Int32, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 1, 1, 1, 2, 2, 3])),
+                Arc::new(Int32Array::from(vec![1, 2, 3, 4, 1, 2, 1])),
+                Arc::new(Int32Array::from(vec![
+                    None,
+                    Some(3),
+                    Some(1),
+                    Some(5),
+                    Some(-2),
+                    None,
+                    None,
+                ])),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+
+        let v: Arc<dyn PhysicalExpr> = Arc::new(Column::new("v", 2));
+        let window_exprs = vec![
+            WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Sum),
+                vec![v.clone()],
+                Arc::new(Field::new("v_sum", DataType::Int64, true)),
+                DataType::Int64,
+            ),
+            WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Count),
+                vec![v.clone()],
+                Arc::new(Field::new("v_count", DataType::Int64, false)),
+                DataType::Int64,
+            ),
+            WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Count),
+                vec![],
+                Arc::new(Field::new("count_all", DataType::Int64, false)),
+                DataType::Int64,
+            ),
+            WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Min),
+                vec![v.clone()],
+                Arc::new(Field::new("v_min", DataType::Int32, true)),
+                DataType::Int32,
+            ),
+            WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Max),
+                vec![v.clone()],
+                Arc::new(Field::new("v_max", DataType::Int32, true)),
+                DataType::Int32,
+            ),
+        ];
+        let window = Arc::new(WindowExec::try_new(
+            input.clone(),
+            window_exprs.clone(),
+            vec![Arc::new(Column::new("p", 0))],
+            vec![PhysicalSortExpr {
+                expr: Arc::new(Column::new("o", 1)),
+                options: Default::default(),
+            }],
+            None,
+            true,
+        )?);
+        let stream = window.execute(0, task_ctx.clone())?;
+        let batches = datafusion::physical_plan::common::collect(stream).await?;
+
+        // same as spark's output with ROWS BETWEEN UNBOUNDED PRECEDING AND
+        // CURRENT ROW framing
+        let expected = vec![
+            "+---+---+----+-------+---------+-----------+-------+-------+",
+            "| p | o | v  | v_sum | v_count | count_all | v_min | v_max |",
+            "+---+---+----+-------+---------+-----------+-------+-------+",
+            "| 1 | 1 |    |       | 0       | 1         |       |       |",
+            "| 1 | 2 | 3  | 3     | 1       | 2         | 3     | 3     |",
+            "| 1 | 3 | 1  | 4     | 2       | 3         | 1     | 3     |",
+            "| 1 | 4 | 5  | 9     | 3       | 4         | 1     | 5     |",
+            "| 2 | 1 | -2 | -2    | 1       | 1         | -2    | -2    |",
+            "| 2 | 2 |    | -2    | 1       | 2         | -2    | -2    |",
+            "| 3 | 1 |    |       | 0       | 1         |       |       |",
+            "+---+---+----+-------+---------+-----------+-------+-------+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_running_decimal_sum_overflow() -> Result<(), Box<dyn std::error::Error>> {
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        let dt = DataType::Decimal128(5, 0);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("p", DataType::Int32, false),
+            Field::new("o", DataType::Int32, false),
+            Field::new("v", dt.clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 1, 1, 2])),
+                Arc::new(Int32Array::from(vec![1, 2, 3, 1])),
+                Arc::new(
+                    Decimal128Array::from(vec![60000, 50000, -50000, 7]).with_data_type(dt.clone()),
+                ),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+        let create_window = |fail_on_overflow: bool| {
+            let window_expr = WindowExpr::new(
+                WindowFunction::Agg(AggFunction::Sum),
+                vec![Arc::new(Column::new("v", 2))],
+                Arc::new(Field::new("v_sum", dt.clone(), true)),
+                dt.clone(),
+            )
+            .with_fail_on_overflow(fail_on_overflow);
+            WindowExec::try_new(
+                input.clone(),
+                vec![window_expr],
+                vec![Arc::new(Column::new("p", 0))],
+                vec![PhysicalSortExpr {
+                    expr: Arc::new(Column::new("o", 1)),
+                    options: Default::default(),
+                }],
+                None,
+                true,
+            )
+        };
+
+        // overflowed sums stay null until the next partition
+        let window = Arc::new(create_window(false)?);
+        let stream = window.execute(0, task_ctx.clone())?;
+        let batches = datafusion::physical_plan::common::collect(stream).await?;
+        let expected = vec![
+            "+---+---+--------+-------+",
+            "| p | o | v      | v_sum |",
+            "+---+---+--------+-------+",
+            "| 1 | 1 | 60000  | 60000 |",
+            "| 1 | 2 | 50000  |       |",
+            "| 1 | 3 | -50000 |       |",
+            "| 2 | 1 | 7      | 7     |",
+            "+---+---+--------+-------+",
+        ];
+        assert_batches_eq!(expected, &batches);
+
+        // ansi mode raises an error on overflow
+        let window = Arc::new(create_window(true)?);
+        let stream = window.execute(0, task_ctx.clone())?;
+        assert!(datafusion::physical_plan::common::collect(stream)
+            .await
+            .is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_window_group_limit() -> Result<(), Box<dyn std::error::Error>> {
         let session_ctx = SessionContext::new();
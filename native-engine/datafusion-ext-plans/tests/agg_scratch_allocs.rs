@@ -0,0 +1,123 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! counts allocations with a dedicated global allocator, which is kept in
+//! its own test binary so that it does not affect other tests.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, Int64Array},
+    datatypes::{DataType, Field, Schema},
+};
+use datafusion::{common::Result, physical_expr::expressions::Column};
+use datafusion_ext_plans::agg::{
+    agg::IdxSelection,
+    agg_ctx::{AggContext, AggScratch},
+    agg_hash_map::AggHashMap,
+    sum::AggSum,
+    AggExecMode, AggExpr, AggMode, GroupingExpr,
+};
+
+// counts allocations of the current thread
+struct CountingAllocator;
+
+thread_local! {
+    static NUM_ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = NUM_ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = NUM_ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_scratch_reuses_index_vectors() -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("k", DataType::Int64, false),
+        Field::new("v", DataType::Int64, false),
+    ]));
+    let agg_ctx = AggContext::try_new(
+        AggExecMode::HashAgg,
+        schema,
+        vec![GroupingExpr {
+            field_name: "k".to_string(),
+            expr: Arc::new(Column::new("k", 0)),
+        }],
+        vec![AggExpr {
+            field_name: "sum".to_string(),
+            mode: AggMode::Partial,
+            agg: Arc::new(AggSum::try_new(
+                Arc::new(Column::new("v", 1)),
+                DataType::Int64,
+            )?),
+        }],
+        false,
+        false,
+    )?;
+
+    let num_rows = 1000;
+    let keys = (0..num_rows as i64)
+        .map(|i| (i % 100).to_ne_bytes())
+        .collect::<Vec<_>>();
+    let input_arrays: Vec<Vec<ArrayRef>> = vec![vec![Arc::new(Int64Array::from_iter_values(
+        0..num_rows as i64,
+    ))]];
+
+    let mut map = AggHashMap::default();
+    let mut acc_table = agg_ctx.create_acc_table(0);
+    let mut scratch = AggScratch::default();
+    let mut num_allocs_per_batch = vec![];
+    for _ in 0..10 {
+        let key_refs = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+        let num_allocs_before = NUM_ALLOCS.with(Cell::get);
+
+        map.upsert_records_into(key_refs, &mut scratch.record_indices);
+        let record_indices = std::mem::take(&mut scratch.record_indices);
+        agg_ctx.partial_update(
+            &mut acc_table,
+            IdxSelection::IndicesU32(&record_indices),
+            &input_arrays,
+            IdxSelection::Range(0, num_rows),
+            &mut scratch,
+        )?;
+        scratch.record_indices = record_indices;
+
+        num_allocs_per_batch.push(NUM_ALLOCS.with(Cell::get) - num_allocs_before);
+    }
+    assert_eq!(map.len(), 100);
+
+    // only the first batch allocates index vectors and acc columns
+    assert!(num_allocs_per_batch[0] > 0);
+    assert_eq!(num_allocs_per_batch[1..], [0; 9]);
+    Ok(())
+}
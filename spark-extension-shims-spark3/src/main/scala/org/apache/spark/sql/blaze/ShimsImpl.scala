@@ -55,6 +55,7 @@ import org.apache.spark.sql.execution.CoalescedPartitionSpec
 import org.apache.spark.sql.execution.FileSourceScanExec
 import org.apache.spark.sql.execution.PartialMapperPartitionSpec
 import org.apache.spark.sql.execution.PartialReducerPartitionSpec
+import org.apache.spark.sql.execution.RangeExec
 import org.apache.spark.sql.execution.ShufflePartitionSpec
 import org.apache.spark.sql.execution.ShuffledRowRDD
 import org.apache.spark.sql.execution.SparkPlan
@@ -84,6 +85,8 @@ import org.apache.spark.sql.execution.blaze.plan.NativeParquetInsertIntoHiveTabl
 import org.apache.spark.sql.execution.blaze.plan.NativeParquetScanBase
 import org.apache.spark.sql.execution.blaze.plan.NativeParquetScanExec
 import org.apache.spark.sql.execution.blaze.plan.NativeProjectBase
+import org.apache.spark.sql.execution.blaze.plan.NativeRangeBase
+import org.apache.spark.sql.execution.blaze.plan.NativeRangeExec
 import org.apache.spark.sql.execution.blaze.plan.NativeRenameColumnsBase
 import org.apache.spark.sql.execution.blaze.plan.NativeShuffleExchangeBase
 import org.apache.spark.sql.execution.blaze.plan.NativeShuffleExchangeExec
@@ -256,6 +259,9 @@ class ShimsImpl extends Shims with Logging {
       child: SparkPlan): NativeProjectBase =
     NativeProjectExecProvider.provide(projectList, child)
 
+  override def createNativeRangeExec(rangeExec: RangeExec): NativeRangeBase =
+    NativeRangeExec(rangeExec.range, rangeExec.numSlices)
+
   override def createNativeRenameColumnsExec(
       child: SparkPlan,
       newColumnNames: Seq[String]): NativeRenameColumnsBase =
@@ -495,6 +501,10 @@ class ShimsImpl extends Shims with Logging {
           case Some(v) => return Some(v)
           case None =>
         }
+        convertMapElementAt(e, isPruningExpr, fallback) match {
+          case Some(v) => return Some(v)
+          case None =>
+        }
         None
     }
   }
@@ -529,6 +539,24 @@ class ShimsImpl extends Shims with Logging {
                 .build())
           case None =>
         }
+        convertModeAgg(agg) match {
+          case Some(aggExpr) =>
+            return Some(
+              pb.PhysicalExprNode
+                .newBuilder()
+                .setAggExpr(aggExpr)
+                .build())
+          case None =>
+        }
+        convertRegrAgg(agg) match {
+          case Some(aggExpr) =>
+            return Some(
+              pb.PhysicalExprNode
+                .newBuilder()
+                .setAggExpr(aggExpr)
+                .build())
+          case None =>
+        }
         None
     }
   }
@@ -537,6 +565,11 @@ class ShimsImpl extends Shims with Logging {
     expr.asInstanceOf[AggregateExpression].filter
   }
 
+  override def withoutAggregateExpressionFilter(
+      expr: AggregateExpression): AggregateExpression = {
+    expr.copy(filter = None)
+  }
+
   @sparkver("3.2 / 3.3 / 3.4 / 3.5")
   private def isAQEShuffleRead(exec: SparkPlan): Boolean = {
     import org.apache.spark.sql.execution.adaptive.AQEShuffleReadExec
@@ -562,6 +595,7 @@ class ShimsImpl extends Shims with Logging {
         val inputRDD = executeNative(child)
         val nativeShuffle = getUnderlyingNativePlan(child).asInstanceOf[NativeShuffleExchangeExec]
         val nativeSchema: pb.Schema = nativeShuffle.nativeSchema
+        val nativeReadStatistics = nativeShuffle.nativeReadStatistics(shuffledRDD.getNumPartitions)
 
         val requiredMetrics = nativeShuffle.readMetrics ++
           nativeShuffle.metrics.filterKeys(_ == "shuffle_read_total_time")
@@ -647,6 +681,7 @@ class ShimsImpl extends Shims with Logging {
                   .setSchema(nativeSchema)
                   .setNumPartitions(shuffledRDD.getNumPartitions)
                   .setIpcProviderResourceId(jniResourceId)
+                  .setStatistics(nativeReadStatistics)
                   .build())
               .build()
           })
@@ -665,6 +700,7 @@ class ShimsImpl extends Shims with Logging {
         val inputRDD = executeNative(child)
         val nativeShuffle = getUnderlyingNativePlan(child).asInstanceOf[NativeShuffleExchangeExec]
         val nativeSchema: pb.Schema = nativeShuffle.nativeSchema
+        val nativeReadStatistics = nativeShuffle.nativeReadStatistics(shuffledRDD.getNumPartitions)
 
         val requiredMetrics = nativeShuffle.readMetrics ++
           nativeShuffle.metrics.filterKeys(_ == "shuffle_read_total_time")
@@ -740,6 +776,7 @@ class ShimsImpl extends Shims with Logging {
                   .setSchema(nativeSchema)
                   .setNumPartitions(shuffledRDD.getNumPartitions)
                   .setIpcProviderResourceId(jniResourceId)
+                  .setStatistics(nativeReadStatistics)
                   .build())
               .build()
           })
@@ -758,6 +795,7 @@ class ShimsImpl extends Shims with Logging {
         val inputRDD = executeNative(child)
         val nativeShuffle = getUnderlyingNativePlan(child).asInstanceOf[NativeShuffleExchangeExec]
         val nativeSchema: pb.Schema = nativeShuffle.nativeSchema
+        val nativeReadStatistics = nativeShuffle.nativeReadStatistics(shuffledRDD.getNumPartitions)
 
         val requiredMetrics = nativeShuffle.readMetrics ++
           nativeShuffle.metrics.filterKeys(_ == "shuffle_read_total_time")
@@ -833,6 +871,7 @@ class ShimsImpl extends Shims with Logging {
                   .setSchema(nativeSchema)
                   .setNumPartitions(shuffledRDD.getNumPartitions)
                   .setIpcProviderResourceId(jniResourceId)
+                  .setStatistics(nativeReadStatistics)
                   .build())
               .build()
           })
@@ -939,6 +978,84 @@ class ShimsImpl extends Shims with Logging {
   @sparkver("3.0 / 3.1 / 3.2")
   private def convertBloomFilterAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = None
 
+  @sparkver("3.4")
+  private def convertModeAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = {
+    import org.apache.spark.sql.catalyst.expressions.aggregate.Mode
+    agg match {
+      case Mode(child, _, _) => Some(buildModeAgg(agg, child))
+      case _ => None
+    }
+  }
+
+  @sparkver("3.5")
+  private def convertModeAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = {
+    import org.apache.spark.sql.catalyst.expressions.aggregate.Mode
+    agg match {
+      // native mode always chooses the smallest value among ties, which is
+      // incompatible with mode() WITHIN GROUP (ORDER BY ... ASC)
+      case Mode(child, _, _, reverseOpt) if !reverseOpt.contains(false) =>
+        Some(buildModeAgg(agg, child))
+      case _ => None
+    }
+  }
+
+  @sparkver("3.0 / 3.1 / 3.2 / 3.3")
+  private def convertModeAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = None
+
+  private def buildModeAgg(agg: AggregateFunction, child: Expression): pb.PhysicalAggExprNode = {
+    pb.PhysicalAggExprNode
+      .newBuilder()
+      .setReturnType(NativeConverters.convertDataType(agg.dataType))
+      .setAggFunction(pb.AggFunction.MODE)
+      .addChildren(NativeConverters.convertExpr(child))
+      .build()
+  }
+
+  @sparkver("3.4 / 3.5")
+  private def convertRegrAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = {
+    import org.apache.spark.sql.catalyst.expressions.aggregate.RegrIntercept
+    import org.apache.spark.sql.catalyst.expressions.aggregate.RegrR2
+    import org.apache.spark.sql.catalyst.expressions.aggregate.RegrSlope
+    val (aggFunction, y, x) = agg match {
+      case RegrSlope(y, x) => (pb.AggFunction.REGR_SLOPE, y, x)
+      case RegrIntercept(y, x) => (pb.AggFunction.REGR_INTERCEPT, y, x)
+      case RegrR2(y, x) => (pb.AggFunction.REGR_R2, y, x)
+      case _ => return None
+    }
+    Some(
+      pb.PhysicalAggExprNode
+        .newBuilder()
+        .setReturnType(NativeConverters.convertDataType(agg.dataType))
+        .setAggFunction(aggFunction)
+        .addChildren(NativeConverters.convertExpr(y))
+        .addChildren(NativeConverters.convertExpr(x))
+        .build())
+  }
+
+  @sparkver("3.0 / 3.1 / 3.2 / 3.3")
+  private def convertRegrAgg(agg: AggregateFunction): Option[pb.PhysicalAggExprNode] = None
+
+  private def convertMapElementAt(
+      e: Expression,
+      isPruningExpr: Boolean,
+      fallback: Expression => pb.PhysicalExprNode): Option[pb.PhysicalExprNode] = {
+    import org.apache.spark.sql.catalyst.expressions.ElementAt
+    import org.apache.spark.sql.types.MapType
+    e match {
+      // non-existent map keys are null instead of errors, failOnError only applies to the
+      // out-of-bound indices of arrays
+      case e: ElementAt if e.left.dataType.isInstanceOf[MapType] =>
+        Some(
+          NativeConverters.buildExtScalarFunctionNode(
+            "ElementAt",
+            e.left :: e.right :: Literal(false) :: Nil,
+            e.dataType,
+            isPruningExpr,
+            fallback))
+      case _ => None
+    }
+  }
+
   @sparkver("3.3 / 3.4 / 3.5")
   private def convertBloomFilterMightContain(
       e: Expression,
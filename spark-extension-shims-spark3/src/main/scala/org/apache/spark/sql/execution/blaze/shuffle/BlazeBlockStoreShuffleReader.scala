@@ -15,13 +15,14 @@
  */
 package org.apache.spark.sql.execution.blaze.shuffle
 
-import java.io.InputStream
+import java.io.{IOException, InputStream}
 
 import org.apache.spark.{MapOutputTracker, SparkEnv, TaskContext}
 import org.apache.spark.internal.{config, Logging}
 import org.apache.spark.io.CompressionCodec
 import org.apache.spark.shuffle.{BaseShuffleHandle, ShuffleReadMetricsReporter}
 import org.apache.spark.storage.{BlockId, BlockManager, BlockManagerId, ShuffleBlockFetcherIterator}
+import org.apache.spark.storage.{ShuffleBlockBatchId, ShuffleBlockId}
 import org.blaze.sparkver
 
 class BlazeBlockStoreShuffleReader[K, C](
@@ -35,9 +36,63 @@ class BlazeBlockStoreShuffleReader[K, C](
     extends BlazeBlockStoreShuffleReaderBase[K, C](handle, context)
     with Logging {
 
-  override def readBlocks(): Iterator[InputStream] = {
-    @sparkver("3.2 / 3.3 / 3.4 / 3.5")
-    def fetchIterator = new ShuffleBlockFetcherIterator(
+  override def readBlocks(): Iterator[InputStream] =
+    readRefetchableBlocks().map(_._1)
+
+  override protected def readRefetchableBlocks()
+      : Iterator[(InputStream, Option[() => InputStream])] = {
+    // block locations are kept for requesting corrupted blocks again
+    val blocks = blocksByAddress.toSeq
+    val blockInfos = blocks.flatMap { case (address, infos) =>
+      infos.map { case (blockId, size, mapIndex) => blockId -> (address, size, mapIndex) }
+    }.toMap
+
+    fetchIterator(blocks.iterator, fetchContinuousBlocksInBatch).map {
+      case (blockId, inputStream) =>
+        val refetchBlock = refetchedBlockIds(blockId)
+          .filter(_.forall(blockInfos.contains))
+          .map(blockIds => () => refetch(blockIds, blockInfos))
+        (inputStream, refetchBlock)
+    }
+  }
+
+  // batched blocks are requested again with their original blocks. other blocks
+  // (like merged blocks of push-based shuffle) are not refetchable
+  private def refetchedBlockIds(blockId: BlockId): Option[Seq[BlockId]] = {
+    blockId match {
+      case blockId: ShuffleBlockId => Some(Seq(blockId))
+      case ShuffleBlockBatchId(shuffleId, mapId, startReduceId, endReduceId) =>
+        Some((startReduceId until endReduceId).map(ShuffleBlockId(shuffleId, mapId, _)))
+      case _ => None
+    }
+  }
+
+  // requests blocks again from their block managers. local blocks are read again
+  // from the local block manager, and remote blocks are fetched again
+  private def refetch(
+      blockIds: Seq[BlockId],
+      blockInfos: Map[BlockId, (BlockManagerId, Long, Int)]): InputStream = {
+    val blocksByAddress = blockIds
+      .map(blockId => (blockId, blockInfos(blockId)))
+      .groupBy { case (_, (address, _, _)) => address }
+      .map { case (address, infos) =>
+        (address, infos.map { case (blockId, (_, size, mapIndex)) => (blockId, size, mapIndex) })
+      }
+    logWarning(s"refetching shuffle blocks: ${blockIds.mkString(", ")}")
+
+    // blocks are fetched in batch again if they were batched in the first fetch
+    val refetched = fetchIterator(blocksByAddress.iterator, blockIds.length > 1)
+    if (!refetched.hasNext) {
+      throw new IOException(s"refetching shuffle blocks returned nothing: $blockIds")
+    }
+    refetched.next()._2
+  }
+
+  @sparkver("3.2 / 3.3 / 3.4 / 3.5")
+  private def fetchIterator(
+      blocksByAddress: Iterator[(BlockManagerId, Seq[(BlockId, Long, Int)])],
+      doBatchFetch: Boolean): Iterator[(BlockId, InputStream)] =
+    new ShuffleBlockFetcherIterator(
       context,
       blockManager.blockStoreClient,
       blockManager,
@@ -55,10 +110,13 @@ class BlazeBlockStoreShuffleReader[K, C](
       false, // checksums not supported
       "ChecksumAlgorithmsNotSupported",
       readMetrics,
-      fetchContinuousBlocksInBatch).toCompletionIterator.map(_._2)
+      doBatchFetch).toCompletionIterator
 
-    @sparkver("3.0 / 3.1")
-    def fetchIterator = new ShuffleBlockFetcherIterator(
+  @sparkver("3.0 / 3.1")
+  private def fetchIterator(
+      blocksByAddress: Iterator[(BlockManagerId, Seq[(BlockId, Long, Int)])],
+      doBatchFetch: Boolean): Iterator[(BlockId, InputStream)] =
+    new ShuffleBlockFetcherIterator(
       context,
       blockManager.blockStoreClient,
       blockManager,
@@ -72,10 +130,7 @@ class BlazeBlockStoreShuffleReader[K, C](
       SparkEnv.get.conf.get(config.SHUFFLE_DETECT_CORRUPT),
       SparkEnv.get.conf.get(config.SHUFFLE_DETECT_CORRUPT_MEMORY),
       readMetrics,
-      fetchContinuousBlocksInBatch).toCompletionIterator.map(_._2)
-
-    fetchIterator
-  }
+      doBatchFetch).toCompletionIterator
 
   private def fetchContinuousBlocksInBatch: Boolean = {
     val conf = SparkEnv.get.conf
@@ -88,16 +143,18 @@ class BlazeBlockStoreShuffleReader[K, C](
       true
     }
     val useOldFetchProtocol = conf.get(config.SHUFFLE_USE_OLD_FETCH_PROTOCOL)
+    // encrypted blocks cannot be concatenated
+    val ioEncryption = conf.get(config.IO_ENCRYPTION_ENABLED)
 
     val doBatchFetch = shouldBatchFetch && serializerRelocatable &&
-      (!compressed || codecConcatenation) && !useOldFetchProtocol
+      (!compressed || codecConcatenation) && !useOldFetchProtocol && !ioEncryption
     if (shouldBatchFetch && !doBatchFetch) {
       logDebug(
         "The feature tag of continuous shuffle block fetching is set to true, but " +
           "we can not enable the feature because other conditions are not satisfied. " +
           s"Shuffle compress: $compressed, serializer relocatable: $serializerRelocatable, " +
           s"codec concatenation: $codecConcatenation, use old shuffle fetch protocol: " +
-          s"$useOldFetchProtocol.")
+          s"$useOldFetchProtocol, io encryption: $ioEncryption.")
     }
     doBatchFetch
   }
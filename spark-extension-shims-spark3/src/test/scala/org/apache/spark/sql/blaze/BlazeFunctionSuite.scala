@@ -56,4 +56,97 @@ class BlazeFunctionSuite extends org.apache.spark.sql.QueryTest with BaseBlazeSQ
     }
   }
 
+  test("rand/randn/uuid functions match spark for the same seed and partition") {
+    withTable("t1") {
+      sql("create table t1 using parquet as select id as c1 from range(0, 4000, 1, 4)")
+      val query =
+        """
+          |select
+          |  spark_partition_id() as pid,
+          |  c1,
+          |  rand(42) as r,
+          |  randn(42) as rn,
+          |  uuid() as u
+          |from t1
+          |where rand(7) < 2.0
+          |""".stripMargin
+
+      // compares the first 1000 values of each partition
+      def collect(blazeEnabled: Boolean): Seq[Row] = {
+        withSQLConf("spark.blaze.enable" -> blazeEnabled.toString) {
+          sql(query).collect().toSeq.groupBy(_.getInt(0)).toSeq.sortBy(_._1).flatMap {
+            case (_, rows) => rows.take(1000).map(row => Row(row.getDouble(2), row.getDouble(3)))
+          }
+        }
+      }
+      assert(collect(blazeEnabled = true) == collect(blazeEnabled = false))
+
+      // uuids are seeded by the planner, so only check their format
+      val uuids = sql(query).collect().map(_.getString(4))
+      assert(uuids.distinct.length == uuids.length)
+      assert(uuids.forall(_.matches("[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}")))
+    }
+  }
+
+  test("map functions") {
+    withTable("t1") {
+      sql("""
+          |create table t1 using parquet as
+          |select
+          |  map('a', id, 'b', if(id % 2 = 0, null, id)) as m,
+          |  array('x', 'y', 'x') as ks,
+          |  array(id, id + 1, id + 2) as vs,
+          |  if(id % 3 = 0, 'a', 'c') as k
+          |from range(10)
+          |""".stripMargin)
+      val query =
+        """
+          |select
+          |  map_keys(m),
+          |  map_values(m),
+          |  element_at(m, 'a'),
+          |  element_at(m, 'c'),
+          |  element_at(m, k),
+          |  map_from_arrays(ks, vs)
+          |from t1
+          |""".stripMargin
+      withSQLConf("spark.sql.mapKeyDedupPolicy" -> "LAST_WIN") {
+        var expected: Seq[Row] = Nil
+        withSQLConf("spark.blaze.enable" -> "false") {
+          expected = sql(query).collect().toSeq
+        }
+        checkAnswer(sql(query), expected)
+      }
+
+      // non-existent map keys are null even in ansi mode
+      withSQLConf("spark.sql.ansi.enabled" -> "true") {
+        checkAnswer(sql("select element_at(m, 'c') from t1"), Seq.fill(10)(Row(null)))
+      }
+    }
+  }
+
+  test("substring_index function") {
+    withTable("t1") {
+      sql("""
+          |create table t1 using parquet as
+          |select if(id % 4 = 0, null, concat('www.', id, '.apache→org')) as s
+          |from range(10)
+          |""".stripMargin)
+      val query =
+        """
+          |select
+          |  substring_index(s, '.', 2),
+          |  substring_index(s, '.', -1),
+          |  substring_index(s, '→', 1),
+          |  substring_index(s, '.', 5),
+          |  substring_index(s, '', 1)
+          |from t1
+          |""".stripMargin
+      var expected: Seq[Row] = Nil
+      withSQLConf("spark.blaze.enable" -> "false") {
+        expected = sql(query).collect().toSeq
+      }
+      checkAnswer(sql(query), expected)
+    }
+  }
 }
@@ -0,0 +1,94 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze
+
+import java.io.{DataInputStream, File, FileInputStream}
+import java.nio.{ByteBuffer, ByteOrder}
+
+import org.apache.commons.io.IOUtils
+import org.apache.spark.{SparkConf, SparkEnv}
+import org.apache.spark.internal.config.IO_ENCRYPTION_ENABLED
+import org.apache.spark.network.util.LimitedInputStream
+import org.apache.spark.security.CryptoStreamUtils
+import org.apache.spark.sql.Row
+
+class BlazeIoEncryptionSuite extends org.apache.spark.sql.QueryTest with BaseBlazeSQLSuite {
+
+  override protected def sparkConf: SparkConf =
+    super.sparkConf.set(IO_ENCRYPTION_ENABLED, true)
+
+  test("native shuffle with io encryption") {
+    withTable("t1") {
+      sql("create table t1 using parquet as select id % 100 as k, id as v from range(10000)")
+      checkAnswer(
+        sql("select k, count(*), sum(v) from t1 group by k"),
+        (0 until 100).map { k =>
+          Row(k.toLong, 100L, (0 until 100).map(i => i * 100L + k).sum)
+        })
+    }
+  }
+
+  test("natively written shuffle blocks are decrypted by CryptoStreamUtils") {
+    withTable("t1") {
+      sql("create table t1 using parquet as select id % 100 as k, id as v from range(10000)")
+
+      val env = SparkEnv.get
+      val existingDataFiles = shuffleDataFiles().toSet
+      sql("select k, count(*) from t1 group by k").collect()
+      val dataFiles = shuffleDataFiles().filterNot(existingDataFiles.contains)
+      assert(dataFiles.nonEmpty)
+
+      val key = env.securityManager.getIOEncryptionKey().get
+      for (dataFile <- dataFiles) {
+        val indexFile = new File(dataFile.getPath.stripSuffix(".data") + ".index")
+        val offsets = readOffsets(indexFile)
+        for ((start, end) <- offsets.zip(offsets.tail) if end > start) {
+          val in = new FileInputStream(dataFile)
+          try {
+            in.getChannel.position(start)
+            val decrypted = IOUtils.toByteArray(
+              CryptoStreamUtils
+                .createCryptoInputStream(new LimitedInputStream(in, end - start), env.conf, key))
+            assert(decrypted.length == end - start - CryptoStreamUtils.IV_LENGTH_IN_BYTES)
+
+            // decrypted partition starts with the length header of a native ipc frame
+            val frameLen = ByteBuffer
+              .wrap(decrypted, 0, 4)
+              .order(ByteOrder.LITTLE_ENDIAN)
+              .getInt & 0x3fffffff
+            assert(frameLen + 4 <= decrypted.length)
+          } finally {
+            in.close()
+          }
+        }
+      }
+    }
+  }
+
+  private def shuffleDataFiles(): Seq[File] =
+    SparkEnv.get.blockManager.diskBlockManager
+      .getAllFiles()
+      .filter(f => f.getName.startsWith("shuffle_") && f.getName.endsWith(".data"))
+
+  private def readOffsets(indexFile: File): Seq[Long] = {
+    val in = new DataInputStream(new FileInputStream(indexFile))
+    try {
+      Seq.fill((indexFile.length() / 8).toInt)(in.readLong())
+    } finally {
+      in.close()
+    }
+  }
+}
@@ -16,6 +16,16 @@
 package org.apache.spark.sql.blaze
 
 import org.apache.spark.sql.Row
+import org.apache.spark.sql.execution.RangeExec
+import org.apache.spark.sql.execution.aggregate.HashAggregateExec
+import org.apache.spark.sql.execution.aggregate.ObjectHashAggregateExec
+import org.apache.spark.sql.execution.aggregate.SortAggregateExec
+import org.apache.spark.sql.execution.blaze.plan.NativeAggBase
+import org.apache.spark.sql.execution.blaze.plan.NativeExpandBase
+import org.apache.spark.sql.execution.blaze.plan.NativeParquetScanBase
+import org.apache.spark.sql.execution.blaze.plan.NativeRangeBase
+import org.apache.spark.sql.execution.exchange.Exchange
+import org.apache.spark.sql.internal.SQLConf
 
 import scala.collection.mutable.ArrayBuffer
 
@@ -34,6 +44,51 @@ class BlazeQuerySuite
     }
   }
 
+  test("join bucketed tables without exchange") {
+    withTable("t1", "t2") {
+      withSQLConf(
+        SQLConf.AUTO_BROADCASTJOIN_THRESHOLD.key -> "-1",
+        SQLConf.ADAPTIVE_EXECUTION_ENABLED.key -> "false") {
+        spark
+          .range(1000)
+          .selectExpr("id as k", "id * 2 as v1")
+          .write
+          .bucketBy(8, "k")
+          .saveAsTable("t1")
+        spark
+          .range(1000)
+          .selectExpr("id as k", "id * 3 as v2")
+          .write
+          .bucketBy(8, "k")
+          .saveAsTable("t2")
+
+        val df = sql("select t1.k, v1, v2 from t1 join t2 on t1.k = t2.k")
+        checkAnswer(df, (0L until 1000L).map(k => Row(k, k * 2, k * 3)))
+
+        val plan = df.queryExecution.executedPlan
+        assert(plan.collect { case scan: NativeParquetScanBase => scan }.size == 2)
+        assert(plan.collect { case e: Exchange => e }.isEmpty, plan.toString)
+      }
+    }
+  }
+
+  test("scan sends catalog statistics to native") {
+    withTable("t1") {
+      sql("create table t1 using parquet as select id as c1, id % 10 as c2 from range(1000)")
+      sql("analyze table t1 compute statistics for columns c1, c2")
+      val df = sql("select c2, count(1) from t1 where c1 >= 0 group by c2")
+      checkAnswer(df, (0L until 10L).map(k => Row(k, 100L)))
+
+      val scans = df.queryExecution.executedPlan.collect { case scan: NativeParquetScanBase =>
+        scan
+      }
+      assert(scans.size == 1)
+      val statistics = scans.head.nativeStatistics
+      assert(statistics.hasNumRows && statistics.getNumRows == 1000)
+      assert(statistics.getColumnStats(1).getDistinctCount == 10)
+    }
+  }
+
   test("empty output in bnlj") {
     withTable("t1", "t2") {
       sql("create table t1 using parquet as select 1 as c1, 2 as c2")
@@ -111,6 +166,206 @@ class BlazeQuerySuite
     }
   }
 
+  test("mixed distinct and non-distinct aggregates on skewed data") {
+    withTable("t1") {
+      // key 0 takes 80% of rows
+      sql("""
+          |create table t1 using parquet as
+          |select if(id % 10 < 8, 0, id % 100) as k, id % 37 as a, id % 11 as b, id as c
+          |from range(100000)
+          |""".stripMargin)
+      Seq(
+        "select k, count(distinct a), sum(b) from t1 group by k",
+        "select k, count(distinct a), count(distinct b), sum(c), max(b) from t1 group by k")
+        .foreach { query =>
+          var expected: Seq[Row] = Nil
+          withSQLConf("spark.blaze.enable" -> "false") {
+            expected = sql(query).collect().toSeq
+          }
+          checkAnswer(sql(query), expected)
+        }
+    }
+  }
+
+  test("mixed distinct and non-distinct aggregates with filters run natively") {
+    withTable("t1") {
+      sql("""
+          |create table t1 using parquet as
+          |select if(id % 10 < 8, 0, id % 100) as k, id % 37 as a, id % 11 as b, id as c
+          |from range(100000)
+          |""".stripMargin)
+      Seq(
+        "select k, count(distinct a) filter (where b > 3), sum(c) filter (where b < 5) " +
+          "from t1 group by k",
+        "select k, count(distinct a), count(distinct b) filter (where c % 2 = 0), " +
+          "count(*) filter (where a > 10), max(c) filter (where a < 5) from t1 group by k")
+        .foreach { query =>
+          var expected: Seq[Row] = Nil
+          withSQLConf("spark.blaze.enable" -> "false") {
+            expected = sql(query).collect().toSeq
+          }
+          val df = sql(query)
+          checkAnswer(df, expected)
+
+          // the expand and both aggregations of the rewritten plan are native
+          val plan = df.queryExecution.executedPlan
+          assert(plan.collect { case agg: NativeAggBase => agg }.nonEmpty, plan.toString)
+          assert(
+            plan.collect {
+              case agg: HashAggregateExec => agg
+              case agg: ObjectHashAggregateExec => agg
+              case agg: SortAggregateExec => agg
+            }.isEmpty,
+            plan.toString)
+          assert(plan.collect { case expand: NativeExpandBase => expand }.size == 1)
+        }
+    }
+  }
+
+  test("global distinct aggregates over different columns") {
+    withTable("t1") {
+      sql("""
+          |create table t1 using parquet as
+          |select id % 37 as a, if(id % 5 = 0, null, id % 11) as b, id as c
+          |from range(100000)
+          |""".stripMargin)
+      Seq(
+        "select count(distinct a), sum(distinct b) from t1",
+        "select count(distinct a), sum(distinct b), sum(c), count(*) from t1",
+        "select count(distinct a), sum(distinct a), avg(distinct a), max(c) from t1",
+        "select count(distinct a, b), count(distinct b), sum(c) from t1 where c < 1000",
+        "select a, count(distinct b), count(distinct c % 7), sum(c) from t1 group by a")
+        .foreach { query =>
+          var expected: Seq[Row] = Nil
+          withSQLConf("spark.blaze.enable" -> "false") {
+            expected = sql(query).collect().toSeq
+          }
+          Seq("true", "false").foreach { preDedup =>
+            withSQLConf("spark.blaze.agg.distinctPreDedup.enable" -> preDedup) {
+              checkAnswer(sql(query), expected)
+            }
+          }
+        }
+    }
+  }
+
+  test("prune unused columns of joins, filters, sorts and scans") {
+    withTable("t1", "t2") {
+      sql("""
+          |create table t1 using parquet as
+          |select id % 100 as k, id as c1, id * 2 as c2, id * 3 as c3, id * 4 as c4
+          |from range(1000)
+          |""".stripMargin)
+      sql("""
+          |create table t2 using parquet as
+          |select id as k, id + 1 as c1, id + 2 as c2, id + 3 as c3, id + 4 as c4
+          |from range(100)
+          |""".stripMargin)
+      withSQLConf(SQLConf.AUTO_BROADCASTJOIN_THRESHOLD.key -> "-1") {
+        Seq(
+          "select t1.c3, t2.c2 from t1 join t2 on t1.k = t2.k where t1.c1 % 7 = 0 " +
+            "order by t2.c4, t1.c3",
+          "select t1.c2 from t1 left join t2 on t1.k = t2.c1 where t2.c3 is null " +
+            "order by t1.c4",
+          "select t1.c1 from t1 left semi join t2 on t1.k = t2.k and t2.c4 > 50 order by t1.c3")
+          .foreach { query =>
+            var expected: Seq[Row] = Nil
+            withSQLConf("spark.blaze.enable" -> "false") {
+              expected = sql(query).collect().toSeq
+            }
+            Seq("true", "false").foreach { columnPruning =>
+              withEnvConf(BlazeConf.COLUMN_PRUNING_ENABLE.key -> columnPruning) {
+                checkAnswer(sql(query), expected)
+              }
+            }
+          }
+      }
+    }
+  }
+
+  test("eliminate redundant sorts after sort merge joins") {
+    withTable("t1", "t2") {
+      sql("""
+          |create table t1 using parquet as
+          |select cast(if(id % 100 = 0, 2147483647, id % 100) as int) as k, id as v1
+          |from range(1000)
+          |""".stripMargin)
+      sql("""
+          |create table t2 using parquet as
+          |select cast(if(id % 10 = 0, 2147483647, id) as int) as k, id as v2
+          |from range(100)
+          |""".stripMargin)
+      withSQLConf(SQLConf.AUTO_BROADCASTJOIN_THRESHOLD.key -> "-1") {
+        Seq(
+          // ordered by the join keys
+          "select t1.k, count(*) from t1 join t2 on t1.k = t2.k group by t1.k order by t1.k",
+          "select t2.k, t2.v2 from t1 join t2 on t1.k = t2.k order by t2.k",
+          // not ordered by the join keys, or by overflowing exprs of them
+          "select t1.k, t2.v2 from t1 join t2 on t1.k = t2.k order by t1.k desc, t2.v2",
+          "select t1.k + 1 as k1, t1.v1 from t1 join t2 on t1.k = t2.k order by k1, t1.v1",
+          "select t2.k + 1 as k1 from t2 join t1 on t1.k = t2.k order by k1")
+          .foreach { query =>
+            var expected: Seq[Row] = Nil
+            withSQLConf("spark.blaze.enable" -> "false") {
+              expected = sql(query).collect().toSeq
+            }
+            Seq("true", "false").foreach { eliminateRedundantSorts =>
+              withEnvConf(
+                BlazeConf.ELIMINATE_REDUNDANT_SORTS_ENABLE.key -> eliminateRedundantSorts) {
+                val result = sql(query).collect().toSeq
+                assert(result == expected, s"$query:\n$result\n$expected")
+              }
+            }
+          }
+
+        // sorts within partitions are directly on top of the joins
+        Seq(
+          "select t2.k, t2.v2 from t1 join t2 on t1.k = t2.k sort by t2.k",
+          "select t1.k + 1 as k1, t2.v2 from t1 join t2 on t1.k = t2.k sort by k1")
+          .foreach { query =>
+            var expected: Seq[Row] = Nil
+            withSQLConf("spark.blaze.enable" -> "false") {
+              expected = sql(query).collect().toSeq
+            }
+            Seq("true", "false").foreach { eliminateRedundantSorts =>
+              withEnvConf(
+                BlazeConf.ELIMINATE_REDUNDANT_SORTS_ENABLE.key -> eliminateRedundantSorts) {
+                val df = sql(query)
+                checkAnswer(df, expected)
+                df.rdd.glom().collect().foreach { partition =>
+                  val keys = partition.map(_.getInt(0)).toSeq
+                  assert(keys == keys.sorted, query)
+                }
+              }
+            }
+          }
+      }
+    }
+  }
+
+  test("range and sequence run natively") {
+    Seq(
+      "select id, id * 2 from range(0, 1000, 3, 4)",
+      "select id from range(100, -100, -7, 3)",
+      "select sum(id), count(*) from range(-5000, 5000, 11, 7)",
+      "select id, sequence(id, id % 5), sequence(0, id, 3) from range(20)",
+      "select id from range(10, 0, 1)")
+      .foreach { query =>
+        var expected: Seq[Row] = Nil
+        withSQLConf("spark.blaze.enable" -> "false") {
+          expected = sql(query).collect().toSeq
+        }
+        val df = sql(query)
+        checkAnswer(df, expected)
+
+        val plan = df.queryExecution.executedPlan
+        assert(plan.collect { case e: RangeExec => e }.isEmpty, plan.toString)
+      }
+
+    val plan = sql("select id from range(0, 1000, 3, 4)").queryExecution.executedPlan
+    assert(plan.collect { case range: NativeRangeBase => range }.size == 1, plan.toString)
+  }
+
   test("SPARK-32234 read ORC table with column names all starting with '_col'") {
     withTable("test_hive_orc_impl") {
       spark.sql(s"""
@@ -0,0 +1,65 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.execution.blaze.shuffle
+
+import java.io.{ByteArrayInputStream, InputStream}
+import java.nio.ByteBuffer
+import java.nio.charset.StandardCharsets
+
+import org.apache.spark.SparkFunSuite
+
+class BlazeBlockObjectSuite extends SparkFunSuite {
+
+  private def readAll(block: BlockObject): String = {
+    val buf = ByteBuffer.allocate(1024)
+    while (block.getChannel.read(buf) >= 0) {}
+    new String(buf.array(), 0, buf.position(), StandardCharsets.UTF_8)
+  }
+
+  private def stream(data: String): InputStream =
+    new ByteArrayInputStream(data.getBytes(StandardCharsets.UTF_8))
+
+  test("refetch requests the block again") {
+    var numFetches = 0
+    val refetchBlock = () => {
+      numFetches += 1
+      stream(s"fetch-$numFetches")
+    }
+    val block = BlazeBlockStoreShuffleReaderBase.createBlockObject(
+      stream("corrupted"),
+      encrypted = true,
+      Some(refetchBlock))
+    val channel = block.getChannel
+
+    val refetched = block.refetch()
+    assert(refetched != null)
+    assert(!channel.isOpen, "corrupted block should be closed after refetching")
+    assert(refetched.isEncrypted)
+    assert(readAll(refetched) == "fetch-1")
+
+    // refetched blocks can be refetched again
+    val refetchedAgain = refetched.refetch()
+    assert(refetchedAgain != null)
+    assert(readAll(refetchedAgain) == "fetch-2")
+    assert(numFetches == 2)
+  }
+
+  test("refetch returns null for blocks which cannot be requested again") {
+    val block = BlazeBlockStoreShuffleReaderBase.createBlockObject(stream("data"))
+    assert(block.refetch() == null)
+    assert(readAll(block) == "data")
+  }
+}
@@ -35,6 +35,14 @@ public enum BlazeConf {
     /// outputs from spark due to different unicode versions.
     CASE_CONVERT_FUNCTIONS_ENABLE("spark.blaze.enable.caseconvert.functions", true),
 
+    /// enable hashing and comparing lower()-wrapped join keys case-insensitively instead of
+    /// materializing the lowered keys
+    COLLATED_JOIN_KEYS_ENABLE("spark.blaze.enable.collatedJoinKeys", true),
+
+    /// enable filtering `expr IN (subquery)` predicates of filters with a native hash set of the
+    /// subquery result, instead of evaluating them row by row in the jvm
+    IN_SUBQUERY_EXEC_ENABLE("spark.blaze.enable.inSubqueryExec", true),
+
     /// enable extra metrics of input batch statistics
     INPUT_BATCH_STATISTICS_ENABLE("spark.blaze.enableInputBatchStatistics", true),
 
@@ -48,6 +56,69 @@ public enum BlazeConf {
     /// by default, all aggs containing udafs are converted to sort-based
     UDAF_FALLBACK_NUM_UDAFS_TRIGGER_SORT_AGG("spark.blaze.udafFallback.num.udafs.trigger.sortAgg", 1),
 
+    /// emit string grouping keys of aggregation output dictionary-encoded
+    AGG_DICT_ENCODE_STRING_KEYS("spark.blaze.agg.dictEncodeStringKeys", false),
+
+    /// drop duplicated expanded rows of distinct aggregates before the first aggregation, with a
+    /// bounded native hash set of the grouping keys
+    DISTINCT_AGG_PRE_DEDUP_ENABLE("spark.blaze.agg.distinctPreDedup.enable", true),
+
+    /// combine records with identical grouping keys before writing agg spills
+    AGG_SPILL_PRE_MERGE_ENABLE("spark.blaze.agg.spill.preMerge.enable", true),
+
+    /// append successive agg spills to one spill file as runs, instead of creating a file for each spill
+    AGG_SPILL_APPEND_RUNS_ENABLE("spark.blaze.agg.spill.appendRuns.enable", false),
+
+    /// behavior of count() exceeding Long.MaxValue: error, saturate or wrap. empty for saturating
+    COUNT_OVERFLOW_BEHAVIOR("spark.blaze.agg.count.overflowBehavior", ""),
+
+    /// min number of contiguous rows for count() to update its counters in bulk instead of row by row
+    COUNT_BULK_UPDATE_MIN_ROWS("spark.blaze.agg.count.bulkUpdateMinRows", 64),
+
+    /// class name of a hive udaf `(value, k[, seed])` sampling up to k values of each group into an
+    /// array, which is converted to the native reservoir sampling aggregate. empty for disabling
+    RESERVOIR_SAMPLE_UDAF_CLASS_NAME("spark.blaze.udaf.reservoirSample.className", ""),
+
+    /// write row counts and checksums of spilled udaf buffers and verify them when unspilling,
+    /// only for debugging the udaf spill format since all spilled rows are serialized again
+    UDAF_SPILL_CHECKSUM_ENABLE("spark.blaze.udaf.spill.checksum.enable", false),
+
+    /// compress shuffle output with a zstd dictionary trained from the first input batches
+    /// of each map task. the dictionary is written in front of each partition segment, so
+    /// this is only beneficial when partition segments are much larger than the dictionary
+    SHUFFLE_ZSTD_DICT_ENABLE("spark.blaze.shuffle.zstdDict.enable", false),
+
+    /// max size of the trained shuffle zstd dictionary
+    SHUFFLE_ZSTD_DICT_SIZE("spark.blaze.shuffle.zstdDict.size", 16384),
+
+    /// max memory size of a batch written in one ipc message, larger batches are split
+    /// into consecutive sub-batches before compression
+    IPC_MAX_BATCH_MEM_SIZE("spark.blaze.ipc.maxBatchMemSize", 268435456),
+
+    /// max memory size of a broadcast join hash map used by one native execution, exceeding
+    /// this limit fails the task instead of risking exhausting off-heap memory
+    BROADCAST_MAX_BYTES_PER_EXEC("spark.blaze.broadcast.maxBytesPerExec", 8589934592L),
+
+    /// comma-separated native operators whose output batches are validated, for debugging
+    /// wrong results. an operator is identified by `{name}#{idx}` (idx is the pre-order index
+    /// in the native plan), a bare name for all operators with that name, or `*` for all
+    VALIDATE_OUTPUT_OPERATORS("spark.blaze.debug.validateOutput.operators", ""),
+
+    /// also log row counts and column hashes of the validated batches
+    VALIDATE_OUTPUT_LOG_HASH("spark.blaze.debug.validateOutput.logHash", false),
+
+    /// elide native sorts whose input is already ordered as required, like sorts on the join
+    /// keys after sort merge joins
+    ELIMINATE_REDUNDANT_SORTS_ENABLE("spark.blaze.eliminateRedundantSorts.enable", true),
+
+    /// prune columns unused by the output of native plans, tightening the outputs of scans and
+    /// join inputs so that unused columns are not carried across the jvm boundary
+    COLUMN_PRUNING_ENABLE("spark.blaze.columnPruning.enable", true),
+
+    /// max number of refetching a corrupted shuffle block before failing with fetch failure,
+    /// only blocks supporting refetching (like local file segments) are retried
+    SHUFFLE_READ_MAX_RETRIES("spark.blaze.shuffle.read.maxRetries", 2),
+
     // TypedImperativeAggregate one row mem use size
     UDAF_FALLBACK_ESTIM_ROW_SIZE("spark.blaze.udafFallback.typedImperativeEstimatedRowSize", 256),
 
@@ -87,6 +158,13 @@ public enum BlazeConf {
     // spark spill compression codec
     SPILL_COMPRESSION_CODEC("spark.blaze.spill.compression.codec", "lz4"),
 
+    // write CRC32C checksums of batches in sort spills and verify them when reading
+    SPILL_CHECKSUM_ENABLE("spark.blaze.spill.checksum.enable", false),
+
+    // check data types of batches before serializing them in shuffle and spills, failing early
+    // with the unsupported field instead of in the middle of writing
+    SERDE_CHECK_DATA_TYPES_ENABLE("spark.blaze.serde.checkDataTypes.enable", true),
+
     // enable hash join falling back to sort merge join when hash table is too big
     SMJ_FALLBACK_ENABLE("spark.blaze.smjfallback.enable", false),
 
@@ -96,6 +174,22 @@ public enum BlazeConf {
     // smj fallback threshold
     SMJ_FALLBACK_MEM_SIZE_THRESHOLD("spark.blaze.smjfallback.mem.threshold", 134217728),
 
+    // keys taking more than this fraction of build side rows of shuffled hash join are joined
+    // with a dedicated heavy hitter map, 0 for disabling skew-aware shuffled hash join
+    SHJ_SKEW_HEAVY_HITTER_FRACTION("spark.blaze.shj.skewHeavyHitterFraction", 0.0),
+
+    // max load factor of join hash map, must be in (0, 0.9]
+    JOIN_HASH_MAP_LOAD_FACTOR("spark.blaze.joinHashMap.loadFactor", 0.5),
+
+    // seed of join hashes, 0 for the default seed. only for reproducing issues with badly
+    // clustered keys, must be the same in all executors since broadcast hash maps are built
+    // with the seed of the building executor
+    JOIN_HASH_SEED("spark.blaze.joinHashMap.hashSeed", 0),
+
+    // serialize row indices of broadcast join hash maps in delta + dictionary + RLE encoding,
+    // which is much smaller when build rows of the same key are stored contiguously
+    JOIN_HASH_MAP_DICT_COMPRESS_INDICES("spark.blaze.joinHashMap.dictCompressIndices", false),
+
     // max memory fraction of on-heap spills
     ON_HEAP_SPILL_MEM_FRACTION("spark.blaze.onHeapSpill.memoryFraction", 0.9),
 
@@ -0,0 +1,90 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze;
+
+/**
+ * Error thrown from native execution, classified by the native engine. The error code is the
+ * same as BlazeError::code() in native code, so that job monitoring can tell memory, spill io,
+ * corrupted data and unsupported features apart.
+ */
+public class BlazeNativeException extends RuntimeException {
+    public static final int OUT_OF_MEMORY = 1;
+    public static final int SPILL_IO = 2;
+    public static final int CORRUPTION = 3;
+    public static final int UNSUPPORTED = 4;
+    public static final int CANCELLED = 5;
+    public static final int INTERNAL = 6;
+
+    private final int errorCode;
+
+    public BlazeNativeException(int errorCode, String message, Throwable cause) {
+        super(message, cause);
+        this.errorCode = errorCode;
+    }
+
+    public int getErrorCode() {
+        return errorCode;
+    }
+
+    // for jni_bridge usage
+    @SuppressWarnings("unused")
+    public static BlazeNativeException create(int errorCode, String message, Throwable cause) {
+        switch (errorCode) {
+            case OUT_OF_MEMORY:
+                return new OutOfMemory(message, cause);
+            case SPILL_IO:
+                return new SpillIo(message, cause);
+            case CORRUPTION:
+                return new Corruption(message, cause);
+            case UNSUPPORTED:
+                return new Unsupported(message, cause);
+            case CANCELLED:
+                return new Cancelled(message, cause);
+            default:
+                return new BlazeNativeException(INTERNAL, message, cause);
+        }
+    }
+
+    public static class OutOfMemory extends BlazeNativeException {
+        public OutOfMemory(String message, Throwable cause) {
+            super(OUT_OF_MEMORY, message, cause);
+        }
+    }
+
+    public static class SpillIo extends BlazeNativeException {
+        public SpillIo(String message, Throwable cause) {
+            super(SPILL_IO, message, cause);
+        }
+    }
+
+    public static class Corruption extends BlazeNativeException {
+        public Corruption(String message, Throwable cause) {
+            super(CORRUPTION, message, cause);
+        }
+    }
+
+    public static class Unsupported extends BlazeNativeException {
+        public Unsupported(String message, Throwable cause) {
+            super(UNSUPPORTED, message, cause);
+        }
+    }
+
+    public static class Cancelled extends BlazeNativeException {
+        public Cancelled(String message, Throwable cause) {
+            super(CANCELLED, message, cause);
+        }
+    }
+}
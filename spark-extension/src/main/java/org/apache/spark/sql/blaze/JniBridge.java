@@ -116,4 +116,10 @@ public class JniBridge {
                 ._2
                 .getPath();
     }
+
+    // returns null if spark.io.encryption.enabled is off
+    public static byte[] getIoEncryptionKey() {
+        scala.Option<byte[]> key = SparkEnv.get().securityManager().getIOEncryptionKey();
+        return key.isDefined() ? key.get() : null;
+    }
 }
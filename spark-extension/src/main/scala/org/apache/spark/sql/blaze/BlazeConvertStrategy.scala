@@ -39,6 +39,7 @@ import org.apache.spark.sql.execution.exchange.ShuffleExchangeLike
 import org.apache.spark.sql.execution.window.WindowExec
 import org.apache.spark.sql.execution.GenerateExec
 import org.apache.spark.sql.execution.LocalTableScanExec
+import org.apache.spark.sql.execution.RangeExec
 import org.apache.spark.sql.execution.blaze.plan.BuildSide
 import org.apache.spark.sql.execution.command.DataWritingCommandExec
 import org.apache.spark.sql.execution.joins.BroadcastNestedLoopJoinExec
@@ -176,6 +177,8 @@ object BlazeConvertStrategy extends Logging {
         e.setTagValue(convertStrategyTag, AlwaysConvert)
       case e: LocalTableScanExec =>
         e.setTagValue(convertStrategyTag, AlwaysConvert)
+      case e: RangeExec =>
+        e.setTagValue(convertStrategyTag, AlwaysConvert)
       case e: DataWritingCommandExec if isNative(e.child) =>
         e.setTagValue(convertStrategyTag, AlwaysConvert)
 
@@ -248,6 +251,15 @@ object BlazeConvertStrategy extends Logging {
           }
         }
 
+        // NativeRange -> NonNative
+        // don't use NativeRange because it requires C2R with a lot of records
+        if (isNeverConvert(e)) {
+          e.children.find(_.isInstanceOf[RangeExec]) match {
+            case Some(range) => dontConvertIf(range, !isNeverConvert(range))
+            case _ =>
+          }
+        }
+
         // NonNative -> NativeSort -> NonNative
         // don't use native sort
         if (isNeverConvert(e)) {
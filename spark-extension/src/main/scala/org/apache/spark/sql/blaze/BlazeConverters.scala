@@ -70,6 +70,7 @@ import org.apache.spark.sql.execution.aggregate.SortAggregateExec
 import org.apache.spark.sql.execution.blaze.plan.NativeBroadcastExchangeBase
 import org.apache.spark.sql.execution.GenerateExec
 import org.apache.spark.sql.execution.LocalTableScanExec
+import org.apache.spark.sql.execution.RangeExec
 import org.apache.spark.sql.execution.UnaryExecNode
 import org.apache.spark.sql.execution.blaze.plan.BroadcastLeft
 import org.apache.spark.sql.execution.blaze.plan.BroadcastRight
@@ -130,6 +131,8 @@ object BlazeConverters extends Logging {
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.generate", defaultValue = true)
   val enableLocalTableScan: Boolean =
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.local.table.scan", defaultValue = true)
+  val enableRange: Boolean =
+    SparkEnv.get.conf.getBoolean("spark.blaze.enable.range", defaultValue = true)
   val enableDataWriting: Boolean =
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.data.writing", defaultValue = false)
   val enableScanParquet: Boolean =
@@ -241,6 +244,8 @@ object BlazeConverters extends Logging {
         tryConvert(e, convertGenerateExec)
       case e: LocalTableScanExec if enableLocalTableScan => // local table scan
         tryConvert(e, convertLocalTableScanExec)
+      case e: RangeExec if enableRange => // range
+        tryConvert(e, convertRangeExec)
       case e: DataWritingCommandExec if enableDataWriting => // data writing
         tryConvert(e, convertDataWritingCommandExec)
 
@@ -904,6 +909,14 @@ object BlazeConverters extends Logging {
     convertToNative(exec)
   }
 
+  def convertRangeExec(exec: RangeExec): SparkPlan = {
+    logDebug(s"Converting RangeExec: ${Shims.get.simpleStringWithNodeId(exec)}")
+    if (exec.isEmptyRange) {
+      return createEmptyExec(exec.output, exec.outputPartitioning, exec.outputOrdering)
+    }
+    Shims.get.createNativeRangeExec(exec)
+  }
+
   def convertDataWritingCommandExec(exec: DataWritingCommandExec): SparkPlan = {
     logDebug(s"Converting DataWritingCommandExec: ${Shims.get.simpleStringWithNodeId(exec)}")
     exec match {
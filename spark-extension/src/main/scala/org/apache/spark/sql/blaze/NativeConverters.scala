@@ -36,8 +36,8 @@ import org.apache.spark.SparkEnv
 import org.blaze.{protobuf => pb}
 import org.apache.spark.internal.Logging
 import org.apache.spark.sql.blaze.util.Using
-import org.apache.spark.sql.catalyst.expressions.{Abs, Acos, Add, Alias, And, Asin, Atan, Attribute, AttributeReference, BitwiseAnd, BitwiseOr, BoundReference, CaseWhen, Cast, Ceil, CheckOverflow, Coalesce, Concat, ConcatWs, Contains, Cos, CreateArray, CreateNamedStruct, DayOfMonth, Divide, EndsWith, EqualTo, Exp, Expression, Floor, GetArrayItem, GetJsonObject, GetMapValue, GetStructField, GreaterThan, GreaterThanOrEqual, If, In, InSet, IsNotNull, IsNull, LeafExpression, Length, LessThan, LessThanOrEqual, Like, Literal, Log, Log10, Log2, Lower, MakeDecimal, Md5, Month, Multiply, Murmur3Hash, Not, NullIf, OctetLength, Or, Remainder, Sha2, ShiftLeft, ShiftRight, Signum, Sin, Sqrt, StartsWith, StringRepeat, StringSpace, StringTrim, StringTrimLeft, StringTrimRight, Substring, Subtract, Tan, TruncDate, Unevaluable, UnscaledValue, Upper, XxHash64, Year}
-import org.apache.spark.sql.catalyst.expressions.aggregate.{AggregateExpression, AggregateFunction, Average, CollectList, CollectSet, Count, DeclarativeAggregate, First, Max, Min, Sum, TypedImperativeAggregate}
+import org.apache.spark.sql.catalyst.expressions.{Abs, Acos, Add, Alias, And, Asin, Atan, Attribute, AttributeReference, BitwiseAnd, BitwiseOr, BoundReference, CaseWhen, Cast, Ceil, CheckOverflow, Coalesce, Concat, ConcatWs, Contains, Cos, CreateArray, CreateNamedStruct, DayOfMonth, Divide, EndsWith, EqualTo, Exp, Expression, Floor, GetArrayItem, GetJsonObject, GetMapValue, GetStructField, GreaterThan, GreaterThanOrEqual, If, In, InSet, IsNotNull, IsNull, LeafExpression, Length, LessThan, LessThanOrEqual, Like, Literal, Log, Log10, Log2, Lower, MakeDecimal, MapFromArrays, MapKeys, MapValues, Md5, Month, Multiply, Murmur3Hash, Not, NullIf, OctetLength, Or, Rand, Randn, Remainder, ScalaUDF, Sequence, Sha2, ShiftLeft, ShiftRight, Signum, Sin, Sqrt, StartsWith, StringRepeat, StringSpace, StringTrim, StringTrimLeft, StringTrimRight, Substring, SubstringIndex, Subtract, Tan, TruncDate, Unevaluable, UnscaledValue, Upper, Uuid, XxHash64, Year}
+import org.apache.spark.sql.catalyst.expressions.aggregate.{AggregateExpression, AggregateFunction, Average, BoolAnd, BoolOr, CollectList, CollectSet, Complete, Count, DeclarativeAggregate, First, Max, Min, Partial, Sum, TypedImperativeAggregate}
 import org.apache.spark.sql.catalyst.expressions.codegen.CodegenContext
 import org.apache.spark.sql.catalyst.expressions.codegen.ExprCode
 import org.apache.spark.sql.catalyst.plans.FullOuter
@@ -69,6 +69,7 @@ import org.apache.spark.sql.types.DecimalType
 import org.apache.spark.sql.types.DoubleType
 import org.apache.spark.sql.types.FloatType
 import org.apache.spark.sql.types.IntegerType
+import org.apache.spark.sql.types.IntegralType
 import org.apache.spark.sql.types.LongType
 import org.apache.spark.sql.types.MapType
 import org.apache.spark.sql.types.NullType
@@ -294,6 +295,10 @@ object NativeConverters extends Logging {
           case _ =>
         }
         val exprString = sparkExpr.toString()
+        val udfName = sparkExpr match {
+          case udf: ScalaUDF => udf.udfName.getOrElse("")
+          case _ => ""
+        }
 
         // bind all convertible children
         val convertedChildren = mutable.LinkedHashMap[pb.PhysicalExprNode, BoundReference]()
@@ -330,7 +335,8 @@ object NativeConverters extends Logging {
               .setReturnType(convertDataType(bound.dataType))
               .setReturnNullable(bound.nullable)
               .addAllParams(convertedChildren.keys.asJava)
-              .setExprString(exprString))
+              .setExprString(exprString)
+              .setUdfName(udfName))
           .build()
     }
   }
@@ -370,26 +376,11 @@ object NativeConverters extends Logging {
       case e: NativeExprWrapperBase => e.wrapped
       case e: Literal =>
         val schema = StructType(Seq(StructField("", e.dataType, e.nullable)))
-        val row = InternalRow(e.eval(null))
-        Using.resource(
-          VectorSchemaRoot.create(ArrowUtils.toArrowSchema(schema), ROOT_ALLOCATOR)) { root =>
-          val arrowWriter = ArrowWriter.create(root)
-          arrowWriter.write(row)
-          arrowWriter.finish()
-
-          val dictionaryProvider = new CDataDictionaryProvider()
-          val bo = new ByteArrayOutputStream()
-          Using(new ArrowStreamWriter(root, dictionaryProvider, bo)) { ipcWriter =>
-            ipcWriter.start()
-            ipcWriter.writeBatch()
-            ipcWriter.end()
-          }
-          val ipcBytes = bo.toByteArray
-          pb.PhysicalExprNode
-            .newBuilder()
-            .setLiteral(pb.ScalarValue.newBuilder().setIpcBytes(ByteString.copyFrom(ipcBytes)))
-            .build()
-        }
+        val ipcBytes = serializeRowsToIpcBytes(schema, Seq(InternalRow(e.eval(null))))
+        pb.PhysicalExprNode
+          .newBuilder()
+          .setLiteral(pb.ScalarValue.newBuilder().setIpcBytes(ByteString.copyFrom(ipcBytes)))
+          .build()
 
       case bound: BoundReference =>
         buildExprNode {
@@ -872,6 +863,16 @@ object NativeConverters extends Logging {
               .setExpr(convertExprWithFallback(expr, isPruningExpr, fallback))
               .setInfix(infix.toString)))
 
+      case SubstringIndex(str, Literal(delim, StringType), Literal(count, IntegerType))
+          if delim != null && count != null =>
+        buildExprNode(
+          _.setStringSubstringIndexExpr(
+            pb.StringSubstringIndexExprNode
+              .newBuilder()
+              .setExpr(convertExprWithFallback(str, isPruningExpr, fallback))
+              .setDelim(delim.toString)
+              .setCount(count.asInstanceOf[Int])))
+
       case Substring(str, Literal(pos, IntegerType), Literal(len, IntegerType))
           if pos.asInstanceOf[Int] > 0 && len.asInstanceOf[Int] >= 0 =>
         val longPos = pos.asInstanceOf[Int].toLong
@@ -962,6 +963,26 @@ object NativeConverters extends Logging {
         buildExtScalarFunction("NormalizeNanAndZero", e.children, e.dataType)
 
       case e: CreateArray => buildExtScalarFunction("MakeArray", e.children, e.dataType)
+      case e: Sequence if e.start.dataType.isInstanceOf[IntegralType] =>
+        val sequenceExpr = pb.SparkSequenceExprNode
+          .newBuilder()
+          .setStart(convertExprWithFallback(e.start, isPruningExpr, fallback))
+          .setStop(convertExprWithFallback(e.stop, isPruningExpr, fallback))
+        e.stepOpt.foreach { step =>
+          sequenceExpr.setStep(convertExprWithFallback(step, isPruningExpr, fallback))
+        }
+        buildExprNode(_.setSparkSequenceExpr(sequenceExpr))
+
+      // map functions
+      case e: MapKeys => buildExtScalarFunction("MapKeys", e.children, e.dataType)
+      case e: MapValues => buildExtScalarFunction("MapValues", e.children, e.dataType)
+      case e: MapFromArrays =>
+        val lastWin = SQLConf.get.getConf(SQLConf.MAP_KEY_DEDUP_POLICY) ==
+          SQLConf.MapKeyDedupPolicy.LAST_WIN.toString
+        buildExtScalarFunction(
+          "MapFromArrays",
+          e.left :: e.right :: Literal(lastWin) :: Nil,
+          e.dataType)
 
       case e: CreateNamedStruct =>
         buildExprNode {
@@ -1014,6 +1035,28 @@ object NativeConverters extends Logging {
           _.setRowNumExpr(pb.RowNumExprNode.newBuilder())
         }
 
+      // random exprs, bound to partitions in native project/filter
+      case e: Rand if e.child.foldable =>
+        buildExprNode {
+          _.setSparkRandExpr(
+            pb.SparkRandExprNode
+              .newBuilder()
+              .setSeed(randSeed(e.child))
+              .setGaussian(false))
+        }
+      case e: Randn if e.child.foldable =>
+        buildExprNode {
+          _.setSparkRandExpr(
+            pb.SparkRandExprNode
+              .newBuilder()
+              .setSeed(randSeed(e.child))
+              .setGaussian(true))
+        }
+      case e: Uuid if e.randomSeed.isDefined =>
+        buildExprNode {
+          _.setSparkUuidExpr(pb.SparkUuidExprNode.newBuilder().setSeed(e.randomSeed.get))
+        }
+
       // hive UDFJson
       // hive UDFJson
       case e
@@ -1047,8 +1090,47 @@ object NativeConverters extends Logging {
     }
   }
 
+  // same as the seed of spark's RDG
+  private def randSeed(seedExpr: Expression): Long = {
+    seedExpr.eval() match {
+      case s: Int => s
+      case s: Long => s
+      case null => 0L
+      case s => throw new NotImplementedError(s"unsupported rand seed: $s")
+    }
+  }
+
+  // aggregate filters (from FILTER clauses, and carried by spark's rewriting of mixed
+  // distinct aggregates into expand and two-phase aggregation) only take effect when
+  // updating with input rows. filtered rows are nulled out for aggregates ignoring nulls
+  private def applyAggregateFilter(
+      e: AggregateExpression,
+      filter: Expression): AggregateExpression = {
+    val unfiltered = Shims.get.withoutAggregateExpressionFilter(e)
+    if (e.mode != Partial && e.mode != Complete) {
+      return unfiltered
+    }
+    val nullIfFiltered = (child: Expression) => If(filter, child, Literal(null, child.dataType))
+    val filteredFunction = e.aggregateFunction match {
+      case f @ (_: Max | _: Min | _: Sum | _: Average | _: Count | _: CollectList |
+          _: CollectSet) =>
+        f.mapChildren(nullIfFiltered)
+      case f @ First(_, ignoresNullExpr) if (ignoresNullExpr.asInstanceOf[Any] match {
+            case Literal(v: Boolean, BooleanType) => v
+            case v: Boolean => v
+          }) =>
+        f.mapChildren(nullIfFiltered)
+      case f =>
+        throw new NotImplementedError(s"aggregate filter not supported: ${f.prettyName}")
+    }
+    unfiltered.copy(aggregateFunction = filteredFunction.asInstanceOf[AggregateFunction])
+  }
+
   def convertAggregateExpr(e: AggregateExpression): pb.PhysicalExprNode = {
-    assert(Shims.get.getAggregateExpressionFilter(e).isEmpty)
+    Shims.get.getAggregateExpressionFilter(e) match {
+      case Some(filter) => return convertAggregateExpr(applyAggregateFilter(e, filter))
+      case None =>
+    }
     val aggBuilder = pb.PhysicalAggExprNode.newBuilder()
     aggBuilder.setReturnType(convertDataType(e.dataType))
 
@@ -1059,9 +1141,19 @@ object NativeConverters extends Logging {
       case e: Min =>
         aggBuilder.setAggFunction(pb.AggFunction.MIN)
         aggBuilder.addChildren(convertExpr(e.child))
+      // bool_and/bool_or and their aliases (every/any/some) are always rewritten
+      // to min/max by spark's ReplaceExpressions, so these branches only apply to
+      // plans built by custom planners which skip the optimizer
+      case e: BoolAnd =>
+        aggBuilder.setAggFunction(pb.AggFunction.BOOL_AND)
+        aggBuilder.addChildren(convertExpr(e.arg))
+      case e: BoolOr =>
+        aggBuilder.setAggFunction(pb.AggFunction.BOOL_OR)
+        aggBuilder.addChildren(convertExpr(e.arg))
       case e: Sum if e.dataType.isInstanceOf[AtomicType] =>
         aggBuilder.setAggFunction(pb.AggFunction.SUM)
         aggBuilder.addChildren(convertExpr(e.child))
+        aggBuilder.setFailOnOverflow(SQLConf.get.ansiEnabled)
       case e: Average if e.dataType.isInstanceOf[AtomicType] =>
         aggBuilder.setAggFunction(pb.AggFunction.AVG)
         aggBuilder.addChildren(convertExpr(e.child))
@@ -1122,6 +1214,24 @@ object NativeConverters extends Logging {
         aggBuilder.setAggFunction(pb.AggFunction.BRICKHOUSE_COMBINE_UNIQUE)
         aggBuilder.addChildren(convertExpr(udaf.children.head))
 
+      // reservoir sampling UDAFs: (value, k[, seed]) with foldable k and seed
+      case udaf
+          if BlazeConf.RESERVOIR_SAMPLE_UDAF_CLASS_NAME.stringConf().nonEmpty
+            && HiveUDFUtil
+              .getFunctionClassName(udaf)
+              .contains(BlazeConf.RESERVOIR_SAMPLE_UDAF_CLASS_NAME.stringConf())
+            && Seq(2, 3).contains(udaf.children.size)
+            && udaf.children.tail.forall(c => c.foldable && c.dataType.isInstanceOf[IntegralType])
+            && (udaf.dataType match {
+              case ArrayType(elementType, _) => elementType == udaf.children.head.dataType
+              case _ => false
+            }) =>
+        aggBuilder.setAggFunction(pb.AggFunction.RESERVOIR_SAMPLE)
+        aggBuilder.addChildren(convertExpr(udaf.children.head))
+        udaf.children.tail.foreach { arg =>
+          aggBuilder.addChildren(convertExpr(Literal(arg.eval(), arg.dataType)))
+        }
+
       case udaf =>
         Shims.get.convertMoreAggregateExpr(e) match {
           case Some(converted) => return converted
@@ -1179,6 +1289,29 @@ object NativeConverters extends Logging {
       .build()
   }
 
+  // converts a join key. lower()-wrapped string keys are converted to collated keys.
+  // hash join probed sides hash and compare them case-insensitively without materializing
+  // lowered values, while build sides and sort-merge joins materialize the folded values,
+  // which are the same as lowered values. so both sides of all joins must be converted
+  // with this function to agree with each other.
+  def convertJoinKeyExpr(key: Expression): pb.PhysicalExprNode = {
+    key match {
+      case Lower(child)
+          if child.dataType == StringType &&
+            BlazeConf.CASE_CONVERT_FUNCTIONS_ENABLE.booleanConf() &&
+            BlazeConf.COLLATED_JOIN_KEYS_ENABLE.booleanConf() =>
+        pb.PhysicalExprNode
+          .newBuilder()
+          .setCollatedExpr(
+            pb.CollatedExprNode
+              .newBuilder()
+              .setExpr(convertExpr(child))
+              .setCollation(pb.Collation.UNICODE_CASE_INSENSITIVE))
+          .build()
+      case _ => convertExpr(key)
+    }
+  }
+
   def convertJoinType(joinType: JoinType): pb.JoinType = {
     joinType match {
       case Inner => pb.JoinType.INNER
@@ -1282,6 +1415,51 @@ object NativeConverters extends Logging {
     }
   }
 
+  // serializes rows as a single-batch arrow ipc stream
+  def serializeRowsToIpcBytes(schema: StructType, rows: Seq[InternalRow]): Array[Byte] = {
+    Using.resource(
+      VectorSchemaRoot.create(ArrowUtils.toArrowSchema(schema), ROOT_ALLOCATOR)) { root =>
+      val arrowWriter = ArrowWriter.create(root)
+      rows.foreach(arrowWriter.write)
+      arrowWriter.finish()
+
+      val dictionaryProvider = new CDataDictionaryProvider()
+      val bo = new ByteArrayOutputStream()
+      Using(new ArrowStreamWriter(root, dictionaryProvider, bo)) { ipcWriter =>
+        ipcWriter.start()
+        ipcWriter.writeBatch()
+        ipcWriter.end()
+      }
+      bo.toByteArray
+    }
+  }
+
+  // returns the probed expr of an InSubqueryExec which can be evaluated by native
+  // InSubqueryExec, or None for other expressions
+  def getNativeInSubqueryProbeExpr(expr: Expression): Option[Expression] = {
+    expr match {
+      case e
+          if e.getClass.getName == "org.apache.spark.sql.execution.InSubqueryExec" &&
+            BlazeConf.IN_SUBQUERY_EXEC_ENABLE.booleanConf() =>
+        val probeExpr = e.children.head
+        Some(probeExpr).filter(_.dataType.isInstanceOf[AtomicType])
+      case _ => None
+    }
+  }
+
+  // serializes the (updated) result of an InSubqueryExec for native InSubqueryExec
+  def serializeInSubqueryResult(expr: Expression): Array[Byte] = {
+    val subquery = expr.asInstanceOf[ExecSubqueryExpression]
+    prepareExecSubquery(subquery)
+    val values = MethodUtils
+      .invokeMethod(subquery, "values")
+      .asInstanceOf[Option[Array[Any]]]
+      .getOrElse(throw new IllegalStateException(s"subquery result not ready: $subquery"))
+    val dataType = subquery.children.head.dataType
+    val schema = StructType(Seq(StructField("", dataType, nullable = true)))
+    serializeRowsToIpcBytes(schema, values.map(v => InternalRow(v)))
+  }
+
   def prepareExecSubquery(subquery: ExecSubqueryExpression): Unit = {
     val isCanonicalized =
       MethodUtils.invokeMethod(subquery.plan, true, "isCanonicalizedPlan").asInstanceOf[Boolean]
@@ -106,13 +106,17 @@ object NativeHelper extends Logging {
       "probed_side_compare_time" -> nanoTimingMetric("Native.probed_side_compare_time"),
       "build_output_time" -> nanoTimingMetric("Native.build_output_time"),
       "fallback_sort_merge_join_time" -> nanoTimingMetric("Native.fallback_sort_merge_join_time"),
+      "num_heavy_hitters" -> metric("Native.num_heavy_hitters"),
+      "num_heavy_hitter_rows" -> metric("Native.num_heavy_hitter_rows"),
       "mem_spill_count" -> metric("Native.mem_spill_count"),
       "mem_spill_size" -> sizeMetric("Native.mem_spill_size"),
       "mem_spill_iotime" -> nanoTimingMetric("Native.mem_spill_iotime"),
       "disk_spill_size" -> sizeMetric("Native.disk_spill_size"),
       "disk_spill_iotime" -> nanoTimingMetric("Native.disk_spill_iotime"),
       "shuffle_write_total_time" -> nanoTimingMetric("Native.shuffle_write_total_time"),
-      "shuffle_read_total_time" -> nanoTimingMetric("Native.shuffle_read_total_time"))
+      "shuffle_read_total_time" -> nanoTimingMetric("Native.shuffle_read_total_time"),
+      "shuffle_read_retried_blocks" -> metric("Native.shuffle_read_retried_blocks"),
+      "shuffle_read_quarantined_blocks" -> metric("Native.shuffle_read_quarantined_blocks"))
 
     if (BlazeConf.INPUT_BATCH_STATISTICS_ENABLE.booleanConf()) {
       metrics ++= TreeMap(
@@ -30,6 +30,7 @@ import org.apache.spark.sql.catalyst.expressions.aggregate.AggregateExpression
 import org.apache.spark.sql.catalyst.plans.physical.BroadcastMode
 import org.apache.spark.sql.catalyst.plans.physical.Partitioning
 import org.apache.spark.sql.execution.FileSourceScanExec
+import org.apache.spark.sql.execution.RangeExec
 import org.apache.spark.sql.execution.SparkPlan
 import org.apache.spark.sql.execution.blaze.plan._
 import org.apache.spark.sql.execution.blaze.shuffle.RssPartitionWriterBase
@@ -129,6 +130,8 @@ abstract class Shims {
       projectList: Seq[NamedExpression],
       child: SparkPlan): NativeProjectBase
 
+  def createNativeRangeExec(rangeExec: RangeExec): NativeRangeBase
+
   def createNativeRenameColumnsExec(
       child: SparkPlan,
       newColumnNames: Seq[String]): NativeRenameColumnsBase
@@ -206,6 +209,8 @@ abstract class Shims {
 
   def getAggregateExpressionFilter(expr: Expression): Option[Expression]
 
+  def withoutAggregateExpressionFilter(expr: AggregateExpression): AggregateExpression
+
   def createFileSegment(file: File, offset: Long, length: Long, numRecords: Long): FileSegment
 
   def commit(
@@ -110,10 +110,23 @@ case class SparkUDAFWrapperContext[B](serialized: ByteBuffer) extends Logging {
     rows
   }
 
+  // pre-sizes the rows storage for the expected number of groups, avoiding
+  // repeated reallocation when the rows grow
+  def initializeWithCapacity(numRow: Int, capacity: Int): BufferRowsColumn[B] = {
+    val rows = aggEvaluator.get.createEmptyColumn()
+    rows.reserve(math.max(numRow, capacity))
+    rows.resize(numRow)
+    rows
+  }
+
   def resize(rows: BufferRowsColumn[B], len: Int): Unit = {
     rows.resize(len)
   }
 
+  def fillNullRange(rows: BufferRowsColumn[B], start: Int, end: Int): Unit = {
+    rows.fillNullRange(start, end)
+  }
+
   def numRecords(rows: BufferRowsColumn[B]): Int = {
     rows.length
   }
@@ -151,13 +164,28 @@ case class SparkUDAFWrapperContext[B](serialized: ByteBuffer) extends Logging {
     }
   }
 
-  def eval(rows: BufferRowsColumn[B], indices: Array[Int], exportFFIArrayPtr: Long): Unit = {
+  // indices are passed from native side with a leading format tag, followed by
+  // plain indices or flattened (start, len) runs
+  private def expandIndexRuns(indexRuns: Array[Int]): Iterator[Int] = {
+    indexRuns(0) match {
+      case SparkUDAFWrapperContext.IDX_FORMAT_PLAIN =>
+        Iterator.range(1, indexRuns.length).map(indexRuns(_))
+      case SparkUDAFWrapperContext.IDX_FORMAT_RUNS =>
+        Iterator
+          .range(1, indexRuns.length, 2)
+          .flatMap(i => Iterator.range(indexRuns(i), indexRuns(i) + indexRuns(i + 1)))
+      case format =>
+        throw new IllegalArgumentException(s"invalid index runs format: $format")
+    }
+  }
+
+  def eval(rows: BufferRowsColumn[B], indexRuns: Array[Int], exportFFIArrayPtr: Long): Unit = {
     Using.resources(
       VectorSchemaRoot.create(outputSchema, ROOT_ALLOCATOR),
       ArrowArray.wrap(exportFFIArrayPtr)) { (outputRoot, exportArray) =>
       // evaluate expression and write to output root
       val outputWriter = ArrowWriter.create(outputRoot)
-      for (i <- indices) {
+      for (i <- expandIndexRuns(indexRuns)) {
         outputWriter.write(rows.evalRow(i))
       }
       outputWriter.finish()
@@ -167,8 +195,13 @@ case class SparkUDAFWrapperContext[B](serialized: ByteBuffer) extends Logging {
     }
   }
 
-  def serializeRows(rows: BufferRowsColumn[B], indices: Array[Int]): Array[Byte] = {
-    aggEvaluator.get.serializeRows(rows, indices.iterator)
+  // keeps only the rows at the given increasing indices, shifting them down in place
+  def compact(rows: BufferRowsColumn[B], indexRuns: Array[Int]): Unit = {
+    rows.compact(expandIndexRuns(indexRuns))
+  }
+
+  def serializeRows(rows: BufferRowsColumn[B], indexRuns: Array[Int]): Array[Byte] = {
+    aggEvaluator.get.serializeRows(rows, expandIndexRuns(indexRuns))
   }
 
   def deserializeRows(dataBuffer: ByteBuffer): BufferRowsColumn[B] = {
@@ -178,9 +211,9 @@ case class SparkUDAFWrapperContext[B](serialized: ByteBuffer) extends Logging {
   def spill(
       memTracker: SparkUDAFMemTracker,
       rows: BufferRowsColumn[B],
-      indices: Array[Int],
+      indexRuns: Array[Int],
       spillIdx: Long): Int = {
-    aggEvaluator.get.spill(memTracker, rows, indices.iterator, spillIdx)
+    aggEvaluator.get.spill(memTracker, rows, expandIndexRuns(indexRuns), spillIdx)
   }
 
   def unspill(
@@ -191,10 +224,19 @@ case class SparkUDAFWrapperContext[B](serialized: ByteBuffer) extends Logging {
   }
 }
 
+object SparkUDAFWrapperContext {
+  // format tags of index runs, must match udaf_context.rs
+  final val IDX_FORMAT_PLAIN = 0
+  final val IDX_FORMAT_RUNS = 1
+}
+
 trait BufferRowsColumn[B] {
   def length: Int
   def memUsed: Int
   def resize(numRows: Int): Unit
+  def reserve(capacity: Int): Unit
+  def fillNullRange(start: Int, end: Int): Unit
+  def compact(retained: Iterator[Int]): Unit
   def updateRow(i: Int, inputRow: InternalRow): Unit
   def mergeRow(i: Int, mergeRows: BufferRowsColumn[B], mergeIdx: Int): Unit
   def evalRow(i: Int): InternalRow
@@ -329,6 +371,35 @@ case class DeclarativeAggRowsColumn(
     rows.trimEnd(rows.length - len)
   }
 
+  override def reserve(capacity: Int): Unit = {
+    rows.sizeHint(capacity)
+  }
+
+  override def fillNullRange(start: Int, end: Int): Unit = {
+    for (i <- start until end) {
+      if (rows(i) != null) {
+        rowsMemUsed -= rows(i).getSizeInBytes
+      }
+      val newRow = evaluator.initializedRow.copy()
+      rowsMemUsed += newRow.getSizeInBytes
+      rows(i) = newRow
+    }
+  }
+
+  override def compact(retained: Iterator[Int]): Unit = {
+    var numRetained = 0
+    rowsMemUsed = 0
+    for (i <- retained) {
+      val row = rows(i)
+      if (row != null) {
+        rowsMemUsed += row.getSizeInBytes
+      }
+      rows(numRetained) = row
+      numRetained += 1
+    }
+    rows.trimEnd(rows.length - numRetained)
+  }
+
   override def updateRow(i: Int, inputRow: InternalRow): Unit = {
     if (i == rows.length) {
       val newRow = evaluator.updater(evaluator.joiner(evaluator.initializedRow.copy(), inputRow))
@@ -479,6 +550,25 @@ case class TypedImperativeAggRowsColumn[B](
     rows.trimEnd(rows.length - len)
   }
 
+  override def reserve(capacity: Int): Unit = {
+    rows.sizeHint(capacity)
+  }
+
+  override def fillNullRange(start: Int, end: Int): Unit = {
+    for (i <- start until end) {
+      rows(i) = DeserializedRowType(evaluator.agg.createAggregationBuffer())
+    }
+  }
+
+  override def compact(retained: Iterator[Int]): Unit = {
+    var numRetained = 0
+    for (i <- retained) {
+      rows(numRetained) = rows(i)
+      numRetained += 1
+    }
+    rows.trimEnd(rows.length - numRetained)
+  }
+
   override def updateRow(i: Int, inputRow: InternalRow): Unit = {
     if (i < rows.length) {
       val updated = evaluator.agg.update(deserializedRow(i), inputRow)
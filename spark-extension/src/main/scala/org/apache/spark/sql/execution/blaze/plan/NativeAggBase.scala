@@ -46,8 +46,10 @@ import org.apache.spark.sql.execution.exchange.ReusedExchangeExec
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.sql.types.DataType
 import org.blaze.{protobuf => pb}
+import org.apache.spark.sql.catalyst.expressions.EqualTo
 import org.apache.spark.sql.catalyst.expressions.ExprId
 import org.apache.spark.sql.catalyst.expressions.Literal
+import org.apache.spark.sql.catalyst.expressions.Not
 import org.apache.spark.sql.catalyst.expressions.SortOrder
 import org.apache.spark.sql.execution.aggregate.SortAggregateExec
 import org.apache.spark.sql.execution.blaze.plan.NativeAggBase.AggExecMode
@@ -56,6 +58,7 @@ import org.apache.spark.sql.execution.blaze.plan.NativeAggBase.NativeAggrInfo
 import org.apache.spark.sql.execution.blaze.plan.NativeAggBase.SortAgg
 import org.apache.spark.sql.execution.metric.SQLMetrics
 import org.apache.spark.sql.types.BinaryType
+import org.apache.spark.sql.types.IntegerType
 
 abstract class NativeAggBase(
     execMode: AggExecMode,
@@ -116,7 +119,35 @@ abstract class NativeAggBase(
     case SortAgg => pb.AggExecMode.SORT_AGG
   }
 
-  private def nativeAggrs = nativeAggrInfos.flatMap(_.nativeAggrs)
+  private def nativeAggrs = {
+    val udafInitialCapacity = this.udafInitialCapacity
+    nativeAggrInfos.flatMap(_.nativeAggrs).map {
+      case aggr if aggr.getAggExpr.hasUdaf && udafInitialCapacity > 0 =>
+        val aggExpr = aggr.getAggExpr
+        aggr.toBuilder
+          .setAggExpr(
+            aggExpr.toBuilder.setUdaf(
+              aggExpr.getUdaf.toBuilder.setInitialCapacity(udafInitialCapacity)))
+          .build()
+      case aggr => aggr
+    }
+  }
+
+  // expected number of groups of each task for pre-sizing udaf buffer rows,
+  // estimated from the row count of the logical aggregate. 0 if unknown.
+  // logical links are set after construction, so this is not cached.
+  private def udafInitialCapacity: Long = {
+    if (execMode != HashAgg || groupingExpressions.isEmpty) {
+      0L
+    } else {
+      logicalLink.flatMap(_.stats.rowCount) match {
+        case Some(rowCount) =>
+          val numPartitions = math.max(child.outputPartitioning.numPartitions, 1)
+          (rowCount / numPartitions).min(NativeAggBase.MAX_UDAF_INITIAL_CAPACITY).toLong
+        case None => 0L
+      }
+    }
+  }
 
   private def nativeGroupingExprs = groupingExpressions.map(NativeConverters.convertExpr(_))
 
@@ -158,9 +189,35 @@ abstract class NativeAggBase(
       && requiredChildDistribution.forall(_ == UnspecifiedDistribution)
   )
 
+  // the first aggregation of distinct aggregates rewritten by RewriteDistinctAggregates, which
+  // groups the expanded rows by the grouping keys, distinct columns and gid. expanded rows of
+  // distinct aggregates (gid != 0 if there are regular aggregates) can be deduplicated before
+  // aggregating
+  private def nativeDistinctPreDedup: Option[(Seq[pb.PhysicalExprNode], pb.PhysicalExprNode)] = {
+    val gidAttr = groupingExpressions.collectFirst {
+      case attr: AttributeReference if attr.name == "gid" && attr.dataType == IntegerType => attr
+    }
+    if (!BlazeConf.DISTINCT_AGG_PRE_DEDUP_ENABLE.booleanConf()
+      || !child.isInstanceOf[NativeExpandBase]
+      || !aggregateExpressions.forall(_.mode == Partial)
+      || gidAttr.isEmpty) {
+      return None
+    }
+    val dedupPredicate = if (aggregateExpressions.nonEmpty) {
+      Not(EqualTo(gidAttr.get, Literal(0)))
+    } else {
+      Literal(true)
+    }
+    Some((nativeGroupingExprs, NativeConverters.convertExpr(dedupPredicate)))
+  }
+
   override def doExecuteNative(): NativeRDD = {
     val inputRDD = NativeHelper.executeNative(child)
-    val nativeMetrics = MetricNode(metrics, inputRDD.metrics :: Nil)
+    val nativeDistinctPreDedup = this.nativeDistinctPreDedup
+    val nativeMetrics = nativeDistinctPreDedup match {
+      case Some(_) => MetricNode(metrics, MetricNode(Map(), inputRDD.metrics :: Nil) :: Nil)
+      case None => MetricNode(metrics, inputRDD.metrics :: Nil)
+    }
     val nativeExecMode = this.nativeExecMode
     val nativeAggrNames = this.nativeAggrNames
     val nativeGroupingNames = this.nativeGroupingNames
@@ -177,8 +234,23 @@ abstract class NativeAggBase(
       inputRDD.isShuffleReadFull,
       (partition, taskContext) => {
 
-        lazy val inputPlan =
-          inputRDD.nativePlan(inputRDD.partitions(partition.index), taskContext)
+        lazy val inputPlan = {
+          val nativeInputPlan =
+            inputRDD.nativePlan(inputRDD.partitions(partition.index), taskContext)
+          nativeDistinctPreDedup match {
+            case Some((nativeKeyExprs, nativeDedupPredicate)) =>
+              pb.PhysicalPlanNode
+                .newBuilder()
+                .setDistinctAgg(
+                  pb.DistinctAggExecNode
+                    .newBuilder()
+                    .setInput(nativeInputPlan)
+                    .addAllKeyExpr(nativeKeyExprs.asJava)
+                    .setDedupPredicate(nativeDedupPredicate))
+                .build()
+            case None => nativeInputPlan
+          }
+        }
         pb.PhysicalPlanNode
           .newBuilder()
           .setAgg(
@@ -209,6 +281,9 @@ object NativeAggBase extends Logging {
   val AGG_BUF_COLUMN_EXPR_ID = 9223372036854775807L
   val AGG_BUF_COLUMN_NAME = s"#$AGG_BUF_COLUMN_EXPR_ID"
 
+  // same as the max initial number of groups of the native hash map
+  val MAX_UDAF_INITIAL_CAPACITY = 65536
+
   trait AggExecMode;
   case object HashAgg extends AggExecMode
   case object SortAgg extends AggExecMode
@@ -298,7 +298,7 @@ object NativeBroadcastExchangeBase {
     val buildHashMapExec = pb.BroadcastJoinBuildHashMapExecNode
       .newBuilder()
       .setInput(pb.PhysicalPlanNode.newBuilder().setIpcReader(readerExec))
-      .addAllKeys(keys.map(key => NativeConverters.convertExpr(key)).asJava)
+      .addAllKeys(keys.map(key => NativeConverters.convertJoinKeyExpr(key)).asJava)
 
     val writerIpcProviderResourceId = s"BuildBroadcastDataWriter:${UUID.randomUUID()}"
     val writerExec = pb.IpcWriterExecNode
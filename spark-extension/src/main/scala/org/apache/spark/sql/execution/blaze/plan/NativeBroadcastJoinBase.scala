@@ -97,8 +97,8 @@ abstract class NativeBroadcastJoinBase(
       rewrittenLeftKeys.zip(rewrittenRightKeys).map { case (leftKey, rightKey) =>
         JoinOn
           .newBuilder()
-          .setLeft(NativeConverters.convertExpr(leftKey))
-          .setRight(NativeConverters.convertExpr(rightKey))
+          .setLeft(NativeConverters.convertJoinKeyExpr(leftKey))
+          .setRight(NativeConverters.convertJoinKeyExpr(rightKey))
           .build()
       }
     } else {
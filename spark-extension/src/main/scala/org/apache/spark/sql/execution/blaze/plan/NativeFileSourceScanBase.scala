@@ -19,9 +19,11 @@ import java.net.URI
 import java.security.PrivilegedExceptionAction
 
 import scala.collection.JavaConverters._
+import scala.util.Try
 
 import org.apache.commons.lang3.reflect.MethodUtils
 import org.apache.hadoop.fs.FileSystem
+import org.apache.hadoop.fs.Path
 import org.apache.spark.broadcast.Broadcast
 import org.blaze.{protobuf => pb}
 import org.apache.spark.rdd.MapPartitionsRDD
@@ -32,10 +34,13 @@ import org.apache.spark.sql.blaze.NativeSupports
 import org.apache.spark.sql.blaze.Shims
 import org.apache.spark.sql.catalyst.expressions.Attribute
 import org.apache.spark.sql.catalyst.expressions.Literal
+import org.apache.spark.sql.catalyst.plans.physical.HashPartitioning
 import org.apache.spark.sql.catalyst.plans.physical.Partitioning
 import org.apache.spark.sql.execution.FileSourceScanExec
 import org.apache.spark.sql.execution.LeafExecNode
+import org.apache.spark.sql.execution.datasources.BucketingUtils
 import org.apache.spark.sql.execution.datasources.FileScanRDD
+import org.apache.spark.sql.execution.datasources.LogicalRelation
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.sql.execution.datasources.FilePartition
 import org.apache.spark.sql.execution.datasources.PartitionedFile
@@ -88,32 +93,92 @@ abstract class NativeFileSourceScanBase(basedFileScan: FileSourceScanExec)
   protected def nativePartitionSchema: pb.Schema =
     NativeConverters.convertSchema(partitionSchema)
 
-  protected def nativeFileGroups: FilePartition => pb.FileGroup = (partition: FilePartition) => {
-    // list input file statuses
-    val nativePartitionedFile = (file: PartitionedFile) => {
-      val nativePartitionValues = partitionSchema.zipWithIndex.map { case (field, index) =>
-        NativeConverters
-          .convertExpr(Literal(file.partitionValues.get(index, field.dataType), field.dataType))
-          .getLiteral
+  // estimated statistics from catalog, column stats are in the order of file schema.
+  // the logical link of a scan is usually the project/filter planned together with the
+  // relation, so the relation is searched in the linked subtree
+  def nativeStatistics: pb.Statistics = {
+    val relation = basedFileScan.logicalLink.flatMap(_.collectFirst {
+      case relation: LogicalRelation => relation
+    })
+    val catalogStats = relation.flatMap(_.catalogTable.flatMap(_.stats))
+    val nativeStatisticsBuilder = pb.Statistics.newBuilder().setIsExact(false)
+    if (catalogStats.isEmpty) {
+      nativeStatisticsBuilder.setTotalByteSize(basedFileScan.relation.sizeInBytes)
+    }
+    catalogStats.foreach { stats =>
+      nativeStatisticsBuilder.setTotalByteSize(stats.sizeInBytes.toLong)
+      stats.rowCount.foreach(rowCount => nativeStatisticsBuilder.setNumRows(rowCount.toLong))
+      basedFileScan.relation.dataSchema.foreach { field =>
+        val nativeColumnStatsBuilder = pb.ColumnStats.newBuilder()
+        stats.colStats.get(field.name).foreach { colStat =>
+          colStat.distinctCount.foreach(n =>
+            nativeColumnStatsBuilder.setDistinctCount(n.min(Int.MaxValue).toInt))
+          colStat.nullCount.foreach(n =>
+            nativeColumnStatsBuilder.setNullCount(n.min(Int.MaxValue).toInt))
+
+          if (basedFileScan.requiredSchema.exists(_.name == field.name)) {
+            val planStat = colStat.toPlanStat(field.name, field.dataType)
+            val convertValue = (v: Any) =>
+              Try(NativeConverters.convertExpr(Literal(v, field.dataType)).getLiteral).toOption
+            planStat.min.flatMap(convertValue).foreach(v => nativeColumnStatsBuilder.setMinValue(v))
+            planStat.max.flatMap(convertValue).foreach(v => nativeColumnStatsBuilder.setMaxValue(v))
+          }
+        }
+        nativeStatisticsBuilder.addColumnStats(nativeColumnStatsBuilder.build())
       }
-      pb.PartitionedFile
-        .newBuilder()
-        .setPath(s"${file.filePath}")
-        .setSize(fileSizes(file.filePath))
-        .addAllPartitionValues(nativePartitionValues.asJava)
-        .setLastModifiedNs(0)
-        .setRange(
-          pb.FileRange
+    }
+    nativeStatisticsBuilder.build()
+  }
+
+  // bucketing is kept only if spark plans a bucketed scan, in which case the files are already
+  // grouped into partitions by bucket ids
+  protected def nativeBucketSpec: Option[pb.BucketSpec] =
+    basedFileScan.outputPartitioning match {
+      case _: HashPartitioning =>
+        basedFileScan.relation.bucketSpec.map { bucketSpec =>
+          pb.BucketSpec
             .newBuilder()
-            .setStart(file.start)
-            .setEnd(file.start + file.length)
-            .build())
+            .addAllBucketColumns(bucketSpec.bucketColumnNames.asJava)
+            .setNumBuckets(bucketSpec.numBuckets)
+            .build()
+        }
+      case _ => None
+    }
+
+  protected def nativeFileGroups: FilePartition => pb.FileGroup = {
+    val isBucketed = nativeBucketSpec.isDefined
+    (partition: FilePartition) => {
+      // list input file statuses
+      val nativePartitionedFile = (file: PartitionedFile) => {
+        val nativePartitionValues = partitionSchema.zipWithIndex.map { case (field, index) =>
+          NativeConverters
+            .convertExpr(Literal(file.partitionValues.get(index, field.dataType), field.dataType))
+            .getLiteral
+        }
+        val nativePartitionedFileBuilder = pb.PartitionedFile
+          .newBuilder()
+          .setPath(s"${file.filePath}")
+          .setSize(fileSizes(file.filePath))
+          .addAllPartitionValues(nativePartitionValues.asJava)
+          .setLastModifiedNs(0)
+          .setRange(
+            pb.FileRange
+              .newBuilder()
+              .setStart(file.start)
+              .setEnd(file.start + file.length)
+              .build())
+        if (isBucketed) {
+          BucketingUtils
+            .getBucketId(new Path(s"${file.filePath}").getName)
+            .foreach(bucketId => nativePartitionedFileBuilder.setBucketId(bucketId))
+        }
+        nativePartitionedFileBuilder.build()
+      }
+      pb.FileGroup
+        .newBuilder()
+        .addAllFiles(partition.files.map(nativePartitionedFile).toList.asJava)
         .build()
     }
-    pb.FileGroup
-      .newBuilder()
-      .addAllFiles(partition.files.map(nativePartitionedFile).toList.asJava)
-      .build()
   }
 
   // check whether native converting is supported
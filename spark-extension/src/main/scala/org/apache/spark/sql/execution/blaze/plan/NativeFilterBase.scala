@@ -31,7 +31,9 @@ import org.apache.spark.sql.execution.SparkPlan
 import org.apache.spark.sql.execution.UnaryExecNode
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.OneToOneDependency
+import com.google.protobuf.ByteString
 import org.blaze.protobuf.FilterExecNode
+import org.blaze.protobuf.InSubqueryExecNode
 import org.blaze.protobuf.PhysicalExprNode
 import org.blaze.protobuf.PhysicalPlanNode
 import org.apache.spark.sql.blaze.NativeSupports
@@ -63,6 +65,7 @@ abstract class NativeFilterBase(condition: Expression, override val child: Spark
 
   private def nativeFilterExprs = {
     val splittedExprs = ArrayBuffer[PhysicalExprNode]()
+    val inSubqueryExprs = ArrayBuffer[(Expression, PhysicalExprNode)]()
 
     // do not split simple IsNotNull(col) exprs
     def isNaiveIsNotNullColumns(expr: Expression): Boolean = {
@@ -77,11 +80,17 @@ abstract class NativeFilterBase(condition: Expression, override val child: Spark
         case e @ And(lhs, rhs) if !isNaiveIsNotNullColumns(e) =>
           split(lhs)
           split(rhs)
-        case expr => splittedExprs.append(NativeConverters.convertExpr(expr))
+        case expr =>
+          NativeConverters.getNativeInSubqueryProbeExpr(expr) match {
+            case Some(probeExpr) =>
+              inSubqueryExprs.append((expr, NativeConverters.convertExpr(probeExpr)))
+            case None =>
+              splittedExprs.append(NativeConverters.convertExpr(expr))
+          }
       }
     }
     split(condition)
-    splittedExprs
+    (splittedExprs, inSubqueryExprs)
   }
 
   // check whether native converting is supported
@@ -89,8 +98,20 @@ abstract class NativeFilterBase(condition: Expression, override val child: Spark
 
   override def doExecuteNative(): NativeRDD = {
     val inputRDD = NativeHelper.executeNative(child)
-    val nativeMetrics = MetricNode(metrics, inputRDD.metrics :: Nil)
-    val nativeFilterExprs = this.nativeFilterExprs
+    val (nativeFilterExprs, inSubqueryExprs) = this.nativeFilterExprs
+
+    // subquery results are collected in driver and shipped with the native plan
+    val nativeInSubqueries = inSubqueryExprs.map { case (expr, nativeProbeExpr) =>
+      (nativeProbeExpr, ByteString.copyFrom(NativeConverters.serializeInSubqueryResult(expr)))
+    }
+
+    // the outermost native node reports the metrics of this plan
+    val numNativeNodes = nativeInSubqueries.length + (if (nativeFilterExprs.nonEmpty) 1 else 0)
+    val nativeMetrics = MetricNode(
+      metrics,
+      (1 until numNativeNodes).foldLeft(inputRDD.metrics) { (childMetrics, _) =>
+        MetricNode(Map(), childMetrics :: Nil)
+      } :: Nil)
     new NativeRDD(
       sparkContext,
       nativeMetrics,
@@ -100,12 +121,25 @@ abstract class NativeFilterBase(condition: Expression, override val child: Spark
       inputRDD.isShuffleReadFull,
       (partition, taskContext) => {
         val inputPartition = inputRDD.partitions(partition.index)
-        val nativeFilterExec = FilterExecNode
-          .newBuilder()
-          .setInput(inputRDD.nativePlan(inputPartition, taskContext))
-          .addAllExpr(nativeFilterExprs.asJava)
-          .build()
-        PhysicalPlanNode.newBuilder().setFilter(nativeFilterExec).build()
+        var nativePlan = inputRDD.nativePlan(inputPartition, taskContext)
+        for ((nativeProbeExpr, subqueryResultIpcBytes) <- nativeInSubqueries) {
+          val nativeInSubqueryExec = InSubqueryExecNode
+            .newBuilder()
+            .setInput(nativePlan)
+            .setProbeExpr(nativeProbeExpr)
+            .setSubqueryResultIpcBytes(subqueryResultIpcBytes)
+            .build()
+          nativePlan = PhysicalPlanNode.newBuilder().setInSubquery(nativeInSubqueryExec).build()
+        }
+        if (nativeFilterExprs.nonEmpty) {
+          val nativeFilterExec = FilterExecNode
+            .newBuilder()
+            .setInput(nativePlan)
+            .addAllExpr(nativeFilterExprs.asJava)
+            .build()
+          nativePlan = PhysicalPlanNode.newBuilder().setFilter(nativeFilterExec).build()
+        }
+        nativePlan
       },
       friendlyName = "NativeRDD.Filter")
   }
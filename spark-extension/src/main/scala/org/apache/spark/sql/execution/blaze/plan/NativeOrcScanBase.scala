@@ -48,6 +48,8 @@ abstract class NativeOrcScanBase(basedFileScan: FileSourceScanExec)
     val nativeFileSchema = this.nativeFileSchema
     val nativeFileGroups = this.nativeFileGroups
     val nativePartitionSchema = this.nativePartitionSchema
+    val nativeStatistics = this.nativeStatistics
+    val nativeBucketSpec = this.nativeBucketSpec
     val projection = schema.map(field => basedFileScan.relation.schema.fieldIndex(field.name))
     val broadcastedHadoopConf = this.broadcastedHadoopConf
     val numPartitions = partitions.length
@@ -64,16 +66,18 @@ abstract class NativeOrcScanBase(basedFileScan: FileSourceScanExec)
         putJniBridgeResource(resourceId, broadcastedHadoopConf)
 
         val nativeFileGroup = nativeFileGroups(partition.asInstanceOf[FilePartition])
-        val nativeFileScanExecConf = pb.FileScanExecConf
+        val nativeFileScanExecConfBuilder = pb.FileScanExecConf
           .newBuilder()
           .setNumPartitions(numPartitions)
           .setPartitionIndex(partition.index)
-          .setStatistics(pb.Statistics.getDefaultInstance)
+          .setStatistics(nativeStatistics)
           .setSchema(nativeFileSchema)
           .setFileGroup(nativeFileGroup)
           .addAllProjection(projection.map(Integer.valueOf).asJava)
           .setPartitionSchema(nativePartitionSchema)
-          .build()
+        nativeBucketSpec.foreach(bucketSpec =>
+          nativeFileScanExecConfBuilder.setBucketSpec(bucketSpec))
+        val nativeFileScanExecConf = nativeFileScanExecConfBuilder.build()
 
         val nativeOrcScanExecBuilder = pb.OrcScanExecNode
           .newBuilder()
@@ -48,6 +48,8 @@ abstract class NativeParquetScanBase(basedFileScan: FileSourceScanExec)
     val nativeFileSchema = this.nativeFileSchema
     val nativeFileGroups = this.nativeFileGroups
     val nativePartitionSchema = this.nativePartitionSchema
+    val nativeStatistics = this.nativeStatistics
+    val nativeBucketSpec = this.nativeBucketSpec
 
     val projection = schema.map(field => basedFileScan.relation.schema.fieldIndex(field.name))
     val broadcastedHadoopConf = this.broadcastedHadoopConf
@@ -65,16 +67,18 @@ abstract class NativeParquetScanBase(basedFileScan: FileSourceScanExec)
         putJniBridgeResource(resourceId, broadcastedHadoopConf)
 
         val nativeFileGroup = nativeFileGroups(partition.asInstanceOf[FilePartition])
-        val nativeParquetScanConf = pb.FileScanExecConf
+        val nativeParquetScanConfBuilder = pb.FileScanExecConf
           .newBuilder()
           .setNumPartitions(numPartitions)
           .setPartitionIndex(partition.index)
-          .setStatistics(pb.Statistics.getDefaultInstance)
+          .setStatistics(nativeStatistics)
           .setSchema(nativeFileSchema)
           .setFileGroup(nativeFileGroup)
           .addAllProjection(projection.map(Integer.valueOf).asJava)
           .setPartitionSchema(nativePartitionSchema)
-          .build()
+        nativeBucketSpec.foreach(bucketSpec =>
+          nativeParquetScanConfBuilder.setBucketSpec(bucketSpec))
+        val nativeParquetScanConf = nativeParquetScanConfBuilder.build()
 
         val nativeParquetScanExecBuilder = pb.ParquetScanExecNode
           .newBuilder()
@@ -0,0 +1,102 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.execution.blaze.plan
+
+import scala.collection.immutable.SortedMap
+
+import org.apache.spark.Partition
+import org.apache.spark.sql.blaze.MetricNode
+import org.apache.spark.sql.blaze.NativeHelper
+import org.apache.spark.sql.blaze.NativeRDD
+import org.apache.spark.sql.blaze.NativeSupports
+import org.apache.spark.sql.catalyst.expressions.Attribute
+import org.apache.spark.sql.catalyst.expressions.SortOrder
+import org.apache.spark.sql.catalyst.plans.logical.Range
+import org.apache.spark.sql.catalyst.plans.physical.Partitioning
+import org.apache.spark.sql.catalyst.plans.physical.SinglePartition
+import org.apache.spark.sql.catalyst.plans.physical.UnknownPartitioning
+import org.apache.spark.sql.execution.LeafExecNode
+import org.apache.spark.sql.execution.metric.SQLMetric
+import org.blaze.protobuf.PhysicalPlanNode
+import org.blaze.protobuf.RangeExecNode
+
+abstract class NativeRangeBase(range: Range, numSlices: Int)
+    extends LeafExecNode
+    with NativeSupports {
+
+  override lazy val metrics: Map[String, SQLMetric] = SortedMap[String, SQLMetric]() ++ Map(
+    NativeHelper
+      .getDefaultNativeMetrics(sparkContext)
+      .filterKeys(Set("stage_id", "output_rows"))
+      .toSeq: _*)
+
+  override def output: Seq[Attribute] = range.output
+  override def outputOrdering: Seq[SortOrder] = range.outputOrdering
+  override def outputPartitioning: Partitioning = if (numSlices == 1) {
+    SinglePartition
+  } else {
+    UnknownPartitioning(numSlices)
+  }
+
+  // same as RangeExec.numElements
+  private def numElements: BigInt = {
+    val distance = BigInt(range.end) - BigInt(range.start)
+    if (distance % range.step == 0 || (distance > 0) != (range.step > 0)) {
+      distance / range.step
+    } else {
+      distance / range.step + 1
+    }
+  }
+
+  override def doExecuteNative(): NativeRDD = {
+    val nativeMetrics = MetricNode(metrics, Nil)
+    val partitions = (0 until numSlices)
+      .map(i =>
+        new Partition {
+          override def index: Int = i
+        })
+      .toArray
+    val numElements = this.numElements
+    val renamedColumnNames = output.map(Util.getFieldNameByExprId)
+
+    new NativeRDD(
+      sparkContext,
+      nativeMetrics,
+      rddPartitions = partitions,
+      rddPartitioner = None,
+      rddDependencies = Nil,
+      rddShuffleReadFull = false,
+      (partition, _taskContext) => {
+        // split the range into slices the same way as RangeExec
+        def safeMargin(bi: BigInt): Long = {
+          if (bi.isValidLong) bi.toLong else if (bi > 0) Long.MaxValue else Long.MinValue
+        }
+        val i = partition.index
+        val partitionStart = (i * numElements) / numSlices * range.step + range.start
+        val partitionEnd = ((i + 1) * numElements) / numSlices * range.step + range.start
+        val nativeRangeExec = RangeExecNode
+          .newBuilder()
+          .setStart(safeMargin(partitionStart))
+          .setEnd(safeMargin(partitionEnd))
+          .setStep(range.step)
+          .build()
+        NativeRenameColumnsBase.buildRenameColumnsExec(
+          PhysicalPlanNode.newBuilder().setRange(nativeRangeExec).build(),
+          renamedColumnNames)
+      },
+      friendlyName = "NativeRDD.Range")
+  }
+}
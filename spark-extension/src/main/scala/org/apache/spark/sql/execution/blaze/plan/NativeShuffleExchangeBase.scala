@@ -20,7 +20,7 @@ import java.util.UUID
 import scala.collection.JavaConverters._
 
 import org.apache.spark.{OneToOneDependency, Partitioner, RangePartitioner, ShuffleDependency, SparkEnv, TaskContext}
-import org.blaze.protobuf.{IpcReaderExecNode, PhysicalExprNode, PhysicalHashRepartition, PhysicalPlanNode, PhysicalRangeRepartition, PhysicalRepartition, PhysicalRoundRobinRepartition, PhysicalSingleRepartition, PhysicalSortExprNode, Schema, SortExecNode}
+import org.blaze.protobuf.{IpcReaderExecNode, PhysicalExprNode, PhysicalHashRepartition, PhysicalPlanNode, PhysicalRangeRepartition, PhysicalRepartition, PhysicalRoundRobinRepartition, PhysicalSingleRepartition, PhysicalSortExprNode, Schema, SortExecNode, Statistics}
 import org.apache.spark.rdd.{PartitionPruningRDD, RDD}
 import org.apache.spark.serializer.Serializer
 import org.apache.spark.shuffle.ShuffleWriteProcessor
@@ -86,6 +86,19 @@ abstract class NativeShuffleExchangeBase(
 
   def nativeSchema: Schema = Util.getNativeSchema(child.output)
 
+  // estimated statistics of one read partition, evenly divided from the written rows and
+  // bytes. unknown until the map stage has finished
+  def nativeReadStatistics(numReadPartitions: Int): Statistics = {
+    val rowCount = metrics(SQLShuffleWriteMetricsReporter.SHUFFLE_RECORDS_WRITTEN).value
+    val dataSize = metrics("dataSize").value
+    val statisticsBuilder = Statistics.newBuilder().setIsExact(false)
+    if (rowCount > 0 && numReadPartitions > 0) {
+      statisticsBuilder.setNumRows(math.max(rowCount / numReadPartitions, 1))
+      statisticsBuilder.setTotalByteSize(dataSize / numReadPartitions)
+    }
+    statisticsBuilder.build()
+  }
+
   private def nativeHashExprs = outputPartitioning match {
     case HashPartitioning(expressions, _) =>
       expressions.map(expr => NativeConverters.convertExpr(expr)).toList
@@ -123,6 +136,7 @@ abstract class NativeShuffleExchangeBase(
   override def doExecuteNative(): NativeRDD = {
     val shuffleHandle = shuffleDependency.shuffleHandle
     val rdd = doExecuteNonNative()
+    val nativeReadStatistics = this.nativeReadStatistics(rdd.getNumPartitions)
 
     val nativeMetrics = MetricNode(
       Map(),
@@ -172,6 +186,7 @@ abstract class NativeShuffleExchangeBase(
               .setSchema(nativeSchema)
               .setNumPartitions(rdd.getNumPartitions)
               .setIpcProviderResourceId(jniResourceId)
+              .setStatistics(nativeReadStatistics)
               .build())
           .build()
       },
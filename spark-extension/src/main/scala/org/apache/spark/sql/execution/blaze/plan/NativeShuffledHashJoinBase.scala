@@ -22,6 +22,7 @@ import org.apache.spark.sql.catalyst.plans.RightOuter
 import org.apache.spark.sql.execution.SparkPlan
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.OneToOneDependency
+import org.apache.spark.sql.blaze.BlazeConf
 import org.apache.spark.sql.blaze.MetricNode
 import org.apache.spark.sql.blaze.NativeConverters
 import org.apache.spark.sql.blaze.NativeHelper
@@ -55,6 +56,8 @@ abstract class NativeShuffledHashJoinBase(
         "probed_side_compare_time",
         "build_output_time",
         "fallback_sort_merge_join_time",
+        "num_heavy_hitters",
+        "num_heavy_hitter_rows",
         "input_batch_count",
         "input_batch_mem_size",
         "input_row_count"))
@@ -68,8 +71,8 @@ abstract class NativeShuffledHashJoinBase(
     rewrittenLeftKeys.zip(rewrittenRightKeys).map { case (leftKey, rightKey) =>
       pb.JoinOn
         .newBuilder()
-        .setLeft(NativeConverters.convertExpr(leftKey))
-        .setRight(NativeConverters.convertExpr(rightKey))
+        .setLeft(NativeConverters.convertJoinKeyExpr(leftKey))
+        .setRight(NativeConverters.convertJoinKeyExpr(rightKey))
         .build()
     }
   }
@@ -96,6 +99,7 @@ abstract class NativeShuffledHashJoinBase(
     val nativeJoinOn = this.nativeJoinOn
     val nativeJoinType = this.nativeJoinType
     val nativeBuildSide = this.nativeBuildSide
+    val skewHeavyHitterFraction = BlazeConf.SHJ_SKEW_HEAVY_HITTER_FRACTION.doubleConf()
 
     val (partitions, partitioner) = if (joinType != RightOuter) {
       (leftRDD.partitions, leftRDD.partitioner)
@@ -126,6 +130,7 @@ abstract class NativeShuffledHashJoinBase(
           .setJoinType(nativeJoinType)
           .addAllOn(nativeJoinOn.asJava)
           .setBuildSide(nativeBuildSide)
+          .setSkewHeavyHitterFraction(skewHeavyHitterFraction)
         pb.PhysicalPlanNode.newBuilder().setHashJoin(hashJoinExec).build()
       },
       friendlyName = "NativeRDD.ShuffledHashJoin")
@@ -74,8 +74,8 @@ abstract class NativeSortMergeJoinBase(
   private def nativeSchema = Util.getNativeSchema(output)
 
   private def nativeJoinOn = leftKeys.zip(rightKeys).map { case (leftKey, rightKey) =>
-    val leftKeyExpr = NativeConverters.convertExpr(leftKey)
-    val rightKeyExpr = NativeConverters.convertExpr(rightKey)
+    val leftKeyExpr = NativeConverters.convertJoinKeyExpr(leftKey)
+    val rightKeyExpr = NativeConverters.convertJoinKeyExpr(rightKey)
     JoinOn
       .newBuilder()
       .setLeft(leftKeyExpr)
@@ -19,7 +19,7 @@ import java.io.{FileInputStream, IOException, InputStream}
 import java.nio.ByteBuffer
 import java.nio.channels.{Channels, ReadableByteChannel}
 import org.apache.commons.lang3.reflect.{FieldUtils, MethodUtils}
-import org.apache.spark.{InterruptibleIterator, ShuffleDependency, TaskContext}
+import org.apache.spark.{InterruptibleIterator, ShuffleDependency, SparkEnv, TaskContext}
 import org.apache.spark.internal.Logging
 import org.apache.spark.network.util.LimitedInputStream
 import org.apache.spark.shuffle.{BaseShuffleHandle, ShuffleReader}
@@ -34,8 +34,21 @@ abstract class BlazeBlockStoreShuffleReaderBase[K, C](
   protected val dep: ShuffleDependency[K, _, C] = handle.dependency
   protected def readBlocks(): Iterator[InputStream]
 
+  // like readBlocks(), with a function requesting each block again, which is used to
+  // refetch corrupted blocks. blocks cannot be refetched by default
+  protected def readRefetchableBlocks(): Iterator[(InputStream, Option[() => InputStream])] =
+    readBlocks().map(inputStream => (inputStream, None))
+
+  // blocks written by native shuffle writer are encrypted with spark's io
+  // encryption key, and decrypted by native ipc reader
+  protected def isIoEncrypted: Boolean =
+    SparkEnv.get.securityManager.getIOEncryptionKey().isDefined
+
   def readIpc(): Iterator[BlockObject] = {
-    val ipcIterator = readBlocks().map(inputStream => createBlockObject(inputStream))
+    val encrypted = isIoEncrypted
+    val ipcIterator = readRefetchableBlocks().map { case (inputStream, refetchBlock) =>
+      createBlockObject(inputStream, encrypted, refetchBlock)
+    }
 
     // An interruptible iterator must be used here in order to support task cancellation
     new InterruptibleIterator[BlockObject](context, ipcIterator)
@@ -48,7 +61,10 @@ abstract class BlazeBlockStoreShuffleReaderBase[K, C](
 }
 
 object BlazeBlockStoreShuffleReaderBase extends Logging {
-  def createBlockObject(in: InputStream): BlockObject = {
+  def createBlockObject(
+      in: InputStream,
+      encrypted: Boolean = false,
+      refetchBlock: Option[() => InputStream] = None): BlockObject = {
     getFileSegmentFromInputStream(in) match {
       case Some((path, offset, limit)) =>
         return new BlockObject {
@@ -56,7 +72,9 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
           override def getFilePath: String = path
           override def getFileOffset: Long = offset
           override def getFileLength: Long = limit
+          override def isEncrypted: Boolean = encrypted
           override def close(): Unit = in.close()
+          override def refetch(): BlockObject = refetchBlockObject(this, encrypted, refetchBlock)
           override def throwFetchFailed(errmsg: String): Unit = {
             throwFetchFailedOnInputStream(in, errmsg)
           }
@@ -69,7 +87,9 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
         return new BlockObject {
           override def hasByteBuffer: Boolean = true
           override def getByteBuffer: ByteBuffer = buf
+          override def isEncrypted: Boolean = encrypted
           override def close(): Unit = in.close()
+          override def refetch(): BlockObject = refetchBlockObject(this, encrypted, refetchBlock)
           override def throwFetchFailed(errmsg: String): Unit = {
             throwFetchFailedOnInputStream(in, errmsg)
           }
@@ -80,13 +100,29 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
     val channel = Channels.newChannel(in)
     new BlockObject {
       override def getChannel: ReadableByteChannel = channel
+      override def isEncrypted: Boolean = encrypted
       override def close(): Unit = channel.close()
+      override def refetch(): BlockObject = refetchBlockObject(this, encrypted, refetchBlock)
       override def throwFetchFailed(errmsg: String): Unit = {
         throwFetchFailedOnInputStream(in, errmsg)
       }
     }
   }
 
+  // closes the corrupted block and requests it again, the refetched block is also
+  // refetchable. returns null if the block cannot be requested again
+  private def refetchBlockObject(
+      block: BlockObject,
+      encrypted: Boolean,
+      refetchBlock: Option[() => InputStream]): BlockObject = {
+    refetchBlock match {
+      case Some(fetch) =>
+        block.close()
+        createBlockObject(fetch(), encrypted, refetchBlock)
+      case None => null
+    }
+  }
+
   private def unwrapInputStream(in: InputStream): InputStream = {
     val bufferReleasingInputStreamCls =
       Class.forName("org.apache.spark.storage.BufferReleasingInputStream")
@@ -178,5 +214,10 @@ trait BlockObject extends AutoCloseable {
   def getFileLength: Long = throw new UnsupportedOperationException
   def getByteBuffer: ByteBuffer = throw new UnsupportedOperationException
   def getChannel: ReadableByteChannel = throw new UnsupportedOperationException
+  def isEncrypted: Boolean = false
   def throwFetchFailed(errmsg: String): Unit = throw new UnsupportedOperationException
+
+  // returns a block object with the data requested again when the data of this block
+  // is corrupted, or null if refetching is not supported
+  def refetch(): BlockObject = null
 }
@@ -21,4 +21,8 @@ import org.apache.spark.shuffle.BaseShuffleHandle
 abstract class BlazeRssShuffleReaderBase[K, C](
     handle: BaseShuffleHandle[K, _, C],
     context: TaskContext)
-    extends BlazeBlockStoreShuffleReaderBase[K, C](handle, context) {}
+    extends BlazeBlockStoreShuffleReaderBase[K, C](handle, context) {
+
+  // rss blocks are not encrypted by native shuffle writer
+  override protected def isIoEncrypted: Boolean = false
+}